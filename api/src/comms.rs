@@ -34,7 +34,8 @@ impl CommsActor {
         mut rx: mpsc::Receiver<Envelope>,
         subscriber: ZmqSocket,
         sender: ZmqSocket,
-        _api_settings: ApiSettings,
+        a_tx: broadcast::Sender<Message>,
+        api_settings: ApiSettings,
     ) {
         // users of the node actor leave their "contact details" behind so the response can be transfered back later.
         type FilterFn = Box<dyn Send + Fn(&Message) -> bool>;
@@ -48,15 +49,20 @@ impl CommsActor {
 
         let waiting = Arc::new(waiting);
 
-        let (a_tx, mut a_rx) = broadcast::channel(1024);
+        let instance_id = api_settings.api_instance_id.unwrap_or_default();
+
+        // Every incoming message is broadcast, so other tasks like the webhook dispatcher can subscribe.
+        let mut a_rx = a_tx.subscribe();
 
         {
             let a_tx = a_tx.clone();
 
             thread::spawn(move || {
                 while let Ok(frames) = subscriber.recv_multipart(0x00) {
-                    if let Ok(message) = bincode::deserialize::<Message>(&frames[2]) {
-                        let _ = a_tx.send(message);
+                    if let Ok(message) = msgs::wire::decode(&frames[2]) {
+                        utils::telemetry::request_span("api.receive", message.trace_id()).in_scope(|| {
+                            let _ = a_tx.send(message);
+                        });
                     };
                 }
             });
@@ -108,7 +114,8 @@ impl CommsActor {
                 waiting.lock().await.push((tx, func, time::time_now()));
             }
 
-            utils::xzmq::send_as_bincode(&sender, &message);
+            utils::telemetry::request_span("api.send", message.trace_id())
+                .in_scope(|| utils::xzmq::send_message_from(&sender, &instance_id, &message));
         }
     }
 }
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tokio::time::{timeout_at, Instant};
+
+use core_types::{RequestId, UserId};
+use msgs::api::*;
+use msgs::*;
+
+/// Completed requests are kept for this long so clients can pick them up after reconnecting.
+const EVENT_RETENTION_MILLIS: u64 = 600_000;
+const MAX_RETAINED_EVENTS: usize = 10_000;
+
+/// A response that completed a payment or invoice request.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionEvent {
+    /// Increases with every event and serves as the cursor to resume from.
+    pub seq: u64,
+    pub req_id: RequestId,
+    #[serde(skip_serializing)]
+    pub uid: UserId,
+    pub created_at: u64,
+    pub message: Message,
+}
+
+fn completion_key(message: &Message) -> Option<(RequestId, UserId)> {
+    match message {
+        Message::Api(Api::PaymentResponse(resp)) => Some((resp.req_id, resp.uid)),
+        Message::Api(Api::InvoiceResponse(resp)) => Some((resp.req_id, resp.uid)),
+        _ => None,
+    }
+}
+
+struct Events {
+    events: VecDeque<CompletionEvent>,
+    next_seq: u64,
+}
+
+/// Keeps recent payment and invoice responses so clients that can't hold a WebSocket can
+/// long-poll or stream them.
+pub struct CompletionEvents {
+    events: Mutex<Events>,
+    latest_seq: watch::Sender<u64>,
+}
+
+impl Default for CompletionEvents {
+    fn default() -> Self {
+        let (latest_seq, _) = watch::channel(0);
+        Self {
+            events: Mutex::new(Events {
+                events: VecDeque::new(),
+                next_seq: 1,
+            }),
+            latest_seq,
+        }
+    }
+}
+
+impl CompletionEvents {
+    pub async fn start(events: Arc<CompletionEvents>, mut receiver: broadcast::Receiver<Message>) {
+        loop {
+            let message = match receiver.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+
+            events.record(message);
+        }
+    }
+
+    fn record(&self, message: Message) {
+        let (req_id, uid) = match completion_key(&message) {
+            Some(key) => key,
+            None => return,
+        };
+
+        // A scheduled retry or a withdrawal held for approval or delayed is not the final outcome of a payment.
+        if let Message::Api(Api::PaymentResponse(PaymentResponse {
+            error:
+                Some(
+                    PaymentResponseError::PaymentRetryScheduled
+                    | PaymentResponseError::PendingApproval
+                    | PaymentResponseError::WithdrawalDelayed,
+                ),
+            ..
+        })) = message
+        {
+            return;
+        }
+
+        let now = utils::time::time_now();
+        let seq = {
+            let mut events = self.events.lock().expect("Completion events lock poisoned");
+            let seq = events.next_seq;
+            events.next_seq += 1;
+            events.events.push_back(CompletionEvent {
+                seq,
+                req_id,
+                uid,
+                created_at: now,
+                message,
+            });
+            while events.events.len() > MAX_RETAINED_EVENTS
+                || events
+                    .events
+                    .front()
+                    .map_or(false, |event| event.created_at + EVENT_RETENTION_MILLIS < now)
+            {
+                events.events.pop_front();
+            }
+            seq
+        };
+
+        let _ = self.latest_seq.send(seq);
+    }
+
+    /// Returns the latest response to a request of the user.
+    pub fn get(&self, req_id: RequestId, uid: UserId) -> Option<CompletionEvent> {
+        let events = self.events.lock().expect("Completion events lock poisoned");
+        events
+            .events
+            .iter()
+            .rev()
+            .find(|event| event.req_id == req_id && event.uid == uid)
+            .cloned()
+    }
+
+    /// Returns all events of the user after `cursor`, oldest first.
+    pub fn since(&self, uid: UserId, cursor: u64) -> Vec<CompletionEvent> {
+        let events = self.events.lock().expect("Completion events lock poisoned");
+        events
+            .events
+            .iter()
+            .filter(|event| event.seq > cursor && event.uid == uid)
+            .cloned()
+            .collect()
+    }
+
+    /// Sequence number of the latest event, the cursor to start from to only receive new events.
+    pub fn latest_seq(&self) -> u64 {
+        *self.latest_seq.borrow()
+    }
+
+    /// Evaluates `check` whenever a new event arrives until it returns a value or `wait` elapsed.
+    pub async fn wait_for<T, F: Fn() -> Option<T>>(&self, wait: Duration, check: F) -> Option<T> {
+        let deadline = Instant::now() + wait;
+        // Subscribing before the first check makes sure no event slips through in between.
+        let mut latest_seq = self.latest_seq.subscribe();
+        loop {
+            if let Some(result) = check() {
+                return Some(result);
+            }
+            match timeout_at(deadline, latest_seq.changed()).await {
+                Ok(Ok(())) => continue,
+                _ => return check(),
+            }
+        }
+    }
+}
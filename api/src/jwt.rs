@@ -1,5 +1,8 @@
-use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use actix_web::{dev::Payload, web, Error, FromRequest, HttpRequest};
+use core_types::access::RequestOrigin;
 use futures::future::{err, ok, Ready};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 use jsonwebtoken::{
     decode, encode, errors as JError, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
@@ -9,6 +12,8 @@ use std::collections::HashSet;
 use std::convert::TryFrom;
 use xerror::api::JWTError;
 
+use crate::rate_limit::TrustedProxies;
+
 use time::get_time;
 
 use xerror::api::*;
@@ -305,6 +310,61 @@ pub fn jwt_check_refresh_token(rewew_token: &str) -> Result<TokenData<RefreshTok
     })
 }
 
+/// Signs a link to a resource that can be fetched without a token until `expires_at`, the signature is the
+/// hex encoded HMAC-SHA256 of `{resource}.{expires_at}` with our KEY.
+pub fn sign_link(resource: &str, expires_at: u64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&KEY).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", resource, expires_at).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks the signature of a link created by `sign_link`, the comparison takes constant time.
+pub fn verify_link(resource: &str, expires_at: u64, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(&KEY).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", resource, expires_at).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Configures how the origin of a request is determined.
+#[derive(Debug, Clone, Default)]
+pub struct OriginSettings {
+    /// Header set by a proxy with the country code of the client, e.g. `CF-IPCountry`.
+    pub country_header: Option<String>,
+    /// Proxies whose forwarded addresses and country header are used, clients could set them to anything.
+    pub trusted_proxies: TrustedProxies,
+}
+
+/// Determines the ip and country the request was made from. Without settings only the peer address is used.
+pub fn request_origin(request: &HttpRequest) -> RequestOrigin {
+    let default_settings = OriginSettings::default();
+    let settings = request
+        .app_data::<web::Data<OriginSettings>>()
+        .map(|settings| settings.get_ref())
+        .unwrap_or(&default_settings);
+    let peer = request.peer_addr().map(|addr| addr.ip());
+    let forwarded_for = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok());
+    let ip = settings.trusted_proxies.client_ip(peer, forwarded_for);
+    let country = settings
+        .country_header
+        .as_ref()
+        .filter(|_| peer.map_or(false, |peer| settings.trusted_proxies.is_trusted(peer)))
+        .and_then(|header| request.headers().get(header.as_str()))
+        .and_then(|value| value.to_str().ok())
+        .map(|country| country.trim().to_uppercase());
+    RequestOrigin {
+        ip: ip.map(|ip| ip.to_string()),
+        country,
+        key_id: None,
+    }
+}
+
 /// This struct unifies auth data across
 /// multiple authentication methods.
 #[derive(Debug, Clone)]
@@ -318,6 +378,7 @@ pub struct AuthData {
     pub user_roles: Option<UserRoles>,
     pub auth_type: AuthType,
     pub tid: Option<i32>,
+    pub origin: RequestOrigin,
 }
 
 impl AuthData {
@@ -382,6 +443,10 @@ impl FromRequest for AuthData {
                         api_key: None,
                         passphrase: None,
                         signature: None,
+                        origin: RequestOrigin {
+                            key_id: x.claims.get_tid(),
+                            ..request_origin(request)
+                        },
                     }),
                     Err(e) => err(Error::from(e)),
                 }
@@ -503,4 +568,28 @@ mod tests {
         let resp = <AuthData as FromRequest>::from_request(&req, &mut payload).await;
         assert_eq!(resp.unwrap_err().as_error::<JWTError>(), Some(&JWTError::InvalidKey));
     }
+
+    #[test]
+    fn forwarded_origin_is_only_read_from_trusted_proxies() {
+        let settings = web::Data::new(OriginSettings {
+            country_header: Some("CF-IPCountry".to_string()),
+            trusted_proxies: TrustedProxies::new(&["10.0.0.0/8".to_string()]).unwrap(),
+        });
+        let request = |peer: &str| {
+            test::TestRequest::default()
+                .peer_addr(peer.parse().unwrap())
+                .insert_header(("x-forwarded-for", "1.2.3.4"))
+                .insert_header(("CF-IPCountry", "ch"))
+                .app_data(settings.clone())
+                .to_http_request()
+        };
+
+        let proxied = request_origin(&request("10.0.0.1:4000"));
+        assert_eq!(proxied.ip.as_deref(), Some("1.2.3.4"));
+        assert_eq!(proxied.country.as_deref(), Some("CH"));
+
+        let direct = request_origin(&request("5.6.7.8:4000"));
+        assert_eq!(direct.ip.as_deref(), Some("5.6.7.8"));
+        assert_eq!(direct.country, None);
+    }
 }
@@ -1,20 +1,30 @@
 #![feature(drain_filter)]
 
 use actix_cors::Cors;
+use actix_web::dev::Service;
+use actix_web::http::header::{HeaderName, HeaderValue};
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
+use actix_web::{error, web, App, HttpServer};
 use diesel::{r2d2::ConnectionManager, PgConnection};
 use serde::{Deserialize, Serialize};
 
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
 
-use actix_ratelimit::{MemoryStore, MemoryStoreActor, RateLimiter};
 use core_types::DbPool;
+use utils::xlogging::{init_log, slog::Logger, LoggingSettings};
 use utils::xzmq::SocketContext;
 
 pub mod comms;
+pub mod events;
 pub mod jwt;
+pub mod market_data;
+pub mod metrics;
+pub mod preflight;
+pub mod rate_limit;
+pub mod read_replica;
 pub mod routes;
+pub mod webhooks;
 
 use comms::*;
 
@@ -25,59 +35,250 @@ pub struct ApiSettings {
     api_zmq_subscribe_address: String,
     quota_replenishment_interval_millis: u64,
     quota_size: u64,
+    /// Header a proxy sets to the country code of the client, only read on requests from trusted proxies.
+    #[serde(default)]
+    geo_country_header: Option<String>,
+    /// Max number of concurrent market data streams.
+    #[serde(default)]
+    max_market_data_subscribers: Option<usize>,
+    /// Addresses or CIDR ranges of the proxies in front of the api. The client addresses they forward are used
+    /// for rate limits and access policies, forwarded addresses of other peers are ignored.
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+    /// Toml file with partner networks that get their own request quota, reloaded when it changes.
+    #[serde(default)]
+    rate_limit_allowlist_path: Option<String>,
+    /// Serves the anonymized flow statistics aggregated by the bank.
+    #[serde(default)]
+    public_flow_statistics: bool,
+    /// Address Prometheus metrics are served on, not served if not set.
+    #[serde(default)]
+    api_metrics_address: Option<String>,
+    /// OTLP collector the spans of requests are exported to, not traced if not set.
+    #[serde(default)]
+    otlp_endpoint: Option<String>,
+    /// Id the bank routes the responses to this instance's requests by, must be unique when several instances
+    /// share a bank. A random id is used if not set.
+    #[serde(default)]
+    api_instance_id: Option<String>,
+    /// Postgres read replica balances, transaction history and node info are served from instead of the bank,
+    /// not used if not set.
+    #[serde(default)]
+    read_replica_psql_url: Option<String>,
+    /// Currencies on top of the built-in ones, e.g. further fiat synthetics.
+    #[serde(default)]
+    currencies: Vec<core_types::currency_registry::CurrencyInfo>,
+    /// Errors are only logged to stdout if not set.
+    #[serde(default)]
+    logging_settings: Option<LoggingSettings>,
 }
 
+/// Address the http server listens on.
+pub const HTTP_ADDRESS: &str = "127.0.0.1:8080";
+
 pub type WebDbPool = web::Data<DbPool>;
 pub type WebSender = web::Data<mpsc::Sender<Envelope>>;
+pub type WebBroadcast = web::Data<broadcast::Sender<msgs::Message>>;
+pub type WebReadReplica = web::Data<Option<read_replica::ReadReplica>>;
+pub type WebLogger = web::Data<Logger>;
+
+pub async fn start(mut settings: ApiSettings) -> std::io::Result<()> {
+    core_types::currency_registry::register_all(&settings.currencies).expect("Failed to register currencies.");
+    // Connections are only checked by the preflight so an unreachable database ends up in its report.
+    let pool = r2d2::Pool::builder().build_unchecked(ConnectionManager::<PgConnection>::new(settings.psql_url.clone()));
+
+    preflight::run(&settings, &pool).print_or_exit();
 
-pub async fn start(settings: ApiSettings) -> std::io::Result<()> {
-    let pool = r2d2::Pool::builder()
-        .build(ConnectionManager::<PgConnection>::new(settings.psql_url.clone()))
-        .expect("Failed to create pool.");
+    let logger = init_log(&settings.logging_settings.clone().unwrap_or_else(|| LoggingSettings {
+        stdout: true,
+        level: String::from("error"),
+        log_path: None,
+        name: String::from("api"),
+        slack_hook: String::new(),
+        slack_channel: String::new(),
+    }));
 
-    {
-        let conn = pool.get().expect("Failed to get DB connection to initialize models");
-        models::init(&conn).expect("Failed to initialize models");
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        if let Err(err) = utils::telemetry::init_tracing("api", endpoint) {
+            eprintln!("Failed to export traces to {}: {}", endpoint, err);
+        }
     }
 
     let (tx, rx) = mpsc::channel(1024);
 
+    let instance_id = settings
+        .api_instance_id
+        .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+        .clone();
+
     let context = SocketContext::new();
-    let subscriber = context.create_subscriber(&settings.api_zmq_subscribe_address);
+    // Only responses to this instance's requests and messages for every instance are received.
+    let instance_topic = utils::xzmq::instance_topic(&instance_id);
+    let subscriber = context.create_subscriber_to(
+        &settings.api_zmq_subscribe_address,
+        &[&instance_topic, utils::xzmq::BROADCAST_TOPIC],
+    );
     let pusher = context.create_push(&settings.api_zmq_push_address);
 
-    tokio::task::spawn(CommsActor::start(tx.clone(), rx, subscriber, pusher, settings.clone()));
+    let (broadcast_tx, _) = broadcast::channel(1024);
+
+    tokio::task::spawn(webhooks::WebhookDispatcher::start(
+        pool.clone(),
+        broadcast_tx.subscribe(),
+    ));
+
+    let completion_events = Arc::new(events::CompletionEvents::default());
+
+    tokio::task::spawn(events::CompletionEvents::start(
+        completion_events.clone(),
+        broadcast_tx.subscribe(),
+    ));
+
+    let market_data = Arc::new(market_data::MarketData::new(
+        settings
+            .max_market_data_subscribers
+            .unwrap_or(market_data::DEFAULT_MAX_SUBSCRIBERS),
+    ));
+
+    tokio::task::spawn(market_data::MarketData::start(
+        market_data.clone(),
+        broadcast_tx.subscribe(),
+    ));
 
-    let ratelimiter_store = MemoryStore::new();
+    tokio::task::spawn(market_data::MarketData::renew_rate_subscriptions(
+        market_data.clone(),
+        tx.clone(),
+    ));
 
-    let replenishment_interval = settings.quota_replenishment_interval_millis;
-    let max_requests = settings.quota_size as usize;
+    let push_broadcast = broadcast_tx.clone();
+
+    tokio::task::spawn(CommsActor::start(
+        tx.clone(),
+        rx,
+        subscriber,
+        pusher,
+        broadcast_tx,
+        settings.clone(),
+    ));
+
+    let rate_limiter = Arc::new(rate_limit::IpRateLimiter::new(
+        settings.quota_replenishment_interval_millis,
+        settings.quota_size,
+        settings.rate_limit_allowlist_path.clone(),
+    ));
+
+    tokio::task::spawn(rate_limit::IpRateLimiter::start(rate_limiter.clone()));
+    let trusted_proxies =
+        Arc::new(rate_limit::TrustedProxies::new(&settings.trusted_proxies).expect("Invalid trusted proxies."));
+
+    let recovery_rate_limiter = Arc::new(routes::recovery::recovery_rate_limiter());
+    tokio::task::spawn(rate_limit::IpRateLimiter::start(recovery_rate_limiter.clone()));
+
+    let origin_settings = jwt::OriginSettings {
+        country_header: settings.geo_country_header.clone(),
+        trusted_proxies: trusted_proxies.as_ref().clone(),
+    };
+
+    let public_flow_statistics = settings.public_flow_statistics;
+
+    let read_replica = Data::new(
+        settings
+            .read_replica_psql_url
+            .as_deref()
+            .map(read_replica::ReadReplica::new),
+    );
+
+    let metrics = Arc::new(metrics::ApiMetrics::new());
+    if let Some(address) = &settings.api_metrics_address {
+        utils::metrics::serve(metrics.registry.clone(), address)?;
+    }
 
     HttpServer::new(move || {
+        let limiter = rate_limiter.clone();
+        let proxies = trusted_proxies.clone();
+        let metrics = metrics.clone();
         App::new()
             .wrap(Cors::permissive())
-            .wrap(
-                RateLimiter::new(MemoryStoreActor::from(ratelimiter_store.clone()).start())
-                    .with_interval(std::time::Duration::from_millis(replenishment_interval))
-                    .with_max_requests(max_requests)
-                    .with_identifier(|req| {
-                        let c = req.connection_info().clone();
-                        let ip_parts: Vec<&str> = c.realip_remote_addr().unwrap().split(':').collect();
-                        Ok(ip_parts[0].to_string()) // should now be "127.0.0.1"
-                    }),
-            )
+            .wrap_fn(move |req, srv| {
+                let decision = limiter.check(rate_limit::client_ip(&req, &proxies), utils::time::time_now());
+                metrics.requests.inc();
+                let response = match decision {
+                    rate_limit::Decision::Throttle { .. } => {
+                        metrics.throttled_requests.inc();
+                        None
+                    }
+                    _ => Some(srv.call(req)),
+                };
+                async move {
+                    let (limit, remaining, reset_secs) = match decision {
+                        rate_limit::Decision::Unlimited => (None, 0, 0),
+                        rate_limit::Decision::Allow {
+                            limit,
+                            remaining,
+                            reset_secs,
+                        } => (Some(limit), remaining, reset_secs),
+                        rate_limit::Decision::Throttle { limit, reset_secs } => (Some(limit), 0, reset_secs),
+                    };
+                    let mut res = match response {
+                        Some(response) => response.await?,
+                        None => return Err(error::ErrorTooManyRequests("")),
+                    };
+                    if let Some(limit) = limit {
+                        let headers = res.headers_mut();
+                        for (name, value) in [
+                            ("x-ratelimit-limit", limit),
+                            ("x-ratelimit-remaining", remaining),
+                            ("x-ratelimit-reset", reset_secs),
+                        ] {
+                            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+                        }
+                    }
+                    Ok(res)
+                }
+            })
             .app_data(Data::new(pool.clone()))
             .app_data(Data::new(tx.clone()))
+            .app_data(Data::new(origin_settings.clone()))
+            .app_data(Data::from(completion_events.clone()))
+            .app_data(Data::new(push_broadcast.clone()))
+            .app_data(Data::from(market_data.clone()))
+            .app_data(Data::from(rate_limiter.clone()))
+            .app_data(read_replica.clone())
+            .app_data(Data::new(logger.clone()))
+            .app_data(Data::new(routes::recovery::RecoveryRateLimiter(recovery_rate_limiter.clone())))
             .service(routes::auth::create)
             .service(routes::auth::auth)
             .service(routes::auth::whoami)
             .service(routes::user::balance)
             .service(routes::user::add_invoice)
             .service(routes::user::pay_invoice)
+            .service(routes::user::keysend)
             .service(routes::user::get_user_invoices)
+            .service(routes::user::get_referrals)
             .service(routes::user::swap)
+            .service(routes::user::cash_out)
+            .service(routes::user::transfer)
+            .service(routes::accounts::create_account)
+            .service(routes::accounts::rename_account)
+            .service(routes::accounts::archive_account)
+            .service(routes::accounts::invite_account_member)
+            .service(routes::accounts::accept_account_invitation)
+            .service(routes::accounts::remove_account_member)
+            .service(routes::accounts::get_account_members)
             .service(routes::user::quote)
+            .service(routes::swap_orders::place_swap_order)
+            .service(routes::swap_orders::get_swap_orders)
+            .service(routes::swap_orders::cancel_swap_order)
+            .service(routes::ecash::withdraw_ecash)
+            .service(routes::ecash::deposit_ecash)
+            .service(routes::liquid::liquid_address)
+            .service(routes::liquid::claim_liquid_pegin)
+            .service(routes::liquid::withdraw_liquid)
             .service(routes::user::get_txs)
+            .service(routes::user::export_statement)
+            .service(routes::user::get_interest_history)
+            .service(routes::data_export::export_my_data)
+            .service(routes::data_export::download_my_data)
             .service(routes::user::get_available_currencies)
             .service(routes::user::get_node_info)
             .service(routes::user::get_query_route)
@@ -86,11 +287,61 @@ pub async fn start(settings: ApiSettings) -> std::io::Result<()> {
             .service(routes::lnurl::create_lnurl_withdrawal)
             .service(routes::lnurl::get_lnurl_withdrawal)
             .service(routes::lnurl::pay_lnurl_withdrawal)
+            .service(routes::lnurl::get_lnurl_channel)
+            .service(routes::lnurl::request_channel_open)
             .service(routes::lnurl::lnurl_pay_address)
             .service(routes::lnurl::pay_address)
             .service(routes::external::get_spot_prices)
+            .service(routes::status::get_status)
+            .service(routes::status::get_reserves)
+            .service(routes::status::get_availability)
+            .service(routes::status::get_rate_limits)
+            .service(routes::access_policy::set_access_policy)
+            .service(routes::access_policy::get_access_policy)
+            .service(routes::access_policy::delete_access_policy)
+            .service(routes::dust_sweeping::set_dust_sweeping)
+            .service(routes::dust_sweeping::get_dust_sweeping)
+            .service(routes::hedge_target::set_hedge_target)
+            .service(routes::hedge_target::get_hedge_target)
+            .service(routes::hedge_target::delete_hedge_target)
+            .service(routes::vault::get_vault_withdrawals)
+            .service(routes::vault::cancel_vault_withdrawal)
+            .service(routes::api_keys::create_api_key)
+            .service(routes::api_keys::get_api_keys)
+            .service(routes::api_keys::set_api_key_budget)
+            .service(routes::api_keys::revoke_api_key)
+            .service(routes::notifications::set_notification_preferences)
+            .service(routes::notifications::get_notification_preferences)
+            .service(routes::events::wait_for_response)
+            .service(routes::events::poll_events)
+            .service(routes::events::stream_events)
+            .service(routes::push::push_events)
+            .service(routes::market::stream_market_prices)
+            .service(routes::market::stream_rates)
+            .service(routes::webhooks::create_webhook)
+            .service(routes::webhooks::get_webhooks)
+            .service(routes::webhooks::delete_webhook)
+            .service(routes::deposit_rules::create_deposit_rule)
+            .service(routes::deposit_rules::get_deposit_rules)
+            .service(routes::deposit_rules::delete_deposit_rule)
+            .service(routes::recovery::set_guardians)
+            .service(routes::recovery::get_guardians)
+            .service(routes::recovery::request_recovery)
+            .service(routes::recovery::approve_recovery)
+            .service(routes::recovery::cancel_recovery)
+            .service(routes::recovery::complete_recovery)
+            .service(routes::recovery::get_recovery_status)
+            .service(routes::recovery::get_recovery_audit_log)
+            .service(routes::two_factor::enroll_totp)
+            .service(routes::two_factor::confirm_totp)
+            .service(routes::two_factor::disable_totp)
+            .configure(|cfg| {
+                if public_flow_statistics {
+                    cfg.service(routes::explorer::get_flow_statistics);
+                }
+            })
     })
-    .bind("127.0.0.1:8080")?
+    .bind(HTTP_ADDRESS)?
     .run()
     .await
 }
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+
+use core_types::Currency;
+use msgs::api::*;
+use msgs::dealer::*;
+use msgs::*;
+
+use crate::comms::Envelope;
+
+/// Prices of a currency are relayed at most this often.
+const THROTTLE_INTERVAL_MS: u64 = 1000;
+/// Subscribers that fall behind by more events skip the oldest.
+const EVENT_BUFFER_SIZE: usize = 64;
+/// The dealer stops publishing the rates of a pair 30 seconds after its last subscription.
+const RATE_SUBSCRIPTION_RENEWAL_SECS: u64 = 10;
+pub const DEFAULT_MAX_SUBSCRIBERS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarketTopic {
+    Price(Currency),
+    Rate(CurrencyPair),
+}
+
+#[derive(Debug, Clone)]
+pub struct MarketEvent {
+    pub topic: MarketTopic,
+    pub payload: String,
+}
+
+/// Fans the dealer's prices and rates out to public subscribers.
+pub struct MarketData {
+    sender: broadcast::Sender<MarketEvent>,
+    subscribers: AtomicUsize,
+    max_subscribers: usize,
+    // number of subscriptions of each pair the dealer is asked to publish rates of
+    rate_pairs: Mutex<HashMap<CurrencyPair, usize>>,
+}
+
+/// Holds one of the limited subscriber slots until dropped.
+pub struct Subscription {
+    market_data: Arc<MarketData>,
+    pairs: Vec<CurrencyPair>,
+    pub receiver: broadcast::Receiver<MarketEvent>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.market_data.subscribers.fetch_sub(1, Ordering::SeqCst);
+        let mut rate_pairs = self.market_data.rate_pairs.lock().unwrap();
+        for pair in &self.pairs {
+            if let Some(count) = rate_pairs.get_mut(pair) {
+                *count -= 1;
+                if *count == 0 {
+                    rate_pairs.remove(pair);
+                }
+            }
+        }
+    }
+}
+
+impl MarketData {
+    pub fn new(max_subscribers: usize) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Self {
+            sender,
+            subscribers: AtomicUsize::new(0),
+            max_subscribers,
+            rate_pairs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns no subscription if all slots are taken.
+    pub fn subscribe(market_data: &Arc<MarketData>) -> Option<Subscription> {
+        Self::subscribe_rates(market_data, Vec::new())
+    }
+
+    /// Subscribes to the rates of the pairs, the dealer publishes them until the subscription is dropped.
+    /// Returns no subscription if all slots are taken.
+    pub fn subscribe_rates(market_data: &Arc<MarketData>, pairs: Vec<CurrencyPair>) -> Option<Subscription> {
+        if market_data.subscribers.fetch_add(1, Ordering::SeqCst) >= market_data.max_subscribers {
+            market_data.subscribers.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        {
+            let mut rate_pairs = market_data.rate_pairs.lock().unwrap();
+            for pair in &pairs {
+                *rate_pairs.entry(*pair).or_insert(0) += 1;
+            }
+        }
+        Some(Subscription {
+            market_data: market_data.clone(),
+            pairs,
+            receiver: market_data.sender.subscribe(),
+        })
+    }
+
+    fn subscribed_pairs(&self) -> Vec<CurrencyPair> {
+        self.rate_pairs.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Keeps the dealer publishing the rates of the pairs clients are subscribed to.
+    pub async fn renew_rate_subscriptions(market_data: Arc<MarketData>, tx: mpsc::Sender<Envelope>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(RATE_SUBSCRIPTION_RENEWAL_SECS));
+        loop {
+            interval.tick().await;
+            let pairs = market_data.subscribed_pairs();
+            if pairs.is_empty() {
+                continue;
+            }
+            let envelope = Envelope {
+                message: Message::Api(Api::SubscribeRates(SubscribeRates { pairs })),
+                response_tx: None,
+                response_filter: None,
+            };
+            if tx.send(envelope).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Relays the prices and rates published by the dealer, throttled per currency and pair.
+    pub async fn start(market_data: Arc<MarketData>, mut receiver: broadcast::Receiver<Message>) {
+        let mut last_relayed = HashMap::<MarketTopic, u64>::new();
+        loop {
+            let events = match receiver.recv().await {
+                Ok(Message::Dealer(Dealer::MarketPrices(market_prices))) => market_prices
+                    .prices
+                    .into_iter()
+                    .map(|price| {
+                        let payload = json!({
+                            "event": "market_price",
+                            "pair": format!("BTC/{}", price.currency),
+                            "bid": price.bid,
+                            "ask": price.ask,
+                            "timestamp": market_prices.timestamp,
+                        });
+                        (MarketTopic::Price(price.currency), payload)
+                    })
+                    .collect::<Vec<_>>(),
+                Ok(Message::Api(Api::RatesUpdate(update))) => update
+                    .rates
+                    .into_iter()
+                    .map(|rate| {
+                        let payload = json!({
+                            "event": "rate",
+                            "pair": rate.pair.to_string(),
+                            "bid": rate.bid,
+                            "mid": rate.mid,
+                            "ask": rate.ask,
+                            "timestamp": update.timestamp,
+                        });
+                        (MarketTopic::Rate(rate.pair), payload)
+                    })
+                    .collect::<Vec<_>>(),
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+
+            let now = utils::time::time_now();
+            for (topic, payload) in events {
+                let last = last_relayed.entry(topic).or_insert(0);
+                if now < *last + THROTTLE_INTERVAL_MS {
+                    continue;
+                }
+                *last = now;
+
+                // Sending only fails while nobody is subscribed.
+                let _ = market_data.sender.send(MarketEvent {
+                    topic,
+                    payload: payload.to_string(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::Arc;
+use utils::metrics::*;
+
+/// Metrics of the api served to Prometheus on their own address, apart from the public http server.
+pub struct ApiMetrics {
+    pub registry: Arc<Registry>,
+    pub requests: Arc<Counter>,
+    pub throttled_requests: Arc<Counter>,
+}
+
+impl ApiMetrics {
+    pub fn new() -> Self {
+        let registry = Arc::new(Registry::new());
+        Self {
+            requests: registry.counter("api_requests_total", "Http requests received."),
+            throttled_requests: registry.counter("api_throttled_requests_total", "Requests refused by the rate limit."),
+            registry,
+        }
+    }
+}
+
+impl Default for ApiMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,58 @@
+use core_types::DbPool;
+use utils::preflight::{check_bindable, PreflightReport};
+
+use crate::{ApiSettings, HTTP_ADDRESS};
+
+/// Problems with the settings that would otherwise only surface once requests come in.
+pub fn check_settings(settings: &ApiSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if settings.quota_size == 0 || settings.quota_replenishment_interval_millis == 0 {
+        problems.push(String::from(
+            "quota_size and quota_replenishment_interval_millis have to be positive",
+        ));
+    }
+    if let Some(path) = &settings.rate_limit_allowlist_path {
+        if let Err(err) = std::fs::metadata(path) {
+            problems.push(format!("can't read the rate limit allowlist at {}: {}", path, err));
+        }
+    }
+    problems
+}
+
+/// Checks the database, runs the migrations and checks the http address and the settings before the api starts.
+pub fn run(settings: &ApiSettings, pool: &DbPool) -> PreflightReport {
+    let mut report = PreflightReport::new("api");
+
+    match pool.get() {
+        Ok(conn) => {
+            report.add("database", Ok(String::from("connected")));
+            let migrations = models::init(&conn)
+                .map(|_| format!("at migration {}", models::LATEST_MIGRATION_VERSION))
+                .map_err(|err| format!("failed to run the migrations: {:?}", err));
+            report.add("schema", migrations);
+        }
+        Err(err) => report.add(
+            "database",
+            Err(format!(
+                "can't connect: {}. Check psql_url and that postgres is running",
+                err
+            )),
+        ),
+    }
+
+    report.add(
+        &format!("http {}", HTTP_ADDRESS),
+        check_bindable(&format!("tcp://{}", HTTP_ADDRESS)),
+    );
+
+    let problems = check_settings(settings);
+    if problems.is_empty() {
+        report.add("settings", Ok(String::from("look sane")));
+    }
+    for problem in problems {
+        report.add("settings", Err(problem));
+    }
+
+    report
+}
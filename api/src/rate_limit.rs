@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use actix_web::dev::ServiceRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The allowlist file is checked for changes this often.
+const RELOAD_INTERVAL_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Partner {
+    pub name: String,
+    /// Addresses or CIDR ranges of the partner, e.g. `203.0.113.0/24`.
+    pub networks: Vec<String>,
+    /// Requests the partner may make per interval across all of its addresses, unlimited if not set.
+    #[serde(default)]
+    pub max_requests: Option<u64>,
+}
+
+/// Contents of the allowlist file, the limits replace the ones of the api settings if set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Allowlist {
+    #[serde(default)]
+    pub interval_millis: Option<u64>,
+    #[serde(default)]
+    pub max_requests: Option<u64>,
+    #[serde(default)]
+    pub partners: Vec<Partner>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Network {
+    addr: IpAddr,
+    prefix_len: u32,
+}
+
+impl Network {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr.trim()).map_err(|_| format!("Invalid address {}", s))?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| format!("Invalid prefix length {}", s))?,
+            None => max_prefix_len,
+        };
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PartnerRule {
+    name: String,
+    networks: Vec<Network>,
+    max_requests: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct RateLimitConfig {
+    interval_millis: u64,
+    max_requests: u64,
+    partners: Vec<PartnerRule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Partners without a limit aren't counted.
+    Unlimited,
+    Allow {
+        limit: u64,
+        remaining: u64,
+        reset_secs: u64,
+    },
+    Throttle {
+        limit: u64,
+        reset_secs: u64,
+    },
+}
+
+#[derive(Default)]
+struct RateLimitMetrics {
+    allowed: AtomicU64,
+    throttled: AtomicU64,
+    partner_requests: AtomicU64,
+    partner_throttled: AtomicU64,
+    reloads: AtomicU64,
+    reload_failures: AtomicU64,
+}
+
+/// Limits the requests per client address in fixed windows before they reach the bank. Partners on the
+/// allowlist get their own, shared quota. The allowlist file is reloaded whenever it changes.
+pub struct IpRateLimiter {
+    default_interval_millis: u64,
+    default_max_requests: u64,
+    allowlist_path: Option<String>,
+    config: RwLock<RateLimitConfig>,
+    /// Window start and number of requests in the window per client.
+    windows: Mutex<HashMap<String, (u64, u64)>>,
+    metrics: RateLimitMetrics,
+}
+
+impl IpRateLimiter {
+    pub fn new(interval_millis: u64, max_requests: u64, allowlist_path: Option<String>) -> Self {
+        let limiter = Self {
+            default_interval_millis: interval_millis,
+            default_max_requests: max_requests,
+            allowlist_path,
+            config: RwLock::new(RateLimitConfig {
+                interval_millis,
+                max_requests,
+                partners: Vec::new(),
+            }),
+            windows: Mutex::new(HashMap::new()),
+            metrics: RateLimitMetrics::default(),
+        };
+        if let Err(err) = limiter.reload() {
+            panic!("Failed to load rate limit allowlist: {}", err);
+        }
+        limiter
+    }
+
+    /// Reloads the allowlist file, the current configuration is kept if the file is invalid.
+    pub fn reload(&self) -> Result<(), String> {
+        let path = match &self.allowlist_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let result = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path, err))
+            .and_then(|content| toml::from_str::<Allowlist>(&content).map_err(|err| err.to_string()))
+            .and_then(|allowlist| self.compile(allowlist));
+        match result {
+            Ok(config) => {
+                *self.config.write().unwrap() = config;
+                self.metrics.reloads.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(err) => {
+                self.metrics.reload_failures.fetch_add(1, Ordering::Relaxed);
+                Err(err)
+            }
+        }
+    }
+
+    fn compile(&self, allowlist: Allowlist) -> Result<RateLimitConfig, String> {
+        let partners = allowlist
+            .partners
+            .into_iter()
+            .map(|partner| {
+                let networks = partner
+                    .networks
+                    .iter()
+                    .map(|network| Network::from_str(network))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(PartnerRule {
+                    name: partner.name,
+                    networks,
+                    max_requests: partner.max_requests,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(RateLimitConfig {
+            interval_millis: allowlist.interval_millis.unwrap_or(self.default_interval_millis),
+            max_requests: allowlist.max_requests.unwrap_or(self.default_max_requests),
+            partners,
+        })
+    }
+
+    /// Counts a request of the client, clients without a known address share one quota.
+    pub fn check(&self, ip: Option<IpAddr>, now: u64) -> Decision {
+        let config = self.config.read().unwrap();
+        let partner = ip.and_then(|ip| {
+            config
+                .partners
+                .iter()
+                .find(|partner| partner.networks.iter().any(|network| network.contains(ip)))
+        });
+
+        let (key, limit) = match partner {
+            Some(partner) => {
+                self.metrics.partner_requests.fetch_add(1, Ordering::Relaxed);
+                match partner.max_requests {
+                    Some(limit) => (format!("partner:{}", partner.name), limit),
+                    None => return Decision::Unlimited,
+                }
+            }
+            None => match ip {
+                Some(ip) => (ip.to_string(), config.max_requests),
+                None => (String::from("unknown"), config.max_requests),
+            },
+        };
+
+        let mut windows = self.windows.lock().unwrap();
+        let (window_start, count) = windows.entry(key).or_insert((now, 0));
+        if now >= *window_start + config.interval_millis {
+            *window_start = now;
+            *count = 0;
+        }
+        let reset_secs = (*window_start + config.interval_millis).saturating_sub(now) / 1000;
+
+        if *count >= limit {
+            self.metrics.throttled.fetch_add(1, Ordering::Relaxed);
+            if partner.is_some() {
+                self.metrics.partner_throttled.fetch_add(1, Ordering::Relaxed);
+            }
+            return Decision::Throttle { limit, reset_secs };
+        }
+        *count += 1;
+        self.metrics.allowed.fetch_add(1, Ordering::Relaxed);
+        Decision::Allow {
+            limit,
+            remaining: limit - *count,
+            reset_secs,
+        }
+    }
+
+    pub fn metrics(&self) -> serde_json::Value {
+        let config = self.config.read().unwrap();
+        json!({
+            "allowed": self.metrics.allowed.load(Ordering::Relaxed),
+            "throttled": self.metrics.throttled.load(Ordering::Relaxed),
+            "partner_requests": self.metrics.partner_requests.load(Ordering::Relaxed),
+            "partner_throttled": self.metrics.partner_throttled.load(Ordering::Relaxed),
+            "reloads": self.metrics.reloads.load(Ordering::Relaxed),
+            "reload_failures": self.metrics.reload_failures.load(Ordering::Relaxed),
+            "interval_millis": config.interval_millis,
+            "max_requests": config.max_requests,
+            "partners": config.partners.len(),
+            "tracked_clients": self.windows.lock().unwrap().len(),
+        })
+    }
+
+    fn prune(&self, now: u64) {
+        let interval_millis = self.config.read().unwrap().interval_millis;
+        self.windows
+            .lock()
+            .unwrap()
+            .retain(|_, (window_start, _)| now < *window_start + interval_millis);
+    }
+
+    /// Reloads the allowlist when the file changed and drops the windows of clients that went quiet.
+    pub async fn start(limiter: Arc<IpRateLimiter>) {
+        let mut last_modified = limiter.allowlist_modified();
+        let mut interval = tokio::time::interval(Duration::from_secs(RELOAD_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            limiter.prune(utils::time::time_now());
+
+            let modified = limiter.allowlist_modified();
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            match limiter.reload() {
+                Ok(()) => println!("Reloaded rate limit allowlist"),
+                Err(err) => eprintln!("Failed to reload rate limit allowlist: {}", err),
+            }
+        }
+    }
+
+    fn allowlist_modified(&self) -> Option<SystemTime> {
+        let path = self.allowlist_path.as_ref()?;
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+fn parse_addr(addr: &str) -> Option<IpAddr> {
+    SocketAddr::from_str(addr)
+        .map(|addr| addr.ip())
+        .or_else(|_| IpAddr::from_str(addr))
+        .ok()
+}
+
+/// Proxies in front of the api, e.g. `10.0.0.0/8`. The addresses they forward in `X-Forwarded-For` are used
+/// instead of theirs, headers of any other peer are ignored since clients can set them to anything.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<Network>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: &[String]) -> Result<Self, String> {
+        let networks = networks
+            .iter()
+            .map(|network| Network::from_str(network))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { networks })
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Address of the client. Hops are followed from the peer back through `forwarded_for` as long as they are
+    /// trusted proxies, the first one that isn't is the client.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let mut client = peer?;
+        for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+            if !self.is_trusted(client) {
+                break;
+            }
+            match parse_addr(hop.trim()) {
+                Some(ip) => client = ip,
+                None => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Address of the client the request is counted for, see [`TrustedProxies::client_ip`].
+pub fn client_ip(req: &ServiceRequest, proxies: &TrustedProxies) -> Option<IpAddr> {
+    let forwarded_for = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok());
+    proxies.client_ip(req.peer_addr().map(|addr| addr.ip()), forwarded_for)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partners_get_their_own_quota() {
+        let limiter = IpRateLimiter::new(1000, 1, None);
+        *limiter.config.write().unwrap() = limiter
+            .compile(Allowlist {
+                partners: vec![
+                    Partner {
+                        name: String::from("exchange"),
+                        networks: vec![String::from("203.0.113.0/24")],
+                        max_requests: Some(2),
+                    },
+                    Partner {
+                        name: String::from("wallet"),
+                        networks: vec![String::from("2001:db8::1")],
+                        max_requests: None,
+                    },
+                ],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let ip = |ip: &str| IpAddr::from_str(ip).ok();
+        let client = ip("198.51.100.7");
+        assert!(matches!(limiter.check(client, 0), Decision::Allow { remaining: 0, .. }));
+        assert!(matches!(limiter.check(client, 10), Decision::Throttle { .. }));
+        assert!(matches!(limiter.check(client, 1000), Decision::Allow { .. }));
+
+        // Addresses of a partner share its quota.
+        assert!(matches!(limiter.check(ip("203.0.113.1"), 0), Decision::Allow { .. }));
+        assert!(matches!(limiter.check(ip("203.0.113.2"), 0), Decision::Allow { .. }));
+        assert!(matches!(limiter.check(ip("203.0.113.3"), 0), Decision::Throttle { .. }));
+
+        assert_eq!(limiter.check(ip("2001:db8::1"), 0), Decision::Unlimited);
+        assert!(Network::from_str("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn forwarded_addresses_are_only_taken_from_trusted_proxies() {
+        let proxies = TrustedProxies::new(&[String::from("10.0.0.0/8")]).unwrap();
+        let ip = |ip: &str| IpAddr::from_str(ip).ok();
+
+        // Clients can't pick their address by sending the header themselves.
+        assert_eq!(
+            proxies.client_ip(ip("198.51.100.7"), Some("203.0.113.1")),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.2"), Some("198.51.100.7")),
+            ip("198.51.100.7")
+        );
+        // Only the hops added by trusted proxies count, whatever the client prepended.
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.2"), Some("203.0.113.1, 198.51.100.7, 10.0.0.1")),
+            ip("198.51.100.7")
+        );
+        assert_eq!(proxies.client_ip(ip("10.0.0.2"), None), ip("10.0.0.2"));
+        assert_eq!(
+            TrustedProxies::default().client_ip(ip("10.0.0.2"), Some("198.51.100.7")),
+            ip("10.0.0.2")
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use diesel::{r2d2::ConnectionManager, PgConnection};
+use rust_decimal::prelude::Decimal;
+use xerror::api::*;
+
+use core_types::{Account, AccountClass, AccountType, Currency, DbPool, RequestId, UserId};
+use models::account_members::AccountMember;
+use models::accounts;
+use models::node_info::NodeInfo;
+use msgs::api::{Balances, GetNodeInfoResponse};
+
+/// Serves reads from a Postgres replica instead of the bank, which is left to process mutations.
+/// Balances lag the bank by the replication delay and pending balances aren't persisted, so they're always zero.
+pub struct ReadReplica {
+    pool: DbPool,
+}
+
+impl ReadReplica {
+    pub fn new(psql_url: &str) -> Self {
+        Self {
+            pool: r2d2::Pool::builder().build_unchecked(ConnectionManager::<PgConnection>::new(psql_url)),
+        }
+    }
+
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    pub fn balances(&self, req_id: RequestId, uid: UserId) -> Result<Balances, ApiError> {
+        let conn = self.pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+        let accounts = accounts::Account::get_by_uid(&conn, uid as i32)
+            .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+            .into_iter()
+            .filter_map(to_account)
+            .map(|account| (account.account_id, account))
+            .collect();
+
+        let shared_accounts = AccountMember::get_by_uid(&conn, uid as i32)
+            .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+            .into_iter()
+            .filter(|member| member.accepted)
+            .filter_map(|member| accounts::Account::get_by_account_id(&conn, member.account_id).ok())
+            .filter_map(to_account)
+            .map(|account| (account.account_id, account))
+            .collect::<HashMap<_, _>>();
+
+        Ok(Balances {
+            req_id,
+            uid,
+            accounts,
+            shared_accounts,
+            error: None,
+        })
+    }
+
+    /// Node info the bank last stored.
+    pub fn node_info(&self, req_id: RequestId) -> Result<GetNodeInfoResponse, ApiError> {
+        let conn = self.pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+        let node_info = NodeInfo::get(&conn).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+        let mut response = serde_json::from_str::<GetNodeInfoResponse>(&node_info.payload)
+            .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+        response.req_id = req_id;
+        Ok(response)
+    }
+}
+
+fn to_account(account: accounts::Account) -> Option<Account> {
+    Some(Account {
+        account_id: account.account_id,
+        balance: Decimal::from_str(&account.balance.to_string()).ok()?,
+        currency: Currency::from_str(&account.currency).ok()?,
+        account_type: AccountType::from_str(&account.account_type).ok()?,
+        account_class: AccountClass::from_str(&account.account_class).ok()?,
+        pending_balance: Decimal::ZERO,
+        label: account.label,
+        archived: account.archived,
+    })
+}
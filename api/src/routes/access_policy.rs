@@ -0,0 +1,99 @@
+use actix_web::{delete, get, post, web::Json, HttpResponse};
+use core_types::access::{is_valid_ip_range, AccessPolicy as Policy};
+use serde::Deserialize;
+use serde_json::json;
+use xerror::api::*;
+
+use models::access_policies::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+const MAX_POLICY_ENTRIES: usize = 32;
+
+#[derive(Deserialize)]
+pub struct AccessPolicyData {
+    #[serde(default)]
+    pub allowed_ip_ranges: Vec<String>,
+    #[serde(default)]
+    pub allowed_countries: Vec<String>,
+}
+
+fn join_list(list: &[String]) -> Option<String> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(list.join(","))
+    }
+}
+
+#[post("/access_policy")]
+pub async fn set_access_policy(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<AccessPolicyData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let allowed_ip_ranges = data
+        .allowed_ip_ranges
+        .iter()
+        .map(|range| range.trim().to_string())
+        .collect::<Vec<_>>();
+    let allowed_countries = data
+        .allowed_countries
+        .iter()
+        .map(|country| country.trim().to_uppercase())
+        .collect::<Vec<_>>();
+
+    if allowed_ip_ranges.len() + allowed_countries.len() > MAX_POLICY_ENTRIES
+        || !allowed_ip_ranges.iter().all(|range| is_valid_ip_range(range))
+        || !allowed_countries
+            .iter()
+            .all(|country| country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let policy = Policy {
+        allowed_ip_ranges,
+        allowed_countries,
+    };
+
+    // Users can't lock themselves out from where they are setting the policy.
+    if !policy.allows(&auth_data.origin) {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let access_policy = AccessPolicy {
+        uid: auth_data.uid,
+        allowed_ip_ranges: join_list(&policy.allowed_ip_ranges),
+        allowed_countries: join_list(&policy.allowed_countries),
+        updated_at: utils::time::time_now() as i64,
+    };
+
+    access_policy
+        .upsert(&conn)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(&access_policy))
+}
+
+#[get("/access_policy")]
+pub async fn get_access_policy(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let access_policy =
+        AccessPolicy::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&access_policy))
+}
+
+#[delete("/access_policy")]
+pub async fn delete_access_policy(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    AccessPolicy::delete(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(json!({ "uid": auth_data.uid })))
+}
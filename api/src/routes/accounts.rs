@@ -0,0 +1,262 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{
+    delete, get, post, put,
+    web::{Json, Path},
+    HttpResponse,
+};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use core_types::{Currency, MemberRole, UserId};
+use models::account_members::AccountMember as AccountMemberRecord;
+use models::accounts::Account;
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::{WebDbPool, WebSender};
+
+const MAX_LABEL_LENGTH: usize = 64;
+
+#[derive(Deserialize)]
+pub struct CreateAccountData {
+    pub currency: Currency,
+    pub label: String,
+    #[serde(default)]
+    pub vault: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RenameAccountData {
+    pub label: String,
+}
+
+#[derive(Deserialize)]
+pub struct InviteMemberData {
+    pub username: String,
+    pub role: MemberRole,
+    /// Largest amount a spender can pay from the account at once, in the account's currency.
+    pub spend_limit: Option<Decimal>,
+}
+
+fn validate_label(label: &str) -> Result<(), ApiError> {
+    if label.trim().is_empty() || label.len() > MAX_LABEL_LENGTH {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+    Ok(())
+}
+
+/// Sends an account request to the bank and waits for its `AccountResponse`.
+async fn send_account_request(web_sender: WebSender, req_id: Uuid, message: Message) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::AccountResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::AccountResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+/// Opens a labeled sub-account, its id can be used for invoices, payments and transfers.
+#[post("/accounts")]
+pub async fn create_account(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<CreateAccountData>,
+) -> Result<HttpResponse, ApiError> {
+    validate_label(&data.label)?;
+
+    let req_id = Uuid::new_v4();
+    let request = CreateAccountRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        currency: data.currency,
+        label: data.label.clone(),
+        vault: data.vault,
+    };
+
+    send_account_request(web_sender, req_id, Message::Api(Api::CreateAccountRequest(request))).await
+}
+
+#[put("/accounts/{account_id}")]
+pub async fn rename_account(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    path: Path<Uuid>,
+    data: Json<RenameAccountData>,
+) -> Result<HttpResponse, ApiError> {
+    validate_label(&data.label)?;
+
+    let req_id = Uuid::new_v4();
+    let request = RenameAccountRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id: path.into_inner(),
+        label: data.label.clone(),
+    };
+
+    send_account_request(web_sender, req_id, Message::Api(Api::RenameAccountRequest(request))).await
+}
+
+#[post("/accounts/{account_id}/archive")]
+pub async fn archive_account(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    path: Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+    let request = ArchiveAccountRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id: path.into_inner(),
+    };
+
+    send_account_request(web_sender, req_id, Message::Api(Api::ArchiveAccountRequest(request))).await
+}
+
+/// Sends a membership request to the bank and waits for its `AccountMemberResponse`.
+async fn send_member_request(web_sender: WebSender, req_id: Uuid, message: Message) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::AccountMemberResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::AccountMemberResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+/// Invites a user to the account, the invitation has to be accepted before the account can be used.
+#[post("/accounts/{account_id}/members")]
+pub async fn invite_account_member(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    path: Path<Uuid>,
+    data: Json<InviteMemberData>,
+) -> Result<HttpResponse, ApiError> {
+    if matches!(data.spend_limit, Some(limit) if limit <= Decimal::ZERO) {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let req_id = Uuid::new_v4();
+    let request = InviteAccountMemberRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id: path.into_inner(),
+        username: data.username.clone(),
+        role: data.role,
+        spend_limit: data.spend_limit,
+    };
+
+    send_member_request(
+        web_sender,
+        req_id,
+        Message::Api(Api::InviteAccountMemberRequest(request)),
+    )
+    .await
+}
+
+#[post("/accounts/{account_id}/members/accept")]
+pub async fn accept_account_invitation(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    path: Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+    let request = AcceptAccountInvitationRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id: path.into_inner(),
+    };
+
+    send_member_request(
+        web_sender,
+        req_id,
+        Message::Api(Api::AcceptAccountInvitationRequest(request)),
+    )
+    .await
+}
+
+/// Removes a member from the account, members can also remove themselves.
+#[delete("/accounts/{account_id}/members/{member_uid}")]
+pub async fn remove_account_member(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    path: Path<(Uuid, UserId)>,
+) -> Result<HttpResponse, ApiError> {
+    let (account_id, member_uid) = path.into_inner();
+
+    let req_id = Uuid::new_v4();
+    let request = RemoveAccountMemberRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id,
+        member_uid,
+    };
+
+    send_member_request(
+        web_sender,
+        req_id,
+        Message::Api(Api::RemoveAccountMemberRequest(request)),
+    )
+    .await
+}
+
+/// Lists the members of the account to its owner and to its members.
+#[get("/accounts/{account_id}/members")]
+pub async fn get_account_members(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    path: Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let account_id = path.into_inner();
+    let account = Account::get_by_account_id(&conn, account_id)
+        .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+
+    let members = AccountMemberRecord::get_by_account_id(&conn, account_id)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    let is_member = members
+        .iter()
+        .any(|member| member.uid == auth_data.uid && member.accepted);
+    if account.uid != auth_data.uid && !is_member {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    Ok(HttpResponse::Ok().json(&members))
+}
@@ -0,0 +1,141 @@
+use actix_web::{
+    delete, get, post, put,
+    web::{Json, Path},
+    HttpResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use xerror::api::*;
+
+use models::api_keys::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+/// Tokens of API keys and devices stay valid for a year unless they are revoked.
+const API_KEY_LIFETIME_SECS: i64 = 365 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyData {
+    pub label: String,
+    /// Whether the token is for a bot integration or a device such as a kiosk, defaults to an API key.
+    #[serde(default)]
+    pub device: bool,
+    pub roles: HashSet<ApiRole>,
+    /// Max value the token pays out over a day, in sats. Unlimited if not set.
+    pub daily_budget_sats: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyBudgetData {
+    pub daily_budget_sats: Option<u64>,
+}
+
+/// Only master tokens manage keys, so a key can't raise its own budget.
+fn require_master(auth_data: &AuthData) -> Result<(), ApiError> {
+    if auth_data.is_master() {
+        Ok(())
+    } else {
+        Err(ApiError::Auth(AuthError::NotPermitted))
+    }
+}
+
+fn budget(daily_budget_sats: Option<u64>) -> Result<Option<i64>, ApiError> {
+    daily_budget_sats
+        .map(|budget| i64::try_from(budget).map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied)))
+        .transpose()
+}
+
+/// Issues a token for an API key or device, payments made with it are limited to its daily budget.
+#[post("/api_keys")]
+pub async fn create_api_key(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<CreateApiKeyData>,
+) -> Result<HttpResponse, ApiError> {
+    require_master(&auth_data)?;
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let label = data.label.trim();
+    if label.is_empty() || data.roles.is_empty() {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let mut roles = data.roles.iter().map(|role| role.to_string()).collect::<Vec<String>>();
+    roles.sort();
+    let key = InsertableApiKey {
+        uid: auth_data.uid,
+        label: label.to_string(),
+        kind: if data.device { KIND_DEVICE } else { KIND_API_KEY }.to_string(),
+        roles: roles.join(","),
+        daily_budget_sats: budget(data.daily_budget_sats)?,
+        created_at: utils::time::time_now() as i64,
+    };
+    let tid = key.insert(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    let token = jwt_generate(
+        auth_data.uid,
+        Some(tid),
+        UserRoles::ApiToken(data.roles.clone()),
+        API_KEY_LIFETIME_SECS,
+    )
+    .map_err(ApiError::JWT)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "tid": tid,
+        "token": token,
+        "label": key.label,
+        "kind": key.kind,
+        "roles": key.roles,
+        "daily_budget_sats": key.daily_budget_sats,
+    })))
+}
+
+/// Keys and devices of the user that weren't revoked.
+#[get("/api_keys")]
+pub async fn get_api_keys(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    require_master(&auth_data)?;
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let keys = ApiKey::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&keys))
+}
+
+#[put("/api_keys/{tid}/budget")]
+pub async fn set_api_key_budget(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    path: Path<i32>,
+    data: Json<ApiKeyBudgetData>,
+) -> Result<HttpResponse, ApiError> {
+    require_master(&auth_data)?;
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let tid = path.into_inner();
+    let daily_budget_sats = budget(data.daily_budget_sats)?;
+    let updated = ApiKey::update_daily_budget(&conn, tid, auth_data.uid, daily_budget_sats)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+    if updated == 0 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "tid": tid, "daily_budget_sats": daily_budget_sats })))
+}
+
+/// Revokes a key or device, the bank refuses payments made with its token from then on.
+#[delete("/api_keys/{tid}")]
+pub async fn revoke_api_key(pool: WebDbPool, auth_data: AuthData, path: Path<i32>) -> Result<HttpResponse, ApiError> {
+    require_master(&auth_data)?;
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let tid = path.into_inner();
+    let revoked = ApiKey::revoke(&conn, tid, auth_data.uid, utils::time::time_now() as i64)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+    if revoked == 0 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "tid": tid })))
+}
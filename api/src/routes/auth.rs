@@ -1,11 +1,13 @@
 use actix_web::{get, post, web::Json, HttpResponse};
 use diesel::result::DatabaseErrorKind;
 use diesel::result::Error as DieselError;
+use diesel::Connection;
 use serde::Deserialize;
 use serde_json::json;
 use uuid::Uuid;
 use xerror::api::*;
 
+use models::referrals::Referral;
 use models::users::*;
 
 use crate::jwt::*;
@@ -19,6 +21,8 @@ pub struct RegisterData {
     pub username: Option<String>,
     /// Password field on supplied json.
     pub password: String,
+    /// Username of the user who referred the new user, if any.
+    pub referrer: Option<String>,
 }
 
 #[post("/create")]
@@ -30,6 +34,14 @@ pub async fn create(pool: WebDbPool, register_data: Json<RegisterData>) -> Resul
         None => Uuid::new_v4().to_string().to_lowercase(),
     };
 
+    let referrer = match &register_data.referrer {
+        Some(referrer) => match User::get_by_username(&conn, referrer.to_lowercase()) {
+            Ok(referrer) if !referrer.is_internal => Some(referrer),
+            _ => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+        },
+        None => None,
+    };
+
     let hashed_password = hash(&username, &register_data.password);
 
     let user = InsertableUser {
@@ -38,7 +50,20 @@ pub async fn create(pool: WebDbPool, register_data: Json<RegisterData>) -> Resul
         is_internal: false,
     };
 
-    if let Err(error) = user.insert(&conn) {
+    let result = conn.transaction::<_, DieselError, _>(|| {
+        let uid = user.insert(&conn)?;
+        if let Some(referrer) = &referrer {
+            Referral {
+                referee_uid: uid,
+                referrer_uid: referrer.uid,
+                created_at: utils::time::time_now() as i64,
+            }
+            .insert(&conn)?;
+        }
+        Ok(uid)
+    });
+
+    if let Err(error) = result {
         match error {
             DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
                 return Err(ApiError::Db(DbError::UserAlreadyExists))
@@ -0,0 +1,202 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::{
+    get,
+    http::header,
+    post,
+    web::{Bytes, Json, Path, Query},
+    HttpResponse,
+};
+use futures::stream;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::time::{timeout, timeout_at, Instant};
+use uuid::Uuid;
+use xerror::api::*;
+
+use core_types::RequestId;
+use models::data_exports::DataExport;
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::{WebBroadcast, WebDbPool, WebSender};
+
+/// Archives are assembled from the whole history of a user which takes a while.
+const DATA_EXPORT_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Deserialize)]
+pub struct ExportMyDataParams {
+    /// Either `link` or `inline`, defaults to `link`.
+    pub delivery: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadParams {
+    pub expires_at: u64,
+    pub signature: String,
+}
+
+fn download_path(export_id: Uuid) -> String {
+    format!("/export/{}", export_id)
+}
+
+/// Assembles an archive of the user's accounts, transactions, invoices and preferences. It is either
+/// streamed right away or stored for a single download through the returned signed link.
+#[post("/export")]
+pub async fn export_my_data(
+    web_sender: WebSender,
+    broadcast: WebBroadcast,
+    auth_data: AuthData,
+    data: Json<ExportMyDataParams>,
+) -> Result<HttpResponse, ApiError> {
+    let delivery = match data.delivery.as_deref() {
+        None | Some("link") => DataExportDelivery::Link,
+        Some("inline") => DataExportDelivery::Inline,
+        Some(_) => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+    };
+
+    let req_id = Uuid::new_v4();
+    let request = ExportMyDataRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        delivery,
+    };
+
+    match delivery {
+        DataExportDelivery::Link => export_as_link(web_sender, req_id, request).await,
+        DataExportDelivery::Inline => export_inline(web_sender, broadcast, req_id, request).await,
+    }
+}
+
+async fn export_as_link(
+    web_sender: WebSender,
+    req_id: RequestId,
+    request: ExportMyDataRequest,
+) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::ExportMyDataResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::ExportMyDataRequest(request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::ExportMyDataResponse(response))))) =
+        timeout(Duration::from_secs(DATA_EXPORT_TIMEOUT_SECS), response_rx.recv()).await
+    {
+        let (export_id, expires_at) = match (response.export_id, response.expires_at) {
+            (Some(export_id), Some(expires_at)) => (export_id, expires_at),
+            _ => return Ok(HttpResponse::Ok().json(&response)),
+        };
+        let path = download_path(export_id);
+        let signature = sign_link(&path, expires_at);
+        return Ok(HttpResponse::Ok().json(json!({
+            "export_id": export_id,
+            "expires_at": expires_at,
+            "url": format!("{}?expires_at={}&signature={}", path, expires_at, signature),
+        })));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+async fn export_inline(
+    web_sender: WebSender,
+    broadcast: WebBroadcast,
+    req_id: RequestId,
+    request: ExportMyDataRequest,
+) -> Result<HttpResponse, ApiError> {
+    // The chunks follow the response, so all messages are read from the broadcast which is subscribed to
+    // before sending the request.
+    let mut receiver = broadcast.subscribe();
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message: Message::Api(Api::ExportMyDataRequest(request)),
+            response_tx: None,
+            response_filter: None,
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    let deadline = Instant::now() + Duration::from_secs(DATA_EXPORT_TIMEOUT_SECS);
+    let response = loop {
+        match timeout_at(deadline, receiver.recv()).await {
+            Ok(Ok(Message::Api(Api::ExportMyDataResponse(response)))) if response.req_id == req_id => break response,
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => return Err(ApiError::Comms(CommsError::ServerResponseTimeout)),
+        }
+    };
+
+    if response.error.is_some() {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+
+    let total = response.chunks;
+    let chunks = stream::unfold(
+        (receiver, 0),
+        move |(mut receiver, index): (broadcast::Receiver<Message>, usize)| async move {
+            if index == total {
+                return None;
+            }
+            loop {
+                match timeout_at(deadline, receiver.recv()).await {
+                    Ok(Ok(Message::Api(Api::ExportMyDataChunk(chunk)))) if chunk.req_id == req_id => {
+                        // A missed chunk would corrupt the archive, so the stream is cut off instead.
+                        if chunk.index != index {
+                            return None;
+                        }
+                        return Some((
+                            Ok::<_, actix_web::Error>(Bytes::from(chunk.data)),
+                            (receiver, index + 1),
+                        ));
+                    }
+                    Ok(Ok(_)) => continue,
+                    _ => return None,
+                }
+            }
+        },
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((header::CONTENT_DISPOSITION, "attachment; filename=\"export.json\""))
+        .streaming(chunks))
+}
+
+/// Hands out a stored archive once, the signed link replaces the token.
+#[get("/export/{export_id}")]
+pub async fn download_my_data(
+    pool: WebDbPool,
+    path: Path<Uuid>,
+    params: Query<DownloadParams>,
+) -> Result<HttpResponse, ApiError> {
+    let export_id = path.into_inner();
+    let now = utils::time::time_now();
+    if params.expires_at <= now || !verify_link(&download_path(export_id), params.expires_at, &params.signature) {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let export = DataExport::take(&conn, export_id, now as i64)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+        .ok_or(ApiError::Request(RequestError::InvalidDataSupplied))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((header::CONTENT_DISPOSITION, "attachment; filename=\"export.json\""))
+        .body(export.content))
+}
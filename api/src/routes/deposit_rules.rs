@@ -0,0 +1,106 @@
+use actix_web::{
+    delete, get, post,
+    web::{Json, Path},
+    HttpResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+use xerror::api::*;
+
+use models::accounts::Account;
+use models::deposit_routing_rules::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+const MAX_RULES_PER_USER: usize = 20;
+const MAX_PATTERN_LENGTH: usize = 128;
+
+#[derive(Deserialize)]
+pub struct DepositRuleData {
+    /// Account matching deposits are credited to, it has to be of the invoice's currency.
+    pub account_id: Uuid,
+    pub memo_pattern: Option<String>,
+    pub metadata_key: Option<String>,
+    pub priority: Option<i32>,
+}
+
+fn validate_condition(condition: &Option<String>) -> Result<(), ApiError> {
+    match condition {
+        Some(condition) if condition.is_empty() || condition.len() > MAX_PATTERN_LENGTH => {
+            Err(ApiError::Request(RequestError::InvalidDataSupplied))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[post("/deposit_rules")]
+pub async fn create_deposit_rule(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<DepositRuleData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    if data.memo_pattern.is_none() && data.metadata_key.is_none() {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+    validate_condition(&data.memo_pattern)?;
+    validate_condition(&data.metadata_key)?;
+
+    match Account::get_by_account_id(&conn, data.account_id) {
+        Ok(account) if account.uid == auth_data.uid => {}
+        _ => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+    }
+
+    let rules =
+        DepositRoutingRule::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    if rules.len() >= MAX_RULES_PER_USER {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let rule = InsertableDepositRoutingRule {
+        uid: auth_data.uid,
+        account_id: data.account_id,
+        memo_pattern: data.memo_pattern.clone(),
+        metadata_key: data.metadata_key.clone(),
+        priority: data.priority.unwrap_or(0),
+        created_at: utils::time::time_now() as i64,
+    };
+
+    let rule = rule.insert(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(&rule))
+}
+
+#[get("/deposit_rules")]
+pub async fn get_deposit_rules(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let rules =
+        DepositRoutingRule::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&rules))
+}
+
+#[delete("/deposit_rules/{rule_id}")]
+pub async fn delete_deposit_rule(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    path: Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let rule_id = path.into_inner();
+
+    let deleted =
+        DepositRoutingRule::delete(&conn, rule_id, auth_data.uid).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    if deleted == 0 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "rule_id": rule_id })))
+}
@@ -0,0 +1,49 @@
+use actix_web::{get, put, web::Json, HttpResponse};
+use serde::Deserialize;
+use xerror::api::*;
+
+use models::dust_sweep_preferences::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+#[derive(Deserialize)]
+pub struct DustSweepingData {
+    pub enabled: bool,
+}
+
+/// Opts in or out of having tiny fiat balances swapped back to the BTC account.
+#[put("/dust_sweeping")]
+pub async fn set_dust_sweeping(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<DustSweepingData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let preference = DustSweepPreference {
+        uid: auth_data.uid,
+        enabled: data.enabled,
+        updated_at: utils::time::time_now() as i64,
+    };
+
+    preference
+        .upsert(&conn)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(&preference))
+}
+
+#[get("/dust_sweeping")]
+pub async fn get_dust_sweeping(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    // Users that never set a preference are opted out.
+    let preference = DustSweepPreference::get_by_uid(&conn, auth_data.uid).unwrap_or(DustSweepPreference {
+        uid: auth_data.uid,
+        enabled: false,
+        updated_at: 0,
+    });
+
+    Ok(HttpResponse::Ok().json(&preference))
+}
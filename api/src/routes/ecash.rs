@@ -0,0 +1,100 @@
+use actix_web::{post, web::Json, HttpResponse};
+use core_types::Money;
+use rust_decimal::prelude::Decimal;
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::WebSender;
+
+#[derive(Deserialize)]
+pub struct EcashWithdrawData {
+    /// Amount in sats.
+    pub amount: Decimal,
+    pub account_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct EcashDepositData {
+    pub notes: String,
+    pub account_id: Option<Uuid>,
+}
+
+async fn send_ecash_request(web_sender: WebSender, req_id: Uuid, message: Message) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(move |message| {
+        matches!(message, Message::Api(Api::EcashWithdrawResponse(response)) if response.req_id == req_id)
+            || matches!(message, Message::Api(Api::EcashDepositResponse(response)) if response.req_id == req_id)
+    });
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    // Both directions wait for a payment through the federation's gateway.
+    match timeout(Duration::from_secs(120), response_rx.recv()).await {
+        Ok(Some(Ok(Message::Api(Api::EcashWithdrawResponse(response))))) => Ok(HttpResponse::Ok().json(&response)),
+        Ok(Some(Ok(Message::Api(Api::EcashDepositResponse(response))))) => Ok(HttpResponse::Ok().json(&response)),
+        _ => Err(ApiError::Comms(CommsError::ServerResponseTimeout)),
+    }
+}
+
+/// Withdraws BTC into ecash notes of the bank's federation.
+#[post("/ecash/withdraw")]
+pub async fn withdraw_ecash(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<EcashWithdrawData>,
+) -> Result<HttpResponse, ApiError> {
+    if data.amount <= Decimal::ZERO {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let req_id = Uuid::new_v4();
+
+    let request = EcashWithdrawRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        amount: Money::from_sats(data.amount),
+        account_id: data.account_id,
+    };
+
+    send_ecash_request(web_sender, req_id, Message::Api(Api::EcashWithdrawRequest(request))).await
+}
+
+/// Deposits ecash notes of the bank's federation into a BTC account.
+#[post("/ecash/deposit")]
+pub async fn deposit_ecash(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<EcashDepositData>,
+) -> Result<HttpResponse, ApiError> {
+    if data.notes.is_empty() {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let req_id = Uuid::new_v4();
+
+    let request = EcashDepositRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        notes: data.notes.clone(),
+        account_id: data.account_id,
+    };
+
+    send_ecash_request(web_sender, req_id, Message::Api(Api::EcashDepositRequest(request))).await
+}
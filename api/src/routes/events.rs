@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use actix_web::{
+    get,
+    web::{Bytes, Data, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use core_types::RequestId;
+use futures::stream;
+use serde::Deserialize;
+use serde_json::json;
+use xerror::api::*;
+
+use crate::events::{CompletionEvent, CompletionEvents};
+use crate::jwt::*;
+
+/// Long-polls never wait longer than this.
+const MAX_WAIT_SECS: u64 = 30;
+const DEFAULT_WAIT_SECS: u64 = 25;
+/// Streams are closed after this long, clients reconnect with the `Last-Event-ID` they received.
+const MAX_STREAM_SECS: u64 = 300;
+
+#[derive(Deserialize)]
+pub struct WaitParams {
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct EventsParams {
+    /// Only events after this cursor are returned, defaults to the latest event.
+    pub cursor: Option<u64>,
+    pub timeout_secs: Option<u64>,
+}
+
+fn wait_duration(timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_WAIT_SECS).min(MAX_WAIT_SECS))
+}
+
+/// Resolves once the response to `req_id` arrived or the wait elapsed.
+#[get("/events/{req_id}")]
+pub async fn wait_for_response(
+    events: Data<CompletionEvents>,
+    auth_data: AuthData,
+    path: Path<RequestId>,
+    params: Query<WaitParams>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = path.into_inner();
+    let uid = auth_data.uid as u64;
+
+    match events
+        .wait_for(wait_duration(params.timeout_secs), || events.get(req_id, uid))
+        .await
+    {
+        Some(event) => Ok(HttpResponse::Ok().json(&event)),
+        None => Ok(HttpResponse::Accepted().json(json!({ "req_id": req_id, "status": "pending" }))),
+    }
+}
+
+/// Returns the events of the user after the cursor, waiting for the first one if there are none yet.
+#[get("/events")]
+pub async fn poll_events(
+    events: Data<CompletionEvents>,
+    auth_data: AuthData,
+    params: Query<EventsParams>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+    let cursor = params.cursor.unwrap_or_else(|| events.latest_seq());
+
+    let received = events
+        .wait_for(wait_duration(params.timeout_secs), || {
+            let received = events.since(uid, cursor);
+            if received.is_empty() {
+                None
+            } else {
+                Some(received)
+            }
+        })
+        .await
+        .unwrap_or_default();
+
+    let cursor = received.last().map_or(cursor, |event| event.seq);
+
+    Ok(HttpResponse::Ok().json(json!({ "cursor": cursor, "events": received })))
+}
+
+fn sse_frame(event: &CompletionEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!("id: {}\nevent: completion\ndata: {}\n\n", event.seq, data))
+}
+
+/// Streams the events of the user as server-sent events.
+#[get("/events_stream")]
+pub async fn stream_events(
+    req: HttpRequest,
+    events: Data<CompletionEvents>,
+    auth_data: AuthData,
+    params: Query<EventsParams>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let cursor = last_event_id.or(params.cursor).unwrap_or_else(|| events.latest_seq());
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(MAX_STREAM_SECS);
+
+    let events = events.into_inner();
+    let stream = stream::unfold((events, cursor), move |(events, cursor)| async move {
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        let wait = (deadline - now).min(Duration::from_secs(MAX_WAIT_SECS));
+        let received = events
+            .wait_for(wait, || {
+                let received = events.since(uid, cursor);
+                if received.is_empty() {
+                    None
+                } else {
+                    Some(received)
+                }
+            })
+            .await;
+
+        let (frame, cursor) = match received {
+            Some(received) => {
+                let cursor = received.last().map_or(cursor, |event| event.seq);
+                let frame = received.iter().map(sse_frame).fold(Vec::new(), |mut frame, bytes| {
+                    frame.extend_from_slice(&bytes);
+                    frame
+                });
+                (Bytes::from(frame), cursor)
+            }
+            // Keeps proxies from closing an idle connection.
+            None => (Bytes::from_static(b": keep-alive\n\n"), cursor),
+        };
+
+        Some((Ok::<_, actix_web::Error>(frame), (events, cursor)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream))
+}
@@ -0,0 +1,51 @@
+use actix_web::http::header;
+use actix_web::{get, web::Query, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use xerror::api::*;
+
+use models::flow_statistics::*;
+
+use crate::WebDbPool;
+
+const DAY_MILLIS: u64 = 86_400_000;
+const DEFAULT_STATISTICS_DAYS: u64 = 30;
+const MAX_STATISTICS_DAYS: u64 = 365;
+/// The statistics only change once a day.
+const STATISTICS_MAX_AGE_SECS: u64 = 3600;
+
+#[derive(Deserialize)]
+pub struct FlowStatisticsParams {
+    pub days: Option<u64>,
+}
+
+/// Anonymized daily deposit, withdrawal and swap volumes of the last days, 30 by default. Only served if the
+/// operator opted in, the bank leaves out flows with too few transactions.
+#[get("/explorer/statistics")]
+pub async fn get_flow_statistics(
+    pool: WebDbPool,
+    params: Query<FlowStatisticsParams>,
+) -> Result<HttpResponse, ApiError> {
+    let days = params.days.unwrap_or(DEFAULT_STATISTICS_DAYS);
+    if days == 0 || days > MAX_STATISTICS_DAYS {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let to = utils::time::time_now();
+    let from = to.saturating_sub(days * DAY_MILLIS);
+
+    let statistics = FlowStatistic::get_between(&conn, from as i64, to as i64)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+        .iter()
+        .map(PublicFlowStatistic::from)
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", STATISTICS_MAX_AGE_SECS),
+        ))
+        .json(json!({ "from": from, "to": to, "statistics": statistics })))
+}
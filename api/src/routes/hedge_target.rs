@@ -0,0 +1,68 @@
+use actix_web::{delete, get, put, web::Json, HttpResponse};
+use bigdecimal::BigDecimal;
+use core_types::{currency_registry, Currency};
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use xerror::api::*;
+
+use models::hedge_targets::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+#[derive(Deserialize)]
+pub struct HedgeTargetData {
+    pub currency: Currency,
+    /// Share of the balance to keep in the currency, between 0 and 1.
+    pub target_ratio: Decimal,
+}
+
+/// Keeps a share of the user's balance in a fiat synthetic, the bank swaps between the main BTC and fiat
+/// accounts whenever the share drifts away from it.
+#[put("/hedge_target")]
+pub async fn set_hedge_target(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<HedgeTargetData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    if !currency_registry::synthetics().contains(&data.currency)
+        || data.target_ratio < Decimal::ZERO
+        || data.target_ratio > Decimal::ONE
+    {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let target_ratio = BigDecimal::from_str(&data.target_ratio.to_string())
+        .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+    let target = HedgeTarget {
+        uid: auth_data.uid,
+        currency: data.currency.to_string(),
+        target_ratio,
+        updated_at: utils::time::time_now() as i64,
+    };
+
+    target.upsert(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(&target))
+}
+
+#[get("/hedge_target")]
+pub async fn get_hedge_target(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let target = HedgeTarget::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&target))
+}
+
+#[delete("/hedge_target")]
+pub async fn delete_hedge_target(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    HedgeTarget::delete(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(json!({ "uid": auth_data.uid })))
+}
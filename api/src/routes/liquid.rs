@@ -0,0 +1,143 @@
+use actix_web::{
+    get, post,
+    web::{Json, Query},
+    HttpResponse,
+};
+use core_types::{Currency, Money};
+use rust_decimal::prelude::Decimal;
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::WebSender;
+
+#[derive(Deserialize)]
+pub struct LiquidAddressParams {
+    pub account_id: Option<Uuid>,
+    /// Hands out a Bitcoin address pegging into Liquid instead of a Liquid address.
+    #[serde(default)]
+    pub peg_in: bool,
+}
+
+#[derive(Deserialize)]
+pub struct LiquidClaimPegInData {
+    /// Hex of the Bitcoin transaction paying the peg-in address.
+    pub bitcoin_tx: String,
+    /// Hex of the proof the transaction was confirmed.
+    pub txout_proof: String,
+    pub claim_script: String,
+}
+
+#[derive(Deserialize)]
+pub struct LiquidWithdrawalData {
+    /// Liquid address, or Bitcoin address for peg-outs.
+    pub address: String,
+    /// Amount in L-BTC.
+    pub amount: Decimal,
+    pub account_id: Option<Uuid>,
+    #[serde(default)]
+    pub peg_out: bool,
+}
+
+async fn send_liquid_request(web_sender: WebSender, req_id: Uuid, message: Message) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(move |message| match message {
+        Message::Api(Api::LiquidAddressResponse(response)) => response.req_id == req_id,
+        Message::Api(Api::LiquidClaimPegInResponse(response)) => response.req_id == req_id,
+        Message::Api(Api::LiquidWithdrawalResponse(response)) => response.req_id == req_id,
+        _ => false,
+    });
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    match timeout(Duration::from_secs(30), response_rx.recv()).await {
+        Ok(Some(Ok(Message::Api(Api::LiquidAddressResponse(response))))) => Ok(HttpResponse::Ok().json(&response)),
+        Ok(Some(Ok(Message::Api(Api::LiquidClaimPegInResponse(response))))) => Ok(HttpResponse::Ok().json(&response)),
+        Ok(Some(Ok(Message::Api(Api::LiquidWithdrawalResponse(response))))) => Ok(HttpResponse::Ok().json(&response)),
+        _ => Err(ApiError::Comms(CommsError::ServerResponseTimeout)),
+    }
+}
+
+/// Hands out an address L-BTC, or BTC pegging into Liquid, is deposited to.
+#[get("/liquid/address")]
+pub async fn liquid_address(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    params: Query<LiquidAddressParams>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = LiquidAddressRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id: params.account_id,
+        peg_in: params.peg_in,
+    };
+
+    send_liquid_request(web_sender, req_id, Message::Api(Api::LiquidAddressRequest(request))).await
+}
+
+/// Claims a confirmed peg-in to an address handed out by `/liquid/address`.
+#[post("/liquid/pegin/claim")]
+pub async fn claim_liquid_pegin(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<LiquidClaimPegInData>,
+) -> Result<HttpResponse, ApiError> {
+    if data.bitcoin_tx.is_empty() || data.txout_proof.is_empty() || data.claim_script.is_empty() {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let req_id = Uuid::new_v4();
+
+    let request = LiquidClaimPegInRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        bitcoin_tx: data.bitcoin_tx.clone(),
+        txout_proof: data.txout_proof.clone(),
+        claim_script: data.claim_script.clone(),
+    };
+
+    send_liquid_request(web_sender, req_id, Message::Api(Api::LiquidClaimPegInRequest(request))).await
+}
+
+/// Sends L-BTC to a Liquid address or pegs it out to a Bitcoin address.
+#[post("/liquid/withdraw")]
+pub async fn withdraw_liquid(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<LiquidWithdrawalData>,
+) -> Result<HttpResponse, ApiError> {
+    if data.amount <= Decimal::ZERO || data.address.is_empty() {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let req_id = Uuid::new_v4();
+
+    let request = LiquidWithdrawalRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        account_id: data.account_id,
+        address: data.address.clone(),
+        amount: Money::new(Currency::LBTC, Some(data.amount)),
+        peg_out: data.peg_out,
+    };
+
+    send_liquid_request(web_sender, req_id, Message::Api(Api::LiquidWithdrawalRequest(request))).await
+}
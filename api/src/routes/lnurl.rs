@@ -24,6 +24,9 @@ use crate::WebDbPool;
 use crate::WebSender;
 
 const RANDOM_META_DATA: &str = "randomstring";
+/// Longest comment payers can send along with a payment (LUD-12).
+const MAX_COMMENT_LENGTH: usize = 255;
+const MAX_PAYER_DATA_LENGTH: usize = 1024;
 
 #[derive(Deserialize, Debug)]
 pub struct CreateLnurlWithdrawalParams {
@@ -54,6 +57,8 @@ pub async fn create_lnurl_withdrawal(
     currency: query.currency,
     rate: None,
     fees: None,
+    requoted: false,
+    origin: Some(auth_data.origin.clone()),
   };
 
   let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -175,6 +180,102 @@ pub async fn pay_lnurl_withdrawal(
   Err(ApiError::Comms(CommsError::ServerResponseTimeout))
 }
 
+#[get("/lnurl_channel")]
+pub async fn get_lnurl_channel(auth_data: AuthData, web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+  let req_id = Uuid::new_v4();
+
+  let request = GetLnurlChannelRequest {
+    req_id,
+    uid: auth_data.uid as u64,
+  };
+
+  let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+    move |message| matches!(message, Message::Api(Api::GetLnurlChannelResponse(response)) if response.req_id == req_id),
+  );
+
+  let (response_tx, mut response_rx) = mpsc::channel(1);
+
+  let message = Message::Api(Api::GetLnurlChannelRequest(request));
+
+  Arc::make_mut(&mut web_sender.into_inner())
+    .send(Envelope {
+      message,
+      response_tx: Some(response_tx),
+      response_filter: Some(response_filter),
+    })
+    .await
+    .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+  if let Ok(Some(Ok(Message::Api(Api::GetLnurlChannelResponse(response))))) =
+    timeout(Duration::from_secs(5), response_rx.recv()).await
+  {
+    if let Some(error) = response.error {
+      return Ok(HttpResponse::Ok().json(json!({"status": "ERROR", "reason": format!("{:?}", error)})));
+    }
+    let response = json!({
+        "uri": response.uri,
+        "callback": response.callback,
+        "k1": response.k1,
+        "tag": "channelRequest".to_string(),
+        "channelSizeSats": response.channel_size_sats,
+        "feeSats": response.fee_sats,
+    });
+    return Ok(HttpResponse::Ok().json(&response));
+  }
+  Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+#[derive(Deserialize)]
+pub struct RequestChannelOpenParams {
+  k1: Uuid,
+  remoteid: String,
+  /// 1 for a private channel.
+  private: Option<u8>,
+}
+
+#[get("/lnurl_channel/open")]
+pub async fn request_channel_open(
+  query: Query<RequestChannelOpenParams>,
+  web_sender: WebSender,
+) -> Result<HttpResponse, ApiError> {
+  let req_id = Uuid::new_v4();
+
+  let request = RequestChannelOpen {
+    req_id,
+    k1: query.k1,
+    remote_id: query.remoteid.clone(),
+    private: query.private == Some(1),
+  };
+
+  let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+    move |message| matches!(message, Message::Api(Api::RequestChannelOpenResponse(response)) if response.req_id == req_id),
+  );
+
+  let (response_tx, mut response_rx) = mpsc::channel(1);
+
+  let message = Message::Api(Api::RequestChannelOpen(request));
+
+  Arc::make_mut(&mut web_sender.into_inner())
+    .send(Envelope {
+      message,
+      response_tx: Some(response_tx),
+      response_filter: Some(response_filter),
+    })
+    .await
+    .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+  // Opening the channel waits for the funding transaction to be published.
+  if let Ok(Some(Ok(Message::Api(Api::RequestChannelOpenResponse(response))))) =
+    timeout(Duration::from_secs(30), response_rx.recv()).await
+  {
+    if let Some(error) = response.error {
+      return Ok(HttpResponse::Ok().json(json!({"status": "ERROR", "reason": format!("{:?}", error)})));
+    }
+    return Ok(HttpResponse::Ok().json(json!({"status": "OK"})));
+  }
+  Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
 #[get("/.well-known/lnurlp/{username}")]
 pub async fn lnurl_pay_address(path: Path<String>, pool: WebDbPool) -> Result<HttpResponse, ApiError> {
   let username = path.into_inner();
@@ -196,6 +297,11 @@ pub async fn lnurl_pay_address(path: Path<String>, pool: WebDbPool) -> Result<Ht
       "minSendable": min_sendable,
       "metadata": metadata.to_string(),
       "tag": "payRequest",
+      "commentAllowed": MAX_COMMENT_LENGTH,
+      "payerData": {
+          "name": { "mandatory": false },
+          "identifier": { "mandatory": false },
+      },
   });
 
   return Ok(HttpResponse::Ok().json(resp));
@@ -204,6 +310,9 @@ pub async fn lnurl_pay_address(path: Path<String>, pool: WebDbPool) -> Result<Ht
 #[derive(Deserialize)]
 pub struct PayAddressParams {
   amount: u64,
+  comment: Option<String>,
+  /// Json object of the payer's identity, the invoice commits to it together with the metadata (LUD-18).
+  payerdata: Option<String>,
 }
 
 #[get("/pay/{username}")]
@@ -239,7 +348,23 @@ pub async fn pay_address(
     return Err(ApiError::Request(RequestError::InvalidDataSupplied));
   }
 
-  let metadata = Some(format!("[[\"text/plain\",\"{:}\"]]", RANDOM_META_DATA));
+  if let Some(comment) = &query.comment {
+    if comment.chars().count() > MAX_COMMENT_LENGTH {
+      return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+  }
+
+  if let Some(payer_data) = &query.payerdata {
+    if payer_data.len() > MAX_PAYER_DATA_LENGTH || serde_json::from_str::<serde_json::Value>(payer_data).is_err() {
+      return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+  }
+
+  let metadata = Some(format!(
+    "[[\"text/plain\",\"{:}\"]]{}",
+    RANDOM_META_DATA,
+    query.payerdata.clone().unwrap_or_default()
+  ));
 
   let invoice_request = InvoiceRequest {
     req_id,
@@ -250,6 +375,10 @@ pub async fn pay_address(
     account_id: None,
     amount: money,
     target_account_currency: None,
+    metadata_fields: None,
+    idempotency_key: None,
+    comment: query.comment.clone().filter(|comment| !comment.is_empty()),
+    payer_data: query.payerdata.clone(),
   };
 
   let response_filter: Box<dyn Send + Fn(&Message) -> bool> =
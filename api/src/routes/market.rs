@@ -0,0 +1,179 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use actix_web::{
+    get,
+    web::{Data, Payload, Query},
+    HttpRequest, HttpResponse,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+use xerror::api::*;
+
+use core_types::Currency;
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::Envelope;
+use crate::market_data::{MarketData, MarketTopic, Subscription};
+use crate::WebSender;
+
+const PING_INTERVAL_SECS: u64 = 30;
+const MAX_CURRENCIES_PER_SUBSCRIPTION: usize = 8;
+
+#[derive(Deserialize)]
+pub struct MarketParams {
+    /// Comma separated currencies prices are streamed for, all if not set.
+    pub currencies: Option<String>,
+}
+
+fn parse_currencies(currencies: &Option<String>) -> Result<Vec<Currency>, ApiError> {
+    let currencies = match currencies {
+        Some(currencies) => currencies
+            .split(',')
+            .map(|currency| Currency::from_str(currency.trim()))
+            .collect::<Result<Vec<Currency>, _>>()
+            .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?,
+        None => return Ok(Vec::new()),
+    };
+    if currencies.len() > MAX_CURRENCIES_PER_SUBSCRIPTION {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+    Ok(currencies)
+}
+
+#[derive(Deserialize)]
+pub struct RatesParams {
+    /// Comma separated pairs rates are streamed for, e.g. `BTC/USD,EUR/BTC`.
+    pub pairs: String,
+}
+
+/// Only pairs with BTC on one side are accepted as swaps are always made through BTC.
+fn parse_pairs(pairs: &str) -> Result<Vec<CurrencyPair>, ApiError> {
+    let mut parsed = Vec::new();
+    for pair in pairs.split(',') {
+        let pair =
+            CurrencyPair::from_str(pair.trim()).map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+        if pair.base == pair.quote || (pair.base != Currency::BTC && pair.quote != Currency::BTC) {
+            return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+        }
+        if !parsed.contains(&pair) {
+            parsed.push(pair);
+        }
+    }
+    if parsed.len() > MAX_CURRENCIES_PER_SUBSCRIPTION {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+    Ok(parsed)
+}
+
+/// Public stream of the rates swaps are made at, at most one update per second and currency.
+#[get("/ws/market")]
+pub async fn stream_market_prices(
+    req: HttpRequest,
+    body: Payload,
+    market_data: Data<MarketData>,
+    params: Query<MarketParams>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let currencies = parse_currencies(&params.currencies)?;
+
+    // Taken before the handshake so rejected clients don't hold a connection.
+    let subscription = match MarketData::subscribe(&market_data.into_inner()) {
+        Some(subscription) => subscription,
+        None => return Ok(HttpResponse::ServiceUnavailable().finish()),
+    };
+
+    stream(req, body, subscription, move |topic| match topic {
+        MarketTopic::Price(currency) => currencies.is_empty() || currencies.contains(currency),
+        MarketTopic::Rate(_) => false,
+    })
+}
+
+/// Public stream of the bid, mid and ask rates of currency pairs as published by the dealer, at most one
+/// update per second and pair. Lets frontends show live conversion rates without requesting quotes.
+#[get("/ws/rates")]
+pub async fn stream_rates(
+    req: HttpRequest,
+    body: Payload,
+    market_data: Data<MarketData>,
+    web_sender: WebSender,
+    params: Query<RatesParams>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pairs = parse_pairs(&params.pairs)?;
+
+    let subscription = match MarketData::subscribe_rates(&market_data.into_inner(), pairs.clone()) {
+        Some(subscription) => subscription,
+        None => return Ok(HttpResponse::ServiceUnavailable().finish()),
+    };
+
+    // Subscribed right away so the first rates don't wait for the next renewal.
+    let envelope = Envelope {
+        message: Message::Api(Api::SubscribeRates(SubscribeRates { pairs: pairs.clone() })),
+        response_tx: None,
+        response_filter: None,
+    };
+    if web_sender.send(envelope).await.is_err() {
+        return Ok(HttpResponse::ServiceUnavailable().finish());
+    }
+
+    stream(req, body, subscription, move |topic| match topic {
+        MarketTopic::Rate(pair) => pairs.contains(pair),
+        MarketTopic::Price(_) => false,
+    })
+}
+
+/// Sends the events of the subscription the client is interested in until the client goes away.
+fn stream(
+    req: HttpRequest,
+    body: Payload,
+    mut subscription: Subscription,
+    wanted: impl Fn(&MarketTopic) -> bool + 'static,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, session, mut messages) = actix_ws::handle(&req, body)?;
+
+    let mut outgoing = session.clone();
+    actix_rt::spawn(async move {
+        loop {
+            let event = match timeout(Duration::from_secs(PING_INTERVAL_SECS), subscription.receiver.recv()).await {
+                Ok(Ok(event)) => event,
+                Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) => break,
+                Err(_) => {
+                    if outgoing.ping(b"").await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            if !wanted(&event.topic) {
+                continue;
+            }
+            if outgoing.text(event.payload).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut incoming = session;
+    actix_rt::spawn(async move {
+        while let Some(Ok(message)) = messages.next().await {
+            match message {
+                actix_ws::Message::Ping(bytes) => {
+                    if incoming.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                actix_ws::Message::Close(reason) => {
+                    let _ = incoming.close(reason).await;
+                    return;
+                }
+                _ => {}
+            }
+        }
+    });
+
+    Ok(response)
+}
@@ -1,4 +1,24 @@
+pub mod access_policy;
+pub mod accounts;
+pub mod api_keys;
 pub mod auth;
+pub mod data_export;
+pub mod deposit_rules;
+pub mod dust_sweeping;
+pub mod ecash;
+pub mod events;
+pub mod explorer;
+pub mod hedge_target;
+pub mod liquid;
 pub mod lnurl;
+pub mod market;
+pub mod notifications;
+pub mod push;
+pub mod recovery;
+pub mod status;
+pub mod swap_orders;
+pub mod two_factor;
 pub mod user;
+pub mod vault;
+pub mod webhooks;
 pub mod external;
@@ -0,0 +1,77 @@
+use actix_web::{get, put, web::Json, HttpResponse};
+use serde::Deserialize;
+use xerror::api::*;
+
+use models::notification_preferences::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+const MAX_ADDRESS_LENGTH: usize = 512;
+const PUSH_PLATFORMS: [&str; 2] = ["fcm", "apns"];
+
+#[derive(Deserialize)]
+pub struct NotificationPreferenceData {
+    pub email: Option<String>,
+    pub push_token: Option<String>,
+    /// Either `fcm` or `apns`, required with a push token.
+    pub push_platform: Option<String>,
+    pub deposits: bool,
+    pub withdrawals: bool,
+    pub failed_payments: bool,
+    pub security: bool,
+}
+
+/// Sets where and about which events the user is notified.
+#[put("/notifications/preferences")]
+pub async fn set_notification_preferences(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<NotificationPreferenceData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    if let Some(email) = &data.email {
+        if email.len() > MAX_ADDRESS_LENGTH || !email.contains('@') {
+            return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+        }
+    }
+
+    match (&data.push_token, &data.push_platform) {
+        (Some(token), Some(platform)) => {
+            if token.is_empty() || token.len() > MAX_ADDRESS_LENGTH || !PUSH_PLATFORMS.contains(&platform.as_str()) {
+                return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+            }
+        }
+        (None, None) => {}
+        _ => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+    }
+
+    let preference = NotificationPreference {
+        uid: auth_data.uid,
+        email: data.email.clone(),
+        push_token: data.push_token.clone(),
+        push_platform: data.push_platform.clone(),
+        deposits: data.deposits,
+        withdrawals: data.withdrawals,
+        failed_payments: data.failed_payments,
+        security: data.security,
+        updated_at: utils::time::time_now() as i64,
+    };
+
+    preference
+        .upsert(&conn)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(&preference))
+}
+
+#[get("/notifications/preferences")]
+pub async fn get_notification_preferences(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let preference = NotificationPreference::get_by_uid(&conn, auth_data.uid)
+        .unwrap_or_else(|_| NotificationPreference::default_for(auth_data.uid));
+
+    Ok(HttpResponse::Ok().json(&preference))
+}
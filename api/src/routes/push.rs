@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use actix_web::{
+    get,
+    web::{Payload, Query},
+    HttpRequest, HttpResponse,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+use xerror::api::*;
+
+use core_types::UserId;
+use msgs::api::*;
+use msgs::*;
+
+use crate::jwt::jwt_check;
+use crate::WebBroadcast;
+
+/// Idle connections are pinged this often, which also ends forwarding to clients that went away.
+const PING_INTERVAL_SECS: u64 = 30;
+
+#[derive(Deserialize)]
+pub struct PushParams {
+    /// Browsers can't set headers on WebSocket connections, so the jwt can be passed as query parameter.
+    pub token: Option<String>,
+}
+
+/// Returns the event pushed to the user if the message is one of theirs.
+fn push_event(message: &Message, uid: UserId) -> Option<String> {
+    let (event, owner) = match message {
+        Message::Api(Api::InvoiceResponse(response)) => ("invoice_response", response.uid),
+        Message::Api(Api::InvoiceSettled(settled)) => ("invoice_settled", settled.uid),
+        Message::Api(Api::PaymentResponse(response)) => ("payment_response", response.uid),
+        Message::Api(Api::SwapResponse(response)) => ("swap_response", response.uid),
+        _ => return None,
+    };
+    if owner != uid {
+        return None;
+    }
+    let message = match message {
+        Message::Api(Api::InvoiceSettled(settled)) => {
+            let mut settled = settled.clone();
+            settled.amount = settled.amount.round_for_display();
+            Message::Api(Api::InvoiceSettled(settled))
+        }
+        Message::Api(Api::PaymentResponse(response)) => {
+            Message::Api(Api::PaymentResponse(response.clone().round_for_display()))
+        }
+        message => message.clone(),
+    };
+    Some(json!({ "event": event, "message": message }).to_string())
+}
+
+/// Pushes the invoice, payment and swap events of the user over a WebSocket.
+#[get("/ws")]
+pub async fn push_events(
+    req: HttpRequest,
+    body: Payload,
+    broadcast: WebBroadcast,
+    params: Query<PushParams>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = match req.headers().get("authorization") {
+        Some(header) => header
+            .to_str()
+            .map_err(|_| ApiError::JWT(JWTError::Invalid))?
+            .to_string(),
+        None => params.token.clone().ok_or(ApiError::JWT(JWTError::NotSupplied))?,
+    };
+    let uid = jwt_check(&token)?.claims.get_user() as UserId;
+
+    // Subscribing before the handshake so no event in between is missed.
+    let mut receiver = broadcast.subscribe();
+    let (response, session, mut messages) = actix_ws::handle(&req, body)?;
+
+    let mut outgoing = session.clone();
+    actix_rt::spawn(async move {
+        loop {
+            let message = match timeout(Duration::from_secs(PING_INTERVAL_SECS), receiver.recv()).await {
+                Ok(Ok(message)) => message,
+                Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) => break,
+                Err(_) => {
+                    if outgoing.ping(b"").await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(event) = push_event(&message, uid) {
+                if outgoing.text(event).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut incoming = session;
+    actix_rt::spawn(async move {
+        while let Some(Ok(message)) = messages.next().await {
+            match message {
+                actix_ws::Message::Ping(bytes) => {
+                    if incoming.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                actix_ws::Message::Close(reason) => {
+                    let _ = incoming.close(reason).await;
+                    return;
+                }
+                _ => {}
+            }
+        }
+    });
+
+    Ok(response)
+}
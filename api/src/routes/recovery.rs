@@ -0,0 +1,448 @@
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse,
+};
+use diesel::result::DatabaseErrorKind;
+use diesel::result::Error as DieselError;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use utils::xlogging::slog::{self, Logger};
+use xerror::api::*;
+
+use models::recovery::*;
+use models::users::*;
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::rate_limit::{Decision, IpRateLimiter};
+use crate::{WebDbPool, WebLogger, WebSender};
+
+const MAX_GUARDIANS: usize = 5;
+/// Recoveries can't be executed sooner than a day after their approval so owners have time to cancel them.
+const MIN_RECOVERY_DELAY_SECS: u64 = 86_400;
+const DEFAULT_RECOVERY_DELAY_SECS: u64 = 259_200;
+/// Pending recoveries expire if they aren't approved within a week.
+const RECOVERY_REQUEST_TTL_MS: i64 = 604_800_000;
+/// Recoveries can be requested without an account, so every address may only request a few per hour.
+const RECOVERY_REQUEST_INTERVAL_MS: u64 = 3_600_000;
+const MAX_RECOVERY_REQUESTS: u64 = 5;
+
+/// Limits the recovery requests per client address, apart from the rate limit of all requests.
+pub struct RecoveryRateLimiter(pub Arc<IpRateLimiter>);
+
+pub fn recovery_rate_limiter() -> IpRateLimiter {
+    IpRateLimiter::new(RECOVERY_REQUEST_INTERVAL_MS, MAX_RECOVERY_REQUESTS, None)
+}
+
+fn audit(
+    logger: &Logger,
+    conn: &diesel::PgConnection,
+    uid: i32,
+    recovery_id: Option<i32>,
+    actor_uid: Option<i32>,
+    action: &str,
+) {
+    let entry = InsertableRecoveryAuditLog {
+        uid,
+        recovery_id,
+        actor_uid,
+        action: action.to_string(),
+        created_at: utils::time::time_now() as i64,
+    };
+    if let Err(err) = entry.insert(conn) {
+        slog::error!(
+            logger,
+            "Failed to insert recovery audit log {} of user {}: {:?}",
+            action,
+            uid,
+            err
+        );
+    }
+}
+
+/// Loads a recovery request and expires it if it hasn't been approved in time.
+fn get_recovery_request(
+    logger: &Logger,
+    conn: &diesel::PgConnection,
+    recovery_id: i32,
+) -> Result<RecoveryRequest, ApiError> {
+    let mut request = RecoveryRequest::get_by_id(conn, recovery_id)
+        .map_err(|_| ApiError::Recovery(RecoveryError::RecoveryDoesNotExist))?;
+
+    let now = utils::time::time_now() as i64;
+    if request.recovery_state() == Some(RecoveryState::Pending) && request.created_at + RECOVERY_REQUEST_TTL_MS < now {
+        request.state = RecoveryState::Expired.to_string();
+        request.closed_at = Some(now);
+        request.update(conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+        audit(
+            logger,
+            conn,
+            request.uid,
+            Some(request.recovery_id),
+            None,
+            "RecoveryExpired",
+        );
+    }
+
+    Ok(request)
+}
+
+#[derive(Deserialize)]
+pub struct GuardiansData {
+    pub guardians: Vec<String>,
+    pub threshold: u32,
+    pub delay_secs: Option<u64>,
+}
+
+#[post("/recovery/guardians")]
+pub async fn set_guardians(
+    pool: WebDbPool,
+    logger: WebLogger,
+    auth_data: AuthData,
+    data: Json<GuardiansData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let delay_secs = data.delay_secs.unwrap_or(DEFAULT_RECOVERY_DELAY_SECS);
+
+    if data.guardians.is_empty()
+        || data.guardians.len() > MAX_GUARDIANS
+        || data.threshold == 0
+        || data.threshold as usize > data.guardians.len()
+        || delay_secs < MIN_RECOVERY_DELAY_SECS
+    {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let mut guardian_uids = Vec::with_capacity(data.guardians.len());
+    for username in data.guardians.iter() {
+        let guardian = User::get_by_username(&conn, username.to_lowercase())
+            .map_err(|_| ApiError::Db(DbError::UserDoesNotExist))?;
+        if guardian.uid == auth_data.uid || guardian.is_internal || guardian_uids.contains(&guardian.uid) {
+            return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+        }
+        guardian_uids.push(guardian.uid);
+    }
+
+    let config = RecoveryConfig {
+        uid: auth_data.uid,
+        threshold: data.threshold as i32,
+        delay_secs: delay_secs as i64,
+        updated_at: utils::time::time_now() as i64,
+    };
+
+    config
+        .replace(&conn, &guardian_uids)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    audit(
+        &logger,
+        &conn,
+        auth_data.uid,
+        None,
+        Some(auth_data.uid),
+        "GuardiansUpdated",
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "guardians": data.guardians,
+        "threshold": config.threshold,
+        "delay_secs": config.delay_secs,
+    })))
+}
+
+#[get("/recovery/guardians")]
+pub async fn get_guardians(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let config = RecoveryConfig::get_by_uid(&conn, auth_data.uid)
+        .map_err(|_| ApiError::Recovery(RecoveryError::NotConfigured))?;
+
+    let guardians = RecoveryGuardian::get_by_uid(&conn, auth_data.uid)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+        .into_iter()
+        .filter_map(|guardian| User::get_by_id(&conn, guardian.guardian_uid).ok())
+        .map(|user| user.username)
+        .collect::<Vec<String>>();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "guardians": guardians,
+        "threshold": config.threshold,
+        "delay_secs": config.delay_secs,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct RecoveryRequestData {
+    pub username: String,
+    pub new_password: String,
+}
+
+/// Open to anyone who lost their credentials. Unknown users get the same error as users without guardians, and
+/// the recoveries of others don't block a request, the owner is notified and cancels the ones that aren't theirs.
+#[post("/recovery/request")]
+pub async fn request_recovery(
+    pool: WebDbPool,
+    logger: WebLogger,
+    web_sender: WebSender,
+    rate_limiter: Data<RecoveryRateLimiter>,
+    http_request: HttpRequest,
+    data: Json<RecoveryRequestData>,
+) -> Result<HttpResponse, ApiError> {
+    let ip = request_origin(&http_request).ip.and_then(|ip| ip.parse().ok());
+    if let Decision::Throttle { .. } = rate_limiter.0.check(ip, utils::time::time_now()) {
+        return Err(ApiError::Recovery(RecoveryError::TooManyRequests));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let user = User::get_by_username(&conn, data.username.to_lowercase())
+        .map_err(|_| ApiError::Recovery(RecoveryError::NotConfigured))?;
+
+    let config =
+        RecoveryConfig::get_by_uid(&conn, user.uid).map_err(|_| ApiError::Recovery(RecoveryError::NotConfigured))?;
+
+    let request = InsertableRecoveryRequest {
+        uid: user.uid,
+        password: hash(&user.username, &data.new_password),
+        state: RecoveryState::Pending.to_string(),
+        approvals_required: config.threshold,
+        delay_secs: config.delay_secs,
+        created_at: utils::time::time_now() as i64,
+    };
+
+    let request = request.insert(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    audit(
+        &logger,
+        &conn,
+        user.uid,
+        Some(request.recovery_id),
+        None,
+        "RecoveryRequested",
+    );
+
+    let requested = RecoveryRequested {
+        uid: user.uid as u64,
+        recovery_id: request.recovery_id,
+    };
+    if Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message: Message::Api(Api::RecoveryRequested(requested)),
+            response_tx: None,
+            response_filter: None,
+        })
+        .await
+        .is_err()
+    {
+        slog::error!(
+            logger,
+            "Failed to notify user {} of recovery {}",
+            user.uid,
+            request.recovery_id
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_id": request.recovery_id, "state": request.state })))
+}
+
+#[derive(Deserialize)]
+pub struct RecoveryIdData {
+    pub recovery_id: i32,
+}
+
+#[post("/recovery/approve")]
+pub async fn approve_recovery(
+    pool: WebDbPool,
+    logger: WebLogger,
+    auth_data: AuthData,
+    data: Json<RecoveryIdData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let mut request = get_recovery_request(&logger, &conn, data.recovery_id)?;
+
+    if request.recovery_state() != Some(RecoveryState::Pending) {
+        return Err(ApiError::Recovery(RecoveryError::InvalidState));
+    }
+
+    let is_guardian = RecoveryGuardian::is_guardian(&conn, request.uid, auth_data.uid)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    if !is_guardian {
+        return Err(ApiError::Recovery(RecoveryError::NotGuardian));
+    }
+
+    let now = utils::time::time_now() as i64;
+
+    let approval = RecoveryApproval {
+        recovery_id: request.recovery_id,
+        guardian_uid: auth_data.uid,
+        created_at: now,
+    };
+
+    if let Err(err) = approval.insert(&conn) {
+        return match err {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                Err(ApiError::Recovery(RecoveryError::InvalidState))
+            }
+            _ => Err(ApiError::Db(DbError::UpdateFailed)),
+        };
+    }
+
+    audit(
+        &logger,
+        &conn,
+        request.uid,
+        Some(request.recovery_id),
+        Some(auth_data.uid),
+        "RecoveryApproved",
+    );
+
+    let approvals = RecoveryApproval::get_by_recovery_id(&conn, request.recovery_id)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    if approvals.len() >= request.approvals_required as usize {
+        request.state = RecoveryState::Approved.to_string();
+        request.approved_at = Some(now);
+        request.update(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+        audit(
+            &logger,
+            &conn,
+            request.uid,
+            Some(request.recovery_id),
+            None,
+            "RecoveryThresholdReached",
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "recovery_id": request.recovery_id,
+        "state": request.state,
+        "approvals": approvals.len(),
+        "approvals_required": request.approvals_required,
+        "executable_at": request.executable_at(),
+    })))
+}
+
+#[post("/recovery/cancel")]
+pub async fn cancel_recovery(
+    pool: WebDbPool,
+    logger: WebLogger,
+    auth_data: AuthData,
+    data: Json<RecoveryIdData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let mut request = get_recovery_request(&logger, &conn, data.recovery_id)?;
+
+    if request.uid != auth_data.uid {
+        return Err(ApiError::Recovery(RecoveryError::RecoveryDoesNotExist));
+    }
+
+    if !request.recovery_state().map(|state| state.is_open()).unwrap_or(false) {
+        return Err(ApiError::Recovery(RecoveryError::InvalidState));
+    }
+
+    request.state = RecoveryState::Cancelled.to_string();
+    request.closed_at = Some(utils::time::time_now() as i64);
+    request.update(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    audit(
+        &logger,
+        &conn,
+        request.uid,
+        Some(request.recovery_id),
+        Some(auth_data.uid),
+        "RecoveryCancelled",
+    );
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_id": request.recovery_id, "state": request.state })))
+}
+
+#[derive(Deserialize)]
+pub struct CompleteRecoveryData {
+    pub recovery_id: i32,
+    pub new_password: String,
+}
+
+#[post("/recovery/complete")]
+pub async fn complete_recovery(
+    pool: WebDbPool,
+    logger: WebLogger,
+    data: Json<CompleteRecoveryData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let mut request = get_recovery_request(&logger, &conn, data.recovery_id)?;
+
+    let user = User::get_by_id(&conn, request.uid).map_err(|_| ApiError::Db(DbError::UserDoesNotExist))?;
+
+    // Only whoever requested the recovery knows the new credentials.
+    if !verify(&user.username, &request.password, &data.new_password) {
+        return Err(ApiError::Auth(AuthError::IncorrectPassword));
+    }
+
+    if request.recovery_state() != Some(RecoveryState::Approved) {
+        return Err(ApiError::Recovery(RecoveryError::InvalidState));
+    }
+
+    let now = utils::time::time_now() as i64;
+    match request.executable_at() {
+        Some(executable_at) if executable_at <= now => {}
+        _ => return Err(ApiError::Recovery(RecoveryError::DelayNotPassed)),
+    }
+
+    User::update_password(&conn, user.uid, &request.password).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    request.state = RecoveryState::Completed.to_string();
+    request.closed_at = Some(now);
+    request.update(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    audit(
+        &logger,
+        &conn,
+        request.uid,
+        Some(request.recovery_id),
+        None,
+        "RecoveryCompleted",
+    );
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_id": request.recovery_id, "state": request.state })))
+}
+
+#[get("/recovery/status/{recovery_id}")]
+pub async fn get_recovery_status(
+    pool: WebDbPool,
+    logger: WebLogger,
+    path: Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let request = get_recovery_request(&logger, &conn, path.into_inner())?;
+
+    let approvals = RecoveryApproval::get_by_recovery_id(&conn, request.recovery_id)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "recovery_id": request.recovery_id,
+        "state": request.state,
+        "approvals": approvals.len(),
+        "approvals_required": request.approvals_required,
+        "executable_at": request.executable_at(),
+    })))
+}
+
+#[get("/recovery/audit")]
+pub async fn get_recovery_audit_log(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let entries =
+        RecoveryAuditLog::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&entries))
+}
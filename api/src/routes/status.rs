@@ -0,0 +1,243 @@
+use actix_web::http::header;
+use actix_web::{
+    get,
+    web::{self, Query},
+    HttpResponse,
+};
+use core_types::{currency_registry, Currency};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use models::dealer_health_events::DealerHealthEvent;
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::rate_limit::IpRateLimiter;
+use crate::{WebDbPool, WebSender};
+
+/// Max age in seconds clients and proxies may cache the status for.
+const STATUS_MAX_AGE_SECS: u64 = 30;
+const DAY_MILLIS: u64 = 86_400_000;
+const DEFAULT_AVAILABILITY_DAYS: u64 = 30;
+const MAX_AVAILABILITY_DAYS: u64 = 90;
+
+#[get("/status")]
+pub async fn get_status(web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = GetStatusRequest { req_id };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::GetStatusResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::GetStatusRequest(request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::GetStatusResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", STATUS_MAX_AGE_SECS)))
+            .json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+/// Proof of reserves signed by the node, see `ReservesReport`.
+#[get("/reserves")]
+pub async fn get_reserves(web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = GetReservesReport { req_id };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::GetReservesReportResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::GetReservesReport(request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::GetReservesReportResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", STATUS_MAX_AGE_SECS)))
+            .json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+#[derive(Deserialize)]
+pub struct AvailabilityParams {
+    pub days: Option<u64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CurrencyAvailability {
+    pub currency: Currency,
+    pub available_millis: u64,
+    pub unavailable_millis: u64,
+    /// Time before the first recorded health event that is left out of the availability.
+    pub unknown_millis: u64,
+    pub outages: u64,
+    pub longest_outage_millis: u64,
+    /// Share of the known time the currency was available in percent.
+    pub availability: Option<f64>,
+}
+
+/// Splits `[from, to]` into available and unavailable time from the state at `from` and the transitions after it.
+fn compute_availability(
+    currency: Currency,
+    initial: Option<bool>,
+    events: &[DealerHealthEvent],
+    from: u64,
+    to: u64,
+) -> CurrencyAvailability {
+    let mut result = CurrencyAvailability {
+        currency,
+        available_millis: 0,
+        unavailable_millis: 0,
+        unknown_millis: 0,
+        outages: 0,
+        longest_outage_millis: 0,
+        availability: None,
+    };
+
+    let mut state = initial;
+    let mut since = from;
+    let mut outage_start = if initial == Some(false) { Some(from) } else { None };
+
+    let transitions = events
+        .iter()
+        .map(|event| ((event.created_at as u64).clamp(from, to), Some(event.available)))
+        .chain(std::iter::once((to, None)));
+
+    for (timestamp, available) in transitions {
+        let elapsed = timestamp.saturating_sub(since);
+        match state {
+            Some(true) => result.available_millis += elapsed,
+            Some(false) => result.unavailable_millis += elapsed,
+            None => result.unknown_millis += elapsed,
+        }
+        since = timestamp;
+
+        let ended = available.is_none() || available == Some(true);
+        if let (true, Some(start)) = (ended, outage_start) {
+            result.outages += 1;
+            result.longest_outage_millis = result.longest_outage_millis.max(timestamp - start);
+            outage_start = None;
+        }
+        if available == Some(false) && outage_start.is_none() {
+            outage_start = Some(timestamp);
+        }
+
+        if available.is_some() {
+            state = available;
+        }
+    }
+
+    let known_millis = result.available_millis + result.unavailable_millis;
+    if known_millis > 0 {
+        let availability = result.available_millis as f64 / known_millis as f64 * 100.0;
+        result.availability = Some((availability * 10_000.0).round() / 10_000.0);
+    }
+
+    result
+}
+
+/// Availability of the currencies served by the dealer over the last days, 30 by default.
+#[get("/status/availability")]
+pub async fn get_availability(pool: WebDbPool, params: Query<AvailabilityParams>) -> Result<HttpResponse, ApiError> {
+    let days = params.days.unwrap_or(DEFAULT_AVAILABILITY_DAYS);
+    if days == 0 || days > MAX_AVAILABILITY_DAYS {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let to = utils::time::time_now();
+    let from = to.saturating_sub(days * DAY_MILLIS);
+
+    let mut currencies = Vec::new();
+    // Only the synthetics depend on the dealer, BTC is always available.
+    for currency in currency_registry::synthetics() {
+        let initial = match DealerHealthEvent::get_latest_before(&conn, currency.to_string(), from as i64) {
+            Ok(event) => Some(event.available),
+            Err(diesel::result::Error::NotFound) => None,
+            Err(_) => return Err(ApiError::Db(DbError::CouldNotFetchData)),
+        };
+        let events = DealerHealthEvent::get_since(&conn, currency.to_string(), from as i64)
+            .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+        currencies.push(compute_availability(currency, initial, &events, from, to));
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", STATUS_MAX_AGE_SECS)))
+        .json(json!({ "from": from, "to": to, "currencies": currencies })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(available: bool, created_at: i64) -> DealerHealthEvent {
+        DealerHealthEvent {
+            id: 0,
+            currency: "USD".to_string(),
+            available,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn availability_counts_outages_and_unknown_time() {
+        let events = vec![event(true, 100), event(false, 400), event(true, 500), event(false, 900)];
+        let result = compute_availability(Currency::USD, None, &events, 0, 1000);
+
+        assert_eq!(result.unknown_millis, 100);
+        assert_eq!(result.available_millis, 700);
+        assert_eq!(result.unavailable_millis, 200);
+        assert_eq!(result.outages, 2);
+        assert_eq!(result.longest_outage_millis, 100);
+        assert_eq!(result.availability, Some(77.7778));
+
+        let result = compute_availability(Currency::USD, Some(false), &[], 0, 1000);
+        assert_eq!(result.unavailable_millis, 1000);
+        assert_eq!(result.outages, 1);
+        assert_eq!(result.availability, Some(0.0));
+    }
+}
+
+/// Counters of the ip rate limiter, e.g. how many requests were throttled since the api started.
+#[get("/status/rate_limits")]
+pub async fn get_rate_limits(rate_limiter: web::Data<IpRateLimiter>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(rate_limiter.metrics()))
+}
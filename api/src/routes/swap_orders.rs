@@ -0,0 +1,116 @@
+use actix_web::{
+    delete, get, post,
+    web::{Json, Path},
+    HttpResponse,
+};
+use core_types::{Currency, Money};
+use rust_decimal::prelude::Decimal;
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::WebSender;
+
+#[derive(Deserialize)]
+pub struct SwapOrderData {
+    pub from_currency: Currency,
+    pub to_currency: Currency,
+    pub amount: Decimal,
+    /// Least amount of the to currency per unit of the from currency.
+    pub limit_rate: Decimal,
+    /// Code of the authenticator app for orders above the 2fa threshold.
+    pub totp_code: Option<String>,
+}
+
+async fn send_swap_order_request(
+    web_sender: WebSender,
+    req_id: Uuid,
+    message: Message,
+) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::SwapOrderResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::SwapOrderResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+/// Places a limit order that is swapped, possibly in parts, once the rate reaches the limit.
+#[post("/swap_orders")]
+pub async fn place_swap_order(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<SwapOrderData>,
+) -> Result<HttpResponse, ApiError> {
+    if data.amount <= Decimal::ZERO || data.limit_rate <= Decimal::ZERO {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let req_id = Uuid::new_v4();
+
+    let request = SwapOrderRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        amount: Money::new(data.from_currency, Some(data.amount)),
+        from: data.from_currency,
+        to: data.to_currency,
+        limit_rate: data.limit_rate,
+        origin: Some(auth_data.origin.clone()),
+        totp_code: data.totp_code.clone(),
+    };
+
+    send_swap_order_request(web_sender, req_id, Message::Api(Api::SwapOrderRequest(request))).await
+}
+
+#[get("/swap_orders")]
+pub async fn get_swap_orders(auth_data: AuthData, web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = GetSwapOrders {
+        req_id,
+        uid: auth_data.uid as u64,
+    };
+
+    send_swap_order_request(web_sender, req_id, Message::Api(Api::GetSwapOrders(request))).await
+}
+
+/// Cancels what is left of an order, fills made so far are kept.
+#[delete("/swap_orders/{order_id}")]
+pub async fn cancel_swap_order(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    path: Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = CancelSwapOrderRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        order_id: path.into_inner(),
+    };
+
+    send_swap_order_request(web_sender, req_id, Message::Api(Api::CancelSwapOrderRequest(request))).await
+}
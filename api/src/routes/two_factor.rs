@@ -0,0 +1,90 @@
+use actix_web::{post, web::Json, HttpResponse};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::WebSender;
+
+#[derive(Deserialize)]
+pub struct TotpCodeData {
+    pub code: String,
+}
+
+async fn send_totp_request(web_sender: WebSender, req_id: Uuid, message: Message) -> Result<HttpResponse, ApiError> {
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::TotpResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::TotpResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+/// Generates a new secret for the authenticator app. 2fa is only enforced once a code was confirmed.
+#[post("/2fa/enroll")]
+pub async fn enroll_totp(auth_data: AuthData, web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = EnrollTotpRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+    };
+
+    send_totp_request(web_sender, req_id, Message::Api(Api::EnrollTotpRequest(request))).await
+}
+
+#[post("/2fa/confirm")]
+pub async fn confirm_totp(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<TotpCodeData>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = ConfirmTotpRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        code: data.code.clone(),
+    };
+
+    send_totp_request(web_sender, req_id, Message::Api(Api::ConfirmTotpRequest(request))).await
+}
+
+#[post("/2fa/disable")]
+pub async fn disable_totp(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<TotpCodeData>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = DisableTotpRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        code: data.code.clone(),
+    };
+
+    send_totp_request(web_sender, req_id, Message::Api(Api::DisableTotpRequest(request))).await
+}
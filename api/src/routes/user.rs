@@ -9,8 +9,9 @@ use tokio::sync::mpsc;
 use tokio::time::timeout;
 
 use actix_web::http::header;
-use std::{sync::Arc, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
+use bigdecimal::BigDecimal;
 use rust_decimal::prelude::Decimal;
 use rust_decimal_macros::*;
 use serde::{Deserialize, Serialize};
@@ -25,20 +26,73 @@ use msgs::*;
 use crate::comms::*;
 use crate::jwt::*;
 use crate::WebDbPool;
+use crate::WebReadReplica;
 use crate::WebSender;
 
 use models::invoices::*;
+use models::referrals::Referral;
 use models::transactions::Transaction;
 use models::users::{ShareableUser, User};
 
 const MINIMUM_PATTERN_LENGTH: usize = 3;
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+
+fn validate_idempotency_key(idempotency_key: &Option<String>) -> Result<(), ApiError> {
+    if let Some(key) = idempotency_key {
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+        }
+    }
+    Ok(())
+}
+
+/// Balances are accounted in msats but only whole sats are shown.
+fn round_balances_for_display(balances: &mut Balances) {
+    balances.accounts.values_mut().for_each(|account| {
+        account.balance = Money::new(account.currency, Some(account.balance))
+            .round_for_display()
+            .value;
+        account.pending_balance = Money::new(account.currency, Some(account.pending_balance))
+            .round_for_display()
+            .value;
+    });
+}
+
+/// Same as the balances, the history hides the msat fractions of BTC amounts.
+fn round_amount_for_display(currency: &str, amount: &BigDecimal) -> BigDecimal {
+    match (Currency::from_str(currency), Decimal::from_str(&amount.to_string())) {
+        (Ok(currency), Ok(value)) => {
+            let rounded = Money::new(currency, Some(value)).round_for_display().value;
+            BigDecimal::from_str(&rounded.to_string()).unwrap_or_else(|_| amount.clone())
+        }
+        _ => amount.clone(),
+    }
+}
+
+fn round_transactions_for_display(transactions: &mut [Transaction]) {
+    transactions.iter_mut().for_each(|tx| {
+        tx.outbound_amount = round_amount_for_display(&tx.outbound_currency, &tx.outbound_amount);
+        tx.inbound_amount = round_amount_for_display(&tx.inbound_currency, &tx.inbound_amount);
+        tx.fees = round_amount_for_display(&tx.outbound_currency, &tx.fees);
+    });
+}
 
 #[get("/balance")]
-pub async fn balance(web_sender: WebSender, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+pub async fn balance(
+    web_sender: WebSender,
+    read_replica: WebReadReplica,
+    auth_data: AuthData,
+) -> Result<HttpResponse, ApiError> {
     let req_id = Uuid::new_v4();
 
     let uid = auth_data.uid as u64;
 
+    if let Some(read_replica) = read_replica.as_ref() {
+        let mut balances = read_replica.balances(req_id, uid)?;
+        round_balances_for_display(&mut balances);
+        return Ok(HttpResponse::Ok().json(&balances));
+    }
+
     let get_balances = GetBalances { req_id, uid };
 
     let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -58,9 +112,10 @@ pub async fn balance(web_sender: WebSender, auth_data: AuthData) -> Result<HttpR
         .await
         .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
 
-    if let Ok(Some(Ok(Message::Api(Api::Balances(balances))))) =
+    if let Ok(Some(Ok(Message::Api(Api::Balances(mut balances))))) =
         timeout(Duration::from_secs(5), response_rx.recv()).await
     {
+        round_balances_for_display(&mut balances);
         return Ok(HttpResponse::Ok().json(&balances));
     }
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
@@ -71,7 +126,14 @@ pub struct PayInvoiceData {
     pub payment_request: Option<String>,
     pub currency: Option<Currency>,
     pub recipient: Option<String>,
+    /// Account of the recipient to credit, e.g. their EUR account.
+    pub account_id: Option<Uuid>,
+    /// Sub-account the payment is made from.
+    pub from_account_id: Option<Uuid>,
     pub amount: Option<Decimal>,
+    pub idempotency_key: Option<String>,
+    /// Code of the authenticator app for payments above the 2fa threshold.
+    pub totp_code: Option<String>,
 }
 
 #[post("/payinvoice")]
@@ -103,6 +165,8 @@ pub async fn pay_invoice(
         }
     }
 
+    validate_idempotency_key(&pay_invoice_data.idempotency_key)?;
+
     let currency = match pay_invoice_data.currency {
         Some(c) => c,
         None => Currency::BTC,
@@ -122,11 +186,21 @@ pub async fn pay_invoice(
         rate: None,
         amount: money,
         receipient: pay_invoice_data.recipient.clone(),
+        target_account_id: pay_invoice_data.account_id,
+        account_id: pay_invoice_data.from_account_id,
         destination: None,
+        keysend_message: None,
         fees: None,
+        requoted: false,
+        idempotency_key: pay_invoice_data.idempotency_key.clone(),
+        origin: Some(auth_data.origin.clone()),
+        totp_code: pay_invoice_data.totp_code.clone(),
     };
 
-    if pay_invoice_data.payment_request.is_none() && pay_invoice_data.recipient.is_none() {
+    if pay_invoice_data.payment_request.is_none()
+        && pay_invoice_data.recipient.is_none()
+        && pay_invoice_data.account_id.is_none()
+    {
         return Ok(HttpResponse::Ok().json(json!({"error": "You have to specify either an invoice or a receipient"})));
     }
 
@@ -150,7 +224,7 @@ pub async fn pay_invoice(
     if let Ok(Some(Ok(Message::Api(Api::PaymentResponse(response))))) =
         timeout(Duration::from_secs(5), response_rx.recv()).await
     {
-        return Ok(HttpResponse::Ok().json(&response));
+        return Ok(HttpResponse::Ok().json(&response.round_for_display()));
     }
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
 }
@@ -163,8 +237,13 @@ pub struct CreateInvoiceParams {
     pub account_id: Option<Uuid>,
     pub currency: Option<Currency>,
     pub target_account_currency: Option<Currency>,
+    /// Json object of string fields stored with the invoice, e.g. `{"order_id":"42"}`.
+    pub metadata_fields: Option<String>,
+    pub idempotency_key: Option<String>,
 }
 
+const MAX_METADATA_FIELDS_LENGTH: usize = 1024;
+
 #[get("/addinvoice")]
 pub async fn add_invoice(
     auth_data: AuthData,
@@ -193,6 +272,20 @@ pub async fn add_invoice(
         None => Currency::BTC,
     };
 
+    validate_idempotency_key(&query.idempotency_key)?;
+
+    let metadata_fields = match &query.metadata_fields {
+        Some(fields) => {
+            if fields.len() > MAX_METADATA_FIELDS_LENGTH {
+                return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+            }
+            let fields = serde_json::from_str::<HashMap<String, String>>(fields)
+                .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+            Some(fields)
+        }
+        None => None,
+    };
+
     let amount = Money::new(currency, Some(query.amount));
 
     let invoice_request = InvoiceRequest {
@@ -204,6 +297,10 @@ pub async fn add_invoice(
         currency,
         account_id: query.account_id,
         target_account_currency: query.target_account_currency,
+        metadata_fields,
+        idempotency_key: query.idempotency_key.clone(),
+        comment: None,
+        payer_data: None,
     };
 
     let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -237,6 +334,11 @@ pub struct SwapData {
     pub to_currency: Currency,
     pub amount: Decimal,
     pub quote_id: Option<u128>,
+    /// Terms of the quote as returned by `/quote`, required to swap at a signed quote.
+    pub signed_quote: Option<FirmQuote>,
+    pub idempotency_key: Option<String>,
+    /// Code of the authenticator app for swaps above the 2fa threshold.
+    pub totp_code: Option<String>,
 }
 
 #[post("/swap")]
@@ -249,6 +351,8 @@ pub async fn swap(auth_data: AuthData, web_sender: WebSender, data: Json<SwapDat
         return Err(ApiError::Request(RequestError::InvalidDataSupplied));
     }
 
+    validate_idempotency_key(&data.idempotency_key)?;
+
     let money = Money::new(data.from_currency, Some(data.amount));
 
     let swap_request = SwapRequest {
@@ -258,6 +362,10 @@ pub async fn swap(auth_data: AuthData, web_sender: WebSender, data: Json<SwapDat
         to: data.to_currency,
         amount: money,
         quote_id: data.quote_id,
+        signed_quote: data.signed_quote.clone(),
+        idempotency_key: data.idempotency_key.clone(),
+        origin: Some(auth_data.origin.clone()),
+        totp_code: data.totp_code.clone(),
     };
 
     let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -285,6 +393,122 @@ pub async fn swap(auth_data: AuthData, web_sender: WebSender, data: Json<SwapDat
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
 }
 
+const CASH_OUT_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Deserialize)]
+pub struct CashOutData {
+    pub currency: Currency,
+    pub payment_request: String,
+    /// Code of the authenticator app for payments above the 2fa threshold.
+    pub totp_code: Option<String>,
+}
+
+/// Swaps the whole balance of a fiat account to BTC and pays the invoice from it in one step.
+#[post("/cashout")]
+pub async fn cash_out(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<CashOutData>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let uid = auth_data.uid as u64;
+
+    if data.currency == Currency::BTC || data.payment_request.len() > 1024 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let cash_out_request = CashOutRequest {
+        req_id,
+        uid,
+        currency: data.currency,
+        payment_request: data.payment_request.clone(),
+        origin: Some(auth_data.origin.clone()),
+        totp_code: data.totp_code.clone(),
+    };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::PaymentResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::CashOutRequest(cash_out_request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    // The swap has to go through the dealer before the payment is made.
+    if let Ok(Some(Ok(Message::Api(Api::PaymentResponse(response))))) =
+        timeout(Duration::from_secs(CASH_OUT_TIMEOUT_SECS), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response.round_for_display()));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+#[derive(Deserialize)]
+pub struct TransferData {
+    pub from_account_id: Uuid,
+    pub to_account_id: Uuid,
+    pub amount: Decimal,
+}
+
+/// Moves funds between two of the user's own accounts of the same currency.
+#[post("/transfer")]
+pub async fn transfer(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    data: Json<TransferData>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let uid = auth_data.uid as u64;
+
+    if data.amount <= dec!(0) || data.from_account_id == data.to_account_id {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let transfer_request = InternalTransferRequest {
+        req_id,
+        uid,
+        from_account_id: data.from_account_id,
+        to_account_id: data.to_account_id,
+        amount: data.amount,
+        origin: Some(auth_data.origin.clone()),
+    };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::InternalTransferResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::InternalTransferRequest(transfer_request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::InternalTransferResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
 #[get("/getuserinvoices")]
 pub async fn get_user_invoices(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
     let uid = auth_data.uid as u64;
@@ -299,6 +523,19 @@ pub async fn get_user_invoices(pool: WebDbPool, auth_data: AuthData) -> Result<H
     Ok(HttpResponse::Ok().json(&invoices))
 }
 
+/// Users who signed up with the authenticated user as their referrer.
+#[get("/referrals")]
+pub async fn get_referrals(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let referees = match Referral::get_referees(&conn, auth_data.uid as i32) {
+        Ok(r) => r,
+        Err(_) => return Err(ApiError::Db(DbError::CouldNotFetchData)),
+    };
+
+    Ok(HttpResponse::Ok().json(&referees))
+}
+
 #[derive(Deserialize)]
 pub struct QuoteParams {
     pub from_currency: Currency,
@@ -365,13 +602,18 @@ pub struct TransactionsParams {
 #[get("/gettxs")]
 pub async fn get_txs(
     pool: WebDbPool,
+    read_replica: WebReadReplica,
     auth_data: AuthData,
     query: Query<TransactionsParams>,
 ) -> Result<HttpResponse, ApiError> {
     let uid = auth_data.uid as u64;
+    let pool = match read_replica.as_ref() {
+        Some(read_replica) => read_replica.pool(),
+        None => pool.get_ref(),
+    };
     let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
     if let Some(currency) = query.currency {
-        let transactions = match Transaction::get_historical_by_uid_and_currency(
+        let mut transactions = match Transaction::get_historical_by_uid_and_currency(
             &conn,
             uid as i32,
             currency.to_string(),
@@ -381,15 +623,139 @@ pub async fn get_txs(
             Ok(i) => i,
             Err(_) => return Err(ApiError::Db(DbError::CouldNotFetchData)),
         };
+        round_transactions_for_display(&mut transactions);
         return Ok(HttpResponse::Ok().json(&transactions));
     }
-    let transactions = match Transaction::get_historical_by_uid(&conn, uid as i32, query.from, query.to) {
+    let mut transactions = match Transaction::get_historical_by_uid(&conn, uid as i32, query.from, query.to) {
         Ok(i) => i,
         Err(_) => return Err(ApiError::Db(DbError::CouldNotFetchData)),
     };
+    round_transactions_for_display(&mut transactions);
     Ok(HttpResponse::Ok().json(&transactions))
 }
 
+#[derive(Deserialize)]
+pub struct StatementParams {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Either `csv` or `json`, defaults to `csv`.
+    pub format: Option<String>,
+    pub valuation_currency: Option<Currency>,
+}
+
+/// Statements are built from the whole transaction history of a user which takes a while.
+const STATEMENT_TIMEOUT_SECS: u64 = 30;
+
+#[get("/statement")]
+pub async fn export_statement(
+    web_sender: WebSender,
+    auth_data: AuthData,
+    query: Query<StatementParams>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+    let uid = auth_data.uid as u64;
+
+    let format = match query.format.as_deref() {
+        None | Some("csv") => StatementFormat::Csv,
+        Some("json") => StatementFormat::Json,
+        Some(_) => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+    };
+
+    let request = ExportStatementRequest {
+        req_id,
+        uid,
+        from: query.from,
+        to: query.to,
+        format,
+        valuation_currency: query.valuation_currency,
+    };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::ExportStatementResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::ExportStatementRequest(request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::ExportStatementResponse(response))))) =
+        timeout(Duration::from_secs(STATEMENT_TIMEOUT_SECS), response_rx.recv()).await
+    {
+        let content = match response.content {
+            Some(content) => content,
+            None => return Ok(HttpResponse::Ok().json(&response)),
+        };
+        let (content_type, extension) = match response.format {
+            StatementFormat::Csv => ("text/csv", "csv"),
+            StatementFormat::Json => ("application/json", "json"),
+        };
+        return Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"statement.{}\"", extension),
+            ))
+            .body(content));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+#[derive(Deserialize)]
+pub struct InterestHistoryParams {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+/// Interest accrued on the fiat balances of the user per account and day.
+#[get("/interest")]
+pub async fn get_interest_history(
+    web_sender: WebSender,
+    auth_data: AuthData,
+    query: Query<InterestHistoryParams>,
+) -> Result<HttpResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = GetInterestHistory {
+        req_id,
+        uid: auth_data.uid as u64,
+        from: query.from,
+        to: query.to,
+    };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::InterestHistory(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::GetInterestHistory(request));
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::InterestHistory(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(HttpResponse::Ok().json(&response));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
 #[get("/getavailablecurrencies")]
 pub async fn get_available_currencies(web_sender: WebSender) -> Result<HttpResponse, ApiError> {
     let req_id = Uuid::new_v4();
@@ -422,9 +788,13 @@ pub async fn get_available_currencies(web_sender: WebSender) -> Result<HttpRespo
 }
 
 #[get("/nodeinfo")]
-pub async fn get_node_info(web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+pub async fn get_node_info(web_sender: WebSender, read_replica: WebReadReplica) -> Result<HttpResponse, ApiError> {
     let req_id = Uuid::new_v4();
 
+    if let Some(read_replica) = read_replica.as_ref() {
+        return Ok(HttpResponse::Ok().json(&read_replica.node_info(req_id)?));
+    }
+
     let request = GetNodeInfoRequest { req_id };
 
     let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -554,7 +924,8 @@ pub struct CheckPaymentParams {
 
 #[derive(Serialize)]
 pub struct CheckPaymentHashResponse {
-    paid: bool
+    paid: bool,
+    expired: bool,
 }
 
 #[get("/checkpayment")]
@@ -573,7 +944,8 @@ pub async fn check_payment(
     };
 
     let response = CheckPaymentHashResponse {
-        paid: invoice.settled
+        paid: invoice.settled,
+        expired: invoice.expired,
     };
 
     Ok(HttpResponse::Ok()
@@ -587,6 +959,8 @@ pub struct KeySendData {
     pub destination: String,
     pub memo: String,
     pub custom_records: Option<HashMap<String, String>>,
+    /// Code of the authenticator app for payments above the 2fa threshold.
+    pub totp_code: Option<String>,
 }
 
 #[post("/keysend")]
@@ -595,14 +969,12 @@ pub async fn keysend(auth_data: AuthData, web_sender: WebSender, data: Json<KeyS
 
     let uid = auth_data.uid as u64;
 
-    if data.amount <= 0 {
+    if data.amount == 0 {
         return Err(ApiError::Request(RequestError::InvalidDataSupplied));
     }
 
-    let a = Decimal::new(data.amount as i64, 0);
-
     let currency = Currency::BTC;
-    let money = Money::new(currency, Some(a));
+    let money = Money::from_sats(Decimal::new(data.amount as i64, 0));
 
     let payment_request = PaymentRequest {
         currency,
@@ -612,8 +984,15 @@ pub async fn keysend(auth_data: AuthData, web_sender: WebSender, data: Json<KeyS
         rate: None,
         amount: Some(money),
         receipient: None,
+        target_account_id: None,
+        account_id: None,
         destination: Some(data.destination.clone()),
+        keysend_message: Some(data.memo.clone()).filter(|memo| !memo.is_empty()),
         fees: None,
+        requoted: false,
+        idempotency_key: None,
+        origin: Some(auth_data.origin.clone()),
+        totp_code: data.totp_code.clone(),
     };
 
     let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -633,10 +1012,10 @@ pub async fn keysend(auth_data: AuthData, web_sender: WebSender, data: Json<KeyS
         .await
         .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
 
-    if let Ok(Some(Ok(Message::Api(Api::SwapResponse(payment_response))))) =
+    if let Ok(Some(Ok(Message::Api(Api::PaymentResponse(payment_response))))) =
         timeout(Duration::from_secs(5), response_rx.recv()).await
     {
-        return Ok(HttpResponse::Ok().json(&payment_response));
+        return Ok(HttpResponse::Ok().json(&payment_response.round_for_display()));
     }
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
 }
\ No newline at end of file
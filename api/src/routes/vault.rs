@@ -0,0 +1,58 @@
+use actix_web::{get, post, web::Path, HttpResponse};
+use diesel::result::Error as DieselError;
+use diesel::Connection;
+use serde_json::json;
+use uuid::Uuid;
+use xerror::api::*;
+
+use models::api_keys::ApiKeySpend;
+use models::vault_withdrawals::*;
+
+use crate::jwt::*;
+use crate::WebDbPool;
+
+/// Withdrawals from the user's vaults that are still waiting out the delay and can be cancelled.
+#[get("/vault/withdrawals")]
+pub async fn get_vault_withdrawals(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let withdrawals = VaultWithdrawal::get_pending_by_uid(&conn, auth_data.uid)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&withdrawals))
+}
+
+/// Cancels a withdrawal from a vault before it is released, the funds stay in the vault and the budget of the key
+/// it was made with is released.
+#[post("/vault/withdrawals/{req_id}/cancel")]
+pub async fn cancel_vault_withdrawal(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    path: Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let req_id = path.into_inner();
+    let cancelled = conn
+        .transaction::<_, DieselError, _>(|| {
+            let cancelled = VaultWithdrawal::decide(
+                &conn,
+                req_id.to_string(),
+                auth_data.uid,
+                CANCELLED,
+                utils::time::time_now() as i64,
+            )?;
+            if cancelled > 0 {
+                ApiKeySpend::delete(&conn, &req_id.to_string())?;
+            }
+            Ok(cancelled)
+        })
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    // Withdrawals that were released or cancelled already can't be cancelled anymore.
+    if cancelled == 0 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "req_id": req_id, "status": CANCELLED })))
+}
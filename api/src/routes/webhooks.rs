@@ -0,0 +1,109 @@
+use actix_web::{
+    delete, get, post,
+    web::{Json, Path},
+    HttpResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+use xerror::api::*;
+
+use models::webhooks::*;
+
+use crate::jwt::*;
+use crate::webhooks::WEBHOOK_EVENTS;
+use crate::WebDbPool;
+
+const MAX_WEBHOOKS_PER_USER: usize = 10;
+const MAX_WEBHOOK_URL_LENGTH: usize = 512;
+
+#[derive(Deserialize)]
+pub struct WebhookData {
+    pub url: String,
+    /// Only invoices carrying all of these metadata fields are dispatched.
+    /// A null value matches any value of the field.
+    pub metadata_filter: Option<HashMap<String, Option<String>>>,
+    /// Events to dispatch, all events if omitted.
+    pub events: Option<Vec<String>>,
+}
+
+#[post("/webhooks")]
+pub async fn create_webhook(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<WebhookData>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    if data.url.len() > MAX_WEBHOOK_URL_LENGTH || !(data.url.starts_with("https://") || data.url.starts_with("http://"))
+    {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let webhooks = Webhook::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    if webhooks.len() >= MAX_WEBHOOKS_PER_USER {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let metadata_filter = match &data.metadata_filter {
+        Some(filter) => {
+            Some(serde_json::to_string(filter).map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?)
+        }
+        None => None,
+    };
+
+    let events = match &data.events {
+        Some(events) => {
+            if events.is_empty() || events.iter().any(|event| !WEBHOOK_EVENTS.contains(&event.as_str())) {
+                return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+            }
+            Some(events.join(","))
+        }
+        None => None,
+    };
+
+    let secret = format!("{}{}", Uuid::new_v4().to_simple(), Uuid::new_v4().to_simple());
+
+    let webhook = InsertableWebhook {
+        uid: auth_data.uid,
+        url: data.url.clone(),
+        metadata_filter,
+        created_at: utils::time::time_now() as i64,
+        secret: Some(secret.clone()),
+        events,
+    };
+
+    let webhook = webhook.insert(&conn).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    // The secret to verify signatures with is only returned once.
+    let mut response = serde_json::to_value(&webhook).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+    response["secret"] = json!(secret);
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/webhooks")]
+pub async fn get_webhooks(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let webhooks = Webhook::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(&webhooks))
+}
+
+#[delete("/webhooks/{webhook_id}")]
+pub async fn delete_webhook(pool: WebDbPool, auth_data: AuthData, path: Path<i32>) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let webhook_id = path.into_inner();
+
+    let deleted = Webhook::delete(&conn, webhook_id, auth_data.uid).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    if deleted == 0 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "webhook_id": webhook_id })))
+}
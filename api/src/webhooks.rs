@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use core_types::{DbPool, UserId};
+use models::webhooks::Webhook;
+use msgs::api::*;
+use msgs::*;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const INVOICE_SETTLED: &str = "invoice_settled";
+pub const PAYMENT_SUCCEEDED: &str = "payment_succeeded";
+pub const PAYMENT_FAILED: &str = "payment_failed";
+pub const SWAP_COMPLETED: &str = "swap_completed";
+pub const WEBHOOK_EVENTS: [&str; 4] = [INVOICE_SETTLED, PAYMENT_SUCCEEDED, PAYMENT_FAILED, SWAP_COMPLETED];
+
+const SIGNATURE_HEADER: &str = "X-Lndhubx-Signature";
+const DELIVERY_HEADER: &str = "X-Lndhubx-Delivery";
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Failed deliveries are retried with exponential backoff starting at this delay.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+const MAX_DELIVERY_ATTEMPTS: u32 = 6;
+
+pub struct WebhookDispatcher;
+
+/// Returns whether the metadata fields of an invoice satisfy a webhook filter.
+/// A filter that can't be parsed never matches so a broken subscription stays quiet.
+pub fn matches_filter(filter: &Option<String>, metadata_fields: &HashMap<String, String>) -> bool {
+    let filter = match filter {
+        Some(f) => f,
+        None => return true,
+    };
+
+    let filter = match serde_json::from_str::<HashMap<String, Option<String>>>(filter) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+
+    filter
+        .iter()
+        .all(|(field, expected)| match (metadata_fields.get(field), expected) {
+            (Some(value), Some(expected)) => value == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+}
+
+/// Signature receivers verify a delivery with: hex encoded HMAC-SHA256 of `{timestamp}.{body}`.
+pub fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Returns the event, its owner and its payload if the message is dispatched to webhooks.
+fn webhook_event(message: &Message) -> Option<(&'static str, UserId, Value)> {
+    match message {
+        Message::Api(Api::InvoiceSettled(invoice_settled)) => Some((
+            INVOICE_SETTLED,
+            invoice_settled.uid,
+            json!({
+                "payment_request": invoice_settled.payment_request,
+                "payment_hash": invoice_settled.payment_hash,
+                "amount": invoice_settled.amount,
+                "metadata_fields": invoice_settled.metadata_fields,
+                "comment": invoice_settled.comment,
+                "payer_data": invoice_settled.payer_data,
+                "description_hash": invoice_settled.description_hash,
+            }),
+        )),
+        // A scheduled retry or a withdrawal held for approval or delayed is not the final outcome of a payment.
+        Message::Api(Api::PaymentResponse(PaymentResponse {
+            error:
+                Some(
+                    PaymentResponseError::PaymentRetryScheduled
+                    | PaymentResponseError::PendingApproval
+                    | PaymentResponseError::WithdrawalDelayed,
+                ),
+            ..
+        })) => None,
+        Message::Api(Api::PaymentResponse(payment_response)) => Some((
+            if payment_response.success {
+                PAYMENT_SUCCEEDED
+            } else {
+                PAYMENT_FAILED
+            },
+            payment_response.uid,
+            json!({
+                "req_id": payment_response.req_id,
+                "payment_hash": payment_response.payment_hash,
+                "payment_request": payment_response.payment_request,
+                "currency": payment_response.currency,
+                "amount": payment_response.amount,
+                "fees": payment_response.fees,
+                "error": payment_response.error,
+            }),
+        )),
+        Message::Api(Api::SwapResponse(swap_response)) if swap_response.success => Some((
+            SWAP_COMPLETED,
+            swap_response.uid,
+            json!({
+                "req_id": swap_response.req_id,
+                "amount": swap_response.amount,
+                "from": swap_response.from,
+                "to": swap_response.to,
+                "rate": swap_response.rate,
+                "fees": swap_response.fees,
+            }),
+        )),
+        _ => None,
+    }
+}
+
+struct Delivery {
+    delivery_id: Uuid,
+    url: String,
+    secret: Option<String>,
+    body: String,
+}
+
+impl Delivery {
+    /// Posts the payload once, returns whether the receiver accepted it.
+    fn send(&self) -> bool {
+        let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(_) => return false,
+        };
+
+        let mut request = client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(DELIVERY_HEADER, self.delivery_id.to_string())
+            .body(self.body.clone());
+
+        // Webhooks created before payloads were signed have no secret.
+        if let Some(secret) = &self.secret {
+            let timestamp = utils::time::time_now() / 1000;
+            let signature = sign(secret, timestamp, &self.body);
+            request = request.header(SIGNATURE_HEADER, format!("t={},v1={}", timestamp, signature));
+        }
+
+        matches!(request.send(), Ok(response) if response.status().is_success())
+    }
+
+    /// Retries until the receiver accepts the payload. Pending retries are lost on restart.
+    async fn deliver(self) {
+        let delivery = Arc::new(self);
+        for attempt in 0..MAX_DELIVERY_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+            let current = delivery.clone();
+            // reqwest's client is blocking so every attempt runs on the blocking pool.
+            if let Ok(true) = tokio::task::spawn_blocking(move || current.send()).await {
+                return;
+            }
+        }
+    }
+}
+
+impl WebhookDispatcher {
+    pub async fn start(pool: DbPool, mut receiver: broadcast::Receiver<Message>) {
+        loop {
+            let message = match receiver.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+
+            let (event, uid, mut payload) = match webhook_event(&message) {
+                Some(event) => event,
+                None => continue,
+            };
+
+            let conn = match pool.get() {
+                Ok(conn) => conn,
+                Err(_) => continue,
+            };
+
+            let webhooks = match Webhook::get_by_uid(&conn, uid as i32) {
+                Ok(webhooks) => webhooks,
+                Err(_) => continue,
+            };
+
+            let metadata_fields = match &message {
+                Message::Api(Api::InvoiceSettled(invoice_settled)) => Some(&invoice_settled.metadata_fields),
+                _ => None,
+            };
+
+            payload["event"] = json!(event);
+            payload["created_at"] = json!(utils::time::time_now());
+            let body = payload.to_string();
+
+            for webhook in webhooks.into_iter().filter(|webhook| {
+                webhook.subscribes_to(event)
+                    && metadata_fields.map_or(true, |fields| matches_filter(&webhook.metadata_filter, fields))
+            }) {
+                let delivery = Delivery {
+                    delivery_id: Uuid::new_v4(),
+                    url: webhook.url,
+                    secret: webhook.secret,
+                    body: body.clone(),
+                };
+                tokio::task::spawn(delivery.deliver());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        assert_eq!(
+            sign("secret", 1669800000, "{\"event\":\"invoice_settled\"}"),
+            "3f0152e2ea5748d38556fb5c3f72a4df872ba4ec361fd563cab4e89674a44825"
+        );
+    }
+}
@@ -0,0 +1,8 @@
+/// Generates the server of the grpc admin api.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    tonic_build::configure()
+        .build_client(false)
+        .compile(&["proto/admin.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use core_types::{Account, Currency};
+use crossbeam_channel::{Sender, TrySendError};
+use msgs::cli::{ApproveWithdrawal, Cli, FreezeUser, MakeTx, ReloadConfig};
+use msgs::dealer::{BankStateRequest, Dealer};
+use msgs::Message;
+use ring::rand::{SecureRandom, SystemRandom};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+pub mod proto {
+    tonic::include_proto!("admin");
+}
+
+use proto::bank_admin_server::{BankAdmin, BankAdminServer};
+
+/// Max time a call waits for the bank to process it.
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(10);
+const MACAROON_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdminApiSettings {
+    pub address: String,
+    pub tls_cert_path: String,
+    pub tls_key_path: String,
+    /// File with the secret calls are authenticated with, created with a random secret if it doesn't exist.
+    pub macaroon_path: String,
+}
+
+/// A call of the admin api waiting to be processed by the bank's loop.
+pub struct AdminRequest {
+    pub message: Message,
+    pub response_tx: oneshot::Sender<Message>,
+}
+
+/// Tells the response to an admin request apart from the other messages sent while processing it.
+pub fn is_response(msg: &Message) -> bool {
+    matches!(msg, Message::Cli(_) | Message::Dealer(Dealer::BankState(_)))
+}
+
+struct AdminService {
+    requests: Sender<AdminRequest>,
+}
+
+impl AdminService {
+    async fn call(&self, message: Message) -> Result<Message, Status> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.requests
+            .try_send(AdminRequest { message, response_tx })
+            .map_err(|err| match err {
+                TrySendError::Full(_) => Status::resource_exhausted("Too many admin calls in flight"),
+                TrySendError::Disconnected(_) => Status::unavailable("Bank is shutting down"),
+            })?;
+        match tokio::time::timeout(ADMIN_TIMEOUT, response_rx).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(Status::internal("Bank dropped the call")),
+            Err(_) => Err(Status::deadline_exceeded("Bank didn't respond in time")),
+        }
+    }
+}
+
+fn account_balance(account: &Account) -> proto::AccountBalance {
+    proto::AccountBalance {
+        account_id: account.account_id.to_string(),
+        currency: account.currency.to_string(),
+        balance: account.balance.to_string(),
+    }
+}
+
+fn parse<T: FromStr>(field: &str, value: &str) -> Result<T, Status> {
+    T::from_str(value).map_err(|_| Status::invalid_argument(format!("Invalid {}: {}", field, value)))
+}
+
+fn unexpected(response: Message) -> Status {
+    Status::internal(format!("Unexpected response: {:?}", response))
+}
+
+#[tonic::async_trait]
+impl BankAdmin for AdminService {
+    async fn get_bank_state(
+        &self,
+        _request: Request<proto::GetBankStateRequest>,
+    ) -> Result<Response<proto::GetBankStateResponse>, Status> {
+        let message = Message::Dealer(Dealer::BankStateRequest(BankStateRequest { req_id: Uuid::new_v4() }));
+        match self.call(message).await? {
+            Message::Dealer(Dealer::BankState(state)) => Ok(Response::new(proto::GetBankStateResponse {
+                total_exposures: state
+                    .total_exposures
+                    .iter()
+                    .map(|(currency, exposure)| (currency.to_string(), exposure.to_string()))
+                    .collect(),
+                fiat_exposures: state.fiat_exposures.values().map(account_balance).collect(),
+                insurance_fund: Some(account_balance(&state.insurance_fund_account)),
+            })),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn make_tx(&self, request: Request<proto::MakeTxRequest>) -> Result<Response<proto::MakeTxResponse>, Status> {
+        let request = request.into_inner();
+        let make_tx = MakeTx {
+            outbound_uid: request.outbound_uid,
+            outbound_account_id: parse::<Uuid>("outbound_account_id", &request.outbound_account_id)?,
+            inbound_uid: request.inbound_uid,
+            inbound_account_id: parse::<Uuid>("inbound_account_id", &request.inbound_account_id)?,
+            amount: parse::<Decimal>("amount", &request.amount)?,
+            currency: parse::<Currency>("currency", &request.currency)?,
+        };
+        match self.call(Message::Cli(Cli::MakeTx(make_tx))).await? {
+            Message::Cli(Cli::MakeTxResult(result)) => {
+                Ok(Response::new(proto::MakeTxResponse { result: result.result }))
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn freeze_user(
+        &self,
+        request: Request<proto::FreezeUserRequest>,
+    ) -> Result<Response<proto::FreezeUserResponse>, Status> {
+        let request = request.into_inner();
+        let freeze_user = FreezeUser {
+            uid: request.uid,
+            unfreeze: request.unfreeze,
+            operator: request.operator,
+            reason: request.reason,
+        };
+        match self.call(Message::Cli(Cli::FreezeUser(freeze_user))).await? {
+            Message::Cli(Cli::FreezeUserResult(result)) => match result.error {
+                Some(error) => Err(Status::failed_precondition(error)),
+                None => Ok(Response::new(proto::FreezeUserResponse {})),
+            },
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn approve_withdrawal(
+        &self,
+        request: Request<proto::ApproveWithdrawalRequest>,
+    ) -> Result<Response<proto::ApproveWithdrawalResponse>, Status> {
+        let request = request.into_inner();
+        let approve = ApproveWithdrawal {
+            req_id: parse::<Uuid>("req_id", &request.req_id)?,
+            operator: request.operator,
+        };
+        match self.call(Message::Cli(Cli::ApproveWithdrawal(approve))).await? {
+            Message::Cli(Cli::ApproveWithdrawalResult(result)) => match result.error {
+                Some(error) => Err(Status::failed_precondition(error)),
+                None => Ok(Response::new(proto::ApproveWithdrawalResponse {})),
+            },
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn reload_config(
+        &self,
+        _request: Request<proto::ReloadConfigRequest>,
+    ) -> Result<Response<proto::ReloadConfigResponse>, Status> {
+        match self.call(Message::Cli(Cli::ReloadConfig(ReloadConfig {}))).await? {
+            Message::Cli(Cli::ReloadConfigResult(result)) => match result.error {
+                Some(error) => Err(Status::failed_precondition(error)),
+                None => Ok(Response::new(proto::ReloadConfigResponse {
+                    reloaded: result.reloaded,
+                })),
+            },
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+/// Reads the secret of the macaroon file, the file is created with a new secret only the bank's user can read if
+/// it doesn't exist yet.
+fn load_or_create_macaroon(path: &str) -> Result<Vec<u8>, String> {
+    if let Ok(macaroon) = fs::read(path) {
+        return Ok(macaroon);
+    }
+    let mut macaroon = vec![0; MACAROON_LEN];
+    SystemRandom::new()
+        .fill(&mut macaroon)
+        .map_err(|_| "Failed to generate macaroon".to_string())?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(&macaroon))
+        .map_err(|err| format!("Failed to write macaroon to {}: {}", path, err))?;
+    Ok(macaroon)
+}
+
+fn check_macaroon(macaroon: Vec<u8>) -> impl Fn(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |request: Request<()>| {
+        let provided = request
+            .metadata()
+            .get("macaroon")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| hex::decode(value).ok());
+        match provided {
+            Some(provided) if ring::constant_time::verify_slices_are_equal(&provided, &macaroon).is_ok() => Ok(request),
+            _ => Err(Status::unauthenticated("Invalid macaroon")),
+        }
+    }
+}
+
+/// Serves the admin api over TLS until it fails. Calls are handed to the bank's loop through `requests`.
+pub async fn serve(settings: AdminApiSettings, requests: Sender<AdminRequest>) -> Result<(), String> {
+    let cert = fs::read(&settings.tls_cert_path)
+        .map_err(|err| format!("Failed to read {}: {}", settings.tls_cert_path, err))?;
+    let key =
+        fs::read(&settings.tls_key_path).map_err(|err| format!("Failed to read {}: {}", settings.tls_key_path, err))?;
+    let macaroon = load_or_create_macaroon(&settings.macaroon_path)?;
+    let address = settings
+        .address
+        .parse()
+        .map_err(|_| format!("Invalid admin api address {}", settings.address))?;
+
+    Server::builder()
+        .tls_config(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+        .map_err(|err| err.to_string())?
+        .add_service(BankAdminServer::with_interceptor(
+            AdminService { requests },
+            check_macaroon(macaroon),
+        ))
+        .serve(address)
+        .await
+        .map_err(|err| err.to_string())
+}
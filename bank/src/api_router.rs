@@ -0,0 +1,134 @@
+use core_types::RequestId;
+use msgs::api::Api;
+use msgs::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Routes are forgotten after this long, requests are answered well before.
+pub const ROUTE_TTL_MS: u64 = 600_000;
+/// Invoices are created with an expiry of a day and reported expired shortly after it.
+pub const INVOICE_ROUTE_TTL_MS: u64 = 90_000_000;
+
+/// Remembers which api instance sent a request so the response is only published to that instance.
+/// Messages without a known origin, like rate updates, are published to every instance.
+#[derive(Default)]
+pub struct ApiRouter {
+    routes: Mutex<Routes>,
+}
+
+#[derive(Default)]
+struct Routes {
+    requests: HashMap<RequestId, (String, u64)>,
+    // Settlements of invoices go to the instance the invoice was created on, so webhooks are sent once.
+    invoices: HashMap<String, (String, u64)>,
+}
+
+impl ApiRouter {
+    pub fn record(&self, msg: &Message, instance_id: &str, now: u64) {
+        let mut routes = self.routes.lock().unwrap();
+        if let Some(req_id) = msg.trace_id() {
+            routes.requests.insert(req_id, (instance_id.to_string(), now));
+        }
+    }
+
+    /// Topic the message is published under. The invoice of an invoice response is routed to the instance that
+    /// requested it until it is settled or expired.
+    pub fn topic(&self, msg: &Message) -> Vec<u8> {
+        let mut routes = self.routes.lock().unwrap();
+        let route = match msg {
+            Message::Api(Api::InvoiceSettled(settled)) => routes.invoices.remove(&settled.payment_request),
+            Message::Api(Api::InvoiceExpired(expired)) => routes.invoices.remove(&expired.payment_request),
+            _ => msg.trace_id().and_then(|req_id| routes.requests.get(&req_id).cloned()),
+        };
+        if let (Message::Api(Api::InvoiceResponse(response)), Some(route)) = (msg, &route) {
+            if let Some(payment_request) = &response.payment_request {
+                routes.invoices.insert(payment_request.clone(), route.clone());
+            }
+        }
+        match route {
+            Some((instance_id, _)) => utils::xzmq::instance_topic(&instance_id),
+            None => utils::xzmq::BROADCAST_TOPIC.to_vec(),
+        }
+    }
+
+    pub fn expire(&self, now: u64) {
+        let mut routes = self.routes.lock().unwrap();
+        routes
+            .requests
+            .retain(|_, (_, recorded_at)| *recorded_at + ROUTE_TTL_MS > now);
+        routes
+            .invoices
+            .retain(|_, (_, recorded_at)| *recorded_at + INVOICE_ROUTE_TTL_MS > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_types::{Currency, Money};
+    use msgs::api::{GetBalances, InvoiceExpired, InvoiceRequest, InvoiceResponse};
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_responses_go_to_the_requesting_instance() {
+        let router = ApiRouter::default();
+        let request = Message::Api(Api::GetBalances(GetBalances {
+            req_id: Uuid::new_v4(),
+            uid: 1,
+        }));
+        router.record(&request, "api-1", 0);
+        assert_eq!(router.topic(&request), utils::xzmq::instance_topic("api-1"));
+
+        router.expire(ROUTE_TTL_MS);
+        assert_eq!(router.topic(&request), utils::xzmq::BROADCAST_TOPIC.to_vec());
+    }
+
+    #[test]
+    fn test_expired_invoices_go_to_the_creating_instance() {
+        let router = ApiRouter::default();
+        let req_id = Uuid::new_v4();
+        let request = Message::Api(Api::InvoiceRequest(InvoiceRequest {
+            req_id,
+            uid: 1,
+            amount: Money::from_sats(dec!(1000)),
+            meta: String::new(),
+            metadata: None,
+            currency: Currency::BTC,
+            account_id: None,
+            target_account_currency: None,
+            metadata_fields: None,
+            idempotency_key: None,
+            comment: None,
+            payer_data: None,
+        }));
+        router.record(&request, "api-1", 0);
+        let response = Message::Api(Api::InvoiceResponse(InvoiceResponse {
+            req_id,
+            uid: 1,
+            payment_request: Some(String::from("lnbc1")),
+            meta: String::new(),
+            metadata: None,
+            amount: Money::from_sats(dec!(1000)),
+            rate: None,
+            currency: Currency::BTC,
+            target_account_currency: None,
+            account_id: None,
+            error: None,
+            fees: None,
+        }));
+        assert_eq!(router.topic(&response), utils::xzmq::instance_topic("api-1"));
+
+        // Invoices outlive the routes of requests.
+        router.expire(ROUTE_TTL_MS);
+        let expired = |payment_request: &str| {
+            Message::Api(Api::InvoiceExpired(InvoiceExpired {
+                uid: 1,
+                payment_request: payment_request.to_string(),
+                payment_hash: String::from("00"),
+            }))
+        };
+        assert_eq!(router.topic(&expired("lnbc2")), utils::xzmq::BROADCAST_TOPIC.to_vec());
+        assert_eq!(router.topic(&expired("lnbc1")), utils::xzmq::instance_topic("api-1"));
+    }
+}
@@ -0,0 +1,141 @@
+use core_types::*;
+use msgs::api::*;
+use msgs::bank::*;
+use msgs::cli::*;
+use msgs::dealer::*;
+use msgs::*;
+use std::fmt;
+
+/// Who caused a balance change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditActor {
+    User(UserId),
+    /// Changes made through the cli.
+    Admin,
+    Dealer,
+    /// Changes the bank makes on its own, e.g. settled deposits or interest.
+    Bank,
+}
+
+impl AuditActor {
+    pub fn actor_id(&self) -> Option<String> {
+        match self {
+            AuditActor::User(uid) => Some(uid.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AuditActor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditActor::User(_) => write!(f, "User"),
+            AuditActor::Admin => write!(f, "Admin"),
+            AuditActor::Dealer => write!(f, "Dealer"),
+            AuditActor::Bank => write!(f, "Bank"),
+        }
+    }
+}
+
+/// Describes the operation currently processed, attached to every transaction booked while it is processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditContext {
+    pub actor: AuditActor,
+    pub operation: &'static str,
+    pub req_id: Option<RequestId>,
+}
+
+impl AuditContext {
+    pub fn bank(operation: &'static str) -> Self {
+        Self {
+            actor: AuditActor::Bank,
+            operation,
+            req_id: None,
+        }
+    }
+
+    fn user(uid: UserId, operation: &'static str, req_id: RequestId) -> Self {
+        Self {
+            actor: AuditActor::User(uid),
+            operation,
+            req_id: Some(req_id),
+        }
+    }
+
+    fn dealer(operation: &'static str, req_id: Option<RequestId>) -> Self {
+        Self {
+            actor: AuditActor::Dealer,
+            operation,
+            req_id,
+        }
+    }
+
+    pub fn from_message(msg: &Message) -> Self {
+        match msg {
+            Message::Api(Api::PaymentRequest(req)) => Self::user(req.uid, "Payment", req.req_id),
+            Message::Api(Api::SwapRequest(req)) => Self::user(req.uid, "Swap", req.req_id),
+            Message::Api(Api::SwapResponse(resp)) => Self::user(resp.uid, "Swap", resp.req_id),
+            Message::Api(Api::CashOutRequest(req)) => Self::user(req.uid, "CashOut", req.req_id),
+            Message::Api(Api::InternalTransferRequest(req)) => Self::user(req.uid, "InternalTransfer", req.req_id),
+            Message::Api(Api::LiquidWithdrawalRequest(req)) => Self::user(req.uid, "LiquidWithdrawal", req.req_id),
+            Message::Api(Api::LiquidClaimPegInRequest(req)) => Self::user(req.uid, "LiquidPegIn", req.req_id),
+            Message::Api(Api::EcashWithdrawRequest(req)) => Self::user(req.uid, "EcashWithdraw", req.req_id),
+            Message::Api(Api::EcashDepositRequest(req)) => Self::user(req.uid, "EcashDeposit", req.req_id),
+            Message::Bank(Bank::PaymentResult(result)) => {
+                Self::user(result.uid, "Payment", result.payment_response.req_id)
+            }
+            Message::Deposit(_) => Self::bank("Deposit"),
+            Message::Dealer(Dealer::PayInvoice(req)) => Self::dealer("PayInvoice", Some(req.req_id)),
+            Message::Dealer(Dealer::PayInsuranceInvoice(req)) => Self::dealer("PayInsuranceInvoice", Some(req.req_id)),
+            Message::Dealer(Dealer::FiatDepositRequest(req)) => Self::dealer("FiatDeposit", Some(req.req_id)),
+            Message::Dealer(Dealer::FundingIncome(_)) => Self::dealer("FundingIncome", None),
+            Message::Dealer(Dealer::HedgingPnl(_)) => Self::dealer("HedgingPnl", None),
+            Message::Dealer(Dealer::SwapOrderFill(fill)) => Self::user(fill.uid, "SwapOrderFill", fill.fill_id),
+            Message::Dealer(_) => Self::dealer("Dealer", None),
+            Message::Cli(Cli::MakeTx(_)) => Self {
+                actor: AuditActor::Admin,
+                operation: "MakeTx",
+                req_id: None,
+            },
+            Message::Cli(Cli::Rebalance(_)) => Self {
+                actor: AuditActor::Admin,
+                operation: "Rebalance",
+                req_id: None,
+            },
+            Message::Cli(_) => Self {
+                actor: AuditActor::Admin,
+                operation: "Admin",
+                req_id: None,
+            },
+            _ => Self::bank("Other"),
+        }
+    }
+}
+
+impl Default for AuditContext {
+    fn default() -> Self {
+        Self::bank("Other")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_context_of_cli_tx() {
+        let msg = Message::Cli(Cli::MakeTx(MakeTx {
+            outbound_uid: 1,
+            outbound_account_id: Uuid::new_v4(),
+            inbound_uid: 2,
+            inbound_account_id: Uuid::new_v4(),
+            amount: rust_decimal_macros::dec!(1),
+            currency: Currency::BTC,
+        }));
+        let context = AuditContext::from_message(&msg);
+        assert_eq!(context.actor, AuditActor::Admin);
+        assert_eq!(context.actor.actor_id(), None);
+        assert_eq!(context.operation, "MakeTx");
+    }
+}
@@ -0,0 +1,168 @@
+use msgs::api::*;
+use msgs::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Messages read from the api socket per iteration of the bank's loop, which processes one of them.
+pub const API_READS_PER_ITERATION: usize = 64;
+
+fn default_zmq_high_water_mark() -> i32 {
+    10_000
+}
+
+fn default_api_inbox_capacity() -> usize {
+    10_000
+}
+
+fn default_shed_threshold() -> usize {
+    1_000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackpressureSettings {
+    /// Messages zmq queues per peer before senders block.
+    #[serde(default = "default_zmq_high_water_mark")]
+    pub zmq_high_water_mark: i32,
+    /// Messages from the api read ahead of processing, the socket isn't read while this many are waiting.
+    #[serde(default = "default_api_inbox_capacity")]
+    pub api_inbox_capacity: usize,
+    /// Requests are answered with `ServiceOverloaded` once this many messages are waiting.
+    #[serde(default = "default_shed_threshold")]
+    pub shed_threshold: usize,
+}
+
+impl Default for BackpressureSettings {
+    fn default() -> Self {
+        Self {
+            zmq_high_water_mark: default_zmq_high_water_mark(),
+            api_inbox_capacity: default_api_inbox_capacity(),
+            shed_threshold: default_shed_threshold(),
+        }
+    }
+}
+
+/// Messages from the api waiting to be processed by the bank's loop.
+pub struct ApiInbox {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    shed_threshold: usize,
+}
+
+impl ApiInbox {
+    pub fn new(settings: &BackpressureSettings) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            capacity: settings.api_inbox_capacity,
+            shed_threshold: settings.shed_threshold,
+        }
+    }
+
+    pub fn has_room(&self) -> bool {
+        self.messages.len() < self.capacity
+    }
+
+    /// Queues the message. Requests are rejected with the returned response instead while the inbox is above
+    /// the shed threshold, everything else is always queued.
+    pub fn push(&mut self, msg: Message) -> Result<(), Message> {
+        if self.messages.len() >= self.shed_threshold {
+            if let Some(response) = overloaded_response(&msg) {
+                return Err(response);
+            }
+        }
+        self.messages.push_back(msg);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<Message> {
+        self.messages.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Response rejecting a request the bank is too busy for, `None` for messages that are never shed.
+pub fn overloaded_response(msg: &Message) -> Option<Message> {
+    let response = match msg {
+        Message::Api(Api::InvoiceRequest(request)) => Api::InvoiceResponse(InvoiceResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            payment_request: None,
+            meta: request.meta.clone(),
+            metadata: request.metadata.clone(),
+            amount: request.amount.clone(),
+            rate: None,
+            currency: request.currency,
+            target_account_currency: request.target_account_currency,
+            account_id: None,
+            error: Some(InvoiceResponseError::ServiceOverloaded),
+            fees: None,
+        }),
+        Message::Api(Api::PaymentRequest(request)) => Api::PaymentResponse(PaymentResponse::error(
+            PaymentResponseError::ServiceOverloaded,
+            request.req_id,
+            request.uid,
+            request.payment_request.clone(),
+            request.currency,
+            None,
+        )),
+        Message::Api(Api::SwapRequest(request)) => Api::SwapResponse(SwapResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            success: false,
+            amount: request.amount.clone(),
+            from: request.from,
+            to: request.to,
+            rate: None,
+            error: Some(SwapResponseError::ServiceOverloaded),
+            fees: None,
+        }),
+        Message::Api(Api::GetBalances(request)) => Api::Balances(Balances {
+            req_id: request.req_id,
+            uid: request.uid,
+            accounts: HashMap::new(),
+            shared_accounts: HashMap::new(),
+            error: Some(BalancesResponseError::ServiceOverloaded),
+        }),
+        _ => return None,
+    };
+    Some(Message::Api(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use msgs::dealer::{BankStateRequest, Dealer};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_requests_are_shed_above_threshold() {
+        let mut inbox = ApiInbox::new(&BackpressureSettings {
+            zmq_high_water_mark: 10,
+            api_inbox_capacity: 10,
+            shed_threshold: 1,
+        });
+        let request = || {
+            Message::Api(Api::GetBalances(GetBalances {
+                req_id: Uuid::new_v4(),
+                uid: 1,
+            }))
+        };
+        assert!(inbox.push(request()).is_ok());
+        match inbox.push(request()) {
+            Err(Message::Api(Api::Balances(balances))) => {
+                assert!(matches!(balances.error, Some(BalancesResponseError::ServiceOverloaded)))
+            }
+            other => panic!("Expected an overloaded response, got {:?}", other),
+        }
+
+        let state_request = Message::Dealer(Dealer::BankStateRequest(BankStateRequest { req_id: Uuid::new_v4() }));
+        assert!(inbox.push(state_request).is_ok());
+        assert_eq!(inbox.len(), 2);
+    }
+}
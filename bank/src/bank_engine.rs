@@ -2,33 +2,132 @@ use rust_decimal::prelude::*;
 use rust_decimal_macros::*;
 
 use bigdecimal::BigDecimal;
-use std::collections::HashMap;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+use core_types::access::{AccessPolicy, RequestOrigin};
+use core_types::currency_registry::{self, CurrencyInfo};
 use core_types::*;
 use diesel::result::Error as DieselError;
-use models::{accounts, invoices::Invoice, users::User};
+use diesel::{Connection, OptionalExtension};
+use models::{
+    access_policies, account_members, accounts,
+    api_keys::{ApiKey, ApiKeySpend},
+    audit_logs::InsertableAuditLog,
+    data_exports::DataExport,
+    dead_letters::{DeadLetter, InsertableDeadLetter},
+    dealer_health_events::InsertableDealerHealthEvent,
+    deposit_anomalies::InsertableDepositAnomaly,
+    deposit_limits::DepositLimit,
+    deposit_routing_rules::DepositRoutingRule,
+    dust_sweep_preferences::DustSweepPreference,
+    flow_statistics::{FlowStatistic, InsertableFlowStatistic},
+    frozen_accounts::FrozenAccount,
+    hedge_targets::HedgeTarget,
+    idempotency_keys::IdempotencyKey,
+    interest_accruals::{InsertableInterestAccrual, InterestAccrual},
+    invoice_checkpoints::InvoiceCheckpoint,
+    invoices::Invoice,
+    liquid::{LiquidAddress, LiquidDeposit},
+    liquidity_swaps::{InsertableLiquiditySwap, LiquiditySwap},
+    lnurl_withdrawal_requests::LnurlWithdrawalRequest,
+    node_info::NodeInfo,
+    operator_revenues::{InsertableOperatorRevenue, OperatorRevenue},
+    payment_retries::{PaymentRetry, ATTEMPT_IN_FLIGHT},
+    pending_payments::PendingPayment,
+    pending_withdrawals,
+    period_closes::{InsertablePeriodClose, PeriodClose},
+    referrals::Referral,
+    settlement_batches::SettlementBatchMember,
+    summary_transactions::SummaryTransaction,
+    swap_orders,
+    transactions::Transaction,
+    users::User,
+    vault_withdrawals,
+    webhooks::Webhook,
+    withdrawal_limits::WithdrawalLimit,
+};
 
 use msgs::api::*;
 use msgs::bank::*;
 use msgs::dealer::*;
 use msgs::*;
 use std::iter::Iterator;
-use utils::currencies::{SATS_DECIMALS, SATS_IN_BITCOIN};
+use utils::currencies::{MSATS_DECIMALS, SATS_DECIMALS};
 use utils::xlogging::*;
 use xerror::bank_engine::*;
+use xerror::lnd_connector::LndConnectorError;
 
 use futures::stream::FuturesUnordered;
-use lnd_connector::connector::{LndConnector, LndConnectorSettings};
-
-use msgs::cli::{Cli, MakeTx, MakeTxResult};
+use lnd_connector::connector::{LndConnector, LndConnectorSettings, PayResponse, PaymentLookup};
+use lnd_connector::pool::LndConnectorPool;
+use lnd_connector::taproot_assets::{TaprootAssetsConnector, TaprootAssetsSettings};
+use tracing::Instrument;
+
+use msgs::cli::{
+    ApproveWithdrawalResult, Cli, ClosePeriod, ClosePeriodResult, ClosingBalance, ClosingReport, DeadLetterInfo,
+    DeadLettersResult, ExportJournal, ExportJournalResult, FeeIncomeBucket, ForceCloseAccount, ForceCloseAccountResult,
+    FreezeAccount, FreezeAccountResult, FreezeReason, FreezeUser, FreezeUserResult, FrozenAccountInfo,
+    FrozenAccountsResult, GetRevenueReport, HeldWithdrawal, LedgerAccountDrift, LedgerAccountEntry, LedgerBook,
+    LedgerPosting, LedgerQueryResult, LiquiditySwapInfo, LiquiditySwapsResult, ListDeadLetters, ListLiquiditySwaps,
+    MakeTx, MakeTxResult, PendingLnurlWithdrawal, PendingLnurlWithdrawalsResult, PendingWithdrawalsResult, QueryLedger,
+    QueryRevenue, Rebalance, RebalanceResult, RejectWithdrawalResult, ReloadConfigResult, ReopenPeriod,
+    ReopenPeriodResult, ReplayDeadLetterResult, ResetRateLimitsResult, RevenueBucket, RevenueQueryResult,
+    RevenueReport, SetDepositLimit, SetDepositLimitResult, SetWithdrawalLimit, SetWithdrawalLimitResult, Simulate,
+    SimulationReport, SimulationResult, UnfreezeAccount, UnfreezeAccountResult,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::admin::AdminApiSettings;
+use crate::audit::AuditContext;
+use crate::backpressure::BackpressureSettings;
+use crate::content_filter::*;
+use crate::data_export::*;
+use crate::db_writer::{DbWriter, DEFAULT_DB_WRITER_THREADS};
+use crate::ecash::{EcashGateway, EcashSettings};
+use crate::exporter::*;
+use crate::fees::*;
+use crate::hedging::*;
+use crate::idempotency::*;
+use crate::interest::*;
+use crate::key_budgets;
 use crate::ledger::*;
+use crate::liquid::*;
+use crate::liquidity_swaps::*;
+use crate::metrics::BankMetrics;
+use crate::probe_cache::ProbeCache;
+use crate::rate_limiter::TokenBucketLimiter;
+use crate::reserves::build_reserves_report;
+use crate::revenue::*;
+use crate::settlement::*;
+use crate::simulator::{simulate, simulation_period, CurrentFees};
+use crate::statement::{build_statement, statement_csv, RATE_LOOKBACK_MILLIS};
+use crate::statistics::{aggregate_flows, statistics_to_json, FlowStatisticsSettings};
+use crate::totp::{self, TotpSettings};
+use crate::velocity::*;
 
 const BANK_UID: u64 = 23193913;
 const DEALER_UID: u64 = 52172712;
+const WITHDRAWAL_DURATIONS_SIZE: usize = 1000;
+// Reserves reports are reused for this long before the node is queried again.
+const RESERVES_REPORT_TTL_MS: u64 = 60_000;
+const MAX_LEDGER_QUERY_LIMIT: usize = 100;
+const MAX_LEDGER_QUERY_POSTINGS: usize = 50;
+// The dealer reports its health every 5 seconds, without a report for this long it is considered down.
+const DEALER_HEALTH_TIMEOUT_MS: u64 = 30_000;
+/// Hedges aren't rebalanced at market prices older than this.
+const MARKET_PRICES_MAX_AGE_MS: u64 = 60_000;
+const MAX_SUB_ACCOUNTS: usize = 10;
+// The inbound capacity of the node is fetched again after this long.
+const INBOUND_CAPACITY_TTL_MS: u64 = 60_000;
+const MAX_ACCOUNT_LABEL_LENGTH: usize = 64;
+const MILLIS_IN_HOUR: u64 = 3_600_000;
+const PAYMENT_LOOKUP_RETRY_SECS: u64 = 30;
+// QR codes of LNURL withdrawals can be scanned for this long.
+const LNURL_WITHDRAWAL_TTL_MS: u64 = 600_000;
+// Reconciliation waits this long for queued balance writes, the loop is blocked meanwhile.
+const RECONCILIATION_WRITE_WAIT_MS: u64 = 2_000;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RateLimiterSettings {
@@ -36,6 +135,132 @@ pub struct RateLimiterSettings {
     pub replenishment_interval: u64,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DustSweepSettings {
+    /// Fiat balances below the threshold of their currency are swapped to BTC, e.g. `USD = 0.05`.
+    pub thresholds: HashMap<String, Decimal>,
+    pub interval_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VaultSettings {
+    /// Time between a withdrawal from a vault and the payment, the user can cancel it in the meantime.
+    pub withdrawal_delay_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaymentRetrySettings {
+    /// Max number of attempts for a payment before it is refunded.
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LnurlChannelSettings {
+    /// Capacity of the channels the node opens to users.
+    pub channel_size_sats: u64,
+    /// Charged from the user's BTC account for opening the channel.
+    pub fee_sats: u64,
+}
+
+fn default_lnurl_base_url() -> String {
+    String::from("https://lndhubx.com/api")
+}
+
+fn default_lnurl_withdrawal_request_path() -> String {
+    String::from("/lnurl_withdrawal/request")
+}
+
+fn default_lnurl_withdrawal_callback_path() -> String {
+    String::from("/lnurl_withdrawal/pay")
+}
+
+fn default_lnurl_channel_callback_path() -> String {
+    String::from("/lnurl_channel/open")
+}
+
+/// Where wallets reach the api's LNURL endpoints, the paths are appended to the base url.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LnurlSettings {
+    /// Public url of the api, e.g. `https://example.com/api`.
+    #[serde(default = "default_lnurl_base_url")]
+    pub base_url: String,
+    /// Encoded into the LNURL of withdrawals, the request id is added as query.
+    #[serde(default = "default_lnurl_withdrawal_request_path")]
+    pub withdrawal_request_path: String,
+    #[serde(default = "default_lnurl_withdrawal_callback_path")]
+    pub withdrawal_callback_path: String,
+    #[serde(default = "default_lnurl_channel_callback_path")]
+    pub channel_callback_path: String,
+}
+
+impl Default for LnurlSettings {
+    fn default() -> Self {
+        Self {
+            base_url: default_lnurl_base_url(),
+            withdrawal_request_path: default_lnurl_withdrawal_request_path(),
+            withdrawal_callback_path: default_lnurl_withdrawal_callback_path(),
+            channel_callback_path: default_lnurl_channel_callback_path(),
+        }
+    }
+}
+
+impl LnurlSettings {
+    pub fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReconciliationSettings {
+    pub interval_secs: u64,
+    /// Largest tolerated difference between the ledger and the database for a single account.
+    pub max_drift: Decimal,
+    /// Stop processing withdrawals while the drift of any account exceeds `max_drift`.
+    #[serde(default)]
+    pub halt_withdrawals: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountDrift {
+    pub account_id: AccountId,
+    pub ledger_balance: Decimal,
+    pub persisted_balance: Option<Decimal>,
+    pub transactions_balance: Option<Decimal>,
+}
+
+impl AccountDrift {
+    pub fn max_drift(&self) -> Decimal {
+        [self.persisted_balance, self.transactions_balance]
+            .iter()
+            .map(|balance| (self.ledger_balance - balance.unwrap_or(Decimal::ZERO)).abs())
+            .max()
+            .unwrap_or(Decimal::ZERO)
+    }
+}
+
+/// A cash out waiting for the swap of the fiat balance to BTC.
+#[derive(Debug, Clone)]
+pub struct PendingCashOut {
+    pub request: CashOutRequest,
+    /// Amount in BTC the BTC account needs after the swap to pay the invoice with the worst case fee.
+    pub required_btc: Decimal,
+}
+
+/// Outcome of checking a deposit with the node.
+enum DepositCheck {
+    Confirmed,
+    /// Not settled or underpaid, the deposit is never credited.
+    Rejected,
+    /// The node couldn't be asked, the deposit is retried.
+    Unknown,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BankEngineSettings {
     /// url to the postgres database.
@@ -47,6 +272,9 @@ pub struct BankEngineSettings {
     /// The margin users have to keep on their account to account for network fees.
     pub ln_network_fee_margin: Decimal,
     pub ln_network_max_fee: Decimal,
+    /// Routing fee budgets by payment size, `ln_network_fee_margin` of the amount applies if none covers a payment.
+    #[serde(default)]
+    pub ln_fee_budgets: Vec<FeeBudget>,
     pub internal_tx_fee: Decimal,
     pub external_tx_fee: Decimal,
     pub reserve_ratio: Decimal,
@@ -60,6 +288,115 @@ pub struct BankEngineSettings {
     pub bank_cli_resp_address: String,
     pub withdrawal_request_rate_limiter_settings: RateLimiterSettings,
     pub deposit_request_rate_limiter_settings: RateLimiterSettings,
+    pub export_settings: Option<ExportSettings>,
+    /// Failed payments are refunded immediately if not set.
+    pub payment_retry_settings: Option<PaymentRetrySettings>,
+    /// Notices shown on the public status page.
+    #[serde(default)]
+    pub maintenance_notices: Vec<String>,
+    /// Payments never pay more than the fee quoted by the probe and users are always charged the quoted fee.
+    #[serde(default)]
+    pub strict_fee_quotes: bool,
+    /// Every ledger mutation is appended to this journal before it is persisted if set.
+    #[serde(default)]
+    pub ledger_journal_path: Option<String>,
+    /// Address Prometheus metrics are served on, not served if not set.
+    #[serde(default)]
+    pub bank_metrics_address: Option<String>,
+    /// OTLP collector the spans of requests are exported to, not traced if not set.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Threads account balances and other writes the bank doesn't read back are written to the database on,
+    /// `DEFAULT_DB_WRITER_THREADS` if not set.
+    #[serde(default)]
+    pub db_writer_threads: Option<usize>,
+    /// Window in which balance writes of the same account are coalesced into one, not coalesced if not set.
+    /// Only used with a ledger journal, which restores the writes held back if the bank stops.
+    #[serde(default)]
+    pub account_write_coalescing_millis: Option<u64>,
+    /// Queue limits of the sockets and of the messages from the api waiting to be processed.
+    #[serde(default)]
+    pub backpressure: BackpressureSettings,
+    /// Grpc api for ops tooling, not served if not set.
+    #[serde(default)]
+    pub admin_api: Option<AdminApiSettings>,
+    /// Ledger balances are only compared with the database if set.
+    pub reconciliation_settings: Option<ReconciliationSettings>,
+    /// Origins money-moving requests of all users are accepted from.
+    #[serde(default)]
+    pub access_policy: AccessPolicy,
+    /// Share of the node's inbound capacity a single invoice may ask for. Invoices aren't capped if not set.
+    #[serde(default)]
+    pub inbound_capacity_headroom: Option<Decimal>,
+    /// Share of the node's outbound capacity a single withdrawal may use. Withdrawals aren't capped if not set.
+    #[serde(default)]
+    pub outbound_capacity_headroom: Option<Decimal>,
+    /// Invoice memos and metadata are accepted as they are if not set.
+    #[serde(default)]
+    pub content_filter: ContentFilterSettings,
+    /// Balances are never swept if not set.
+    #[serde(default)]
+    pub dust_sweep_settings: Option<DustSweepSettings>,
+    /// Invoice memos and metadata are replaced by their hash once they are this many days old. Kept if not set.
+    #[serde(default)]
+    pub description_retention_days: Option<u64>,
+    /// Share of the fees of referred users paid to their referrer, e.g. 0.2 for 20%. Nothing is shared if not set.
+    #[serde(default)]
+    pub referral_share: Option<Decimal>,
+    /// Operations are free if not set.
+    #[serde(default)]
+    pub fee_schedule: FeeScheduleSettings,
+    /// Fiat balances don't accrue interest if not set.
+    #[serde(default)]
+    pub interest: InterestSettings,
+    /// Dealer settlements are executed one by one if not set.
+    #[serde(default)]
+    pub settlement_batching: Option<SettlementBatchingSettings>,
+    /// Withdrawals are only limited by the number of requests if not set.
+    #[serde(default)]
+    pub withdrawal_velocity: VelocityLimits,
+    /// No statistics are aggregated or published if not set.
+    #[serde(default)]
+    pub flow_statistics: Option<FlowStatisticsSettings>,
+    /// Withdrawals above this many sats are held until an admin approves them. Nothing is held if not set.
+    #[serde(default)]
+    pub withdrawal_approval_threshold_sats: Option<u64>,
+    /// Routing fees in sats rebalancing may spend over the last 24 hours. Rebalancing is refused if not set.
+    #[serde(default)]
+    pub rebalance_daily_fee_budget_sats: Option<u64>,
+    /// Payments and swaps never need a 2fa code if not set.
+    #[serde(default)]
+    pub totp: TotpSettings,
+    /// Key the dealer signs its quotes with. Swaps at a quote are left to the dealer to check if not set.
+    #[serde(default)]
+    pub quote_signing_key: Option<String>,
+    /// Liquidity is left to the operator if not set.
+    #[serde(default)]
+    pub liquidity_swaps: Option<LiquiditySwapSettings>,
+    /// Users can't request channels from the node if not set.
+    #[serde(default)]
+    pub lnurl_channel: Option<LnurlChannelSettings>,
+    /// Asset backed currencies can't be deposited or withdrawn if not set.
+    #[serde(default)]
+    pub taproot_assets: Option<TaprootAssetsSettings>,
+    /// Federation users withdraw ecash from and deposit it to, ecash isn't offered if not set.
+    #[serde(default)]
+    pub ecash: Option<EcashSettings>,
+    /// Elementsd L-BTC is deposited to and withdrawn from, L-BTC isn't offered if not set.
+    #[serde(default)]
+    pub liquid: Option<LiquidSettings>,
+    /// Urls of the LNURL endpoints handed to wallets, lndhubx.com's if not set.
+    #[serde(default)]
+    pub lnurl: LnurlSettings,
+    /// Currencies on top of the built-in ones, e.g. further fiat synthetics.
+    #[serde(default)]
+    pub currencies: Vec<CurrencyInfo>,
+    /// Hedge targets of users are ignored if not set.
+    #[serde(default)]
+    pub hedge_rebalance_settings: Option<HedgeRebalanceSettings>,
+    /// Vault accounts can't be opened if not set.
+    #[serde(default)]
+    pub vault_settings: Option<VaultSettings>,
 }
 
 impl Default for Ledger {
@@ -68,56 +405,346 @@ impl Default for Ledger {
     }
 }
 
-pub struct FeeStructure {
-    _internal_tx_fee: Decimal,
-    _external_tx_fee: Decimal,
-    _conversion_fee: Decimal,
-}
-
-impl FeeStructure {
-    pub fn new() -> Self {
-        Self {
-            _internal_tx_fee: dec!(0),
-            _external_tx_fee: dec!(0),
-            _conversion_fee: dec!(0),
-        }
-    }
-}
-
-impl Default for FeeStructure {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 pub struct BankEngine {
     pub bank_uid: UserId,
     /// Bank state.
     pub ledger: Ledger,
-    /// Fees structure of bank operation.
-    pub fee_structure: FeeStructure,
+    /// Fees charged on bank operations.
+    pub fee_engine: FeeEngine,
     /// Connection to the postgres DB.
     pub conn_pool: Option<DbPool>,
+    /// Writes to the DB the loop doesn't wait for, only set with a DB.
+    pub db_writer: Option<DbWriter>,
+    /// Accounts known to be in the DB, their balances are written by the db writer.
+    pub persisted_accounts: HashSet<AccountId>,
+    pub account_write_coalescing_millis: Option<u64>,
+    pub last_account_flush_timestamp: u64,
     pub lnd_connector: LndConnector,
     pub lnd_node_info: LndNodeInfo,
     pub available_currencies: Vec<Currency>,
     pub ln_network_fee_margin: Decimal,
     pub ln_network_max_fee: Decimal,
+    pub ln_fee_budgets: Vec<FeeBudget>,
     pub internal_tx_fee: Decimal,
     pub external_tx_fee: Decimal,
     pub reserve_ratio: Decimal,
     pub withdrawal_only: bool,
     pub deposit_limits: HashMap<Currency, Decimal>,
     pub logger: slog::Logger,
-    pub tx_seq: u64,
     pub lnurl_withdrawal_requests: HashMap<Uuid, (u64, PaymentRequest)>,
+    pub lnurl_settings: LnurlSettings,
+    pub lnurl_channel_settings: Option<LnurlChannelSettings>,
+    /// Channels offered through LNURL-channel by their k1, waiting for the user's node to call back.
+    pub lnurl_channel_requests: HashMap<Uuid, (u64, UserId)>,
     pub payment_thread_sender: crossbeam_channel::Sender<Message>,
     pub lnd_connector_settings: LndConnectorSettings,
+    /// Connection shared with the payment tasks.
+    pub lnd_connector_pool: LndConnectorPool,
     pub payment_threads: FuturesUnordered<tokio::task::JoinHandle<()>>,
     pub withdrawal_request_rate_limiter_settings: RateLimiterSettings,
     pub deposit_request_rate_limiter_settings: RateLimiterSettings,
-    pub withdrawal_request_rate_limiter: HashMap<UserId, (u64, Instant)>,
-    pub deposit_request_rate_limiter: HashMap<UserId, (u64, Instant)>,
+    pub withdrawal_request_rate_limiter: TokenBucketLimiter,
+    pub deposit_request_rate_limiter: TokenBucketLimiter,
+    pub export_settings: Option<ExportSettings>,
+    pub payment_retry_settings: Option<PaymentRetrySettings>,
+    pub maintenance_notices: Vec<String>,
+    pub strict_fee_quotes: bool,
+    /// Start of every outgoing payment that has not returned a final result yet.
+    pub payment_starts: HashMap<RequestId, Instant>,
+    /// Processing times of the most recent withdrawals in millis.
+    pub withdrawal_durations: VecDeque<u64>,
+    /// End of the period covered by the last scheduled journal export.
+    pub last_export_timestamp: u64,
+    /// Requests with an idempotency key that are waiting for their response, with the time they were received.
+    pub idempotent_requests: HashMap<RequestId, (UserId, String, u64)>,
+    pub ledger_journal: Option<LedgerJournal>,
+    pub reconciliation_settings: Option<ReconciliationSettings>,
+    pub last_db_reconciliation_timestamp: u64,
+    /// Drifts found by the last reconciliation with the database.
+    pub account_drifts: Vec<AccountDrift>,
+    pub withdrawals_halted: bool,
+    /// Set while the db writer retries a failed write, withdrawals are halted meanwhile.
+    pub db_writes_failing: bool,
+    pub metrics: BankMetrics,
+    /// Dead letters queued for replay, processed by the main loop like newly received messages.
+    pub replayed_messages: VecDeque<Message>,
+    pub access_policy: AccessPolicy,
+    pub last_reserves_report: Option<ReservesReport>,
+    /// Last persisted availability of every dealer currency.
+    pub dealer_availability: HashMap<Currency, bool>,
+    pub last_dealer_health_timestamp: u64,
+    pub pending_cash_outs: HashMap<RequestId, PendingCashOut>,
+    /// Accounts fiat deposits waiting for their conversion were routed to.
+    pub deposit_targets: HashMap<RequestId, AccountId>,
+    /// Deposits that couldn't be confirmed or credited because the node or the database was unavailable.
+    pub retried_deposits: Vec<Deposit>,
+    /// Internal transfers to an account of another currency waiting for a rate from the dealer.
+    pub pending_transfers: HashMap<RequestId, PaymentRequest>,
+    pub inbound_capacity_headroom: Option<Decimal>,
+    /// Largest invoice amount in sats the node can realistically receive, unknown until first fetched.
+    pub max_receivable_sats: Option<u64>,
+    pub last_inbound_capacity_timestamp: u64,
+    pub outbound_capacity_headroom: Option<Decimal>,
+    /// Largest withdrawal in sats the node's channels can currently send, unknown until first fetched.
+    pub max_payable_sats: Option<u64>,
+    pub last_outbound_capacity_timestamp: u64,
+    pub content_filter: ContentFilter,
+    /// Start of the next day whose revenue is aggregated, looked up in the database if not set.
+    pub next_revenue_day: Option<u64>,
+    pub dust_sweep_settings: Option<DustSweepSettings>,
+    pub last_dust_sweep_timestamp: u64,
+    /// End of the last closed period, no transactions can be booked up to it.
+    pub closed_until: Option<u64>,
+    pub description_retention_days: Option<u64>,
+    pub last_description_hashing_timestamp: u64,
+    pub referral_share: Option<Decimal>,
+    pub interest_settings: InterestSettings,
+    /// Start of the next day interest is accrued for, looked up in the database if not set.
+    pub next_interest_day: Option<u64>,
+    pub settlement_batching: Option<SettlementBatchingSettings>,
+    pub settlement_queue: SettlementQueue,
+    pub velocity_limits: VelocityLimits,
+    /// Sats of payments that are being paid, counted towards the velocity limits until they complete.
+    pub withdrawals_in_flight: HashMap<RequestId, (UserId, u64)>,
+    pub flow_statistics: Option<FlowStatisticsSettings>,
+    /// Start of the next day whose flows are aggregated, looked up in the database if not set.
+    pub next_flow_statistics_day: Option<u64>,
+    pub withdrawal_approval_threshold_sats: Option<u64>,
+    pub rebalance_daily_fee_budget_sats: Option<u64>,
+    /// Held withdrawals that were approved and are on their way back through the loopback.
+    pub approved_withdrawals: HashSet<RequestId>,
+    pub totp_settings: TotpSettings,
+    /// Cash outs whose 2fa code was checked, their payment comes back through the loopback.
+    pub totp_verified: HashSet<RequestId>,
+    /// Accounts frozen by an admin, mirrors the frozen_accounts table.
+    pub frozen_accounts: HashMap<AccountId, FreezeReason>,
+    pub quote_signing_key: Option<String>,
+    /// Signed quotes of swaps sent to the dealer, their rate replaces the rate of the dealer's response.
+    pub firm_quotes: HashMap<RequestId, FirmQuote>,
+    /// Open limit swap orders, mirrors the open orders of the swap_orders table.
+    pub swap_orders: HashMap<Uuid, SwapOrder>,
+    /// Operation being processed, recorded in the audit log of every transaction it books.
+    pub audit_context: AuditContext,
+    pub probe_cache: ProbeCache,
+    pub liquidity_swap_settings: Option<LiquiditySwapSettings>,
+    pub swap_provider: Option<Box<dyn SwapProvider>>,
+    pub last_liquidity_swap_check: u64,
+    pub taproot_assets: Option<TaprootAssetsConnector>,
+    pub ecash_gateway: Option<EcashGateway>,
+    pub liquid: Option<ElementsClient>,
+    /// Block the wallet is checked for deposits from, the whole history is checked after a restart.
+    pub liquid_last_block: Option<String>,
+    pub last_liquid_deposit_check: u64,
+    /// Latest prices streamed by the dealer, hedges are valued at them.
+    pub last_market_prices: Option<MarketPrices>,
+    pub hedge_rebalance_settings: Option<HedgeRebalanceSettings>,
+    pub last_hedge_rebalance_timestamp: u64,
+    pub vault_settings: Option<VaultSettings>,
+    /// Vault withdrawals whose delay passed, they skip the delay when they come back through the loopback.
+    pub released_vault_withdrawals: HashSet<RequestId>,
+}
+
+fn account_member(member: &account_members::AccountMember) -> Option<AccountMember> {
+    Some(AccountMember {
+        account_id: member.account_id,
+        uid: member.uid as UserId,
+        role: MemberRole::from_str(&member.role).ok()?,
+        spend_limit: member
+            .spend_limit
+            .as_ref()
+            .and_then(|limit| Decimal::from_str(&limit.to_string()).ok()),
+        accepted: member.accepted,
+    })
+}
+
+fn swap_order(order: &swap_orders::SwapOrder) -> Option<SwapOrder> {
+    Some(SwapOrder {
+        order_id: order.order_id,
+        uid: order.uid as UserId,
+        from: Currency::from_str(&order.from_currency).ok()?,
+        to: Currency::from_str(&order.to_currency).ok()?,
+        amount: Decimal::from_str(&order.amount.to_string()).ok()?,
+        filled_amount: Decimal::from_str(&order.filled_amount.to_string()).ok()?,
+        limit_rate: Decimal::from_str(&order.limit_rate.to_string()).ok()?,
+        status: SwapOrderStatus::from_str(&order.status).ok()?,
+        created_at: order.created_at as u64,
+    })
+}
+
+/// Hash lnd tracks a pending payment by, `None` if its payment request can't be decoded.
+fn pending_payment_hash(pending: &PaymentResult) -> Option<String> {
+    match &pending.keysend {
+        Some(keysend) => Some(keysend.payment_hash.clone()),
+        None => pending
+            .payment_response
+            .payment_request
+            .as_ref()
+            .and_then(|payment_request| payment_request.parse::<lightning_invoice::Invoice>().ok())
+            .map(|invoice| invoice.payment_hash().to_string()),
+    }
+}
+
+/// Whether a withdrawal is held until an admin approves it, nothing is held without a threshold.
+fn needs_approval(threshold_sats: Option<u64>, amount_sats: u64) -> bool {
+    threshold_sats.map_or(false, |threshold_sats| amount_sats > threshold_sats)
+}
+
+/// Approved withdrawals go back through the loopback to be paid, the user is told about rejected ones. The key
+/// of an approved one is cleared, its held response is stored under it and would be replayed instead.
+fn reviewed_withdrawal_message(status: &str, mut request: PaymentRequest) -> (Message, ServiceIdentity) {
+    if status == pending_withdrawals::APPROVED {
+        request.idempotency_key = None;
+        return (Message::Api(Api::PaymentRequest(request)), ServiceIdentity::Loopback);
+    }
+    let payment_response = PaymentResponse::error(
+        PaymentResponseError::WithdrawalRejected,
+        request.req_id,
+        request.uid,
+        request.payment_request,
+        request.currency,
+        None,
+    );
+    (
+        Message::Api(Api::PaymentResponse(payment_response)),
+        ServiceIdentity::Api,
+    )
+}
+
+/// Amount a payment from a vault withdraws. BTC invoices carry it, for fiat ones it isn't known until quoted.
+fn vault_withdrawal_amount(request: &PaymentRequest, vault_currency: Currency) -> Option<Money> {
+    match &request.amount {
+        Some(amount) => Some(amount.clone()),
+        None if vault_currency == Currency::BTC => request
+            .payment_request
+            .as_ref()
+            .and_then(|payment_request| payment_request.parse::<lightning_invoice::Invoice>().ok())
+            .and_then(|invoice| invoice.amount_milli_satoshis())
+            .map(|msats| Money::from_msats(Decimal::new(msats as i64, 0))),
+        None => None,
+    }
+}
+
+/// Whether a vault withdrawal is paid now, cancelled ones and ones that were released already never are.
+fn is_releasable(withdrawal: &vault_withdrawals::VaultWithdrawal, now: i64) -> bool {
+    withdrawal.status == vault_withdrawals::PENDING && withdrawal.release_at <= now
+}
+
+/// Whether the dealer quotes a transfer between the currencies, it only quotes available currencies against BTC.
+fn is_quoted_transfer(from: Currency, to: Currency, available_currencies: &[Currency]) -> bool {
+    let fiat = if from == Currency::BTC { to } else { from };
+    (from == Currency::BTC || to == Currency::BTC) && available_currencies.contains(&fiat)
+}
+
+/// Part of the fee reserve of a payment it didn't use.
+fn unused_fee_reserve(reserved: Decimal, amount: Decimal, fees: Decimal) -> Decimal {
+    reserved - (amount + fees)
+}
+
+/// Retries that were marked as in flight but whose attempt was never persisted, the bank stopped before it was
+/// dispatched. Attempts that were persisted are recovered like any other pending payment.
+fn interrupted_retries(retries: Vec<PaymentRetry>, pending_payments: &[PendingPayment]) -> Vec<PaymentRetry> {
+    let in_flight = pending_payments
+        .iter()
+        .map(|pending_payment| pending_payment.req_id.as_str())
+        .collect::<HashSet<_>>();
+    retries
+        .into_iter()
+        .filter(|retry| !in_flight.contains(retry.req_id.as_str()))
+        .collect()
+}
+
+/// Waits until lnd knows the final state of a payment, failed lookups are retried. Payments lnd never
+/// dispatched count as failed.
+async fn await_payment_outcome(
+    lnd_connector_pool: &LndConnectorPool,
+    payment_hash: &str,
+    req_id: RequestId,
+    logger: &slog::Logger,
+) -> Result<PayResponse, LndConnectorError> {
+    loop {
+        let lookup = match lnd_connector_pool.get().await {
+            Ok(mut lnd_connector) => lnd_connector.lookup_payment(payment_hash).await,
+            Err(err) => Err(err),
+        };
+        match lookup {
+            Ok(PaymentLookup::Succeeded(response)) => return Ok(response),
+            Ok(PaymentLookup::Failed) | Ok(PaymentLookup::NotFound) => {
+                return Err(LndConnectorError::FailedToSendPayment)
+            }
+            Err(err) => {
+                slog::error!(logger, "Failed to look up payment {}, retrying: {}", req_id, err);
+                tokio::time::sleep(Duration::from_secs(PAYMENT_LOOKUP_RETRY_SECS)).await;
+            }
+        }
+    }
+}
+
+/// Result of a pending payment once lnd paid it or gave up on it.
+fn payment_result(pending: PaymentResult, payment: Result<PayResponse, LndConnectorError>) -> PaymentResult {
+    let PaymentResult {
+        uid,
+        currency,
+        rate,
+        amount,
+        payment_response,
+        max_fee_in_sats,
+        attempts,
+        service_fee,
+        keysend,
+        ..
+    } = pending;
+    let aib = payment_response.amount.unwrap_or_else(|| Money::from_sats(dec!(0)));
+    let (payment_response, error, is_retryable) = match payment {
+        Ok(result) => (
+            PaymentResponse {
+                uid,
+                req_id: payment_response.req_id,
+                currency,
+                payment_hash: result.payment_hash,
+                success: true,
+                payment_request: payment_response.payment_request,
+                amount: Some(aib),
+                fees: Some(Money::from_msats(Decimal::new(result.fee_msat as i64, 0))),
+                rate: Some(rate.clone()),
+                error: None,
+                preimage: result.preimage,
+            },
+            None,
+            false,
+        ),
+        Err(e) => (
+            PaymentResponse {
+                uid,
+                req_id: payment_response.req_id,
+                currency,
+                payment_hash: String::from(""),
+                success: false,
+                payment_request: payment_response.payment_request,
+                amount: Some(aib),
+                fees: Some(Money::from_sats(dec!(0))),
+                rate: Some(rate.clone()),
+                error: Some(PaymentResponseError::InsufficientFundsForFees),
+                preimage: None,
+            },
+            Some(e.to_string()),
+            e.is_retryable(),
+        ),
+    };
+    PaymentResult {
+        uid,
+        currency,
+        rate,
+        is_success: payment_response.success,
+        amount,
+        payment_response,
+        error,
+        max_fee_in_sats,
+        attempts: attempts + 1,
+        // Retries are scheduled by payment request, keysend payments are refunded instead.
+        is_retryable: is_retryable && keysend.is_none(),
+        service_fee,
+        keysend,
+    }
 }
 
 impl BankEngine {
@@ -126,17 +753,41 @@ impl BankEngine {
         lnd_connector: LndConnector,
         mut settings: BankEngineSettings,
         lnd_connector_settings: LndConnectorSettings,
+        lnd_connector_pool: LndConnectorPool,
         payment_thread_sender: crossbeam_channel::Sender<Message>,
     ) -> Self {
         settings.logging_settings.name = String::from("Bank");
         let logger = init_log(&settings.logging_settings);
 
+        let ledger_journal = settings.ledger_journal_path.as_ref().map(|path| {
+            LedgerJournal::open(path).unwrap_or_else(|err| panic!("Failed to open ledger journal {}: {}", path, err))
+        });
+
+        // Scheduled exports continue where the last journal written before a restart ended.
+        let last_export_timestamp = settings
+            .export_settings
+            .as_ref()
+            .and_then(|export_settings| last_export_end(&export_settings.output_dir))
+            .unwrap_or_else(utils::time::time_now);
+
+        let db_writer = conn_pool.clone().map(|pool| {
+            DbWriter::start(
+                pool,
+                settings.db_writer_threads.unwrap_or(DEFAULT_DB_WRITER_THREADS),
+                logger.clone(),
+            )
+        });
+
         Self {
             lnd_node_info: LndNodeInfo::default(),
             bank_uid: BANK_UID,
             ledger: Ledger::new(BANK_UID, DEALER_UID),
-            fee_structure: FeeStructure::new(),
+            fee_engine: FeeEngine::new(settings.fee_schedule.clone()),
             conn_pool,
+            db_writer,
+            persisted_accounts: HashSet::new(),
+            account_write_coalescing_millis: settings.account_write_coalescing_millis,
+            last_account_flush_timestamp: 0,
             lnd_connector,
             available_currencies: vec![Currency::BTC],
             internal_tx_fee: settings.internal_tx_fee,
@@ -144,6 +795,7 @@ impl BankEngine {
             ln_network_fee_margin: settings.ln_network_fee_margin,
             reserve_ratio: settings.reserve_ratio,
             ln_network_max_fee: settings.ln_network_max_fee,
+            ln_fee_budgets: settings.ln_fee_budgets.clone(),
             withdrawal_only: settings.withdrawal_only,
             deposit_limits: settings
                 .deposit_limits
@@ -157,54 +809,222 @@ impl BankEngine {
                 })
                 .collect::<HashMap<Currency, Decimal>>(),
             logger,
-            tx_seq: 0,
             lnurl_withdrawal_requests: HashMap::new(),
+            lnurl_settings: settings.lnurl.clone(),
+            lnurl_channel_settings: settings.lnurl_channel.clone(),
+            lnurl_channel_requests: HashMap::new(),
             payment_threads: FuturesUnordered::new(),
-            withdrawal_request_rate_limiter_settings: settings.withdrawal_request_rate_limiter_settings,
-            deposit_request_rate_limiter_settings: settings.deposit_request_rate_limiter_settings,
-            withdrawal_request_rate_limiter: HashMap::new(),
-            deposit_request_rate_limiter: HashMap::new(),
+            withdrawal_request_rate_limiter_settings: settings.withdrawal_request_rate_limiter_settings.clone(),
+            deposit_request_rate_limiter_settings: settings.deposit_request_rate_limiter_settings.clone(),
+            withdrawal_request_rate_limiter: TokenBucketLimiter::new(
+                "withdrawal",
+                settings.withdrawal_request_rate_limiter_settings.clone(),
+            ),
+            deposit_request_rate_limiter: TokenBucketLimiter::new(
+                "deposit",
+                settings.deposit_request_rate_limiter_settings.clone(),
+            ),
             payment_thread_sender,
             lnd_connector_settings,
+            lnd_connector_pool,
+            export_settings: settings.export_settings,
+            payment_retry_settings: settings.payment_retry_settings,
+            maintenance_notices: settings.maintenance_notices,
+            strict_fee_quotes: settings.strict_fee_quotes,
+            payment_starts: HashMap::new(),
+            withdrawal_durations: VecDeque::with_capacity(WITHDRAWAL_DURATIONS_SIZE),
+            last_export_timestamp,
+            idempotent_requests: HashMap::new(),
+            ledger_journal,
+            reconciliation_settings: settings.reconciliation_settings,
+            last_db_reconciliation_timestamp: utils::time::time_now(),
+            account_drifts: Vec::new(),
+            withdrawals_halted: false,
+            db_writes_failing: false,
+            metrics: BankMetrics::new(),
+            replayed_messages: VecDeque::new(),
+            access_policy: settings.access_policy,
+            last_reserves_report: None,
+            dealer_availability: HashMap::new(),
+            last_dealer_health_timestamp: utils::time::time_now(),
+            pending_cash_outs: HashMap::new(),
+            deposit_targets: HashMap::new(),
+            retried_deposits: Vec::new(),
+            pending_transfers: HashMap::new(),
+            inbound_capacity_headroom: settings.inbound_capacity_headroom,
+            max_receivable_sats: None,
+            last_inbound_capacity_timestamp: 0,
+            outbound_capacity_headroom: settings.outbound_capacity_headroom,
+            max_payable_sats: None,
+            last_outbound_capacity_timestamp: 0,
+            content_filter: ContentFilter::new(&settings.content_filter),
+            next_revenue_day: None,
+            dust_sweep_settings: settings.dust_sweep_settings,
+            last_dust_sweep_timestamp: 0,
+            closed_until: None,
+            description_retention_days: settings.description_retention_days,
+            last_description_hashing_timestamp: 0,
+            referral_share: settings.referral_share,
+            interest_settings: settings.interest.clone(),
+            next_interest_day: None,
+            settlement_batching: settings.settlement_batching.clone(),
+            settlement_queue: SettlementQueue::default(),
+            velocity_limits: settings.withdrawal_velocity,
+            withdrawals_in_flight: HashMap::new(),
+            flow_statistics: settings.flow_statistics.clone(),
+            next_flow_statistics_day: None,
+            withdrawal_approval_threshold_sats: settings.withdrawal_approval_threshold_sats,
+            rebalance_daily_fee_budget_sats: settings.rebalance_daily_fee_budget_sats,
+            approved_withdrawals: HashSet::new(),
+            totp_settings: settings.totp.clone(),
+            totp_verified: HashSet::new(),
+            frozen_accounts: HashMap::new(),
+            quote_signing_key: settings.quote_signing_key.clone(),
+            firm_quotes: HashMap::new(),
+            swap_orders: HashMap::new(),
+            audit_context: AuditContext::default(),
+            probe_cache: ProbeCache::default(),
+            liquidity_swap_settings: settings.liquidity_swaps.clone(),
+            swap_provider: settings.liquidity_swaps.as_ref().map(swap_provider),
+            last_liquidity_swap_check: 0,
+            taproot_assets: settings.taproot_assets.as_ref().map(|settings| {
+                TaprootAssetsConnector::new(settings)
+                    .unwrap_or_else(|err| panic!("Failed to set up the tapd connection: {}", err))
+            }),
+            ecash_gateway: settings.ecash.clone().map(EcashGateway::new),
+            liquid: settings.liquid.clone().map(ElementsClient::new),
+            liquid_last_block: None,
+            last_liquid_deposit_check: 0,
+            last_market_prices: None,
+            hedge_rebalance_settings: settings.hedge_rebalance_settings,
+            last_hedge_rebalance_timestamp: 0,
+            vault_settings: settings.vault_settings,
+            released_vault_withdrawals: HashSet::new(),
+        }
+    }
+
+    /// Checks the origin of a money-moving request against the global policy and the policy of the user.
+    /// Fails closed if the policy of the user can't be loaded.
+    fn check_access_policy(&self, uid: UserId, origin: &Option<RequestOrigin>) -> bool {
+        let origin = origin.clone().unwrap_or_default();
+
+        if !self.access_policy.is_empty() && !self.access_policy.allows(&origin) {
+            return false;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return true,
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return false;
+            }
+        };
+
+        match access_policies::AccessPolicy::get_by_uid(&c, uid as i32) {
+            Ok(user_policy) => {
+                let policy = AccessPolicy {
+                    allowed_ip_ranges: user_policy.ip_ranges(),
+                    allowed_countries: user_policy.countries(),
+                };
+                policy.allows(&origin)
+            }
+            Err(DieselError::NotFound) => true,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load access policy of user {}: {:?}", uid, err);
+                false
+            }
         }
     }
 
     fn check_deposit_request_rate_limit(&mut self, user_id: UserId) -> bool {
-        let (counter, last_request) = self
+        let conn = self.conn_pool.as_ref().and_then(|pool| pool.get().ok());
+        match self
             .deposit_request_rate_limiter
-            .entry(user_id)
-            .or_insert_with(|| (0, Instant::now()));
-        if (last_request.elapsed().as_millis() as u64)
-            < self.deposit_request_rate_limiter_settings.replenishment_interval
+            .take(conn.as_deref(), user_id, utils::time::time_now())
         {
-            *counter += 1;
-            if *counter > self.deposit_request_rate_limiter_settings.request_limit {
-                return false;
+            Ok(allowed) => allowed,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to take a deposit request token: {:?}", err);
+                self.deposit_request_rate_limiter
+                    .take_in_memory(user_id, utils::time::time_now())
             }
-        } else {
-            *counter = 0;
-            *last_request = Instant::now();
         }
-        true
     }
 
     fn check_withdrawal_request_rate_limit(&mut self, user_id: UserId) -> bool {
-        let (counter, last_request) = self
+        let conn = self.conn_pool.as_ref().and_then(|pool| pool.get().ok());
+        match self
             .withdrawal_request_rate_limiter
-            .entry(user_id)
-            .or_insert_with(|| (0, Instant::now()));
-        if (last_request.elapsed().as_millis() as u64)
-            < self.withdrawal_request_rate_limiter_settings.replenishment_interval
+            .take(conn.as_deref(), user_id, utils::time::time_now())
         {
-            *counter += 1;
-            if *counter > self.withdrawal_request_rate_limiter_settings.request_limit {
-                return false;
+            Ok(allowed) => allowed,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to take a withdrawal request token: {:?}", err);
+                self.withdrawal_request_rate_limiter
+                    .take_in_memory(user_id, utils::time::time_now())
             }
-        } else {
-            *counter = 0;
-            *last_request = Instant::now();
         }
-        true
+    }
+
+    /// Drops the rate limit buckets of users that went quiet, they are full again by now.
+    pub fn prune_rate_limit_buckets(&mut self) {
+        let conn = self.conn_pool.as_ref().and_then(|pool| pool.get().ok());
+        let now = utils::time::time_now();
+        for limiter in [
+            &mut self.deposit_request_rate_limiter,
+            &mut self.withdrawal_request_rate_limiter,
+        ] {
+            if let Err(err) = limiter.prune(conn.as_deref(), now) {
+                slog::error!(self.logger, "Failed to prune rate limit buckets: {:?}", err);
+            }
+        }
+    }
+
+    /// Checks the amount against the sats the user withdrew over the last day and week, including payments
+    /// that are still in flight.
+    fn check_withdrawal_velocity(
+        &self,
+        conn: &diesel::PgConnection,
+        uid: UserId,
+        payment_request: &str,
+        amount_in_sats: u64,
+    ) -> Result<bool, DieselError> {
+        let user_override = WithdrawalLimit::get_by_uid(conn, uid as i32)?;
+        let limits = self.velocity_limits.for_user(user_override.as_ref());
+        if limits.is_unlimited() {
+            return Ok(true);
+        }
+
+        let now = utils::time::time_now();
+        let in_flight = self
+            .withdrawals_in_flight
+            .values()
+            .filter(|(in_flight_uid, _)| *in_flight_uid == uid)
+            .map(|(_, sats)| *sats)
+            .sum::<u64>();
+        let withdrawn_last_day = Invoice::get_withdrawn_since(
+            conn,
+            uid as i32,
+            now.saturating_sub(MILLIS_IN_DAY) as i64,
+            payment_request,
+        )?;
+        let withdrawn_last_week = Invoice::get_withdrawn_since(
+            conn,
+            uid as i32,
+            now.saturating_sub(MILLIS_IN_WEEK) as i64,
+            payment_request,
+        )?;
+
+        Ok(limits.allows(
+            withdrawn_last_day.max(0) as u64 + in_flight,
+            withdrawn_last_week.max(0) as u64 + in_flight,
+            amount_in_sats,
+        ))
     }
 
     fn fetch_accounts<F: FnMut(&diesel::PgConnection) -> Result<Vec<accounts::Account>, DieselError>>(
@@ -264,6 +1084,9 @@ impl BankEngine {
                 currency,
                 account_type,
                 account_class,
+                pending_balance: dec!(0),
+                label: a.label.clone(),
+                archived: a.archived,
             };
             parsed_accounts.push(new_account);
         });
@@ -321,6 +1144,30 @@ impl BankEngine {
         dbg!(&dealer_accounts);
         self.ledger.dealer_accounts.accounts = dealer_accounts;
 
+        self.ledger.fee_account.accounts = self
+            .fetch_accounts(&c, &mut accounts::Account::get_bank_fee_accounts)
+            .into_iter()
+            .map(|account| (account.account_id, account))
+            .collect();
+
+        self.ledger.funding_account.accounts = self
+            .fetch_accounts(&c, &mut accounts::Account::get_dealer_funding_accounts)
+            .into_iter()
+            .map(|account| (account.account_id, account))
+            .collect();
+
+        self.ledger.pnl_account.accounts = self
+            .fetch_accounts(&c, &mut accounts::Account::get_dealer_pnl_accounts)
+            .into_iter()
+            .map(|account| (account.account_id, account))
+            .collect();
+
+        self.ledger.expense_account.accounts = self
+            .fetch_accounts(&c, &mut accounts::Account::get_bank_expense_accounts)
+            .into_iter()
+            .map(|account| (account.account_id, account))
+            .collect();
+
         let accounts = match accounts::Account::get_non_internal_users_accounts(&c) {
             Ok(accs) => accs,
             Err(_) => return,
@@ -377,9 +1224,65 @@ impl BankEngine {
                 account_id,
                 account_type,
                 account_class,
+                pending_balance: dec!(0),
+                label: account.label.clone(),
+                archived: account.archived,
             };
 
             user_account.accounts.insert(account.account_id, acc);
+            self.ledger.index_account(account.uid as u64, account.account_id);
+        }
+    }
+
+    /// Loads the frozen users and accounts and the per-user deposit limits set by admins.
+    pub fn init_user_controls(&mut self) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let frozen_uids = User::get_frozen_uids(&c).expect("Failed to load frozen users");
+        let deposit_limits = DepositLimit::get_all(&c).expect("Failed to load deposit limits");
+        let frozen_accounts = FrozenAccount::get_all(&c).expect("Failed to load frozen accounts");
+
+        for uid in frozen_uids {
+            let uid = uid as UserId;
+            self.ledger
+                .user_accounts
+                .entry(uid)
+                .or_insert_with(|| UserAccount::new(uid))
+                .frozen = true;
+        }
+
+        for deposit_limit in deposit_limits {
+            let uid = deposit_limit.uid as UserId;
+            let currency = Currency::from_str(&deposit_limit.currency)
+                .unwrap_or_else(|_| panic!("Failed to convert {} into a valid currency", deposit_limit.currency));
+            let max_balance = Decimal::from_str(&deposit_limit.max_balance.to_string())
+                .unwrap_or_else(|_| panic!("Failed to convert {} into a valid limit", deposit_limit.max_balance));
+            self.ledger
+                .user_accounts
+                .entry(uid)
+                .or_insert_with(|| UserAccount::new(uid))
+                .deposit_limits
+                .insert(currency, max_balance);
+        }
+
+        for frozen_account in frozen_accounts {
+            let reason = FreezeReason::from_str(&frozen_account.reason_code)
+                .unwrap_or_else(|_| panic!("Failed to convert {} into a freeze reason", frozen_account.reason_code));
+            self.frozen_accounts.insert(frozen_account.account_id, reason);
         }
     }
 
@@ -397,12 +1300,14 @@ impl BankEngine {
             total_exposures,
             insurance_fund_account: self.ledger.insurance_fund_account.clone(),
             fiat_exposures: self.ledger.dealer_accounts.accounts.clone(),
+            reserves: self.last_reserves_report.clone(),
         }
     }
 
     fn insert_into_ledger(&mut self, uid: &UserId, account_id: AccountId, account: Account) {
         if let Some(user_account) = self.ledger.user_accounts.get_mut(uid) {
             user_account.accounts.insert(account_id, account);
+            self.ledger.index_account(*uid, account_id);
         } else {
             panic!(
                 "Failed to find user account, uid: {} while inserting account state: account_id: {}, account: {:?}",
@@ -411,19 +1316,133 @@ impl BankEngine {
         }
     }
 
-    pub fn update_account(&mut self, account: &Account, uid: UserId) {
-        let conn = match &self.conn_pool {
-            Some(conn) => conn,
-            None => {
-                slog::error!(self.logger, "No database provided.");
-                return;
+    fn journal_event(&self, account: &Account, uid: UserId) -> LedgerEvent {
+        let account = account.clone();
+        if uid == BANK_UID && account.account_class == AccountClass::Fees {
+            LedgerEvent::FeeAccountUpdated { account }
+        } else if uid == BANK_UID && account.account_class == AccountClass::Expense {
+            LedgerEvent::ExpenseAccountUpdated { account }
+        } else if uid == BANK_UID {
+            LedgerEvent::BankLiabilityUpdated { account }
+        } else if uid == DEALER_UID && account.account_class == AccountClass::Fees {
+            LedgerEvent::FundingAccountUpdated { account }
+        } else if uid == DEALER_UID && account.account_class == AccountClass::Pnl {
+            LedgerEvent::PnlAccountUpdated { account }
+        } else if uid == DEALER_UID && account.account_id == self.ledger.insurance_fund_account.account_id {
+            LedgerEvent::InsuranceFundUpdated { account }
+        } else if uid == DEALER_UID {
+            LedgerEvent::DealerAccountUpdated { account }
+        } else {
+            LedgerEvent::UserAccountUpdated { uid, account }
+        }
+    }
+
+    /// Writes a snapshot of the ledger into an empty journal. Otherwise the journal is replayed and
+    /// accounts whose persisted state lags behind the journal, e.g. after a crash in between journaling
+    /// and persisting a mutation, are restored from it.
+    pub fn init_ledger_journal(&mut self) {
+        let journal = match self.ledger_journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+
+        if journal.is_empty() {
+            for event in self.ledger.snapshot() {
+                if let Err(err) = journal.append(event) {
+                    panic!("Failed to write ledger snapshot to journal: {}", err);
+                }
             }
+            slog::info!(self.logger, "Wrote ledger snapshot to journal {}", journal.path);
+            return;
+        }
+
+        let entries = match read_journal(&journal.path) {
+            Ok(entries) => entries,
+            Err(err) => panic!("Failed to read ledger journal {}: {}", journal.path, err),
         };
 
-        let c = match conn.get() {
-            Ok(psql_connection) => psql_connection,
-            Err(_) => {
-                slog::error!(self.logger, "Couldn't get psql connection.");
+        let replayed = Ledger::replay(BANK_UID, DEALER_UID, &entries);
+        slog::info!(
+            self.logger,
+            "Replayed {} ledger journal entries from {}",
+            entries.len(),
+            journal.path
+        );
+
+        for event in replayed.snapshot() {
+            let (uid, account) = match &event {
+                LedgerEvent::UserAccountUpdated { uid, account } => (*uid, account),
+                LedgerEvent::BankLiabilityUpdated { account }
+                | LedgerEvent::FeeAccountUpdated { account }
+                | LedgerEvent::ExpenseAccountUpdated { account } => (BANK_UID, account),
+                LedgerEvent::DealerAccountUpdated { account }
+                | LedgerEvent::InsuranceFundUpdated { account }
+                | LedgerEvent::FundingAccountUpdated { account }
+                | LedgerEvent::PnlAccountUpdated { account } => (DEALER_UID, account),
+            };
+
+            let persisted = match &event {
+                LedgerEvent::UserAccountUpdated { uid, account } => self
+                    .ledger
+                    .user_accounts
+                    .get(uid)
+                    .and_then(|user_account| user_account.accounts.get(&account.account_id)),
+                LedgerEvent::BankLiabilityUpdated { account } => {
+                    self.ledger.bank_liabilities.accounts.get(&account.account_id)
+                }
+                LedgerEvent::DealerAccountUpdated { account } => {
+                    self.ledger.dealer_accounts.accounts.get(&account.account_id)
+                }
+                LedgerEvent::FeeAccountUpdated { account } => self.ledger.fee_account.accounts.get(&account.account_id),
+                LedgerEvent::FundingAccountUpdated { account } => {
+                    self.ledger.funding_account.accounts.get(&account.account_id)
+                }
+                LedgerEvent::PnlAccountUpdated { account } => self.ledger.pnl_account.accounts.get(&account.account_id),
+                LedgerEvent::ExpenseAccountUpdated { account } => {
+                    self.ledger.expense_account.accounts.get(&account.account_id)
+                }
+                LedgerEvent::InsuranceFundUpdated { .. } => continue,
+            };
+
+            if persisted.map(|persisted| persisted.balance) != Some(account.balance) {
+                slog::warn!(
+                    self.logger,
+                    "Restoring account {} of user {} from ledger journal. Persisted: {:?}, journaled: {}",
+                    account.account_id,
+                    uid,
+                    persisted.map(|persisted| persisted.balance),
+                    account.balance
+                );
+                let account = account.clone();
+                self.ledger.apply(&event);
+                self.persist_account(&account, uid);
+            }
+        }
+    }
+
+    pub fn update_account(&mut self, account: &Account, uid: UserId) {
+        if self.ledger.user_accounts.contains_key(&uid) {
+            self.ledger.index_account(uid, account.account_id);
+        }
+        let event = self.journal_event(account, uid);
+        if let Some(journal) = self.ledger_journal.as_mut() {
+            // Without a journal entry the mutation could not be recovered, so it must not be persisted either.
+            if let Err(err) = journal.append(event) {
+                slog::error!(self.logger, "Failed to append to ledger journal: {}", err);
+                panic!("Failed to append to ledger journal: {}", err);
+            }
+        }
+        self.persist_account(account, uid);
+    }
+
+    /// Balances are written by the db writer, the ledger journal restores them if the bank stops before.
+    /// The first write of an account since the start is made right away as it may insert the account, which
+    /// other tables reference.
+    fn persist_account(&mut self, account: &Account, uid: UserId) {
+        let conn = match (&self.conn_pool, &self.db_writer) {
+            (Some(conn), Some(_)) => conn,
+            _ => {
+                slog::error!(self.logger, "No database provided.");
                 return;
             }
         };
@@ -444,44 +1463,135 @@ impl BankEngine {
             account_type: None,
             account_class: None,
             uid: None,
+            label: account.label.clone(),
+            archived: Some(account.archived),
         };
-        if let Ok(res) = update_account.update(&c, account.account_id) {
-            if res == 0 {
-                let insertable_account = accounts::InsertableAccount {
-                    account_id: account.account_id,
-                    balance: Some(big_decimal),
-                    currency: account.currency.to_string(),
-                    uid: uid as i32,
-                    account_type: account.account_type.to_string(),
-                    account_class: account.account_class.to_string(),
-                };
-                if insertable_account.insert(&c).is_err() {
-                    dbg!("Error inserting!");
+        let insertable_account = accounts::InsertableAccount {
+            account_id: account.account_id,
+            balance: Some(big_decimal),
+            currency: account.currency.to_string(),
+            uid: uid as i32,
+            account_type: account.account_type.to_string(),
+            account_class: account.account_class.to_string(),
+            label: account.label.clone(),
+        };
+        let account_id = account.account_id;
+        let write = move |c: &diesel::PgConnection| -> Result<(), DieselError> {
+            if update_account.update(c, account_id)? == 0 {
+                insertable_account.insert(c)?;
+            }
+            Ok(())
+        };
+
+        if self.persisted_accounts.contains(&account_id) {
+            let coalesce = self.account_write_coalescing_millis.is_some() && self.ledger_journal.is_some();
+            if let Some(db_writer) = self.db_writer.as_mut() {
+                if coalesce {
+                    db_writer.coalesce(account_id.as_u128(), "account", Box::new(write));
+                } else {
+                    db_writer.submit(account_id.as_u128(), "account", Box::new(write));
                 }
             }
-        }
-    }
-    /// Double entry transaction logic.
-    pub fn make_summary_tx(
-        &self,
-        outbound_account: &Account,
-        outbound_uid: u64,
-        inbound_account: &Account,
-        inbound_uid: u64,
-        amount: Money,
-        rate: Option<Rate>,
-        fees: Option<Money>,
-        outbound_txid: Option<String>,
-        inbound_txid: Option<String>,
-        fee_txid: Option<String>,
-        reference: Option<String>,
-    ) -> Result<String, BankError> {
-        if amount.value <= dec!(0) {
-            return Err(BankError::FailedTransaction);
+            return;
         }
 
-        let conn = match &self.conn_pool {
-            Some(conn) => conn,
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+        match write(&c) {
+            Ok(()) => {
+                self.persisted_accounts.insert(account_id);
+            }
+            Err(err) => slog::error!(self.logger, "Failed to persist account {}: {:?}", account_id, err),
+        }
+    }
+
+    /// Queues the balance writes coalesced in the last window.
+    pub fn flush_account_writes(&mut self) {
+        let window = match self.account_write_coalescing_millis {
+            Some(window) => window,
+            None => return,
+        };
+        let now = utils::time::time_now();
+        if now < self.last_account_flush_timestamp + window {
+            return;
+        }
+        self.last_account_flush_timestamp = now;
+        if let Some(db_writer) = self.db_writer.as_mut() {
+            db_writer.flush();
+        }
+    }
+
+    /// Halts withdrawals while the db writer can't write, the balances in the database lag behind the ledger.
+    /// Once the writes went through the ledger is reconciled with the database right away.
+    pub fn check_db_writes(&mut self) {
+        let failing = self
+            .db_writer
+            .as_ref()
+            .map_or(false, |db_writer| db_writer.is_failing());
+        if failing == self.db_writes_failing {
+            return;
+        }
+        self.db_writes_failing = failing;
+        if failing {
+            slog::error!(self.logger, "Database writes are failing. Halting withdrawals.");
+            self.withdrawals_halted = true;
+        } else if self.reconciliation_settings.is_some() {
+            slog::info!(
+                self.logger,
+                "Database writes recovered. Reconciling before resuming withdrawals."
+            );
+            self.last_db_reconciliation_timestamp = 0;
+        } else {
+            slog::info!(self.logger, "Database writes recovered. Resuming withdrawals.");
+            self.withdrawals_halted = false;
+        }
+    }
+
+    /// Rejects transactions booked into a closed period, e.g. after the clock of the host went back.
+    /// The database rejects them as well, this keeps the in-memory ledger from being changed first.
+    fn ensure_period_open(&self, created_at: u64) -> Result<(), BankError> {
+        match self.closed_until {
+            Some(closed_until) if created_at <= closed_until => {
+                slog::error!(
+                    self.logger,
+                    "Rejected transaction at {} in period closed until {}",
+                    created_at,
+                    closed_until
+                );
+                Err(BankError::PeriodClosed)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Double entry transaction logic.
+    pub fn make_summary_tx(
+        &self,
+        outbound_account: &Account,
+        outbound_uid: u64,
+        inbound_account: &Account,
+        inbound_uid: u64,
+        amount: Money,
+        rate: Option<Rate>,
+        fees: Option<Money>,
+        outbound_txid: Option<String>,
+        inbound_txid: Option<String>,
+        fee_txid: Option<String>,
+        reference: Option<String>,
+    ) -> Result<String, BankError> {
+        if amount.value <= dec!(0) {
+            return Err(BankError::FailedTransaction);
+        }
+
+        self.ensure_period_open(utils::time::time_now())?;
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
             None => {
                 slog::error!(self.logger, "No database provided.");
                 return Err(BankError::FailedTransaction);
@@ -500,6 +1610,7 @@ impl BankEngine {
             base: outbound_account.currency,
             quote: inbound_account.currency,
             value: dec!(1),
+            ..Default::default()
         });
 
         let fees = fees.unwrap_or_else(|| Money::new(inbound_account.currency, None));
@@ -558,7 +1669,7 @@ impl BankEngine {
         };
 
         let t = utils::time::time_now();
-        let txid = format!("{}", t);
+        let txid = utils::txid::new_txid();
 
         let tx = models::summary_transactions::SummaryTransaction {
             txid: txid.clone(),
@@ -580,7 +1691,8 @@ impl BankEngine {
             reference,
         };
 
-        if tx.insert(&c).is_err() {
+        if let Err(err) = tx.insert(&c) {
+            slog::error!(self.logger, "Failed to insert summary transaction {}: {:?}", txid, err);
             return Err(BankError::FailedTransaction);
         }
 
@@ -605,6 +1717,8 @@ impl BankEngine {
             return Err(BankError::FailedTransaction);
         }
 
+        self.ensure_period_open(utils::time::time_now())?;
+
         let conn = match &self.conn_pool {
             Some(conn) => conn,
             None => {
@@ -625,6 +1739,7 @@ impl BankEngine {
             base: outbound_account.currency,
             quote: inbound_account.currency,
             value: Decimal::ONE,
+            ..Default::default()
         };
 
         let fees = Money::new(inbound_account.currency, None);
@@ -632,6 +1747,8 @@ impl BankEngine {
         let outbound_amount = amount.value;
         let inbound_amount = outbound_amount;
 
+        let outbound_balance_before = outbound_account.balance;
+        let inbound_balance_before = inbound_account.balance;
         outbound_account.balance -= outbound_amount;
         inbound_account.balance += inbound_amount;
 
@@ -679,8 +1796,7 @@ impl BankEngine {
         };
 
         let t = utils::time::time_now();
-        self.tx_seq += 1;
-        let txid = format!("{}-{}", t, self.tx_seq);
+        let txid = utils::txid::new_txid();
 
         let tx = models::transactions::Transaction {
             txid: txid.clone(),
@@ -698,16 +1814,65 @@ impl BankEngine {
             fees: fee_bigdec,
         };
 
-        if tx.insert(&c).is_err() {
+        let audit_entry = |uid: u64,
+                           account: &Account,
+                           amount: Decimal,
+                           balance_before: Decimal|
+         -> Result<InsertableAuditLog, bigdecimal::ParseBigDecimalError> {
+            let to_bigdec = |value: Decimal| BigDecimal::from_str(&value.to_string());
+            Ok(InsertableAuditLog {
+                txid: txid.clone(),
+                actor: self.audit_context.actor.to_string(),
+                actor_id: self.audit_context.actor.actor_id(),
+                operation: self.audit_context.operation.to_string(),
+                req_id: self.audit_context.req_id.map(|req_id| req_id.to_string()),
+                uid: uid as i32,
+                account_id: account.account_id,
+                currency: account.currency.to_string(),
+                amount: to_bigdec(amount)?,
+                balance_before: to_bigdec(balance_before)?,
+                balance_after: to_bigdec(account.balance)?,
+                created_at: t as i64,
+            })
+        };
+        let audit_entries: Result<Vec<_>, _> = vec![
+            audit_entry(
+                outbound_uid,
+                outbound_account,
+                -outbound_amount,
+                outbound_balance_before,
+            ),
+            audit_entry(inbound_uid, inbound_account, inbound_amount, inbound_balance_before),
+        ]
+        .into_iter()
+        .collect();
+        let audit_entries = match audit_entries {
+            Ok(entries) => entries,
+            Err(_) => {
+                dbg!("couldn't parse big decimal");
+                return Err(BankError::FailedTransaction);
+            }
+        };
+
+        let inserted = c.transaction::<_, DieselError, _>(|| {
+            tx.insert(&c)?;
+            InsertableAuditLog::insert_all(&c, &audit_entries)?;
+            Ok(())
+        });
+        if let Err(err) = inserted {
+            slog::error!(self.logger, "Failed to insert transaction {}: {:?}", txid, err);
             return Err(BankError::FailedTransaction);
         }
 
         Ok(txid)
     }
 
+    /// Transfers to another user. `transfer_rate` is the dealer's quote for a transfer to an account of another
+    /// currency, it is requested here and never taken from the payment request.
     pub fn make_internal_tx<F: FnMut(Message, ServiceIdentity)>(
         &mut self,
         payment_request: PaymentRequest,
+        transfer_rate: Option<Rate>,
         listener: &mut F,
     ) {
         let conn = match &self.conn_pool {
@@ -726,19 +1891,15 @@ impl BankEngine {
             }
         };
 
-        let username = payment_request
-            .receipient
-            .as_ref()
-            .unwrap_or_else(|| panic!("Recipient's username not specified: {:?}", payment_request))
-            .clone();
         let outbound_uid = payment_request.uid;
-        let amount = payment_request.amount.unwrap();
+        let amount = payment_request.amount.clone().unwrap();
         let rate = dec!(1);
 
         let rate = Rate {
             base: payment_request.currency,
             quote: payment_request.currency,
             value: rate,
+            ..Default::default()
         };
 
         let fees = Money::new(payment_request.currency, Some(dec!(0)));
@@ -753,42 +1914,83 @@ impl BankEngine {
             currency: payment_request.currency,
             fees: Some(fees.clone()),
             error: None,
-            rate: Some(rate.clone()),
+            rate: Some(payment_request.rate.clone().unwrap_or(rate)),
             preimage: None,
         };
 
-        let inbound_user = match User::get_by_username(&c, username) {
-            Ok(u) => u,
-            Err(_) => {
-                payment_response.error = Some(PaymentResponseError::UserDoesNotExist);
-                let msg = Message::Api(Api::PaymentResponse(payment_response));
-                listener(msg, ServiceIdentity::Api);
-                return;
+        let recipient_uid = match payment_request.receipient.clone() {
+            Some(username) => match User::get_by_username(&c, username) {
+                Ok(u) => Some(u.uid as u64),
+                Err(_) => {
+                    payment_response.error = Some(PaymentResponseError::UserDoesNotExist);
+                    let msg = Message::Api(Api::PaymentResponse(payment_response));
+                    listener(msg, ServiceIdentity::Api);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        // A target account takes precedence over the recipient's default account, if a username
+        // was given as well it has to be the owner of the account.
+        let (inbound_uid, target_account) = match payment_request.target_account_id {
+            Some(account_id) => {
+                let owner = self.account_owner(account_id).filter(|(_, account)| !account.archived);
+                match owner {
+                    Some((owner_uid, account)) if recipient_uid.map_or(true, |uid| uid == owner_uid) => {
+                        (owner_uid, Some(account))
+                    }
+                    _ => {
+                        payment_response.error = Some(PaymentResponseError::AccountDoesNotExist);
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                }
             }
+            None => match recipient_uid {
+                Some(uid) => (uid, None),
+                None => panic!("Recipient not specified: {:?}", payment_request),
+            },
         };
 
-        let inbound_uid = inbound_user.uid as u64;
         if inbound_uid == outbound_uid {
             slog::error!(self.logger, "User tried to send to self via username.");
+            if payment_request.target_account_id.is_some() {
+                payment_response.error = Some(PaymentResponseError::SelfPayment);
+                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                listener(msg, ServiceIdentity::Api);
+            }
             return;
         }
 
-        let mut outbound_account = {
-            let user_account = match self.ledger.user_accounts.get_mut(&outbound_uid) {
-                Some(ua) => ua,
-                None => return,
-            };
-
-            user_account.get_default_account(payment_request.currency, None)
+        let outbound_account = self
+            .ledger
+            .user_accounts
+            .get_mut(&outbound_uid)
+            .and_then(|user_account| {
+                user_account.get_account_or_default(payment_request.account_id, payment_request.currency)
+            });
+        let mut outbound_account = match outbound_account {
+            Some(account) => account,
+            None => {
+                payment_response.error = Some(PaymentResponseError::AccountDoesNotExist);
+                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                listener(msg, ServiceIdentity::Api);
+                return;
+            }
         };
 
-        let mut inbound_account = {
-            let user_account = self
-                .ledger
-                .user_accounts
-                .entry(inbound_uid)
-                .or_insert_with(|| UserAccount::new(inbound_uid));
-            user_account.get_default_account(payment_request.currency, None)
+        let mut inbound_account = match target_account {
+            Some(account) => account,
+            None => {
+                let user_account = self
+                    .ledger
+                    .user_accounts
+                    .entry(inbound_uid)
+                    .or_insert_with(|| UserAccount::new(inbound_uid));
+                user_account.get_default_account(payment_request.currency, None)
+            }
         };
 
         if outbound_account.balance < amount.value {
@@ -798,6 +2000,32 @@ impl BankEngine {
             return;
         }
 
+        if inbound_account.currency != payment_request.currency {
+            self.make_converted_internal_tx(
+                payment_request,
+                transfer_rate,
+                payment_response,
+                outbound_account,
+                inbound_account,
+                inbound_uid,
+                listener,
+            );
+            return;
+        }
+
+        let fee = self.operation_fee(
+            outbound_uid,
+            payment_request.currency,
+            FeeOperation::Internal,
+            amount.value,
+        );
+        if outbound_account.balance < amount.value + fee.value {
+            payment_response.error = Some(PaymentResponseError::InsufficientFundsForFees);
+            let msg = Message::Api(Api::PaymentResponse(payment_response));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        }
+
         let txid = if let Ok(txid) = self.make_tx(
             &mut outbound_account,
             outbound_uid,
@@ -829,6 +2057,21 @@ impl BankEngine {
             return;
         }
 
+        if let Err(err) = self.collect_fee(&mut outbound_account, outbound_uid, FeeOperation::Internal, &fee) {
+            slog::error!(
+                self.logger,
+                "Failed to collect fee of internal transfer {}: {}",
+                payment_request.req_id,
+                err
+            );
+        }
+        self.fee_engine.record_volume(
+            outbound_uid,
+            payment_request.currency,
+            amount.value,
+            utils::time::time_now(),
+        );
+
         self.insert_into_ledger(&inbound_uid, inbound_account.account_id, inbound_account.clone());
         self.insert_into_ledger(&outbound_uid, outbound_account.account_id, outbound_account.clone());
 
@@ -836,2197 +2079,10474 @@ impl BankEngine {
         self.update_account(&outbound_account, outbound_uid);
         self.update_account(&inbound_account, inbound_uid);
 
+        self.share_fee_with_referrer(outbound_uid, &fee);
+
+        payment_response.fees = Some(fee);
         payment_response.success = true;
         let msg = Message::Api(Api::PaymentResponse(payment_response));
         listener(msg, ServiceIdentity::Api);
     }
 
-    pub async fn process_msg<F: FnMut(Message, ServiceIdentity)>(&mut self, msg: Message, listener: &mut F) {
-        match msg {
-            Message::Dealer(msg) => match msg {
-                Dealer::Health(dealer_health) => {
-                    self.available_currencies = dealer_health.available_currencies;
-                    if dealer_health.status == HealthStatus::Down || self.is_insurance_fund_depleted() {
-                        if dealer_health.status == HealthStatus::Down {
-                            slog::warn!(self.logger, "Dealer is disconnected from the exchange!");
-                        }
-                        self.available_currencies = Vec::new();
-                    }
-                }
-                Dealer::BankStateRequest(_) => {
-                    let bank_state = self.get_bank_state();
-                    let msg = Message::Dealer(Dealer::BankState(bank_state));
-                    listener(msg, ServiceIdentity::Dealer);
-                }
-                Dealer::PayInvoice(pay_invoice) => {
-                    slog::info!(self.logger, "Dealer wants to withdraw: {:?}", pay_invoice);
-                    self.process_dealer_invoice(pay_invoice, false).await;
-                }
-                Dealer::PayInsuranceInvoice(pay_invoice) => {
-                    self.process_dealer_invoice(pay_invoice, true).await;
-                }
-                Dealer::CreateInvoiceRequest(mut req) => {
-                    slog::info!(self.logger, "Dealer wants to deposit: {:?}", req);
-                    req.memo = "KolliderSettlement".to_string();
-                    self.process_create_invoice_request(req, DEALER_UID, listener).await;
-                }
-                Dealer::CreateInsuranceInvoiceRequest(mut req) => {
-                    slog::info!(self.logger, "Dealer requested insurance payment: {:?}", req);
-                    req.memo = "ExternalDeposit".to_string();
-                    self.process_create_invoice_request(req, DEALER_UID, listener).await;
-                }
-                Dealer::FiatDepositResponse(msg) => {
-                    // Fiat deposits happen in BTC and then get converted into a Fiat currency.
-                    slog::info!(self.logger, "Received fiat deposit response: {:?}", msg);
+    /// Transfers to an account of another currency. Without a quote the request is parked and the
+    /// dealer is asked for one, the transfer is made through the dealer accounts once it is quoted.
+    fn make_converted_internal_tx<F: FnMut(Message, ServiceIdentity)>(
+        &mut self,
+        payment_request: PaymentRequest,
+        transfer_rate: Option<Rate>,
+        mut payment_response: PaymentResponse,
+        mut outbound_account: Account,
+        mut inbound_account: Account,
+        inbound_uid: UserId,
+        listener: &mut F,
+    ) {
+        let outbound_uid = payment_request.uid;
+        let amount = payment_request.amount.clone().unwrap();
+        let from = outbound_account.currency;
+        let to = inbound_account.currency;
 
-                    //TODO: Fiat deposit failed we should revert to just a BTC deposit as backup.
-                    if msg.error.is_some() {
-                        return;
-                    }
+        let rate = match transfer_rate {
+            Some(rate) => rate,
+            None => {
+                if !is_quoted_transfer(from, to, &self.available_currencies) || self.is_insurance_fund_depleted() {
+                    payment_response.error = Some(PaymentResponseError::RateNotAvailable);
+                    let msg = Message::Api(Api::PaymentResponse(payment_response));
+                    listener(msg, ServiceIdentity::Api);
+                    return;
+                }
 
-                    // TODO: BTC deposit backup here.
-                    let rate = match msg.rate {
-                        Some(r) => r,
-                        None => return,
-                    };
+                let swap_request = SwapRequest {
+                    req_id: payment_request.req_id,
+                    uid: outbound_uid,
+                    amount,
+                    from,
+                    to,
+                    quote_id: None,
+                    signed_quote: None,
+                    idempotency_key: None,
+                    origin: payment_request.origin.clone(),
+                    totp_code: None,
+                };
+                self.pending_transfers.insert(payment_request.req_id, payment_request);
+                let msg = Message::Api(Api::SwapRequest(swap_request));
+                listener(msg, ServiceIdentity::Dealer);
+                return;
+            }
+        };
 
-                    let (mut inbound_account, inbound_uid) = {
-                        let user_account = self
-                            .ledger
-                            .user_accounts
-                            .entry(msg.uid as u64)
-                            .or_insert_with(|| UserAccount::new(msg.uid as u64));
+        payment_response.rate = Some(rate.clone());
+        let inbound_amount = match amount.exchange(&rate) {
+            Ok(inbound_amount) => inbound_amount,
+            Err(_) => {
+                payment_response.error = Some(PaymentResponseError::RateNotAvailable);
+                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                listener(msg, ServiceIdentity::Api);
+                return;
+            }
+        };
 
-                        let account = user_account.get_default_account(msg.currency, None);
+        // Rates can take a while to be quoted, so the balance is checked again including the fee.
+        let fee = self.operation_fee(outbound_uid, from, FeeOperation::Conversion, amount.value);
+        if outbound_account.balance < amount.value + fee.value {
+            payment_response.error = Some(PaymentResponseError::InsufficientFundsForFees);
+            let msg = Message::Api(Api::PaymentResponse(payment_response));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        }
 
-                        (account, user_account.owner)
-                    };
+        let mut inbound_dealer_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(from, Some(AccountType::Internal));
+        let mut outbound_dealer_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(to, Some(AccountType::Internal));
 
-                    let mut dealer_fiat_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(msg.currency.clone(), Some(AccountType::Internal));
+        let outbound_txid = match self.make_tx(
+            &mut outbound_account,
+            outbound_uid,
+            &mut inbound_dealer_account,
+            DEALER_UID,
+            amount.clone(),
+        ) {
+            Ok(txid) => txid,
+            Err(_) => {
+                payment_response.error = Some(PaymentResponseError::TransactionFailed);
+                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                listener(msg, ServiceIdentity::Api);
+                return;
+            }
+        };
 
-                    let mut dealer_btc_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(Currency::BTC, Some(AccountType::Internal));
+        let inbound_txid = match self.make_tx(
+            &mut outbound_dealer_account,
+            DEALER_UID,
+            &mut inbound_account,
+            inbound_uid,
+            inbound_amount,
+        ) {
+            Ok(txid) => txid,
+            Err(_) => {
+                slog::error!(
+                    self.logger,
+                    "Internal transfer {} went through on outbound only.",
+                    payment_request.req_id
+                );
+                payment_response.error = Some(PaymentResponseError::TransactionFailed);
+                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                listener(msg, ServiceIdentity::Api);
+                return;
+            }
+        };
 
-                    let mut liabilities_btc_account = self
-                        .ledger
-                        .bank_liabilities
-                        .get_default_account(Currency::BTC, Some(AccountType::External));
+        if let Err(err) = self.collect_fee(&mut outbound_account, outbound_uid, FeeOperation::Conversion, &fee) {
+            slog::error!(
+                self.logger,
+                "Failed to collect fee of internal transfer {}: {}",
+                payment_request.req_id,
+                err
+            );
+        }
+        self.fee_engine
+            .record_volume(outbound_uid, from, amount.value, utils::time::time_now());
 
-                    let value = msg.amount;
+        self.insert_into_ledger(&outbound_uid, outbound_account.account_id, outbound_account.clone());
+        self.insert_into_ledger(&inbound_uid, inbound_account.account_id, inbound_account.clone());
 
-                    let fiat_value = value.exchange(&rate).unwrap();
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(outbound_dealer_account.account_id, outbound_dealer_account.clone());
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
 
-                    let fees = if let Some(f) = msg.fees {
-                        f
-                    } else {
-                        Money::new(msg.currency, Some(dec!(0)))
-                    };
+        self.update_account(&outbound_account, outbound_uid);
+        self.update_account(&inbound_account, inbound_uid);
+        self.update_account(&outbound_dealer_account, DEALER_UID);
+        self.update_account(&inbound_dealer_account, DEALER_UID);
 
-                    // Adding BTC to dealer account.
-                    let outbound_txid = if let Ok(txid) = self.make_tx(
-                        &mut liabilities_btc_account,
-                        BANK_UID,
-                        &mut dealer_btc_account,
-                        DEALER_UID,
-                        value.clone(),
-                    ) {
-                        txid
-                    } else {
-                        return;
-                    };
+        if self
+            .make_summary_tx(
+                &outbound_account,
+                outbound_uid,
+                &inbound_account,
+                inbound_uid,
+                amount,
+                Some(rate),
+                None,
+                Some(outbound_txid),
+                Some(inbound_txid),
+                None,
+                Some(String::from("InternalTransfer")),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of internal transfer {}.",
+                payment_request.req_id
+            );
+        }
 
-                    // Adding fiat to User Account from dealer.
-                    let inbound_txid = if let Ok(txid) = self.make_tx(
-                        &mut dealer_fiat_account,
-                        DEALER_UID,
-                        &mut inbound_account,
-                        inbound_uid,
-                        fiat_value.clone(),
-                    ) {
-                        txid
-                    } else {
-                        return;
-                    };
+        self.share_fee_with_referrer(outbound_uid, &fee);
 
-                    self.insert_into_ledger(&inbound_uid, inbound_account.account_id, inbound_account.clone());
+        payment_response.fees = Some(fee);
+        payment_response.success = true;
+        let msg = Message::Api(Api::PaymentResponse(payment_response));
+        listener(msg, ServiceIdentity::Api);
 
-                    self.ledger
-                        .bank_liabilities
-                        .accounts
-                        .insert(liabilities_btc_account.account_id, liabilities_btc_account.clone());
+        // Updating the dealer of the new state of the bank.
+        let bank_state = self.get_bank_state();
+        let msg = Message::Dealer(Dealer::BankState(bank_state));
+        listener(msg, ServiceIdentity::Dealer);
+    }
 
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+    /// Moves funds between two accounts of the same user and currency, e.g. from cash to savings.
+    fn process_internal_transfer(&mut self, msg: InternalTransferRequest) -> InternalTransferResponse {
+        let mut response = InternalTransferResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            success: false,
+            from_account_id: msg.from_account_id,
+            to_account_id: msg.to_account_id,
+            amount: msg.amount,
+            txid: None,
+            error: None,
+        };
 
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
+        if !self.check_access_policy(msg.uid, &msg.origin) {
+            response.error = Some(InternalTransferError::OriginNotAllowed);
+            return response;
+        }
 
-                    self.update_account(&inbound_account, inbound_uid);
-                    self.update_account(&liabilities_btc_account, BANK_UID);
-                    self.update_account(&dealer_btc_account, DEALER_UID);
-                    self.update_account(&dealer_fiat_account, DEALER_UID);
+        if msg.amount <= dec!(0) {
+            response.error = Some(InternalTransferError::InvalidAmount);
+            return response;
+        }
 
-                    let bank_state = self.get_bank_state();
-                    let msg = Message::Dealer(Dealer::BankState(bank_state));
-                    listener(msg, ServiceIdentity::Dealer);
+        if msg.from_account_id == msg.to_account_id {
+            response.error = Some(InternalTransferError::SameAccount);
+            return response;
+        }
 
-                    if self
-                        .make_summary_tx(
-                            &liabilities_btc_account,
-                            BANK_UID,
-                            &inbound_account,
-                            inbound_uid,
-                            value,
-                            Some(rate),
-                            None,
-                            Some(outbound_txid),
-                            Some(inbound_txid),
-                            None,
-                            Some(String::from("ExternalDeposit")),
-                        )
-                        .is_err()
-                    {
-                        return;
-                    }
+        let (mut outbound_account, mut inbound_account) = match self.ledger.user_accounts.get(&msg.uid) {
+            Some(user_account) => match (
+                user_account.accounts.get(&msg.from_account_id),
+                user_account.accounts.get(&msg.to_account_id),
+            ) {
+                (Some(outbound), Some(inbound)) if !inbound.archived => (outbound.clone(), inbound.clone()),
+                _ => {
+                    response.error = Some(InternalTransferError::AccountNotFound);
+                    return response;
                 }
-                _ => {}
             },
+            None => {
+                response.error = Some(InternalTransferError::AccountNotFound);
+                return response;
+            }
+        };
 
-            Message::Deposit(msg) => {
-                slog::warn!(self.logger, "Received deposit: {:?}", msg);
-                // Deposit can only be triggered if someone external has payed an invoice generated by someone internal.
-                let conn = match &self.conn_pool {
-                    Some(conn) => conn,
-                    None => {
-                        slog::error!(self.logger, "No database provided.");
-                        return;
-                    }
-                };
+        if outbound_account.currency != inbound_account.currency {
+            response.error = Some(InternalTransferError::CurrencyMismatch);
+            return response;
+        }
 
-                let c = match conn.get() {
-                    Ok(psql_connection) => psql_connection,
-                    Err(_) => {
-                        slog::error!(self.logger, "Couldn't get psql connection.");
-                        return;
-                    }
-                };
+        if outbound_account.account_class == AccountClass::Vault {
+            response.error = Some(InternalTransferError::VaultAccount);
+            return response;
+        }
 
-                // Check whether we know about this invoice.
-                if let Ok(invoice) = Invoice::get_by_payment_request(&c, msg.payment_request.clone()) {
-                    dbg!("getting deposit");
-                    dbg!(&invoice);
-                    let is_dealer_invoice = invoice.uid as UserId == DEALER_UID;
+        if outbound_account.balance < msg.amount {
+            response.error = Some(InternalTransferError::InsufficientFunds);
+            return response;
+        }
 
-                    dbg!(&is_dealer_invoice);
+        let amount = Money::new(outbound_account.currency, Some(msg.amount));
 
-                    if is_dealer_invoice {
-                        self.handle_dealer_deposit(msg).await;
-                        return;
-                    }
+        let txid = match self.make_tx(
+            &mut outbound_account,
+            msg.uid,
+            &mut inbound_account,
+            msg.uid,
+            amount.clone(),
+        ) {
+            Ok(txid) => txid,
+            Err(_) => {
+                response.error = Some(InternalTransferError::TransactionFailed);
+                return response;
+            }
+        };
 
-                    // Value of the depoist.
-                    let value = Money::from_sats(Decimal::new(invoice.value as i64, 0));
+        self.insert_into_ledger(&msg.uid, outbound_account.account_id, outbound_account.clone());
+        self.insert_into_ledger(&msg.uid, inbound_account.account_id, inbound_account.clone());
 
-                    let currency = match invoice.currency {
-                        Some(c) => match Currency::from_str(&c) {
-                            Ok(converted) => converted,
-                            Err(err) => {
-                                panic!("Failed to convert {} into a valid currency, reason: {:?}", c, err);
-                            }
-                        },
-                        None => Currency::BTC,
-                    };
+        self.update_account(&outbound_account, msg.uid);
+        self.update_account(&inbound_account, msg.uid);
 
-                    // If user wants to deposit into a fiat account.
-                    let target_account_currency = match invoice.target_account_currency {
-                        Some(c) => match Currency::from_str(&c) {
-                            Ok(converted) => converted,
-                            Err(err) => {
-                                panic!("Failed to convert {} into a valid currency, reason: {:?}", c, err);
-                            }
-                        },
-                        None => Currency::BTC,
-                    };
+        if self
+            .make_summary_tx(
+                &outbound_account,
+                msg.uid,
+                &inbound_account,
+                msg.uid,
+                amount,
+                None,
+                None,
+                Some(txid.clone()),
+                Some(txid.clone()),
+                None,
+                Some(String::from("SelfTransfer")),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of self transfer {}.",
+                msg.req_id
+            );
+        }
 
-                    // If its not a fiat deposit we need to get the current rate.
-                    // Note a user could deposit with a sat specified invoice and then deposit into a fiat account.
-                    if currency != Currency::BTC
-                        || (currency == Currency::BTC && target_account_currency != Currency::BTC)
-                    {
-                        let c = if currency == Currency::BTC {
-                            target_account_currency
-                        } else {
-                            currency
-                        };
-                        let fiat_deposit_request = FiatDepositRequest {
-                            uid: invoice.uid as u64,
-                            currency: c,
-                            req_id: Uuid::new_v4(),
-                            amount: value,
-                        };
-                        let msg = Message::Dealer(Dealer::FiatDepositRequest(fiat_deposit_request));
-                        listener(msg, ServiceIdentity::Dealer);
-                        return;
-                    }
+        response.success = true;
+        response.txid = Some(txid);
+        response
+    }
 
-                    let (mut inbound_account, inbound_uid) = {
-                        let user_account = self
-                            .ledger
-                            .user_accounts
-                            .entry(invoice.uid as u64)
-                            .or_insert_with(|| UserAccount::new(invoice.uid as u64));
+    /// Checks a label for a sub-account, labels are unique among the user's accounts that aren't archived.
+    fn validate_account_label(&self, uid: UserId, label: &str) -> Option<AccountResponseError> {
+        let label = label.trim();
+        if label.is_empty() || label.len() > MAX_ACCOUNT_LABEL_LENGTH {
+            return Some(AccountResponseError::InvalidLabel);
+        }
+        let used = self.ledger.user_accounts.get(&uid).map_or(false, |user_account| {
+            user_account
+                .accounts
+                .values()
+                .any(|account| !account.archived && account.label.as_deref() == Some(label))
+        });
+        if used {
+            return Some(AccountResponseError::LabelAlreadyUsed);
+        }
+        None
+    }
 
-                        let account = user_account.get_default_account(currency, None);
+    fn create_sub_account(&mut self, msg: CreateAccountRequest) -> AccountResponse {
+        let mut response = AccountResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            account: None,
+            error: self.validate_account_label(msg.uid, &msg.label),
+        };
+        if response.error.is_some() {
+            return response;
+        }
 
-                        (account, user_account.owner)
-                    };
+        if msg.vault && self.vault_settings.is_none() {
+            response.error = Some(AccountResponseError::VaultsNotOffered);
+            return response;
+        }
 
-                    // At this point only BTC deposits are possible.
-                    let mut liability_account = self
-                        .ledger
-                        .bank_liabilities
-                        .get_default_account(Currency::BTC, Some(AccountType::External));
+        let user_account = self
+            .ledger
+            .user_accounts
+            .entry(msg.uid)
+            .or_insert_with(|| UserAccount::new(msg.uid));
+
+        let sub_accounts = user_account
+            .accounts
+            .values()
+            .filter(|account| account.label.is_some() && !account.archived)
+            .count();
+        if sub_accounts >= MAX_SUB_ACCOUNTS {
+            response.error = Some(AccountResponseError::AccountLimitExceeded);
+            return response;
+        }
 
-                    // Making the transaction and inserting it into the DB.
-                    let txid = if let Ok(txid) = self.make_tx(
-                        &mut liability_account,
-                        BANK_UID,
-                        &mut inbound_account,
-                        inbound_uid,
-                        value.clone(),
-                    ) {
-                        txid
-                    } else {
-                        return;
-                    };
+        // The main account of the currency is created first so the sub-account never becomes the default.
+        user_account.get_default_account(msg.currency, None);
 
-                    // Safe to unwrap as we created this account above.
-                    self.insert_into_ledger(&inbound_uid, inbound_account.account_id, inbound_account.clone());
-                    // Updating cache of external account.
-                    self.ledger
-                        .bank_liabilities
-                        .accounts
-                        .insert(liability_account.account_id, liability_account.clone());
+        let account_class = if msg.vault {
+            AccountClass::Vault
+        } else {
+            AccountClass::Cash
+        };
+        let mut account = Account::new(msg.currency, AccountType::Internal, account_class);
+        account.label = Some(msg.label.trim().to_string());
+        user_account.accounts.insert(account.account_id, account.clone());
 
-                    // Updating db of internal account.
-                    self.update_account(&inbound_account, inbound_uid);
+        self.update_account(&account, msg.uid);
+        response.account = Some(account);
+        response
+    }
 
-                    // Updating db of internal account.
-                    self.update_account(&liability_account, BANK_UID);
+    fn rename_sub_account(&mut self, msg: RenameAccountRequest) -> AccountResponse {
+        let mut response = AccountResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            account: None,
+            error: None,
+        };
 
-                    if self
-                        .make_summary_tx(
-                            &liability_account,
-                            BANK_UID,
-                            &inbound_account,
-                            inbound_uid,
-                            value,
-                            None,
-                            None,
-                            Some(txid.clone()),
-                            Some(txid),
-                            None,
-                            Some(String::from("ExternalDeposit")),
-                        )
-                        .is_err()
-                    {
-                        return;
-                    }
-                }
+        let mut account = match self.sub_account(msg.uid, msg.account_id) {
+            Ok(account) => account,
+            Err(error) => {
+                response.error = Some(error);
+                return response;
             }
-            Message::Api(msg) => match msg {
-                Api::InvoiceRequest(msg) => {
-                    slog::warn!(self.logger, "Received invoice request: {:?}", msg);
+        };
 
-                    if !self.check_deposit_request_rate_limit(msg.uid) {
-                        let invoice_response = InvoiceResponse {
-                            amount: msg.amount,
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            meta: msg.meta,
-                            metadata: msg.metadata.clone(),
-                            rate: None,
-                            payment_request: None,
-                            currency: msg.currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: None,
-                            error: Some(InvoiceResponseError::RequestLimitExceeded),
-                            fees: None,
-                        };
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        response.error = self.validate_account_label(msg.uid, &msg.label);
+        if response.error.is_some() {
+            return response;
+        }
 
-                    if self.is_insurance_fund_depleted() {
-                        slog::warn!(self.logger, "Insurance is depleted Deposit request Failed!");
-                        let invoice_response = InvoiceResponse {
-                            amount: msg.amount,
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            rate: None,
-                            meta: msg.meta.clone(),
-                            metadata: msg.metadata.clone(),
-                            payment_request: None,
-                            currency: msg.currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: None,
-                            error: Some(InvoiceResponseError::InvoicingSuspended),
-                            fees: None,
-                        };
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        account.label = Some(msg.label.trim().to_string());
+        self.insert_into_ledger(&msg.uid, account.account_id, account.clone());
+        self.update_account(&account, msg.uid);
+        response.account = Some(account);
+        response
+    }
 
-                    let user_account = self
-                        .ledger
-                        .user_accounts
-                        .entry(msg.uid)
-                        .or_insert_with(|| UserAccount::new(msg.uid));
+    fn archive_sub_account(&mut self, msg: ArchiveAccountRequest) -> AccountResponse {
+        let mut response = AccountResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            account: None,
+            error: None,
+        };
 
-                    if self.withdrawal_only {
-                        slog::info!(self.logger, "Bank is in withdrawal only mode");
-                        let invoice_response = InvoiceResponse {
-                            amount: msg.amount,
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            meta: msg.meta,
-                            metadata: msg.metadata.clone(),
-                            rate: None,
-                            payment_request: None,
-                            currency: msg.currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: None,
-                            error: Some(InvoiceResponseError::WithdrawalOnly),
-                            fees: None,
-                        };
+        let mut account = match self.sub_account(msg.uid, msg.account_id) {
+            Ok(account) => account,
+            Err(error) => {
+                response.error = Some(error);
+                return response;
+            }
+        };
 
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        if account.balance != dec!(0) || account.pending_balance != dec!(0) {
+            response.error = Some(AccountResponseError::AccountNotEmpty);
+            return response;
+        }
 
-                    let conn = match &self.conn_pool {
-                        Some(conn) => conn,
-                        None => {
-                            slog::error!(self.logger, "No database provided.");
-                            let invoice_response = InvoiceResponse {
-                                amount: msg.amount,
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: None,
-                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+        account.archived = true;
+        self.insert_into_ledger(&msg.uid, account.account_id, account.clone());
+        self.update_account(&account, msg.uid);
+        response.account = Some(account);
+        response
+    }
 
-                    let c = match conn.get() {
-                        Ok(psql_connection) => psql_connection,
-                        Err(_) => {
-                            slog::error!(self.logger, "Couldn't get psql connection.");
-                            let invoice_response = InvoiceResponse {
-                                amount: msg.amount,
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: None,
-                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+    /// Returns a labeled account of the user that isn't archived, main accounts can't be changed.
+    fn sub_account(&self, uid: UserId, account_id: AccountId) -> Result<Account, AccountResponseError> {
+        let account = self
+            .ledger
+            .user_accounts
+            .get(&uid)
+            .and_then(|user_account| user_account.accounts.get(&account_id))
+            .filter(|account| !account.archived)
+            .ok_or(AccountResponseError::AccountNotFound)?;
+        if account.label.is_none() {
+            return Err(AccountResponseError::MainAccount);
+        }
+        Ok(account.clone())
+    }
 
-                    let amount = msg.amount.clone();
-                    let currency = msg.currency;
+    /// Returns the owner of a user account together with the account. Accounts the ledger hasn't indexed yet are
+    /// looked up by their id in the database.
+    fn account_owner(&self, account_id: AccountId) -> Option<(UserId, Account)> {
+        if let Some(owner) = self.ledger.account_owner(account_id) {
+            return Some(owner);
+        }
+        let conn = self.conn_pool.as_ref().and_then(|pool| pool.get().ok())?;
+        let uid = accounts::Account::get_by_account_id(&conn, account_id).ok()?.uid as u64;
+        self.ledger
+            .user_accounts
+            .get(&uid)
+            .and_then(|user_account| user_account.accounts.get(&account_id))
+            .map(|account| (uid, account.clone()))
+    }
 
-                    let mut target_account = Account::new(msg.currency, AccountType::Internal, AccountClass::Cash);
+    /// Returns the membership of the user in the account, invitations that weren't accepted yet included.
+    fn account_membership(&self, uid: UserId, account_id: AccountId) -> Option<account_members::AccountMember> {
+        let conn = self.conn_pool.as_ref()?;
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return None;
+            }
+        };
+        account_members::AccountMember::get(&c, account_id, uid as i32).ok()
+    }
 
-                    if let Some(account_id) = msg.account_id {
-                        if let Some(acc) = user_account.accounts.get(&account_id) {
-                            target_account = acc.clone();
-                        } else {
-                            let invoice_response = InvoiceResponse {
-                                amount,
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: Some(target_account.account_id),
-                                error: Some(InvoiceResponseError::AccountDoesNotExist),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    } else {
-                        // If user does not specify an account_id we select or create one for him.
-                        let account = user_account.get_default_account(msg.currency, None);
-                        target_account = account;
-                    }
+    /// Role of the user in an account they don't own, if they accepted the invitation.
+    fn member_role(&self, uid: UserId, account_id: AccountId) -> Option<(MemberRole, Option<Decimal>)> {
+        let member = self
+            .account_membership(uid, account_id)
+            .filter(|member| member.accepted)?;
+        let role = MemberRole::from_str(&member.role).ok()?;
+        let spend_limit = member
+            .spend_limit
+            .and_then(|limit| Decimal::from_str(&limit.to_string()).ok());
+        Some((role, spend_limit))
+    }
 
-                    let deposit_limit = self
-                        .deposit_limits
-                        .get(&currency)
-                        .unwrap_or_else(|| panic!("Failed to get deposit limits for {}", currency));
-                    // Check whether deposit limit is exceeded.
-                    if target_account.balance + amount.value > *deposit_limit {
-                        let invoice_response = InvoiceResponse {
-                            amount,
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            rate: None,
-                            meta: msg.meta.clone(),
-                            metadata: msg.metadata.clone(),
-                            payment_request: None,
-                            currency: msg.currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: Some(target_account.account_id),
-                            error: Some(InvoiceResponseError::DepositLimitExceeded),
-                            fees: None,
-                        };
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+    /// Returns the uid a payment from the requested account is made as, which is the owner of the account
+    /// if the sender is a member allowed to spend the amount.
+    fn authorize_account_spend(&self, msg: &PaymentRequest) -> Result<UserId, PaymentResponseError> {
+        let account_id = match msg.account_id {
+            Some(account_id) => account_id,
+            None => return Ok(msg.uid),
+        };
 
-                    // If user wants to deposit another currency we have to go through the dealer.
-                    if currency != Currency::BTC {
-                        let msg = Message::Api(Api::InvoiceRequest(msg));
-                        listener(msg, ServiceIdentity::Dealer);
-                        return;
-                    }
+        let (owner, account) = self
+            .account_owner(account_id)
+            .ok_or(PaymentResponseError::AccountDoesNotExist)?;
+        if owner == msg.uid {
+            return Ok(owner);
+        }
 
-                    let amount_in_sats = amount.try_sats().unwrap().to_u64().unwrap_or_else(|| {
-                        panic!(
-                            "Failed to convert  decimal amount in BTC: {:?} to u64 amount in SATs",
-                            amount
-                        )
-                    });
+        // Users that aren't members don't learn whether the account exists.
+        let (role, spend_limit) = self
+            .member_role(msg.uid, account_id)
+            .ok_or(PaymentResponseError::AccountDoesNotExist)?;
 
-                    dbg!("Creating invoice");
+        let spend_limit = match (role, spend_limit) {
+            (MemberRole::Viewer, _) => return Err(PaymentResponseError::NotPermitted),
+            (MemberRole::Spender, Some(spend_limit)) => spend_limit,
+            _ => return Ok(owner),
+        };
 
-                    if let Ok(mut invoice) = self
-                        .lnd_connector
-                        .create_invoice(
-                            amount_in_sats,
-                            msg.meta.clone(),
-                            msg.uid,
-                            target_account.account_id,
-                            msg.metadata.clone(),
-                        )
-                        .await
-                    {
-                        dbg!(&invoice);
-                        invoice.currency = Some(msg.currency.to_string());
-                        if let Some(target_account_currency) = msg.target_account_currency {
-                            invoice.target_account_currency = Some(target_account_currency.to_string());
-                        } else {
-                            invoice.target_account_currency = None
-                        }
-                        if let Err(_err) = invoice.insert(&c) {
-                            dbg!(&_err);
-                            slog::error!(self.logger, "Error inserting invoice.");
-                            let invoice_response = InvoiceResponse {
-                                amount,
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                payment_request: None,
-                                metadata: msg.metadata.clone(),
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: Some(target_account.account_id),
-                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
+        let amount = match &msg.amount {
+            Some(amount) => Some(amount.value),
+            None if account.currency == Currency::BTC => msg
+                .payment_request
+                .as_ref()
+                .and_then(|payment_request| payment_request.parse::<lightning_invoice::Invoice>().ok())
+                .and_then(|invoice| invoice.amount_milli_satoshis())
+                .map(|msats| Money::from_msats(Decimal::new(msats as i64, 0)).value),
+            None => None,
+        };
 
-                        let invoice_response = InvoiceResponse {
-                            amount,
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            meta: msg.meta,
-                            metadata: msg.metadata.clone(),
-                            rate: None,
-                            payment_request: Some(invoice.payment_request),
-                            currency: msg.currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: Some(target_account.account_id),
-                            error: None,
-                            fees: None,
-                        };
+        match amount {
+            Some(amount) if amount <= spend_limit => Ok(owner),
+            _ => Err(PaymentResponseError::SpendLimitExceeded),
+        }
+    }
 
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api)
-                    }
-                }
-                Api::InvoiceResponse(ref msg) => {
-                    let rate = match &msg.rate {
-                        Some(r) => r,
-                        None => {
-                            let mut m = msg.clone();
-                            m.error = Some(InvoiceResponseError::RateNotAvailable);
-                            let msg = Message::Api(Api::InvoiceResponse(m));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
-                    let amount_in_btc = msg.amount.div(rate.value);
-                    let money = Money::from_btc(amount_in_btc);
-                    let amount_in_sats = money
-                        .try_sats()
+    /// Accounts of other users the user accepted to be a member of.
+    fn shared_accounts(&self, uid: UserId) -> HashMap<AccountId, Account> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return HashMap::new(),
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return HashMap::new();
+            }
+        };
+
+        let members = match account_members::AccountMember::get_by_uid(&c, uid as i32) {
+            Ok(members) => members,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load account memberships of {}: {:?}", uid, err);
+                return HashMap::new();
+            }
+        };
+
+        members
+            .iter()
+            .filter(|member| member.accepted)
+            .filter_map(|member| self.account_owner(member.account_id))
+            .map(|(_, account)| (account.account_id, account))
+            .collect()
+    }
+
+    fn can_manage_members(&self, uid: UserId, account_id: AccountId, owner: UserId) -> bool {
+        uid == owner || matches!(self.member_role(uid, account_id), Some((MemberRole::Admin, _)))
+    }
+
+    fn invite_account_member(&mut self, msg: InviteAccountMemberRequest) -> AccountMemberResponse {
+        let mut response = AccountMemberResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            member: None,
+            error: None,
+        };
+
+        let owner = match self.account_owner(msg.account_id) {
+            Some((owner, account)) if !account.archived => owner,
+            _ => {
+                response.error = Some(AccountMemberError::AccountNotFound);
+                return response;
+            }
+        };
+
+        if !self.can_manage_members(msg.uid, msg.account_id, owner) {
+            response.error = Some(AccountMemberError::NotPermitted);
+            return response;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        let invitee = match User::get_by_username(&c, msg.username.clone()) {
+            Ok(user) => user,
+            Err(_) => {
+                response.error = Some(AccountMemberError::UserDoesNotExist);
+                return response;
+            }
+        };
+
+        if invitee.uid as UserId == owner
+            || account_members::AccountMember::get(&c, msg.account_id, invitee.uid).is_ok()
+        {
+            response.error = Some(AccountMemberError::AlreadyMember);
+            return response;
+        }
+
+        // Only spenders are limited.
+        let spend_limit = match msg.role {
+            MemberRole::Spender => msg.spend_limit,
+            _ => None,
+        };
+
+        let member = account_members::InsertableAccountMember {
+            account_id: msg.account_id,
+            uid: invitee.uid,
+            role: msg.role.to_string(),
+            spend_limit: spend_limit.and_then(|limit| BigDecimal::from_str(&limit.to_string()).ok()),
+            invited_by: msg.uid as i32,
+            accepted: false,
+            created_at: utils::time::time_now() as i64,
+        };
+
+        match member.insert(&c) {
+            Ok(member) => response.member = account_member(&member),
+            Err(err) => {
+                slog::error!(self.logger, "Failed to insert account member: {:?}", err);
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+            }
+        }
+        response
+    }
+
+    fn accept_account_invitation(&mut self, msg: AcceptAccountInvitationRequest) -> AccountMemberResponse {
+        let mut response = AccountMemberResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            member: None,
+            error: None,
+        };
+
+        let mut member = match self.account_membership(msg.uid, msg.account_id) {
+            Some(member) if !member.accepted => member,
+            _ => {
+                response.error = Some(AccountMemberError::MemberNotFound);
+                return response;
+            }
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        if account_members::AccountMember::accept(&c, msg.account_id, msg.uid as i32).is_err() {
+            response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+            return response;
+        }
+
+        member.accepted = true;
+        response.member = account_member(&member);
+        response
+    }
+
+    fn remove_account_member(&mut self, msg: RemoveAccountMemberRequest) -> AccountMemberResponse {
+        let mut response = AccountMemberResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            member: None,
+            error: None,
+        };
+
+        let owner = match self.account_owner(msg.account_id) {
+            Some((owner, _)) => owner,
+            None => {
+                response.error = Some(AccountMemberError::AccountNotFound);
+                return response;
+            }
+        };
+
+        if msg.member_uid != msg.uid && !self.can_manage_members(msg.uid, msg.account_id, owner) {
+            response.error = Some(AccountMemberError::NotPermitted);
+            return response;
+        }
+
+        let member = match self.account_membership(msg.member_uid, msg.account_id) {
+            Some(member) => member,
+            None => {
+                response.error = Some(AccountMemberError::MemberNotFound);
+                return response;
+            }
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        if account_members::AccountMember::delete(&c, msg.account_id, msg.member_uid as i32).is_err() {
+            response.error = Some(AccountMemberError::DatabaseConnectionFailed);
+            return response;
+        }
+
+        response.member = account_member(&member);
+        response
+    }
+
+    /// Claims the idempotency key of a request. Returns false if the request was answered
+    /// from the key's stored response and must not be processed again.
+    fn claim_idempotency_key<F: FnMut(Message, ServiceIdentity)>(&mut self, msg: &Message, listener: &mut F) -> bool {
+        let (req_id, uid, key) = match request_idempotency_key(msg) {
+            Some(claim) => claim,
+            None => return true,
+        };
+
+        // Requests coming back from the dealer with a rate have already claimed their key.
+        if self.idempotent_requests.contains_key(&req_id) {
+            return true;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return true;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return true;
+            }
+        };
+
+        if let Ok(existing) = IdempotencyKey::get(&c, uid as i32, key.clone()) {
+            slog::info!(
+                self.logger,
+                "Request {} reuses idempotency key of {}",
+                req_id,
+                existing.req_id
+            );
+            let replayed = existing
+                .response
+                .and_then(|response| serde_json::from_str::<Message>(&response).ok())
+                .and_then(|response| replay_response(response, req_id));
+            if let Some(msg) = replayed.or_else(|| duplicate_request_response(msg)) {
+                listener(msg, ServiceIdentity::Api);
+            }
+            return false;
+        }
+
+        let now = utils::time::time_now();
+        let idempotency_key = IdempotencyKey {
+            uid: uid as i32,
+            idempotency_key: key.clone(),
+            req_id: req_id.to_string(),
+            response: None,
+            created_at: now as i64,
+        };
+
+        // Another request with the same key was inserted in the meantime.
+        if idempotency_key.insert(&c).is_err() {
+            if let Some(msg) = duplicate_request_response(msg) {
+                listener(msg, ServiceIdentity::Api);
+            }
+            return false;
+        }
+
+        self.idempotent_requests.insert(req_id, (uid, key, now));
+        true
+    }
+
+    /// Drops idempotency keys past their ttl.
+    pub fn expire_idempotency_keys(&mut self) {
+        let now = utils::time::time_now();
+        let expired_before = now.saturating_sub(IDEMPOTENCY_KEY_TTL_MS);
+
+        self.idempotent_requests
+            .retain(|_, (_, _, created_at)| *created_at >= expired_before);
+
+        if let Some(db_writer) = &self.db_writer {
+            db_writer.submit(
+                0,
+                "expired idempotency keys",
+                Box::new(move |c| IdempotencyKey::delete_older_than(c, expired_before as i64).map(|_| ())),
+            );
+        }
+    }
+
+    /// Loads the LNURL withdrawals that were created before a restart and can still be paid.
+    pub fn init_lnurl_withdrawals(&mut self) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let stored = LnurlWithdrawalRequest::get_unexpired(&c, utils::time::time_now() as i64)
+            .expect("Failed to load LNURL withdrawals");
+        for withdrawal in stored {
+            match serde_json::from_str::<PaymentRequest>(&withdrawal.payment_request) {
+                Ok(payment_request) => {
+                    self.lnurl_withdrawal_requests
+                        .insert(payment_request.req_id, (withdrawal.created_at as u64, payment_request));
+                }
+                Err(err) => slog::error!(
+                    self.logger,
+                    "Failed to restore LNURL withdrawal {}: {:?}",
+                    withdrawal.req_id,
+                    err
+                ),
+            }
+        }
+    }
+
+    fn store_lnurl_withdrawal(&self, payment_request: &PaymentRequest, created_at: u64) -> Result<(), String> {
+        let conn = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or_else(|| "Couldn't get psql connection".to_string())?;
+        let withdrawal = LnurlWithdrawalRequest {
+            req_id: payment_request.req_id.to_string(),
+            uid: payment_request.uid as i32,
+            payment_request: serde_json::to_string(payment_request).map_err(|err| err.to_string())?,
+            created_at: created_at as i64,
+            expires_at: (created_at + LNURL_WITHDRAWAL_TTL_MS) as i64,
+        };
+        withdrawal.insert(&conn).map(|_| ()).map_err(|err| format!("{:?}", err))
+    }
+
+    fn unexpired_lnurl_withdrawal(&self, req_id: RequestId) -> Option<&PaymentRequest> {
+        let now = utils::time::time_now();
+        self.lnurl_withdrawal_requests
+            .get(&req_id)
+            .filter(|(created_at, _)| created_at + LNURL_WITHDRAWAL_TTL_MS > now)
+            .map(|(_, payment_request)| payment_request)
+    }
+
+    fn remove_lnurl_withdrawal(&mut self, req_id: RequestId) -> Option<(u64, PaymentRequest)> {
+        let removed = self.lnurl_withdrawal_requests.remove(&req_id);
+        match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => {
+                if let Err(err) = LnurlWithdrawalRequest::delete(&conn, &req_id.to_string()) {
+                    slog::error!(self.logger, "Failed to delete LNURL withdrawal {}: {:?}", req_id, err);
+                }
+            }
+            None => slog::error!(self.logger, "Couldn't get psql connection."),
+        }
+        removed
+    }
+
+    /// Drops LNURL withdrawals whose QR codes weren't scanned and paid in time.
+    pub fn expire_lnurl_withdrawals(&mut self) {
+        let now = utils::time::time_now();
+        self.lnurl_withdrawal_requests
+            .retain(|_, (created_at, _)| *created_at + LNURL_WITHDRAWAL_TTL_MS > now);
+
+        if let Some(db_writer) = &self.db_writer {
+            db_writer.submit(
+                0,
+                "expired LNURL withdrawals",
+                Box::new(move |c| LnurlWithdrawalRequest::delete_expired(c, now as i64).map(|_| ())),
+            );
+        }
+    }
+
+    fn list_pending_lnurl_withdrawals(&self) -> Vec<PendingLnurlWithdrawal> {
+        let mut withdrawals = self
+            .lnurl_withdrawal_requests
+            .values()
+            .map(|(created_at, payment_request)| {
+                let (amount, currency) = match &payment_request.amount {
+                    Some(amount) => (amount.value, amount.currency),
+                    None => (Decimal::ZERO, payment_request.currency),
+                };
+                PendingLnurlWithdrawal {
+                    req_id: payment_request.req_id,
+                    uid: payment_request.uid,
+                    amount,
+                    currency,
+                    created_at: *created_at,
+                    expires_at: created_at + LNURL_WITHDRAWAL_TTL_MS,
+                }
+            })
+            .collect::<Vec<_>>();
+        withdrawals.sort_by_key(|withdrawal| withdrawal.created_at);
+        withdrawals
+    }
+
+    /// Replaces the memos and metadata of invoices past the retention period with their hashes, once an hour.
+    pub fn hash_invoice_descriptions(&mut self) {
+        let retention_days = match self.description_retention_days {
+            Some(retention_days) => retention_days,
+            None => return,
+        };
+
+        let now = utils::time::time_now();
+        if now < self.last_description_hashing_timestamp + MILLIS_IN_HOUR {
+            return;
+        }
+        self.last_description_hashing_timestamp = now;
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let created_before = now.saturating_sub(retention_days * MILLIS_IN_DAY);
+        match Invoice::hash_descriptions(&c, created_before as i64, now as i64) {
+            Ok(0) => {}
+            Ok(hashed) => slog::info!(self.logger, "Hashed the descriptions of {} invoices", hashed),
+            Err(err) => slog::error!(self.logger, "Failed to hash invoice descriptions: {:?}", err),
+        }
+    }
+
+    /// Persists every change in the availability of the dealer currencies, the history availability
+    /// reports are computed from.
+    fn record_dealer_availability(&mut self) {
+        let changes = currency_registry::synthetics()
+            .into_iter()
+            .map(|currency| (currency, self.available_currencies.contains(&currency)))
+            .filter(|(currency, available)| self.dealer_availability.get(currency) != Some(available))
+            .collect::<Vec<_>>();
+
+        if changes.is_empty() {
+            return;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let now = utils::time::time_now();
+        for (currency, available) in changes {
+            let event = InsertableDealerHealthEvent {
+                currency: currency.to_string(),
+                available,
+                created_at: now as i64,
+            };
+            // Not updating the last availability on failure retries the insert with the next health report.
+            match event.insert(&c) {
+                Ok(_) => {
+                    slog::info!(self.logger, "Availability of {} changed to {}", currency, available);
+                    self.dealer_availability.insert(currency, available);
+                }
+                Err(err) => {
+                    slog::error!(self.logger, "Failed to insert dealer health event: {:?}", err);
+                }
+            }
+        }
+    }
+
+    /// Registers the synthetics the dealer hedges, so currencies added to the dealer become
+    /// usable without restarting the bank.
+    fn register_dealer_currencies(&mut self, currencies: &[CurrencyInfo]) {
+        for info in currencies.iter() {
+            let known = info
+                .currency()
+                .ok()
+                .and_then(currency_registry::get)
+                .map_or(false, |known| &known == info);
+            if known {
+                continue;
+            }
+            match currency_registry::register(info.clone()) {
+                Ok(currency) => slog::info!(self.logger, "Registered currency {} advertised by the dealer", currency),
+                Err(err) => slog::error!(self.logger, "Dealer advertised an invalid currency: {}", err),
+            }
+        }
+    }
+
+    /// Takes the dealer currencies offline if the dealer stopped reporting its health.
+    pub fn check_dealer_health_timeout(&mut self) {
+        if utils::time::time_now().saturating_sub(self.last_dealer_health_timestamp) < DEALER_HEALTH_TIMEOUT_MS {
+            return;
+        }
+
+        let synthetics = currency_registry::synthetics();
+        if self
+            .available_currencies
+            .iter()
+            .any(|currency| synthetics.contains(currency))
+        {
+            slog::warn!(self.logger, "No health report received from the dealer!");
+            self.available_currencies
+                .retain(|currency| !synthetics.contains(currency));
+        }
+        self.record_dealer_availability();
+    }
+
+    /// Refreshes the max receivable amount from the inbound liquidity of the node's channels.
+    /// The last known value is kept if the node can't be queried.
+    pub async fn refresh_inbound_capacity(&mut self) {
+        let headroom = match self.inbound_capacity_headroom {
+            Some(headroom) => headroom,
+            None => return,
+        };
+
+        let now = utils::time::time_now();
+        if now.saturating_sub(self.last_inbound_capacity_timestamp) < INBOUND_CAPACITY_TTL_MS {
+            return;
+        }
+        self.last_inbound_capacity_timestamp = now;
+
+        match self.lnd_connector.get_inbound_capacity().await {
+            Ok(capacity) => {
+                let max_receivable = (Decimal::from(capacity.total) * headroom).floor().to_u64().unwrap_or(0);
+                self.max_receivable_sats = Some(max_receivable);
+            }
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch inbound capacity: {:?}", err);
+            }
+        }
+    }
+
+    /// Returns the max receivable amount if an invoice of the amount could never be paid to the node.
+    fn exceeded_inbound_capacity(&self, amount_in_sats: u64) -> Option<u64> {
+        match self.max_receivable_sats {
+            Some(max_receivable) if amount_in_sats > max_receivable => {
+                slog::warn!(
+                    self.logger,
+                    "Rejecting invoice of {} sats, node can receive at most {} sats.",
+                    amount_in_sats,
+                    max_receivable
+                );
+                Some(max_receivable)
+            }
+            _ => None,
+        }
+    }
+
+    /// Refreshes the max payable amount from the outbound liquidity of the node's channels.
+    /// The last known value is kept if the node can't be queried.
+    pub async fn refresh_outbound_capacity(&mut self) {
+        let headroom = match self.outbound_capacity_headroom {
+            Some(headroom) => headroom,
+            None => return,
+        };
+
+        let now = utils::time::time_now();
+        if now.saturating_sub(self.last_outbound_capacity_timestamp) < INBOUND_CAPACITY_TTL_MS {
+            return;
+        }
+        self.last_outbound_capacity_timestamp = now;
+
+        match self.lnd_connector.get_outbound_capacity().await {
+            Ok(capacity) => {
+                let max_payable = (Decimal::from(capacity.total) * headroom).floor().to_u64().unwrap_or(0);
+                self.max_payable_sats = Some(max_payable);
+            }
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch outbound capacity: {:?}", err);
+            }
+        }
+    }
+
+    /// Returns the max payable amount if a withdrawal of the amount can't leave the node's channels right now.
+    fn exceeded_outbound_capacity(&self, amount_in_sats: u64) -> Option<u64> {
+        match self.max_payable_sats {
+            Some(max_payable) if amount_in_sats > max_payable => {
+                slog::warn!(
+                    self.logger,
+                    "Rejecting withdrawal of {} sats, node can send at most {} sats.",
+                    amount_in_sats,
+                    max_payable
+                );
+                Some(max_payable)
+            }
+            _ => None,
+        }
+    }
+
+    /// Compares ledger balances with the accounts table and the sum of all transactions
+    /// once the reconciliation interval has elapsed.
+    pub async fn reconcile_with_database(&mut self) {
+        let settings = match &self.reconciliation_settings {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+
+        let now = utils::time::time_now();
+        if now < self.last_db_reconciliation_timestamp + settings.interval_secs * 1000 {
+            return;
+        }
+        if self.db_writes_failing {
+            return;
+        }
+        self.last_db_reconciliation_timestamp = now;
+
+        // Balances still held back or queued would show up as drifts. The loop waits here, so no new ones come in.
+        if let Some(db_writer) = self.db_writer.as_mut() {
+            if !db_writer
+                .wait_until_written(Duration::from_millis(RECONCILIATION_WRITE_WAIT_MS))
+                .await
+            {
+                slog::warn!(
+                    self.logger,
+                    "Database writes didn't finish within {} ms, skipping reconciliation.",
+                    RECONCILIATION_WRITE_WAIT_MS
+                );
+                return;
+            }
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let persisted_balances = match accounts::Account::get_all(&c) {
+            Ok(accounts) => accounts
+                .into_iter()
+                .filter_map(|account| {
+                    Decimal::from_str(&account.balance.to_string())
+                        .ok()
+                        .map(|balance| (account.account_id, balance))
+                })
+                .collect::<HashMap<AccountId, Decimal>>(),
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch accounts for reconciliation: {:?}", err);
+                return;
+            }
+        };
+
+        let transactions_balances = match Transaction::get_net_amounts_by_account(&c) {
+            Ok(sums) => sums
+                .into_iter()
+                .filter_map(|sum| {
+                    Decimal::from_str(&sum.net_amount.to_string())
+                        .ok()
+                        .map(|balance| (sum.account_id, balance))
+                })
+                .collect::<HashMap<AccountId, Decimal>>(),
+            Err(err) => {
+                slog::error!(self.logger, "Failed to sum transactions for reconciliation: {:?}", err);
+                return;
+            }
+        };
+
+        let ledger_accounts = self
+            .ledger
+            .user_accounts
+            .values()
+            .chain([&self.ledger.bank_liabilities, &self.ledger.dealer_accounts])
+            .flat_map(|user_account| user_account.accounts.values());
+
+        self.account_drifts = ledger_accounts
+            .filter_map(|account| {
+                let persisted_balance = persisted_balances.get(&account.account_id).copied();
+                let transactions_balance = transactions_balances.get(&account.account_id).copied();
+                if persisted_balance == Some(account.balance)
+                    && transactions_balance.unwrap_or(Decimal::ZERO) == account.balance
+                {
+                    return None;
+                }
+                Some(AccountDrift {
+                    account_id: account.account_id,
+                    ledger_balance: account.balance,
+                    persisted_balance,
+                    transactions_balance,
+                })
+            })
+            .collect();
+
+        for drift in self.account_drifts.iter() {
+            slog::warn!(self.logger, "Ledger drift detected: {:?}", drift);
+        }
+        let max_drift = self
+            .account_drifts
+            .iter()
+            .map(|drift| drift.max_drift())
+            .max()
+            .unwrap_or(Decimal::ZERO);
+        self.metrics.drifting_accounts.set(self.account_drifts.len() as f64);
+        self.metrics.max_ledger_drift.set(max_drift.to_f64().unwrap_or(0.0));
+
+        let exceeds_threshold = self
+            .account_drifts
+            .iter()
+            .any(|drift| drift.max_drift() > settings.max_drift);
+
+        let withdrawals_halted = settings.halt_withdrawals && exceeds_threshold;
+        if withdrawals_halted != self.withdrawals_halted {
+            if withdrawals_halted {
+                slog::error!(
+                    self.logger,
+                    "Ledger drift exceeds {}. Halting withdrawals.",
+                    settings.max_drift
+                );
+            } else {
+                slog::info!(self.logger, "Ledger drift resolved. Resuming withdrawals.");
+            }
+            self.withdrawals_halted = withdrawals_halted;
+        }
+    }
+
+    pub async fn process_msg<F: FnMut(Message, ServiceIdentity)>(&mut self, msg: Message, listener: &mut F) {
+        if !self.claim_idempotency_key(&msg, listener) {
+            return;
+        }
+
+        self.audit_context = AuditContext::from_message(&msg);
+
+        let conn_pool = self.conn_pool.clone();
+        let logger = self.logger.clone();
+        let mut idempotent_requests = std::mem::take(&mut self.idempotent_requests);
+        let mut refused_payments = Vec::new();
+
+        // Responses to requests with an idempotency key are stored so retries get the same response.
+        let mut recording_listener = |msg: Message, identity: ServiceIdentity| {
+            if let Some(req_id) = key_budgets::refused_payment(&msg) {
+                refused_payments.push(req_id);
+            }
+            if let (ServiceIdentity::Api, Some(req_id)) = (&identity, response_req_id(&msg)) {
+                if let Some((uid, key, _)) = idempotent_requests.remove(&req_id) {
+                    let stored = match (&conn_pool, serde_json::to_string(&msg)) {
+                        (Some(pool), Ok(response)) => match pool.get() {
+                            Ok(c) => IdempotencyKey::set_response(&c, uid as i32, key, response).is_ok(),
+                            Err(_) => false,
+                        },
+                        _ => false,
+                    };
+                    if !stored {
+                        slog::error!(logger, "Failed to store response of idempotent request {}", req_id);
+                    }
+                }
+            }
+            listener(msg, identity);
+        };
+
+        let span = utils::telemetry::request_span("bank", msg.trace_id());
+        self.handle_msg(msg, &mut recording_listener).instrument(span).await;
+
+        self.idempotent_requests = idempotent_requests;
+        for req_id in refused_payments {
+            self.release_key_spend(req_id);
+        }
+    }
+
+    async fn handle_msg<F: FnMut(Message, ServiceIdentity)>(&mut self, msg: Message, listener: &mut F) {
+        match msg {
+            Message::Dealer(msg) => match msg {
+                Dealer::Health(dealer_health) => {
+                    self.last_dealer_health_timestamp = utils::time::time_now();
+                    self.register_dealer_currencies(&dealer_health.currencies);
+                    self.available_currencies = dealer_health.available_currencies;
+                    if dealer_health.status == HealthStatus::Down || self.is_insurance_fund_depleted() {
+                        if dealer_health.status == HealthStatus::Down {
+                            slog::warn!(self.logger, "Dealer is disconnected from the exchange!");
+                        }
+                        self.available_currencies = Vec::new();
+                    }
+                    self.record_dealer_availability();
+                }
+                Dealer::MarketPrices(prices) => {
+                    self.last_market_prices = Some(prices.clone());
+                    let msg = Message::Dealer(Dealer::MarketPrices(prices));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Dealer::FundingIncome(income) => {
+                    if let Err(err) = self.book_funding_income(&income) {
+                        slog::error!(self.logger, "Failed to book funding income {:?}: {}", income, err);
+                    }
+                }
+                Dealer::HedgingPnl(pnl) => {
+                    if let Err(err) = self.book_hedging_pnl(&pnl) {
+                        slog::error!(self.logger, "Failed to book hedging pnl {:?}: {}", pnl, err);
+                    }
+                }
+                Dealer::BankStateRequest(_) => {
+                    let bank_state = self.get_bank_state();
+                    let msg = Message::Dealer(Dealer::BankState(bank_state));
+                    listener(msg, ServiceIdentity::Dealer);
+                    self.send_swap_orders(listener);
+                }
+                Dealer::SwapOrderFill(fill) => {
+                    self.book_swap_order_fill(fill, listener);
+                }
+                Dealer::PayInvoice(pay_invoice) => {
+                    slog::info!(self.logger, "Dealer wants to withdraw: {:?}", pay_invoice);
+                    if self.settlement_batching.is_some() {
+                        self.queue_dealer_invoice(pay_invoice, SettlementKind::Trading);
+                    } else {
+                        self.process_dealer_invoice(pay_invoice, false).await;
+                    }
+                }
+                Dealer::PayInsuranceInvoice(pay_invoice) => {
+                    if self.settlement_batching.is_some() {
+                        self.queue_dealer_invoice(pay_invoice, SettlementKind::Insurance);
+                    } else {
+                        self.process_dealer_invoice(pay_invoice, true).await;
+                    }
+                }
+                Dealer::CreateInvoiceRequest(mut req) => {
+                    slog::info!(self.logger, "Dealer wants to deposit: {:?}", req);
+                    req.memo = "KolliderSettlement".to_string();
+                    if self.settlement_batching.is_some() {
+                        self.queue_dealer_withdrawal(req, SettlementKind::Trading);
+                    } else {
+                        self.process_create_invoice_request(req, DEALER_UID, listener).await;
+                    }
+                }
+                Dealer::CreateInsuranceInvoiceRequest(mut req) => {
+                    slog::info!(self.logger, "Dealer requested insurance payment: {:?}", req);
+                    req.memo = "ExternalDeposit".to_string();
+                    if self.settlement_batching.is_some() {
+                        self.queue_dealer_withdrawal(req, SettlementKind::Insurance);
+                    } else {
+                        self.process_create_invoice_request(req, DEALER_UID, listener).await;
+                    }
+                }
+                Dealer::FiatDepositResponse(msg) => {
+                    // Fiat deposits happen in BTC and then get converted into a Fiat currency.
+                    slog::info!(self.logger, "Received fiat deposit response: {:?}", msg);
+
+                    self.ledger.release_pending(&msg.req_id);
+                    let routed_account_id = self.deposit_targets.remove(&msg.req_id);
+
+                    //TODO: Fiat deposit failed we should revert to just a BTC deposit as backup.
+                    if msg.error.is_some() {
+                        return;
+                    }
+
+                    // TODO: BTC deposit backup here.
+                    let rate = match msg.rate {
+                        Some(r) => r,
+                        None => return,
+                    };
+
+                    let (mut inbound_account, inbound_uid) = {
+                        let user_account = self
+                            .ledger
+                            .user_accounts
+                            .entry(msg.uid as u64)
+                            .or_insert_with(|| UserAccount::new(msg.uid as u64));
+
+                        let account = match routed_account_id.and_then(|id| user_account.accounts.get(&id).cloned()) {
+                            Some(account) => account,
+                            None => user_account.get_default_account(msg.currency, None),
+                        };
+
+                        (account, user_account.owner)
+                    };
+
+                    let mut dealer_fiat_account = self
+                        .ledger
+                        .dealer_accounts
+                        .get_default_account(msg.currency.clone(), Some(AccountType::Internal));
+
+                    let mut dealer_btc_account = self
+                        .ledger
+                        .dealer_accounts
+                        .get_default_account(Currency::BTC, Some(AccountType::Internal));
+
+                    let mut liabilities_btc_account = self
+                        .ledger
+                        .bank_liabilities
+                        .get_default_account(Currency::BTC, Some(AccountType::External));
+
+                    let value = msg.amount;
+
+                    let fiat_value = value.exchange(&rate).unwrap();
+
+                    let fees = if let Some(f) = msg.fees {
+                        f
+                    } else {
+                        Money::new(msg.currency, Some(dec!(0)))
+                    };
+
+                    // Adding BTC to dealer account.
+                    let outbound_txid = if let Ok(txid) = self.make_tx(
+                        &mut liabilities_btc_account,
+                        BANK_UID,
+                        &mut dealer_btc_account,
+                        DEALER_UID,
+                        value.clone(),
+                    ) {
+                        txid
+                    } else {
+                        return;
+                    };
+
+                    // Adding fiat to User Account from dealer.
+                    let inbound_txid = if let Ok(txid) = self.make_tx(
+                        &mut dealer_fiat_account,
+                        DEALER_UID,
+                        &mut inbound_account,
+                        inbound_uid,
+                        fiat_value.clone(),
+                    ) {
+                        txid
+                    } else {
+                        return;
+                    };
+
+                    self.insert_into_ledger(&inbound_uid, inbound_account.account_id, inbound_account.clone());
+
+                    self.ledger
+                        .bank_liabilities
+                        .accounts
+                        .insert(liabilities_btc_account.account_id, liabilities_btc_account.clone());
+
+                    self.ledger
+                        .dealer_accounts
+                        .accounts
+                        .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+
+                    self.ledger
+                        .dealer_accounts
+                        .accounts
+                        .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
+
+                    self.update_account(&inbound_account, inbound_uid);
+                    self.update_account(&liabilities_btc_account, BANK_UID);
+                    self.update_account(&dealer_btc_account, DEALER_UID);
+                    self.update_account(&dealer_fiat_account, DEALER_UID);
+
+                    let bank_state = self.get_bank_state();
+                    let msg = Message::Dealer(Dealer::BankState(bank_state));
+                    listener(msg, ServiceIdentity::Dealer);
+
+                    if self
+                        .make_summary_tx(
+                            &liabilities_btc_account,
+                            BANK_UID,
+                            &inbound_account,
+                            inbound_uid,
+                            value,
+                            Some(rate),
+                            None,
+                            Some(outbound_txid),
+                            Some(inbound_txid),
+                            None,
+                            Some(String::from("ExternalDeposit")),
+                        )
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                msg => self.store_unhandled_message(Message::Dealer(msg)),
+            },
+
+            Message::Deposit(msg) => {
+                slog::warn!(self.logger, "Received deposit: {:?}", msg);
+                // Deposit can only be triggered if someone external has payed an invoice generated by someone internal.
+                let conn = match &self.conn_pool {
+                    Some(conn) => conn,
+                    None => {
+                        slog::error!(self.logger, "No database provided.");
+                        self.retry_deposit(msg);
+                        return;
+                    }
+                };
+
+                let c = match conn.get() {
+                    Ok(psql_connection) => psql_connection,
+                    Err(_) => {
+                        slog::error!(self.logger, "Couldn't get psql connection.");
+                        self.retry_deposit(msg);
+                        return;
+                    }
+                };
+
+                // Check whether we know about this invoice.
+                let invoice = match Invoice::get_by_payment_request(&c, msg.payment_request.clone()) {
+                    Ok(invoice) => Some(invoice),
+                    Err(DieselError::NotFound) => None,
+                    Err(err) => {
+                        slog::error!(self.logger, "Failed to load invoice of deposit: {:?}", err);
+                        self.retry_deposit(msg);
+                        return;
+                    }
+                };
+                if let Some(invoice) = invoice {
+                    dbg!("getting deposit");
+                    dbg!(&invoice);
+                    match self.confirm_deposit(&c, &invoice).await {
+                        DepositCheck::Confirmed => {}
+                        DepositCheck::Rejected => return,
+                        DepositCheck::Unknown => {
+                            self.retry_deposit(msg);
+                            return;
+                        }
+                    }
+                    // Lnd redelivers settled invoices when the subscription resumes from a checkpoint.
+                    match Invoice::mark_settled(&c, &invoice.payment_request, utils::time::time_now() as i64) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            slog::info!(self.logger, "Deposit {} was already credited", invoice.payment_request);
+                            return;
+                        }
+                        Err(err) => {
+                            slog::error!(self.logger, "Failed to mark invoice settled: {:?}", err);
+                            self.retry_deposit(msg);
+                            return;
+                        }
+                    }
+                    let is_dealer_invoice = invoice.uid as UserId == DEALER_UID;
+
+                    dbg!(&is_dealer_invoice);
+
+                    if is_dealer_invoice {
+                        self.handle_dealer_deposit(msg).await;
+                        return;
+                    }
+
+                    let currency = match &invoice.currency {
+                        Some(c) => match Currency::from_str(c) {
+                            Ok(converted) => converted,
+                            Err(err) => {
+                                panic!("Failed to convert {} into a valid currency, reason: {:?}", c, err);
+                            }
+                        },
+                        None => Currency::BTC,
+                    };
+
+                    // Value of the depoist, asset invoices are credited with the asset amount they were created for.
+                    let value = match invoice.asset_units {
+                        Some(units) => match self.taproot_assets.as_ref().and_then(|tapd| tapd.asset(currency)) {
+                            Some(asset) => Money::new(currency, Some(asset.from_units(units as u64))),
+                            None => {
+                                slog::error!(
+                                    self.logger,
+                                    "Asset deposit {} of {} can't be credited, the asset isn't configured.",
+                                    invoice.payment_request,
+                                    currency
+                                );
+                                return;
+                            }
+                        },
+                        None => Money::from_msats(Decimal::new(invoice.value_msat, 0)),
+                    };
+
+                    // If user wants to deposit into a fiat account.
+                    let target_account_currency = match &invoice.target_account_currency {
+                        Some(c) => match Currency::from_str(c) {
+                            Ok(converted) => converted,
+                            Err(err) => {
+                                panic!("Failed to convert {} into a valid currency, reason: {:?}", c, err);
+                            }
+                        },
+                        None => Currency::BTC,
+                    };
+
+                    // If its not a fiat deposit we need to get the current rate.
+                    // Note a user could deposit with a sat specified invoice and then deposit into a fiat account.
+                    if !currency.is_asset_backed()
+                        && (currency != Currency::BTC
+                            || (currency == Currency::BTC && target_account_currency != Currency::BTC))
+                    {
+                        let c = if currency == Currency::BTC {
+                            target_account_currency
+                        } else {
+                            currency
+                        };
+                        let fiat_deposit_request = FiatDepositRequest {
+                            uid: invoice.uid as u64,
+                            currency: c,
+                            req_id: Uuid::new_v4(),
+                            amount: value.clone(),
+                        };
+                        // Until the dealer converted the deposit it shows up as pending on the BTC account it arrived in.
+                        let btc_account = self
+                            .ledger
+                            .user_accounts
+                            .entry(invoice.uid as u64)
+                            .or_insert_with(|| UserAccount::new(invoice.uid as u64))
+                            .get_default_account(Currency::BTC, None);
+                        self.ledger.add_pending(
+                            fiat_deposit_request.req_id,
+                            PendingFunds {
+                                uid: invoice.uid as u64,
+                                account_id: btc_account.account_id,
+                                amount: value.value,
+                            },
+                        );
+                        if let Some(account_id) = self
+                            .routed_deposit_account(&c, &invoice, fiat_deposit_request.currency)
+                            .or_else(|| self.invoice_deposit_account(&invoice, fiat_deposit_request.currency))
+                        {
+                            self.deposit_targets.insert(fiat_deposit_request.req_id, account_id);
+                        }
+                        let msg = Message::Dealer(Dealer::FiatDepositRequest(fiat_deposit_request));
+                        listener(msg, ServiceIdentity::Dealer);
+                        self.notify_invoice_settled(&invoice, value, listener);
+                        return;
+                    }
+
+                    let routed_account_id = self
+                        .routed_deposit_account(&c, &invoice, currency)
+                        .or_else(|| self.invoice_deposit_account(&invoice, currency));
+
+                    let (mut inbound_account, inbound_uid) = {
+                        let user_account = self
+                            .ledger
+                            .user_accounts
+                            .entry(invoice.uid as u64)
+                            .or_insert_with(|| UserAccount::new(invoice.uid as u64));
+
+                        let account = match routed_account_id.and_then(|id| user_account.accounts.get(&id).cloned()) {
+                            Some(account) => account,
+                            None => user_account.get_default_account(currency, None),
+                        };
+
+                        (account, user_account.owner)
+                    };
+
+                    // At this point only BTC and asset deposits are possible.
+                    let mut liability_account = self
+                        .ledger
+                        .bank_liabilities
+                        .get_default_account(currency, Some(AccountType::External));
+
+                    // Making the transaction and inserting it into the DB.
+                    let txid = if let Ok(txid) = self.make_tx(
+                        &mut liability_account,
+                        BANK_UID,
+                        &mut inbound_account,
+                        inbound_uid,
+                        value.clone(),
+                    ) {
+                        txid
+                    } else {
+                        return;
+                    };
+
+                    // Safe to unwrap as we created this account above.
+                    self.insert_into_ledger(&inbound_uid, inbound_account.account_id, inbound_account.clone());
+                    // Updating cache of external account.
+                    self.ledger
+                        .bank_liabilities
+                        .accounts
+                        .insert(liability_account.account_id, liability_account.clone());
+
+                    // Updating db of internal account.
+                    self.update_account(&inbound_account, inbound_uid);
+
+                    // Updating db of internal account.
+                    self.update_account(&liability_account, BANK_UID);
+
+                    if self
+                        .make_summary_tx(
+                            &liability_account,
+                            BANK_UID,
+                            &inbound_account,
+                            inbound_uid,
+                            value.clone(),
+                            None,
+                            None,
+                            Some(txid.clone()),
+                            Some(txid),
+                            None,
+                            Some(String::from("ExternalDeposit")),
+                        )
+                        .is_err()
+                    {
+                        return;
+                    }
+
+                    self.notify_invoice_settled(&invoice, value, listener);
+                }
+            }
+            Message::Api(msg) => match msg {
+                Api::InvoiceRequest(msg) => {
+                    // Rejected texts are kept out of the logs as well.
+                    let metadata_values = msg.metadata_fields.iter().flat_map(|fields| fields.values());
+                    let texts = std::iter::once(&msg.meta)
+                        .chain(msg.metadata.iter())
+                        .chain(metadata_values)
+                        .chain(msg.comment.iter())
+                        .map(|text| text.as_str());
+                    if let Err(rejection) = self.content_filter.check_all(texts) {
+                        slog::warn!(
+                            self.logger,
+                            "Rejected invoice request {} of user {}: {:?}",
+                            msg.req_id,
+                            msg.uid,
+                            rejection
+                        );
+                        let invoice_response = InvoiceResponse {
+                            amount: msg.amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            meta: String::new(),
+                            metadata: None,
+                            rate: None,
+                            payment_request: None,
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: None,
+                            error: Some(InvoiceResponseError::ContentRejected),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    slog::warn!(self.logger, "Received invoice request: {:?}", msg);
+
+                    if !self.check_deposit_request_rate_limit(msg.uid) {
+                        let invoice_response = InvoiceResponse {
+                            amount: msg.amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            meta: msg.meta,
+                            metadata: msg.metadata.clone(),
+                            rate: None,
+                            payment_request: None,
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: None,
+                            error: Some(InvoiceResponseError::RequestLimitExceeded),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if self.is_insurance_fund_depleted() {
+                        slog::warn!(self.logger, "Insurance is depleted Deposit request Failed!");
+                        let invoice_response = InvoiceResponse {
+                            amount: msg.amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            rate: None,
+                            meta: msg.meta.clone(),
+                            metadata: msg.metadata.clone(),
+                            payment_request: None,
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: None,
+                            error: Some(InvoiceResponseError::InvoicingSuspended),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    let user_account = self
+                        .ledger
+                        .user_accounts
+                        .entry(msg.uid)
+                        .or_insert_with(|| UserAccount::new(msg.uid));
+
+                    if self.withdrawal_only {
+                        slog::info!(self.logger, "Bank is in withdrawal only mode");
+                        let invoice_response = InvoiceResponse {
+                            amount: msg.amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            meta: msg.meta,
+                            metadata: msg.metadata.clone(),
+                            rate: None,
+                            payment_request: None,
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: None,
+                            error: Some(InvoiceResponseError::WithdrawalOnly),
+                            fees: None,
+                        };
+
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    let conn = match &self.conn_pool {
+                        Some(conn) => conn,
+                        None => {
+                            slog::error!(self.logger, "No database provided.");
+                            let invoice_response = InvoiceResponse {
+                                amount: msg.amount,
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: None,
+                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    let c = match conn.get() {
+                        Ok(psql_connection) => psql_connection,
+                        Err(_) => {
+                            slog::error!(self.logger, "Couldn't get psql connection.");
+                            let invoice_response = InvoiceResponse {
+                                amount: msg.amount,
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: None,
+                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    let amount = msg.amount.clone();
+                    let currency = msg.currency;
+
+                    let mut target_account = Account::new(msg.currency, AccountType::Internal, AccountClass::Cash);
+
+                    if let Some(account_id) = msg.account_id {
+                        if let Some(acc) = user_account.accounts.get(&account_id).filter(|acc| !acc.archived) {
+                            target_account = acc.clone();
+                        } else {
+                            let invoice_response = InvoiceResponse {
+                                amount,
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: Some(target_account.account_id),
+                                error: Some(InvoiceResponseError::AccountDoesNotExist),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    } else {
+                        // If user does not specify an account_id we select or create one for him.
+                        let account = user_account.get_default_account(msg.currency, None);
+                        target_account = account;
+                    }
+
+                    let deposit_limit = user_account
+                        .deposit_limits
+                        .get(&currency)
+                        .or_else(|| self.deposit_limits.get(&currency))
+                        .unwrap_or_else(|| panic!("Failed to get deposit limits for {}", currency));
+                    // Check whether deposit limit is exceeded.
+                    if target_account.balance + amount.value > *deposit_limit {
+                        let invoice_response = InvoiceResponse {
+                            amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            rate: None,
+                            meta: msg.meta.clone(),
+                            metadata: msg.metadata.clone(),
+                            payment_request: None,
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: Some(target_account.account_id),
+                            error: Some(InvoiceResponseError::DepositLimitExceeded),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // Asset backed currencies are received as the asset, there is nothing to convert.
+                    if currency.is_asset_backed() {
+                        let account_id = target_account.account_id;
+                        let invoice_response = self.create_asset_invoice(&c, msg, account_id).await;
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // If user wants to deposit another currency we have to go through the dealer.
+                    if currency != Currency::BTC {
+                        let msg = Message::Api(Api::InvoiceRequest(msg));
+                        listener(msg, ServiceIdentity::Dealer);
+                        return;
+                    }
+
+                    let amount_in_sats = amount.try_sats().unwrap().to_u64().unwrap_or_else(|| {
+                        panic!(
+                            "Failed to convert  decimal amount in BTC: {:?} to u64 amount in SATs",
+                            amount
+                        )
+                    });
+
+                    if let Some(max_receivable_sats) = self.exceeded_inbound_capacity(amount_in_sats) {
+                        let invoice_response = InvoiceResponse {
+                            amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            rate: None,
+                            meta: msg.meta.clone(),
+                            metadata: msg.metadata.clone(),
+                            payment_request: None,
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: Some(target_account.account_id),
+                            error: Some(InvoiceResponseError::InboundCapacityExceeded { max_receivable_sats }),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    dbg!("Creating invoice");
+
+                    if let Ok(mut invoice) = self
+                        .lnd_connector
+                        .create_invoice(
+                            amount_in_sats,
+                            msg.meta.clone(),
+                            msg.uid,
+                            target_account.account_id,
+                            msg.metadata.clone(),
+                        )
+                        .await
+                    {
+                        self.metrics.invoices_created.inc();
+                        dbg!(&invoice);
+                        invoice.currency = Some(msg.currency.to_string());
+                        invoice.metadata_fields = msg
+                            .metadata_fields
+                            .as_ref()
+                            .and_then(|fields| serde_json::to_string(fields).ok());
+                        invoice.comment = msg.comment.clone();
+                        invoice.payer_data = msg.payer_data.clone();
+                        if let Some(target_account_currency) = msg.target_account_currency {
+                            invoice.target_account_currency = Some(target_account_currency.to_string());
+                        } else {
+                            invoice.target_account_currency = None
+                        }
+                        if let Err(_err) = invoice.insert(&c) {
+                            dbg!(&_err);
+                            slog::error!(self.logger, "Error inserting invoice.");
+                            let invoice_response = InvoiceResponse {
+                                amount,
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                payment_request: None,
+                                metadata: msg.metadata.clone(),
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: Some(target_account.account_id),
+                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+
+                        let invoice_response = InvoiceResponse {
+                            amount,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            meta: msg.meta,
+                            metadata: msg.metadata.clone(),
+                            rate: None,
+                            payment_request: Some(invoice.payment_request),
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: Some(target_account.account_id),
+                            error: None,
+                            fees: None,
+                        };
+
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api)
+                    }
+                }
+                Api::InvoiceResponse(ref msg) => {
+                    let rate = match &msg.rate {
+                        Some(r) => r,
+                        None => {
+                            let mut m = msg.clone();
+                            m.error = Some(InvoiceResponseError::RateNotAvailable);
+                            let msg = Message::Api(Api::InvoiceResponse(m));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+                    let amount_in_btc = msg.amount.div(rate.value);
+                    let money = Money::from_btc(amount_in_btc);
+                    let amount_in_sats = money
+                        .try_sats()
                         .unwrap()
                         .round_dp_with_strategy(0, RoundingStrategy::AwayFromZero)
                         .to_u64()
                         .unwrap_or_else(|| panic!());
 
-                    let conn = match &self.conn_pool {
-                        Some(conn) => conn,
-                        None => {
-                            slog::error!(self.logger, "No database provided.");
-                            let invoice_response = InvoiceResponse {
-                                amount: msg.amount.clone(),
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: None,
-                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+                    if let Some(max_receivable_sats) = self.exceeded_inbound_capacity(amount_in_sats) {
+                        let mut m = msg.clone();
+                        m.error = Some(InvoiceResponseError::InboundCapacityExceeded { max_receivable_sats });
+                        let msg = Message::Api(Api::InvoiceResponse(m));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    let conn = match &self.conn_pool {
+                        Some(conn) => conn,
+                        None => {
+                            slog::error!(self.logger, "No database provided.");
+                            let invoice_response = InvoiceResponse {
+                                amount: msg.amount.clone(),
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: None,
+                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    let c = match conn.get() {
+                        Ok(psql_connection) => psql_connection,
+                        Err(_) => {
+                            slog::error!(self.logger, "Couldn't get psql connection.");
+                            let invoice_response = InvoiceResponse {
+                                amount: msg.amount.clone(),
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: None,
+                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    let user_account = self
+                        .ledger
+                        .user_accounts
+                        .entry(msg.uid)
+                        .or_insert_with(|| UserAccount::new(msg.uid));
+
+                    let mut target_account = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+
+                    if let Some(account_id) = msg.account_id {
+                        if let Some(acc) = user_account.accounts.get(&account_id).filter(|acc| !acc.archived) {
+                            target_account = acc.clone();
+                        } else {
+                            let invoice_response = InvoiceResponse {
+                                amount: msg.amount.clone(),
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: Some(target_account.account_id),
+                                error: Some(InvoiceResponseError::AccountDoesNotExist),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    } else {
+                        // If user does not specify an account_id we select or create one for him.
+                        let account = user_account.get_default_account(Currency::BTC, None);
+                        target_account = account;
+                    }
+
+                    let currency = msg.currency;
+                    let deposit_limit = user_account
+                        .deposit_limits
+                        .get(&currency)
+                        .or_else(|| self.deposit_limits.get(&currency))
+                        .unwrap_or_else(|| panic!("Failed to get deposit limit for {}", currency));
+
+                    // Check whether deposit limit is exceeded.
+                    if target_account.balance + msg.amount.value > *deposit_limit {
+                        let invoice_response = InvoiceResponse {
+                            amount: money,
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            rate: None,
+                            meta: msg.meta.clone(),
+                            metadata: msg.metadata.clone(),
+                            payment_request: None,
+                            currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: Some(target_account.account_id),
+                            error: Some(InvoiceResponseError::DepositLimitExceeded),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if let Ok(mut invoice) = self
+                        .lnd_connector
+                        .create_invoice(
+                            amount_in_sats,
+                            msg.meta.clone(),
+                            msg.uid,
+                            target_account.account_id,
+                            msg.metadata.clone(),
+                        )
+                        .await
+                    {
+                        self.metrics.invoices_created.inc();
+                        invoice.currency = Some(msg.currency.to_string());
+                        invoice.metadata_fields = msg
+                            .metadata_fields
+                            .as_ref()
+                            .and_then(|fields| serde_json::to_string(fields).ok());
+                        invoice.comment = msg.comment.clone();
+                        invoice.payer_data = msg.payer_data.clone();
+                        if let Err(_err) = invoice.insert(&c) {
+                            slog::error!(self.logger, "Error inserting invoice.");
+                            let invoice_response = InvoiceResponse {
+                                amount: msg.amount.clone(),
+                                req_id: msg.req_id,
+                                uid: msg.uid,
+                                rate: None,
+                                meta: msg.meta.clone(),
+                                metadata: msg.metadata.clone(),
+                                payment_request: None,
+                                currency: msg.currency,
+                                target_account_currency: msg.target_account_currency,
+                                account_id: Some(target_account.account_id),
+                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
+                                fees: None,
+                            };
+                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+
+                        let invoice_response = InvoiceResponse {
+                            amount: msg.amount.clone(),
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            meta: msg.meta.clone(),
+                            metadata: msg.metadata.clone(),
+                            rate: msg.rate.clone(),
+                            payment_request: Some(invoice.payment_request),
+                            currency: msg.currency,
+                            target_account_currency: msg.target_account_currency,
+                            account_id: Some(target_account.account_id),
+                            error: None,
+                            fees: msg.fees.clone(),
+                        };
+
+                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
+                        listener(msg, ServiceIdentity::Api)
+                    }
+                }
+                Api::PaymentRequest(mut msg) => {
+                    slog::warn!(self.logger, "Received withdrawal request: {:?}", msg);
+
+                    let uid = msg.uid;
+
+                    if self.withdrawals_halted {
+                        let payment_response = PaymentResponse::error(
+                            PaymentResponseError::WithdrawalsHalted,
+                            msg.req_id,
+                            uid,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if !self.check_access_policy(uid, &msg.origin) {
+                        slog::warn!(
+                            self.logger,
+                            "Withdrawal of user {} blocked by access policy: {:?}",
+                            uid,
+                            msg.origin
+                        );
+                        let payment_response = PaymentResponse::error(
+                            PaymentResponseError::OriginNotAllowed,
+                            msg.req_id,
+                            uid,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if !self.check_withdrawal_request_rate_limit(uid) {
+                        let payment_response = PaymentResponse::error(
+                            PaymentResponseError::RequestLimitExceeded,
+                            msg.req_id,
+                            uid,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // Members of a shared account pay from it on behalf of its owner.
+                    match self.authorize_account_spend(&msg) {
+                        Ok(payer) if payer != uid => {
+                            slog::info!(
+                                self.logger,
+                                "User {} pays {} from shared account of user {}",
+                                uid,
+                                msg.req_id,
+                                payer
+                            );
+                            msg.uid = payer;
+                        }
+                        Ok(_) => {}
+                        Err(error) => {
+                            let payment_response =
+                                PaymentResponse::error(error, msg.req_id, uid, msg.payment_request, msg.currency, None);
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+                    // 2FA codes are checked against the member making the payment, not the owner of the account.
+                    let requester = uid;
+                    let uid = msg.uid;
+
+                    if self.is_frozen(requester) || self.is_frozen(uid) {
+                        let payment_response = PaymentResponse::error(
+                            PaymentResponseError::UserFrozen,
+                            msg.req_id,
+                            requester,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    let mut outbound_account = {
+                        let user_account = match self.ledger.user_accounts.get_mut(&uid) {
+                            Some(ua) => ua,
+                            None => {
+                                let payment_response = PaymentResponse::error(
+                                    PaymentResponseError::UserAccountNotFound,
+                                    msg.req_id,
+                                    uid,
+                                    msg.payment_request,
+                                    msg.currency,
+                                    None,
+                                );
+                                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                        };
+                        match user_account.get_account_or_default(msg.account_id, msg.currency) {
+                            Some(account) => account,
+                            None => {
+                                let payment_response = PaymentResponse::error(
+                                    PaymentResponseError::AccountDoesNotExist,
+                                    msg.req_id,
+                                    uid,
+                                    msg.payment_request,
+                                    msg.currency,
+                                    None,
+                                );
+                                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                        }
+                    };
+
+                    if self.frozen_accounts.contains_key(&outbound_account.account_id) {
+                        let payment_response = PaymentResponse::error(
+                            PaymentResponseError::AccountFrozen,
+                            msg.req_id,
+                            requester,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if msg.rate.is_none() && !msg.requoted {
+                        if let Err(error) = self.spend_key_budget(&msg, requester) {
+                            slog::warn!(
+                                self.logger,
+                                "Withdrawal {} of user {} refused by the budget of key {:?}: {:?}",
+                                msg.req_id,
+                                requester,
+                                msg.origin.as_ref().and_then(|origin| origin.key_id),
+                                error
+                            );
+                            let payment_response = PaymentResponse::error(
+                                error,
+                                msg.req_id,
+                                requester,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+
+                    // Payments from a vault wait out the delay first. Released ones come back through the loopback
+                    // before they are quoted.
+                    if outbound_account.account_class == AccountClass::Vault
+                        && msg.rate.is_none()
+                        && !msg.requoted
+                        && !self.released_vault_withdrawals.remove(&msg.req_id)
+                    {
+                        let error = match self.delay_vault_withdrawal(&msg, &outbound_account) {
+                            Ok(release_at) => {
+                                slog::info!(
+                                    self.logger,
+                                    "Withdrawal {} from vault {} is delayed until {}",
+                                    msg.req_id,
+                                    outbound_account.account_id,
+                                    release_at
+                                );
+                                PaymentResponseError::WithdrawalDelayed
+                            }
+                            Err(error) => error,
+                        };
+                        let payment_response = PaymentResponse::error(
+                            error,
+                            msg.req_id,
+                            requester,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if self.is_insurance_fund_depleted() {
+                        slog::warn!(
+                            self.logger,
+                            "Insurance fund is depleted. Rejecting Lnurl Withdrawal Request."
+                        );
+                    }
+
+                    if let Some(ref amount) = msg.amount {
+                        if amount.value <= dec!(0) {
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::InvalidAmount,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+
+                    // If user specified a username or account then we attempt to make an internal transaction.
+                    if msg.receipient.is_some() || msg.target_account_id.is_some() {
+                        // Transfers paid from fiat come back here once quoted, the code is only checked once.
+                        let needs_totp_check = msg.rate.is_none() && !msg.requoted;
+                        if let Some(amount) = msg.amount.as_ref().filter(|_| needs_totp_check) {
+                            let thresholds = &self.totp_settings.payment_thresholds;
+                            if !self.check_totp(requester, amount, thresholds, msg.totp_code.as_deref()) {
+                                let payment_response = PaymentResponse::error(
+                                    PaymentResponseError::InvalidTotpCode,
+                                    msg.req_id,
+                                    uid,
+                                    msg.payment_request,
+                                    msg.currency,
+                                    None,
+                                );
+                                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                        }
+                        self.make_internal_tx(msg, None, listener);
+                        return;
+                    }
+
+                    // Without an invoice the amount is pushed to the destination node.
+                    if msg.payment_request.is_none() {
+                        if let Some(destination) = msg.destination.clone() {
+                            self.make_keysend_payment(msg, requester, outbound_account, destination, listener);
+                            return;
+                        }
+                    }
+
+                    let conn = match &self.conn_pool {
+                        Some(conn) => conn,
+                        None => {
+                            slog::error!(self.logger, "No database provided.");
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::DatabaseConnectionFailed,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    let psql_connection = match conn.get() {
+                        Ok(psql_connection) => psql_connection,
+                        Err(_) => {
+                            slog::error!(self.logger, "Couldn't get psql connection.");
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::DatabaseConnectionFailed,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    let payment_request = msg
+                        .clone()
+                        .payment_request
+                        .unwrap_or_else(|| panic!("Payment request not specified in the message: {:?}", msg));
+
+                    let decoded = match payment_request.parse::<lightning_invoice::Invoice>() {
+                        Ok(d) => d,
+                        Err(_) => {
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::InvalidInvoice,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+
+                    // If the user supplied a zero-amount invoice, return an error
+                    let (invoice_amount_millisats, invoice_amount_sats) =
+                        if let Some(millisats) = decoded.amount_milli_satoshis() {
+                            (millisats, millisats / 1000)
+                        } else {
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::ZeroAmountInvoice,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        };
+
+                    // Amount in sats that we're paying.
+                    let amount_in_sats = Decimal::new(invoice_amount_sats as i64, 0);
+                    // Amount in btc that we're paying, kept at msat precision.
+                    let amount_in_btc = Money::from_msats(Decimal::new(invoice_amount_millisats as i64, 0));
+
+                    msg.amount = Some(amount_in_btc.clone());
+
+                    let within_velocity_limits = match self.check_withdrawal_velocity(
+                        &psql_connection,
+                        uid,
+                        &payment_request,
+                        invoice_amount_sats,
+                    ) {
+                        Ok(within_velocity_limits) => within_velocity_limits,
+                        Err(err) => {
+                            slog::error!(
+                                self.logger,
+                                "Failed to check withdrawal velocity of user {}: {:?}",
+                                uid,
+                                err
+                            );
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::DatabaseConnectionFailed,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+                    if !within_velocity_limits {
+                        slog::warn!(
+                            self.logger,
+                            "Withdrawal {} exceeds velocity limits of user {}",
+                            msg.req_id,
+                            uid
+                        );
+                        let payment_response = PaymentResponse::error(
+                            PaymentResponseError::VelocityLimitExceeded,
+                            msg.req_id,
+                            uid,
+                            msg.payment_request,
+                            msg.currency,
+                            None,
+                        );
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // Withdrawals above the threshold wait for an admin. Approved ones come back through the loopback
+                    // before they are quoted.
+                    let is_first_pass = msg.rate.is_none() && !msg.requoted;
+                    let is_approved = is_first_pass && self.approved_withdrawals.remove(&msg.req_id);
+
+                    // The code is checked before the withdrawal is held, cash outs had theirs checked already.
+                    if is_first_pass && !is_approved && !self.totp_verified.remove(&msg.req_id) {
+                        let thresholds = &self.totp_settings.payment_thresholds;
+                        if !self.check_totp(requester, &amount_in_btc, thresholds, msg.totp_code.as_deref()) {
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::InvalidTotpCode,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+
+                    if is_first_pass && !is_approved {
+                        if let Some(error) = self.hold_for_approval(
+                            &psql_connection,
+                            &msg,
+                            invoice_amount_sats,
+                            invoice_amount_millisats,
+                        ) {
+                            let payment_response =
+                                PaymentResponse::error(error, msg.req_id, uid, msg.payment_request, msg.currency, None);
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+
+                    // Asset backed currencies are paid with the asset at the rate of the asset channel peer.
+                    if msg.currency.is_asset_backed() {
+                        let payment_response = self
+                            .pay_with_asset(
+                                &psql_connection,
+                                &msg,
+                                outbound_account,
+                                payment_request,
+                                invoice_amount_millisats,
+                            )
+                            .await;
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // If payed from a fiat account we have to get a quote first.
+                    if msg.currency != Currency::BTC && msg.rate.is_none() {
+                        let msg = Message::Api(Api::PaymentRequest(msg));
+                        listener(msg, ServiceIdentity::Dealer);
+                        return;
+                    }
+
+                    // A rate that went stale on its way through the dealer is re-quoted once.
+                    if let Some(rate) = &msg.rate {
+                        if msg.currency != Currency::BTC && rate.is_stale(utils::time::time_now()) {
+                            if msg.requoted {
+                                slog::info!(self.logger, "Rate is stale after re-quote. Rejecting payment.");
+                                let payment_response = PaymentResponse::error(
+                                    PaymentResponseError::RateExpired,
+                                    msg.req_id,
+                                    uid,
+                                    msg.payment_request,
+                                    msg.currency,
+                                    None,
+                                );
+                                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                            msg.rate = None;
+                            msg.fees = None;
+                            msg.requoted = true;
+                            let msg = Message::Api(Api::PaymentRequest(msg));
+                            listener(msg, ServiceIdentity::Dealer);
+                            return;
+                        }
+                    }
+
+                    if msg.currency == Currency::BTC {
+                        msg.rate = Some(Rate {
+                            base: Currency::BTC,
+                            quote: Currency::BTC,
+                            value: dec!(1),
+                            ..Default::default()
+                        });
+                    }
+
+                    let invoice = if let Ok(invoice) =
+                        models::invoices::Invoice::get_by_payment_request(&psql_connection, payment_request.clone())
+                    {
+                        invoice
+                    } else {
+                        let invoice = models::invoices::Invoice {
+                            payment_request: payment_request.clone(),
+                            rhash: decoded.payment_hash().to_string(),
+                            payment_hash: decoded.payment_hash().to_string(),
+                            created_at: utils::time::time_now() as i64,
+                            value: invoice_amount_sats as i64,
+                            value_msat: invoice_amount_millisats as i64,
+                            expiry: decoded.expiry_time().as_secs() as i64,
+                            settled: false,
+                            add_index: -1,
+                            settled_date: 0,
+                            uid: uid as i32,
+                            account_id: Uuid::default().to_string(),
+                            owner: None,
+                            fees: None,
+                            incoming: false,
+                            currency: Some(msg.currency.to_string()),
+                            target_account_currency: None,
+                            reference: None,
+                            expired: false,
+                            metadata_fields: None,
+                            reference_hash: None,
+                            metadata_fields_hash: None,
+                            descriptions_hashed_at: None,
+                            asset_units: None,
+                            comment: None,
+                            payer_data: None,
+                            description_hash: None,
+                        };
+                        invoice
+                            .insert(&psql_connection)
+                            .expect("Failed to insert psql connection");
+                        invoice
+                    };
+
+                    dbg!(&invoice);
+
+                    let amount = amount_in_btc.clone();
+
+                    let fees = if let Some(f) = msg.fees.clone() {
+                        f
+                    } else {
+                        Money::from_sats(dec!(0))
+                    };
+
+                    let rate = if let Some(r) = msg.rate.clone() {
+                        r
+                    } else {
+                        Rate {
+                            base: Currency::BTC,
+                            quote: Currency::BTC,
+                            value: dec!(1),
+                            ..Default::default()
+                        }
+                    };
+
+                    // Preparing a generic response.
+                    let mut payment_response = PaymentResponse {
+                        amount: Some(amount),
+                        payment_hash: Uuid::new_v4().to_string(),
+                        req_id: msg.req_id,
+                        uid,
+                        success: false,
+                        payment_request: Some(payment_request.clone()),
+                        currency: msg.currency,
+                        fees: Some(fees),
+                        rate: Some(rate.clone()),
+                        error: None,
+                        preimage: None,
+                    };
+
+                    if let Some(owner) = invoice.owner {
+                        if uid == owner as u64 {
+                            slog::info!(self.logger, "User tried to make self payment. Not allowed.");
+                            payment_response.error = Some(PaymentResponseError::SelfPayment);
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+
+                    // If invoice was already paid we reject this the payment request. AMP invoices can be paid
+                    // repeatedly.
+                    if invoice.settled
+                        && !(self.lnd_connector_settings.amp_enabled
+                            && self.lnd_connector.supports_amp(&payment_request).await)
+                    {
+                        slog::info!(self.logger, "Invoice is already settled.");
+                        payment_response.error = Some(PaymentResponseError::InvoiceAlreadyPaid);
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // Payments the channels can't carry would only fail after burning probes and retries.
+                    if invoice.owner.is_none() {
+                        if let Some(max_payable_sats) = self.exceeded_outbound_capacity(invoice_amount_sats) {
+                            payment_response.error =
+                                Some(PaymentResponseError::TemporaryLiquidityShortage { max_payable_sats });
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    }
+
+                    // We could be dealing with an internal transaction in which case we cannot borrow two accounts
+                    // as mutable. Hence we have to work with local scoping. We first deal with the payer.
+
+                    let outbound_balance = outbound_account.balance;
+
+                    // Worst case amount user will have to pay for this transaction in Bitcoin.
+                    let max_fee_in_btc = self.fee_budget(amount_in_btc.value);
+                    let fee_budget_rate = if amount_in_btc.value > Decimal::ZERO {
+                        max_fee_in_btc / amount_in_btc.value
+                    } else {
+                        self.ln_network_fee_margin
+                    };
+
+                    let estimated_fee = match self.probe_fee_msat(&payment_request, fee_budget_rate).await {
+                        Ok(Some(fee_msat)) => Decimal::new(fee_msat, 0) / MSATS_IN_BITCOIN,
+                        _ => max_fee_in_btc,
+                    };
+
+                    let estimated_fee = Money::from_btc(estimated_fee);
+
+                    let outbound_amount_in_btc_plus_max_fees =
+                        Money::from_btc(amount_in_btc.value + estimated_fee.value);
+
+                    // Worst case amount user will have to pay for this transaction in outbound Currency.
+                    let outbound_amount_in_outbound_currency_plus_max_fee =
+                        outbound_amount_in_btc_plus_max_fees.exchange(&rate).unwrap();
+
+                    // Checking whether user has enough funds on their outbound currency account.
+                    dbg!(&outbound_balance);
+                    dbg!(&outbound_amount_in_outbound_currency_plus_max_fee);
+                    if outbound_balance < outbound_amount_in_outbound_currency_plus_max_fee.value {
+                        payment_response.error = Some(PaymentResponseError::InsufficientFundsForFees);
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    // If invoice is not owned by any user (its leaving the platform).
+                    if invoice.owner.is_none() {
+                        let amount_in_outbound_currency = amount_in_btc.exchange(&rate).unwrap();
+                        let service_fee = self.operation_fee(
+                            uid,
+                            msg.currency,
+                            FeeOperation::External,
+                            amount_in_outbound_currency.value,
+                        );
+                        if outbound_balance
+                            < outbound_amount_in_outbound_currency_plus_max_fee.value + service_fee.value
+                        {
+                            payment_response.error = Some(PaymentResponseError::InsufficientFundsForFees);
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+
+                        // We need to debit amount a user is trying to send before sending the payment so he cannot
+                        // double spend.
+                        // We always going to be sending funds to an external BTC acount.
+                        let mut bank_liability_account = self
+                            .ledger
+                            .bank_liabilities
+                            .get_default_account(Currency::BTC, Some(AccountType::External));
+
+                        // If its a fiat payment then we need to transact with the dealer.
+                        if msg.currency != Currency::BTC {
+                            let mut dealer_fiat_account = self
+                                .ledger
+                                .dealer_accounts
+                                .get_default_account(msg.currency, Some(AccountType::Internal));
+
+                            // User account to dealer account.
+                            let outbound_txid = if let Ok(txid) = self.make_tx(
+                                &mut outbound_account,
+                                uid,
+                                &mut dealer_fiat_account,
+                                DEALER_UID,
+                                outbound_amount_in_outbound_currency_plus_max_fee.clone(),
+                            ) {
+                                txid
+                            } else {
+                                slog::error!(self.logger, "Error making transaction.");
+                                return;
+                            };
+
+                            let mut dealer_btc_account = self
+                                .ledger
+                                .dealer_accounts
+                                .get_default_account(Currency::BTC, Some(AccountType::Internal));
+
+                            let inbound_txid = if let Ok(txid) = self.make_tx(
+                                &mut dealer_btc_account,
+                                DEALER_UID,
+                                &mut bank_liability_account,
+                                BANK_UID,
+                                outbound_amount_in_btc_plus_max_fees.clone(),
+                            ) {
+                                txid
+                            } else {
+                                slog::error!(self.logger, "Error making transaction.");
+                                return;
+                            };
+
+                            self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+
+                            self.ledger
+                                .bank_liabilities
+                                .accounts
+                                .insert(bank_liability_account.account_id, bank_liability_account.clone());
+                            self.ledger
+                                .dealer_accounts
+                                .accounts
+                                .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
+                            self.ledger
+                                .dealer_accounts
+                                .accounts
+                                .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+
+                            self.update_account(&outbound_account, msg.uid);
+                            self.update_account(&bank_liability_account, BANK_UID);
+
+                            self.update_account(&dealer_btc_account, DEALER_UID);
+                            self.update_account(&dealer_fiat_account, DEALER_UID);
+
+                            if self
+                                .make_summary_tx(
+                                    &outbound_account,
+                                    uid,
+                                    &bank_liability_account,
+                                    BANK_UID,
+                                    outbound_amount_in_outbound_currency_plus_max_fee.clone(),
+                                    Some(rate.clone()),
+                                    None,
+                                    Some(outbound_txid),
+                                    Some(inbound_txid),
+                                    None,
+                                    Some(String::from("ExternalPayment")),
+                                )
+                                .is_err()
+                            {
+                                return;
+                            }
+                        } else {
+                            let txid = if let Ok(txid) = self.make_tx(
+                                &mut outbound_account,
+                                uid,
+                                &mut bank_liability_account,
+                                BANK_UID,
+                                outbound_amount_in_outbound_currency_plus_max_fee.clone(),
+                            ) {
+                                txid
+                            } else {
+                                payment_response.error = Some(PaymentResponseError::TransactionFailed);
+                                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            };
+
+                            self.ledger
+                                .bank_liabilities
+                                .accounts
+                                .insert(bank_liability_account.account_id, bank_liability_account.clone());
+
+                            self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+
+                            self.update_account(&outbound_account, msg.uid);
+                            self.update_account(&bank_liability_account, BANK_UID);
+
+                            self.make_summary_tx(
+                                &outbound_account,
+                                uid,
+                                &bank_liability_account,
+                                BANK_UID,
+                                outbound_amount_in_btc_plus_max_fees.clone(),
+                                None,
+                                None,
+                                Some(txid.clone()),
+                                Some(txid),
+                                None,
+                                Some(String::from("ExternalPayment")),
+                            );
+                        }
+
+                        // Collected upfront like the fee reserve and refunded if the payment fails.
+                        if service_fee.value > Decimal::ZERO {
+                            if let Err(err) =
+                                self.collect_fee(&mut outbound_account, uid, FeeOperation::External, &service_fee)
+                            {
+                                slog::error!(self.logger, "Failed to collect fee of payment {}: {}", msg.req_id, err);
+                            }
+                            self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+                            self.update_account(&outbound_account, msg.uid);
+                        }
+                        self.fee_engine.record_volume(
+                            uid,
+                            msg.currency,
+                            amount_in_outbound_currency.value,
+                            utils::time::time_now(),
+                        );
+
+                        payment_response.success = false;
+                        // The budget lnd may spend, the payment is charged the routing fee it actually paid.
+                        payment_response.fees = Some(Money::from_btc(max_fee_in_btc));
+
+                        // The debited amount stays visible as pending until the payment completes.
+                        self.ledger.add_pending(
+                            payment_response.req_id,
+                            PendingFunds {
+                                uid,
+                                account_id: outbound_account.account_id,
+                                amount: -outbound_amount_in_outbound_currency_plus_max_fee.value,
+                            },
+                        );
+
+                        let pending = PaymentResult {
+                            uid,
+                            currency: msg.currency,
+                            rate,
+                            is_success: false,
+                            amount: outbound_amount_in_btc_plus_max_fees,
+                            payment_response,
+                            error: None,
+                            max_fee_in_sats: estimated_fee.try_sats().unwrap(),
+                            attempts: 0,
+                            is_retryable: false,
+                            service_fee: Some(service_fee),
+                            keysend: None,
+                        };
+                        self.spawn_payment_task(pending);
+                        return;
+                    }
+
+                    let owner_username = match models::users::User::get_by_id(&psql_connection, invoice.owner.unwrap())
+                    {
+                        Ok(username) => username,
+                        Err(_) => {
+                            slog::error!(self.logger, "Couldn't get psql connection.");
+                            let payment_response = PaymentResponse::error(
+                                PaymentResponseError::DatabaseConnectionFailed,
+                                msg.req_id,
+                                uid,
+                                msg.payment_request,
+                                msg.currency,
+                                None,
+                            );
+                            let msg = Message::Api(Api::PaymentResponse(payment_response));
+                            listener(msg, ServiceIdentity::Api);
+                            return;
+                        }
+                    };
+                    dbg!(&owner_username);
+
+                    // If there is an owner we make an internal tx.
+                    msg.receipient = Some(owner_username.username);
+                    dbg!(&msg);
+                    self.make_internal_tx(msg, None, listener);
+                }
+
+                Api::SwapRequest(msg) => {
+                    if self.is_insurance_fund_depleted() {
+                        slog::warn!(self.logger, "Insurance is depleted Deposit request Failed!");
+                        return;
+                    }
+                    slog::warn!(self.logger, "Received swap request: {:?}", msg);
+                    if !self.check_access_policy(msg.uid, &msg.origin) {
+                        let swap_response = SwapResponse {
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            success: false,
+                            amount: msg.amount,
+                            from: msg.from,
+                            to: msg.to,
+                            rate: None,
+                            error: Some(SwapResponseError::OriginNotAllowed),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::SwapResponse(swap_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    if self.is_frozen(msg.uid) {
+                        let swap_response = SwapResponse {
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            success: false,
+                            amount: msg.amount,
+                            from: msg.from,
+                            to: msg.to,
+                            rate: None,
+                            error: Some(SwapResponseError::UserFrozen),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::SwapResponse(swap_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    if self.is_default_account_frozen(msg.uid, msg.from) {
+                        let swap_response = SwapResponse {
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            success: false,
+                            amount: msg.amount,
+                            from: msg.from,
+                            to: msg.to,
+                            rate: None,
+                            error: Some(SwapResponseError::AccountFrozen),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::SwapResponse(swap_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    let thresholds = &self.totp_settings.swap_thresholds;
+                    if !self.check_totp(msg.uid, &msg.amount, thresholds, msg.totp_code.as_deref()) {
+                        let swap_response = SwapResponse {
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            success: false,
+                            amount: msg.amount,
+                            from: msg.from,
+                            to: msg.to,
+                            rate: None,
+                            error: Some(SwapResponseError::InvalidTotpCode),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::SwapResponse(swap_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    if let Err(error) = self.check_firm_quote(&msg) {
+                        let swap_response = SwapResponse {
+                            req_id: msg.req_id,
+                            uid: msg.uid,
+                            success: false,
+                            amount: msg.amount,
+                            from: msg.from,
+                            to: msg.to,
+                            rate: None,
+                            error: Some(error),
+                            fees: None,
+                        };
+                        let msg = Message::Api(Api::SwapResponse(swap_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    let msg = Message::Api(Api::SwapRequest(msg));
+                    listener(msg, ServiceIdentity::Dealer);
+                }
+                Api::SwapResponse(msg) => {
+                    let msg = self.apply_firm_quote(msg);
+                    if let Some(mut payment_request) = self.pending_transfers.remove(&msg.req_id) {
+                        match (msg.success, msg.rate) {
+                            (true, Some(rate)) => {
+                                payment_request.fees = msg.fees;
+                                self.make_internal_tx(payment_request, Some(rate), listener);
+                            }
+                            _ => {
+                                let error = match msg.error {
+                                    Some(SwapResponseError::NotEnoughAvailableBalance) => {
+                                        PaymentResponseError::InsufficientFunds
+                                    }
+                                    _ => PaymentResponseError::RateNotAvailable,
+                                };
+                                let payment_response = PaymentResponse::error(
+                                    error,
+                                    payment_request.req_id,
+                                    payment_request.uid,
+                                    None,
+                                    payment_request.currency,
+                                    None,
+                                );
+                                let msg = Message::Api(Api::PaymentResponse(payment_response));
+                                listener(msg, ServiceIdentity::Api);
+                            }
+                        }
+                        return;
+                    }
+                    let cash_out = self.pending_cash_outs.remove(&msg.req_id);
+                    let mut final_response = None;
+                    let mut swap_listener = |msg: Message, destination: ServiceIdentity| {
+                        if let (Message::Api(Api::SwapResponse(swap_response)), ServiceIdentity::Api) =
+                            (&msg, &destination)
+                        {
+                            final_response = Some(swap_response.clone());
+                        }
+                        listener(msg, destination);
+                    };
+                    match cash_out {
+                        Some(cash_out) => {
+                            if self.covers_cash_out(&msg, &cash_out) {
+                                self.process_swap_response(msg, &mut swap_listener);
+                            } else {
+                                let swap_response = SwapResponse {
+                                    success: false,
+                                    error: Some(SwapResponseError::NotEnoughAvailableBalance),
+                                    ..msg
+                                };
+                                swap_listener(Message::Api(Api::SwapResponse(swap_response)), ServiceIdentity::Api);
+                            }
+                            self.continue_cash_out(cash_out, final_response, listener);
+                        }
+                        None => self.process_swap_response(msg, &mut swap_listener),
+                    }
+                }
+
+                Api::GetBalances(msg) => {
+                    let user_account = self
+                        .ledger
+                        .user_accounts
+                        .entry(msg.uid)
+                        .or_insert_with(|| UserAccount::new(msg.uid));
+                    let accounts = user_account.accounts.clone();
+                    let shared_accounts = if msg.uid == DEALER_UID {
+                        HashMap::new()
+                    } else {
+                        self.shared_accounts(msg.uid)
+                    };
+                    let balances = Balances {
+                        req_id: msg.req_id,
+                        uid: msg.uid,
+                        accounts,
+                        shared_accounts,
+                        error: None,
+                    };
+                    let uid = msg.uid;
+                    let msg = Message::Api(Api::Balances(balances));
+                    if uid == DEALER_UID {
+                        listener(msg, ServiceIdentity::Dealer);
+                    } else {
+                        listener(msg, ServiceIdentity::Api);
+                    }
+                }
+                Api::CashOutRequest(msg) => {
+                    self.process_cash_out_request(msg, listener);
+                }
+                Api::SwapOrderRequest(msg) => {
+                    let response = self.place_swap_order(msg);
+                    let placed = response.error.is_none();
+                    let msg = Message::Api(Api::SwapOrderResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                    if placed {
+                        self.send_swap_orders(listener);
+                    }
+                }
+                Api::CancelSwapOrderRequest(msg) => {
+                    let response = self.cancel_swap_order(msg);
+                    let cancelled = response.error.is_none();
+                    let msg = Message::Api(Api::SwapOrderResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                    if cancelled {
+                        self.send_swap_orders(listener);
+                    }
+                }
+                Api::GetSwapOrders(msg) => {
+                    let response = self.get_swap_orders(msg);
+                    let msg = Message::Api(Api::SwapOrderResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::EnrollTotpRequest(msg) => {
+                    let response = self.enroll_totp(msg);
+                    let msg = Message::Api(Api::TotpResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::ConfirmTotpRequest(msg) => {
+                    let response = self.confirm_totp(msg);
+                    let msg = Message::Api(Api::TotpResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::DisableTotpRequest(msg) => {
+                    let response = self.disable_totp(msg);
+                    let msg = Message::Api(Api::TotpResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::InviteAccountMemberRequest(msg) => {
+                    let response = self.invite_account_member(msg);
+                    let msg = Message::Api(Api::AccountMemberResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::AcceptAccountInvitationRequest(msg) => {
+                    let response = self.accept_account_invitation(msg);
+                    let msg = Message::Api(Api::AccountMemberResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::RemoveAccountMemberRequest(msg) => {
+                    let response = self.remove_account_member(msg);
+                    let msg = Message::Api(Api::AccountMemberResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::CreateAccountRequest(msg) => {
+                    let response = self.create_sub_account(msg);
+                    let msg = Message::Api(Api::AccountResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::RenameAccountRequest(msg) => {
+                    let response = self.rename_sub_account(msg);
+                    let msg = Message::Api(Api::AccountResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::ArchiveAccountRequest(msg) => {
+                    let response = self.archive_sub_account(msg);
+                    let msg = Message::Api(Api::AccountResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::InternalTransferRequest(msg) => {
+                    let response = self.process_internal_transfer(msg);
+                    let msg = Message::Api(Api::InternalTransferResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::ExportMyDataRequest(msg) => {
+                    let (response, chunks) = self.export_my_data(&msg);
+                    let msg = Message::Api(Api::ExportMyDataResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                    for chunk in chunks {
+                        let msg = Message::Api(Api::ExportMyDataChunk(chunk));
+                        listener(msg, ServiceIdentity::Api);
+                    }
+                }
+                Api::ExportStatementRequest(msg) => {
+                    let response = self.export_statement(&msg);
+                    let msg = Message::Api(Api::ExportStatementResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::GetInterestHistory(msg) => {
+                    let response = self.interest_history(&msg);
+                    let msg = Message::Api(Api::InterestHistory(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::QuoteRequest(msg) => {
+                    let msg = Message::Api(Api::QuoteRequest(msg));
+                    listener(msg, ServiceIdentity::Dealer);
+                }
+                Api::QuoteResponse(msg) => {
+                    let msg = Message::Api(Api::QuoteResponse(msg));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::AvailableCurrenciesRequest(msg) => {
+                    let msg = Message::Api(Api::AvailableCurrenciesRequest(msg));
+                    listener(msg, ServiceIdentity::Dealer);
+                }
+                Api::SubscribeRates(msg) => {
+                    let msg = Message::Api(Api::SubscribeRates(msg));
+                    listener(msg, ServiceIdentity::Dealer);
+                }
+                Api::RatesUpdate(msg) => {
+                    let msg = Message::Api(Api::RatesUpdate(msg));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                // Published so the notification service tells the owner about the recovery.
+                Api::RecoveryRequested(msg) => {
+                    let msg = Message::Api(Api::RecoveryRequested(msg));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::AvailableCurrenciesResponse(msg) => {
+                    let msg = Message::Api(Api::AvailableCurrenciesResponse(msg));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::GetStatusRequest(msg) => {
+                    let response = GetStatusResponse {
+                        req_id: msg.req_id,
+                        node_alias: self.lnd_node_info.alias.clone(),
+                        available_currencies: self.available_currencies.clone(),
+                        withdrawal_only: self.withdrawal_only,
+                        withdrawal_processing_p95_ms: self.get_withdrawal_p95(),
+                        maintenance_notices: self.maintenance_notices.clone(),
+                    };
+                    let msg = Message::Api(Api::GetStatusResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::GetReservesReport(msg) => {
+                    let now = utils::time::time_now();
+                    let cached_report = self
+                        .last_reserves_report
+                        .clone()
+                        .filter(|report| report.timestamp + RESERVES_REPORT_TTL_MS > now);
+
+                    let result = match cached_report {
+                        Some(report) => Ok(report),
+                        None => {
+                            let liabilities = self.get_bank_state().total_exposures;
+                            let node_pubkey = self.lnd_node_info.identity_pubkey.clone();
+                            build_reserves_report(&mut self.lnd_connector, &node_pubkey, liabilities, now).await
+                        }
+                    };
+
+                    let response = match result {
+                        Ok(report) => {
+                            self.last_reserves_report = Some(report.clone());
+                            GetReservesReportResponse {
+                                req_id: msg.req_id,
+                                report: Some(report),
+                                error: None,
+                            }
+                        }
+                        Err(err) => {
+                            slog::error!(self.logger, "Failed to build reserves report: {:?}", err);
+                            GetReservesReportResponse {
+                                req_id: msg.req_id,
+                                report: None,
+                                error: Some(err),
+                            }
+                        }
+                    };
+                    let msg = Message::Api(Api::GetReservesReportResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::GetNodeInfoRequest(msg) => {
+                    let lnd_node_info = match self.lnd_connector.get_node_info().await {
+                        Ok(ni) => {
+                            self.lnd_node_info = ni.clone();
+                            self.store_node_info();
+                            ni
+                        }
+                        Err(_) => LndNodeInfo::default(),
+                    };
+                    let response = self.node_info_response(msg.req_id, lnd_node_info);
+                    let msg = Message::Api(Api::GetNodeInfoResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::CreateLnurlWithdrawalRequest(msg) => {
+                    if self.is_insurance_fund_depleted() {
+                        slog::warn!(
+                            self.logger,
+                            "Insurance fund is depleted. Rejecting Lnurl Withdrawal Request."
+                        );
+                    }
+
+                    slog::warn!(self.logger, "Received LNURL withdrawal request: {:?}", msg);
+                    let uid = msg.uid;
+
+                    let mut response = CreateLnurlWithdrawalResponse {
+                        req_id: msg.req_id,
+                        lnurl: None,
+                        error: None,
+                    };
+
+                    if !self.check_access_policy(uid, &msg.origin) {
+                        response.error = Some(CreateLnurlWithdrawalError::OriginNotAllowed);
+                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    if msg.amount.value <= dec!(0) {
+                        response.error = Some(CreateLnurlWithdrawalError::InvalidAmount);
+                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    let outbound_account = {
+                        let user_account = match self.ledger.user_accounts.get_mut(&uid) {
+                            Some(ua) => ua,
+                            None => {
+                                response.error = Some(CreateLnurlWithdrawalError::UserAccountNotFound);
+                                let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                        };
+
+                        user_account.get_default_account(msg.currency, None)
+                    };
+
+                    if msg.currency != Currency::BTC && msg.rate.is_none() {
+                        let msg = Message::Api(Api::CreateLnurlWithdrawalRequest(msg));
+                        listener(msg, ServiceIdentity::Dealer);
+                        return;
+                    }
+
+                    if let Some(rate) = &msg.rate {
+                        if msg.currency != Currency::BTC && rate.is_stale(utils::time::time_now()) {
+                            if msg.requoted {
+                                response.error = Some(CreateLnurlWithdrawalError::RateExpired);
+                                let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                            let mut msg = msg;
+                            msg.rate = None;
+                            msg.fees = None;
+                            msg.requoted = true;
+                            let msg = Message::Api(Api::CreateLnurlWithdrawalRequest(msg));
+                            listener(msg, ServiceIdentity::Dealer);
+                            return;
+                        }
+                    }
+
+                    if outbound_account.balance < msg.amount.value {
+                        response.error = Some(CreateLnurlWithdrawalError::InsufficientFunds);
+                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    let now = utils::time::time_now();
+
+                    let amount = msg.amount;
+
+                    let payment_request = PaymentRequest {
+                        uid: msg.uid,
+                        req_id: msg.req_id,
+                        amount: Some(amount),
+                        currency: msg.currency,
+                        rate: msg.rate,
+                        payment_request: Some(String::from("")),
+                        destination: None,
+                        keysend_message: None,
+                        receipient: None,
+                        target_account_id: None,
+                        account_id: None,
+                        fees: msg.fees,
+                        requoted: msg.requoted,
+                        idempotency_key: None,
+                        origin: msg.origin.clone(),
+                        totp_code: None,
+                    };
+
+                    let lnurl_path = self.lnurl_settings.url(&self.lnurl_settings.withdrawal_request_path);
+                    let q = msg.req_id;
+                    let lnurl = if let Ok(encoded) = utils::lnurl::encode(lnurl_path, Some(q.to_string())) {
+                        encoded
+                    } else {
+                        response.error = Some(CreateLnurlWithdrawalError::FailedToCreateLnUrl);
+                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    };
+                    if let Err(err) = self.store_lnurl_withdrawal(&payment_request, now) {
+                        slog::error!(self.logger, "Failed to store LNURL withdrawal {}: {}", msg.req_id, err);
+                        response.error = Some(CreateLnurlWithdrawalError::FailedToCreateLnUrl);
+                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+                    response.lnurl = Some(lnurl);
+                    self.lnurl_withdrawal_requests
+                        .insert(payment_request.req_id, (now, payment_request));
+
+                    let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::GetLnurlWithdrawalRequest(msg) => {
+                    let callback = self.lnurl_settings.url(&self.lnurl_settings.withdrawal_callback_path);
+                    let mut response = GetLnurlWithdrawalResponse {
+                        callback,
+                        req_id: msg.req_id,
+                        max_withdrawable: 0,
+                        default_description: String::from("Lndhubx Withdrawal"),
+                        min_withdrawable: 1,
+                        tag: String::from("withdrawalRequest"),
+                        error: None,
+                    };
+                    // The wallet may fetch the request several times before it calls back, so it is only removed
+                    // once paid.
+                    if let Some(payment_request) = self.unexpired_lnurl_withdrawal(msg.req_id).cloned() {
+                        if let Some(a) = &payment_request.amount {
+                            let a = match &payment_request.rate {
+                                Some(r) => a.exchange(&r).unwrap(),
+                                None => a.clone(),
+                            };
+                            let a = a.try_sats().unwrap();
+                            if let Some(ma) = a.to_u64() {
+                                response.max_withdrawable = ma;
+                                let msg = Message::Api(Api::GetLnurlWithdrawalResponse(response));
+                                listener(msg, ServiceIdentity::Api);
+                                return;
+                            }
+                        }
+                    }
+                    response.error = Some(GetLnurlWithdrawalError::RequestNotFound);
+                    let msg = Message::Api(Api::GetLnurlWithdrawalResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::PayLnurlWithdrawalRequest(msg) => {
+                    if self.unexpired_lnurl_withdrawal(msg.req_id).is_some() {
+                        // Removed before paying so a second callback can't withdraw again.
+                        if let Some((_, mut payment_request)) = self.remove_lnurl_withdrawal(msg.req_id) {
+                            payment_request.payment_request = Some(msg.payment_request);
+                            let msg = Message::Api(Api::PaymentRequest(payment_request));
+                            listener(msg, ServiceIdentity::Loopback);
+                            return;
+                        }
+                    }
+                    let response = PayLnurlWithdrawalResponse {
+                        req_id: msg.req_id,
+                        error: Some(PayLnurlWithdrawalError::RequestNotFound),
+                    };
+                    let msg = Message::Api(Api::PayLnurlWithdrawalResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::LiquidAddressRequest(msg) => {
+                    let mut response = LiquidAddressResponse {
+                        req_id: msg.req_id,
+                        uid: msg.uid,
+                        address: None,
+                        claim_script: None,
+                        error: None,
+                    };
+                    match self.liquid_address(&msg) {
+                        Ok((address, claim_script)) => {
+                            response.address = Some(address);
+                            response.claim_script = claim_script;
+                        }
+                        Err(err) => response.error = Some(err),
+                    }
+                    let msg = Message::Api(Api::LiquidAddressResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::LiquidClaimPegInRequest(msg) => {
+                    let mut response = LiquidClaimPegInResponse {
+                        req_id: msg.req_id,
+                        uid: msg.uid,
+                        txid: None,
+                        amount: None,
+                        error: None,
+                    };
+                    match self.claim_liquid_pegin(&msg) {
+                        Ok((txid, amount)) => {
+                            response.txid = Some(txid);
+                            response.amount = Some(amount);
+                        }
+                        Err(err) => response.error = Some(err),
+                    }
+                    let msg = Message::Api(Api::LiquidClaimPegInResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::LiquidWithdrawalRequest(msg) => {
+                    let mut response = LiquidWithdrawalResponse {
+                        req_id: msg.req_id,
+                        uid: msg.uid,
+                        txid: None,
+                        fees: None,
+                        error: None,
+                    };
+                    match self.withdraw_liquid(&msg) {
+                        Ok((txid, fees)) => {
+                            response.txid = Some(txid);
+                            response.fees = Some(fees);
+                        }
+                        Err(err) => response.error = Some(err),
+                    }
+                    let msg = Message::Api(Api::LiquidWithdrawalResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::EcashWithdrawRequest(msg) => {
+                    let mut response = EcashWithdrawResponse {
+                        req_id: msg.req_id,
+                        uid: msg.uid,
+                        amount: msg.amount.clone(),
+                        notes: None,
+                        fees: None,
+                        error: None,
+                    };
+                    match self.withdraw_ecash(&msg).await {
+                        Ok((notes, fees)) => {
+                            response.notes = Some(notes);
+                            response.fees = Some(fees);
+                        }
+                        Err(err) => response.error = Some(err),
+                    }
+                    let msg = Message::Api(Api::EcashWithdrawResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::EcashDepositRequest(msg) => {
+                    let mut response = EcashDepositResponse {
+                        req_id: msg.req_id,
+                        uid: msg.uid,
+                        amount: None,
+                        fees: None,
+                        error: None,
+                    };
+                    match self.deposit_ecash(&msg).await {
+                        Ok((amount, fees)) => {
+                            response.amount = Some(amount);
+                            response.fees = Some(fees);
+                        }
+                        Err(err) => response.error = Some(err),
+                    }
+                    let msg = Message::Api(Api::EcashDepositResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::GetLnurlChannelRequest(msg) => {
+                    let response = self.get_lnurl_channel(msg).await;
+                    let msg = Message::Api(Api::GetLnurlChannelResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::RequestChannelOpen(msg) => {
+                    let mut response = RequestChannelOpenResponse {
+                        req_id: msg.req_id,
+                        funding_txid: None,
+                        error: None,
+                    };
+                    match self.open_lnurl_channel(msg).await {
+                        Ok(txid) => response.funding_txid = Some(txid),
+                        Err(err) => response.error = Some(err),
+                    }
+                    let msg = Message::Api(Api::RequestChannelOpenResponse(response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Api::QueryRouteRequest(msg) => {
+                    if let Ok(fee_msat) = self.probe_fee_msat(&msg.payment_request, dec!(0.0005)).await {
+                        if let Some(fee_msat) = fee_msat {
+                            let msg = Message::Api(Api::QueryRouteResponse(QueryRouteResponse {
+                                req_id: msg.req_id,
+                                total_fee: Decimal::new(fee_msat / 1000, 0),
+                                error: None,
+                            }));
+                            listener(msg, ServiceIdentity::Api);
+                        } else {
+                            let msg = Message::Api(Api::QueryRouteResponse(QueryRouteResponse {
+                                req_id: msg.req_id,
+                                total_fee: dec!(0),
+                                error: Some(QueryRouteError::NoRouteFound),
+                            }));
+                            listener(msg, ServiceIdentity::Api);
+                        }
+                    }
+                }
+
+                msg => self.store_unhandled_message(Message::Api(msg)),
+            },
+            Message::Bank(msg) => match msg {
+                Bank::PaymentResult(res) => {
+                    slog::warn!(self.logger, "Received payment result: {:?}", res);
+
+                    if res.amount.value <= dec!(0) {
+                        panic!("Amount is smaller than zero.");
+                    }
+
+                    if res.is_success {
+                        self.metrics.payments_succeeded.inc();
+                    } else {
+                        self.metrics.payments_failed.inc();
+                    }
+
+                    let conn = match &self.conn_pool {
+                        Some(conn) => conn,
+                        None => {
+                            slog::error!(self.logger, "No database provided.");
+                            return;
+                        }
+                    };
+
+                    let psql_connection = match conn.get() {
+                        Ok(psql_connection) => psql_connection,
+                        Err(_) => {
+                            slog::error!(self.logger, "Couldn't get psql connection.");
+                            return;
+                        }
+                    };
+
+                    if !res.is_success && res.is_retryable && self.schedule_payment_retry(&psql_connection, &res) {
+                        // Until the next attempt starts the payment is only tracked by its retry.
+                        if let Err(err) =
+                            PendingPayment::delete(&psql_connection, res.payment_response.req_id.to_string())
+                        {
+                            slog::error!(self.logger, "Failed to delete pending payment: {:?}", err);
+                        }
+                        let mut payment_response = res.payment_response;
+                        payment_response.error = Some(PaymentResponseError::PaymentRetryScheduled);
+                        let msg = Message::Api(Api::PaymentResponse(payment_response));
+                        listener(msg, ServiceIdentity::Api);
+                        return;
+                    }
+
+                    self.withdrawals_in_flight.remove(&res.payment_response.req_id);
+                    if let Some(start) = self.payment_starts.remove(&res.payment_response.req_id) {
+                        self.metrics.payment_latency.observe(start.elapsed().as_secs_f64());
+                        if self.withdrawal_durations.len() >= WITHDRAWAL_DURATIONS_SIZE {
+                            self.withdrawal_durations.pop_front();
+                        }
+                        self.withdrawal_durations.push_back(start.elapsed().as_millis() as u64);
+                    }
+
+                    // The outcome is final, whichever attempt it came from.
+                    if let Err(err) = PaymentRetry::delete(&psql_connection, res.payment_response.req_id.to_string()) {
+                        slog::error!(self.logger, "Failed to delete payment retry: {:?}", err);
+                    }
+                    if let Err(err) = PendingPayment::delete(&psql_connection, res.payment_response.req_id.to_string())
+                    {
+                        slog::error!(self.logger, "Failed to delete pending payment: {:?}", err);
+                    }
+
+                    let uid = res.uid;
+
+                    // The payment was made from the account its funds were reserved on.
+                    let paying_account_id = self
+                        .ledger
+                        .release_pending(&res.payment_response.req_id)
+                        .map(|funds| funds.account_id);
+
+                    let mut btc_liabilities_account = self
+                        .ledger
+                        .bank_liabilities
+                        .get_default_account(Currency::BTC, Some(AccountType::External));
+
+                    let mut inbound_account = {
+                        let user_account = match self.ledger.user_accounts.get_mut(&uid) {
+                            Some(ua) => ua,
+                            None => return,
+                        };
+
+                        match paying_account_id.and_then(|account_id| user_account.accounts.get(&account_id)) {
+                            Some(account) => account.clone(),
+                            None => user_account.get_default_account(res.currency, None),
+                        }
+                    };
+
+                    let mut payment_response = res.payment_response;
+
+                    if res.is_success {
+                        // If successful the part of the fee reserve the payment didn't use has to be settled.
+                        let fees_payed_in_btc = payment_response.fees.clone();
+
+                        let payment_amount = payment_response.amount.clone().unwrap();
+
+                        let excess_fees_in_btc = unused_fee_reserve(
+                            res.amount.value,
+                            payment_amount.value,
+                            fees_payed_in_btc.unwrap().value,
+                        );
+
+                        let excess_fees = Money::new(Currency::BTC, Some(excess_fees_in_btc));
+
+                        assert!(excess_fees_in_btc >= dec!(0));
+
+                        // With strict quotes the bank keeps the difference to the actual fee, otherwise the unused
+                        // part of the reserve is returned to the account it was debited from.
+                        if excess_fees_in_btc > dec!(0) && self.strict_fee_quotes {
+                            let mut fee_account = self.ledger.get_fee_account(Currency::BTC);
+                            let txid = match self.make_tx(
+                                &mut btc_liabilities_account,
+                                BANK_UID,
+                                &mut fee_account,
+                                BANK_UID,
+                                excess_fees.clone(),
+                            ) {
+                                Ok(txid) => txid,
+                                Err(_) => return,
+                            };
+
+                            self.ledger
+                                .bank_liabilities
+                                .accounts
+                                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+                            self.ledger
+                                .fee_account
+                                .accounts
+                                .insert(fee_account.account_id, fee_account.clone());
+
+                            self.update_account(&fee_account, BANK_UID);
+                            self.update_account(&btc_liabilities_account, BANK_UID);
+
+                            // Booked as fee so it shows up in the operator revenue.
+                            if self
+                                .make_summary_tx(
+                                    &btc_liabilities_account,
+                                    BANK_UID,
+                                    &fee_account,
+                                    BANK_UID,
+                                    excess_fees.clone(),
+                                    None,
+                                    Some(excess_fees),
+                                    None,
+                                    None,
+                                    Some(txid),
+                                    Some(String::from("ExcessRoutingFees")),
+                                )
+                                .is_err()
+                            {
+                                slog::error!(self.logger, "Failed to make summary transaction of excess fees.");
+                            }
+                        } else if excess_fees_in_btc > dec!(0) {
+                            if let Err(err) = self.refund_unused_fees(uid, &mut inbound_account, excess_fees, &res.rate)
+                            {
+                                slog::error!(
+                                    self.logger,
+                                    "Failed to refund unused fees of payment {}: {}",
+                                    payment_response.req_id,
+                                    err
+                                );
+                            }
+                        }
+
+                        // The bank keeps the difference to the actual fee, users only ever see the quote.
+                        if self.strict_fee_quotes {
+                            payment_response.fees = Some(Money::from_sats(res.max_fee_in_sats));
+                        }
+
+                        payment_response.success = true;
+
+                        // Fees of failed payments are refunded, so they are only shared once the payment went through.
+                        if let Some(service_fee) = &res.service_fee {
+                            self.share_fee_with_referrer(uid, service_fee);
+                        }
+
+                        let pr = match &res.keysend {
+                            Some(keysend) => keysend.payment_request(),
+                            None => payment_response.clone().payment_request.unwrap_or_else(|| {
+                                panic!(
+                                    "Payment request has not been specified in the payment response: {:?}",
+                                    payment_response
+                                )
+                            }),
+                        };
+
+                        let mut invoice = if let Ok(invoice) =
+                            models::invoices::Invoice::get_by_payment_request(&psql_connection, pr)
+                        {
+                            invoice
+                        } else {
+                            return;
+                        };
+
+                        invoice.settled = true;
+
+                        if invoice.update(&psql_connection).is_err() {
+                            slog::error!(self.logger, "Error updating updating invoices!");
+                        }
+                    } else {
+                        let refund = res.amount.clone();
+                        let rate = res.rate;
+                        let refund_exchanged = refund.clone().exchange(&rate).unwrap();
+
+                        if res.currency != Currency::BTC {
+                            let mut dealer_btc_account = self
+                                .ledger
+                                .dealer_accounts
+                                .get_default_account(Currency::BTC, Some(AccountType::Internal));
+                            let mut dealer_fiat_account = self
+                                .ledger
+                                .dealer_accounts
+                                .get_default_account(res.currency, Some(AccountType::Internal));
+
+                            let outbound_txid = if let Ok(txid) = self.make_tx(
+                                &mut btc_liabilities_account,
+                                BANK_UID,
+                                &mut dealer_btc_account,
+                                uid,
+                                refund.clone(),
+                            ) {
+                                txid
+                            } else {
+                                return;
+                            };
+
+                            let inbound_txid = if let Ok(txid) = self.make_tx(
+                                &mut dealer_fiat_account,
+                                DEALER_UID,
+                                &mut inbound_account,
+                                uid,
+                                refund_exchanged,
+                            ) {
+                                txid
+                            } else {
+                                return;
+                            };
+
+                            self.ledger
+                                .bank_liabilities
+                                .accounts
+                                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+
+                            self.ledger
+                                .dealer_accounts
+                                .accounts
+                                .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+                            self.ledger
+                                .dealer_accounts
+                                .accounts
+                                .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
+
+                            self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+
+                            self.update_account(&inbound_account, res.uid);
+                            self.update_account(&btc_liabilities_account, BANK_UID);
+
+                            self.update_account(&dealer_btc_account, DEALER_UID);
+                            self.update_account(&dealer_fiat_account, DEALER_UID);
+
+                            if self
+                                .make_summary_tx(
+                                    &btc_liabilities_account,
+                                    BANK_UID,
+                                    &inbound_account,
+                                    uid,
+                                    refund,
+                                    Some(rate.clone()),
+                                    None,
+                                    Some(outbound_txid),
+                                    Some(inbound_txid),
+                                    None,
+                                    Some(String::from("PaymentRefund")),
+                                )
+                                .is_err()
+                            {
+                                return;
+                            }
+                        } else {
+                            let txid = if let Ok(txid) = self.make_tx(
+                                &mut btc_liabilities_account,
+                                BANK_UID,
+                                &mut inbound_account,
+                                uid,
+                                refund.clone(),
+                            ) {
+                                txid
+                            } else {
+                                return;
+                            };
+
+                            self.ledger
+                                .bank_liabilities
+                                .accounts
+                                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+
+                            self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+
+                            self.update_account(&inbound_account, res.uid);
+                            self.update_account(&btc_liabilities_account, BANK_UID);
+
+                            if self
+                                .make_summary_tx(
+                                    &btc_liabilities_account,
+                                    BANK_UID,
+                                    &inbound_account,
+                                    uid,
+                                    refund,
+                                    Some(rate.clone()),
+                                    None,
+                                    Some(txid.clone()),
+                                    Some(txid),
+                                    None,
+                                    Some(String::from("PaymentRefund")),
+                                )
+                                .is_err()
+                            {
+                                return;
+                            }
+                        }
+
+                        if let Some(service_fee) = res.service_fee.filter(|fee| fee.value > Decimal::ZERO) {
+                            if let Err(err) =
+                                self.refund_fee(&mut inbound_account, uid, FeeOperation::External, &service_fee)
+                            {
+                                slog::error!(
+                                    self.logger,
+                                    "Failed to refund fee of payment {}: {}",
+                                    payment_response.req_id,
+                                    err
+                                );
+                            }
+                            self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+                            self.update_account(&inbound_account, uid);
+                        }
+                    }
+
+                    let bank_state = self.get_bank_state();
+                    let msg = Message::Dealer(Dealer::BankState(bank_state));
+                    listener(msg, ServiceIdentity::Dealer);
+
+                    let msg = Message::Api(Api::PaymentResponse(payment_response));
+                    listener(msg, ServiceIdentity::Api);
+                }
+                Bank::LndNodeHealth(health) => {
+                    if health.is_healthy {
+                        slog::warn!(
+                            self.logger,
+                            "Lnd node {} is unreachable. Switched over to {}",
+                            health.failed_node,
+                            health.active_node
+                        );
+                    } else {
+                        slog::error!(
+                            self.logger,
+                            "Lnd node {} is unreachable and no standby node is available",
+                            health.failed_node
+                        );
+                    }
+                }
+            },
+            Message::Cli(Cli::ExportJournal(export_journal)) => {
+                let result = match self.export_journal(&export_journal) {
+                    Ok(count) => format!("Exported {} transactions", count),
+                    Err(err) => err,
+                };
+                let msg = Message::Cli(Cli::ExportJournalResult(ExportJournalResult {
+                    request: export_journal,
+                    result,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::Simulate(simulation)) => {
+                let (report, error) = match self.run_simulation(&simulation) {
+                    Ok(report) => (Some(report), None),
+                    Err(err) => (None, Some(err)),
+                };
+                let msg = Message::Cli(Cli::SimulationResult(SimulationResult {
+                    request: simulation,
+                    report,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::QueryLedger(query)) => {
+                let msg = Message::Cli(Cli::LedgerQueryResult(self.query_ledger(query)));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::QueryRevenue(query)) => {
+                let (buckets, error) = match self.query_revenue(&query) {
+                    Ok(buckets) => (buckets, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let msg = Message::Cli(Cli::RevenueQueryResult(RevenueQueryResult {
+                    request: query,
+                    buckets,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::GetRevenueReport(request)) => {
+                let (buckets, error) = match self.revenue_report(&request) {
+                    Ok(buckets) => (buckets, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let mut balances = HashMap::new();
+                for account in self.ledger.fee_account.accounts.values() {
+                    *balances.entry(account.currency).or_insert(Decimal::ZERO) += account.balance;
+                }
+                let msg = Message::Cli(Cli::RevenueReport(RevenueReport {
+                    request,
+                    buckets,
+                    balances,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ClosePeriod(close)) => {
+                let (report, error) = match self.close_period(&close) {
+                    Ok(report) => (Some(report), None),
+                    Err(err) => (None, Some(err)),
+                };
+                let msg = Message::Cli(Cli::ClosePeriodResult(ClosePeriodResult {
+                    request: close,
+                    report,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ReopenPeriod(reopen)) => {
+                let (reopened_close_id, error) = match self.reopen_period(&reopen) {
+                    Ok(close_id) => (Some(close_id), None),
+                    Err(err) => (None, Some(err)),
+                };
+                let msg = Message::Cli(Cli::ReopenPeriodResult(ReopenPeriodResult {
+                    request: reopen,
+                    reopened_close_id,
+                    closed_until: self.closed_until,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::SetWithdrawalLimit(request)) => {
+                let error = self.set_withdrawal_limit(&request).err();
+                let msg = Message::Cli(Cli::SetWithdrawalLimitResult(SetWithdrawalLimitResult {
+                    request,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::FreezeUser(request)) => {
+                let error = self.freeze_user(&request).err();
+                let msg = Message::Cli(Cli::FreezeUserResult(FreezeUserResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ForceCloseAccount(request)) => {
+                let (balance, error) = match self.force_close_account(&request) {
+                    Ok(balance) => (Some(balance), None),
+                    Err(err) => (None, Some(err)),
+                };
+                let msg = Message::Cli(Cli::ForceCloseAccountResult(ForceCloseAccountResult {
+                    request,
+                    balance,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ResetRateLimits(request)) => {
+                let error = self.reset_rate_limits(request.uid).err();
+                let msg = Message::Cli(Cli::ResetRateLimitsResult(ResetRateLimitsResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::SetDepositLimit(request)) => {
+                let error = self.set_deposit_limit(&request).err();
+                let msg = Message::Cli(Cli::SetDepositLimitResult(SetDepositLimitResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::FreezeAccount(request)) => {
+                let error = self.freeze_account(&request).err();
+                let msg = Message::Cli(Cli::FreezeAccountResult(FreezeAccountResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::UnfreezeAccount(request)) => {
+                let error = self.unfreeze_account(&request).err();
+                let msg = Message::Cli(Cli::UnfreezeAccountResult(UnfreezeAccountResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ListFrozenAccounts(_)) => {
+                let (accounts, error) = match self.list_frozen_accounts() {
+                    Ok(accounts) => (accounts, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let msg = Message::Cli(Cli::FrozenAccountsResult(FrozenAccountsResult { accounts, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ListPendingWithdrawals(_)) => {
+                let (withdrawals, error) = match self.list_pending_withdrawals() {
+                    Ok(withdrawals) => (withdrawals, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let msg = Message::Cli(Cli::PendingWithdrawalsResult(PendingWithdrawalsResult {
+                    withdrawals,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ApproveWithdrawal(request)) => {
+                let error = self
+                    .decide_withdrawal(request.req_id, pending_withdrawals::APPROVED, &request.operator, None)
+                    .err();
+                let msg = Message::Cli(Cli::ApproveWithdrawalResult(ApproveWithdrawalResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::RejectWithdrawal(request)) => {
+                let error = self
+                    .decide_withdrawal(
+                        request.req_id,
+                        pending_withdrawals::REJECTED,
+                        &request.operator,
+                        request.reason.clone(),
+                    )
+                    .err();
+                let msg = Message::Cli(Cli::RejectWithdrawalResult(RejectWithdrawalResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::MakeTx(make_tx)) => {
+                let tx = make_tx.clone();
+                let result = match self.process_make_tx(make_tx).await {
+                    Ok(_) => "Successful".to_string(),
+                    Err(err) => err.to_string(),
+                };
+                let msg = Message::Cli(Cli::MakeTxResult(MakeTxResult { tx, result }));
+                // the identity is ignored by cli listener, so we are using ServiceIdentity::Api here
+                // just to pass some argument
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ListDeadLetters(request)) => {
+                let (dead_letters, error) = match self.list_dead_letters(&request) {
+                    Ok(dead_letters) => (dead_letters, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let msg = Message::Cli(Cli::DeadLettersResult(DeadLettersResult { dead_letters, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ListPendingLnurlWithdrawals(_)) => {
+                let withdrawals = self.list_pending_lnurl_withdrawals();
+                let msg = Message::Cli(Cli::PendingLnurlWithdrawalsResult(PendingLnurlWithdrawalsResult {
+                    withdrawals,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ListLiquiditySwaps(request)) => {
+                let (swaps, error) = match self.list_liquidity_swaps(&request) {
+                    Ok(swaps) => (swaps, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let msg = Message::Cli(Cli::LiquiditySwapsResult(LiquiditySwapsResult { swaps, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ReloadConfig(_)) => {
+                let result = utils::config::register_currencies_from_env()
+                    .and_then(|_| {
+                        utils::config::get_config_from_env::<BankEngineSettings>()
+                            .map_err(|err| format!("Failed to load settings: {:?}", err))
+                    })
+                    .and_then(|settings| self.reload_settings(settings));
+                let (reloaded, error) = match result {
+                    Ok(reloaded) => (reloaded, None),
+                    Err(err) => (Vec::new(), Some(err)),
+                };
+                let msg = Message::Cli(Cli::ReloadConfigResult(ReloadConfigResult { reloaded, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::ReplayDeadLetter(request)) => {
+                let error = self.replay_dead_letter(request.id).err();
+                let msg = Message::Cli(Cli::ReplayDeadLetterResult(ReplayDeadLetterResult { request, error }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::Rebalance(request)) => {
+                let (fee_msat, error) = match self.rebalance(&request).await {
+                    Ok(fee_msat) => (Some(fee_msat), None),
+                    Err(err) => (None, Some(err)),
+                };
+                let msg = Message::Cli(Cli::RebalanceResult(RebalanceResult {
+                    request,
+                    fee_msat,
+                    error,
+                }));
+                listener(msg, ServiceIdentity::Api);
+            }
+            msg => self.store_unhandled_message(msg),
+        }
+    }
+
+    async fn handle_dealer_deposit(&mut self, deposit: Deposit) {
+        dbg!("Dealer Deposit Received!");
+        // Dealer can transfer from an outside unknown wallet or between the external
+        // kollider wallet and lndhubx.
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        // Check whether we know about this invoice.
+        if let Ok(invoice) = Invoice::get_by_payment_request(&c, deposit.payment_request) {
+            dbg!(&invoice);
+            if let None = invoice.reference {
+                return;
+            }
+
+            let is_internal = match invoice.reference.unwrap().as_ref() {
+                "KolliderSettlement" => true,
+                "ExternalDeposit" => false,
+                _ => return,
+            };
+            dbg!(is_internal);
+
+            let (mut inbound_dealer_account, mut outbound_account, outbound_uid) = if is_internal {
+                let inbound = self
+                    .ledger
+                    .dealer_accounts
+                    .get_default_account(Currency::BTC, Some(AccountType::Internal));
+                let outbound = self
+                    .ledger
+                    .dealer_accounts
+                    .get_default_account(Currency::BTC, Some(AccountType::External));
+                (inbound, outbound, DEALER_UID)
+            } else {
+                let inbound = self
+                    .ledger
+                    .dealer_accounts
+                    .get_default_account(Currency::BTC, Some(AccountType::Internal));
+                let outbound = self
+                    .ledger
+                    .bank_liabilities
+                    .get_default_account(Currency::BTC, Some(AccountType::External));
+                (inbound, outbound, BANK_UID)
+            };
+
+            let value = Money::from_msats(Decimal::new(invoice.value_msat, 0));
+
+            if self
+                .make_tx(
+                    &mut outbound_account,
+                    outbound_uid,
+                    &mut inbound_dealer_account,
+                    DEALER_UID,
+                    value,
+                )
+                .is_err()
+            {
+                slog::error!(self.logger, "Failed to make deposit it dealer account");
+            };
+
+            self.ledger
+                .dealer_accounts
+                .accounts
+                .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
+            self.update_account(&inbound_dealer_account, DEALER_UID);
+
+            if is_internal {
+                self.ledger
+                    .dealer_accounts
+                    .accounts
+                    .insert(outbound_account.account_id, outbound_account.clone());
+                self.update_account(&outbound_account, DEALER_UID);
+            } else {
+                self.ledger
+                    .bank_liabilities
+                    .accounts
+                    .insert(outbound_account.account_id, outbound_account.clone());
+                self.update_account(&outbound_account, BANK_UID);
+            }
+            dbg!("DONE");
+        }
+    }
+
+    async fn process_dealer_invoice(&mut self, pay_invoice: PayInvoice, is_external: bool) {
+        let decoded = match pay_invoice
+            .payment_request
+            .clone()
+            .parse::<lightning_invoice::Invoice>()
+        {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+        let amount_in_milli_satoshi = decoded
+            .amount_milli_satoshis()
+            .unwrap_or_else(|| panic!("Amount in millisatoshi is not specified: {:?}", decoded));
+        // scale 3, which corresponds to dividing by 10^3 = 1000
+        let amount_in_sats = Decimal::new(amount_in_milli_satoshi as i64, 3);
+
+        slog::debug!(
+            self.logger,
+            "Dealer requests to pay {}invoice: {} of amount: {}",
+            is_external,
+            pay_invoice.payment_request,
+            amount_in_sats
+        );
+
+        match self
+            .lnd_connector
+            .pay_invoice(
+                pay_invoice.payment_request.clone(),
+                amount_in_sats,
+                Some(self.ln_network_max_fee),
+                None,
+            )
+            .await
+        {
+            Ok(result) => {
+                slog::debug!(self.logger, "{:?}", result);
+                let _ = self.book_dealer_payment(Money::from_sats(amount_in_sats), is_external);
+            }
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to pay {}invoice {:?}, reason: {:?}",
+                    is_external,
+                    pay_invoice,
+                    err
+                );
+            }
+        }
+    }
+
+    /// Books an invoice paid for the dealer, from the dealer's internal account to either its external
+    /// account or, for insurance payments, the bank's liabilities. Returns the updated accounts and the txid.
+    fn book_dealer_payment(
+        &mut self,
+        amount: Money,
+        is_external: bool,
+    ) -> Result<(Account, Account, UserId, String), BankError> {
+        let (mut outbound_account, mut inbound_account, inbound_uid) = if is_external {
+            let inbound_account = self
+                .ledger
+                .bank_liabilities
+                .get_default_account(Currency::BTC, Some(AccountType::External));
+            let outbound_account = self
+                .ledger
+                .dealer_accounts
+                .get_default_account(Currency::BTC, Some(AccountType::Internal));
+            (outbound_account, inbound_account, BANK_UID)
+        } else {
+            let inbound_account = self
+                .ledger
+                .dealer_accounts
+                .get_default_account(Currency::BTC, Some(AccountType::External));
+            let outbound_account = self
+                .ledger
+                .dealer_accounts
+                .get_default_account(Currency::BTC, Some(AccountType::Internal));
+            (outbound_account, inbound_account, DEALER_UID)
+        };
+
+        let txid = self.make_tx(
+            &mut outbound_account,
+            DEALER_UID,
+            &mut inbound_account,
+            inbound_uid,
+            amount,
+        )?;
+
+        if is_external {
+            self.update_account(&inbound_account, BANK_UID);
+            self.update_account(&outbound_account, DEALER_UID);
+
+            self.ledger
+                .bank_liabilities
+                .accounts
+                .insert(inbound_account.account_id, inbound_account.clone());
+            self.ledger
+                .dealer_accounts
+                .accounts
+                .insert(outbound_account.account_id, outbound_account.clone());
+        } else {
+            self.update_account(&inbound_account, DEALER_UID);
+            self.update_account(&outbound_account, DEALER_UID);
+
+            self.ledger
+                .dealer_accounts
+                .accounts
+                .insert(inbound_account.account_id, inbound_account.clone());
+            self.ledger
+                .dealer_accounts
+                .accounts
+                .insert(outbound_account.account_id, outbound_account.clone());
+        }
+
+        Ok((outbound_account, inbound_account, inbound_uid, txid))
+    }
+
+    fn queue_dealer_invoice(&mut self, pay_invoice: PayInvoice, kind: SettlementKind) {
+        let decoded = match pay_invoice
+            .payment_request
+            .clone()
+            .parse::<lightning_invoice::Invoice>()
+        {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+        let amount_in_milli_satoshi = match decoded.amount_milli_satoshis() {
+            Some(amount) => amount,
+            None => {
+                slog::error!(self.logger, "Amount in millisatoshi is not specified: {:?}", decoded);
+                return;
+            }
+        };
+
+        self.settlement_queue.push_payment(
+            kind,
+            PendingPayment {
+                req_id: pay_invoice.req_id,
+                payment_request: pay_invoice.payment_request,
+                payment_hash: decoded.payment_hash().to_string(),
+                destination: decoded.recover_payee_pub_key().to_string(),
+                amount_in_sats: Decimal::new(amount_in_milli_satoshi as i64, 3),
+                queued_at: utils::time::time_now(),
+            },
+        );
+    }
+
+    fn queue_dealer_withdrawal(&mut self, req: CreateInvoiceRequest, kind: SettlementKind) {
+        self.settlement_queue.push_withdrawal(
+            kind,
+            PendingWithdrawal {
+                req_id: req.req_id,
+                amount: req.amount,
+                memo: req.memo,
+                queued_at: utils::time::time_now(),
+            },
+        );
+    }
+
+    /// Executes the dealer settlements that waited out the batching window. The withdrawals of a batch are
+    /// merged into a single invoice and the payments to a destination share one route.
+    pub async fn flush_settlements<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        let settings = match &self.settlement_batching {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+
+        let batches = self.settlement_queue.take_due(utils::time::time_now(), &settings);
+        self.audit_context = AuditContext::bank("Settlement");
+        for batch in batches {
+            self.execute_settlement_batch(batch, listener).await;
+        }
+    }
+
+    async fn execute_settlement_batch<F: FnMut(Message, ServiceIdentity)>(
+        &mut self,
+        batch: SettlementBatch,
+        listener: &mut F,
+    ) {
+        slog::info!(
+            self.logger,
+            "Executing {} settlement batch {} with {} payments and {} withdrawals",
+            batch.kind,
+            batch.group_id,
+            batch.payments.len(),
+            batch.withdrawals.len()
+        );
+
+        let now = utils::time::time_now() as i64;
+        let mut members = Vec::new();
+
+        if !batch.withdrawals.is_empty() {
+            // Withdrawals of a kind share the memo, it decides how the invoice is booked once paid.
+            let memo = batch.withdrawals[0].memo.clone();
+            let req = CreateInvoiceRequest {
+                req_id: batch.group_id,
+                amount: batch.withdrawal_total(),
+                memo,
+            };
+            let netted_req_ids = batch
+                .withdrawals
+                .iter()
+                .map(|withdrawal| withdrawal.req_id)
+                .collect::<Vec<_>>();
+            let mut forward = |msg: Message, destination: ServiceIdentity| {
+                let msg = match msg {
+                    Message::Dealer(Dealer::CreateInvoiceResponse(mut response)) => {
+                        response.netted_req_ids = netted_req_ids.clone();
+                        Message::Dealer(Dealer::CreateInvoiceResponse(response))
+                    }
+                    msg => msg,
+                };
+                listener(msg, destination)
+            };
+            self.process_create_invoice_request(req, DEALER_UID, &mut forward).await;
+
+            for withdrawal in batch.withdrawals.iter() {
+                members.push(SettlementBatchMember {
+                    group_id: batch.group_id,
+                    req_id: withdrawal.req_id,
+                    kind: batch.kind.to_string(),
+                    direction: String::from("Withdrawal"),
+                    amount: BigDecimal::from(withdrawal.amount),
+                    txid: None,
+                    created_at: now,
+                });
+            }
+        }
+
+        let is_external = batch.kind == SettlementKind::Insurance;
+        for (destination, payments) in batch.payments_by_destination() {
+            let payment_requests = payments
+                .iter()
+                .map(|payment| payment.payment_request.clone())
+                .collect::<Vec<_>>();
+            let results = match self
+                .lnd_connector
+                .pay_invoices_along_route(&payment_requests, self.ln_network_max_fee)
+                .await
+            {
+                Ok(results) => results,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to pay settlement batch {} to {}, reason: {:?}",
+                        batch.group_id,
+                        destination,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            for (payment, result) in payments.into_iter().zip(results) {
+                if let Err(err) = result {
+                    slog::error!(
+                        self.logger,
+                        "Failed to pay {} invoice {} of settlement batch {}, reason: {:?}",
+                        batch.kind,
+                        payment.payment_request,
+                        batch.group_id,
+                        err
+                    );
+                    continue;
+                }
+
+                let amount = Money::from_sats(payment.amount_in_sats);
+                let txid = match self.book_dealer_payment(amount.clone(), is_external) {
+                    Ok((outbound_account, inbound_account, inbound_uid, txid)) => self
+                        .make_summary_tx(
+                            &outbound_account,
+                            DEALER_UID,
+                            &inbound_account,
+                            inbound_uid,
+                            amount,
+                            None,
+                            None,
+                            Some(txid.clone()),
+                            Some(txid),
+                            None,
+                            Some(String::from("SettlementBatch")),
+                        )
+                        .ok(),
+                    Err(err) => {
+                        slog::error!(self.logger, "Failed to book settlement payment: {:?}", err);
+                        None
+                    }
+                };
+
+                members.push(SettlementBatchMember {
+                    group_id: batch.group_id,
+                    req_id: payment.req_id,
+                    kind: batch.kind.to_string(),
+                    direction: String::from("Payment"),
+                    amount: BigDecimal::from_str(&payment.amount_in_sats.to_string()).unwrap_or_default(),
+                    txid,
+                    created_at: now,
+                });
+            }
+        }
+
+        if members.is_empty() {
+            return;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        if let Err(err) = SettlementBatchMember::insert_all(&c, &members) {
+            slog::error!(
+                self.logger,
+                "Failed to record settlement batch {}: {:?}",
+                batch.group_id,
+                err
+            );
+        }
+    }
+
+    async fn process_create_invoice_request<F: FnMut(Message, ServiceIdentity)>(
+        &mut self,
+        req: CreateInvoiceRequest,
+        invoice_owner: UserId,
+        listener: &mut F,
+    ) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let account_id = match invoice_owner {
+            BANK_UID => {
+                self.ledger
+                    .bank_liabilities
+                    .get_default_account(Currency::BTC, Some(AccountType::External))
+                    .account_id
+            }
+            DEALER_UID => {
+                self.ledger
+                    .dealer_accounts
+                    .get_default_account(Currency::BTC, Some(AccountType::Internal))
+                    .account_id
+            }
+            _ => panic!("Unexpected invoice owner. It can be Bank or Dealer only"),
+        };
+
+        if let Ok(invoice) = self
+            .lnd_connector
+            .create_invoice(req.amount, req.memo, invoice_owner, account_id, None)
+            .await
+        {
+            self.metrics.invoices_created.inc();
+            slog::info!(self.logger, "Inserting invoice into db: {:?}", invoice);
+            if let Err(_err) = invoice.insert(&c) {
+                slog::error!(self.logger, "Couldn't insert invoice: {:?}", invoice);
+                return;
+            }
+
+            let create_invoice_response = CreateInvoiceResponse {
+                req_id: req.req_id,
+                payment_request: invoice.payment_request,
+                amount: req.amount,
+                netted_req_ids: Vec::new(),
+            };
+
+            let msg = Message::Dealer(Dealer::CreateInvoiceResponse(create_invoice_response));
+            listener(msg, ServiceIdentity::Dealer)
+        }
+    }
+
+    /// Starts a cash out by swapping the whole fiat balance to BTC, the invoice is paid once the swap is done.
+    fn process_cash_out_request<F: FnMut(Message, ServiceIdentity)>(&mut self, msg: CashOutRequest, listener: &mut F) {
+        slog::info!(self.logger, "Received cash out request: {:?}", msg);
+
+        let error = if self.withdrawals_halted {
+            Some(PaymentResponseError::WithdrawalsHalted)
+        } else if !self.check_access_policy(msg.uid, &msg.origin) {
+            Some(PaymentResponseError::OriginNotAllowed)
+        } else if self.is_frozen(msg.uid) {
+            Some(PaymentResponseError::UserFrozen)
+        } else if self.is_default_account_frozen(msg.uid, msg.currency)
+            || self.is_default_account_frozen(msg.uid, Currency::BTC)
+        {
+            Some(PaymentResponseError::AccountFrozen)
+        } else if msg.currency == Currency::BTC
+            || !self.available_currencies.contains(&msg.currency)
+            || self.is_insurance_fund_depleted()
+        {
+            Some(PaymentResponseError::RateNotAvailable)
+        } else {
+            None
+        };
+
+        let invoice_amount = match msg.payment_request.parse::<lightning_invoice::Invoice>() {
+            Ok(decoded) => decoded.amount_milli_satoshis(),
+            Err(_) => None,
+        };
+
+        let error = error.or(match invoice_amount {
+            Some(_) => None,
+            None => Some(PaymentResponseError::InvalidInvoice),
+        });
+
+        let balance = self
+            .ledger
+            .user_accounts
+            .get_mut(&msg.uid)
+            .map(|user_account| user_account.get_default_account(msg.currency, None).balance);
+
+        let error = error.or(match balance {
+            Some(balance) if balance > dec!(0) => None,
+            Some(_) => Some(PaymentResponseError::InsufficientFunds),
+            None => Some(PaymentResponseError::UserAccountNotFound),
+        });
+
+        let error = error.or_else(|| {
+            let amount = Money::from_msats(Decimal::new(invoice_amount.unwrap_or(0) as i64, 0));
+            let thresholds = &self.totp_settings.payment_thresholds;
+            if self.check_totp(msg.uid, &amount, thresholds, msg.totp_code.as_deref()) {
+                None
+            } else {
+                Some(PaymentResponseError::InvalidTotpCode)
+            }
+        });
+
+        if let Some(error) = error {
+            let payment_response = PaymentResponse::error(
+                error,
+                msg.req_id,
+                msg.uid,
+                Some(msg.payment_request),
+                msg.currency,
+                None,
+            );
+            let msg = Message::Api(Api::PaymentResponse(payment_response));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        }
+
+        // Same worst case fee the payment itself will be checked against.
+        let invoice_amount = Money::from_msats(Decimal::new(invoice_amount.unwrap_or(0) as i64, 0)).value;
+        let required_btc = invoice_amount + self.fee_budget(invoice_amount);
+
+        let swap_request = SwapRequest {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            amount: Money::new(msg.currency, balance),
+            from: msg.currency,
+            to: Currency::BTC,
+            quote_id: None,
+            signed_quote: None,
+            idempotency_key: None,
+            origin: msg.origin.clone(),
+            totp_code: None,
+        };
+
+        self.pending_cash_outs.insert(
+            msg.req_id,
+            PendingCashOut {
+                request: msg,
+                required_btc,
+            },
+        );
+
+        let msg = Message::Api(Api::SwapRequest(swap_request));
+        listener(msg, ServiceIdentity::Dealer);
+    }
+
+    /// Whether the BTC account can pay the invoice of the cash out once the swap is done.
+    /// The balance is rounded down to sats so rounding never makes the payment fail for fees.
+    fn covers_cash_out(&mut self, swap_response: &SwapResponse, cash_out: &PendingCashOut) -> bool {
+        let rate = match (&swap_response.rate, swap_response.success) {
+            (Some(rate), true) => rate,
+            // Failed swaps are reported as usual.
+            _ => return true,
+        };
+
+        let swapped = match swap_response.amount.exchange(rate) {
+            Ok(swapped) => swapped.value,
+            Err(_) => return false,
+        };
+
+        let btc_balance = match self.ledger.user_accounts.get_mut(&swap_response.uid) {
+            Some(user_account) => user_account.get_default_account(Currency::BTC, None).balance,
+            None => return false,
+        };
+
+        (btc_balance + swapped).round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::ToZero) >= cash_out.required_btc
+    }
+
+    /// Pays the invoice of a cash out after its swap succeeded, otherwise rejects the cash out.
+    fn continue_cash_out<F: FnMut(Message, ServiceIdentity)>(
+        &mut self,
+        cash_out: PendingCashOut,
+        swap_response: Option<SwapResponse>,
+        listener: &mut F,
+    ) {
+        let request = cash_out.request;
+
+        let error = match swap_response {
+            Some(swap_response) if swap_response.success => {
+                self.totp_verified.insert(request.req_id);
+                let payment_request = PaymentRequest {
+                    req_id: request.req_id,
+                    uid: request.uid,
+                    payment_request: Some(request.payment_request),
+                    currency: Currency::BTC,
+                    receipient: None,
+                    target_account_id: None,
+                    account_id: None,
+                    destination: None,
+                    keysend_message: None,
+                    amount: None,
+                    rate: None,
+                    fees: None,
+                    requoted: false,
+                    idempotency_key: None,
+                    origin: request.origin,
+                    totp_code: None,
+                };
+                let msg = Message::Api(Api::PaymentRequest(payment_request));
+                listener(msg, ServiceIdentity::Loopback);
+                return;
+            }
+            Some(SwapResponse {
+                error: Some(SwapResponseError::NotEnoughAvailableBalance),
+                ..
+            }) => PaymentResponseError::InsufficientFundsForFees,
+            _ => PaymentResponseError::RateNotAvailable,
+        };
+
+        slog::info!(self.logger, "Cash out {} failed: {:?}", request.req_id, error);
+        let payment_response = PaymentResponse::error(
+            error,
+            request.req_id,
+            request.uid,
+            Some(request.payment_request),
+            request.currency,
+            None,
+        );
+        let msg = Message::Api(Api::PaymentResponse(payment_response));
+        listener(msg, ServiceIdentity::Api);
+    }
+
+    fn process_swap_response<F: FnMut(Message, ServiceIdentity)>(&mut self, mut msg: SwapResponse, listener: &mut F) {
+        slog::warn!(self.logger, "Received swap response: {:?}", msg);
+        if msg.error.is_some() || !msg.success {
+            let msg = Message::Api(Api::SwapResponse(msg));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        }
+
+        let mut swap_response = msg.clone();
+
+        // Checking whether we can convert into the target curreny.
+        if !self.available_currencies.contains(&msg.to) {
+            swap_response.success = false;
+            swap_response.error = Some(SwapResponseError::CurrencyNotAvailable);
+            let msg = Message::Api(Api::SwapResponse(swap_response));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                swap_response.success = false;
+                swap_response.error = Some(SwapResponseError::DatabaseConnectionFailed);
+                let msg = Message::Api(Api::SwapResponse(swap_response));
+                listener(msg, ServiceIdentity::Api);
+                return;
+            }
+        };
+
+        let uid = msg.uid;
+        let swap_amount = msg.amount.clone();
+
+        let rate = match msg.rate {
+            Some(ref rate) => rate,
+            None => {
+                swap_response.success = false;
+                swap_response.error = Some(SwapResponseError::CurrencyNotAvailable);
+                let msg = Message::Api(Api::SwapResponse(swap_response));
+                listener(msg, ServiceIdentity::Api);
+                return;
+            }
+        };
+
+        let (mut outbound_account, mut inbound_account) = {
+            let user_account = match self.ledger.user_accounts.get_mut(&msg.uid) {
+                Some(ua) => ua,
+                None => {
+                    swap_response.success = false;
+                    swap_response.error = Some(SwapResponseError::UserAccountNotFound);
+                    let msg = Message::Api(Api::SwapResponse(swap_response));
+                    listener(msg, ServiceIdentity::Api);
+                    return;
+                }
+            };
+
+            let outbound_account = user_account.get_default_account(msg.from, None);
+            let inbound_account = user_account.get_default_account(msg.to, None);
+
+            (outbound_account, inbound_account)
+        };
+
+        let (mut outbound_dealer_account, mut inbound_dealer_account) = {
+            let outbound_dealer_account = self
+                .ledger
+                .dealer_accounts
+                .get_default_account(msg.to, Some(AccountType::Internal));
+            let inbound_dealer_account = self
+                .ledger
+                .dealer_accounts
+                .get_default_account(msg.from, Some(AccountType::Internal));
+            (outbound_dealer_account, inbound_dealer_account)
+        };
+
+        let fee = self.operation_fee(uid, msg.from, FeeOperation::Swap, swap_amount.value);
+
+        if outbound_account.balance < swap_amount.value + fee.value {
+            slog::info!(
+                self.logger,
+                "User: {} has not enough available balance. Available: {}",
+                uid,
+                outbound_account.balance
+            );
+            msg.success = false;
+            msg.error = Some(SwapResponseError::NotEnoughAvailableBalance);
+            let msg = Message::Api(Api::SwapResponse(msg));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        }
+
+        let fees = Money::new(msg.to, None);
+
+        let outbound_txid = if let Ok(txid) = self.make_tx(
+            &mut outbound_account,
+            uid,
+            &mut inbound_dealer_account,
+            BANK_UID,
+            msg.amount.clone(),
+        ) {
+            txid
+        } else {
+            slog::info!(self.logger, "SWAP tx didn't go through on outbound.");
+            swap_response.success = false;
+            swap_response.error = Some(SwapResponseError::TransactionFailed);
+            let msg = Message::Api(Api::SwapResponse(swap_response));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        };
+
+        let value = msg.amount.clone();
+
+        let inbound_amount = value.clone().exchange(&rate).unwrap();
+
+        let inbound_txid = if let Ok(txid) = self.make_tx(
+            &mut outbound_dealer_account,
+            BANK_UID,
+            &mut inbound_account,
+            uid,
+            inbound_amount,
+        ) {
+            txid
+        } else {
+            slog::info!(self.logger, "SWAP tx didn't go through on inbound.");
+            swap_response.success = false;
+            swap_response.error = Some(SwapResponseError::TransactionFailed);
+            let msg = Message::Api(Api::SwapResponse(swap_response));
+            listener(msg, ServiceIdentity::Api);
+            return;
+        };
+
+        if let Err(err) = self.collect_fee(&mut outbound_account, uid, FeeOperation::Swap, &fee) {
+            slog::error!(self.logger, "Failed to collect fee of swap {}: {}", msg.req_id, err);
+        }
+        self.fee_engine
+            .record_volume(uid, msg.from, swap_amount.value, utils::time::time_now());
+
+        self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+        self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(outbound_dealer_account.account_id, outbound_dealer_account.clone());
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
+
+        self.update_account(&outbound_account, uid);
+        self.update_account(&inbound_account, uid);
+
+        self.update_account(&outbound_dealer_account, uid);
+        self.update_account(&inbound_dealer_account, uid);
+
+        self.share_fee_with_referrer(uid, &fee);
+
+        // The spread the dealer quoted, it is booked as fee of the swap.
+        let spread = swap_response.fees.clone();
+
+        let msg = Message::Api(Api::SwapResponse(swap_response));
+        listener(msg, ServiceIdentity::Api);
+
+        // Updating the dealer of the new state of the bank.
+        let bank_state = self.get_bank_state();
+        let msg = Message::Dealer(Dealer::BankState(bank_state));
+        listener(msg, ServiceIdentity::Dealer);
+
+        if self
+            .make_summary_tx(
+                &outbound_account,
+                uid,
+                &inbound_account,
+                uid,
+                value,
+                Some(rate.clone()),
+                spread,
+                Some(outbound_txid),
+                Some(inbound_txid),
+                None,
+                Some(String::from("Swap")),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of swap for user {}.",
+                uid
+            );
+        }
+    }
+
+    /// Checks with the node that the invoice of a deposit is settled and paid in full rather than trusting the
+    /// subscription alone. Deposits the node rejects are recorded as anomalies and not credited.
+    async fn confirm_deposit(&mut self, conn: &diesel::PgConnection, invoice: &Invoice) -> DepositCheck {
+        let (reason, paid_msat) = match self.lnd_connector.lookup_invoice(&invoice.rhash).await {
+            Ok(lookup) if !lookup.settled => ("not settled", lookup.amt_paid_msat),
+            Ok(lookup) if lookup.amt_paid_msat < invoice.value_msat => ("underpaid", lookup.amt_paid_msat),
+            Ok(_) => return DepositCheck::Confirmed,
+            Err(err) => {
+                slog::warn!(
+                    self.logger,
+                    "Failed to look up invoice of deposit {}, retrying: {:?}",
+                    invoice.payment_request,
+                    err
+                );
+                return DepositCheck::Unknown;
+            }
+        };
+
+        slog::error!(
+            self.logger,
+            "Not crediting deposit {}, {}: expected {} msat, paid {} msat",
+            invoice.payment_request,
+            reason,
+            invoice.value_msat,
+            paid_msat
+        );
+        let anomaly = InsertableDepositAnomaly {
+            payment_request: invoice.payment_request.clone(),
+            reason: reason.to_string(),
+            expected_msat: invoice.value_msat,
+            paid_msat: Some(paid_msat),
+            created_at: utils::time::time_now() as i64,
+        };
+        if let Err(err) = anomaly.insert(conn) {
+            slog::error!(self.logger, "Failed to store deposit anomaly: {:?}", err);
+        }
+        DepositCheck::Rejected
+    }
+
+    fn retry_deposit(&mut self, deposit: Deposit) {
+        if !self
+            .retried_deposits
+            .iter()
+            .any(|retried| retried.payment_request == deposit.payment_request)
+        {
+            self.retried_deposits.push(deposit);
+        }
+    }
+
+    /// Processes the deposits that failed on an unavailable node or database again.
+    pub async fn retry_deposits<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        for deposit in std::mem::take(&mut self.retried_deposits) {
+            self.process_msg(Message::Deposit(deposit), listener).await;
+        }
+    }
+
+    /// Returns the account of the first deposit routing rule of the invoice owner matching the invoice.
+    /// Rules pointing to an account that doesn't exist or has another currency are skipped.
+    fn routed_deposit_account(
+        &self,
+        conn: &diesel::PgConnection,
+        invoice: &Invoice,
+        currency: Currency,
+    ) -> Option<AccountId> {
+        let rules = match DepositRoutingRule::get_by_uid(conn, invoice.uid) {
+            Ok(rules) => rules,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load deposit routing rules: {:?}", err);
+                return None;
+            }
+        };
+        if rules.is_empty() {
+            return None;
+        }
+
+        let user_account = self.ledger.user_accounts.get(&(invoice.uid as UserId))?;
+        let memo = invoice.reference.clone().unwrap_or_default();
+        let metadata_fields = invoice
+            .metadata_fields
+            .as_ref()
+            .and_then(|fields| serde_json::from_str::<HashMap<String, String>>(fields).ok())
+            .unwrap_or_default();
+
+        rules
+            .iter()
+            .filter(|rule| rule.matches(&memo, &metadata_fields))
+            .find(|rule| {
+                user_account
+                    .accounts
+                    .get(&rule.account_id)
+                    .map_or(false, |account| account.currency == currency && !account.archived)
+            })
+            .map(|rule| rule.account_id)
+    }
+
+    /// Returns the sub-account the invoice was created for if it can still receive the deposit.
+    fn invoice_deposit_account(&self, invoice: &Invoice, currency: Currency) -> Option<AccountId> {
+        let account_id = Uuid::parse_str(&invoice.account_id).ok()?;
+        self.ledger
+            .user_accounts
+            .get(&(invoice.uid as UserId))?
+            .accounts
+            .get(&account_id)
+            .filter(|account| account.currency == currency && !account.archived)
+            .map(|account| account.account_id)
+    }
+
+    /// Lets the api dispatch the settlement of a user invoice to the owner's webhooks.
+    fn notify_invoice_settled<F: FnMut(Message, ServiceIdentity)>(
+        &self,
+        invoice: &Invoice,
+        amount: Money,
+        listener: &mut F,
+    ) {
+        let metadata_fields = invoice
+            .metadata_fields
+            .as_ref()
+            .and_then(|fields| serde_json::from_str::<HashMap<String, String>>(fields).ok())
+            .unwrap_or_default();
+
+        let invoice_settled = InvoiceSettled {
+            uid: invoice.uid as UserId,
+            payment_request: invoice.payment_request.clone(),
+            payment_hash: invoice.payment_hash.clone(),
+            amount,
+            metadata_fields,
+            comment: invoice.comment.clone(),
+            payer_data: invoice.payer_data.clone(),
+            description_hash: invoice.description_hash.clone(),
+        };
+        let msg = Message::Api(Api::InvoiceSettled(invoice_settled));
+        listener(msg, ServiceIdentity::Api);
+    }
+
+    fn get_withdrawal_p95(&self) -> Option<u64> {
+        if self.withdrawal_durations.is_empty() {
+            return None;
+        }
+        let mut durations = self.withdrawal_durations.iter().copied().collect::<Vec<u64>>();
+        durations.sort_unstable();
+        let index = (durations.len() * 95 + 99) / 100 - 1;
+        Some(durations[index])
+    }
+
+    /// Marks unsettled invoices past their expiry as expired and notifies the api about them. Open invoices are
+    /// only tracked in the invoices table, the engine keeps no in-memory state for them that needs to be dropped.
+    pub fn expire_invoices<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let expired = match Invoice::expire_unsettled(&psql_connection, utils::time::time_now() as i64) {
+            Ok(expired) => expired,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to expire invoices: {:?}", err);
+                return;
+            }
+        };
+
+        if !expired.is_empty() {
+            slog::info!(self.logger, "Marked {} invoices as expired", expired.len());
+        }
+
+        for invoice in expired {
+            let msg = Message::Api(Api::InvoiceExpired(InvoiceExpired {
+                uid: invoice.uid as u64,
+                payment_request: invoice.payment_request,
+                payment_hash: invoice.payment_hash,
+            }));
+            listener(msg, ServiceIdentity::Api);
+        }
+    }
+
+    /// Routing fee budget in BTC of a payment of `amount` BTC.
+    fn fee_budget(&self, amount: Decimal) -> Decimal {
+        fee_budget(&self.ln_fee_budgets, amount, self.ln_network_fee_margin)
+    }
+
+    /// Fee in msat of the best route to the payee of the invoice, `None` if no route was found. Probes go over the
+    /// bank's connection and found routes are reused for `PROBE_TTL_MS`.
+    async fn probe_fee_msat(
+        &mut self,
+        payment_request: &str,
+        max_fee: Decimal,
+    ) -> Result<Option<i64>, LndConnectorError> {
+        let key = payment_request
+            .parse::<lightning_invoice::Invoice>()
+            .ok()
+            .map(|invoice| {
+                (
+                    invoice.payment_hash().to_string(),
+                    invoice.amount_milli_satoshis().unwrap_or(0),
+                )
+            });
+        let now = utils::time::time_now();
+        if let Some(fee_msat) = key.as_ref().and_then(|key| self.probe_cache.get(key, now)) {
+            return Ok(Some(fee_msat));
+        }
+
+        let routes = self.lnd_connector.probe(payment_request.to_string(), max_fee).await?;
+        let fee_msat = routes.first().map(|route| route.total_fees_msat);
+        if let (Some(key), Some(fee_msat)) = (key, fee_msat) {
+            self.probe_cache.insert(key, fee_msat, now);
+        }
+        Ok(fee_msat)
+    }
+
+    /// Debits a keysend payment like the payment of an external invoice and dispatches it. Keysend payments are
+    /// only made from BTC accounts and recorded in the invoices table under `Keysend::payment_request`. The 2FA code
+    /// belongs to the `requester`, who pays on behalf of the owner of a shared account.
+    fn make_keysend_payment<F: FnMut(Message, ServiceIdentity)>(
+        &mut self,
+        msg: PaymentRequest,
+        requester: UserId,
+        mut outbound_account: Account,
+        destination: String,
+        listener: &mut F,
+    ) {
+        let uid = msg.uid;
+        let (preimage, payment_hash) = lnd_connector::connector::keysend_preimage();
+        let keysend = Keysend {
+            destination,
+            preimage: hex::encode(preimage),
+            payment_hash,
+            message: msg.keysend_message.clone(),
+        };
+
+        let mut respond_error = |error: PaymentResponseError| {
+            let payment_response = PaymentResponse::error(error, msg.req_id, uid, None, msg.currency, None);
+            listener(
+                Message::Api(Api::PaymentResponse(payment_response)),
+                ServiceIdentity::Api,
+            );
+        };
+
+        if msg.currency != Currency::BTC {
+            respond_error(PaymentResponseError::NotPermitted);
+            return;
+        }
+        let amount = match msg.amount.clone().filter(|amount| amount.value > dec!(0)) {
+            Some(amount) => amount,
+            None => {
+                respond_error(PaymentResponseError::InvalidAmount);
+                return;
+            }
+        };
+        let amount_in_sats = match amount.try_sats() {
+            Ok(sats) => sats.ceil().to_u64().unwrap_or(0),
+            Err(_) => {
+                respond_error(PaymentResponseError::InvalidAmount);
+                return;
+            }
+        };
+        let amount_in_msats = amount
+            .try_msats()
+            .ok()
+            .and_then(|msats| msats.ceil().to_u64())
+            .unwrap_or(amount_in_sats * 1000);
+
+        if let Some(max_payable_sats) = self.exceeded_outbound_capacity(amount_in_sats) {
+            respond_error(PaymentResponseError::TemporaryLiquidityShortage { max_payable_sats });
+            return;
+        }
+
+        // Approved payments come back through the loopback, their code was checked before they were held.
+        let is_approved = self.approved_withdrawals.remove(&msg.req_id);
+        let thresholds = &self.totp_settings.payment_thresholds;
+        if !is_approved && !self.check_totp(requester, &amount, thresholds, msg.totp_code.as_deref()) {
+            respond_error(PaymentResponseError::InvalidTotpCode);
+            return;
+        }
+
+        let psql_connection = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(psql_connection) => psql_connection,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                respond_error(PaymentResponseError::DatabaseConnectionFailed);
+                return;
+            }
+        };
+
+        let payment_request = keysend.payment_request();
+        match self.check_withdrawal_velocity(&psql_connection, uid, &payment_request, amount_in_sats) {
+            Ok(true) => {}
+            Ok(false) => {
+                respond_error(PaymentResponseError::VelocityLimitExceeded);
+                return;
+            }
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to check withdrawal velocity of user {}: {:?}",
+                    uid,
+                    err
+                );
+                respond_error(PaymentResponseError::DatabaseConnectionFailed);
+                return;
+            }
+        }
+
+        if !is_approved {
+            if let Some(error) = self.hold_for_approval(&psql_connection, &msg, amount_in_sats, amount_in_msats) {
+                respond_error(error);
+                return;
+            }
+        }
+
+        let max_fee = Money::from_btc(self.fee_budget(amount.value));
+        let amount_plus_max_fee = Money::from_btc(amount.value + max_fee.value);
+        let service_fee = self.operation_fee(uid, Currency::BTC, FeeOperation::External, amount.value);
+        if outbound_account.balance < amount_plus_max_fee.value + service_fee.value {
+            respond_error(PaymentResponseError::InsufficientFundsForFees);
+            return;
+        }
+
+        let invoice = Invoice {
+            payment_request: payment_request.clone(),
+            rhash: keysend.payment_hash.clone(),
+            payment_hash: keysend.payment_hash.clone(),
+            created_at: utils::time::time_now() as i64,
+            value: amount_in_sats as i64,
+            value_msat: amount_in_sats as i64 * 1000,
+            expiry: 0,
+            settled: false,
+            add_index: -1,
+            settled_date: 0,
+            uid: uid as i32,
+            account_id: outbound_account.account_id.to_string(),
+            owner: None,
+            fees: None,
+            incoming: false,
+            currency: Some(Currency::BTC.to_string()),
+            target_account_currency: None,
+            reference: keysend.message.clone(),
+            expired: false,
+            metadata_fields: None,
+            reference_hash: None,
+            metadata_fields_hash: None,
+            descriptions_hashed_at: None,
+            asset_units: None,
+            comment: None,
+            payer_data: None,
+            description_hash: None,
+        };
+        if let Err(err) = invoice.insert(&psql_connection) {
+            slog::error!(
+                self.logger,
+                "Failed to record keysend payment {}: {:?}",
+                msg.req_id,
+                err
+            );
+            respond_error(PaymentResponseError::DatabaseConnectionFailed);
+            return;
+        }
+
+        let mut bank_liability_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::BTC, Some(AccountType::External));
+        let txid = match self.make_tx(
+            &mut outbound_account,
+            uid,
+            &mut bank_liability_account,
+            BANK_UID,
+            amount_plus_max_fee.clone(),
+        ) {
+            Ok(txid) => txid,
+            Err(_) => {
+                respond_error(PaymentResponseError::TransactionFailed);
+                return;
+            }
+        };
+
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(bank_liability_account.account_id, bank_liability_account.clone());
+        self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+        self.update_account(&outbound_account, uid);
+        self.update_account(&bank_liability_account, BANK_UID);
+
+        // The message sent along is kept with the transaction.
+        let reference = match &keysend.message {
+            Some(message) => format!("Keysend: {}", message),
+            None => String::from("Keysend"),
+        };
+        if self
+            .make_summary_tx(
+                &outbound_account,
+                uid,
+                &bank_liability_account,
+                BANK_UID,
+                amount_plus_max_fee.clone(),
+                None,
+                None,
+                Some(txid.clone()),
+                Some(txid),
+                None,
+                Some(reference),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of keysend {}.",
+                msg.req_id
+            );
+        }
+
+        if service_fee.value > Decimal::ZERO {
+            if let Err(err) = self.collect_fee(&mut outbound_account, uid, FeeOperation::External, &service_fee) {
+                slog::error!(self.logger, "Failed to collect fee of payment {}: {}", msg.req_id, err);
+            }
+            self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+            self.update_account(&outbound_account, uid);
+        }
+        self.fee_engine
+            .record_volume(uid, Currency::BTC, amount.value, utils::time::time_now());
+
+        self.ledger.add_pending(
+            msg.req_id,
+            PendingFunds {
+                uid,
+                account_id: outbound_account.account_id,
+                amount: -amount_plus_max_fee.value,
+            },
+        );
+
+        let rate = Rate {
+            base: Currency::BTC,
+            quote: Currency::BTC,
+            value: dec!(1),
+            ..Default::default()
+        };
+        let payment_response = PaymentResponse {
+            amount: Some(amount),
+            payment_hash: keysend.payment_hash.clone(),
+            req_id: msg.req_id,
+            uid,
+            success: false,
+            payment_request: None,
+            currency: Currency::BTC,
+            fees: Some(max_fee.clone()),
+            rate: Some(rate.clone()),
+            error: None,
+            preimage: None,
+        };
+        let pending = PaymentResult {
+            uid,
+            currency: Currency::BTC,
+            rate,
+            is_success: false,
+            amount: amount_plus_max_fee,
+            payment_response,
+            error: None,
+            max_fee_in_sats: max_fee.try_sats().unwrap_or(Decimal::ZERO),
+            attempts: 0,
+            is_retryable: false,
+            service_fee: Some(service_fee),
+            keysend: Some(keysend),
+        };
+        self.spawn_payment_task(pending);
+    }
+
+    /// Pays the invoice of a pending payment on a separate task. The outcome is sent
+    /// back to the bank as a `Bank::PaymentResult`. The payment is persisted first so it can be recovered if
+    /// the bank stops before its result is booked, it is refunded right away if that fails.
+    fn spawn_payment_task(&mut self, pending: PaymentResult) {
+        self.payment_starts
+            .entry(pending.payment_response.req_id)
+            .or_insert_with(Instant::now);
+        if let Ok(sats) = pending.amount.try_sats() {
+            self.withdrawals_in_flight.insert(
+                pending.payment_response.req_id,
+                (pending.uid, sats.ceil().to_u64().unwrap_or(0)),
+            );
+        }
+
+        let payment_task_sender = self.payment_thread_sender.clone();
+
+        if let Err(err) = self.persist_pending_payment(&pending) {
+            slog::error!(
+                self.logger,
+                "Failed to persist payment {}, refunding it: {}",
+                pending.payment_response.req_id,
+                err
+            );
+            let msg = Message::Bank(Bank::PaymentResult(payment_result(
+                pending,
+                Err(LndConnectorError::FailedToSendPayment),
+            )));
+            if let Err(err) = payment_task_sender.send(msg) {
+                panic!("Failed to send a payment task: {:?}", err);
+            }
+            return;
+        }
+
+        self.metrics.payments_attempted.inc();
+        let lnd_connector_pool = self.lnd_connector_pool.clone();
+        let strict_fee_quotes = self.strict_fee_quotes;
+        let payment_hash = pending_payment_hash(&pending);
+        let logger = self.logger.clone();
+        let span = utils::telemetry::request_span("bank.payment", Some(pending.payment_response.req_id));
+
+        let payment_task = tokio::task::spawn(
+            async move {
+                let payment_req = pending.payment_response.payment_request.clone().unwrap_or_default();
+                let amount_in_sats = pending
+                    .payment_response
+                    .amount
+                    .as_ref()
+                    .and_then(|amount| amount.try_sats().ok())
+                    .unwrap_or(dec!(0));
+                let max_fee_in_sats = pending.max_fee_in_sats;
+
+                let mut lnd_connector = match lnd_connector_pool.get().await {
+                    Ok(lnd_connector) => lnd_connector,
+                    Err(err) => {
+                        let msg = Message::Bank(Bank::PaymentResult(payment_result(pending, Err(err))));
+                        if let Err(err) = payment_task_sender.send(msg) {
+                            panic!("Failed to send a payment task: {:?}", err);
+                        }
+                        return;
+                    }
+                };
+                let payment = if let Some(keysend) = &pending.keysend {
+                    let preimage = hex::decode(&keysend.preimage).unwrap_or_default();
+                    let max_fee_msat = (max_fee_in_sats * dec!(1000)).floor().to_i64().unwrap_or(0);
+                    lnd_connector
+                        .keysend(
+                            &keysend.destination,
+                            amount_in_sats,
+                            max_fee_msat,
+                            &preimage,
+                            keysend.message.as_deref(),
+                        )
+                        .await
+                } else if strict_fee_quotes {
+                    let quoted_fee_msat = (max_fee_in_sats * dec!(1000)).floor().to_i64().unwrap_or(0);
+                    lnd_connector
+                        .pay_invoice_with_quoted_fee(payment_req, amount_in_sats, quoted_fee_msat)
+                        .await
+                } else {
+                    lnd_connector
+                        .pay_invoice(payment_req, amount_in_sats, None, Some(max_fee_in_sats))
+                        .await
+                };
+                dbg!(&payment);
+                // The payment stays pending until lnd knows whether it went through, a crash in the meantime
+                // leaves it to the recovery.
+                let payment = match (payment, &payment_hash) {
+                    (Err(LndConnectorError::PaymentStatusUnknown), Some(payment_hash)) => {
+                        let req_id = pending.payment_response.req_id;
+                        await_payment_outcome(&lnd_connector_pool, payment_hash, req_id, &logger).await
+                    }
+                    (payment, _) => payment,
+                };
+                let msg = Message::Bank(Bank::PaymentResult(payment_result(pending, payment)));
+                if let Err(err) = payment_task_sender.send(msg) {
+                    panic!("Failed to send a payment task: {:?}", err);
+                }
+            }
+            .instrument(span),
+        );
+        self.payment_threads.push(payment_task);
+    }
+
+    fn persist_pending_payment(&self, pending: &PaymentResult) -> Result<(), String> {
+        let psql_connection = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or_else(|| "Couldn't get psql connection".to_string())?;
+
+        let payment_hash = pending_payment_hash(pending).ok_or_else(|| "Invalid payment request".to_string())?;
+        let amount = BigDecimal::from_str(&pending.amount.value.to_string()).map_err(|err| err.to_string())?;
+        let estimated_fee_sats =
+            BigDecimal::from_str(&pending.max_fee_in_sats.to_string()).map_err(|err| err.to_string())?;
+        let payment_result = serde_json::to_string(pending).map_err(|err| err.to_string())?;
+
+        PendingPayment {
+            req_id: pending.payment_response.req_id.to_string(),
+            uid: pending.uid as i32,
+            payment_hash,
+            amount,
+            estimated_fee_sats,
+            payment_result,
+            created_at: utils::time::time_now() as i64,
+        }
+        .upsert(&psql_connection)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+    }
+
+    /// Looks up the payments that were in flight when the bank stopped and books their outcome once lnd knows
+    /// it. Payments lnd never dispatched are refunded. Retries whose attempt was never dispatched are due again.
+    /// Has to run once the ledger is loaded.
+    pub fn recover_pending_payments(&mut self) {
+        let psql_connection = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(psql_connection) => psql_connection,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let pending_payments = match PendingPayment::get_all(&psql_connection) {
+            Ok(pending_payments) => pending_payments,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load pending payments: {:?}", err);
+                return;
+            }
+        };
+
+        match PaymentRetry::get_in_flight(&psql_connection) {
+            Ok(retries) => {
+                let now = utils::time::time_now() as i64;
+                for mut retry in interrupted_retries(retries, &pending_payments) {
+                    slog::warn!(
+                        self.logger,
+                        "Rescheduling interrupted retry of payment {}",
+                        retry.req_id
+                    );
+                    retry.next_attempt_at = now;
+                    if let Err(err) = retry.update(&psql_connection) {
+                        slog::error!(
+                            self.logger,
+                            "Failed to reschedule payment retry {}: {:?}",
+                            retry.req_id,
+                            err
+                        );
+                    }
+                }
+            }
+            Err(err) => slog::error!(self.logger, "Failed to load payment retries: {:?}", err),
+        }
+
+        for pending_payment in pending_payments {
+            let pending = match serde_json::from_str::<PaymentResult>(&pending_payment.payment_result) {
+                Ok(pending) => pending,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to deserialize pending payment {}: {:?}",
+                        pending_payment.req_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+            slog::warn!(
+                self.logger,
+                "Recovering payment {} of user {} with hash {}",
+                pending_payment.req_id,
+                pending_payment.uid,
+                pending_payment.payment_hash
+            );
+            self.restore_pending_funds(&pending);
+
+            let payment_task_sender = self.payment_thread_sender.clone();
+            let lnd_connector_pool = self.lnd_connector_pool.clone();
+            let logger = self.logger.clone();
+
+            let recovery_task = tokio::task::spawn(async move {
+                let req_id = pending.payment_response.req_id;
+                let payment =
+                    await_payment_outcome(&lnd_connector_pool, &pending_payment.payment_hash, req_id, &logger).await;
+                let mut result = payment_result(pending, payment);
+                // The outcome is final, a payment lnd gave up on isn't attempted again.
+                result.is_retryable = false;
+                let msg = Message::Bank(Bank::PaymentResult(result));
+                if let Err(err) = payment_task_sender.send(msg) {
+                    panic!("Failed to send a payment task: {:?}", err);
+                }
+            });
+            self.payment_threads.push(recovery_task);
+        }
+    }
+
+    /// Pending funds aren't restored after a restart, payments loaded from the database re-register theirs.
+    fn restore_pending_funds(&mut self, pending: &PaymentResult) {
+        let req_id = pending.payment_response.req_id;
+        if self.ledger.pending_funds.contains_key(&req_id) {
+            return;
+        }
+        let account = self
+            .ledger
+            .user_accounts
+            .get_mut(&pending.uid)
+            .map(|user_account| user_account.get_default_account(pending.currency, None));
+        if let (Some(account), Ok(reserved)) = (account, pending.amount.exchange(&pending.rate)) {
+            self.ledger.add_pending(
+                req_id,
+                PendingFunds {
+                    uid: pending.uid,
+                    account_id: account.account_id,
+                    amount: -reserved.value,
+                },
+            );
+        }
+    }
+
+    /// Seeds the volumes fee tiers are picked by with the transfers, payments and swaps of the volume window.
+    pub fn init_fee_volumes(&mut self) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let now = utils::time::time_now();
+        let from = now.saturating_sub(self.fee_engine.volume_window());
+        let txs = match SummaryTransaction::get_between(&psql_connection, from as i64, now as i64) {
+            Ok(txs) => txs,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to fetch summary transactions for fee volumes: {:?}",
+                    err
+                );
+                return;
+            }
+        };
+
+        for tx in txs {
+            let charged = matches!(
+                tx.reference.as_deref(),
+                Some("InternalTransfer") | Some("ExternalPayment") | Some("Swap")
+            ) || matches!(tx.reference.as_deref(), Some(reference) if reference.starts_with("Keysend"));
+            let uid = tx.outbound_uid as UserId;
+            if !charged || uid == BANK_UID || uid == DEALER_UID {
+                continue;
+            }
+            if let (Ok(currency), Ok(amount)) = (
+                Currency::from_str(&tx.outbound_currency),
+                Decimal::from_str(&tx.outbound_amount.to_string()),
+            ) {
+                self.fee_engine
+                    .record_volume(uid, currency, amount, tx.created_at as u64);
+            }
+        }
+    }
+
+    /// Fee of an operation paid from an account of the currency. The bank and the dealer are never charged.
+    fn operation_fee(&mut self, uid: UserId, currency: Currency, operation: FeeOperation, amount: Decimal) -> Money {
+        if uid == BANK_UID || uid == DEALER_UID {
+            return Money::new(currency, Some(Decimal::ZERO));
+        }
+        let fee = self
+            .fee_engine
+            .fee(uid, currency, operation, amount, utils::time::time_now());
+        Money::new(currency, Some(fee))
+    }
+
+    /// Posts a fee from the account to the fee income account of its currency. The caller persists the account.
+    fn collect_fee(
+        &mut self,
+        account: &mut Account,
+        uid: UserId,
+        operation: FeeOperation,
+        fee: &Money,
+    ) -> Result<(), BankError> {
+        if fee.value <= Decimal::ZERO {
+            return Ok(());
+        }
+
+        let mut fee_account = self.ledger.get_fee_account(fee.currency);
+        let txid = self.make_tx(account, uid, &mut fee_account, BANK_UID, fee.clone())?;
+
+        self.ledger
+            .fee_account
+            .accounts
+            .insert(fee_account.account_id, fee_account.clone());
+        self.update_account(&fee_account, BANK_UID);
+
+        self.make_summary_tx(
+            account,
+            uid,
+            &fee_account,
+            BANK_UID,
+            fee.clone(),
+            None,
+            Some(fee.clone()),
+            None,
+            None,
+            Some(txid),
+            Some(format!("{}Fee", operation)),
+        )?;
+        Ok(())
+    }
+
+    /// Returns a collected fee of an operation that didn't go through. The caller persists the account.
+    fn refund_fee(
+        &mut self,
+        account: &mut Account,
+        uid: UserId,
+        operation: FeeOperation,
+        fee: &Money,
+    ) -> Result<(), BankError> {
+        if fee.value <= Decimal::ZERO {
+            return Ok(());
+        }
+
+        let mut fee_account = self.ledger.get_fee_account(fee.currency);
+        let txid = self.make_tx(&mut fee_account, BANK_UID, account, uid, fee.clone())?;
+
+        self.ledger
+            .fee_account
+            .accounts
+            .insert(fee_account.account_id, fee_account.clone());
+        self.update_account(&fee_account, BANK_UID);
+
+        self.make_summary_tx(
+            &fee_account,
+            BANK_UID,
+            account,
+            uid,
+            fee.clone(),
+            None,
+            Some(fee.clone()),
+            None,
+            None,
+            Some(txid),
+            Some(format!("{}FeeRefund", operation)),
+        )?;
+        Ok(())
+    }
+
+    /// Pays the referrer of the user their share of a collected fee out of the fee account. Must be
+    /// called once the caller persisted its accounts, as the referrer may own one of them.
+    fn share_fee_with_referrer(&mut self, uid: UserId, fee: &Money) {
+        let share = match self.referral_share {
+            Some(share) if share > Decimal::ZERO => share,
+            _ => return,
+        };
+        if fee.value <= Decimal::ZERO {
+            return;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let referrer_uid = match Referral::get_by_referee(&c, uid as i32) {
+            Ok(Some(referral)) => referral.referrer_uid as UserId,
+            Ok(None) => return,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch referrer of {}: {}", uid, err);
+                return;
+            }
+        };
+
+        let payout = Money::new(
+            fee.currency,
+            Some((fee.value * share).round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::ToZero)),
+        );
+        if payout.value <= Decimal::ZERO {
+            return;
+        }
+
+        let mut fee_account = self.ledger.get_fee_account(fee.currency);
+        let mut referrer_account = self
+            .ledger
+            .user_accounts
+            .entry(referrer_uid)
+            .or_insert_with(|| UserAccount::new(referrer_uid))
+            .get_default_account(fee.currency, None);
+
+        let txid = match self.make_tx(
+            &mut fee_account,
+            BANK_UID,
+            &mut referrer_account,
+            referrer_uid,
+            payout.clone(),
+        ) {
+            Ok(txid) => txid,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to pay referrer {} of {}: {}",
+                    referrer_uid,
+                    uid,
+                    err
+                );
+                return;
+            }
+        };
+
+        self.ledger
+            .fee_account
+            .accounts
+            .insert(fee_account.account_id, fee_account.clone());
+        self.insert_into_ledger(&referrer_uid, referrer_account.account_id, referrer_account.clone());
+        self.update_account(&fee_account, BANK_UID);
+        self.update_account(&referrer_account, referrer_uid);
+
+        if let Err(err) = self.make_summary_tx(
+            &fee_account,
+            BANK_UID,
+            &referrer_account,
+            referrer_uid,
+            payout.clone(),
+            None,
+            Some(payout),
+            Some(txid.clone()),
+            Some(txid),
+            None,
+            Some(String::from("ReferralPayout")),
+        ) {
+            slog::error!(
+                self.logger,
+                "Failed to record referral payout to {}: {}",
+                referrer_uid,
+                err
+            );
+        }
+    }
+
+    /// Returns the part of the fee reserve a payment didn't use to the account it was debited from.
+    /// Fiat reserves are exchanged back at the rate they were debited at.
+    fn refund_unused_fees(
+        &mut self,
+        uid: UserId,
+        account: &mut Account,
+        unused_fees: Money,
+        rate: &Rate,
+    ) -> Result<(), BankError> {
+        let mut btc_liabilities_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::BTC, Some(AccountType::External));
+
+        if account.currency == Currency::BTC {
+            let txid = self.make_tx(
+                &mut btc_liabilities_account,
+                BANK_UID,
+                account,
+                uid,
+                unused_fees.clone(),
+            )?;
+
+            self.ledger
+                .bank_liabilities
+                .accounts
+                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+            self.insert_into_ledger(&uid, account.account_id, account.clone());
+
+            self.update_account(account, uid);
+            self.update_account(&btc_liabilities_account, BANK_UID);
+
+            self.make_summary_tx(
+                &btc_liabilities_account,
+                BANK_UID,
+                account,
+                uid,
+                unused_fees,
+                None,
+                None,
+                Some(txid.clone()),
+                Some(txid),
+                None,
+                Some(String::from("FeeRefund")),
+            )?;
+            return Ok(());
+        }
+
+        let unused_fees_exchanged = unused_fees.exchange(rate).map_err(|_| BankError::FailedTransaction)?;
+
+        let mut dealer_btc_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(Currency::BTC, Some(AccountType::Internal));
+        let mut dealer_fiat_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(account.currency, Some(AccountType::Internal));
+
+        let outbound_txid = self.make_tx(
+            &mut btc_liabilities_account,
+            BANK_UID,
+            &mut dealer_btc_account,
+            DEALER_UID,
+            unused_fees.clone(),
+        )?;
+        let inbound_txid = self.make_tx(
+            &mut dealer_fiat_account,
+            DEALER_UID,
+            account,
+            uid,
+            unused_fees_exchanged,
+        )?;
+
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+
+        self.update_account(account, uid);
+        self.update_account(&btc_liabilities_account, BANK_UID);
+        self.update_account(&dealer_btc_account, DEALER_UID);
+        self.update_account(&dealer_fiat_account, DEALER_UID);
+
+        self.make_summary_tx(
+            &btc_liabilities_account,
+            BANK_UID,
+            account,
+            uid,
+            unused_fees,
+            Some(rate.clone()),
+            None,
+            Some(outbound_txid),
+            Some(inbound_txid),
+            None,
+            Some(String::from("FeeRefund")),
+        )?;
+        Ok(())
+    }
+
+    /// Persists a failed payment for another attempt with exponential backoff.
+    /// Returns false if the payment has to be refunded instead.
+    fn schedule_payment_retry(&self, conn: &diesel::PgConnection, res: &PaymentResult) -> bool {
+        let settings = match &self.payment_retry_settings {
+            Some(settings) => settings,
+            None => return false,
+        };
+
+        if res.attempts >= settings.max_attempts {
+            slog::warn!(
+                self.logger,
+                "Payment {} failed after {} attempts. Refunding.",
+                res.payment_response.req_id,
+                res.attempts
+            );
+            return false;
+        }
+
+        let payment_result = match serde_json::to_string(res) {
+            Ok(payment_result) => payment_result,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to serialize payment result: {:?}", err);
+                return false;
+            }
+        };
+
+        let backoff = settings
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(res.attempts.saturating_sub(1)))
+            .min(settings.max_backoff_ms);
+        let now = utils::time::time_now();
+        let req_id = res.payment_response.req_id.to_string();
+
+        let existing = PaymentRetry::get_by_req_id(conn, req_id.clone()).ok();
+        let retry = PaymentRetry {
+            req_id,
+            uid: res.uid as i32,
+            payment_request: res.payment_response.payment_request.clone().unwrap_or_default(),
+            payment_result,
+            attempts: res.attempts as i32,
+            next_attempt_at: (now + backoff) as i64,
+            created_at: existing.as_ref().map(|r| r.created_at).unwrap_or(now as i64),
+        };
+
+        let stored = match existing {
+            Some(_) => retry.update(conn).map(|_| ()),
+            None => retry.insert(conn).map(|_| ()),
+        };
+
+        match stored {
+            Ok(_) => {
+                slog::info!(
+                    self.logger,
+                    "Scheduled retry of payment {} in {} ms",
+                    retry.req_id,
+                    backoff
+                );
+                true
+            }
+            Err(err) => {
+                slog::error!(self.logger, "Failed to store payment retry: {:?}", err);
+                false
+            }
+        }
+    }
+
+    /// Starts a new attempt for every payment retry that is due.
+    pub fn process_payment_retries(&mut self) {
+        if self.payment_retry_settings.is_none() {
+            return;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let due = match PaymentRetry::get_due(&psql_connection, utils::time::time_now() as i64) {
+            Ok(due) => due,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load payment retries: {:?}", err);
+                return;
+            }
+        };
+
+        for mut retry in due {
+            let pending = match serde_json::from_str::<PaymentResult>(&retry.payment_result) {
+                Ok(pending) => pending,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to deserialize payment retry {}: {:?}",
+                        retry.req_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+            // The attempt is in flight until its result comes back and reschedules it.
+            retry.next_attempt_at = ATTEMPT_IN_FLIGHT;
+            if let Err(err) = retry.update(&psql_connection) {
+                slog::error!(
+                    self.logger,
+                    "Failed to update payment retry {}: {:?}",
+                    retry.req_id,
+                    err
+                );
+                continue;
+            }
+            slog::info!(
+                self.logger,
+                "Retrying payment {}, attempt {}",
+                retry.req_id,
+                pending.attempts + 1
+            );
+            self.restore_pending_funds(&pending);
+            self.spawn_payment_task(pending);
+        }
+    }
+
+    fn export_journal(&self, request: &ExportJournal) -> Result<usize, String> {
+        let settings = match &self.export_settings {
+            Some(settings) => settings,
+            None => return Err("Journal export is not configured".to_string()),
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
+
+        let to = request.to.unwrap_or_else(utils::time::time_now);
+        export_journal(
+            &psql_connection,
+            settings,
+            request.from as i64,
+            to as i64,
+            &request.path,
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    fn run_simulation(&self, request: &Simulate) -> Result<SimulationReport, String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
+
+        let (from, to) = simulation_period(request, utils::time::time_now());
+        let txs = SummaryTransaction::get_between(&psql_connection, from as i64, to as i64)
+            .map_err(|err| format!("Failed to fetch summary transactions: {:?}", err))?;
+
+        let current = CurrentFees {
+            internal_tx_fee: self.internal_tx_fee,
+            external_tx_fee: self.external_tx_fee,
+        };
+
+        let mut report = simulate(&txs, request, &current, BANK_UID, DEALER_UID);
+        report.from = from;
+        report.to = to;
+        Ok(report)
+    }
+
+    fn export_statement(&self, request: &ExportStatementRequest) -> ExportStatementResponse {
+        let mut response = ExportStatementResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            format: request.format,
+            content: None,
+            error: None,
+        };
+
+        let from = request.from.unwrap_or(0);
+        let to = request.to.unwrap_or_else(utils::time::time_now);
+        if from > to {
+            response.error = Some(ExportStatementError::InvalidPeriod);
+            return response;
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                response.error = Some(ExportStatementError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(ExportStatementError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+
+        // All earlier transactions are needed for the running balances.
+        let user_txs = match SummaryTransaction::get_historical_by_uid(
+            &psql_connection,
+            request.uid as i32,
+            None,
+            Some(to as i64),
+        ) {
+            Ok(txs) => txs,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch transactions of statement: {:?}", err);
+                response.error = Some(ExportStatementError::FailedToFetchTransactions);
+                return response;
+            }
+        };
+
+        let rate_txs = match SummaryTransaction::get_between(
+            &psql_connection,
+            from.saturating_sub(RATE_LOOKBACK_MILLIS) as i64,
+            to as i64 + 1,
+        ) {
+            Ok(txs) => txs,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch rates of statement: {:?}", err);
+                response.error = Some(ExportStatementError::FailedToFetchTransactions);
+                return response;
+            }
+        };
+
+        let valuation_currency = request.valuation_currency.unwrap_or(Currency::USD);
+        let entries = build_statement(request.uid, &user_txs, &rate_txs, from, to, valuation_currency);
+
+        response.content = match request.format {
+            StatementFormat::Csv => Some(statement_csv(&entries)),
+            StatementFormat::Json => serde_json::to_string(&entries).ok(),
+        };
+        response
+    }
+
+    /// Assembles the archive of the user's data and either stores it for a one-time download or splits it
+    /// into the chunks that follow the response.
+    fn export_my_data(&self, request: &ExportMyDataRequest) -> (ExportMyDataResponse, Vec<ExportMyDataChunk>) {
+        let mut response = ExportMyDataResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            delivery: request.delivery,
+            export_id: None,
+            expires_at: None,
+            chunks: 0,
+            error: None,
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                response.error = Some(ExportMyDataError::DatabaseConnectionFailed);
+                return (response, Vec::new());
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(ExportMyDataError::DatabaseConnectionFailed);
+                return (response, Vec::new());
+            }
+        };
+
+        let now = utils::time::time_now();
+        let archive = match self.user_data_archive(&psql_connection, request.uid, now) {
+            Ok(archive) => archive,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to fetch data export of user {}: {:?}",
+                    request.uid,
+                    err
+                );
+                response.error = Some(ExportMyDataError::FailedToFetchData);
+                return (response, Vec::new());
+            }
+        };
+
+        let content = match serde_json::to_string(&archive) {
+            Ok(content) => content,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to serialize data export of user {}: {:?}",
+                    request.uid,
+                    err
+                );
+                response.error = Some(ExportMyDataError::FailedToFetchData);
+                return (response, Vec::new());
+            }
+        };
+
+        match request.delivery {
+            DataExportDelivery::Link => {
+                let export = DataExport {
+                    export_id: Uuid::new_v4(),
+                    uid: request.uid as i32,
+                    content,
+                    created_at: now as i64,
+                    expires_at: (now + DATA_EXPORT_TTL_MS) as i64,
+                };
+                if let Err(err) = export.insert(&psql_connection) {
+                    slog::error!(
+                        self.logger,
+                        "Failed to store data export of user {}: {:?}",
+                        request.uid,
+                        err
+                    );
+                    response.error = Some(ExportMyDataError::FailedToStoreExport);
+                    return (response, Vec::new());
+                }
+                response.export_id = Some(export.export_id);
+                response.expires_at = Some(export.expires_at as u64);
+                (response, Vec::new())
+            }
+            DataExportDelivery::Inline => {
+                let chunks = split_chunks(&content, DATA_EXPORT_CHUNK_SIZE)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, data)| ExportMyDataChunk {
+                        req_id: request.req_id,
+                        uid: request.uid,
+                        index,
+                        data: data.to_string(),
+                    })
+                    .collect::<Vec<ExportMyDataChunk>>();
+                response.chunks = chunks.len();
+                (response, chunks)
+            }
+        }
+    }
+
+    fn user_data_archive(
+        &self,
+        conn: &diesel::PgConnection,
+        uid: UserId,
+        now: u64,
+    ) -> Result<UserDataArchive, DieselError> {
+        let user = User::get_by_id(conn, uid as i32)?;
+        let accounts = self
+            .ledger
+            .user_accounts
+            .get(&uid)
+            .map(|user_account| user_account.accounts.values().cloned().collect())
+            .unwrap_or_default();
+
+        Ok(UserDataArchive {
+            uid,
+            username: user.username,
+            exported_at: now,
+            accounts,
+            transactions: SummaryTransaction::get_historical_by_uid(conn, uid as i32, None, Some(now as i64))?,
+            invoices: Invoice::get_invoices_by_uid(conn, uid as i32)?,
+            preferences: UserPreferences {
+                dust_sweep: DustSweepPreference::get_by_uid(conn, uid as i32).optional()?,
+                access_policy: access_policies::AccessPolicy::get_by_uid(conn, uid as i32).optional()?,
+                deposit_routing_rules: DepositRoutingRule::get_by_uid(conn, uid as i32)?,
+                webhooks: Webhook::get_by_uid(conn, uid as i32)?,
+            },
+        })
+    }
+
+    /// Deletes stored data exports that weren't downloaded in time.
+    pub fn expire_data_exports(&mut self) {
+        let now = utils::time::time_now();
+        if let Some(db_writer) = &self.db_writer {
+            db_writer.submit(
+                0,
+                "expired data exports",
+                Box::new(move |c| DataExport::delete_expired(c, now as i64).map(|_| ())),
+            );
+        }
+    }
+
+    fn get_postings(&self, account_id: AccountId, limit: usize) -> Result<Vec<LedgerPosting>, String> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
+
+        let txs = Transaction::get_recent_by_account(&psql_connection, account_id, limit as i64)
+            .map_err(|err| format!("Failed to fetch postings: {:?}", err))?;
+
+        let postings = txs
+            .into_iter()
+            .filter_map(|tx| {
+                let (amount, currency, counterparty_uid) = if tx.inbound_account_id == account_id {
+                    (tx.inbound_amount, tx.inbound_currency, tx.outbound_uid)
+                } else {
+                    (-tx.outbound_amount, tx.outbound_currency, tx.inbound_uid)
+                };
+                Some(LedgerPosting {
+                    txid: tx.txid,
+                    created_at: tx.created_at as u64,
+                    amount: Decimal::from_str(&amount.to_string()).ok()?,
+                    currency: Currency::from_str(&currency).ok()?,
+                    counterparty_uid: counterparty_uid as u64,
+                    tx_type: tx.tx_type,
+                })
+            })
+            .collect();
+
+        Ok(postings)
+    }
+
+    /// Returns a page of the accounts of an internal book together with their recent postings
+    /// and the outcome of the last reconciliation.
+    fn query_ledger(&self, query: QueryLedger) -> LedgerQueryResult {
+        let mut accounts: Vec<Account> = match query.book {
+            LedgerBook::BankLiabilities => self.ledger.bank_liabilities.accounts.values().cloned().collect(),
+            LedgerBook::DealerAccounts => self.ledger.dealer_accounts.accounts.values().cloned().collect(),
+            LedgerBook::InsuranceFund => vec![self.ledger.insurance_fund_account.clone()],
+            LedgerBook::FundingIncome => self.ledger.funding_account.accounts.values().cloned().collect(),
+            LedgerBook::HedgingPnl => self.ledger.pnl_account.accounts.values().cloned().collect(),
+            LedgerBook::OperatorExpenses => self.ledger.expense_account.accounts.values().cloned().collect(),
+        };
+
+        accounts.retain(|account| {
+            query.currency.map_or(true, |currency| account.currency == currency)
+                && query
+                    .account_type
+                    .map_or(true, |account_type| account.account_type == account_type)
+        });
+        accounts.sort_by(|a, b| (a.currency.to_string(), a.account_id).cmp(&(b.currency.to_string(), b.account_id)));
+
+        let total = accounts.len();
+        let limit = query.limit.min(MAX_LEDGER_QUERY_LIMIT);
+        let postings_limit = query.postings.min(MAX_LEDGER_QUERY_POSTINGS);
+
+        let mut entries = Vec::new();
+        let mut error = None;
+        for account in accounts.into_iter().skip(query.offset).take(limit) {
+            let postings = match self.get_postings(account.account_id, postings_limit) {
+                Ok(postings) => postings,
+                Err(err) => {
+                    error = Some(err);
+                    Vec::new()
+                }
+            };
+            let drift = self
+                .account_drifts
+                .iter()
+                .find(|drift| drift.account_id == account.account_id)
+                .map(|drift| LedgerAccountDrift {
+                    persisted_balance: drift.persisted_balance,
+                    transactions_balance: drift.transactions_balance,
+                });
+            entries.push(LedgerAccountEntry {
+                account,
+                postings,
+                drift,
+            });
+        }
+
+        LedgerQueryResult {
+            request: query,
+            total,
+            accounts: entries,
+            last_reconciliation: self
+                .reconciliation_settings
+                .as_ref()
+                .map(|_| self.last_db_reconciliation_timestamp),
+            withdrawals_halted: self.withdrawals_halted,
+            error,
+        }
+    }
+
+    /// Swaps the fiat balances below the dust thresholds of users that opted in back to their BTC account.
+    /// Only main accounts are swept, the swaps are reported to users like their own.
+    pub fn sweep_dust<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        let settings = match &self.dust_sweep_settings {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+
+        let now = utils::time::time_now();
+        if now < self.last_dust_sweep_timestamp + settings.interval_secs * 1000 {
+            return;
+        }
+        self.last_dust_sweep_timestamp = now;
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let uids = match DustSweepPreference::get_enabled_uids(&psql_connection) {
+            Ok(uids) => uids,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch dust sweep preferences: {:?}", err);
+                return;
+            }
+        };
+
+        for uid in uids.into_iter().map(|uid| uid as UserId) {
+            let user_account = match self.ledger.user_accounts.get(&uid) {
+                Some(user_account) => user_account,
+                None => continue,
+            };
 
-                    let c = match conn.get() {
-                        Ok(psql_connection) => psql_connection,
-                        Err(_) => {
-                            slog::error!(self.logger, "Couldn't get psql connection.");
-                            let invoice_response = InvoiceResponse {
-                                amount: msg.amount.clone(),
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: None,
-                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+            let dust = user_account
+                .accounts
+                .values()
+                .filter(|account| {
+                    account.currency != Currency::BTC
+                        && account.account_type == AccountType::Internal
+                        && account.label.is_none()
+                        && !account.archived
+                        && account.balance > dec!(0)
+                        && self.available_currencies.contains(&account.currency)
+                        && settings
+                            .thresholds
+                            .get(&account.currency.to_string())
+                            .map_or(false, |threshold| account.balance < *threshold)
+                })
+                .map(|account| Money::new(account.currency, Some(account.balance)))
+                .collect::<Vec<Money>>();
+
+            for amount in dust {
+                slog::info!(self.logger, "Sweeping {:?} of user {} to BTC", amount, uid);
+                let swap_request = SwapRequest {
+                    req_id: Uuid::new_v4(),
+                    uid,
+                    amount: amount.clone(),
+                    from: amount.currency,
+                    to: Currency::BTC,
+                    quote_id: None,
+                    signed_quote: None,
+                    idempotency_key: None,
+                    origin: None,
+                    totp_code: None,
+                };
+                let msg = Message::Api(Api::SwapRequest(swap_request));
+                listener(msg, ServiceIdentity::Dealer);
+            }
+        }
+    }
+
+    /// Swaps between the main BTC and fiat accounts of users with a hedge target whose share of the fiat
+    /// synthetic drifted out of the tolerance. The swaps go through the dealer like the users' own.
+    pub fn rebalance_hedges<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        let settings = match &self.hedge_rebalance_settings {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+
+        let now = utils::time::time_now();
+        if now < self.last_hedge_rebalance_timestamp + settings.interval_secs * 1000 {
+            return;
+        }
+
+        let prices = match &self.last_market_prices {
+            Some(prices) if now.saturating_sub(prices.timestamp) <= MARKET_PRICES_MAX_AGE_MS => prices.prices.clone(),
+            _ => {
+                slog::warn!(self.logger, "No recent market prices, hedges aren't rebalanced.");
+                return;
+            }
+        };
+        self.last_hedge_rebalance_timestamp = now;
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let targets = match HedgeTarget::get_all(&psql_connection) {
+            Ok(targets) => targets,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch hedge targets: {:?}", err);
+                return;
+            }
+        };
+
+        for target in targets {
+            let uid = target.uid as UserId;
+            let currency = match Currency::from_str(&target.currency) {
+                Ok(currency) if self.available_currencies.contains(&currency) => currency,
+                _ => continue,
+            };
+            let price = match prices.iter().find(|price| price.currency == currency) {
+                Some(price) => (price.bid + price.ask) / dec!(2),
+                None => continue,
+            };
+            let target_ratio = match Decimal::from_str(&target.target_ratio.to_string()) {
+                Ok(target_ratio) => target_ratio,
+                Err(_) => continue,
+            };
+            let user_account = match self.ledger.user_accounts.get(&uid) {
+                Some(user_account) => user_account,
+                None => continue,
+            };
+
+            let main_balance = |currency: Currency| {
+                user_account
+                    .accounts
+                    .values()
+                    .filter(|account| {
+                        account.currency == currency
+                            && account.account_type == AccountType::Internal
+                            && account.label.is_none()
+                            && !account.archived
+                    })
+                    .map(|account| account.balance)
+                    .sum::<Decimal>()
+            };
+
+            let amount = match rebalance_swap(
+                &settings,
+                currency,
+                main_balance(Currency::BTC),
+                main_balance(currency),
+                price,
+                target_ratio,
+            ) {
+                Some(amount) => amount,
+                None => continue,
+            };
+            let to = if amount.currency == Currency::BTC {
+                currency
+            } else {
+                Currency::BTC
+            };
+
+            slog::info!(self.logger, "Rebalancing {:?} of user {} to {}", amount, uid, to);
+            let swap_request = SwapRequest {
+                req_id: Uuid::new_v4(),
+                uid,
+                amount: amount.clone(),
+                from: amount.currency,
+                to,
+                quote_id: None,
+                signed_quote: None,
+                idempotency_key: None,
+                origin: None,
+                totp_code: None,
+            };
+            let msg = Message::Api(Api::SwapRequest(swap_request));
+            listener(msg, ServiceIdentity::Dealer);
+        }
+    }
+
+    /// Aggregates the revenue of the next day that is over. Catches up one day per call after downtimes.
+    pub fn aggregate_operator_revenue(&mut self) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let now = utils::time::time_now();
+
+        let day = match self.next_revenue_day {
+            Some(day) => day,
+            None => match OperatorRevenue::get_last_day(&psql_connection) {
+                Ok(Some(last_day)) => last_day as u64 + MILLIS_IN_DAY,
+                Ok(None) => match SummaryTransaction::get_first_created_at(&psql_connection) {
+                    Ok(first_created_at) => day_start(first_created_at.map_or(now, |created_at| created_at as u64)),
+                    Err(err) => {
+                        slog::error!(self.logger, "Failed to fetch first summary transaction: {:?}", err);
+                        return;
+                    }
+                },
+                Err(err) => {
+                    slog::error!(self.logger, "Failed to fetch last revenue day: {:?}", err);
+                    return;
+                }
+            },
+        };
+
+        if day + MILLIS_IN_DAY > now {
+            self.next_revenue_day = Some(day);
+            return;
+        }
+
+        let txs = match SummaryTransaction::get_between(&psql_connection, day as i64, (day + MILLIS_IN_DAY) as i64) {
+            Ok(txs) => txs,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch summary transactions: {:?}", err);
+                return;
+            }
+        };
+
+        let revenues = aggregate_revenue(&txs)
+            .into_iter()
+            .filter_map(|((source, currency), (amount, tx_count))| {
+                Some(InsertableOperatorRevenue {
+                    day: day as i64,
+                    source: source.to_string(),
+                    currency: currency.to_string(),
+                    amount: BigDecimal::from_str(&amount.to_string()).ok()?,
+                    tx_count: tx_count as i32,
+                    created_at: now as i64,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        match OperatorRevenue::replace_day(&psql_connection, day as i64, &revenues) {
+            Ok(_) => {
+                slog::info!(self.logger, "Aggregated operator revenue of day {}", day);
+                self.next_revenue_day = Some(day + MILLIS_IN_DAY);
+            }
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to store operator revenue of day {}: {:?}",
+                    day,
+                    err
+                );
+            }
+        }
+    }
+
+    /// Moves the funding the dealer's hedge of a currency received between the dealer's account and the
+    /// funding income account. Paid funding is only taken from the income account as far as it covers it.
+    fn book_funding_income(&mut self, income: &FundingIncome) -> Result<(), BankError> {
+        let mut funding_account = self.ledger.get_funding_account(income.currency);
+        let mut dealer_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(income.currency, Some(AccountType::Internal));
+
+        let amount = if income.amount > Decimal::ZERO {
+            income.amount
+        } else {
+            (-income.amount).min(funding_account.balance)
+        };
+        if amount <= Decimal::ZERO {
+            return Ok(());
+        }
+        let amount = Money::new(income.currency, Some(amount));
+
+        let (outbound_account, inbound_account) = if income.amount > Decimal::ZERO {
+            (&mut dealer_account, &mut funding_account)
+        } else {
+            (&mut funding_account, &mut dealer_account)
+        };
+        let txid = self.make_tx(
+            outbound_account,
+            DEALER_UID,
+            inbound_account,
+            DEALER_UID,
+            amount.clone(),
+        )?;
+
+        self.ledger
+            .funding_account
+            .accounts
+            .insert(funding_account.account_id, funding_account.clone());
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(dealer_account.account_id, dealer_account.clone());
+        self.update_account(&funding_account, DEALER_UID);
+        self.update_account(&dealer_account, DEALER_UID);
+
+        let (outbound_account, inbound_account) = if income.amount > Decimal::ZERO {
+            (&dealer_account, &funding_account)
+        } else {
+            (&funding_account, &dealer_account)
+        };
+        self.make_summary_tx(
+            outbound_account,
+            DEALER_UID,
+            inbound_account,
+            DEALER_UID,
+            amount,
+            None,
+            None,
+            Some(txid.clone()),
+            Some(txid),
+            None,
+            Some(String::from("FundingIncome")),
+        )?;
+        Ok(())
+    }
+
+    /// Pays the node itself to move liquidity between two of its channels. The routing fee may not exceed the
+    /// fee of the request nor what is left of the daily budget, the fee paid is booked from the bank's
+    /// liabilities on the operator expenses. Returns the fee paid in msat.
+    async fn rebalance(&mut self, request: &Rebalance) -> Result<u64, String> {
+        let daily_budget_sats = self
+            .rebalance_daily_fee_budget_sats
+            .ok_or_else(|| String::from("Rebalancing is disabled, no daily fee budget is set"))?;
+        if request.amount_sats == 0 {
+            return Err(String::from("Amount has to be positive"));
+        }
+
+        let spent = self.rebalance_fees_spent()?;
+        let remaining = Decimal::new(daily_budget_sats as i64, 0) / SATS_IN_BITCOIN - spent;
+        let max_fee = Decimal::new(request.max_fee_sats as i64, 0) / SATS_IN_BITCOIN;
+        let max_fee_msat = (max_fee.min(remaining) * MSATS_IN_BITCOIN)
+            .floor()
+            .to_i64()
+            .unwrap_or(0);
+        if max_fee_msat <= 0 {
+            return Err(format!(
+                "Daily rebalance fee budget of {} sats is spent",
+                daily_budget_sats
+            ));
+        }
+
+        let pay_response = self
+            .lnd_connector
+            .rebalance(
+                request.outgoing_chan_id,
+                &request.last_hop_pubkey,
+                request.amount_sats,
+                max_fee_msat,
+            )
+            .await
+            .map_err(|err| format!("Rebalance payment failed: {:?}", err))?;
+        slog::info!(
+            self.logger,
+            "Rebalanced {} sats out of channel {} to {}, fee: {} msat",
+            request.amount_sats,
+            request.outgoing_chan_id,
+            request.last_hop_pubkey,
+            pay_response.fee_msat
+        );
+
+        if pay_response.fee_msat > 0 {
+            let fee = Money::from_msats(Decimal::new(pay_response.fee_msat as i64, 0));
+            if let Err(err) = self.book_operator_expense(fee, "RebalanceFee") {
+                slog::error!(self.logger, "Failed to book rebalance fee: {}", err);
+                return Err(format!("Rebalanced but failed to book the fee: {}", err));
+            }
+        }
+        Ok(pay_response.fee_msat)
+    }
+
+    /// Rebalance fees in BTC booked over the last 24 hours.
+    fn rebalance_fees_spent(&self) -> Result<Decimal, String> {
+        let conn = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or_else(|| String::from("Couldn't get psql connection"))?;
+        let now = utils::time::time_now();
+        let txs = SummaryTransaction::get_between(&conn, now.saturating_sub(MILLIS_IN_DAY) as i64, now as i64 + 1)
+            .map_err(|err| format!("Failed to fetch summary transactions: {:?}", err))?;
+        Ok(txs
+            .iter()
+            .filter(|tx| tx.reference.as_deref() == Some("RebalanceFee"))
+            .filter_map(|tx| Decimal::from_str(&tx.outbound_amount.to_string()).ok())
+            .sum())
+    }
+
+    /// Books a cost of running the node, paid out of the node's funds, from the bank's liabilities on the
+    /// operator expenses. The reference tells the kind of expense apart.
+    fn book_operator_expense(&mut self, fee: Money, reference: &str) -> Result<(), BankError> {
+        let mut expense_account = self.ledger.get_expense_account(Currency::BTC);
+        let mut btc_liabilities_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::BTC, Some(AccountType::External));
+        let txid = self.make_tx(
+            &mut btc_liabilities_account,
+            BANK_UID,
+            &mut expense_account,
+            BANK_UID,
+            fee.clone(),
+        )?;
+
+        self.ledger
+            .expense_account
+            .accounts
+            .insert(expense_account.account_id, expense_account.clone());
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+        self.update_account(&expense_account, BANK_UID);
+        self.update_account(&btc_liabilities_account, BANK_UID);
+
+        self.make_summary_tx(
+            &btc_liabilities_account,
+            BANK_UID,
+            &expense_account,
+            BANK_UID,
+            fee,
+            None,
+            None,
+            Some(txid.clone()),
+            Some(txid),
+            None,
+            Some(reference.to_string()),
+        )?;
+        Ok(())
+    }
+
+    /// Polls the swap in flight or, if there is none, starts a swap once the liquidity of the node crosses one
+    /// of the thresholds. One swap runs at a time so a swap that didn't settle yet doesn't trigger another one.
+    /// Costs of finished swaps are booked on the operator expenses.
+    pub async fn manage_liquidity_swaps(&mut self) {
+        let settings = match &self.liquidity_swap_settings {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+        let now = utils::time::time_now();
+        if now.saturating_sub(self.last_liquidity_swap_check) < LIQUIDITY_SWAP_CHECK_INTERVAL_MS {
+            return;
+        }
+        self.last_liquidity_swap_check = now;
+
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+        let pending_state = SwapState::Pending.to_string();
+        let pending = match LiquiditySwap::get_by_states(&conn, &[pending_state.as_str()]) {
+            Ok(pending) => pending,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load liquidity swaps: {:?}", err);
+                return;
+            }
+        };
+        if !pending.is_empty() {
+            for swap in pending {
+                self.poll_liquidity_swap(&conn, &swap);
+            }
+            return;
+        }
+
+        let inbound = self.lnd_connector.get_inbound_capacity().await;
+        let outbound = self.lnd_connector.get_outbound_capacity().await;
+        let (inbound, outbound) = match (inbound, outbound) {
+            (Ok(inbound), Ok(outbound)) => (inbound.total, outbound.total),
+            (Err(err), _) | (_, Err(err)) => {
+                slog::error!(self.logger, "Failed to fetch channel liquidity: {:?}", err);
+                return;
+            }
+        };
+        let direction = match swap_needed(&settings, inbound, outbound) {
+            Some(direction) => direction,
+            None => return,
+        };
+        let provider = match &self.swap_provider {
+            Some(provider) => provider,
+            None => return,
+        };
+
+        match provider.start(direction, settings.swap_amount_sats, settings.max_cost_sats) {
+            Ok(swap_id) => {
+                slog::info!(
+                    self.logger,
+                    "Started {} swap {} {} of {} sats, inbound: {} sats, outbound: {} sats",
+                    provider.name(),
+                    direction,
+                    swap_id,
+                    settings.swap_amount_sats,
+                    inbound,
+                    outbound
+                );
+                let swap = InsertableLiquiditySwap {
+                    provider: provider.name().to_string(),
+                    swap_id,
+                    direction: direction.to_string(),
+                    amount_sats: settings.swap_amount_sats as i64,
+                    state: pending_state,
+                    cost_sats: None,
+                    created_at: now as i64,
+                    updated_at: now as i64,
+                };
+                if let Err(err) = swap.insert(&conn) {
+                    slog::error!(self.logger, "Failed to store liquidity swap {:?}: {:?}", swap, err);
+                }
+            }
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to start {} swap {}: {}",
+                    provider.name(),
+                    direction,
+                    err
+                );
+            }
+        }
+    }
+
+    fn poll_liquidity_swap(&mut self, conn: &diesel::PgConnection, swap: &LiquiditySwap) {
+        let status = match self
+            .swap_provider
+            .as_ref()
+            .map(|provider| provider.status(&swap.swap_id))
+        {
+            Some(Ok(status)) => status,
+            Some(Err(err)) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to fetch state of liquidity swap {}: {}",
+                    swap.swap_id,
+                    err
+                );
+                return;
+            }
+            None => return,
+        };
+        if status.state == SwapState::Pending {
+            return;
+        }
+
+        let cost_sats = status.cost_sats.map(|cost_sats| cost_sats as i64);
+        let state = status.state.to_string();
+        if let Err(err) = LiquiditySwap::update_state(conn, swap.id, &state, cost_sats, utils::time::time_now() as i64)
+        {
+            slog::error!(
+                self.logger,
+                "Failed to update liquidity swap {}: {:?}",
+                swap.swap_id,
+                err
+            );
+            return;
+        }
+        slog::info!(
+            self.logger,
+            "{} swap {} {}, cost: {:?} sats",
+            swap.provider,
+            swap.swap_id,
+            state,
+            status.cost_sats
+        );
+
+        if let Some(cost_sats) = status.cost_sats.filter(|cost_sats| *cost_sats > 0) {
+            let cost = Money::from_sats(Decimal::new(cost_sats as i64, 0));
+            if let Err(err) = self.book_operator_expense(cost, "LiquiditySwapFee") {
+                slog::error!(
+                    self.logger,
+                    "Failed to book cost of liquidity swap {}: {}",
+                    swap.swap_id,
+                    err
+                );
+            }
+        }
+    }
+
+    fn list_liquidity_swaps(&self, request: &ListLiquiditySwaps) -> Result<Vec<LiquiditySwapInfo>, String> {
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => return Err("Couldn't get psql connection".to_string()),
+        };
+
+        let swaps = LiquiditySwap::get_recent(&conn, request.limit as i64)
+            .map_err(|err| format!("Failed to load liquidity swaps: {:?}", err))?;
+        Ok(swaps
+            .into_iter()
+            .map(|swap| LiquiditySwapInfo {
+                provider: swap.provider,
+                swap_id: swap.swap_id,
+                direction: swap.direction,
+                amount_sats: swap.amount_sats as u64,
+                state: swap.state,
+                cost_sats: swap.cost_sats.map(|cost_sats| cost_sats as u64),
+                created_at: swap.created_at as u64,
+                updated_at: swap.updated_at as u64,
+            })
+            .collect())
+    }
+
+    /// Moves the profit the dealer's hedge of a currency realized between the dealer's account and the
+    /// hedging pnl account. Losses are booked in full, so the balance of the pnl account is the cumulative
+    /// result of hedging and goes negative if hedging costs more than it makes.
+    fn book_hedging_pnl(&mut self, pnl: &HedgingPnl) -> Result<(), BankError> {
+        if pnl.amount.is_zero() {
+            return Ok(());
+        }
+        let mut pnl_account = self.ledger.get_pnl_account(pnl.currency);
+        let mut dealer_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(pnl.currency, Some(AccountType::Internal));
+        let amount = Money::new(pnl.currency, Some(pnl.amount.abs()));
+
+        let (outbound_account, inbound_account) = if pnl.amount > Decimal::ZERO {
+            (&mut dealer_account, &mut pnl_account)
+        } else {
+            (&mut pnl_account, &mut dealer_account)
+        };
+        let txid = self.make_tx(
+            outbound_account,
+            DEALER_UID,
+            inbound_account,
+            DEALER_UID,
+            amount.clone(),
+        )?;
 
-                    let user_account = self
-                        .ledger
-                        .user_accounts
-                        .entry(msg.uid)
-                        .or_insert_with(|| UserAccount::new(msg.uid));
+        self.ledger
+            .pnl_account
+            .accounts
+            .insert(pnl_account.account_id, pnl_account.clone());
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(dealer_account.account_id, dealer_account.clone());
+        self.update_account(&pnl_account, DEALER_UID);
+        self.update_account(&dealer_account, DEALER_UID);
 
-                    let mut target_account = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+        let (outbound_account, inbound_account) = if pnl.amount > Decimal::ZERO {
+            (&dealer_account, &pnl_account)
+        } else {
+            (&pnl_account, &dealer_account)
+        };
+        self.make_summary_tx(
+            outbound_account,
+            DEALER_UID,
+            inbound_account,
+            DEALER_UID,
+            amount,
+            None,
+            None,
+            Some(txid.clone()),
+            Some(txid),
+            None,
+            Some(String::from("HedgingPnl")),
+        )?;
+        Ok(())
+    }
 
-                    if let Some(account_id) = msg.account_id {
-                        if let Some(acc) = user_account.accounts.get(&account_id) {
-                            target_account = acc.clone();
-                        } else {
-                            let invoice_response = InvoiceResponse {
-                                amount: msg.amount.clone(),
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: Some(target_account.account_id),
-                                error: Some(InvoiceResponseError::AccountDoesNotExist),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    } else {
-                        // If user does not specify an account_id we select or create one for him.
-                        let account = user_account.get_default_account(Currency::BTC, None);
-                        target_account = account;
-                    }
+    /// Aggregates the anonymized flows of the next day that is over and publishes the statistics of the
+    /// last days if a file is configured. Catches up one day per call after downtimes.
+    pub fn aggregate_flow_statistics(&mut self) {
+        let settings = match &self.flow_statistics {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
 
-                    let currency = msg.currency;
-                    let deposit_limit = self
-                        .deposit_limits
-                        .get(&currency)
-                        .unwrap_or_else(|| panic!("Failed to get deposit limit for {}", currency));
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return,
+        };
 
-                    // Check whether deposit limit is exceeded.
-                    if target_account.balance + msg.amount.value > *deposit_limit {
-                        let invoice_response = InvoiceResponse {
-                            amount: money,
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            rate: None,
-                            meta: msg.meta.clone(),
-                            metadata: msg.metadata.clone(),
-                            payment_request: None,
-                            currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: Some(target_account.account_id),
-                            error: Some(InvoiceResponseError::DepositLimitExceeded),
-                            fees: None,
-                        };
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api);
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let now = utils::time::time_now();
+
+        let day = match self.next_flow_statistics_day {
+            Some(day) => day,
+            None => match FlowStatistic::get_last_day(&psql_connection) {
+                Ok(Some(last_day)) => last_day as u64 + MILLIS_IN_DAY,
+                Ok(None) => match SummaryTransaction::get_first_created_at(&psql_connection) {
+                    Ok(first_created_at) => day_start(first_created_at.map_or(now, |created_at| created_at as u64)),
+                    Err(err) => {
+                        slog::error!(self.logger, "Failed to fetch first summary transaction: {:?}", err);
                         return;
                     }
+                },
+                Err(err) => {
+                    slog::error!(self.logger, "Failed to fetch last flow statistics day: {:?}", err);
+                    return;
+                }
+            },
+        };
 
-                    if let Ok(mut invoice) = self
-                        .lnd_connector
-                        .create_invoice(
-                            amount_in_sats,
-                            msg.meta.clone(),
-                            msg.uid,
-                            target_account.account_id,
-                            msg.metadata.clone(),
-                        )
-                        .await
-                    {
-                        invoice.currency = Some(msg.currency.to_string());
-                        if let Err(_err) = invoice.insert(&c) {
-                            slog::error!(self.logger, "Error inserting invoice.");
-                            let invoice_response = InvoiceResponse {
-                                amount: msg.amount.clone(),
-                                req_id: msg.req_id,
-                                uid: msg.uid,
-                                rate: None,
-                                meta: msg.meta.clone(),
-                                metadata: msg.metadata.clone(),
-                                payment_request: None,
-                                currency: msg.currency,
-                                target_account_currency: msg.target_account_currency,
-                                account_id: Some(target_account.account_id),
-                                error: Some(InvoiceResponseError::DatabaseConnectionFailed),
-                                fees: None,
-                            };
-                            let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
+        if day + MILLIS_IN_DAY > now {
+            self.next_flow_statistics_day = Some(day);
+            return;
+        }
 
-                        let invoice_response = InvoiceResponse {
-                            amount: msg.amount.clone(),
-                            req_id: msg.req_id,
-                            uid: msg.uid,
-                            meta: msg.meta.clone(),
-                            metadata: msg.metadata.clone(),
-                            rate: msg.rate.clone(),
-                            payment_request: Some(invoice.payment_request),
-                            currency: msg.currency,
-                            target_account_currency: msg.target_account_currency,
-                            account_id: Some(target_account.account_id),
-                            error: None,
-                            fees: msg.fees.clone(),
-                        };
+        let txs = match SummaryTransaction::get_between(&psql_connection, day as i64, (day + MILLIS_IN_DAY) as i64) {
+            Ok(txs) => txs,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch summary transactions: {:?}", err);
+                return;
+            }
+        };
 
-                        let msg = Message::Api(Api::InvoiceResponse(invoice_response));
-                        listener(msg, ServiceIdentity::Api)
-                    }
+        let statistics = aggregate_flows(&txs, self.bank_uid, settings.min_tx_count)
+            .into_iter()
+            .filter_map(|((flow, market), aggregate)| {
+                Some(InsertableFlowStatistic {
+                    day: day as i64,
+                    flow: flow.to_string(),
+                    market,
+                    volume: BigDecimal::from_str(&aggregate.volume.to_string()).ok()?,
+                    fees: BigDecimal::from_str(&aggregate.fees.to_string()).ok()?,
+                    tx_count: aggregate.tx_count as i32,
+                    created_at: now as i64,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if let Err(err) = FlowStatistic::replace_day(&psql_connection, day as i64, &statistics) {
+            slog::error!(self.logger, "Failed to store flow statistics of day {}: {:?}", day, err);
+            return;
+        }
+        slog::info!(self.logger, "Aggregated flow statistics of day {}", day);
+        self.next_flow_statistics_day = Some(day + MILLIS_IN_DAY);
+
+        let path = match &settings.publish_path {
+            Some(path) => path,
+            None => return,
+        };
+        let from = (day + MILLIS_IN_DAY).saturating_sub(settings.publish_days * MILLIS_IN_DAY);
+        let published = FlowStatistic::get_between(&psql_connection, from as i64, (day + MILLIS_IN_DAY) as i64)
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|statistics| {
+                std::fs::write(path, statistics_to_json(&statistics).to_string()).map_err(|err| err.to_string())
+            });
+        if let Err(err) = published {
+            slog::error!(self.logger, "Failed to publish flow statistics to {}: {}", path, err);
+        }
+    }
+
+    /// Accrues interest on the fiat balances of users for the last completed day, paid out of the
+    /// funding income account of the currency. Every account is claimed in the database before it is
+    /// paid, so a day that is run again, e.g. after a restart, never pays an account twice.
+    pub fn accrue_interest(&mut self) {
+        if self.interest_settings.apy.is_empty() {
+            return;
+        }
+        self.audit_context = AuditContext::bank("Interest");
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let now = utils::time::time_now();
+
+        // Interest starts accruing with the day the engine was first run with interest configured.
+        let day = match self.next_interest_day {
+            Some(day) => day,
+            None => match InterestAccrual::get_last_day(&psql_connection) {
+                Ok(Some(last_day)) => last_day as u64 + MILLIS_IN_DAY,
+                Ok(None) => day_start(now).saturating_sub(MILLIS_IN_DAY),
+                Err(err) => {
+                    slog::error!(self.logger, "Failed to fetch last interest day: {:?}", err);
+                    return;
                 }
-                Api::PaymentRequest(mut msg) => {
-                    slog::warn!(self.logger, "Received withdrawal request: {:?}", msg);
+            },
+        };
 
-                    let uid = msg.uid;
+        if day + MILLIS_IN_DAY > now {
+            self.next_interest_day = Some(day);
+            return;
+        }
 
-                    if !self.check_withdrawal_request_rate_limit(uid) {
-                        let payment_response = PaymentResponse::error(
-                            PaymentResponseError::RequestLimitExceeded,
-                            msg.req_id,
-                            uid,
-                            msg.payment_request,
-                            msg.currency,
-                            None,
-                        );
-                        let msg = Message::Api(Api::PaymentResponse(payment_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        let mut accounts = self
+            .ledger
+            .user_accounts
+            .iter()
+            .flat_map(|(uid, user_account)| {
+                user_account
+                    .accounts
+                    .values()
+                    .map(move |account| (*uid, account.clone()))
+            })
+            .filter_map(|(uid, account)| {
+                let apy = self.interest_settings.apy(account.currency)?;
+                let amount = daily_interest(account.balance, apy);
+                (amount > Decimal::ZERO).then(|| (uid, account, apy, amount))
+            })
+            .collect::<Vec<_>>();
+        accounts.sort_by_key(|(uid, account, _, _)| (*uid, account.account_id));
+
+        let mut totals = HashMap::<Currency, Decimal>::new();
+        for (_, account, _, amount) in accounts.iter() {
+            *totals.entry(account.currency).or_insert(Decimal::ZERO) += *amount;
+        }
 
-                    let mut outbound_account = {
-                        let user_account = match self.ledger.user_accounts.get_mut(&uid) {
-                            Some(ua) => ua,
-                            None => {
-                                let payment_response = PaymentResponse::error(
-                                    PaymentResponseError::UserAccountNotFound,
-                                    msg.req_id,
-                                    uid,
-                                    msg.payment_request,
-                                    msg.currency,
-                                    None,
-                                );
-                                let msg = Message::Api(Api::PaymentResponse(payment_response));
-                                listener(msg, ServiceIdentity::Api);
-                                return;
-                            }
-                        };
-                        user_account.get_default_account(msg.currency, None)
-                    };
+        // Interest is only paid out of funding income, a currency without enough of it accrues nothing that day.
+        let mut funded = HashSet::new();
+        for (currency, total) in totals {
+            let available = self.ledger.get_funding_account(currency).balance;
+            if available < total {
+                slog::warn!(
+                    self.logger,
+                    "Funding income of {} {} doesn't cover interest of {} for day {}",
+                    available,
+                    currency,
+                    total,
+                    day
+                );
+                continue;
+            }
+            funded.insert(currency);
+        }
 
-                    if self.is_insurance_fund_depleted() {
-                        slog::warn!(
+        for (uid, account, apy, amount) in accounts {
+            if !funded.contains(&account.currency) {
+                continue;
+            }
+
+            let accrual = InsertableInterestAccrual {
+                account_id: account.account_id,
+                uid: uid as i32,
+                day: day as i64,
+                currency: account.currency.to_string(),
+                balance: BigDecimal::from_str(&account.balance.to_string()).unwrap_or_else(|_| BigDecimal::from(0)),
+                apy: BigDecimal::from_str(&apy.to_string()).unwrap_or_else(|_| BigDecimal::from(0)),
+                amount: BigDecimal::from_str(&amount.to_string()).unwrap_or_else(|_| BigDecimal::from(0)),
+                created_at: now as i64,
+            };
+            let accrual_id = match accrual.claim(&psql_connection) {
+                Ok(Some(accrual_id)) => accrual_id,
+                Ok(None) => continue,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to claim interest of account {} for day {}: {:?}",
+                        account.account_id,
+                        day,
+                        err
+                    );
+                    return;
+                }
+            };
+
+            match self.pay_interest(uid, account.account_id, Money::new(account.currency, Some(amount))) {
+                Ok(txid) => {
+                    if let Err(err) = InterestAccrual::set_txid(&psql_connection, accrual_id, &txid) {
+                        slog::error!(
                             self.logger,
-                            "Insurance fund is depleted. Rejecting Lnurl Withdrawal Request."
+                            "Failed to store txid of interest {}: {:?}",
+                            accrual_id,
+                            err
                         );
                     }
+                }
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to pay interest of account {} for day {}: {}",
+                        account.account_id,
+                        day,
+                        err
+                    );
+                }
+            }
+        }
 
-                    if let Some(ref amount) = msg.amount {
-                        if amount.value <= dec!(0) {
-                            let payment_response = PaymentResponse::error(
-                                PaymentResponseError::InvalidAmount,
-                                msg.req_id,
-                                uid,
-                                msg.payment_request,
-                                msg.currency,
-                                None,
-                            );
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    }
+        slog::info!(self.logger, "Accrued interest of day {}", day);
+        self.next_interest_day = Some(day + MILLIS_IN_DAY);
+    }
+
+    fn pay_interest(&mut self, uid: UserId, account_id: AccountId, amount: Money) -> Result<String, BankError> {
+        let mut account = self
+            .ledger
+            .user_accounts
+            .get(&uid)
+            .and_then(|user_account| user_account.accounts.get(&account_id))
+            .cloned()
+            .ok_or(BankError::AccountNotFound)?;
+        let mut funding_account = self.ledger.get_funding_account(amount.currency);
+
+        let txid = self.make_tx(&mut funding_account, DEALER_UID, &mut account, uid, amount.clone())?;
+
+        self.ledger
+            .funding_account
+            .accounts
+            .insert(funding_account.account_id, funding_account.clone());
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+        self.update_account(&funding_account, DEALER_UID);
+        self.update_account(&account, uid);
+
+        self.make_summary_tx(
+            &funding_account,
+            DEALER_UID,
+            &account,
+            uid,
+            amount,
+            None,
+            None,
+            Some(txid.clone()),
+            Some(txid.clone()),
+            None,
+            Some(String::from("Interest")),
+        )?;
+        Ok(txid)
+    }
+
+    fn interest_history(&self, request: &GetInterestHistory) -> InterestHistory {
+        let mut response = InterestHistory {
+            req_id: request.req_id,
+            uid: request.uid,
+            entries: Vec::new(),
+            totals: HashMap::new(),
+            error: None,
+        };
+
+        let from = request.from.unwrap_or(0);
+        let to = request.to.unwrap_or_else(utils::time::time_now);
+        if from > to {
+            response.error = Some(InterestHistoryError::InvalidPeriod);
+            return response;
+        }
 
-                    // If user specified a username then we attempt to make an internal transaction.
-                    if msg.receipient.is_some() {
-                        self.make_internal_tx(msg, listener);
-                        return;
-                    }
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                response.error = Some(InterestHistoryError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
 
-                    let conn = match &self.conn_pool {
-                        Some(conn) => conn,
-                        None => {
-                            slog::error!(self.logger, "No database provided.");
-                            let payment_response = PaymentResponse::error(
-                                PaymentResponseError::DatabaseConnectionFailed,
-                                msg.req_id,
-                                uid,
-                                msg.payment_request,
-                                msg.currency,
-                                None,
-                            );
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(InterestHistoryError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
 
-                    let psql_connection = match conn.get() {
-                        Ok(psql_connection) => psql_connection,
-                        Err(_) => {
-                            slog::error!(self.logger, "Couldn't get psql connection.");
-                            let payment_response = PaymentResponse::error(
-                                PaymentResponseError::DatabaseConnectionFailed,
-                                msg.req_id,
-                                uid,
-                                msg.payment_request,
-                                msg.currency,
-                                None,
-                            );
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+        let accruals =
+            match InterestAccrual::get_by_uid_between(&psql_connection, request.uid as i32, from as i64, to as i64) {
+                Ok(accruals) => accruals,
+                Err(err) => {
+                    slog::error!(self.logger, "Failed to fetch interest of {}: {:?}", request.uid, err);
+                    response.error = Some(InterestHistoryError::FailedToFetchData);
+                    return response;
+                }
+            };
 
-                    let payment_request = msg
-                        .clone()
-                        .payment_request
-                        .unwrap_or_else(|| panic!("Payment request not specified in the message: {:?}", msg));
+        let to_decimal = |value: &BigDecimal| Decimal::from_str(&value.to_string()).unwrap_or(Decimal::ZERO);
+        for accrual in accruals {
+            let currency = match Currency::from_str(&accrual.currency) {
+                Ok(currency) => currency,
+                Err(_) => continue,
+            };
+            let amount = to_decimal(&accrual.amount);
+            *response.totals.entry(currency).or_insert(Decimal::ZERO) += amount;
+            response.entries.push(InterestEntry {
+                day: accrual.day as u64,
+                account_id: accrual.account_id,
+                currency,
+                balance: to_decimal(&accrual.balance),
+                apy: to_decimal(&accrual.apy),
+                amount,
+                txid: accrual.txid,
+            });
+        }
+        response
+    }
 
-                    let decoded = match payment_request.parse::<lightning_invoice::Invoice>() {
-                        Ok(d) => d,
-                        Err(_) => {
-                            let payment_response = PaymentResponse::error(
-                                PaymentResponseError::InvalidInvoice,
-                                msg.req_id,
-                                uid,
-                                msg.payment_request,
-                                msg.currency,
-                                None,
-                            );
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+    /// Loads the end of the last closed period, transactions up to it are rejected.
+    pub fn init_period_close(&mut self) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
 
-                    // If the user supplied a zero-amount invoice, return an error
-                    let (invoice_amount_millisats, invoice_amount_sats) =
-                        if let Some(millisats) = decoded.amount_milli_satoshis() {
-                            (millisats, millisats / 1000)
-                        } else {
-                            let payment_response = PaymentResponse::error(
-                                PaymentResponseError::ZeroAmountInvoice,
-                                msg.req_id,
-                                uid,
-                                msg.payment_request,
-                                msg.currency,
-                                None,
-                            );
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        };
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
 
-                    // Amount in sats that we're paying.
-                    let amount_in_sats = Decimal::new(invoice_amount_sats as i64, 0);
-                    // Amount in btc that we're paying.
-                    let amount_in_btc = Money::from_sats(amount_in_sats);
+        match PeriodClose::get_latest_active(&psql_connection) {
+            Ok(close) => self.closed_until = close.map(|close| close.closed_until as u64),
+            Err(err) => panic!("Failed to load closed periods: {:?}", err),
+        }
+    }
 
-                    msg.amount = Some(amount_in_btc.clone());
+    fn close_period(&mut self, request: &ClosePeriod) -> Result<ClosingReport, String> {
+        if request.operator.trim().is_empty() {
+            return Err("Operator is required".to_string());
+        }
 
-                    // If payed from a fiat account we have to get a quote first.
-                    if msg.currency != Currency::BTC && msg.rate.is_none() {
-                        let msg = Message::Api(Api::PaymentRequest(msg));
-                        listener(msg, ServiceIdentity::Dealer);
-                        return;
-                    }
+        let now = utils::time::time_now();
+        if request.until >= now {
+            return Err("Only periods in the past can be closed".to_string());
+        }
+        if matches!(self.closed_until, Some(closed_until) if request.until <= closed_until) {
+            return Err("Period ends before the last closed period".to_string());
+        }
 
-                    if msg.currency == Currency::BTC {
-                        msg.rate = Some(Rate {
-                            base: Currency::BTC,
-                            quote: Currency::BTC,
-                            value: dec!(1),
-                        });
-                    }
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                    let invoice = if let Ok(invoice) =
-                        models::invoices::Invoice::get_by_payment_request(&psql_connection, payment_request.clone())
-                    {
-                        invoice
-                    } else {
-                        let invoice = models::invoices::Invoice {
-                            payment_request: payment_request.clone(),
-                            rhash: decoded.payment_hash().to_string(),
-                            payment_hash: decoded.payment_hash().to_string(),
-                            created_at: utils::time::time_now() as i64,
-                            value: invoice_amount_sats as i64,
-                            value_msat: invoice_amount_millisats as i64,
-                            expiry: decoded.expiry_time().as_secs() as i64,
-                            settled: false,
-                            add_index: -1,
-                            settled_date: 0,
-                            uid: uid as i32,
-                            account_id: Uuid::default().to_string(),
-                            owner: None,
-                            fees: None,
-                            incoming: false,
-                            currency: Some(msg.currency.to_string()),
-                            target_account_currency: None,
-                            reference: None,
-                        };
-                        invoice
-                            .insert(&psql_connection)
-                            .expect("Failed to insert psql connection");
-                        invoice
-                    };
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                    dbg!(&invoice);
+        let close = InsertablePeriodClose {
+            closed_until: request.until as i64,
+            closed_at: now as i64,
+            closed_by: request.operator.clone(),
+        };
+        let (close, balances) = close
+            .close(&psql_connection)
+            .map_err(|err| format!("Failed to close period: {:?}", err))?;
 
-                    let amount = amount_in_btc.clone();
+        self.closed_until = Some(close.closed_until as u64);
+        slog::info!(
+            self.logger,
+            "Period until {} closed by {}",
+            close.closed_until,
+            close.closed_by
+        );
 
-                    let fees = if let Some(f) = msg.fees.clone() {
-                        f
-                    } else {
-                        Money::from_sats(dec!(0))
-                    };
+        let mut totals = HashMap::new();
+        let balances = balances
+            .into_iter()
+            .filter_map(|balance| {
+                let owner = self.account_owner(balance.account_id);
+                let closing_balance = ClosingBalance {
+                    account_id: balance.account_id,
+                    uid: owner.as_ref().map(|(uid, _)| *uid),
+                    currency: owner.as_ref().map(|(_, account)| account.currency),
+                    balance: Decimal::from_str(&balance.balance.to_string()).ok()?,
+                };
+                if let Some(currency) = closing_balance.currency {
+                    *totals.entry(currency).or_insert(Decimal::ZERO) += closing_balance.balance;
+                }
+                Some(closing_balance)
+            })
+            .collect();
+
+        Ok(ClosingReport {
+            close_id: close.id,
+            closed_until: close.closed_until as u64,
+            closed_at: close.closed_at as u64,
+            closed_by: close.closed_by,
+            balances,
+            totals,
+        })
+    }
 
-                    let rate = if let Some(r) = msg.rate.clone() {
-                        r
-                    } else {
-                        Rate {
-                            base: Currency::BTC,
-                            quote: Currency::BTC,
-                            value: dec!(1),
-                        }
-                    };
+    fn is_frozen(&self, uid: UserId) -> bool {
+        self.ledger
+            .user_accounts
+            .get(&uid)
+            .map_or(false, |user_account| user_account.frozen)
+    }
 
-                    // Preparing a generic response.
-                    let mut payment_response = PaymentResponse {
-                        amount: Some(amount),
-                        payment_hash: Uuid::new_v4().to_string(),
-                        req_id: msg.req_id,
-                        uid,
-                        success: false,
-                        payment_request: Some(payment_request.clone()),
-                        currency: msg.currency,
-                        fees: Some(fees),
-                        rate: Some(rate.clone()),
-                        error: None,
-                        preimage: None,
-                    };
+    /// Checks that the user can pay for the channel and offers it under a new k1.
+    async fn get_lnurl_channel(&mut self, msg: GetLnurlChannelRequest) -> GetLnurlChannelResponse {
+        let mut response = GetLnurlChannelResponse {
+            req_id: msg.req_id,
+            uri: None,
+            callback: self.lnurl_settings.url(&self.lnurl_settings.channel_callback_path),
+            k1: Uuid::new_v4(),
+            channel_size_sats: 0,
+            fee_sats: 0,
+            error: None,
+        };
+        let settings = match &self.lnurl_channel_settings {
+            Some(settings) => settings.clone(),
+            None => {
+                response.error = Some(LnurlChannelError::NotOffered);
+                return response;
+            }
+        };
+        response.channel_size_sats = settings.channel_size_sats;
+        response.fee_sats = settings.fee_sats;
 
-                    if let Some(owner) = invoice.owner {
-                        if uid == owner as u64 {
-                            slog::info!(self.logger, "User tried to make self payment. Not allowed.");
-                            payment_response.error = Some(PaymentResponseError::SelfPayment);
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    }
+        if let Err(err) = self.check_lnurl_channel_fee(msg.uid, settings.fee_sats) {
+            response.error = Some(err);
+            return response;
+        }
 
-                    // If invoice was already paid we reject this the payment request.
-                    if invoice.settled {
-                        slog::info!(self.logger, "Invoice is already settled.");
-                        payment_response.error = Some(PaymentResponseError::InvoiceAlreadyPaid);
-                        let msg = Message::Api(Api::PaymentResponse(payment_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        if self.lnd_node_info.uris.is_empty() {
+            if let Ok(node_info) = self.lnd_connector.get_node_info().await {
+                self.lnd_node_info = node_info;
+            }
+        }
+        response.uri = match self.lnd_node_info.uris.first() {
+            Some(uri) => Some(uri.clone()),
+            None => {
+                response.error = Some(LnurlChannelError::NodeNotReachable);
+                return response;
+            }
+        };
 
-                    // We could be dealing with an internal transaction in which case we cannot borrow two accounts
-                    // as mutable. Hence we have to work with local scoping. We first deal with the payer.
+        self.lnurl_channel_requests
+            .insert(response.k1, (utils::time::time_now(), msg.uid));
+        response
+    }
 
-                    let outbound_balance = outbound_account.balance;
+    fn check_lnurl_channel_fee(&mut self, uid: UserId, fee_sats: u64) -> Result<(), LnurlChannelError> {
+        let user_account = self
+            .ledger
+            .user_accounts
+            .get_mut(&uid)
+            .ok_or(LnurlChannelError::UserAccountNotFound)?;
+        if user_account.frozen {
+            return Err(LnurlChannelError::TransactionFailed);
+        }
+        let account = user_account.get_default_account(Currency::BTC, Some(AccountType::Internal));
+        if account.balance < Money::from_sats(Decimal::from(fee_sats)).value {
+            return Err(LnurlChannelError::InsufficientFunds);
+        }
+        Ok(())
+    }
 
-                    // Worst case amount user will have to pay for this transaction in Bitcoin.
-                    let max_fee_in_btc = (amount_in_btc.value * self.ln_network_fee_margin)
-                        .round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::AwayFromZero);
+    /// Opens the channel offered under the k1 of the callback and charges the user for it.
+    async fn open_lnurl_channel(&mut self, msg: RequestChannelOpen) -> Result<String, LnurlChannelError> {
+        let settings = self
+            .lnurl_channel_settings
+            .clone()
+            .ok_or(LnurlChannelError::NotOffered)?;
+        let (_, uid) = self
+            .lnurl_channel_requests
+            .remove(&msg.k1)
+            .ok_or(LnurlChannelError::RequestNotFound)?;
+        // The balance may have changed since the channel was offered.
+        self.check_lnurl_channel_fee(uid, settings.fee_sats)?;
+
+        let funding_txid = match self
+            .lnd_connector
+            .open_channel(&msg.remote_id, settings.channel_size_sats, msg.private)
+            .await
+        {
+            Ok(txid) => txid,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to open channel to {} for user {}: {:?}",
+                    msg.remote_id,
+                    uid,
+                    err
+                );
+                return Err(LnurlChannelError::FailedToOpenChannel);
+            }
+        };
+        slog::info!(
+            self.logger,
+            "Opened channel to {} for user {}, funding txid {}.",
+            msg.remote_id,
+            uid,
+            funding_txid
+        );
 
-                    let settings = self.lnd_connector_settings.clone();
-                    let mut lnd_connector = LndConnector::new(settings).await;
+        let mut account = match self.ledger.user_accounts.get_mut(&uid) {
+            Some(user_account) => user_account.get_default_account(Currency::BTC, Some(AccountType::Internal)),
+            None => return Err(LnurlChannelError::UserAccountNotFound),
+        };
+        let fee = Money::from_sats(Decimal::from(settings.fee_sats));
+        if let Err(err) = self.collect_fee(&mut account, uid, FeeOperation::ChannelOpen, &fee) {
+            // The channel is open already, the operator has to settle the fee with the user.
+            slog::error!(
+                self.logger,
+                "Failed to collect channel open fee of user {}: {}",
+                uid,
+                err
+            );
+            return Ok(funding_txid);
+        }
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+        self.update_account(&account, uid);
+        Ok(funding_txid)
+    }
 
-                    let estimated_fee = if let Ok(res) = lnd_connector
-                        .probe(payment_request.clone(), self.ln_network_fee_margin)
-                        .await
-                    {
-                        if !res.is_empty() {
-                            let best_route = res[0].clone();
-                            Decimal::new(best_route.total_fees, 0) / Decimal::new(SATS_IN_BITCOIN as i64, 0)
-                        } else {
-                            max_fee_in_btc
-                        }
-                    } else {
-                        max_fee_in_btc
-                    };
+    /// Creates an invoice paid in the asset backing the currency of the request.
+    async fn create_asset_invoice(
+        &mut self,
+        conn: &diesel::PgConnection,
+        msg: InvoiceRequest,
+        account_id: AccountId,
+    ) -> InvoiceResponse {
+        let mut invoice_response = InvoiceResponse {
+            amount: msg.amount.clone(),
+            req_id: msg.req_id,
+            uid: msg.uid,
+            meta: msg.meta.clone(),
+            metadata: msg.metadata.clone(),
+            rate: None,
+            payment_request: None,
+            currency: msg.currency,
+            target_account_currency: msg.target_account_currency,
+            account_id: Some(account_id),
+            error: None,
+            fees: None,
+        };
+        let (tapd, asset) = match self
+            .taproot_assets
+            .as_ref()
+            .and_then(|tapd| tapd.asset(msg.currency).map(|asset| (tapd, asset.clone())))
+        {
+            Some(tapd) => tapd,
+            None => {
+                invoice_response.error = Some(InvoiceResponseError::AssetNotAvailable);
+                return invoice_response;
+            }
+        };
+        let units = match asset.to_units(msg.amount.value).filter(|units| *units > 0) {
+            Some(units) => units,
+            None => {
+                invoice_response.error = Some(InvoiceResponseError::InvalidAmount);
+                return invoice_response;
+            }
+        };
+        let asset_invoice = match tapd.add_invoice(&asset, units, msg.meta.clone()).await {
+            Ok(asset_invoice) => asset_invoice,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to create asset invoice {}: {:?}", msg.req_id, err);
+                invoice_response.error = Some(InvoiceResponseError::AssetNotAvailable);
+                return invoice_response;
+            }
+        };
+        self.metrics.invoices_created.inc();
+
+        // The payer's sats are converted by the asset channel peer and tapd only settles the invoice once the
+        // asset amount arrived, so there is no amount in sats to check.
+        let invoice = Invoice {
+            payment_request: asset_invoice.payment_request.clone(),
+            rhash: asset_invoice.r_hash,
+            payment_hash: asset_invoice.payment_addr,
+            created_at: utils::time::time_now() as i64,
+            value: 0,
+            value_msat: 0,
+            expiry: 86400,
+            settled: false,
+            add_index: asset_invoice.add_index as i64,
+            settled_date: 0,
+            account_id: account_id.to_string(),
+            uid: msg.uid as i32,
+            incoming: true,
+            owner: Some(msg.uid as i32),
+            fees: None,
+            currency: Some(msg.currency.to_string()),
+            target_account_currency: None,
+            reference: Some(msg.meta.clone()),
+            expired: false,
+            metadata_fields: msg
+                .metadata_fields
+                .as_ref()
+                .and_then(|fields| serde_json::to_string(fields).ok()),
+            reference_hash: None,
+            metadata_fields_hash: None,
+            descriptions_hashed_at: None,
+            asset_units: Some(units as i64),
+            comment: msg.comment.clone(),
+            payer_data: msg.payer_data.clone(),
+            description_hash: None,
+        };
+        if let Err(err) = invoice.insert(conn) {
+            slog::error!(self.logger, "Error inserting asset invoice: {:?}", err);
+            invoice_response.error = Some(InvoiceResponseError::DatabaseConnectionFailed);
+            return invoice_response;
+        }
+        invoice_response.payment_request = Some(asset_invoice.payment_request);
+        invoice_response
+    }
 
-                    let estimated_fee = Money::from_btc(estimated_fee);
+    /// Hands out a new address of the bank's Liquid wallet, or a peg-in address, for deposits to an L-BTC account.
+    fn liquid_address(&mut self, msg: &LiquidAddressRequest) -> Result<(String, Option<String>), LiquidError> {
+        let client = self.liquid.as_ref().ok_or(LiquidError::NotAvailable)?;
+        let account = self
+            .ledger
+            .user_accounts
+            .entry(msg.uid)
+            .or_insert_with(|| UserAccount::new(msg.uid))
+            .get_account_or_default(msg.account_id, Currency::LBTC)
+            .ok_or(LiquidError::AccountDoesNotExist)?;
+        let conn = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or(LiquidError::DatabaseConnectionFailed)?;
 
-                    let outbound_amount_in_btc_plus_max_fees =
-                        Money::from_btc(amount_in_btc.value + estimated_fee.value);
+        let (address, claim_script) = if msg.peg_in {
+            client.pegin_address().map(|(address, script)| (address, Some(script)))
+        } else {
+            client.new_address().map(|address| (address, None))
+        }
+        .map_err(|err| {
+            slog::error!(
+                self.logger,
+                "Failed to get a Liquid address for {}: {}",
+                msg.req_id,
+                err
+            );
+            LiquidError::NodeFailed
+        })?;
+
+        let liquid_address = LiquidAddress {
+            address: address.clone(),
+            uid: msg.uid as i32,
+            account_id: account.account_id.to_string(),
+            claim_script: claim_script.clone(),
+            created_at: utils::time::time_now() as i64,
+        };
+        if let Err(err) = liquid_address.insert(&conn) {
+            slog::error!(self.logger, "Failed to store Liquid address: {:?}", err);
+            return Err(LiquidError::DatabaseConnectionFailed);
+        }
+        Ok((address, claim_script))
+    }
 
-                    // Worst case amount user will have to pay for this transaction in outbound Currency.
-                    let outbound_amount_in_outbound_currency_plus_max_fee =
-                        outbound_amount_in_btc_plus_max_fees.exchange(&rate).unwrap();
+    /// Books L-BTC the bank's wallet received for a user, on the account the address was handed out for if it
+    /// still exists.
+    fn credit_lbtc(
+        &mut self,
+        uid: UserId,
+        account_id: Option<AccountId>,
+        amount_sats: u64,
+        reference: &str,
+    ) -> Result<Money, BankError> {
+        let amount = Money::new(Currency::LBTC, Some(sats_to_btc(amount_sats)));
+        let mut account = {
+            let user_account = self
+                .ledger
+                .user_accounts
+                .entry(uid)
+                .or_insert_with(|| UserAccount::new(uid));
+            user_account
+                .get_account_or_default(account_id, Currency::LBTC)
+                .unwrap_or_else(|| user_account.get_default_account(Currency::LBTC, None))
+        };
+        let mut liability_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::LBTC, Some(AccountType::External));
+        let txid = self.make_tx(&mut liability_account, BANK_UID, &mut account, uid, amount.clone())?;
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(liability_account.account_id, liability_account.clone());
+        self.update_account(&account, uid);
+        self.update_account(&liability_account, BANK_UID);
+        self.make_summary_tx(
+            &liability_account,
+            BANK_UID,
+            &account,
+            uid,
+            amount.clone(),
+            None,
+            None,
+            Some(txid.clone()),
+            Some(txid),
+            None,
+            Some(reference.to_string()),
+        )?;
+        Ok(amount)
+    }
 
-                    // Checking whether user has enough funds on their outbound currency account.
-                    dbg!(&outbound_balance);
-                    dbg!(&outbound_amount_in_outbound_currency_plus_max_fee);
-                    if outbound_balance < outbound_amount_in_outbound_currency_plus_max_fee.value {
-                        payment_response.error = Some(PaymentResponseError::InsufficientFundsForFees);
-                        let msg = Message::Api(Api::PaymentResponse(payment_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+    /// Credits confirmed L-BTC payments to the addresses handed out to users. Outputs are recorded so that they
+    /// are credited once even though the wallet history is checked again after a restart.
+    pub async fn check_liquid_deposits(&mut self) {
+        let client = match &self.liquid {
+            Some(client) => client,
+            None => return,
+        };
+        let now = utils::time::time_now();
+        if now.saturating_sub(self.last_liquid_deposit_check) < LIQUID_DEPOSIT_CHECK_INTERVAL_MS {
+            return;
+        }
+        self.last_liquid_deposit_check = now;
 
-                    // If invoice is not owned by any user (its leaving the platform).
-                    if invoice.owner.is_none() {
-                        // We need to debit amount a user is trying to send before sending the payment so he cannot
-                        // double spend.
-                        // We always going to be sending funds to an external BTC acount.
-                        let mut bank_liability_account = self
-                            .ledger
-                            .bank_liabilities
-                            .get_default_account(Currency::BTC, Some(AccountType::External));
+        let min_confirmations = client.settings().min_confirmations;
+        let (receipts, last_block) = match client.received_since(self.liquid_last_block.as_deref()) {
+            Ok(received) => received,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to check the Liquid wallet for deposits: {}", err);
+                return;
+            }
+        };
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
 
-                        // If its a fiat payment then we need to transact with the dealer.
-                        if msg.currency != Currency::BTC {
-                            let mut dealer_fiat_account = self
-                                .ledger
-                                .dealer_accounts
-                                .get_default_account(msg.currency, Some(AccountType::Internal));
+        for receipt in receipts {
+            if receipt.confirmations < min_confirmations {
+                continue;
+            }
+            let address = match LiquidAddress::get(&conn, &receipt.address) {
+                Ok(address) => address,
+                // Change and claimed peg-ins aren't paid to addresses of users.
+                Err(_) => continue,
+            };
+            let deposit = LiquidDeposit {
+                txid: receipt.txid.clone(),
+                vout: receipt.vout as i32,
+                uid: address.uid,
+                amount_sats: receipt.amount_sats as i64,
+                credited_at: now as i64,
+            };
+            match deposit.insert_new(&conn) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to record Liquid deposit {}: {:?}",
+                        receipt.txid,
+                        err
+                    );
+                    return;
+                }
+            }
+            let account_id = Uuid::parse_str(&address.account_id).ok();
+            match self.credit_lbtc(address.uid as UserId, account_id, receipt.amount_sats, "LiquidDeposit") {
+                Ok(amount) => slog::info!(
+                    self.logger,
+                    "Credited Liquid deposit {}:{} of {} to user {}",
+                    receipt.txid,
+                    receipt.vout,
+                    amount.value,
+                    address.uid
+                ),
+                Err(err) => slog::error!(
+                    self.logger,
+                    "Liquid deposit {}:{} was recorded but not credited: {:?}",
+                    receipt.txid,
+                    receipt.vout,
+                    err
+                ),
+            }
+        }
+        self.liquid_last_block = Some(last_block);
+    }
 
-                            // User account to dealer account.
-                            let outbound_txid = if let Ok(txid) = self.make_tx(
-                                &mut outbound_account,
-                                uid,
-                                &mut dealer_fiat_account,
-                                DEALER_UID,
-                                outbound_amount_in_outbound_currency_plus_max_fee.clone(),
-                            ) {
-                                txid
-                            } else {
-                                slog::error!(self.logger, "Error making transaction.");
-                                return;
-                            };
+    /// Claims a peg-in to an address handed out to the user and credits the claimed L-BTC right away, the claim
+    /// is only accepted by elementsd once the Bitcoin transaction is final.
+    fn claim_liquid_pegin(&mut self, msg: &LiquidClaimPegInRequest) -> Result<(String, Money), LiquidError> {
+        let client = self.liquid.as_ref().ok_or(LiquidError::NotAvailable)?;
+        let conn = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or(LiquidError::DatabaseConnectionFailed)?;
+        let address = LiquidAddress::get_by_claim_script(&conn, msg.uid as i32, &msg.claim_script)
+            .map_err(|_| LiquidError::UnknownClaimScript)?;
+
+        let txid = client
+            .claim_pegin(&msg.bitcoin_tx, &msg.txout_proof, &msg.claim_script)
+            .map_err(|err| {
+                slog::info!(self.logger, "Peg-in claim {} rejected: {}", msg.req_id, err);
+                LiquidError::NodeFailed
+            })?;
+        let amount_sats = client.received_sats(&txid).map_err(|err| {
+            slog::error!(
+                self.logger,
+                "Peg-in {} was claimed in {} but not credited: {}",
+                msg.req_id,
+                txid,
+                err
+            );
+            LiquidError::NodeFailed
+        })?;
 
-                            let mut dealer_btc_account = self
-                                .ledger
-                                .dealer_accounts
-                                .get_default_account(Currency::BTC, Some(AccountType::Internal));
+        let deposit = LiquidDeposit {
+            txid: txid.clone(),
+            vout: 0,
+            uid: address.uid,
+            amount_sats: amount_sats as i64,
+            credited_at: utils::time::time_now() as i64,
+        };
+        if !matches!(deposit.insert_new(&conn), Ok(true)) {
+            return Err(LiquidError::TransactionFailed);
+        }
+        let account_id = Uuid::parse_str(&address.account_id).ok();
+        let amount = self
+            .credit_lbtc(msg.uid, account_id, amount_sats, "LiquidPegIn")
+            .map_err(|err| {
+                slog::error!(self.logger, "Peg-in {} was claimed but not credited: {:?}", txid, err);
+                LiquidError::TransactionFailed
+            })?;
+        Ok((txid, amount))
+    }
 
-                            let inbound_txid = if let Ok(txid) = self.make_tx(
-                                &mut dealer_btc_account,
-                                DEALER_UID,
-                                &mut bank_liability_account,
-                                BANK_UID,
-                                outbound_amount_in_btc_plus_max_fees.clone(),
-                            ) {
-                                txid
-                            } else {
-                                slog::error!(self.logger, "Error making transaction.");
-                                return;
-                            };
+    /// Sends L-BTC of the user to a Liquid address or pegs it out. The user is charged the amount and the
+    /// withdrawal fee once elementsd broadcast the transaction.
+    fn withdraw_liquid(&mut self, msg: &LiquidWithdrawalRequest) -> Result<(String, Money), LiquidError> {
+        let uid = msg.uid;
+        let client = self.liquid.as_ref().ok_or(LiquidError::NotAvailable)?;
+        if self.withdrawals_halted {
+            return Err(LiquidError::WithdrawalsHalted);
+        }
+        if self.is_frozen(uid) {
+            return Err(LiquidError::UserFrozen);
+        }
+        let amount_sats = match btc_to_sats(msg.amount.value) {
+            Some(sats) if msg.amount.currency == Currency::LBTC && sats > 0 => sats,
+            _ => return Err(LiquidError::InvalidAmount),
+        };
+        let mut account = self
+            .ledger
+            .user_accounts
+            .get_mut(&uid)
+            .ok_or(LiquidError::UserAccountNotFound)?
+            .get_account_or_default(msg.account_id, Currency::LBTC)
+            .ok_or(LiquidError::AccountDoesNotExist)?;
+        if self.frozen_accounts.contains_key(&account.account_id) {
+            return Err(LiquidError::AccountFrozen);
+        }
+        let fee_sats = client.settings().withdrawal_fee_sats;
+        let total = Money::new(Currency::LBTC, Some(sats_to_btc(amount_sats + fee_sats)));
+        if account.balance < total.value {
+            return Err(LiquidError::InsufficientFunds);
+        }
 
-                            self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+        let sent = if msg.peg_out {
+            client.send_to_mainchain(&msg.address, amount_sats)
+        } else {
+            client.send_to_address(&msg.address, amount_sats)
+        };
+        let txid = sent.map_err(|err| {
+            slog::info!(self.logger, "Liquid withdrawal {} failed: {}", msg.req_id, err);
+            LiquidError::NodeFailed
+        })?;
+
+        let mut liability_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::LBTC, Some(AccountType::External));
+        let tx_id = self
+            .make_tx(&mut account, uid, &mut liability_account, BANK_UID, total.clone())
+            .map_err(|err| {
+                slog::error!(
+                    self.logger,
+                    "Liquid withdrawal {} was sent but not booked: {:?}",
+                    txid,
+                    err
+                );
+                LiquidError::TransactionFailed
+            })?;
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(liability_account.account_id, liability_account.clone());
+        self.update_account(&account, uid);
+        self.update_account(&liability_account, BANK_UID);
+
+        let fees = Money::new(Currency::LBTC, Some(sats_to_btc(fee_sats)));
+        let reference = if msg.peg_out {
+            "LiquidPegOut"
+        } else {
+            "LiquidWithdrawal"
+        };
+        if self
+            .make_summary_tx(
+                &account,
+                uid,
+                &liability_account,
+                BANK_UID,
+                total,
+                None,
+                Some(fees.clone()),
+                Some(tx_id.clone()),
+                Some(tx_id),
+                None,
+                Some(reference.to_string()),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of Liquid withdrawal {}.",
+                txid
+            );
+        }
+        Ok((txid, fees))
+    }
 
-                            self.ledger
-                                .bank_liabilities
-                                .accounts
-                                .insert(bank_liability_account.account_id, bank_liability_account.clone());
-                            self.ledger
-                                .dealer_accounts
-                                .accounts
-                                .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
-                            self.ledger
-                                .dealer_accounts
-                                .accounts
-                                .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+    /// Pays an invoice of the federation's gateway and hands the user the ecash it issued. The user is charged the
+    /// amount, the gateway fee and the routing fee once the notes were taken out of the bank's federation wallet.
+    async fn withdraw_ecash(&mut self, msg: &EcashWithdrawRequest) -> Result<(String, Money), EcashError> {
+        let uid = msg.uid;
+        let gateway = self.ecash_gateway.as_ref().ok_or(EcashError::NotAvailable)?;
+        if self.withdrawals_halted {
+            return Err(EcashError::WithdrawalsHalted);
+        }
+        if self.is_frozen(uid) {
+            return Err(EcashError::UserFrozen);
+        }
+        let amount_sats = match msg.amount.try_sats() {
+            Ok(sats) if msg.amount.currency == Currency::BTC && sats > Decimal::ZERO && sats.fract().is_zero() => {
+                sats.to_u64().ok_or(EcashError::InvalidAmount)?
+            }
+            _ => return Err(EcashError::InvalidAmount),
+        };
+        let mut account = self
+            .ledger
+            .user_accounts
+            .get_mut(&uid)
+            .ok_or(EcashError::UserAccountNotFound)?
+            .get_account_or_default(msg.account_id, Currency::BTC)
+            .ok_or(EcashError::AccountDoesNotExist)?;
+        if self.frozen_accounts.contains_key(&account.account_id) {
+            return Err(EcashError::AccountFrozen);
+        }
 
-                            self.update_account(&outbound_account, msg.uid);
-                            self.update_account(&bank_liability_account, BANK_UID);
+        // The gateway takes its fee from the incoming payment, the invoice covers it on top of the amount.
+        let invoice_sats = amount_sats + gateway.settings().gateway_fee_sats;
+        let max_fee = self.fee_budget(Money::from_sats(Decimal::from(invoice_sats)).value);
+        if account.balance < Money::from_sats(Decimal::from(invoice_sats)).value + max_fee {
+            return Err(EcashError::InsufficientFunds);
+        }
+        let max_fee_sats = Money::from_btc(max_fee)
+            .try_sats()
+            .map_err(|_| EcashError::InvalidAmount)?
+            .ceil();
+
+        let (operation_id, invoice) = gateway
+            .create_invoice(invoice_sats * 1000, "Lndhubx ecash withdrawal")
+            .map_err(|err| {
+                slog::error!(
+                    self.logger,
+                    "Failed to create gateway invoice for {}: {}",
+                    msg.req_id,
+                    err
+                );
+                EcashError::GatewayFailed
+            })?;
+        let pay_response = match self
+            .lnd_connector
+            .pay_invoice(invoice, Decimal::from(invoice_sats), None, Some(max_fee_sats))
+            .await
+        {
+            Ok(pay_response) => pay_response,
+            Err(err) => {
+                slog::info!(
+                    self.logger,
+                    "Payment to the ecash gateway {} failed: {:?}",
+                    msg.req_id,
+                    err
+                );
+                return Err(EcashError::GatewayFailed);
+            }
+        };
+        // The sats left the node, from here on failures leave the ecash in the bank's federation wallet.
+        let notes = match gateway
+            .await_invoice(&operation_id)
+            .and_then(|_| gateway.spend(amount_sats * 1000))
+        {
+            Ok(notes) => notes,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Ecash withdrawal {} of user {} was paid but no notes were issued, the funds are in the \
+                     federation wallet: {}",
+                    msg.req_id,
+                    uid,
+                    err
+                );
+                return Err(EcashError::GatewayFailed);
+            }
+        };
 
-                            self.update_account(&dealer_btc_account, DEALER_UID);
-                            self.update_account(&dealer_fiat_account, DEALER_UID);
+        let fees = Money::from_msats(Decimal::from(
+            gateway.settings().gateway_fee_sats * 1000 + pay_response.fee_msat,
+        ));
+        let total = Money::from_sats(Decimal::from(amount_sats)).value + fees.value;
+        let mut liability_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::BTC, Some(AccountType::External));
+        let txid = self
+            .make_tx(
+                &mut account,
+                uid,
+                &mut liability_account,
+                BANK_UID,
+                Money::from_btc(total),
+            )
+            .map_err(|err| {
+                slog::error!(self.logger, "Ecash withdrawal {} wasn't booked: {:?}", msg.req_id, err);
+                EcashError::TransactionFailed
+            })?;
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(liability_account.account_id, liability_account.clone());
+        self.update_account(&account, uid);
+        self.update_account(&liability_account, BANK_UID);
 
-                            if self
-                                .make_summary_tx(
-                                    &outbound_account,
-                                    uid,
-                                    &bank_liability_account,
-                                    BANK_UID,
-                                    outbound_amount_in_outbound_currency_plus_max_fee.clone(),
-                                    Some(rate.clone()),
-                                    None,
-                                    Some(outbound_txid),
-                                    Some(inbound_txid),
-                                    None,
-                                    Some(String::from("ExternalPayment")),
-                                )
-                                .is_err()
-                            {
-                                return;
-                            }
-                        } else {
-                            let txid = if let Ok(txid) = self.make_tx(
-                                &mut outbound_account,
-                                uid,
-                                &mut bank_liability_account,
-                                BANK_UID,
-                                outbound_amount_in_outbound_currency_plus_max_fee.clone(),
-                            ) {
-                                txid
-                            } else {
-                                payment_response.error = Some(PaymentResponseError::TransactionFailed);
-                                let msg = Message::Api(Api::PaymentResponse(payment_response));
-                                listener(msg, ServiceIdentity::Api);
-                                return;
-                            };
+        if self
+            .make_summary_tx(
+                &account,
+                uid,
+                &liability_account,
+                BANK_UID,
+                Money::from_btc(total),
+                None,
+                Some(fees.clone()),
+                Some(txid.clone()),
+                Some(txid),
+                None,
+                Some(String::from("EcashWithdrawal")),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of ecash withdrawal {}.",
+                msg.req_id
+            );
+        }
+        Ok((notes, fees))
+    }
 
-                            self.ledger
-                                .bank_liabilities
-                                .accounts
-                                .insert(bank_liability_account.account_id, bank_liability_account.clone());
+    /// Redeems the user's notes into the bank's federation wallet, credits their value less the gateway fee and
+    /// moves the sats from the federation to the node through the gateway.
+    async fn deposit_ecash(&mut self, msg: &EcashDepositRequest) -> Result<(Money, Money), EcashError> {
+        let uid = msg.uid;
+        let gateway = self.ecash_gateway.as_ref().ok_or(EcashError::NotAvailable)?;
+        let user_account = self
+            .ledger
+            .user_accounts
+            .entry(uid)
+            .or_insert_with(|| UserAccount::new(uid));
+        let mut account = user_account
+            .get_account_or_default(msg.account_id, Currency::BTC)
+            .ok_or(EcashError::AccountDoesNotExist)?;
+
+        let amount_msat = gateway.validate(&msg.notes).map_err(|err| {
+            slog::info!(self.logger, "Notes of ecash deposit {} rejected: {}", msg.req_id, err);
+            EcashError::InvalidNotes
+        })?;
+        let credit_sats = gateway.settings().deposit_credit_sats(amount_msat);
+        if credit_sats == 0 {
+            return Err(EcashError::InvalidAmount);
+        }
+        let amount = Money::from_sats(Decimal::from(credit_sats));
+        let deposit_limit = user_account
+            .deposit_limits
+            .get(&Currency::BTC)
+            .or_else(|| self.deposit_limits.get(&Currency::BTC));
+        if matches!(deposit_limit, Some(limit) if account.balance + amount.value > *limit) {
+            return Err(EcashError::DepositLimitExceeded);
+        }
 
-                            self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+        let redeemed_msat = gateway.reissue(&msg.notes).map_err(|err| {
+            slog::info!(
+                self.logger,
+                "Failed to redeem notes of ecash deposit {}: {}",
+                msg.req_id,
+                err
+            );
+            EcashError::InvalidNotes
+        })?;
+        let credit_sats = gateway.settings().deposit_credit_sats(redeemed_msat);
+        let amount = Money::from_sats(Decimal::from(credit_sats));
+        let fees = Money::from_msats(Decimal::from(redeemed_msat) - amount.try_msats().unwrap_or_default());
 
-                            self.update_account(&outbound_account, msg.uid);
-                            self.update_account(&bank_liability_account, BANK_UID);
+        // The notes are redeemed, the user is credited even if moving the sats to the node fails.
+        match self
+            .lnd_connector
+            .create_invoice(
+                credit_sats,
+                String::from("Lndhubx ecash deposit"),
+                BANK_UID,
+                Uuid::nil(),
+                None,
+            )
+            .await
+        {
+            Ok(invoice) => {
+                if let Err(err) = gateway.pay(&invoice.payment_request) {
+                    slog::error!(
+                        self.logger,
+                        "Ecash of deposit {} stays in the federation wallet, paying the node failed: {}",
+                        msg.req_id,
+                        err
+                    );
+                }
+            }
+            Err(err) => slog::error!(
+                self.logger,
+                "Ecash of deposit {} stays in the federation wallet, no invoice of the node: {:?}",
+                msg.req_id,
+                err
+            ),
+        }
 
-                            self.make_summary_tx(
-                                &outbound_account,
-                                uid,
-                                &bank_liability_account,
-                                BANK_UID,
-                                outbound_amount_in_btc_plus_max_fees.clone(),
-                                None,
-                                None,
-                                Some(txid.clone()),
-                                Some(txid),
-                                None,
-                                Some(String::from("ExternalPayment")),
-                            );
-                        }
+        let mut liability_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(Currency::BTC, Some(AccountType::External));
+        let txid = self
+            .make_tx(&mut liability_account, BANK_UID, &mut account, uid, amount.clone())
+            .map_err(|err| {
+                slog::error!(self.logger, "Ecash deposit {} wasn't booked: {:?}", msg.req_id, err);
+                EcashError::TransactionFailed
+            })?;
+        self.insert_into_ledger(&uid, account.account_id, account.clone());
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(liability_account.account_id, liability_account.clone());
+        self.update_account(&account, uid);
+        self.update_account(&liability_account, BANK_UID);
 
-                        payment_response.success = false;
-                        payment_response.fees = Some(estimated_fee.clone());
+        if self
+            .make_summary_tx(
+                &liability_account,
+                BANK_UID,
+                &account,
+                uid,
+                amount.clone(),
+                None,
+                Some(fees.clone()),
+                Some(txid.clone()),
+                Some(txid),
+                None,
+                Some(String::from("EcashDeposit")),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of ecash deposit {}.",
+                msg.req_id
+            );
+        }
+        Ok((amount, fees))
+    }
 
-                        let payment_task_sender = self.payment_thread_sender.clone();
+    /// Pays an external invoice with the asset backing the currency of the outbound account. The account is charged
+    /// the asset amount the invoice and its routing fees cost at the rate of the asset channel peer.
+    async fn pay_with_asset(
+        &mut self,
+        conn: &diesel::PgConnection,
+        msg: &PaymentRequest,
+        mut outbound_account: Account,
+        payment_request: String,
+        amount_msat: u64,
+    ) -> PaymentResponse {
+        let uid = msg.uid;
+        let error = |error| {
+            PaymentResponse::error(
+                error,
+                msg.req_id,
+                uid,
+                Some(payment_request.clone()),
+                msg.currency,
+                None,
+            )
+        };
 
-                        let settings = self.lnd_connector_settings.clone();
-                        let req_id = msg.req_id;
-                        let payment_req = payment_request;
-                        let aib = amount_in_btc;
-                        let currency = msg.currency;
+        // Assets only leave through lightning, internal invoices are paid with a transfer.
+        if matches!(Invoice::get_by_payment_request(conn, payment_request.clone()), Ok(invoice) if invoice.owner.is_some())
+        {
+            return error(PaymentResponseError::NotPermitted);
+        }
 
-                        let estimated_fee_in_sats = estimated_fee.try_sats().unwrap();
-                        let rate_2 = rate.clone();
+        let (tapd, asset) = match self
+            .taproot_assets
+            .as_ref()
+            .and_then(|tapd| tapd.asset(msg.currency).map(|asset| (tapd, asset.clone())))
+        {
+            Some(tapd) => tapd,
+            None => return error(PaymentResponseError::AssetNotAvailable),
+        };
+        let max_amount_msat = amount_msat + tapd.fee_limit_sats() * 1000;
+        let quote = match tapd.sell_quote(&asset, max_amount_msat).await {
+            Ok(quote) => quote,
+            Err(err) => {
+                slog::error!(self.logger, "No asset quote for payment {}: {:?}", msg.req_id, err);
+                return error(PaymentResponseError::AssetNotAvailable);
+            }
+        };
+        let max_units = match quote.units_for_msat(max_amount_msat) {
+            Some(units) => units,
+            None => return error(PaymentResponseError::InvalidAmount),
+        };
+        if outbound_account.balance < asset.from_units(max_units) {
+            return error(PaymentResponseError::InsufficientFunds);
+        }
 
-                        let payment_task = tokio::task::spawn(async move {
-                            let mut lnd_connector = LndConnector::new(settings).await;
-                            match lnd_connector
-                                .pay_invoice(payment_req.clone(), amount_in_sats, None, Some(estimated_fee_in_sats))
-                                .await
-                            {
-                                Ok(result) => {
-                                    dbg!(&result);
-                                    let payment_response = PaymentResponse {
-                                        uid,
-                                        req_id,
-                                        currency,
-                                        payment_hash: result.payment_hash,
-                                        success: true,
-                                        payment_request: Some(payment_req.clone()),
-                                        amount: Some(aib),
-                                        fees: Some(Money::from_sats(Decimal::new(result.fee as i64, 0))),
-                                        rate: Some(rate_2.clone()),
-                                        error: None,
-                                        preimage: result.preimage,
-                                    };
-                                    let msg = Message::Bank(Bank::PaymentResult(PaymentResult {
-                                        uid,
-                                        currency,
-                                        rate: rate_2,
-                                        is_success: true,
-                                        amount: outbound_amount_in_btc_plus_max_fees,
-                                        payment_response,
-                                        error: None,
-                                    }));
-                                    if let Err(err) = payment_task_sender.send(msg) {
-                                        panic!("Failed to send a payment task: {:?}", err);
-                                    }
-                                }
-                                Err(e) => {
-                                    dbg!(&e);
-                                    let payment_response = PaymentResponse {
-                                        uid,
-                                        req_id,
-                                        currency,
-                                        payment_hash: String::from(""),
-                                        success: false,
-                                        payment_request: Some(payment_req.clone()),
-                                        amount: Some(aib),
-                                        fees: Some(Money::from_sats(dec!(0))),
-                                        rate: Some(rate_2.clone()),
-                                        error: Some(PaymentResponseError::InsufficientFundsForFees),
-                                        preimage: None,
-                                    };
-                                    let msg = Message::Bank(Bank::PaymentResult(PaymentResult {
-                                        uid,
-                                        currency,
-                                        rate: rate_2,
-                                        is_success: false,
-                                        amount: outbound_amount_in_btc_plus_max_fees,
-                                        payment_response,
-                                        error: Some(e.to_string()),
-                                    }));
-                                    if let Err(err) = payment_task_sender.send(msg) {
-                                        panic!("Failed to send a payment task: {:?}", err);
-                                    }
-                                }
-                            }
-                        });
-                        self.payment_threads.push(payment_task);
-                        return;
-                    }
+        let pay_response = match tapd.send_payment(&asset, &payment_request, &quote).await {
+            Ok(pay_response) => pay_response,
+            Err(err) => {
+                slog::info!(self.logger, "Asset payment {} failed: {:?}", msg.req_id, err);
+                return error(PaymentResponseError::TransactionFailed);
+            }
+        };
 
-                    let owner_username = match models::users::User::get_by_id(&psql_connection, invoice.owner.unwrap())
-                    {
-                        Ok(username) => username,
-                        Err(_) => {
-                            slog::error!(self.logger, "Couldn't get psql connection.");
-                            let payment_response = PaymentResponse::error(
-                                PaymentResponseError::DatabaseConnectionFailed,
-                                msg.req_id,
-                                uid,
-                                msg.payment_request,
-                                msg.currency,
-                                None,
-                            );
-                            let msg = Message::Api(Api::PaymentResponse(payment_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
-                    dbg!(&owner_username);
+        let units = quote
+            .units_for_msat(amount_msat + pay_response.fee_msat)
+            .unwrap_or(max_units);
+        let amount = Money::new(msg.currency, Some(asset.from_units(units)));
+        let mut liability_account = self
+            .ledger
+            .bank_liabilities
+            .get_default_account(msg.currency, Some(AccountType::External));
+        let txid = match self.make_tx(
+            &mut outbound_account,
+            uid,
+            &mut liability_account,
+            BANK_UID,
+            amount.clone(),
+        ) {
+            Ok(txid) => txid,
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Asset payment {} of user {} went out but wasn't booked: {:?}",
+                    msg.req_id,
+                    uid,
+                    err
+                );
+                return error(PaymentResponseError::TransactionFailed);
+            }
+        };
+        self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
+        self.ledger
+            .bank_liabilities
+            .accounts
+            .insert(liability_account.account_id, liability_account.clone());
+        self.update_account(&outbound_account, uid);
+        self.update_account(&liability_account, BANK_UID);
 
-                    // If there is an owner we make an internal tx.
-                    msg.receipient = Some(owner_username.username);
-                    dbg!(&msg);
-                    self.make_internal_tx(msg, listener);
-                }
+        if self
+            .make_summary_tx(
+                &outbound_account,
+                uid,
+                &liability_account,
+                BANK_UID,
+                amount.clone(),
+                None,
+                None,
+                Some(txid.clone()),
+                Some(txid),
+                None,
+                Some(String::from("ExternalPayment")),
+            )
+            .is_err()
+        {
+            slog::error!(
+                self.logger,
+                "Failed to make summary transaction of asset payment {}.",
+                msg.req_id
+            );
+        }
 
-                Api::SwapRequest(msg) => {
-                    if self.is_insurance_fund_depleted() {
-                        slog::warn!(self.logger, "Insurance is depleted Deposit request Failed!");
-                        return;
-                    }
-                    slog::warn!(self.logger, "Received swap request: {:?}", msg);
-                    let msg = Message::Api(Api::SwapRequest(msg));
-                    listener(msg, ServiceIdentity::Dealer);
-                }
-                Api::SwapResponse(mut msg) => {
-                    slog::warn!(self.logger, "Received swap response: {:?}", msg);
-                    if msg.error.is_some() || !msg.success {
-                        let msg = Message::Api(Api::SwapResponse(msg));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        PaymentResponse {
+            amount: Some(amount),
+            payment_hash: pay_response.payment_hash,
+            req_id: msg.req_id,
+            uid,
+            success: true,
+            payment_request: Some(payment_request.clone()),
+            currency: msg.currency,
+            fees: Some(Money::from_msats(Decimal::from(pay_response.fee_msat))),
+            rate: None,
+            error: None,
+            preimage: pay_response.preimage,
+        }
+    }
 
-                    let mut swap_response = msg.clone();
+    fn freeze_user(&mut self, request: &FreezeUser) -> Result<(), String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                    // Checking whether we can convert into the target curreny.
-                    if !self.available_currencies.contains(&msg.to) {
-                        swap_response.success = false;
-                        swap_response.error = Some(SwapResponseError::CurrencyNotAvailable);
-                        let msg = Message::Api(Api::SwapResponse(swap_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                    let conn = match &self.conn_pool {
-                        Some(conn) => conn,
-                        None => {
-                            slog::error!(self.logger, "No database provided.");
-                            swap_response.success = false;
-                            swap_response.error = Some(SwapResponseError::DatabaseConnectionFailed);
-                            let msg = Message::Api(Api::SwapResponse(swap_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+        let frozen = !request.unfreeze;
+        let reason = request.reason.as_deref().filter(|_| frozen);
+        match User::set_frozen(&psql_connection, request.uid as i32, frozen, reason) {
+            Ok(0) => return Err(format!("User {} does not exist", request.uid)),
+            Ok(_) => {}
+            Err(err) => return Err(format!("Failed to update user: {:?}", err)),
+        }
 
-                    let uid = msg.uid;
-                    let swap_amount = msg.amount.clone();
+        self.ledger
+            .user_accounts
+            .entry(request.uid)
+            .or_insert_with(|| UserAccount::new(request.uid))
+            .frozen = frozen;
+        slog::warn!(
+            self.logger,
+            "User {} {} by {}, reason: {:?}",
+            request.uid,
+            if frozen { "frozen" } else { "unfrozen" },
+            request.operator,
+            request.reason
+        );
+        Ok(())
+    }
 
-                    let rate = match msg.rate {
-                        Some(ref rate) => rate,
-                        None => {
-                            swap_response.success = false;
-                            swap_response.error = Some(SwapResponseError::CurrencyNotAvailable);
-                            let msg = Message::Api(Api::SwapResponse(swap_response));
-                            listener(msg, ServiceIdentity::Api);
-                            return;
-                        }
-                    };
+    /// Loads the open limit swap orders, the dealer gets them with the next bank state.
+    pub fn init_swap_orders(&mut self) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
 
-                    let (mut outbound_account, mut inbound_account) = {
-                        let user_account = match self.ledger.user_accounts.get_mut(&msg.uid) {
-                            Some(ua) => ua,
-                            None => {
-                                swap_response.success = false;
-                                swap_response.error = Some(SwapResponseError::UserAccountNotFound);
-                                let msg = Message::Api(Api::SwapResponse(swap_response));
-                                listener(msg, ServiceIdentity::Api);
-                                return;
-                            }
-                        };
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
 
-                        let outbound_account = user_account.get_default_account(msg.from, None);
-                        let inbound_account = user_account.get_default_account(msg.to, None);
+        let orders = swap_orders::SwapOrder::get_open(&c).expect("Failed to load swap orders");
+        for order in orders {
+            let order = swap_order(&order).unwrap_or_else(|| panic!("Failed to convert swap order {}", order.order_id));
+            self.swap_orders.insert(order.order_id, order);
+        }
+    }
 
-                        (outbound_account, inbound_account)
-                    };
+    /// Sends all open limit swap orders to the dealer, which fills them once the market reaches their limit.
+    fn send_swap_orders<F: FnMut(Message, ServiceIdentity)>(&self, listener: &mut F) {
+        let orders = self
+            .swap_orders
+            .values()
+            .map(|order| OpenSwapOrder {
+                order_id: order.order_id,
+                uid: order.uid,
+                from: order.from,
+                to: order.to,
+                remaining: order.remaining(),
+                limit_rate: order.limit_rate,
+            })
+            .collect();
+        let msg = Message::Dealer(Dealer::SwapOrders(SwapOrders { orders }));
+        listener(msg, ServiceIdentity::Dealer);
+    }
 
-                    let (mut outbound_dealer_account, mut inbound_dealer_account) = {
-                        let outbound_dealer_account = self
-                            .ledger
-                            .dealer_accounts
-                            .get_default_account(msg.to, Some(AccountType::Internal));
-                        let inbound_dealer_account = self
-                            .ledger
-                            .dealer_accounts
-                            .get_default_account(msg.from, Some(AccountType::Internal));
-                        (outbound_dealer_account, inbound_dealer_account)
-                    };
+    /// Places a limit swap order. The amount isn't reserved, but all open orders of the user from the same
+    /// currency together must be covered by the balance when they are placed.
+    fn place_swap_order(&mut self, msg: SwapOrderRequest) -> SwapOrderResponse {
+        let mut response = SwapOrderResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            orders: Vec::new(),
+            error: None,
+        };
 
-                    if outbound_account.balance < swap_amount.value {
-                        slog::info!(
-                            self.logger,
-                            "User: {} has not enough available balance. Available: {}",
-                            uid,
-                            outbound_account.balance
-                        );
-                        msg.success = false;
-                        msg.error = Some(SwapResponseError::NotEnoughAvailableBalance);
-                        let msg = Message::Api(Api::SwapResponse(msg));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+        if msg.amount.value <= Decimal::ZERO
+            || msg.limit_rate <= Decimal::ZERO
+            || msg.amount.currency != msg.from
+            || msg.from == msg.to
+        {
+            response.error = Some(SwapOrderError::Invalid);
+            return response;
+        }
+        if msg.from != Currency::BTC && msg.to != Currency::BTC {
+            response.error = Some(SwapOrderError::BTCNotFromTo);
+            return response;
+        }
+        let fiat = if msg.from == Currency::BTC { msg.to } else { msg.from };
+        if !self.available_currencies.contains(&fiat) {
+            response.error = Some(SwapOrderError::CurrencyNotAvailable);
+            return response;
+        }
+        if !self.check_access_policy(msg.uid, &msg.origin) {
+            response.error = Some(SwapOrderError::OriginNotAllowed);
+            return response;
+        }
+        if self.is_frozen(msg.uid) {
+            response.error = Some(SwapOrderError::UserFrozen);
+            return response;
+        }
+        if self.is_default_account_frozen(msg.uid, msg.from) {
+            response.error = Some(SwapOrderError::AccountFrozen);
+            return response;
+        }
+        let thresholds = &self.totp_settings.swap_thresholds;
+        if !self.check_totp(msg.uid, &msg.amount, thresholds, msg.totp_code.as_deref()) {
+            response.error = Some(SwapOrderError::InvalidTotpCode);
+            return response;
+        }
 
-                    let fees = Money::new(msg.to, None);
+        let balance = match self.ledger.user_accounts.get_mut(&msg.uid) {
+            Some(user_account) => user_account.get_default_account(msg.from, None).balance,
+            None => Decimal::ZERO,
+        };
+        let committed: Decimal = self
+            .swap_orders
+            .values()
+            .filter(|order| order.uid == msg.uid && order.from == msg.from)
+            .map(|order| order.remaining())
+            .sum();
+        if committed + msg.amount.value > balance {
+            response.error = Some(SwapOrderError::NotEnoughAvailableBalance);
+            return response;
+        }
 
-                    let outbound_txid = if let Ok(txid) = self.make_tx(
-                        &mut outbound_account,
-                        uid,
-                        &mut inbound_dealer_account,
-                        BANK_UID,
-                        msg.amount.clone(),
-                    ) {
-                        txid
-                    } else {
-                        slog::info!(self.logger, "SWAP tx didn't go through on outbound.");
-                        swap_response.success = false;
-                        swap_response.error = Some(SwapResponseError::TransactionFailed);
-                        let msg = Message::Api(Api::SwapResponse(swap_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    };
+        let now = utils::time::time_now();
+        let order = SwapOrder {
+            order_id: Uuid::new_v4(),
+            uid: msg.uid,
+            from: msg.from,
+            to: msg.to,
+            amount: msg.amount.value,
+            filled_amount: Decimal::ZERO,
+            limit_rate: msg.limit_rate,
+            status: SwapOrderStatus::Open,
+            created_at: now,
+        };
 
-                    let value = msg.amount.clone();
+        let persisted = self.conn_pool.as_ref().and_then(|pool| pool.get().ok()).map(|c| {
+            swap_orders::SwapOrder {
+                order_id: order.order_id,
+                uid: order.uid as i32,
+                from_currency: order.from.to_string(),
+                to_currency: order.to.to_string(),
+                amount: BigDecimal::from_str(&order.amount.to_string()).unwrap(),
+                filled_amount: BigDecimal::from_str(&order.filled_amount.to_string()).unwrap(),
+                limit_rate: BigDecimal::from_str(&order.limit_rate.to_string()).unwrap(),
+                status: order.status.to_string(),
+                created_at: now as i64,
+                updated_at: now as i64,
+            }
+            .insert(&c)
+        });
+        if !matches!(persisted, Some(Ok(_))) {
+            slog::error!(self.logger, "Failed to persist swap order of user {}", msg.uid);
+            response.error = Some(SwapOrderError::DatabaseConnectionFailed);
+            return response;
+        }
 
-                    let inbound_amount = value.clone().exchange(&rate).unwrap();
+        self.swap_orders.insert(order.order_id, order.clone());
+        response.orders.push(order);
+        response
+    }
 
-                    let inbound_txid = if let Ok(txid) = self.make_tx(
-                        &mut outbound_dealer_account,
-                        BANK_UID,
-                        &mut inbound_account,
-                        uid,
-                        inbound_amount,
-                    ) {
-                        txid
-                    } else {
-                        slog::info!(self.logger, "SWAP tx didn't go through on inbound.");
-                        swap_response.success = false;
-                        swap_response.error = Some(SwapResponseError::TransactionFailed);
-                        let msg = Message::Api(Api::SwapResponse(swap_response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    };
+    fn cancel_swap_order(&mut self, msg: CancelSwapOrderRequest) -> SwapOrderResponse {
+        let mut response = SwapOrderResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            orders: Vec::new(),
+            error: None,
+        };
 
-                    self.insert_into_ledger(&uid, outbound_account.account_id, outbound_account.clone());
-                    self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+        let mut order = match self.swap_orders.get(&msg.order_id) {
+            Some(order) if order.uid == msg.uid => order.clone(),
+            _ => {
+                response.error = Some(SwapOrderError::OrderNotFound);
+                return response;
+            }
+        };
 
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(outbound_dealer_account.account_id, outbound_dealer_account.clone());
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
+        let now = utils::time::time_now();
+        let cancelled = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .map(|c| swap_orders::SwapOrder::cancel(&c, order.order_id, order.uid as i32, now as i64));
+        match cancelled {
+            Some(Ok(1)) => {}
+            Some(Ok(_)) => {
+                response.error = Some(SwapOrderError::OrderNotFound);
+                return response;
+            }
+            _ => {
+                slog::error!(self.logger, "Failed to cancel swap order {}", order.order_id);
+                response.error = Some(SwapOrderError::DatabaseConnectionFailed);
+                return response;
+            }
+        }
 
-                    self.update_account(&outbound_account, uid);
-                    self.update_account(&inbound_account, uid);
+        self.swap_orders.remove(&order.order_id);
+        order.status = SwapOrderStatus::Cancelled;
+        response.orders.push(order);
+        response
+    }
 
-                    self.update_account(&outbound_dealer_account, uid);
-                    self.update_account(&inbound_dealer_account, uid);
+    fn get_swap_orders(&self, msg: GetSwapOrders) -> SwapOrderResponse {
+        let mut response = SwapOrderResponse {
+            req_id: msg.req_id,
+            uid: msg.uid,
+            orders: Vec::new(),
+            error: None,
+        };
 
-                    let msg = Message::Api(Api::SwapResponse(swap_response));
-                    listener(msg, ServiceIdentity::Api);
+        let orders = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .map(|c| swap_orders::SwapOrder::get_by_uid(&c, msg.uid as i32));
+        match orders {
+            Some(Ok(orders)) => response.orders = orders.iter().filter_map(swap_order).collect(),
+            _ => response.error = Some(SwapOrderError::DatabaseConnectionFailed),
+        }
+        response
+    }
 
-                    // Updating the dealer of the new state of the bank.
-                    let bank_state = self.get_bank_state();
-                    let msg = Message::Dealer(Dealer::BankState(bank_state));
-                    listener(msg, ServiceIdentity::Dealer);
+    /// Books a fill of a limit swap order like a swap at the rate of the fill. Fills that don't match what
+    /// is left of the order or miss its limit are refused. An order the user can't cover anymore is cancelled.
+    fn book_swap_order_fill<F: FnMut(Message, ServiceIdentity)>(&mut self, fill: SwapOrderFill, listener: &mut F) {
+        let mut order = match self.swap_orders.get(&fill.order_id) {
+            Some(order) => order.clone(),
+            None => {
+                slog::warn!(
+                    self.logger,
+                    "Fill {} of unknown swap order {}",
+                    fill.fill_id,
+                    fill.order_id
+                );
+                self.send_swap_orders(listener);
+                return;
+            }
+        };
+        if fill.uid != order.uid
+            || fill.from != order.from
+            || fill.to != order.to
+            || fill.amount.currency != order.from
+            || fill.amount.value <= Decimal::ZERO
+            || fill.amount.value > order.remaining()
+            || fill.rate.value < order.limit_rate
+        {
+            slog::warn!(self.logger, "Refusing fill {:?} of swap order {:?}", fill, order);
+            self.send_swap_orders(listener);
+            return;
+        }
 
-                    if self
-                        .make_summary_tx(
-                            &outbound_account,
-                            uid,
-                            &inbound_account,
-                            uid,
-                            value,
-                            Some(rate.clone()),
-                            None,
-                            Some(outbound_txid),
-                            Some(inbound_txid),
-                            None,
-                            Some(String::from("Swap")),
-                        )
-                        .is_err()
-                    {
-                        return;
-                    }
-                }
+        let swap_response = SwapResponse {
+            req_id: fill.fill_id,
+            uid: fill.uid,
+            success: true,
+            amount: fill.amount.clone(),
+            from: fill.from,
+            to: fill.to,
+            rate: Some(fill.rate),
+            error: None,
+            fees: fill.fees,
+        };
+        let mut booked = None;
+        let mut fill_listener = |msg: Message, destination: ServiceIdentity| {
+            if let (Message::Api(Api::SwapResponse(swap_response)), ServiceIdentity::Api) = (&msg, &destination) {
+                booked = Some(swap_response.clone());
+            }
+            listener(msg, destination);
+        };
+        self.process_swap_response(swap_response, &mut fill_listener);
 
-                Api::GetBalances(msg) => {
-                    let user_account = self
-                        .ledger
-                        .user_accounts
-                        .entry(msg.uid)
-                        .or_insert_with(|| UserAccount::new(msg.uid));
-                    let balances = Balances {
-                        req_id: msg.req_id,
-                        uid: msg.uid,
-                        accounts: user_account.accounts.clone(),
-                        error: None,
-                    };
-                    let uid = msg.uid;
-                    let msg = Message::Api(Api::Balances(balances));
-                    if uid == DEALER_UID {
-                        listener(msg, ServiceIdentity::Dealer);
-                    } else {
-                        listener(msg, ServiceIdentity::Api);
-                    }
-                }
-                Api::QuoteRequest(msg) => {
-                    let msg = Message::Api(Api::QuoteRequest(msg));
-                    listener(msg, ServiceIdentity::Dealer);
-                }
-                Api::QuoteResponse(msg) => {
-                    let msg = Message::Api(Api::QuoteResponse(msg));
-                    listener(msg, ServiceIdentity::Api);
-                }
-                Api::AvailableCurrenciesRequest(msg) => {
-                    let msg = Message::Api(Api::AvailableCurrenciesRequest(msg));
-                    listener(msg, ServiceIdentity::Dealer);
-                }
-                Api::AvailableCurrenciesResponse(msg) => {
-                    let msg = Message::Api(Api::AvailableCurrenciesResponse(msg));
-                    listener(msg, ServiceIdentity::Api);
+        let now = utils::time::time_now() as i64;
+        let c = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(c) => c,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+        match booked {
+            Some(response) if response.success => {
+                order.filled_amount += fill.amount.value;
+                order.status = if order.remaining() > Decimal::ZERO {
+                    SwapOrderStatus::PartiallyFilled
+                } else {
+                    SwapOrderStatus::Filled
+                };
+                let filled_amount = BigDecimal::from_str(&order.filled_amount.to_string()).unwrap();
+                let status = order.status.to_string();
+                if let Err(err) = swap_orders::SwapOrder::record_fill(&c, order.order_id, filled_amount, &status, now) {
+                    slog::error!(
+                        self.logger,
+                        "Failed to record fill of swap order {}: {}",
+                        order.order_id,
+                        err
+                    );
                 }
-                Api::GetNodeInfoRequest(msg) => {
-                    let lnd_node_info = match self.lnd_connector.get_node_info().await {
-                        Ok(ni) => ni,
-                        Err(_) => LndNodeInfo::default(),
-                    };
-                    let response = GetNodeInfoResponse {
-                        req_id: msg.req_id,
-                        lnd_node_info,
-                        ln_network_fee_margin: self.ln_network_fee_margin,
-                        ln_network_max_fee: self.ln_network_max_fee,
-                        internal_tx_fee: self.internal_tx_fee,
-                        external_tx_fee: self.external_tx_fee,
-                        reserve_ratio: self.reserve_ratio,
-                        error: None,
-                    };
-                    let msg = Message::Api(Api::GetNodeInfoResponse(response));
-                    listener(msg, ServiceIdentity::Api);
+            }
+            Some(SwapResponse {
+                error: Some(SwapResponseError::NotEnoughAvailableBalance),
+                ..
+            }) => {
+                slog::info!(
+                    self.logger,
+                    "Cancelling swap order {} that isn't covered anymore",
+                    order.order_id
+                );
+                order.status = SwapOrderStatus::Cancelled;
+                if let Err(err) = swap_orders::SwapOrder::cancel(&c, order.order_id, order.uid as i32, now) {
+                    slog::error!(self.logger, "Failed to cancel swap order {}: {}", order.order_id, err);
                 }
-                Api::CreateLnurlWithdrawalRequest(msg) => {
-                    if self.is_insurance_fund_depleted() {
-                        slog::warn!(
-                            self.logger,
-                            "Insurance fund is depleted. Rejecting Lnurl Withdrawal Request."
-                        );
-                    }
+            }
+            _ => slog::warn!(
+                self.logger,
+                "Fill {} of swap order {} failed",
+                fill.fill_id,
+                order.order_id
+            ),
+        }
 
-                    slog::warn!(self.logger, "Received LNURL withdrawal request: {:?}", msg);
-                    let uid = msg.uid;
+        if order.status.is_open() {
+            self.swap_orders.insert(order.order_id, order);
+        } else {
+            self.swap_orders.remove(&order.order_id);
+        }
+        self.send_swap_orders(listener);
+    }
 
-                    let mut response = CreateLnurlWithdrawalResponse {
-                        req_id: msg.req_id,
-                        lnurl: None,
-                        error: None,
-                    };
+    /// Checks the signature and expiry of the quote a swap refers to. The signed rate is kept until the
+    /// dealer responds, so the swap can't be booked at any other rate.
+    fn check_firm_quote(&mut self, msg: &SwapRequest) -> Result<(), SwapResponseError> {
+        let (quote_id, key) = match (msg.quote_id, &self.quote_signing_key) {
+            (Some(quote_id), Some(key)) => (quote_id, key),
+            _ => return Ok(()),
+        };
+        let quote = msg.signed_quote.as_ref().ok_or(SwapResponseError::InvalidQuoteId)?;
+        let payload = quote.signing_payload(quote_id, msg.uid, &msg.amount, msg.from, msg.to);
+        if !utils::signing::verify(key, &payload, &quote.signature) {
+            slog::warn!(
+                self.logger,
+                "Swap {} of user {} refers to quote {} with an invalid signature",
+                msg.req_id,
+                msg.uid,
+                quote_id
+            );
+            return Err(SwapResponseError::InvalidQuoteId);
+        }
+        if utils::time::time_now() > quote.valid_until {
+            return Err(SwapResponseError::QuoteExpired);
+        }
+        self.firm_quotes.insert(msg.req_id, quote.clone());
+        Ok(())
+    }
 
-                    if msg.amount.value <= dec!(0) {
-                        response.error = Some(CreateLnurlWithdrawalError::InvalidAmount);
-                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
+    /// Replaces the rate of the dealer's response to a swap at a signed quote by the quoted rate.
+    fn apply_firm_quote(&mut self, mut msg: SwapResponse) -> SwapResponse {
+        let quote = match self.firm_quotes.remove(&msg.req_id) {
+            Some(quote) => quote,
+            None => return msg,
+        };
+        if let Some(rate) = msg.rate.as_mut() {
+            if rate.value != quote.rate {
+                slog::warn!(
+                    self.logger,
+                    "Dealer responded to swap {} with rate {} instead of the quoted rate {}",
+                    msg.req_id,
+                    rate.value,
+                    quote.rate
+                );
+                rate.value = quote.rate;
+            }
+        }
+        msg
+    }
 
-                    let outbound_account = {
-                        let user_account = match self.ledger.user_accounts.get_mut(&uid) {
-                            Some(ua) => ua,
-                            None => {
-                                response.error = Some(CreateLnurlWithdrawalError::UserAccountNotFound);
-                                let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
-                                listener(msg, ServiceIdentity::Api);
-                                return;
-                            }
-                        };
+    /// Whether the account a swap or cash out of the currency would be taken from is frozen.
+    fn is_default_account_frozen(&self, uid: UserId, currency: Currency) -> bool {
+        self.ledger
+            .user_accounts
+            .get(&uid)
+            .and_then(|user_account| {
+                user_account
+                    .accounts
+                    .values()
+                    .filter(|account| account.currency == currency && !account.archived)
+                    .min_by_key(|account| account.label.is_some())
+            })
+            .map_or(false, |account| self.frozen_accounts.contains_key(&account.account_id))
+    }
 
-                        user_account.get_default_account(msg.currency, None)
-                    };
+    fn freeze_account(&mut self, request: &FreezeAccount) -> Result<(), String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                    if msg.currency != Currency::BTC && msg.rate.is_none() {
-                        let msg = Message::Api(Api::CreateLnurlWithdrawalRequest(msg));
-                        listener(msg, ServiceIdentity::Dealer);
-                        return;
-                    }
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                    if outbound_account.balance < msg.amount.value {
-                        response.error = Some(CreateLnurlWithdrawalError::InsufficientFunds);
-                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    }
-                    let now = utils::time::time_now();
+        let owns_account = self
+            .ledger
+            .user_accounts
+            .get(&request.uid)
+            .map_or(false, |user_account| {
+                user_account.accounts.contains_key(&request.account_id)
+            });
+        if !owns_account {
+            return Err(format!("User {} has no account {}", request.uid, request.account_id));
+        }
 
-                    let amount = msg.amount;
+        let frozen_account = FrozenAccount {
+            account_id: request.account_id,
+            uid: request.uid as i32,
+            reason_code: request.reason.to_string(),
+            note: request.note.clone(),
+            frozen_by: request.operator.clone(),
+            frozen_at: utils::time::time_now() as i64,
+        };
+        frozen_account
+            .freeze(&psql_connection)
+            .map_err(|err| format!("Failed to freeze account: {:?}", err))?;
 
-                    let payment_request = PaymentRequest {
-                        uid: msg.uid,
-                        req_id: msg.req_id,
-                        amount: Some(amount),
-                        currency: msg.currency,
-                        rate: msg.rate,
-                        payment_request: Some(String::from("")),
-                        destination: None,
-                        receipient: None,
-                        fees: msg.fees,
-                    };
+        self.frozen_accounts.insert(request.account_id, request.reason);
+        slog::warn!(
+            self.logger,
+            "Account {} of user {} frozen by {}, reason: {}, note: {:?}",
+            request.account_id,
+            request.uid,
+            request.operator,
+            request.reason,
+            request.note
+        );
+        Ok(())
+    }
 
-                    let lnurl_path = String::from("https://lndhubx.com/api/lnurl_withdrawal/request");
-                    let q = msg.req_id;
-                    let lnurl = if let Ok(encoded) = utils::lnurl::encode(lnurl_path, Some(q.to_string())) {
-                        encoded
-                    } else {
-                        response.error = Some(CreateLnurlWithdrawalError::FailedToCreateLnUrl);
-                        let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
-                        listener(msg, ServiceIdentity::Api);
-                        return;
-                    };
-                    response.lnurl = Some(lnurl);
-                    self.lnurl_withdrawal_requests
-                        .insert(payment_request.req_id, (now, payment_request));
+    fn unfreeze_account(&mut self, request: &UnfreezeAccount) -> Result<(), String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                    let msg = Message::Api(Api::CreateLnurlWithdrawalResponse(response));
-                    listener(msg, ServiceIdentity::Api);
-                }
-                Api::GetLnurlWithdrawalRequest(msg) => {
-                    let callback = String::from("https://lndhubx.com/api/lnurl_withdrawal/pay");
-                    let mut response = GetLnurlWithdrawalResponse {
-                        callback,
-                        req_id: msg.req_id,
-                        max_withdrawable: 0,
-                        default_description: String::from("Lndhubx Withdrawal"),
-                        min_withdrawable: 1,
-                        tag: String::from("withdrawalRequest"),
-                        error: None,
-                    };
-                    if let Some((_, payment_request)) = self.lnurl_withdrawal_requests.remove(&msg.req_id) {
-                        if let Some(a) = &payment_request.amount {
-                            let a = match &payment_request.rate {
-                                Some(r) => a.exchange(&r).unwrap(),
-                                None => a.clone(),
-                            };
-                            let a = a.try_sats().unwrap();
-                            if let Some(ma) = a.to_u64() {
-                                response.max_withdrawable = ma;
-                                let msg = Message::Api(Api::GetLnurlWithdrawalResponse(response));
-                                listener(msg, ServiceIdentity::Api);
-                                return;
-                            }
-                        }
-                    }
-                    response.error = Some(GetLnurlWithdrawalError::RequestNotFound);
-                    let msg = Message::Api(Api::GetLnurlWithdrawalResponse(response));
-                    listener(msg, ServiceIdentity::Api);
-                }
-                Api::PayLnurlWithdrawalRequest(msg) => {
-                    if let Some((_, payment_request)) = self.lnurl_withdrawal_requests.get_mut(&msg.req_id) {
-                        payment_request.payment_request = Some(msg.payment_request);
-                        let msg = Message::Api(Api::PaymentRequest(payment_request.clone()));
-                        listener(msg, ServiceIdentity::Loopback);
-                        return;
-                    }
-                    let response = PayLnurlWithdrawalResponse {
-                        req_id: msg.req_id,
-                        error: Some(PayLnurlWithdrawalError::RequestNotFound),
-                    };
-                    let msg = Message::Api(Api::PayLnurlWithdrawalResponse(response));
-                    listener(msg, ServiceIdentity::Api);
-                }
-                Api::QueryRouteRequest(msg) => {
-                    let settings = self.lnd_connector_settings.clone();
-                    let mut lnd_connector = LndConnector::new(settings).await;
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
+
+        match FrozenAccount::unfreeze(
+            &psql_connection,
+            request.account_id,
+            request.operator.clone(),
+            request.note.clone(),
+            utils::time::time_now() as i64,
+        ) {
+            Ok(0) => return Err(format!("Account {} is not frozen", request.account_id)),
+            Ok(_) => {}
+            Err(err) => return Err(format!("Failed to unfreeze account: {:?}", err)),
+        }
+
+        self.frozen_accounts.remove(&request.account_id);
+        slog::warn!(
+            self.logger,
+            "Account {} unfrozen by {}, note: {:?}",
+            request.account_id,
+            request.operator,
+            request.note
+        );
+        Ok(())
+    }
+
+    fn list_frozen_accounts(&self) -> Result<Vec<FrozenAccountInfo>, String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                    if let Ok(res) = lnd_connector.probe(msg.payment_request, dec!(0.0005)).await {
-                        if !res.is_empty() {
-                            let best_route = res[0].clone();
-                            let msg = Message::Api(Api::QueryRouteResponse(QueryRouteResponse {
-                                req_id: msg.req_id,
-                                total_fee: Decimal::new(best_route.total_fees, 0),
-                                error: None,
-                            }));
-                            listener(msg, ServiceIdentity::Api);
-                        } else {
-                            let msg = Message::Api(Api::QueryRouteResponse(QueryRouteResponse {
-                                req_id: msg.req_id,
-                                total_fee: dec!(0),
-                                error: Some(QueryRouteError::NoRouteFound),
-                            }));
-                            listener(msg, ServiceIdentity::Api);
-                        }
-                    }
-                }
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                _ => {}
-            },
-            Message::Bank(msg) => match msg {
-                Bank::PaymentResult(res) => {
-                    slog::warn!(self.logger, "Received payment result: {:?}", res);
+        let frozen_accounts = FrozenAccount::get_all(&psql_connection)
+            .map_err(|err| format!("Failed to load frozen accounts: {:?}", err))?;
+        frozen_accounts
+            .into_iter()
+            .map(|frozen_account| {
+                Ok(FrozenAccountInfo {
+                    uid: frozen_account.uid as UserId,
+                    account_id: frozen_account.account_id,
+                    reason: FreezeReason::from_str(&frozen_account.reason_code)?,
+                    note: frozen_account.note,
+                    frozen_by: frozen_account.frozen_by,
+                    frozen_at: frozen_account.frozen_at as u64,
+                })
+            })
+            .collect()
+    }
 
-                    if res.amount.value <= dec!(0) {
-                        panic!("Amount is smaller than zero.");
-                    }
+    /// Applies the settings that can change while the bank is running, everything else needs a restart.
+    /// Returns the names of the applied settings.
+    fn reload_settings(&mut self, settings: BankEngineSettings) -> Result<Vec<String>, String> {
+        let deposit_limits = settings
+            .deposit_limits
+            .into_iter()
+            .map(|(currency, limit)| {
+                Currency::from_str(&currency)
+                    .map(|currency| (currency, limit))
+                    .map_err(|_| format!("Invalid currency {} in deposit limits", currency))
+            })
+            .collect::<Result<HashMap<Currency, Decimal>, String>>()?;
+
+        self.deposit_limits = deposit_limits;
+        self.fee_engine.set_settings(settings.fee_schedule);
+        self.internal_tx_fee = settings.internal_tx_fee;
+        self.external_tx_fee = settings.external_tx_fee;
+        self.ln_network_fee_margin = settings.ln_network_fee_margin;
+        self.ln_network_max_fee = settings.ln_network_max_fee;
+        self.ln_fee_budgets = settings.ln_fee_budgets;
+        self.reserve_ratio = settings.reserve_ratio;
+        self.withdrawal_only = settings.withdrawal_only;
+        self.strict_fee_quotes = settings.strict_fee_quotes;
+        self.maintenance_notices = settings.maintenance_notices;
+        self.access_policy = settings.access_policy;
+        self.velocity_limits = settings.withdrawal_velocity;
+        self.withdrawal_approval_threshold_sats = settings.withdrawal_approval_threshold_sats;
+        self.rebalance_daily_fee_budget_sats = settings.rebalance_daily_fee_budget_sats;
+
+        let reloaded = [
+            "deposit_limits",
+            "fee_schedule",
+            "internal_tx_fee",
+            "external_tx_fee",
+            "ln_network_fee_margin",
+            "ln_network_max_fee",
+            "ln_fee_budgets",
+            "reserve_ratio",
+            "withdrawal_only",
+            "strict_fee_quotes",
+            "maintenance_notices",
+            "access_policy",
+            "withdrawal_velocity",
+            "withdrawal_approval_threshold_sats",
+            "rebalance_daily_fee_budget_sats",
+        ];
+        slog::info!(self.logger, "Reloaded settings: {}", reloaded.join(", "));
+        // The fees in the stored node info change with the settings.
+        self.store_node_info();
+        Ok(reloaded.iter().map(|name| name.to_string()).collect())
+    }
 
-                    let conn = match &self.conn_pool {
-                        Some(conn) => conn,
-                        None => {
-                            slog::error!(self.logger, "No database provided.");
-                            return;
-                        }
-                    };
+    /// Indices the invoice subscription resumes after, zero if no invoice was processed yet.
+    pub fn invoice_checkpoint(&self) -> (u64, u64) {
+        let checkpoint = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .and_then(|conn| InvoiceCheckpoint::get(&conn).ok().flatten());
+        match checkpoint {
+            Some(checkpoint) => (checkpoint.add_index as u64, checkpoint.settle_index as u64),
+            None => (0, 0),
+        }
+    }
 
-                    let psql_connection = match conn.get() {
-                        Ok(psql_connection) => psql_connection,
-                        Err(_) => {
-                            slog::error!(self.logger, "Couldn't get psql connection.");
-                            return;
-                        }
-                    };
+    /// Stored after the deposit was processed. A deposit processed again after a crash is skipped as its
+    /// invoice is already settled. The checkpoint is kept before the deposits waiting for a retry, so lnd
+    /// redelivers them after a restart.
+    pub fn store_invoice_checkpoint(&self, add_index: u64, settle_index: u64) {
+        let db_writer = match &self.db_writer {
+            Some(db_writer) => db_writer,
+            None => return,
+        };
+        let (add_index, settle_index) =
+            self.retried_deposits
+                .iter()
+                .fold((add_index, settle_index), |(add_index, settle_index), deposit| {
+                    (
+                        add_index.min(deposit.add_index.saturating_sub(1)),
+                        settle_index.min(deposit.settle_index.saturating_sub(1)),
+                    )
+                });
+        let checkpoint = InvoiceCheckpoint::new(add_index as i64, settle_index as i64, utils::time::time_now() as i64);
+        db_writer.submit(
+            0,
+            "invoice checkpoint",
+            Box::new(move |c| checkpoint.upsert(c).map(|_| ())),
+        );
+    }
 
-                    let uid = res.uid;
+    fn node_info_response(&self, req_id: RequestId, lnd_node_info: LndNodeInfo) -> GetNodeInfoResponse {
+        GetNodeInfoResponse {
+            req_id,
+            lnd_node_info,
+            ln_network_fee_margin: self.ln_network_fee_margin,
+            ln_network_max_fee: self.ln_network_max_fee,
+            internal_tx_fee: self.internal_tx_fee,
+            external_tx_fee: self.external_tx_fee,
+            reserve_ratio: self.reserve_ratio,
+            error: None,
+        }
+    }
 
-                    let mut btc_liabilities_account = self
-                        .ledger
-                        .bank_liabilities
-                        .get_default_account(Currency::BTC, Some(AccountType::External));
+    /// Keeps the node info in the database up to date so api instances can serve it from a read replica.
+    pub fn store_node_info(&self) {
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection, node info isn't stored.");
+                return;
+            }
+        };
 
-                    let mut inbound_account = {
-                        let user_account = match self.ledger.user_accounts.get_mut(&uid) {
-                            Some(ua) => ua,
-                            None => return,
-                        };
+        let response = self.node_info_response(Uuid::nil(), self.lnd_node_info.clone());
+        let payload = match serde_json::to_string(&response) {
+            Ok(payload) => payload,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to serialize node info: {:?}", err);
+                return;
+            }
+        };
+        if let Err(err) = NodeInfo::new(payload, utils::time::time_now() as i64).upsert(&conn) {
+            slog::error!(self.logger, "Failed to store node info: {:?}", err);
+        }
+    }
 
-                        user_account.get_default_account(res.currency, None)
-                    };
+    /// Persists a message that couldn't be decoded or had no handler instead of dropping it.
+    pub fn store_dead_letter(&self, source: &str, reason: &str, payload: Vec<u8>) {
+        slog::warn!(self.logger, "Dead letter from {}: {}", source, reason);
 
-                    let mut payment_response = res.payment_response;
+        let db_writer = match &self.db_writer {
+            Some(db_writer) => db_writer,
+            None => {
+                slog::error!(self.logger, "No database provided, dropping dead letter.");
+                return;
+            }
+        };
 
-                    let mut dealer_btc_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(Currency::BTC, Some(AccountType::Internal));
+        let dead_letter = InsertableDeadLetter {
+            source: source.to_string(),
+            reason: reason.to_string(),
+            payload,
+            created_at: utils::time::time_now() as i64,
+        };
+        db_writer.submit(0, "dead letter", Box::new(move |c| dead_letter.insert(c).map(|_| ())));
+    }
 
-                    if res.is_success {
-                        // If successful and there are excess fees we send it to the bank fee account.
-                        let fees_payed_in_btc = payment_response.fees.clone();
+    fn store_unhandled_message(&self, msg: Message) {
+        let source = match &msg {
+            Message::Api(_) => "api",
+            Message::Dealer(_) => "dealer",
+            Message::Cli(_) => "cli",
+            _ => "bank",
+        };
+        match msgs::wire::encode(&msg) {
+            Ok(payload) => self.store_dead_letter(source, "No handler for message", payload),
+            Err(err) => slog::error!(
+                self.logger,
+                "Failed to serialize unhandled message {:?}: {:?}",
+                msg,
+                err
+            ),
+        }
+    }
 
-                        let payment_amount = payment_response.amount.clone().unwrap();
+    fn list_dead_letters(&self, request: &ListDeadLetters) -> Result<Vec<DeadLetterInfo>, String> {
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => return Err("Couldn't get psql connection".to_string()),
+        };
 
-                        let excess_fees_in_btc =
-                            res.amount.value - (payment_amount.value + fees_payed_in_btc.unwrap().value);
+        let dead_letters = DeadLetter::get_recent(&conn, request.limit as i64, request.include_replayed)
+            .map_err(|err| format!("Failed to load dead letters: {:?}", err))?;
+        Ok(dead_letters
+            .into_iter()
+            .map(|dead_letter| DeadLetterInfo {
+                id: dead_letter.id,
+                source: dead_letter.source,
+                reason: dead_letter.reason,
+                message: msgs::wire::decode(&dead_letter.payload)
+                    .ok()
+                    .map(|msg| format!("{:?}", msg)),
+                size: dead_letter.payload.len(),
+                received_at: dead_letter.created_at as u64,
+                replayed_at: dead_letter.replayed_at.map(|replayed_at| replayed_at as u64),
+            })
+            .collect())
+    }
 
-                        let excess_fees = Money::new(Currency::BTC, Some(excess_fees_in_btc));
+    /// Queues a dead letter to be processed again, it fails if the payload still can't be decoded.
+    fn replay_dead_letter(&mut self, id: i32) -> Result<(), String> {
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => return Err("Couldn't get psql connection".to_string()),
+        };
 
-                        assert!(excess_fees_in_btc >= dec!(0));
+        let dead_letter =
+            DeadLetter::get_by_id(&conn, id).map_err(|err| format!("Failed to load dead letter {}: {:?}", id, err))?;
+        if dead_letter.replayed_at.is_some() {
+            return Err(format!("Dead letter {} was already replayed", id));
+        }
+        let msg = msgs::wire::decode(&dead_letter.payload)
+            .map_err(|err| format!("Dead letter {} still can't be decoded: {}", id, err))?;
+        DeadLetter::mark_replayed(&conn, id, utils::time::time_now() as i64)
+            .map_err(|err| format!("Failed to mark dead letter {} as replayed: {:?}", id, err))?;
+        self.replayed_messages.push_back(msg);
+        Ok(())
+    }
 
-                        if excess_fees_in_btc > dec!(0) {
-                            if self
-                                .make_tx(
-                                    &mut btc_liabilities_account,
-                                    BANK_UID,
-                                    &mut dealer_btc_account,
-                                    DEALER_UID,
-                                    excess_fees,
-                                )
-                                .is_err()
-                            {
-                                return;
-                            }
+    /// Archives an account regardless of its balance, returns the balance left on it.
+    fn force_close_account(&mut self, request: &ForceCloseAccount) -> Result<Decimal, String> {
+        let mut account = match self
+            .ledger
+            .user_accounts
+            .get(&request.uid)
+            .and_then(|user_account| user_account.accounts.get(&request.account_id))
+        {
+            Some(account) if account.archived => return Err("Account is already closed".to_string()),
+            Some(account) => account.clone(),
+            None => return Err(format!("User {} has no account {}", request.uid, request.account_id)),
+        };
 
-                            self.ledger
-                                .bank_liabilities
-                                .accounts
-                                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
-                            self.ledger
-                                .dealer_accounts
-                                .accounts
-                                .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
+        if account.pending_balance != dec!(0) {
+            return Err("Account has funds in flight, try again once they settled".to_string());
+        }
 
-                            self.update_account(&dealer_btc_account, DEALER_UID);
-                            self.update_account(&btc_liabilities_account, BANK_UID);
-                        }
+        account.archived = true;
+        self.insert_into_ledger(&request.uid, account.account_id, account.clone());
+        self.update_account(&account, request.uid);
+        slog::warn!(
+            self.logger,
+            "Account {} of user {} force closed by {} with a balance of {} {}, reason: {:?}",
+            account.account_id,
+            request.uid,
+            request.operator,
+            account.balance,
+            account.currency,
+            request.reason
+        );
+        Ok(account.balance)
+    }
 
-                        payment_response.success = true;
+    fn reset_rate_limits(&mut self, uid: UserId) -> Result<(), String> {
+        let conn = self.conn_pool.as_ref().and_then(|pool| pool.get().ok());
+        for limiter in [
+            &mut self.deposit_request_rate_limiter,
+            &mut self.withdrawal_request_rate_limiter,
+        ] {
+            limiter
+                .reset(conn.as_deref(), uid)
+                .map_err(|err| format!("Failed to reset rate limits: {:?}", err))?;
+        }
+        slog::info!(self.logger, "Reset rate limits of user {}", uid);
+        Ok(())
+    }
 
-                        let pr = payment_response.clone().payment_request.unwrap_or_else(|| {
-                            panic!(
-                                "Payment request has not been specified in the payment response: {:?}",
-                                payment_response
-                            )
-                        });
+    fn set_deposit_limit(&mut self, request: &SetDepositLimit) -> Result<(), String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                        let mut invoice = if let Ok(invoice) =
-                            models::invoices::Invoice::get_by_payment_request(&psql_connection, pr)
-                        {
-                            invoice
-                        } else {
-                            return;
-                        };
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                        invoice.settled = true;
+        let currency = request.currency.to_string();
+        match request.max_balance {
+            Some(max_balance) if max_balance < dec!(0) => {
+                return Err("Deposit limit can't be negative".to_string());
+            }
+            Some(max_balance) => {
+                let deposit_limit = DepositLimit {
+                    uid: request.uid as i32,
+                    currency,
+                    max_balance: BigDecimal::from_str(&max_balance.to_string())
+                        .map_err(|err| format!("Invalid deposit limit: {:?}", err))?,
+                    updated_at: utils::time::time_now() as i64,
+                };
+                deposit_limit
+                    .upsert(&psql_connection)
+                    .map_err(|err| format!("Failed to set deposit limit: {:?}", err))?;
+            }
+            None => {
+                DepositLimit::delete(&psql_connection, request.uid as i32, &currency)
+                    .map_err(|err| format!("Failed to remove deposit limit: {:?}", err))?;
+            }
+        }
 
-                        if invoice.update(&psql_connection).is_err() {
-                            slog::error!(self.logger, "Error updating updating invoices!");
-                        }
-                    } else {
-                        let refund = res.amount.clone();
-                        let rate = res.rate;
-                        let refund_exchanged = refund.clone().exchange(&rate).unwrap();
+        let user_account = self
+            .ledger
+            .user_accounts
+            .entry(request.uid)
+            .or_insert_with(|| UserAccount::new(request.uid));
+        match request.max_balance {
+            Some(max_balance) => user_account.deposit_limits.insert(request.currency, max_balance),
+            None => user_account.deposit_limits.remove(&request.currency),
+        };
+        slog::info!(self.logger, "Set deposit limit: {:?}", request);
+        Ok(())
+    }
 
-                        if res.currency != Currency::BTC {
-                            let mut dealer_btc_account = self
-                                .ledger
-                                .dealer_accounts
-                                .get_default_account(Currency::BTC, Some(AccountType::Internal));
-                            let mut dealer_fiat_account = self
-                                .ledger
-                                .dealer_accounts
-                                .get_default_account(res.currency, Some(AccountType::Internal));
+    fn set_withdrawal_limit(&self, request: &SetWithdrawalLimit) -> Result<(), String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                            let outbound_txid = if let Ok(txid) = self.make_tx(
-                                &mut btc_liabilities_account,
-                                BANK_UID,
-                                &mut dealer_btc_account,
-                                uid,
-                                refund.clone(),
-                            ) {
-                                txid
-                            } else {
-                                return;
-                            };
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                            let inbound_txid = if let Ok(txid) = self.make_tx(
-                                &mut dealer_fiat_account,
-                                DEALER_UID,
-                                &mut inbound_account,
-                                uid,
-                                refund_exchanged,
-                            ) {
-                                txid
-                            } else {
-                                return;
-                            };
+        if request.clear {
+            WithdrawalLimit::delete(&psql_connection, request.uid as i32)
+                .map_err(|err| format!("Failed to remove withdrawal limit: {:?}", err))?;
+            return Ok(());
+        }
 
-                            self.ledger
-                                .bank_liabilities
-                                .accounts
-                                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+        let to_sats = |limit: Option<u64>| limit.map(|limit| limit.min(i64::MAX as u64) as i64);
+        let withdrawal_limit = WithdrawalLimit {
+            uid: request.uid as i32,
+            daily_sats: to_sats(request.daily_sats),
+            weekly_sats: to_sats(request.weekly_sats),
+            updated_at: utils::time::time_now() as i64,
+        };
+        withdrawal_limit
+            .upsert(&psql_connection)
+            .map_err(|err| format!("Failed to set withdrawal limit: {:?}", err))?;
+        slog::info!(self.logger, "Set withdrawal limit: {:?}", withdrawal_limit);
+        Ok(())
+    }
 
-                            self.ledger
-                                .dealer_accounts
-                                .accounts
-                                .insert(dealer_btc_account.account_id, dealer_btc_account.clone());
-                            self.ledger
-                                .dealer_accounts
-                                .accounts
-                                .insert(dealer_fiat_account.account_id, dealer_fiat_account.clone());
+    /// Checks the code supplied with an operation whose amount is above the 2fa threshold of its currency.
+    /// Users without 2fa pass unless enrollment is required. Fails closed if the user can't be loaded.
+    fn check_totp(
+        &self,
+        uid: UserId,
+        amount: &Money,
+        thresholds: &HashMap<String, Decimal>,
+        code: Option<&str>,
+    ) -> bool {
+        match thresholds.get(&amount.currency.to_string()) {
+            Some(threshold) if amount.value > *threshold => self.verify_totp(uid, code),
+            _ => true,
+        }
+    }
+
+    /// Checks the code of a user whatever the amount, consuming its time step so it can't be used twice.
+    fn verify_totp(&self, uid: UserId, code: Option<&str>) -> bool {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return false;
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return false;
+            }
+        };
+
+        let user = match User::get_by_id(&psql_connection, uid as i32) {
+            Ok(user) => user,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load user {} for 2fa check: {:?}", uid, err);
+                return false;
+            }
+        };
 
-                            self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+        let secret = match (&user.totp_secret, user.totp_enabled) {
+            (Some(secret), true) => secret,
+            _ => return !self.totp_settings.require_enrollment,
+        };
+        let step = match code.and_then(|code| {
+            totp::verify(
+                secret,
+                code,
+                utils::time::time_now() / 1000,
+                user.totp_last_step.map(|step| step as u64),
+            )
+        }) {
+            Some(step) => step,
+            None => {
+                slog::info!(self.logger, "Rejected 2fa code of user {}", uid);
+                return false;
+            }
+        };
+        matches!(User::use_totp_step(&psql_connection, uid as i32, step as i64), Ok(1))
+    }
 
-                            self.update_account(&inbound_account, res.uid);
-                            self.update_account(&btc_liabilities_account, BANK_UID);
+    fn enroll_totp(&self, request: EnrollTotpRequest) -> TotpResponse {
+        let mut response = TotpResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            enabled: false,
+            secret: None,
+            otpauth_uri: None,
+            error: None,
+        };
 
-                            self.update_account(&dealer_btc_account, DEALER_UID);
-                            self.update_account(&dealer_fiat_account, DEALER_UID);
+        let psql_connection = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(psql_connection) => psql_connection,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
 
-                            if self
-                                .make_summary_tx(
-                                    &btc_liabilities_account,
-                                    BANK_UID,
-                                    &inbound_account,
-                                    uid,
-                                    refund,
-                                    Some(rate.clone()),
-                                    None,
-                                    Some(outbound_txid),
-                                    Some(inbound_txid),
-                                    None,
-                                    Some(String::from("PaymentRefund")),
-                                )
-                                .is_err()
-                            {
-                                return;
-                            }
-                        } else {
-                            let txid = if let Ok(txid) = self.make_tx(
-                                &mut btc_liabilities_account,
-                                BANK_UID,
-                                &mut inbound_account,
-                                uid,
-                                refund.clone(),
-                            ) {
-                                txid
-                            } else {
-                                return;
-                            };
+        let user = match User::get_by_id(&psql_connection, request.uid as i32) {
+            Ok(user) => user,
+            Err(_) => {
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+        if user.totp_enabled {
+            response.enabled = true;
+            response.error = Some(TotpError::AlreadyEnabled);
+            return response;
+        }
 
-                            self.ledger
-                                .bank_liabilities
-                                .accounts
-                                .insert(btc_liabilities_account.account_id, btc_liabilities_account.clone());
+        let secret = match totp::generate_secret() {
+            Ok(secret) => secret,
+            Err(err) => {
+                slog::error!(self.logger, "{}", err);
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+        if User::set_totp_secret(&psql_connection, request.uid as i32, Some(&secret)).is_err() {
+            response.error = Some(TotpError::DatabaseConnectionFailed);
+            return response;
+        }
 
-                            self.insert_into_ledger(&uid, inbound_account.account_id, inbound_account.clone());
+        let issuer = self.totp_settings.issuer.as_deref().unwrap_or("lndhubx");
+        response.otpauth_uri = Some(totp::provisioning_uri(issuer, &user.username, &secret));
+        response.secret = Some(secret);
+        response
+    }
 
-                            self.update_account(&inbound_account, res.uid);
-                            self.update_account(&btc_liabilities_account, BANK_UID);
+    /// Verifies a code against the secret of the user and records its step, returns the error to answer with.
+    fn verify_totp_code(&self, conn: &diesel::PgConnection, user: &User, code: &str) -> Option<TotpError> {
+        let secret = match &user.totp_secret {
+            Some(secret) => secret,
+            None => return Some(TotpError::NotEnrolled),
+        };
+        let last_used_step = user.totp_last_step.map(|step| step as u64);
+        match totp::verify(secret, code, utils::time::time_now() / 1000, last_used_step) {
+            Some(step) => match User::use_totp_step(conn, user.uid, step as i64) {
+                Ok(1) => None,
+                Ok(_) => Some(TotpError::InvalidCode),
+                Err(_) => Some(TotpError::DatabaseConnectionFailed),
+            },
+            None => Some(TotpError::InvalidCode),
+        }
+    }
 
-                            if self
-                                .make_summary_tx(
-                                    &btc_liabilities_account,
-                                    BANK_UID,
-                                    &inbound_account,
-                                    uid,
-                                    refund,
-                                    Some(rate.clone()),
-                                    None,
-                                    Some(txid.clone()),
-                                    Some(txid),
-                                    None,
-                                    Some(String::from("PaymentRefund")),
-                                )
-                                .is_err()
-                            {
-                                return;
-                            }
-                        }
-                    }
+    fn confirm_totp(&self, request: ConfirmTotpRequest) -> TotpResponse {
+        let mut response = TotpResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            enabled: false,
+            secret: None,
+            otpauth_uri: None,
+            error: None,
+        };
 
-                    let bank_state = self.get_bank_state();
-                    let msg = Message::Dealer(Dealer::BankState(bank_state));
-                    listener(msg, ServiceIdentity::Dealer);
+        let psql_connection = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(psql_connection) => psql_connection,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
 
-                    let msg = Message::Api(Api::PaymentResponse(payment_response));
-                    listener(msg, ServiceIdentity::Api);
+        let user = match User::get_by_id(&psql_connection, request.uid as i32) {
+            Ok(user) => user,
+            Err(_) => {
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
+            }
+        };
+        if user.totp_enabled {
+            response.enabled = true;
+            response.error = Some(TotpError::AlreadyEnabled);
+            return response;
+        }
+
+        response.error = self.verify_totp_code(&psql_connection, &user, &request.code);
+        if response.error.is_none() {
+            match User::set_totp_enabled(&psql_connection, user.uid, true) {
+                Ok(_) => {
+                    slog::info!(self.logger, "User {} enabled 2fa", user.uid);
+                    response.enabled = true;
                 }
-            },
-            Message::Cli(Cli::MakeTx(make_tx)) => {
-                let tx = make_tx.clone();
-                let result = match self.process_make_tx(make_tx).await {
-                    Ok(_) => "Successful".to_string(),
-                    Err(err) => err.to_string(),
-                };
-                let msg = Message::Cli(Cli::MakeTxResult(MakeTxResult { tx, result }));
-                // the identity is ignored by cli listener, so we are using ServiceIdentity::Api here
-                // just to pass some argument
-                listener(msg, ServiceIdentity::Api);
+                Err(_) => response.error = Some(TotpError::DatabaseConnectionFailed),
             }
-            _ => {}
         }
+        response
     }
 
-    async fn handle_dealer_deposit(&mut self, deposit: Deposit) {
-        dbg!("Dealer Deposit Received!");
-        // Dealer can transfer from an outside unknown wallet or between the external
-        // kollider wallet and lndhubx.
-        let conn = match &self.conn_pool {
-            Some(conn) => conn,
+    fn disable_totp(&self, request: DisableTotpRequest) -> TotpResponse {
+        let mut response = TotpResponse {
+            req_id: request.req_id,
+            uid: request.uid,
+            enabled: true,
+            secret: None,
+            otpauth_uri: None,
+            error: None,
+        };
+
+        let psql_connection = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(psql_connection) => psql_connection,
             None => {
-                slog::error!(self.logger, "No database provided.");
-                return;
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
             }
         };
 
-        let c = match conn.get() {
-            Ok(psql_connection) => psql_connection,
+        let user = match User::get_by_id(&psql_connection, request.uid as i32) {
+            Ok(user) => user,
             Err(_) => {
-                slog::error!(self.logger, "Couldn't get psql connection.");
-                return;
+                response.error = Some(TotpError::DatabaseConnectionFailed);
+                return response;
             }
         };
+        if !user.totp_enabled {
+            response.enabled = false;
+            response.error = Some(TotpError::NotEnrolled);
+            return response;
+        }
 
-        // Check whether we know about this invoice.
-        if let Ok(invoice) = Invoice::get_by_payment_request(&c, deposit.payment_request) {
-            dbg!(&invoice);
-            if let None = invoice.reference {
-                return;
+        response.error = self.verify_totp_code(&psql_connection, &user, &request.code);
+        if response.error.is_none() {
+            match User::set_totp_secret(&psql_connection, user.uid, None) {
+                Ok(_) => {
+                    slog::info!(self.logger, "User {} disabled 2fa", user.uid);
+                    response.enabled = false;
+                }
+                Err(_) => response.error = Some(TotpError::DatabaseConnectionFailed),
             }
+        }
+        response
+    }
 
-            let is_internal = match invoice.reference.unwrap().as_ref() {
-                "KolliderSettlement" => true,
-                "ExternalDeposit" => false,
-                _ => return,
-            };
-            dbg!(is_internal);
+    /// Holds a withdrawal above the approval threshold for an admin, before any of its funds move. Returns the
+    /// error the request is answered with if it was held.
+    fn hold_for_approval(
+        &self,
+        conn: &diesel::PgConnection,
+        request: &PaymentRequest,
+        amount_sats: u64,
+        amount_msat: u64,
+    ) -> Option<PaymentResponseError> {
+        if !needs_approval(self.withdrawal_approval_threshold_sats, amount_sats) {
+            return None;
+        }
+        match self.hold_withdrawal(conn, request, amount_sats, amount_msat) {
+            Ok(()) => {
+                slog::info!(
+                    self.logger,
+                    "Withdrawal {} of user {} is held for approval",
+                    request.req_id,
+                    request.uid
+                );
+                Some(PaymentResponseError::PendingApproval)
+            }
+            Err(err) => {
+                slog::error!(self.logger, "Failed to hold withdrawal {}: {}", request.req_id, err);
+                Some(PaymentResponseError::DatabaseConnectionFailed)
+            }
+        }
+    }
 
-            let (mut inbound_dealer_account, mut outbound_account, outbound_uid) = if is_internal {
-                let inbound = self
-                    .ledger
-                    .dealer_accounts
-                    .get_default_account(Currency::BTC, Some(AccountType::Internal));
-                let outbound = self
-                    .ledger
-                    .dealer_accounts
-                    .get_default_account(Currency::BTC, Some(AccountType::External));
-                (inbound, outbound, DEALER_UID)
-            } else {
-                let inbound = self
-                    .ledger
-                    .dealer_accounts
-                    .get_default_account(Currency::BTC, Some(AccountType::Internal));
-                let outbound = self
-                    .ledger
-                    .bank_liabilities
-                    .get_default_account(Currency::BTC, Some(AccountType::External));
-                (inbound, outbound, BANK_UID)
-            };
+    fn hold_withdrawal(
+        &self,
+        conn: &diesel::PgConnection,
+        request: &PaymentRequest,
+        amount_sats: u64,
+        amount_msat: u64,
+    ) -> Result<(), String> {
+        let payment_request =
+            serde_json::to_string(request).map_err(|err| format!("Failed to serialize payment request: {:?}", err))?;
+        pending_withdrawals::PendingWithdrawal {
+            req_id: request.req_id.to_string(),
+            uid: request.uid as i32,
+            amount_sats: amount_sats as i64,
+            currency: request.currency.to_string(),
+            payment_request,
+            status: pending_withdrawals::PENDING.to_string(),
+            decided_by: None,
+            reason: None,
+            created_at: utils::time::time_now() as i64,
+            decided_at: None,
+            processed_at: None,
+            amount_msat: amount_msat as i64,
+        }
+        .insert(conn)
+        .map_err(|err| format!("Failed to insert pending withdrawal: {:?}", err))?;
+        Ok(())
+    }
 
-            let value = Money::from_sats(Decimal::new(invoice.value as i64, 0));
+    /// Counts a payment against the daily budget of the API key or device it was made with. Each payment is
+    /// counted once, so it passes when it comes back through the loopback, and released again if it is refused
+    /// or fails. Payments whose value can't be determined are refused.
+    fn spend_key_budget(&self, request: &PaymentRequest, requester: UserId) -> Result<(), PaymentResponseError> {
+        let tid = match request.origin.as_ref().and_then(|origin| origin.key_id) {
+            Some(tid) => tid,
+            None => return Ok(()),
+        };
 
-            if self
-                .make_tx(
-                    &mut outbound_account,
-                    outbound_uid,
-                    &mut inbound_dealer_account,
-                    DEALER_UID,
-                    value,
-                )
-                .is_err()
-            {
-                slog::error!(self.logger, "Failed to make deposit it dealer account");
-            };
+        let conn = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or(PaymentResponseError::DatabaseConnectionFailed)?;
 
-            self.ledger
-                .dealer_accounts
-                .accounts
-                .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
-            self.update_account(&inbound_dealer_account, DEALER_UID);
+        let key = match ApiKey::get(&conn, tid, requester as i32) {
+            Ok(Some(key)) if !key.is_revoked() => key,
+            Ok(_) => return Err(PaymentResponseError::NotPermitted),
+            Err(err) => {
+                slog::error!(self.logger, "Failed to fetch api key {}: {:?}", tid, err);
+                return Err(PaymentResponseError::DatabaseConnectionFailed);
+            }
+        };
+        let daily_budget_sats = match key.daily_budget_sats {
+            Some(daily_budget_sats) => daily_budget_sats.max(0) as u64,
+            None => return Ok(()),
+        };
 
-            if is_internal {
-                self.ledger
-                    .dealer_accounts
-                    .accounts
-                    .insert(outbound_account.account_id, outbound_account.clone());
-                self.update_account(&outbound_account, DEALER_UID);
-            } else {
-                self.ledger
-                    .bank_liabilities
-                    .accounts
-                    .insert(outbound_account.account_id, outbound_account.clone());
-                self.update_account(&outbound_account, BANK_UID);
+        let req_id = request.req_id.to_string();
+        let db_error = |err: DieselError| {
+            slog::error!(self.logger, "Failed to access spends of api key {}: {:?}", tid, err);
+            PaymentResponseError::DatabaseConnectionFailed
+        };
+        if ApiKeySpend::exists(&conn, tid, &req_id).map_err(db_error)? {
+            return Ok(());
+        }
+
+        // The invoice amount is what leaves the node, whatever the currency the user pays in.
+        let amount = request
+            .payment_request
+            .as_ref()
+            .and_then(|payment_request| payment_request.parse::<lightning_invoice::Invoice>().ok())
+            .and_then(|invoice| invoice.amount_milli_satoshis())
+            .map(|msats| Money::from_msats(Decimal::new(msats as i64, 0)))
+            .or_else(|| request.amount.clone())
+            .ok_or(PaymentResponseError::KeyBudgetExceeded)?;
+        let now = utils::time::time_now();
+        let price = self
+            .last_market_prices
+            .as_ref()
+            .filter(|prices| now.saturating_sub(prices.timestamp) <= MARKET_PRICES_MAX_AGE_MS)
+            .and_then(|prices| prices.prices.iter().find(|price| price.currency == amount.currency))
+            .map(|price| (price.bid + price.ask) / dec!(2));
+        let amount_sats = key_budgets::value_in_sats(&amount, price).ok_or(PaymentResponseError::KeyBudgetExceeded)?;
+
+        let spent_last_day =
+            ApiKeySpend::get_spent_since(&conn, tid, key_budgets::window_start(now) as i64).map_err(db_error)?;
+        if !key_budgets::allows(daily_budget_sats, spent_last_day.max(0) as u64, amount_sats) {
+            return Err(PaymentResponseError::KeyBudgetExceeded);
+        }
+
+        ApiKeySpend {
+            tid,
+            req_id,
+            amount_sats: amount_sats as i64,
+            created_at: now as i64,
+        }
+        .insert(&conn)
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn release_key_spend(&self, req_id: RequestId) {
+        let released = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => ApiKeySpend::delete(&conn, &req_id.to_string()).map_err(|err| format!("{:?}", err)),
+            None => Err("Couldn't get psql connection".to_string()),
+        };
+        match released {
+            Ok(0) => {}
+            Ok(_) => slog::info!(self.logger, "Released the key budget spent by payment {}", req_id),
+            Err(err) => slog::error!(
+                self.logger,
+                "Failed to release the key budget of payment {}: {}",
+                req_id,
+                err
+            ),
+        }
+    }
+
+    /// Stores a payment from a vault to be made once the delay passed, returns the time it is released at.
+    /// The 2fa code is checked now since it would have expired by then.
+    fn delay_vault_withdrawal(&self, request: &PaymentRequest, vault: &Account) -> Result<u64, PaymentResponseError> {
+        let settings = match &self.vault_settings {
+            Some(settings) => settings.clone(),
+            None => return Err(PaymentResponseError::NotPermitted),
+        };
+
+        let amount = vault_withdrawal_amount(request, vault.currency);
+        let thresholds = &self.totp_settings.payment_thresholds;
+        let code = request.totp_code.as_deref();
+        let verified = match &amount {
+            Some(amount) => self.check_totp(request.uid, amount, thresholds, code),
+            // Fiat invoices are only quoted once released, when the code expired. It is required up front as
+            // if the amount was above the threshold.
+            None if thresholds.contains_key(&vault.currency.to_string()) => self.verify_totp(request.uid, code),
+            None => true,
+        };
+        if !verified {
+            return Err(PaymentResponseError::InvalidTotpCode);
+        }
+
+        let conn = self
+            .conn_pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .ok_or(PaymentResponseError::DatabaseConnectionFailed)?;
+
+        let payment_request = serde_json::to_string(request).map_err(|err| {
+            slog::error!(self.logger, "Failed to serialize payment request: {:?}", err);
+            PaymentResponseError::TransactionFailed
+        })?;
+        let now = utils::time::time_now();
+        let release_at = now + settings.withdrawal_delay_secs * 1000;
+        vault_withdrawals::VaultWithdrawal {
+            req_id: request.req_id.to_string(),
+            uid: request.uid as i32,
+            account_id: vault.account_id,
+            amount: amount.and_then(|amount| BigDecimal::from_str(&amount.value.to_string()).ok()),
+            currency: vault.currency.to_string(),
+            payment_request,
+            status: vault_withdrawals::PENDING.to_string(),
+            created_at: now as i64,
+            release_at: release_at as i64,
+            decided_at: None,
+        }
+        .insert(&conn)
+        .map_err(|err| {
+            slog::error!(self.logger, "Failed to insert vault withdrawal: {:?}", err);
+            PaymentResponseError::DatabaseConnectionFailed
+        })?;
+        Ok(release_at)
+    }
+
+    /// Sends the vault withdrawals whose delay passed back through the loopback to be paid.
+    pub fn release_vault_withdrawals<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        if self.vault_settings.is_none() {
+            return;
+        }
+
+        let conn = match self.conn_pool.as_ref().and_then(|pool| pool.get().ok()) {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let now = utils::time::time_now() as i64;
+        let due = match vault_withdrawals::VaultWithdrawal::get_due(&conn, now) {
+            Ok(due) => due,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load due vault withdrawals: {:?}", err);
+                return;
+            }
+        };
+
+        for withdrawal in due.into_iter().filter(|withdrawal| is_releasable(withdrawal, now)) {
+            let mut request = match serde_json::from_str::<PaymentRequest>(&withdrawal.payment_request) {
+                Ok(request) => request,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to deserialize vault withdrawal {}: {:?}",
+                        withdrawal.req_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+            // Marked first so a withdrawal is never released twice or after it was cancelled.
+            match vault_withdrawals::VaultWithdrawal::decide(
+                &conn,
+                withdrawal.req_id.clone(),
+                withdrawal.uid,
+                vault_withdrawals::RELEASED,
+                now,
+            ) {
+                Ok(1) => {}
+                Ok(_) => continue,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to release vault withdrawal {}: {:?}",
+                        withdrawal.req_id,
+                        err
+                    );
+                    continue;
+                }
             }
-            dbg!("DONE");
+
+            slog::info!(self.logger, "Releasing vault withdrawal {}", request.req_id);
+            // The delayed response is stored under the key and would be replayed instead of paying it.
+            request.idempotency_key = None;
+            self.released_vault_withdrawals.insert(request.req_id);
+            self.totp_verified.insert(request.req_id);
+            let msg = Message::Api(Api::PaymentRequest(request));
+            listener(msg, ServiceIdentity::Loopback);
         }
     }
 
-    async fn process_dealer_invoice(&mut self, pay_invoice: PayInvoice, is_external: bool) {
-        let decoded = match pay_invoice
-            .payment_request
-            .clone()
-            .parse::<lightning_invoice::Invoice>()
-        {
-            Ok(d) => d,
-            Err(_) => return,
+    fn list_pending_withdrawals(&self) -> Result<Vec<HeldWithdrawal>, String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
         };
-        let amount_in_milli_satoshi = decoded
-            .amount_milli_satoshis()
-            .unwrap_or_else(|| panic!("Amount in millisatoshi is not specified: {:?}", decoded));
-        // scale 3, which corresponds to dividing by 10^3 = 1000
-        let amount_in_sats = Decimal::new(amount_in_milli_satoshi as i64, 3);
 
-        slog::debug!(
-            self.logger,
-            "Dealer requests to pay {}invoice: {} of amount: {}",
-            is_external,
-            pay_invoice.payment_request,
-            amount_in_sats
-        );
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-        match self
-            .lnd_connector
-            .pay_invoice(
-                pay_invoice.payment_request.clone(),
-                amount_in_sats,
-                Some(self.ln_network_max_fee),
-                None,
-            )
-            .await
-        {
-            Ok(result) => {
-                slog::debug!(self.logger, "{:?}", result);
-                let (mut outbound_account, mut inbound_account, inbound_uid) = if is_external {
-                    let inbound_account = self
-                        .ledger
-                        .bank_liabilities
-                        .get_default_account(Currency::BTC, Some(AccountType::External));
-                    let outbound_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(Currency::BTC, Some(AccountType::Internal));
-                    (outbound_account, inbound_account, BANK_UID)
-                } else {
-                    let inbound_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(Currency::BTC, Some(AccountType::External));
-                    let outbound_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(Currency::BTC, Some(AccountType::Internal));
-                    (outbound_account, inbound_account, DEALER_UID)
-                };
+        let withdrawals = pending_withdrawals::PendingWithdrawal::get_pending(&psql_connection)
+            .map_err(|err| format!("Failed to load pending withdrawals: {:?}", err))?;
+        Ok(withdrawals
+            .into_iter()
+            .filter_map(|withdrawal| {
+                Some(HeldWithdrawal {
+                    req_id: Uuid::parse_str(&withdrawal.req_id).ok()?,
+                    uid: withdrawal.uid as UserId,
+                    amount_sats: withdrawal.amount_sats as u64,
+                    currency: Currency::from_str(&withdrawal.currency).ok()?,
+                    created_at: withdrawal.created_at as u64,
+                })
+            })
+            .collect())
+    }
 
-                let fees = Money::new(Currency::BTC, Some(dec!(0)));
-                let rate = Rate {
-                    quote: Currency::BTC,
-                    base: Currency::BTC,
-                    value: Decimal::ONE,
-                };
+    /// Records the decision of an admin, the withdrawal is released or rejected by `process_reviewed_withdrawals`.
+    fn decide_withdrawal(
+        &self,
+        req_id: RequestId,
+        status: &str,
+        operator: &str,
+        reason: Option<String>,
+    ) -> Result<(), String> {
+        if operator.trim().is_empty() {
+            return Err("Operator is required".to_string());
+        }
 
-                let amount = Money::from_sats(amount_in_sats);
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
+            }
+        };
 
-                if self
-                    .make_tx(
-                        &mut outbound_account,
-                        DEALER_UID,
-                        &mut inbound_account,
-                        inbound_uid,
-                        amount,
-                    )
-                    .is_err()
-                {
-                    return;
-                }
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-                if is_external {
-                    self.update_account(&inbound_account, BANK_UID);
-                    self.update_account(&outbound_account, DEALER_UID);
+        let updated = pending_withdrawals::PendingWithdrawal::decide(
+            &psql_connection,
+            req_id.to_string(),
+            status,
+            operator.to_string(),
+            reason,
+            utils::time::time_now() as i64,
+        )
+        .map_err(|err| format!("Failed to update pending withdrawal: {:?}", err))?;
+        if updated == 0 {
+            return Err(format!("Withdrawal {} is not pending", req_id));
+        }
+        slog::info!(self.logger, "Withdrawal {} {} by {}", req_id, status, operator);
+        Ok(())
+    }
 
-                    self.ledger
-                        .bank_liabilities
-                        .accounts
-                        .insert(inbound_account.account_id, inbound_account.clone());
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(outbound_account.account_id, outbound_account.clone());
-                } else {
-                    self.update_account(&inbound_account, DEALER_UID);
-                    self.update_account(&outbound_account, DEALER_UID);
+    /// Sends approved withdrawals back through the loopback to be paid and tells users about rejected ones.
+    pub fn process_reviewed_withdrawals<F: FnMut(Message, ServiceIdentity)>(&mut self, listener: &mut F) {
+        if self.withdrawal_approval_threshold_sats.is_none() {
+            return;
+        }
 
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(inbound_account.account_id, inbound_account.clone());
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(outbound_account.account_id, outbound_account.clone());
-                }
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
             }
+        };
+
+        let reviewed = match pending_withdrawals::PendingWithdrawal::get_decided_unprocessed(&psql_connection) {
+            Ok(reviewed) => reviewed,
             Err(err) => {
+                slog::error!(self.logger, "Failed to load reviewed withdrawals: {:?}", err);
+                return;
+            }
+        };
+
+        for withdrawal in reviewed {
+            let request = match serde_json::from_str::<PaymentRequest>(&withdrawal.payment_request) {
+                Ok(request) => request,
+                Err(err) => {
+                    slog::error!(
+                        self.logger,
+                        "Failed to deserialize pending withdrawal {}: {:?}",
+                        withdrawal.req_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+            // Marked first so a withdrawal is never released twice.
+            if let Err(err) = pending_withdrawals::PendingWithdrawal::mark_processed(
+                &psql_connection,
+                withdrawal.req_id.clone(),
+                utils::time::time_now() as i64,
+            ) {
                 slog::error!(
                     self.logger,
-                    "Failed to pay {}invoice {:?}, reason: {:?}",
-                    is_external,
-                    pay_invoice,
+                    "Failed to mark pending withdrawal {} as processed: {:?}",
+                    withdrawal.req_id,
                     err
                 );
+                continue;
+            }
+
+            if withdrawal.status == pending_withdrawals::APPROVED {
+                self.approved_withdrawals.insert(request.req_id);
+            } else {
+                self.release_key_spend(request.req_id);
             }
+            let (msg, identity) = reviewed_withdrawal_message(&withdrawal.status, request);
+            listener(msg, identity);
         }
     }
 
-    async fn process_create_invoice_request<F: FnMut(Message, ServiceIdentity)>(
-        &mut self,
-        req: CreateInvoiceRequest,
-        invoice_owner: UserId,
-        listener: &mut F,
-    ) {
+    /// Reopens the last closed period, the period closed before it stays closed.
+    fn reopen_period(&mut self, request: &ReopenPeriod) -> Result<i32, String> {
+        if request.operator.trim().is_empty() || request.reason.trim().is_empty() {
+            return Err("Operator and reason are required".to_string());
+        }
+
         let conn = match &self.conn_pool {
             Some(conn) => conn,
             None => {
                 slog::error!(self.logger, "No database provided.");
-                return;
+                return Err("No database provided".to_string());
             }
         };
 
-        let c = match conn.get() {
+        let psql_connection = match conn.get() {
             Ok(psql_connection) => psql_connection,
             Err(_) => {
                 slog::error!(self.logger, "Couldn't get psql connection.");
-                return;
+                return Err("Couldn't get psql connection".to_string());
             }
         };
 
-        let account_id = match invoice_owner {
-            BANK_UID => {
-                self.ledger
-                    .bank_liabilities
-                    .get_default_account(Currency::BTC, Some(AccountType::External))
-                    .account_id
+        let close = match PeriodClose::get_latest_active(&psql_connection) {
+            Ok(Some(close)) => close,
+            Ok(None) => return Err("No closed period".to_string()),
+            Err(err) => return Err(format!("Failed to fetch closed periods: {:?}", err)),
+        };
+
+        let reopened = PeriodClose::reopen(
+            &psql_connection,
+            close.id,
+            utils::time::time_now() as i64,
+            &request.operator,
+            &request.reason,
+        )
+        .map_err(|err| format!("Failed to reopen period: {:?}", err))?;
+
+        // Keeping the stricter end if the remaining closes can't be loaded, it's reloaded on restart.
+        match PeriodClose::get_latest_active(&psql_connection) {
+            Ok(close) => self.closed_until = close.map(|close| close.closed_until as u64),
+            Err(err) => slog::error!(self.logger, "Failed to fetch closed periods: {:?}", err),
+        }
+        slog::warn!(
+            self.logger,
+            "Period until {} reopened by {}: {}",
+            reopened.closed_until,
+            request.operator,
+            request.reason
+        );
+
+        Ok(reopened.id)
+    }
+
+    fn query_revenue(&self, query: &QueryRevenue) -> Result<Vec<RevenueBucket>, String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
             }
-            DEALER_UID => {
-                self.ledger
-                    .dealer_accounts
-                    .get_default_account(Currency::BTC, Some(AccountType::Internal))
-                    .account_id
+        };
+
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
             }
-            _ => panic!("Unexpected invoice owner. It can be Bank or Dealer only"),
         };
 
-        if let Ok(invoice) = self
-            .lnd_connector
-            .create_invoice(req.amount, req.memo, invoice_owner, account_id, None)
-            .await
-        {
-            slog::info!(self.logger, "Inserting invoice into db: {:?}", invoice);
-            if let Err(_err) = invoice.insert(&c) {
-                slog::error!(self.logger, "Couldn't insert invoice: {:?}", invoice);
-                return;
+        // Whole periods are returned, also if the range starts in the middle of one.
+        let from = period_start(day_start(query.from), query.period);
+        let to = query.to.unwrap_or_else(utils::time::time_now);
+        let revenues = OperatorRevenue::get_between(&psql_connection, from as i64, to as i64)
+            .map_err(|err| format!("Failed to fetch operator revenue: {:?}", err))?;
+
+        Ok(bucket_revenues(&revenues, query.period))
+    }
+
+    fn revenue_report(&self, request: &GetRevenueReport) -> Result<Vec<FeeIncomeBucket>, String> {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return Err("No database provided".to_string());
             }
+        };
 
-            let create_invoice_response = CreateInvoiceResponse {
-                req_id: req.req_id,
-                payment_request: invoice.payment_request,
-                amount: req.amount,
-            };
+        let psql_connection = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return Err("Couldn't get psql connection".to_string());
+            }
+        };
 
-            let msg = Message::Dealer(Dealer::CreateInvoiceResponse(create_invoice_response));
-            listener(msg, ServiceIdentity::Dealer)
+        let from = period_start(day_start(request.from), request.period);
+        let to = request.to.unwrap_or_else(utils::time::time_now);
+        let txs = SummaryTransaction::get_between(&psql_connection, from as i64, to as i64)
+            .map_err(|err| format!("Failed to fetch summary transactions: {:?}", err))?;
+
+        let fee_account_ids: HashSet<Uuid> = self.ledger.fee_account.accounts.keys().copied().collect();
+        Ok(bucket_fee_income(&txs, &fee_account_ids, request.period))
+    }
+
+    /// Exports the journal of all transactions since the last scheduled export
+    /// if the export interval has elapsed.
+    pub fn run_scheduled_export(&mut self) {
+        let (output_dir, interval_secs) = match &self.export_settings {
+            Some(ExportSettings {
+                output_dir,
+                interval_secs: Some(interval_secs),
+                ..
+            }) => (output_dir.clone(), *interval_secs),
+            _ => return,
+        };
+
+        let now = utils::time::time_now();
+        if now < self.last_export_timestamp + interval_secs * 1000 {
+            return;
+        }
+
+        let request = ExportJournal {
+            from: self.last_export_timestamp,
+            to: Some(now),
+            path: format!("{}/journal_{}_{}.csv", output_dir, self.last_export_timestamp, now),
+        };
+
+        match self.export_journal(&request) {
+            Ok(count) => {
+                slog::info!(self.logger, "Exported {} transactions to {}", count, request.path);
+                self.last_export_timestamp = now;
+            }
+            Err(err) => {
+                slog::error!(self.logger, "Failed to export journal to {}: {}", request.path, err);
+            }
         }
     }
 
@@ -3118,6 +12638,7 @@ impl BankEngine {
             quote: currency,
             base: currency,
             value: Decimal::ONE,
+            ..Default::default()
         };
 
         let amount = Money::new(currency, Some(amount));
@@ -3161,6 +12682,153 @@ impl BankEngine {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn retry(req_id: &str) -> PaymentRetry {
+        PaymentRetry {
+            req_id: req_id.to_string(),
+            uid: 1,
+            payment_request: String::from("lnbc1"),
+            payment_result: String::new(),
+            attempts: 1,
+            next_attempt_at: ATTEMPT_IN_FLIGHT,
+            created_at: 0,
+        }
+    }
+
+    fn pending_payment(req_id: &str) -> PendingPayment {
+        PendingPayment {
+            req_id: req_id.to_string(),
+            uid: 1,
+            payment_hash: String::from("00"),
+            amount: BigDecimal::from(1),
+            estimated_fee_sats: BigDecimal::from(0),
+            payment_result: String::new(),
+            created_at: 0,
+        }
+    }
+
+    fn payment_request(amount: Option<Money>, payment_request: Option<&str>) -> PaymentRequest {
+        PaymentRequest {
+            req_id: Uuid::new_v4(),
+            uid: 1,
+            payment_request: payment_request.map(String::from),
+            currency: Currency::BTC,
+            receipient: None,
+            target_account_id: None,
+            account_id: None,
+            destination: None,
+            keysend_message: None,
+            amount,
+            rate: None,
+            fees: None,
+            requoted: false,
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
+        }
+    }
+
+    fn vault_withdrawal(status: &str, release_at: i64) -> vault_withdrawals::VaultWithdrawal {
+        vault_withdrawals::VaultWithdrawal {
+            req_id: Uuid::new_v4().to_string(),
+            uid: 1,
+            account_id: Uuid::new_v4(),
+            amount: None,
+            currency: Currency::BTC.to_string(),
+            payment_request: String::new(),
+            status: status.to_string(),
+            created_at: 0,
+            release_at,
+            decided_at: None,
+        }
+    }
+
     #[tokio::test]
     async fn test_create_bank_manager() {}
+
+    #[test]
+    fn test_withdrawals_above_the_threshold_are_held() {
+        assert!(!needs_approval(None, u64::MAX));
+        assert!(!needs_approval(Some(100_000), 100_000));
+        assert!(needs_approval(Some(100_000), 100_001));
+    }
+
+    #[test]
+    fn test_approved_withdrawals_are_paid_and_rejected_ones_answered() {
+        let mut request = payment_request(Some(Money::from_sats(dec!(200_000))), None);
+        request.idempotency_key = Some(String::from("key"));
+        let req_id = request.req_id;
+
+        match reviewed_withdrawal_message(pending_withdrawals::APPROVED, request.clone()) {
+            (Message::Api(Api::PaymentRequest(approved)), ServiceIdentity::Loopback) => {
+                assert_eq!(approved.req_id, req_id);
+                assert!(approved.idempotency_key.is_none());
+            }
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+
+        match reviewed_withdrawal_message(pending_withdrawals::REJECTED, request) {
+            (Message::Api(Api::PaymentResponse(response)), ServiceIdentity::Api) => {
+                assert_eq!(response.req_id, req_id);
+                assert!(!response.success);
+                assert!(matches!(response.error, Some(PaymentResponseError::WithdrawalRejected)));
+            }
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_vault_withdrawals_are_released_after_the_delay_unless_cancelled() {
+        use vault_withdrawals::{CANCELLED, PENDING, RELEASED};
+
+        let now = 1_000_000;
+        assert!(!is_releasable(&vault_withdrawal(PENDING, now + 1), now));
+        assert!(is_releasable(&vault_withdrawal(PENDING, now), now));
+        assert!(!is_releasable(&vault_withdrawal(CANCELLED, now), now));
+        assert!(!is_releasable(&vault_withdrawal(RELEASED, now), now));
+    }
+
+    #[test]
+    fn test_vault_withdrawal_amounts() {
+        let request = payment_request(Some(Money::new(Currency::USD, Some(dec!(25)))), None);
+        let amount = vault_withdrawal_amount(&request, Currency::USD).map(|amount| amount.value);
+        assert_eq!(amount, Some(dec!(25)));
+
+        // Not known for fiat invoices until they are quoted, nor for invoices that don't decode.
+        let request = payment_request(None, Some("lnbc1"));
+        assert!(vault_withdrawal_amount(&request, Currency::USD).is_none());
+        assert!(vault_withdrawal_amount(&request, Currency::BTC).is_none());
+    }
+
+    #[test]
+    fn test_transfers_are_only_converted_through_quoted_pairs() {
+        let available = [Currency::USD, Currency::EUR];
+        assert!(is_quoted_transfer(Currency::BTC, Currency::USD, &available));
+        assert!(is_quoted_transfer(Currency::EUR, Currency::BTC, &available));
+        assert!(!is_quoted_transfer(Currency::USD, Currency::EUR, &available));
+        assert!(!is_quoted_transfer(Currency::BTC, Currency::GBP, &available));
+    }
+
+    #[test]
+    fn test_unused_fee_reserve_is_refunded() {
+        assert_eq!(
+            unused_fee_reserve(dec!(0.00011), dec!(0.0001), dec!(0.000004)),
+            dec!(0.000006)
+        );
+        assert_eq!(unused_fee_reserve(dec!(0.00011), dec!(0.0001), dec!(0.00001)), dec!(0));
+    }
+
+    #[test]
+    fn test_restart_during_retry_only_reschedules_undispatched_attempts() {
+        // The bank stopped while one retry was being paid and before the attempt of another was persisted.
+        let retries = vec![retry("dispatched"), retry("undispatched")];
+        let pending_payments = vec![pending_payment("dispatched")];
+
+        let interrupted = interrupted_retries(retries, &pending_payments);
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0].req_id, "undispatched");
+
+        assert!(interrupted_retries(Vec::new(), &pending_payments).is_empty());
+    }
 }
@@ -0,0 +1,173 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Classifier requests taking longer than this are treated as if the classifier accepted the text.
+const CLASSIFIER_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentFilterSettings {
+    /// Texts matching any of these regular expressions are rejected.
+    #[serde(default)]
+    pub deny_patterns: Vec<String>,
+    /// Texts containing more urls than this are rejected.
+    pub max_urls: Option<usize>,
+    /// Texts are also posted to this url as `{"text": ...}`, the classifier answers with `{"spam": bool}`.
+    pub classifier_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentRejection {
+    DeniedPattern(String),
+    TooManyUrls(usize),
+    Classified,
+}
+
+/// Decides whether a text is spam, e.g. an external moderation service.
+pub trait ContentClassifier: Send {
+    fn is_spam(&self, text: &str) -> bool;
+}
+
+/// Asks a classifier over http. Texts are accepted if the classifier can't be reached.
+pub struct HttpClassifier {
+    url: String,
+}
+
+impl HttpClassifier {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClassifierResponse {
+    spam: bool,
+}
+
+impl ContentClassifier for HttpClassifier {
+    fn is_spam(&self, text: &str) -> bool {
+        let client = match reqwest::Client::builder().timeout(CLASSIFIER_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(_) => return false,
+        };
+
+        match client.post(&self.url).json(&serde_json::json!({ "text": text })).send() {
+            Ok(mut response) if response.status().is_success() => response
+                .json::<ClassifierResponse>()
+                .map(|classified| classified.spam)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+/// Checks user supplied texts like invoice memos before they are logged, stored or shown to anyone.
+pub struct ContentFilter {
+    deny_patterns: Vec<Regex>,
+    max_urls: Option<usize>,
+    classifier: Option<Box<dyn ContentClassifier>>,
+}
+
+impl ContentFilter {
+    /// Panics on invalid deny patterns so a broken config is noticed on startup.
+    pub fn new(settings: &ContentFilterSettings) -> Self {
+        let deny_patterns = settings
+            .deny_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).unwrap_or_else(|err| panic!("Invalid content deny pattern {}: {}", pattern, err))
+            })
+            .collect();
+
+        Self {
+            deny_patterns,
+            max_urls: settings.max_urls,
+            classifier: settings
+                .classifier_url
+                .clone()
+                .map(|url| Box::new(HttpClassifier::new(url)) as Box<dyn ContentClassifier>),
+        }
+    }
+
+    /// Replaces the classifier configured in the settings.
+    pub fn set_classifier(&mut self, classifier: Box<dyn ContentClassifier>) {
+        self.classifier = Some(classifier);
+    }
+
+    pub fn check(&self, text: &str) -> Result<(), ContentRejection> {
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(pattern) = self.deny_patterns.iter().find(|pattern| pattern.is_match(text)) {
+            return Err(ContentRejection::DeniedPattern(pattern.as_str().to_string()));
+        }
+
+        if let Some(max_urls) = self.max_urls {
+            let url_count = count_urls(text);
+            if url_count > max_urls {
+                return Err(ContentRejection::TooManyUrls(url_count));
+            }
+        }
+
+        match &self.classifier {
+            Some(classifier) if classifier.is_spam(text) => Err(ContentRejection::Classified),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks all texts, the classifier is asked once with the texts joined.
+    pub fn check_all<'a>(&self, texts: impl IntoIterator<Item = &'a str>) -> Result<(), ContentRejection> {
+        let texts = texts.into_iter().filter(|text| !text.is_empty()).collect::<Vec<&str>>();
+        if texts.is_empty() {
+            return Ok(());
+        }
+        self.check(&texts.join("\n"))
+    }
+}
+
+fn count_urls(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| {
+            let word = word.to_lowercase();
+            word.contains("://") || word.starts_with("www.") || word.starts_with("lightning:")
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DenyAll;
+
+    impl ContentClassifier for DenyAll {
+        fn is_spam(&self, _text: &str) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn rejects_denied_patterns_urls_and_classified_texts() {
+        let mut filter = ContentFilter::new(&ContentFilterSettings {
+            deny_patterns: vec![String::from("(?i)free\\s+bitcoin")],
+            max_urls: Some(1),
+            classifier_url: None,
+        });
+
+        assert_eq!(filter.check("coffee"), Ok(()));
+        assert!(matches!(
+            filter.check("FREE Bitcoin here"),
+            Err(ContentRejection::DeniedPattern(_))
+        ));
+        assert_eq!(filter.check("see https://a.example"), Ok(()));
+        assert_eq!(
+            filter.check_all(["https://a.example", "www.b.example"]),
+            Err(ContentRejection::TooManyUrls(2))
+        );
+
+        filter.set_classifier(Box::new(DenyAll));
+        assert_eq!(filter.check("coffee"), Err(ContentRejection::Classified));
+        assert_eq!(filter.check_all([""]), Ok(()));
+    }
+}
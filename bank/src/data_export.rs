@@ -0,0 +1,65 @@
+use core_types::{Account, UserId};
+use models::access_policies::AccessPolicy;
+use models::deposit_routing_rules::DepositRoutingRule;
+use models::dust_sweep_preferences::DustSweepPreference;
+use models::invoices::Invoice;
+use models::summary_transactions::SummaryTransaction;
+use models::webhooks::Webhook;
+use serde::Serialize;
+
+/// Inline archives are sent in chunks of at most this many bytes to keep the messages small.
+pub const DATA_EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+/// Stored archives are deleted after this long if they weren't downloaded.
+pub const DATA_EXPORT_TTL_MS: u64 = 24 * 3_600_000;
+
+#[derive(Debug, Serialize)]
+pub struct UserPreferences {
+    pub dust_sweep: Option<DustSweepPreference>,
+    pub access_policy: Option<AccessPolicy>,
+    pub deposit_routing_rules: Vec<DepositRoutingRule>,
+    pub webhooks: Vec<Webhook>,
+}
+
+/// Everything stored about a user, as handed out to the user.
+#[derive(Debug, Serialize)]
+pub struct UserDataArchive {
+    pub uid: UserId,
+    pub username: String,
+    pub exported_at: u64,
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<SummaryTransaction>,
+    pub invoices: Vec<Invoice>,
+    pub preferences: UserPreferences,
+}
+
+/// Splits the text into chunks of at most `size` bytes without splitting characters.
+pub fn split_chunks(text: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = size.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_end_on_character_boundaries() {
+        let chunks = split_chunks("ab€cd", 3);
+        assert_eq!(chunks, vec!["ab", "€", "cd"]);
+        assert_eq!(chunks.concat(), "ab€cd");
+        assert!(split_chunks("", 3).is_empty());
+    }
+}
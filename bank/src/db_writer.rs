@@ -0,0 +1,232 @@
+use core_types::DbPool;
+use crossbeam_channel::{bounded, Sender, TrySendError};
+use diesel::result::Error as DieselError;
+use diesel::PgConnection;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Writes are queued on this many threads if not set.
+pub const DEFAULT_DB_WRITER_THREADS: usize = 4;
+/// Writes waiting per thread before submitting blocks the bank's loop.
+const DB_WRITER_QUEUE_SIZE: usize = 4096;
+/// Coalesced writes are queued early once this many are held back.
+const MAX_COALESCED_WRITES: usize = 10_000;
+/// Wait before a failed write is attempted again, doubled up to `MAX_WRITE_RETRY_BACKOFF_MS` while it keeps failing.
+const WRITE_RETRY_BACKOFF_MS: u64 = 100;
+const MAX_WRITE_RETRY_BACKOFF_MS: u64 = 5_000;
+
+/// Attempted again until it succeeds, so it must be safe to run more than once.
+pub type DbCommand = Box<dyn Fn(&PgConnection) -> Result<(), DieselError> + Send>;
+
+/// Runs writes whose result the bank doesn't wait for on dedicated threads, so a slow database doesn't stall
+/// the loop. Writes with the same key run on the same thread in the order they were submitted. A failed write is
+/// retried until it goes through, the writes queued behind it wait for it.
+///
+/// Only account balances and other writes nothing is read back from go through here. Reads and writes the bank
+/// needs the result of, like transactions, idempotency keys or 2fa steps, still run on the bank's loop.
+pub struct DbWriter {
+    queues: Vec<Sender<(&'static str, DbCommand)>>,
+    // latest write per key held back until the next flush
+    coalesced: HashMap<u128, (&'static str, DbCommand)>,
+    // threads currently retrying a failed write
+    failing: Arc<AtomicUsize>,
+    // writes queued that haven't finished yet
+    unwritten: Arc<AtomicUsize>,
+    // notified when the last unwritten write finished
+    written: Arc<Notify>,
+    pool: DbPool,
+    logger: slog::Logger,
+}
+
+impl DbWriter {
+    pub fn start(pool: DbPool, threads: usize, logger: slog::Logger) -> Self {
+        let failing = Arc::new(AtomicUsize::new(0));
+        let unwritten = Arc::new(AtomicUsize::new(0));
+        let written = Arc::new(Notify::new());
+        let queues = (0..threads.max(1))
+            .map(|index| {
+                let (tx, rx) = bounded::<(&'static str, DbCommand)>(DB_WRITER_QUEUE_SIZE);
+                let pool = pool.clone();
+                let logger = logger.clone();
+                let failing = failing.clone();
+                let unwritten = unwritten.clone();
+                let written = written.clone();
+                thread::Builder::new()
+                    .name(format!("db-writer-{}", index))
+                    .spawn(move || {
+                        for (name, command) in rx {
+                            run(&pool, &logger, &failing, name, command);
+                            finish(&unwritten, &written);
+                        }
+                    })
+                    .expect("Failed to spawn db writer thread");
+                tx
+            })
+            .collect();
+        Self {
+            queues,
+            coalesced: HashMap::new(),
+            failing,
+            unwritten,
+            written,
+            pool,
+            logger,
+        }
+    }
+
+    /// Queues the write. A full queue blocks until there is room again, so writes are neither dropped nor
+    /// reordered.
+    pub fn submit(&self, key: u128, name: &'static str, command: DbCommand) {
+        let queue = &self.queues[(key % self.queues.len() as u128) as usize];
+        self.unwritten.fetch_add(1, Ordering::SeqCst);
+        let command = match queue.try_send((name, command)) {
+            Ok(()) => return,
+            Err(TrySendError::Full(command)) => {
+                slog::warn!(self.logger, "Db writer queue is full, waiting to queue {}", name);
+                command
+            }
+            Err(TrySendError::Disconnected((name, command))) => {
+                run(&self.pool, &self.logger, &self.failing, name, command);
+                finish(&self.unwritten, &self.written);
+                return;
+            }
+        };
+        if let Err(err) = queue.send(command) {
+            let (name, command) = err.into_inner();
+            run(&self.pool, &self.logger, &self.failing, name, command);
+            finish(&self.unwritten, &self.written);
+        }
+    }
+
+    /// Holds the write back until the next flush, replacing a write of the same key held back before. Only for
+    /// writes that overwrite the whole state of the key, e.g. the balance of an account.
+    pub fn coalesce(&mut self, key: u128, name: &'static str, command: DbCommand) {
+        self.coalesced.insert(key, (name, command));
+        if self.coalesced.len() >= MAX_COALESCED_WRITES {
+            self.flush();
+        }
+    }
+
+    /// Queues the writes held back.
+    pub fn flush(&mut self) {
+        for (key, (name, command)) in std::mem::take(&mut self.coalesced) {
+            self.submit(key, name, command);
+        }
+    }
+
+    /// Writes that were queued or held back but haven't finished yet.
+    pub fn pending(&self) -> usize {
+        self.coalesced.len() + self.unwritten.load(Ordering::SeqCst)
+    }
+
+    /// Queues the writes held back and waits until every write submitted so far has finished. Returns false if
+    /// that took longer than `timeout`. Writes submitted while waiting are waited for as well, so the bank has to
+    /// wait on its own loop for the result to be meaningful.
+    pub async fn wait_until_written(&mut self, timeout: Duration) -> bool {
+        self.flush();
+        let deadline = tokio::time::Instant::now() + timeout;
+        // A notification left over from writes finished before only leads to another check.
+        while self.unwritten.load(Ordering::SeqCst) > 0 {
+            let notified = tokio::time::timeout_at(deadline, self.written.notified()).await;
+            if notified.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether a write is being retried, the database lags behind the writes submitted since.
+    pub fn is_failing(&self) -> bool {
+        self.failing.load(Ordering::SeqCst) > 0
+    }
+}
+
+/// Counts a queued write as finished, waking the bank if it waits for the writes.
+fn finish(unwritten: &AtomicUsize, written: &Notify) {
+    if unwritten.fetch_sub(1, Ordering::SeqCst) == 1 {
+        written.notify_one();
+    }
+}
+
+/// Runs the write, attempting it again with a growing backoff until it succeeds.
+fn run(pool: &DbPool, logger: &slog::Logger, failing: &AtomicUsize, name: &str, command: DbCommand) {
+    let mut backoff = WRITE_RETRY_BACKOFF_MS;
+    let mut failed = false;
+    loop {
+        let result = match pool.get() {
+            Ok(conn) => command(&conn).map_err(|err| format!("{:?}", err)),
+            Err(err) => Err(format!("couldn't get psql connection: {}", err)),
+        };
+        match result {
+            Ok(()) => break,
+            Err(err) => {
+                slog::error!(logger, "Failed to write {}, retrying in {} ms: {}", name, backoff, err);
+                if !failed {
+                    failed = true;
+                    failing.fetch_add(1, Ordering::SeqCst);
+                }
+                thread::sleep(Duration::from_millis(backoff));
+                backoff = (backoff * 2).min(MAX_WRITE_RETRY_BACKOFF_MS);
+            }
+        }
+    }
+    if failed {
+        failing.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::r2d2::ConnectionManager;
+
+    #[test]
+    fn test_writes_of_the_same_key_are_coalesced() {
+        let pool =
+            r2d2::Pool::builder().build_unchecked(ConnectionManager::<PgConnection>::new("postgres://localhost/test"));
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let mut db_writer = DbWriter::start(pool, 1, logger);
+
+        db_writer.coalesce(1, "account", Box::new(|_| Ok(())));
+        db_writer.coalesce(1, "account", Box::new(|_| Ok(())));
+        db_writer.coalesce(2, "account", Box::new(|_| Ok(())));
+        assert_eq!(db_writer.pending(), 2);
+    }
+
+    #[test]
+    fn test_failed_writes_are_retried() {
+        let pool = r2d2::Pool::builder()
+            .connection_timeout(Duration::from_millis(10))
+            .build_unchecked(ConnectionManager::<PgConnection>::new("postgres://127.0.0.1:1/test"));
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let db_writer = DbWriter::start(pool, 1, logger);
+        assert!(!db_writer.is_failing());
+
+        db_writer.submit(1, "account", Box::new(|_| Ok(())));
+        db_writer.submit(1, "account", Box::new(|_| Ok(())));
+        thread::sleep(Duration::from_millis(200));
+
+        // Without a database the first write is kept and the second waits behind it.
+        assert!(db_writer.is_failing());
+        assert_eq!(db_writer.pending(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_for_writes_is_bounded() {
+        let pool = r2d2::Pool::builder()
+            .connection_timeout(Duration::from_millis(10))
+            .build_unchecked(ConnectionManager::<PgConnection>::new("postgres://127.0.0.1:1/test"));
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let mut db_writer = DbWriter::start(pool, 1, logger);
+        assert!(db_writer.wait_until_written(Duration::from_millis(10)).await);
+
+        // Held back writes are queued and waited for, the unreachable database never lets them finish.
+        db_writer.coalesce(1, "account", Box::new(|_| Ok(())));
+        assert!(!db_writer.wait_until_written(Duration::from_millis(50)).await);
+        assert_eq!(db_writer.pending(), 1);
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Requests to the federation client taking longer than this fail, waiting for an incoming payment included.
+const ECASH_CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+/// Seconds invoices of the federation's gateway are valid for.
+const GATEWAY_INVOICE_EXPIRY_SECS: u64 = 600;
+/// Seconds after which notes nobody redeemed go back to the bank's federation wallet.
+const SPEND_TIMEOUT_SECS: u64 = 86400 * 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcashSettings {
+    /// Url of the fedimint-clientd of the bank, e.g. `http://localhost:3333`.
+    pub url: String,
+    pub password: String,
+    /// Federation the bank's client is joined to, the client's default federation if not set.
+    pub federation_id: Option<String>,
+    /// Lightning gateway of the federation payments are bridged through.
+    pub gateway_id: String,
+    /// Charged on every deposit and withdrawal for the fees of the gateway.
+    pub gateway_fee_sats: u64,
+}
+
+impl EcashSettings {
+    /// Sats a deposit of notes worth `amount_msat` is credited with, the gateway fee of moving them to the node
+    /// is kept back.
+    pub fn deposit_credit_sats(&self, amount_msat: u64) -> u64 {
+        (amount_msat / 1000).saturating_sub(self.gateway_fee_sats)
+    }
+}
+
+/// Talks to the REST api of a fedimint-clientd joined to the federation the ecash is issued by.
+pub struct EcashGateway {
+    settings: EcashSettings,
+}
+
+impl EcashGateway {
+    pub fn new(settings: EcashSettings) -> Self {
+        Self {
+            settings: EcashSettings {
+                url: settings.url.trim_end_matches('/').to_string(),
+                ..settings
+            },
+        }
+    }
+
+    pub fn settings(&self) -> &EcashSettings {
+        &self.settings
+    }
+
+    fn post(&self, path: &str, mut body: Value) -> Result<Value, String> {
+        if let Some(federation_id) = &self.settings.federation_id {
+            body["federationId"] = json!(federation_id);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(ECASH_CLIENT_TIMEOUT)
+            .build()
+            .map_err(|err| format!("Failed to build http client: {}", err))?;
+        let response = client
+            .post(&format!("{}{}", self.settings.url, path))
+            .bearer_auth(&self.settings.password)
+            .json(&body)
+            .send();
+        match response {
+            Ok(mut response) if response.status().is_success() => response
+                .json::<Value>()
+                .map_err(|err| format!("Invalid response: {}", err)),
+            Ok(mut response) => Err(format!(
+                "Federation client answered {}: {}",
+                response.status(),
+                response.text().unwrap_or_default()
+            )),
+            Err(err) => Err(format!("Federation client unreachable: {}", err)),
+        }
+    }
+
+    /// Creates an invoice of the gateway that pays out as ecash to the bank's federation wallet. Returns the
+    /// operation id and the invoice.
+    pub fn create_invoice(&self, amount_msat: u64, description: &str) -> Result<(String, String), String> {
+        let response = self.post(
+            "/v2/ln/invoice",
+            json!({
+                "amountMsat": amount_msat,
+                "description": description,
+                "expiryTime": GATEWAY_INVOICE_EXPIRY_SECS,
+                "gatewayId": self.settings.gateway_id,
+            }),
+        )?;
+        match (response["operationId"].as_str(), response["invoice"].as_str()) {
+            (Some(operation_id), Some(invoice)) => Ok((operation_id.to_string(), invoice.to_string())),
+            _ => Err(format!("No invoice in response: {}", response)),
+        }
+    }
+
+    /// Waits until the gateway issued the ecash of a paid invoice.
+    pub fn await_invoice(&self, operation_id: &str) -> Result<(), String> {
+        self.post("/v2/ln/await-invoice", json!({ "operationId": operation_id }))
+            .map(|_| ())
+    }
+
+    /// Takes notes worth exactly `amount_msat` out of the bank's federation wallet.
+    pub fn spend(&self, amount_msat: u64) -> Result<String, String> {
+        let response = self.post(
+            "/v2/mint/spend",
+            json!({
+                "amountMsat": amount_msat,
+                "allowOverpay": false,
+                "timeout": SPEND_TIMEOUT_SECS,
+                "includeInvite": false,
+            }),
+        )?;
+        response["notes"]
+            .as_str()
+            .map(|notes| notes.to_string())
+            .ok_or_else(|| format!("No notes in response: {}", response))
+    }
+
+    /// Value of notes, checked with the federation without redeeming them.
+    pub fn validate(&self, notes: &str) -> Result<u64, String> {
+        let response = self.post("/v2/mint/validate", json!({ "notes": notes }))?;
+        response["amountMsat"]
+            .as_u64()
+            .ok_or_else(|| format!("No amount in response: {}", response))
+    }
+
+    /// Redeems notes into the bank's federation wallet and returns their value. Notes that were spent already are
+    /// rejected by the federation.
+    pub fn reissue(&self, notes: &str) -> Result<u64, String> {
+        let response = self.post("/v2/mint/reissue", json!({ "notes": notes }))?;
+        response["amountMsat"]
+            .as_u64()
+            .ok_or_else(|| format!("No amount in response: {}", response))
+    }
+
+    /// Pays an invoice of the bank's node from the federation wallet through the gateway.
+    pub fn pay(&self, payment_request: &str) -> Result<(), String> {
+        self.post(
+            "/v2/ln/pay",
+            json!({
+                "paymentInfo": payment_request,
+                "gatewayId": self.settings.gateway_id,
+            }),
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deposit_credit_sats() {
+        let settings = EcashSettings {
+            url: String::from("http://localhost:3333"),
+            password: String::new(),
+            federation_id: None,
+            gateway_id: String::new(),
+            gateway_fee_sats: 10,
+        };
+        assert_eq!(settings.deposit_credit_sats(100_999), 90);
+        assert_eq!(settings.deposit_credit_sats(5_000), 0);
+    }
+}
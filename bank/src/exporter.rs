@@ -0,0 +1,246 @@
+use models::transactions::Transaction;
+
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use uuid::Uuid;
+
+const JOURNAL_HEADER: &str = "date,txid,account,debit,credit,currency,exchange_rate,fees,tx_type";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportSettings {
+    /// Directory the scheduled journals are written to.
+    pub output_dir: String,
+    /// Interval in seconds between scheduled exports. Journals are only exported via cli if not set.
+    pub interval_secs: Option<u64>,
+    /// Account name used for every account without an explicit mapping.
+    pub default_account: String,
+    /// Maps an account id or a uid to the account name used by the accounting software.
+    #[serde(default)]
+    pub account_mapping: HashMap<String, String>,
+    /// Clearing account that balances the legs of a transaction between two currencies.
+    #[serde(default = "default_exchange_account")]
+    pub exchange_account: String,
+    /// Account that takes the difference between the outbound and inbound amount of a transaction.
+    #[serde(default = "default_fee_account")]
+    pub fee_account: String,
+}
+
+fn default_exchange_account() -> String {
+    String::from("Currency Exchange")
+}
+
+fn default_fee_account() -> String {
+    String::from("Fees")
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Database,
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Database => write!(f, "Database"),
+            ExportError::Io(err) => write!(f, "Io: {}", err),
+        }
+    }
+}
+
+fn map_account(settings: &ExportSettings, uid: i32, account_id: &Uuid) -> String {
+    settings
+        .account_mapping
+        .get(&account_id.to_string())
+        .or_else(|| settings.account_mapping.get(&uid.to_string()))
+        .cloned()
+        .unwrap_or_else(|| settings.default_account.clone())
+}
+
+/// Quotes a field if it contains a separator, a quote or a line break, quotes are doubled.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Legs of a transaction as account, amount and currency. Positive amounts are debits, negative ones credits.
+fn legs(settings: &ExportSettings, tx: &Transaction) -> Vec<(String, BigDecimal, String)> {
+    let outbound_account = map_account(settings, tx.outbound_uid, &tx.outbound_account_id);
+    let inbound_account = map_account(settings, tx.inbound_uid, &tx.inbound_account_id);
+    let (outbound, inbound) = (tx.outbound_amount.clone(), tx.inbound_amount.clone());
+    let (outbound_currency, inbound_currency) = (tx.outbound_currency.clone(), tx.inbound_currency.clone());
+
+    if outbound_currency != inbound_currency {
+        let exchange = settings.exchange_account.clone();
+        return vec![
+            (outbound_account, outbound.clone(), outbound_currency.clone()),
+            (exchange.clone(), -outbound, outbound_currency),
+            (exchange, inbound.clone(), inbound_currency.clone()),
+            (inbound_account, -inbound, inbound_currency),
+        ];
+    }
+
+    let difference = &outbound - &inbound;
+    let mut legs = vec![
+        (outbound_account, outbound, outbound_currency.clone()),
+        (inbound_account, -inbound, outbound_currency.clone()),
+    ];
+    if !difference.is_zero() {
+        legs.push((settings.fee_account.clone(), -difference, outbound_currency));
+    }
+    legs
+}
+
+/// Builds a double-entry journal where every transaction debits the outbound
+/// account and credits the inbound account. Debits and credits balance per
+/// currency: transactions between two currencies go through the exchange account
+/// and any difference between the outbound and inbound amount is booked on the
+/// fee account.
+pub fn journal_csv(settings: &ExportSettings, txs: &[Transaction]) -> String {
+    let mut journal = String::from(JOURNAL_HEADER);
+    journal.push('\n');
+
+    for tx in txs {
+        for (account, amount, currency) in legs(settings, tx) {
+            let (debit, credit) = if amount < BigDecimal::zero() {
+                (String::new(), (-amount).to_string())
+            } else {
+                (amount.to_string(), String::new())
+            };
+            let fields = [
+                tx.created_at.to_string(),
+                tx.txid.clone(),
+                account,
+                debit,
+                credit,
+                currency,
+                tx.exchange_rate.to_string(),
+                tx.fees.to_string(),
+                tx.tx_type.clone(),
+            ];
+            let line = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+            journal.push_str(&line.join(","));
+            journal.push('\n');
+        }
+    }
+
+    journal
+}
+
+/// End of the period covered by the latest journal in `output_dir`, read from the
+/// `journal_{from}_{to}.csv` names of the scheduled exports.
+pub fn last_export_end(output_dir: &str) -> Option<u64> {
+    std::fs::read_dir(output_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let period = name.strip_prefix("journal_")?.strip_suffix(".csv")?;
+            let (_, to) = period.split_once('_')?;
+            to.parse::<u64>().ok()
+        })
+        .max()
+}
+
+/// Writes the journal of all transactions between `from` and `to` to `path`
+/// and returns the number of exported transactions.
+pub fn export_journal(
+    conn: &diesel::PgConnection,
+    settings: &ExportSettings,
+    from: i64,
+    to: i64,
+    path: &str,
+) -> Result<usize, ExportError> {
+    let txs = Transaction::get_between(conn, from, to).map_err(|_| ExportError::Database)?;
+    let journal = journal_csv(settings, &txs);
+    let mut file = File::create(path).map_err(ExportError::Io)?;
+    file.write_all(journal.as_bytes()).map_err(ExportError::Io)?;
+    Ok(txs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn settings() -> ExportSettings {
+        ExportSettings {
+            output_dir: String::new(),
+            interval_secs: None,
+            default_account: String::from("Customers, EU"),
+            account_mapping: HashMap::new(),
+            exchange_account: default_exchange_account(),
+            fee_account: default_fee_account(),
+        }
+    }
+
+    fn tx(outbound: &str, outbound_currency: &str, inbound: &str, inbound_currency: &str) -> Transaction {
+        Transaction {
+            txid: String::from("tx"),
+            created_at: 1,
+            outbound_amount: BigDecimal::from_str(outbound).unwrap(),
+            inbound_amount: BigDecimal::from_str(inbound).unwrap(),
+            outbound_account_id: Uuid::nil(),
+            inbound_account_id: Uuid::nil(),
+            outbound_uid: 1,
+            inbound_uid: 2,
+            outbound_currency: outbound_currency.to_string(),
+            inbound_currency: inbound_currency.to_string(),
+            exchange_rate: BigDecimal::from_str("1").unwrap(),
+            tx_type: String::from("Internal"),
+            fees: BigDecimal::zero(),
+        }
+    }
+
+    /// Sums debits minus credits per currency.
+    fn balances(journal: &str) -> HashMap<String, BigDecimal> {
+        let mut balances = HashMap::new();
+        for line in journal.lines().skip(1) {
+            // The account is quoted in these tests, so the fields after it are split from the right.
+            let fields = line.rsplitn(7, ',').collect::<Vec<_>>();
+            let (currency, credit, debit) = (fields[3], fields[4], fields[5]);
+            let amount = |field: &str| BigDecimal::from_str(field).unwrap_or_else(|_| BigDecimal::zero());
+            let balance = balances.entry(currency.to_string()).or_insert_with(BigDecimal::zero);
+            *balance = balance.clone() + amount(debit) - amount(credit);
+        }
+        balances
+    }
+
+    #[test]
+    fn journal_fields_are_quoted_and_legs_balance() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+
+        let txs = [
+            tx("10", "BTC", "10", "BTC"),
+            tx("0.001", "BTC", "20", "USD"),
+            tx("10", "USD", "9.5", "USD"),
+        ];
+        let journal = journal_csv(&settings(), &txs);
+        assert!(journal.contains("\"Customers, EU\""));
+        assert!(journal.contains("Currency Exchange"));
+        assert!(journal.contains(",Fees,,0.5,USD,"));
+        assert_eq!(journal.lines().count(), 1 + 2 + 4 + 3);
+        assert!(balances(&journal).values().all(|balance| balance.is_zero()));
+    }
+
+    #[test]
+    fn last_export_end_is_read_from_journal_names() {
+        let dir = std::env::temp_dir().join(format!("journals_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(last_export_end(dir.to_str().unwrap()), None);
+
+        for name in ["journal_0_100.csv", "journal_100_250.csv", "notes.txt"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        assert_eq!(last_export_end(dir.to_str().unwrap()), Some(250));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+use core_types::{Currency, UserId, SATS_IN_BITCOIN};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use utils::currencies::{MSATS_DECIMALS, SATS_DECIMALS};
+
+const MILLIS_IN_DAY: u64 = 86_400_000;
+
+fn default_volume_window_days() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FeeOperation {
+    /// Transfers to other users of the same currency.
+    Internal,
+    /// Payments leaving the platform.
+    External,
+    /// Swaps between the accounts of a user.
+    Swap,
+    /// Transfers to other users that are converted into another currency.
+    Conversion,
+    /// Channels the bank's node opens to users.
+    ChannelOpen,
+}
+
+impl fmt::Display for FeeOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation = match self {
+            Self::Internal => "Internal",
+            Self::External => "External",
+            Self::Swap => "Swap",
+            Self::Conversion => "Conversion",
+            Self::ChannelOpen => "ChannelOpen",
+        };
+        write!(f, "{}", operation)
+    }
+}
+
+/// Fee charged once the volume of a user reaches `min_volume`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeeTier {
+    /// Volume the user moved out of accounts of the currency within the volume window.
+    #[serde(default)]
+    pub min_volume: Decimal,
+    #[serde(default)]
+    pub flat: Decimal,
+    /// Share of the amount, e.g. 0.001 for 0.1%.
+    #[serde(default)]
+    pub percentage: Decimal,
+}
+
+/// Routing fee lnd may spend on payments up to `max_amount_sats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeBudget {
+    /// Largest payment in sats the budget applies to, all larger ones if not set.
+    #[serde(default)]
+    pub max_amount_sats: Option<u64>,
+    /// Share of the amount, e.g. 0.01 for 1%.
+    pub fee_rate: Decimal,
+    /// Cap of the budget in sats, not capped if not set.
+    #[serde(default)]
+    pub max_fee_sats: Option<u64>,
+}
+
+/// Routing fee budget in BTC of a payment of `amount` BTC. The first budget covering the amount applies,
+/// `default_rate` of the amount if none does.
+pub fn fee_budget(budgets: &[FeeBudget], amount: Decimal, default_rate: Decimal) -> Decimal {
+    let amount_in_sats = amount * SATS_IN_BITCOIN;
+    let budget = budgets.iter().find(|budget| {
+        budget
+            .max_amount_sats
+            .map_or(true, |max_amount| amount_in_sats <= Decimal::from(max_amount))
+    });
+    let fee = match budget {
+        Some(budget) => {
+            let fee = amount * budget.fee_rate;
+            match budget.max_fee_sats {
+                Some(max_fee) => fee.min(Decimal::from(max_fee) / SATS_IN_BITCOIN),
+                None => fee,
+            }
+        }
+        None => amount * default_rate,
+    };
+    fee.round_dp_with_strategy(MSATS_DECIMALS, RoundingStrategy::AwayFromZero)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeScheduleSettings {
+    #[serde(default = "default_volume_window_days")]
+    pub volume_window_days: u64,
+    /// Tiers per currency of the charged account and operation. Operations without tiers are free.
+    #[serde(default)]
+    pub tiers: HashMap<Currency, HashMap<FeeOperation, Vec<FeeTier>>>,
+}
+
+impl Default for FeeScheduleSettings {
+    fn default() -> Self {
+        Self {
+            volume_window_days: default_volume_window_days(),
+            tiers: HashMap::new(),
+        }
+    }
+}
+
+/// Computes the fees of the configured schedule. Tiers are picked by the volume of the user over the
+/// volume window, which is tracked in memory and seeded from the summary transactions at startup.
+#[derive(Debug, Default)]
+pub struct FeeEngine {
+    settings: FeeScheduleSettings,
+    volumes: HashMap<(UserId, Currency), VecDeque<(u64, Decimal)>>,
+}
+
+impl FeeEngine {
+    pub fn new(settings: FeeScheduleSettings) -> Self {
+        Self {
+            settings,
+            volumes: HashMap::new(),
+        }
+    }
+
+    /// Replaces the fee schedule, the recorded volumes are kept.
+    pub fn set_settings(&mut self, settings: FeeScheduleSettings) {
+        self.settings = settings;
+    }
+
+    pub fn volume_window(&self) -> u64 {
+        self.settings.volume_window_days * MILLIS_IN_DAY
+    }
+
+    pub fn record_volume(&mut self, uid: UserId, currency: Currency, amount: Decimal, timestamp: u64) {
+        self.volumes
+            .entry((uid, currency))
+            .or_default()
+            .push_back((timestamp, amount));
+    }
+
+    /// Volume of the user within the volume window before `now`, older volume is dropped.
+    pub fn volume(&mut self, uid: UserId, currency: Currency, now: u64) -> Decimal {
+        let window_start = now.saturating_sub(self.volume_window());
+        let volumes = match self.volumes.get_mut(&(uid, currency)) {
+            Some(volumes) => volumes,
+            None => return Decimal::ZERO,
+        };
+        while matches!(volumes.front(), Some((timestamp, _)) if *timestamp < window_start) {
+            volumes.pop_front();
+        }
+        volumes.iter().map(|(_, amount)| *amount).sum()
+    }
+
+    /// Fee of the operation in the currency of the charged account, zero if no tier applies.
+    pub fn fee(
+        &mut self,
+        uid: UserId,
+        currency: Currency,
+        operation: FeeOperation,
+        amount: Decimal,
+        now: u64,
+    ) -> Decimal {
+        let volume = self.volume(uid, currency, now);
+        let tier = self
+            .settings
+            .tiers
+            .get(&currency)
+            .and_then(|operations| operations.get(&operation))
+            .and_then(|tiers| {
+                tiers
+                    .iter()
+                    .filter(|tier| tier.min_volume <= volume)
+                    .max_by_key(|tier| tier.min_volume)
+            });
+        match tier {
+            Some(tier) => (tier.flat + amount * tier.percentage)
+                .max(Decimal::ZERO)
+                .round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::AwayFromZero),
+            None => Decimal::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn picks_the_tier_of_the_volume() {
+        let mut settings = FeeScheduleSettings::default();
+        settings.tiers.insert(
+            Currency::USD,
+            HashMap::from([(
+                FeeOperation::Internal,
+                vec![
+                    FeeTier {
+                        min_volume: dec!(0),
+                        flat: dec!(0.1),
+                        percentage: dec!(0.01),
+                    },
+                    FeeTier {
+                        min_volume: dec!(1000),
+                        flat: dec!(0),
+                        percentage: dec!(0.001),
+                    },
+                ],
+            )]),
+        );
+        let mut engine = FeeEngine::new(settings);
+        let now = 100 * MILLIS_IN_DAY;
+        let fee = |engine: &mut FeeEngine, currency, operation| engine.fee(1, currency, operation, dec!(100), now);
+
+        assert_eq!(fee(&mut engine, Currency::USD, FeeOperation::Internal), dec!(1.1));
+        assert_eq!(fee(&mut engine, Currency::USD, FeeOperation::External), dec!(0));
+        assert_eq!(fee(&mut engine, Currency::EUR, FeeOperation::Internal), dec!(0));
+
+        engine.record_volume(1, Currency::USD, dec!(1000), now - 31 * MILLIS_IN_DAY);
+        assert_eq!(fee(&mut engine, Currency::USD, FeeOperation::Internal), dec!(1.1));
+
+        engine.record_volume(1, Currency::USD, dec!(1000), now - MILLIS_IN_DAY);
+        assert_eq!(fee(&mut engine, Currency::USD, FeeOperation::Internal), dec!(0.1));
+    }
+
+    #[test]
+    fn picks_the_budget_of_the_payment_size() {
+        let budgets = vec![
+            FeeBudget {
+                max_amount_sats: Some(1_000),
+                fee_rate: dec!(0.03),
+                max_fee_sats: None,
+            },
+            FeeBudget {
+                max_amount_sats: None,
+                fee_rate: dec!(0.005),
+                max_fee_sats: Some(1_000),
+            },
+        ];
+        let sats = |sats: i64| Decimal::new(sats, 0) / SATS_IN_BITCOIN;
+
+        assert_eq!(fee_budget(&budgets, sats(1_000), dec!(0.01)), sats(30));
+        assert_eq!(fee_budget(&budgets, sats(100_000), dec!(0.01)), sats(500));
+        assert_eq!(fee_budget(&budgets, sats(1_000_000), dec!(0.01)), sats(1_000));
+        assert_eq!(fee_budget(&[], sats(1_000), dec!(0.01)), sats(10));
+    }
+}
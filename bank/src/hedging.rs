@@ -0,0 +1,80 @@
+use core_types::{Currency, Money};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use utils::currencies::SATS_DECIMALS;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HedgeRebalanceSettings {
+    pub interval_secs: u64,
+    /// How far the share of the fiat synthetic may drift from the target before it is rebalanced, e.g. 0.05.
+    pub tolerance: Decimal,
+    /// Smallest swap worth doing, valued in the fiat synthetic.
+    pub min_swap_value: Decimal,
+}
+
+/// Swap that brings the share of the fiat balance back to the target ratio, `None` while it is within the
+/// tolerance. The price is the fiat value of one BTC.
+pub fn rebalance_swap(
+    settings: &HedgeRebalanceSettings,
+    currency: Currency,
+    btc_balance: Decimal,
+    fiat_balance: Decimal,
+    price: Decimal,
+    target_ratio: Decimal,
+) -> Option<Money> {
+    if price <= Decimal::ZERO {
+        return None;
+    }
+    let total_value = btc_balance * price + fiat_balance;
+    if total_value <= Decimal::ZERO {
+        return None;
+    }
+
+    let ratio = fiat_balance / total_value;
+    if (ratio - target_ratio).abs() <= settings.tolerance {
+        return None;
+    }
+
+    let excess_fiat = fiat_balance - total_value * target_ratio;
+    if excess_fiat.abs() < settings.min_swap_value {
+        return None;
+    }
+
+    if excess_fiat > Decimal::ZERO {
+        Some(Money::new(currency, Some(excess_fiat.min(fiat_balance))))
+    } else {
+        let btc_amount = (-excess_fiat / price).round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::ToZero);
+        Some(Money::new(Currency::BTC, Some(btc_amount.min(btc_balance))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn swaps_back_to_the_target_ratio() {
+        let settings = HedgeRebalanceSettings {
+            interval_secs: 3600,
+            tolerance: dec!(0.05),
+            min_swap_value: dec!(1),
+        };
+
+        // 70% USD of 1000 USD total, within the tolerance.
+        assert!(rebalance_swap(&settings, Currency::USD, dec!(0.015), dec!(700), dec!(20000), dec!(0.7)).is_none());
+
+        // All in BTC, 700 USD worth of BTC has to be swapped.
+        let swap = rebalance_swap(&settings, Currency::USD, dec!(0.05), dec!(0), dec!(20000), dec!(0.7)).unwrap();
+        assert_eq!(swap.currency, Currency::BTC);
+        assert_eq!(swap.value, dec!(0.035));
+
+        // All in USD, 300 USD have to be swapped back to BTC.
+        let swap = rebalance_swap(&settings, Currency::USD, dec!(0), dec!(1000), dec!(20000), dec!(0.7)).unwrap();
+        assert_eq!(swap.currency, Currency::USD);
+        assert_eq!(swap.value, dec!(300));
+
+        // Too small to be worth a swap.
+        assert!(rebalance_swap(&settings, Currency::USD, dec!(0.00005), dec!(0), dec!(20000), dec!(0.7)).is_none());
+    }
+}
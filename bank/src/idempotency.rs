@@ -0,0 +1,94 @@
+use core_types::*;
+use msgs::api::*;
+use msgs::*;
+
+/// Keys are kept for a day after which they can be reused.
+pub const IDEMPOTENCY_KEY_TTL_MS: u64 = 86_400_000;
+
+/// Returns request id, user and idempotency key of requests that carry a key.
+pub fn request_idempotency_key(msg: &Message) -> Option<(RequestId, UserId, String)> {
+    match msg {
+        Message::Api(Api::PaymentRequest(req)) => req.idempotency_key.clone().map(|key| (req.req_id, req.uid, key)),
+        Message::Api(Api::SwapRequest(req)) => req.idempotency_key.clone().map(|key| (req.req_id, req.uid, key)),
+        Message::Api(Api::InvoiceRequest(req)) => req.idempotency_key.clone().map(|key| (req.req_id, req.uid, key)),
+        _ => None,
+    }
+}
+
+/// Returns the request id of responses to requests that can carry an idempotency key.
+pub fn response_req_id(msg: &Message) -> Option<RequestId> {
+    match msg {
+        Message::Api(Api::PaymentResponse(resp)) => Some(resp.req_id),
+        Message::Api(Api::SwapResponse(resp)) => Some(resp.req_id),
+        Message::Api(Api::InvoiceResponse(resp)) => Some(resp.req_id),
+        _ => None,
+    }
+}
+
+/// Readdresses a stored response to the request id of a retried request.
+pub fn replay_response(msg: Message, req_id: RequestId) -> Option<Message> {
+    match msg {
+        Message::Api(Api::PaymentResponse(mut resp)) => {
+            resp.req_id = req_id;
+            Some(Message::Api(Api::PaymentResponse(resp)))
+        }
+        Message::Api(Api::SwapResponse(mut resp)) => {
+            resp.req_id = req_id;
+            Some(Message::Api(Api::SwapResponse(resp)))
+        }
+        Message::Api(Api::InvoiceResponse(mut resp)) => {
+            resp.req_id = req_id;
+            Some(Message::Api(Api::InvoiceResponse(resp)))
+        }
+        _ => None,
+    }
+}
+
+/// Response to a retried request whose original is still being processed.
+pub fn duplicate_request_response(msg: &Message) -> Option<Message> {
+    match msg {
+        Message::Api(Api::PaymentRequest(req)) => {
+            let payment_response = PaymentResponse::error(
+                PaymentResponseError::DuplicateRequest,
+                req.req_id,
+                req.uid,
+                req.payment_request.clone(),
+                req.currency,
+                None,
+            );
+            Some(Message::Api(Api::PaymentResponse(payment_response)))
+        }
+        Message::Api(Api::SwapRequest(req)) => {
+            let swap_response = SwapResponse {
+                req_id: req.req_id,
+                uid: req.uid,
+                success: false,
+                amount: req.amount.clone(),
+                from: req.from,
+                to: req.to,
+                rate: None,
+                error: Some(SwapResponseError::DuplicateRequest),
+                fees: None,
+            };
+            Some(Message::Api(Api::SwapResponse(swap_response)))
+        }
+        Message::Api(Api::InvoiceRequest(req)) => {
+            let invoice_response = InvoiceResponse {
+                req_id: req.req_id,
+                uid: req.uid,
+                payment_request: None,
+                meta: req.meta.clone(),
+                metadata: req.metadata.clone(),
+                amount: req.amount.clone(),
+                rate: None,
+                currency: req.currency,
+                target_account_currency: req.target_account_currency,
+                account_id: None,
+                error: Some(InvoiceResponseError::DuplicateRequest),
+                fees: None,
+            };
+            Some(Message::Api(Api::InvoiceResponse(invoice_response)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,56 @@
+use core_types::Currency;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utils::currencies::SATS_DECIMALS;
+
+const DAYS_IN_YEAR: f64 = 365.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InterestSettings {
+    /// Annual percentage yield per fiat currency, e.g. 0.03 for 3%. Balances of other currencies don't accrue interest.
+    #[serde(default)]
+    pub apy: HashMap<Currency, Decimal>,
+}
+
+impl InterestSettings {
+    pub fn apy(&self, currency: Currency) -> Option<Decimal> {
+        if currency == Currency::BTC {
+            return None;
+        }
+        self.apy.get(&currency).copied().filter(|apy| *apy > Decimal::ZERO)
+    }
+}
+
+/// Interest accrued on the balance over a day, compounding daily to the annual yield.
+/// Rounded down so no more than the yield is ever paid.
+pub fn daily_interest(balance: Decimal, apy: Decimal) -> Decimal {
+    if balance <= Decimal::ZERO || apy <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    let daily_rate = match apy.to_f64() {
+        Some(apy) => (1.0 + apy).powf(1.0 / DAYS_IN_YEAR) - 1.0,
+        None => return Decimal::ZERO,
+    };
+    match Decimal::from_f64(daily_rate) {
+        Some(daily_rate) => (balance * daily_rate).round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::ToZero),
+        None => Decimal::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn compounds_to_the_annual_yield() {
+        let mut balance = dec!(1000);
+        for _ in 0..365 {
+            balance += daily_interest(balance, dec!(0.05));
+        }
+        assert!(balance > dec!(1049.99) && balance <= dec!(1050));
+        assert_eq!(daily_interest(dec!(-10), dec!(0.05)), dec!(0));
+        assert_eq!(daily_interest(dec!(1000), dec!(0)), dec!(0));
+    }
+}
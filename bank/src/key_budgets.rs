@@ -0,0 +1,108 @@
+use core_types::{Currency, Money, RequestId};
+use msgs::api::{Api, PaymentResponseError};
+use msgs::Message;
+use rust_decimal::prelude::*;
+use utils::currencies::SATS_DECIMALS;
+
+use crate::revenue::MILLIS_IN_DAY;
+
+/// Value of a payment in sats, fiat amounts are valued at the price of one BTC in the currency. `None` if the
+/// value can't be determined, the payment is then refused rather than let past the budget.
+pub fn value_in_sats(amount: &Money, price: Option<Decimal>) -> Option<u64> {
+    let btc_value = if amount.currency == Currency::BTC {
+        amount.value
+    } else {
+        match price {
+            Some(price) if price > Decimal::ZERO => amount.value / price,
+            _ => return None,
+        }
+    };
+    let btc_value = btc_value.round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::AwayFromZero);
+    Money::new(Currency::BTC, Some(btc_value)).try_sats().ok()?.to_u64()
+}
+
+/// Start of the day a budget covers, spends made before it no longer count.
+pub fn window_start(now: u64) -> u64 {
+    now.saturating_sub(MILLIS_IN_DAY)
+}
+
+/// Request id of a payment that was refused or failed for good, what it spent no longer counts against the
+/// budget. Payments that are held, delayed or retried still count, as do the requests a duplicate was refused for.
+pub fn refused_payment(msg: &Message) -> Option<RequestId> {
+    match msg {
+        Message::Api(Api::PaymentResponse(response)) => match response.error.as_ref()? {
+            PaymentResponseError::PendingApproval
+            | PaymentResponseError::WithdrawalDelayed
+            | PaymentResponseError::PaymentRetryScheduled
+            | PaymentResponseError::DuplicateRequest => None,
+            _ => Some(response.req_id),
+        },
+        _ => None,
+    }
+}
+
+/// Whether spending the amount on top of what the key spent over the last day stays within its budget.
+pub fn allows(daily_budget_sats: u64, spent_last_day: u64, amount: u64) -> bool {
+    spent_last_day.saturating_add(amount) <= daily_budget_sats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use msgs::api::PaymentResponse;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    #[test]
+    fn fiat_payments_are_valued_in_sats() {
+        assert_eq!(
+            value_in_sats(&Money::new(Currency::BTC, Some(dec!(0.0001))), None),
+            Some(10_000)
+        );
+        assert_eq!(
+            value_in_sats(&Money::new(Currency::USD, Some(dec!(2))), Some(dec!(20000))),
+            Some(10_000)
+        );
+        // Rounded up so fractions of a sat still count.
+        assert_eq!(
+            value_in_sats(&Money::new(Currency::USD, Some(dec!(1))), Some(dec!(30000))),
+            Some(3_334)
+        );
+        assert_eq!(value_in_sats(&Money::new(Currency::USD, Some(dec!(2))), None), None);
+
+        assert!(allows(100_000, 90_000, 10_000));
+        assert!(!allows(100_000, 90_000, 10_001));
+        assert!(!allows(0, 0, 1));
+    }
+
+    #[test]
+    fn failed_payments_release_their_spend() {
+        let req_id = Uuid::new_v4();
+        let response = |error| PaymentResponse::error(error, req_id, 1, None, Currency::BTC, None);
+        let refused = |error| refused_payment(&Message::Api(Api::PaymentResponse(response(error))));
+
+        assert_eq!(refused(PaymentResponseError::TransactionFailed), Some(req_id));
+        assert_eq!(refused(PaymentResponseError::InsufficientFunds), Some(req_id));
+        assert_eq!(refused(PaymentResponseError::WithdrawalRejected), Some(req_id));
+        assert_eq!(refused(PaymentResponseError::PendingApproval), None);
+        assert_eq!(refused(PaymentResponseError::WithdrawalDelayed), None);
+        assert_eq!(refused(PaymentResponseError::PaymentRetryScheduled), None);
+        assert_eq!(refused(PaymentResponseError::DuplicateRequest), None);
+
+        let mut paid = response(PaymentResponseError::TransactionFailed);
+        paid.error = None;
+        paid.success = true;
+        assert_eq!(refused_payment(&Message::Api(Api::PaymentResponse(paid))), None);
+    }
+
+    #[test]
+    fn spends_stop_counting_after_a_day() {
+        let spent_at = 10 * MILLIS_IN_DAY;
+        let spent_last_day = |now: u64| if spent_at >= window_start(now) { 90_000 } else { 0 };
+
+        assert!(!allows(100_000, spent_last_day(spent_at + MILLIS_IN_DAY - 1), 20_000));
+        assert!(!allows(100_000, spent_last_day(spent_at + MILLIS_IN_DAY), 20_000));
+        assert!(allows(100_000, spent_last_day(spent_at + MILLIS_IN_DAY + 1), 20_000));
+        assert_eq!(window_start(MILLIS_IN_DAY - 1), 0);
+    }
+}
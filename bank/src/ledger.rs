@@ -1,5 +1,8 @@
-use core_types::{Account, AccountClass, AccountId, AccountType, Currency, UserId};
+use core_types::{Account, AccountClass, AccountId, AccountType, Currency, RequestId, UserId};
+use rust_decimal::Decimal;
 use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -11,6 +14,12 @@ pub struct UserAccount {
     pub invoices: Vec<String>,
     pub last_withdrawal_request: std::time::SystemTime,
     pub last_deposit_request: std::time::SystemTime,
+    /// Set by an admin, frozen users can receive deposits but neither withdraw nor swap.
+    #[serde(default)]
+    pub frozen: bool,
+    /// Overrides of the global deposit limits.
+    #[serde(default)]
+    pub deposit_limits: HashMap<Currency, Decimal>,
 }
 
 impl UserAccount {
@@ -21,24 +30,28 @@ impl UserAccount {
             invoices: Vec::new(),
             last_withdrawal_request: std::time::SystemTime::UNIX_EPOCH,
             last_deposit_request: std::time::SystemTime::UNIX_EPOCH,
+            frozen: false,
+            deposit_limits: HashMap::new(),
         }
     }
 
     /// Since users can have multiple accounts of the same currency we need
     /// a getter that returns the first best account if the user does not specify one.
+    /// Archived accounts are never picked and the main, unlabeled account is preferred over sub-accounts.
     pub fn get_default_account(&mut self, currency: Currency, account_type: Option<AccountType>) -> Account {
-        let accounts = self
+        let mut accounts = self
             .accounts
             .clone()
             .into_iter()
             .filter(|(_key, value)| {
                 if let Some(at) = account_type {
-                    value.currency == currency && value.account_type == at
+                    value.currency == currency && value.account_type == at && !value.archived
                 } else {
-                    value.currency == currency
+                    value.currency == currency && !value.archived
                 }
             })
             .collect::<Vec<(Uuid, Account)>>();
+        accounts.sort_by_key(|(_key, value)| value.label.is_some());
 
         if !accounts.is_empty() {
             return accounts[0].1.clone();
@@ -54,6 +67,28 @@ impl UserAccount {
         self.accounts.insert(new_account.account_id, new_account.clone());
         new_account
     }
+
+    /// Returns the requested account if it is a usable account of the currency, otherwise the default account.
+    /// None if an account was requested that can't be used.
+    pub fn get_account_or_default(&mut self, account_id: Option<AccountId>, currency: Currency) -> Option<Account> {
+        match account_id {
+            Some(account_id) => self
+                .accounts
+                .get(&account_id)
+                .filter(|account| account.currency == currency && !account.archived)
+                .cloned(),
+            None => Some(self.get_default_account(currency, None)),
+        }
+    }
+}
+
+/// Funds reserved for, or awaiting, a request that hasn't completed yet.
+#[derive(Debug, Clone)]
+pub struct PendingFunds {
+    pub uid: UserId,
+    pub account_id: AccountId,
+    /// Negative for outgoing funds.
+    pub amount: Decimal,
 }
 
 #[derive(Debug)]
@@ -65,12 +100,22 @@ pub struct Ledger {
     pub insurance_fund_account: Account,
     /// Holds all fees collected by the Bank.
     pub fee_account: UserAccount,
+    /// Holds the funding the dealer's hedges received, interest on fiat balances is paid from it.
+    pub funding_account: UserAccount,
+    /// Holds the realized profit and loss of the dealer's hedges. Negative if hedging cost more than it made.
+    pub pnl_account: UserAccount,
+    /// Holds the costs the operator paid out of the bank's funds, e.g. routing fees of rebalancing channels.
+    pub expense_account: UserAccount,
     // These are the liabilities.
     pub bank_liabilities: UserAccount,
     // The account of the dealer.
     pub dealer_accounts: UserAccount,
     /// The external account is the counterparty for every deposit from an unknown external user.
     pub external_fee_account: Account,
+    /// Unsettled funds by the request they belong to. Reflected in `Account::pending_balance`.
+    pub pending_funds: HashMap<RequestId, PendingFunds>,
+    /// Owners of user accounts by account id, filled as accounts are loaded and updated.
+    pub account_owners: HashMap<AccountId, UserId>,
 }
 
 impl Ledger {
@@ -79,9 +124,464 @@ impl Ledger {
             user_accounts: HashMap::new(),
             insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash),
             fee_account: UserAccount::new(owner),
+            funding_account: UserAccount::new(dealer),
+            pnl_account: UserAccount::new(dealer),
+            expense_account: UserAccount::new(owner),
             bank_liabilities: UserAccount::new(owner),
             dealer_accounts: UserAccount::new(dealer),
             external_fee_account: Account::new(Currency::BTC, AccountType::External, AccountClass::Cash),
+            pending_funds: HashMap::new(),
+            account_owners: HashMap::new(),
+        }
+    }
+
+    /// Remembers the owner of a user account so the account can be found by its id alone.
+    pub fn index_account(&mut self, uid: UserId, account_id: AccountId) {
+        self.account_owners.insert(account_id, uid);
+    }
+
+    /// Returns the owner of an indexed user account together with the account.
+    pub fn account_owner(&self, account_id: AccountId) -> Option<(UserId, Account)> {
+        let uid = *self.account_owners.get(&account_id)?;
+        self.user_accounts
+            .get(&uid)
+            .and_then(|user_account| user_account.accounts.get(&account_id))
+            .map(|account| (uid, account.clone()))
+    }
+
+    /// Records unsettled funds of a request and returns the account with its updated pending balance.
+    pub fn add_pending(&mut self, req_id: RequestId, funds: PendingFunds) -> Option<Account> {
+        let (uid, account_id) = (funds.uid, funds.account_id);
+        self.pending_funds.insert(req_id, funds);
+        self.refresh_pending_balance(uid, account_id)
+    }
+
+    /// Returns the fee income account of the currency, fees of all operations are collected on it.
+    pub fn get_fee_account(&mut self, currency: Currency) -> Account {
+        if let Some(account) = self
+            .fee_account
+            .accounts
+            .values()
+            .find(|account| account.currency == currency)
+        {
+            return account.clone();
+        }
+        let account = Account::new(currency, AccountType::Internal, AccountClass::Fees);
+        self.fee_account.accounts.insert(account.account_id, account.clone());
+        account
+    }
+
+    /// Returns the funding income account of the currency.
+    pub fn get_funding_account(&mut self, currency: Currency) -> Account {
+        if let Some(account) = self
+            .funding_account
+            .accounts
+            .values()
+            .find(|account| account.currency == currency)
+        {
+            return account.clone();
+        }
+        let account = Account::new(currency, AccountType::Internal, AccountClass::Fees);
+        self.funding_account
+            .accounts
+            .insert(account.account_id, account.clone());
+        account
+    }
+
+    /// Returns the hedging profit and loss account of the currency.
+    pub fn get_pnl_account(&mut self, currency: Currency) -> Account {
+        if let Some(account) = self
+            .pnl_account
+            .accounts
+            .values()
+            .find(|account| account.currency == currency)
+        {
+            return account.clone();
+        }
+        let account = Account::new(currency, AccountType::Internal, AccountClass::Pnl);
+        self.pnl_account.accounts.insert(account.account_id, account.clone());
+        account
+    }
+
+    /// Returns the operator expense account of the currency.
+    pub fn get_expense_account(&mut self, currency: Currency) -> Account {
+        if let Some(account) = self
+            .expense_account
+            .accounts
+            .values()
+            .find(|account| account.currency == currency)
+        {
+            return account.clone();
         }
+        let account = Account::new(currency, AccountType::Internal, AccountClass::Expense);
+        self.expense_account
+            .accounts
+            .insert(account.account_id, account.clone());
+        account
+    }
+
+    /// Removes the unsettled funds of a completed request.
+    pub fn release_pending(&mut self, req_id: &RequestId) -> Option<PendingFunds> {
+        let funds = self.pending_funds.remove(req_id)?;
+        self.refresh_pending_balance(funds.uid, funds.account_id);
+        Some(funds)
+    }
+
+    fn refresh_pending_balance(&mut self, uid: UserId, account_id: AccountId) -> Option<Account> {
+        let pending_balance = self
+            .pending_funds
+            .values()
+            .filter(|funds| funds.account_id == account_id)
+            .map(|funds| funds.amount)
+            .sum();
+        let account = self.user_accounts.get_mut(&uid)?.accounts.get_mut(&account_id)?;
+        account.pending_balance = pending_balance;
+        Some(account.clone())
+    }
+}
+
+impl Ledger {
+    /// Applies a single journaled mutation to the ledger.
+    /// Pending balances only live as long as the requests they belong to and are never restored.
+    pub fn apply(&mut self, event: &LedgerEvent) {
+        match event {
+            LedgerEvent::UserAccountUpdated { uid, account } => {
+                let mut account = account.clone();
+                account.pending_balance = Decimal::ZERO;
+                self.index_account(*uid, account.account_id);
+                self.user_accounts
+                    .entry(*uid)
+                    .or_insert_with(|| UserAccount::new(*uid))
+                    .accounts
+                    .insert(account.account_id, account);
+            }
+            LedgerEvent::BankLiabilityUpdated { account } => {
+                self.bank_liabilities
+                    .accounts
+                    .insert(account.account_id, account.clone());
+            }
+            LedgerEvent::DealerAccountUpdated { account } => {
+                self.dealer_accounts
+                    .accounts
+                    .insert(account.account_id, account.clone());
+            }
+            LedgerEvent::InsuranceFundUpdated { account } => {
+                self.insurance_fund_account = account.clone();
+            }
+            LedgerEvent::FeeAccountUpdated { account } => {
+                self.fee_account.accounts.insert(account.account_id, account.clone());
+            }
+            LedgerEvent::FundingAccountUpdated { account } => {
+                self.funding_account
+                    .accounts
+                    .insert(account.account_id, account.clone());
+            }
+            LedgerEvent::PnlAccountUpdated { account } => {
+                self.pnl_account.accounts.insert(account.account_id, account.clone());
+            }
+            LedgerEvent::ExpenseAccountUpdated { account } => {
+                self.expense_account
+                    .accounts
+                    .insert(account.account_id, account.clone());
+            }
+        }
+    }
+
+    /// Rebuilds the ledger by applying all journal entries in sequence order.
+    pub fn replay(owner: UserId, dealer: UserId, entries: &[JournalEntry]) -> Self {
+        let mut ledger = Self::new(owner, dealer);
+        let mut entries = entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.seq);
+        entries.into_iter().for_each(|entry| ledger.apply(&entry.event));
+        ledger
+    }
+
+    /// Returns events that recreate the current state of the ledger when replayed.
+    pub fn snapshot(&self) -> Vec<LedgerEvent> {
+        let mut events = vec![LedgerEvent::InsuranceFundUpdated {
+            account: self.insurance_fund_account.clone(),
+        }];
+        events.extend(
+            self.bank_liabilities
+                .accounts
+                .values()
+                .map(|account| LedgerEvent::BankLiabilityUpdated {
+                    account: account.clone(),
+                }),
+        );
+        events.extend(
+            self.dealer_accounts
+                .accounts
+                .values()
+                .map(|account| LedgerEvent::DealerAccountUpdated {
+                    account: account.clone(),
+                }),
+        );
+        events.extend(
+            self.fee_account
+                .accounts
+                .values()
+                .map(|account| LedgerEvent::FeeAccountUpdated {
+                    account: account.clone(),
+                }),
+        );
+        events.extend(
+            self.funding_account
+                .accounts
+                .values()
+                .map(|account| LedgerEvent::FundingAccountUpdated {
+                    account: account.clone(),
+                }),
+        );
+        events.extend(
+            self.pnl_account
+                .accounts
+                .values()
+                .map(|account| LedgerEvent::PnlAccountUpdated {
+                    account: account.clone(),
+                }),
+        );
+        events.extend(
+            self.expense_account
+                .accounts
+                .values()
+                .map(|account| LedgerEvent::ExpenseAccountUpdated {
+                    account: account.clone(),
+                }),
+        );
+        for (uid, user_account) in self.user_accounts.iter() {
+            events.extend(
+                user_account
+                    .accounts
+                    .values()
+                    .map(|account| LedgerEvent::UserAccountUpdated {
+                        uid: *uid,
+                        account: account.clone(),
+                    }),
+            );
+        }
+        events
+    }
+}
+
+/// A mutation of the ledger. Every event carries the full state of the account after the mutation
+/// so replaying the journal doesn't depend on the transaction logic.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum LedgerEvent {
+    UserAccountUpdated { uid: UserId, account: Account },
+    BankLiabilityUpdated { account: Account },
+    DealerAccountUpdated { account: Account },
+    InsuranceFundUpdated { account: Account },
+    FeeAccountUpdated { account: Account },
+    FundingAccountUpdated { account: Account },
+    PnlAccountUpdated { account: Account },
+    ExpenseAccountUpdated { account: Account },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub event: LedgerEvent,
+}
+
+/// Append-only journal of ledger mutations, stored as one json encoded entry per line.
+/// Entries are synced to disk before the mutation is persisted to the database.
+#[derive(Debug)]
+pub struct LedgerJournal {
+    pub path: String,
+    file: File,
+    next_seq: u64,
+}
+
+impl LedgerJournal {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let next_seq = match read_journal(path) {
+            Ok(entries) => entries.iter().map(|entry| entry.seq + 1).max().unwrap_or(0),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_string(),
+            file,
+            next_seq,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_seq == 0
+    }
+
+    pub fn append(&mut self, event: LedgerEvent) -> std::io::Result<JournalEntry> {
+        let entry = JournalEntry {
+            seq: self.next_seq,
+            timestamp: utils::time::time_now(),
+            event,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.next_seq += 1;
+        Ok(entry)
+    }
+}
+
+/// Reads all entries of a journal. A truncated last line left behind by a crash is skipped.
+pub fn read_journal(path: &str) -> std::io::Result<Vec<JournalEntry>> {
+    let file = File::open(path)?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) if err.is_eof() => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn replay_rebuilds_latest_account_states() {
+        let mut account = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+        let mut dealer_account = Account::new(Currency::USD, AccountType::Internal, AccountClass::Cash);
+
+        let mut entries = Vec::new();
+        account.balance = dec!(1);
+        entries.push(JournalEntry {
+            seq: 0,
+            timestamp: 0,
+            event: LedgerEvent::UserAccountUpdated {
+                uid: 1,
+                account: account.clone(),
+            },
+        });
+        dealer_account.balance = dec!(100);
+        entries.push(JournalEntry {
+            seq: 2,
+            timestamp: 0,
+            event: LedgerEvent::DealerAccountUpdated {
+                account: dealer_account.clone(),
+            },
+        });
+        account.balance = dec!(0.5);
+        entries.push(JournalEntry {
+            seq: 1,
+            timestamp: 0,
+            event: LedgerEvent::UserAccountUpdated {
+                uid: 1,
+                account: account.clone(),
+            },
+        });
+
+        let ledger = Ledger::replay(0, 2, &entries);
+
+        assert_eq!(
+            ledger.user_accounts[&1].accounts[&account.account_id].balance,
+            dec!(0.5)
+        );
+        assert_eq!(
+            ledger.dealer_accounts.accounts[&dealer_account.account_id].balance,
+            dec!(100)
+        );
+    }
+
+    #[test]
+    fn default_account_skips_sub_accounts_and_archived_accounts() {
+        let mut user_account = UserAccount::new(1);
+        let mut savings = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+        savings.label = Some(String::from("savings"));
+        let mut archived = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+        archived.archived = true;
+        user_account.accounts.insert(savings.account_id, savings.clone());
+        user_account.accounts.insert(archived.account_id, archived.clone());
+
+        assert_eq!(
+            user_account.get_default_account(Currency::BTC, None).account_id,
+            savings.account_id
+        );
+
+        let main = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+        user_account.accounts.insert(main.account_id, main.clone());
+
+        assert_eq!(
+            user_account.get_default_account(Currency::BTC, None).account_id,
+            main.account_id
+        );
+    }
+
+    #[test]
+    fn pending_balance_follows_open_requests() {
+        let mut ledger = Ledger::new(0, 2);
+        let mut user_account = UserAccount::new(1);
+        let account = user_account.get_default_account(Currency::BTC, None);
+        ledger.user_accounts.insert(1, user_account);
+
+        let withdrawal = Uuid::new_v4();
+        let deposit = Uuid::new_v4();
+        let funds = |amount| PendingFunds {
+            uid: 1,
+            account_id: account.account_id,
+            amount,
+        };
+
+        ledger.add_pending(withdrawal, funds(dec!(-0.2)));
+        let updated = ledger.add_pending(deposit, funds(dec!(0.5))).unwrap();
+        assert_eq!(updated.pending_balance, dec!(0.3));
+
+        assert!(ledger.release_pending(&withdrawal).is_some());
+        assert!(ledger.release_pending(&withdrawal).is_none());
+        assert_eq!(
+            ledger.user_accounts[&1].accounts[&account.account_id].pending_balance,
+            dec!(0.5)
+        );
+    }
+
+    #[test]
+    fn replayed_accounts_are_found_by_id() {
+        let account = Account::new(Currency::EUR, AccountType::Internal, AccountClass::Cash);
+        let ledger = Ledger::replay(
+            0,
+            2,
+            &[JournalEntry {
+                seq: 0,
+                timestamp: 0,
+                event: LedgerEvent::UserAccountUpdated {
+                    uid: 7,
+                    account: account.clone(),
+                },
+            }],
+        );
+
+        let (owner, found) = ledger.account_owner(account.account_id).unwrap();
+        assert_eq!(owner, 7);
+        assert_eq!(found.account_id, account.account_id);
+        assert!(ledger.account_owner(Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn unindexed_accounts_are_not_found() {
+        let mut ledger = Ledger::new(0, 2);
+        let mut user_account = UserAccount::new(1);
+        let account = user_account.get_default_account(Currency::BTC, None);
+        ledger.user_accounts.insert(1, user_account);
+        assert!(ledger.account_owner(account.account_id).is_none());
+
+        ledger.index_account(1, account.account_id);
+        assert_eq!(ledger.account_owner(account.account_id).unwrap().0, 1);
+
+        // An index entry pointing at the wrong user never yields the account.
+        ledger.index_account(3, account.account_id);
+        assert!(ledger.account_owner(account.account_id).is_none());
     }
 }
@@ -3,9 +3,38 @@ extern crate core;
 pub mod bank_engine;
 pub mod ledger;
 pub mod accountant;
+pub mod admin;
+pub mod api_router;
+pub mod audit;
+pub mod backpressure;
+pub mod content_filter;
+pub mod data_export;
+pub mod db_writer;
+pub mod ecash;
+pub mod exporter;
+pub mod fees;
+pub mod hedging;
+pub mod idempotency;
+pub mod interest;
+pub mod key_budgets;
+pub mod liquid;
+pub mod liquidity_swaps;
+pub mod metrics;
+pub mod preflight;
+pub mod probe_cache;
+pub mod rate_limiter;
+pub mod reserves;
+pub mod revenue;
+pub mod settlement;
+pub mod simulator;
+pub mod statement;
+pub mod statistics;
+pub mod totp;
+pub mod velocity;
 
 use bank_engine::*;
 use futures::prelude::*;
+use std::collections::HashMap;
 use std::time::Instant;
 
 use diesel::{r2d2::ConnectionManager, PgConnection};
@@ -16,6 +45,7 @@ use crossbeam_channel::bounded;
 use msgs::*;
 
 use lnd_connector::connector::*;
+use lnd_connector::pool::LndConnectorPool;
 use rust_decimal::prelude::*;
 use rust_decimal_macros::*;
 
@@ -25,26 +55,23 @@ use influxdb2::Client;
 use accountant::*;
 
 pub async fn insert_bank_state(bank: &BankEngine, client: &Client, bucket: &str) {
-    let mut btc_balance = dec!(0);
-    let mut usd_balance = dec!(0);
-    let mut eur_balance = dec!(0);
-
-    for (_, user_account) in bank.ledger.user_accounts.clone().into_iter() {
-        for (_, account) in user_account.accounts.into_iter() {
-            if account.currency == Currency::BTC {
-                btc_balance += account.balance;
-            } else if account.currency == Currency::EUR {
-                eur_balance += account.balance;
-            } else if account.currency == Currency::USD {
-                usd_balance += account.balance;
-            }
+    let mut user_balances: HashMap<Currency, Decimal> = HashMap::from([
+        (Currency::BTC, dec!(0)),
+        (Currency::EUR, dec!(0)),
+        (Currency::USD, dec!(0)),
+    ]);
+
+    for (_, user_account) in bank.ledger.user_accounts.iter() {
+        for (_, account) in user_account.accounts.iter() {
+            *user_balances.entry(account.currency).or_insert(dec!(0)) += account.balance;
         }
     }
+    let user_balance_fields = user_balances
+        .into_iter()
+        .map(|(currency, balance)| (format!("{}_user_balance", currency.code().to_lowercase()), balance))
+        .collect::<Vec<_>>();
 
     let fields = vec![
-        ("btc_user_balance", btc_balance),
-        ("eur_user_balance", eur_balance),
-        ("usd_user_balance", usd_balance),
         // ("fee_balance", bank.ledger.fee_account.balance),
         ("fee_balance", dec!(0)),
         ("insurance_fund_balance", bank.ledger.insurance_fund_account.balance),
@@ -53,9 +80,26 @@ pub async fn insert_bank_state(bank: &BankEngine, client: &Client, bucket: &str)
         ("internal_tx_fee", bank.internal_tx_fee),
         ("external_tx_fee", bank.external_tx_fee),
         ("external_tx_fee", bank.external_tx_fee),
+        (
+            "reconciliation_drift_accounts",
+            Decimal::from(bank.account_drifts.len()),
+        ),
+        (
+            "reconciliation_max_drift",
+            bank.account_drifts
+                .iter()
+                .map(|drift| drift.max_drift())
+                .max()
+                .unwrap_or(dec!(0)),
+        ),
     ];
 
-    let builder = fields.into_iter().fold(
+    let fields = fields
+        .into_iter()
+        .map(|(field_name, value)| (field_name.to_string(), value))
+        .chain(user_balance_fields);
+
+    let builder = fields.fold(
         influxdb2::models::DataPoint::builder("bank_states"),
         |builder, (field_name, value)| match value.to_f64() {
             Some(converted) => builder.field(field_name, converted),
@@ -84,8 +128,12 @@ pub async fn start(
         .build(ConnectionManager::<PgConnection>::new(settings.psql_url.clone()))
         .expect("Failed to create pool.");
 
-    let lnd_connector = LndConnector::new(lnd_connector_settings.clone()).await;
-    let mut lnd_connector_invoices = LndConnector::new(lnd_connector_settings.clone()).await;
+    let (payment_thread_tx, payment_thread_rx) = crossbeam_channel::bounded(2024);
+
+    let lnd_connector_pool =
+        LndConnectorPool::new(lnd_connector_settings.clone()).with_health_listener(payment_thread_tx.clone());
+    let lnd_connector = lnd_connector_pool.get().await.expect("Failed to connect to lnd");
+    let mut lnd_connector_invoices = lnd_connector.clone();
 
     let influx_client = Client::new(
         settings.influx_host.clone(),
@@ -95,38 +143,76 @@ pub async fn start(
 
     let (invoice_tx, invoice_rx) = bounded(1024);
     let (priority_tx, priority_rx) = bounded(1024);
-
-    let invoice_task = {
-        async move {
-            lnd_connector_invoices.sub_invoices(invoice_tx).await;
-        }
-    };
-
-    tokio::spawn(invoice_task);
-
-    let (payment_thread_tx, payment_thread_rx) = crossbeam_channel::bounded(2024);
+    let (admin_tx, admin_rx) = bounded::<admin::AdminRequest>(64);
 
     let mut bank_engine = BankEngine::new(
         Some(pool),
         lnd_connector,
         settings.clone(),
         lnd_connector_settings,
+        lnd_connector_pool,
         payment_thread_tx,
     )
     .await;
     bank_engine.init_accounts();
+    bank_engine.init_user_controls();
+    bank_engine.init_ledger_journal();
+    bank_engine.init_period_close();
+    bank_engine.init_fee_volumes();
+    bank_engine.init_swap_orders();
+    bank_engine.init_lnurl_withdrawals();
+    bank_engine.recover_pending_payments();
+
+    let (add_index, settle_index) = bank_engine.invoice_checkpoint();
+    let invoice_task = {
+        async move {
+            lnd_connector_invoices
+                .sub_invoices(invoice_tx, add_index, settle_index)
+                .await;
+        }
+    };
+
+    tokio::spawn(invoice_task);
+
+    if let Some(address) = &settings.bank_metrics_address {
+        if let Err(err) = utils::metrics::serve(bank_engine.metrics.registry.clone(), address) {
+            slog::error!(bank_engine.logger, "Failed to serve metrics on {}: {}", address, err);
+        }
+    }
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        if let Err(err) = utils::telemetry::init_tracing("bank", endpoint) {
+            slog::error!(bank_engine.logger, "Failed to export traces to {}: {}", endpoint, err);
+        }
+    }
+    if let Some(admin_api) = settings.admin_api.clone() {
+        let logger = bank_engine.logger.clone();
+        tokio::spawn(async move {
+            if let Err(err) = admin::serve(admin_api, admin_tx).await {
+                slog::error!(logger, "Admin api stopped: {}", err);
+            }
+        });
+    }
+    if let Ok(lnd_node_info) = bank_engine.lnd_connector.get_node_info().await {
+        bank_engine.lnd_node_info = lnd_node_info;
+        bank_engine.store_node_info();
+    }
 
     let mut state_insertion_interval = Instant::now();
     let mut reconciliation_interval = Instant::now();
+    let mut payment_retry_interval = Instant::now();
+    let mut invoice_expiry_interval = Instant::now();
 
     insert_bank_state(&bank_engine, &influx_client, &settings.influx_bucket.clone()).await;
 
+    let api_router = api_router::ApiRouter::default();
+    let mut api_inbox = backpressure::ApiInbox::new(&settings.backpressure);
+
     let mut listener = |msg: Message, destination: ServiceIdentity| match destination {
         ServiceIdentity::Api => {
-            utils::xzmq::send_multipart_as_bincode(&api_sender, &msg);
+            utils::xzmq::send_multipart_message(&api_sender, &api_router.topic(&msg), &msg);
         }
         ServiceIdentity::Dealer => {
-            utils::xzmq::send_as_bincode(&dealer_sender, &msg);
+            utils::xzmq::send_message(&dealer_sender, &msg);
         }
         ServiceIdentity::Loopback => {
             if let Err(err) = priority_tx.send(msg) {
@@ -145,22 +231,52 @@ pub async fn start(
             bank_engine.process_msg(msg, &mut listener).await;
         }
         // Receiving msgs from the api.
-        if let Ok(frame) = api_recv.recv_msg(1) {
-            if let Ok(message) = bincode::deserialize::<Message>(&frame) {
-                bank_engine.process_msg(message, &mut listener).await;
+        // Messages are read ahead into the inbox so requests can be shed while too many are waiting.
+        let mut api_reads = 0;
+        while api_inbox.has_room() && api_reads < backpressure::API_READS_PER_ITERATION {
+            let mut frames = match api_recv.recv_multipart(1) {
+                Ok(frames) => frames,
+                Err(_) => break,
             };
+            api_reads += 1;
+            // Api instances send their id ahead of the message, older ones only send the message.
+            let frame = frames.pop().unwrap_or_default();
+            let instance_id = frames.pop().map(|id| String::from_utf8_lossy(&id).to_string());
+            match msgs::wire::decode(&frame) {
+                Ok(message) => {
+                    if let Some(instance_id) = &instance_id {
+                        api_router.record(&message, instance_id, utils::time::time_now());
+                    }
+                    if let Err(response) = api_inbox.push(message) {
+                        bank_engine.metrics.shed_requests.inc();
+                        listener(response, ServiceIdentity::Api);
+                    }
+                }
+                Err(err) => bank_engine.store_dead_letter("api", &err.to_string(), frame),
+            }
+        }
+        if let Some(message) = api_inbox.pop() {
+            bank_engine.process_msg(message, &mut listener).await;
         }
 
         // Receiving msgs from the invoice subscribtion.
         if let Ok(msg) = invoice_rx.try_recv() {
+            let checkpoint = match &msg {
+                Message::Deposit(deposit) => Some((deposit.add_index, deposit.settle_index)),
+                _ => None,
+            };
             bank_engine.process_msg(msg, &mut listener).await;
+            if let Some((add_index, settle_index)) = checkpoint {
+                bank_engine.store_invoice_checkpoint(add_index, settle_index);
+            }
         }
 
         // Receiving msgs from dealer.
         if let Ok(frame) = dealer_recv.recv_msg(1) {
-            if let Ok(message) = bincode::deserialize::<Message>(&frame) {
-                bank_engine.process_msg(message, &mut listener).await;
-            };
+            match msgs::wire::decode(&frame) {
+                Ok(message) => bank_engine.process_msg(message, &mut listener).await,
+                Err(err) => bank_engine.store_dead_letter("dealer", &err.to_string(), frame.to_vec()),
+            }
         }
 
         if let Ok(msg) = priority_rx.try_recv() {
@@ -168,9 +284,29 @@ pub async fn start(
         }
 
         if let Ok(frame) = cli_socket.recv_msg(1) {
-            if let Ok(message) = bincode::deserialize::<Message>(&frame) {
-                bank_engine.process_msg(message, &mut cli_listener).await;
+            match msgs::wire::decode(&frame) {
+                Ok(message) => bank_engine.process_msg(message, &mut cli_listener).await,
+                Err(err) => bank_engine.store_dead_letter("cli", &err.to_string(), frame.to_vec()),
+            }
+        }
+
+        if let Some(msg) = bank_engine.replayed_messages.pop_front() {
+            bank_engine.process_msg(msg, &mut listener).await;
+        }
+
+        if let Ok(request) = admin_rx.try_recv() {
+            let mut response = None;
+            let mut admin_listener = |msg: Message, destination: ServiceIdentity| {
+                if response.is_none() && admin::is_response(&msg) {
+                    response = Some(msg);
+                } else {
+                    listener(msg, destination);
+                }
             };
+            bank_engine.process_msg(request.message, &mut admin_listener).await;
+            if let Some(response) = response {
+                let _ = request.response_tx.send(response);
+            }
         }
 
         if state_insertion_interval.elapsed().as_secs() > 5 {
@@ -184,8 +320,60 @@ pub async fn start(
                 .filter(|t| t.is_finished())
                 .collect::<FuturesUnordered<tokio::task::JoinHandle<()>>>();
 
+            bank_engine
+                .metrics
+                .queue_depth("payments")
+                .set(payment_thread_rx.len() as f64);
+            bank_engine.metrics.queue_depth("invoices").set(invoice_rx.len() as f64);
+            bank_engine.metrics.queue_depth("api").set(api_inbox.len() as f64);
+            if let Some(db_writer) = &bank_engine.db_writer {
+                bank_engine
+                    .metrics
+                    .queue_depth("db_writes")
+                    .set(db_writer.pending() as f64);
+            }
+            bank_engine
+                .metrics
+                .queue_depth("priority")
+                .set(priority_rx.len() as f64);
+
+            api_router.expire(utils::time::time_now());
+            bank_engine.run_scheduled_export();
+            bank_engine.check_dealer_health_timeout();
+            bank_engine.refresh_inbound_capacity().await;
+            bank_engine.refresh_outbound_capacity().await;
+            bank_engine.manage_liquidity_swaps().await;
+            bank_engine.check_liquid_deposits().await;
         }
 
+        if payment_retry_interval.elapsed().as_secs() > 1 {
+            payment_retry_interval = Instant::now();
+            bank_engine.process_payment_retries();
+            bank_engine.flush_settlements(&mut listener).await;
+            bank_engine.process_reviewed_withdrawals(&mut listener);
+            bank_engine.retry_deposits(&mut listener).await;
+        }
+
+        if invoice_expiry_interval.elapsed().as_secs() > 60 {
+            invoice_expiry_interval = Instant::now();
+            bank_engine.expire_invoices(&mut listener);
+            bank_engine.expire_idempotency_keys();
+            bank_engine.expire_lnurl_withdrawals();
+            bank_engine.expire_data_exports();
+            bank_engine.hash_invoice_descriptions();
+            bank_engine.aggregate_operator_revenue();
+            bank_engine.aggregate_flow_statistics();
+            bank_engine.accrue_interest();
+            bank_engine.sweep_dust(&mut listener);
+            bank_engine.rebalance_hedges(&mut listener);
+            bank_engine.release_vault_withdrawals(&mut listener);
+            bank_engine.prune_rate_limit_buckets();
+        }
+
+        bank_engine.flush_account_writes();
+        bank_engine.check_db_writes();
+        bank_engine.reconcile_with_database().await;
+
         if reconciliation_interval.elapsed().as_secs() > 3 {
             reconciliation_interval = Instant::now();
             if let Err(error) = reconcile_ledger(&bank_engine.ledger) {
@@ -0,0 +1,214 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Requests to elementsd taking longer than this fail.
+const ELEMENTS_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+/// The wallet is checked for deposits this often.
+pub const LIQUID_DEPOSIT_CHECK_INTERVAL_MS: u64 = 60_000;
+const SATS_IN_BITCOIN: u64 = 100_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidSettings {
+    /// RPC url of elementsd, e.g. `http://localhost:7041`.
+    pub url: String,
+    pub rpc_user: String,
+    pub rpc_password: String,
+    /// Wallet of elementsd the bank's L-BTC is held in, the default wallet if not set.
+    pub wallet: Option<String>,
+    /// Deposits are credited once they have this many confirmations.
+    pub min_confirmations: u32,
+    /// Charged on every withdrawal for the on-chain fee.
+    pub withdrawal_fee_sats: u64,
+}
+
+/// An output paid to the bank's wallet.
+#[derive(Debug, Clone)]
+pub struct LiquidReceipt {
+    pub txid: String,
+    pub vout: u32,
+    pub address: String,
+    pub amount_sats: u64,
+    pub confirmations: u32,
+}
+
+pub fn btc_to_sats(amount: Decimal) -> Option<u64> {
+    (amount * Decimal::from(SATS_IN_BITCOIN)).round().to_u64()
+}
+
+pub fn sats_to_btc(sats: u64) -> Decimal {
+    Decimal::new(sats as i64, 8)
+}
+
+/// Talks to the JSON-RPC interface of elementsd.
+pub struct ElementsClient {
+    settings: LiquidSettings,
+}
+
+impl ElementsClient {
+    pub fn new(settings: LiquidSettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn settings(&self) -> &LiquidSettings {
+        &self.settings
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let url = match &self.settings.wallet {
+            Some(wallet) => format!("{}/wallet/{}", self.settings.url.trim_end_matches('/'), wallet),
+            None => self.settings.url.clone(),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(ELEMENTS_RPC_TIMEOUT)
+            .build()
+            .map_err(|err| format!("Failed to build http client: {}", err))?;
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "lndhubx",
+            "method": method,
+            "params": params,
+        });
+        let mut response = client
+            .post(&url)
+            .basic_auth(&self.settings.rpc_user, Some(&self.settings.rpc_password))
+            .json(&body)
+            .send()
+            .map_err(|err| format!("elementsd unreachable: {}", err))?;
+        // Errors come back with a 500 and the error in the body.
+        let response = response
+            .json::<Value>()
+            .map_err(|err| format!("Invalid response: {}", err))?;
+        if !response["error"].is_null() {
+            return Err(format!("{} failed: {}", method, response["error"]));
+        }
+        Ok(response["result"].clone())
+    }
+
+    fn as_string(value: Value, method: &str) -> Result<String, String> {
+        value
+            .as_str()
+            .map(|value| value.to_string())
+            .ok_or_else(|| format!("Unexpected result of {}: {}", method, value))
+    }
+
+    pub fn new_address(&self) -> Result<String, String> {
+        Self::as_string(self.call("getnewaddress", json!([]))?, "getnewaddress")
+    }
+
+    /// Bitcoin address a peg-in is paid to and the script it is claimed with.
+    pub fn pegin_address(&self) -> Result<(String, String), String> {
+        let result = self.call("getpeginaddress", json!([]))?;
+        match (result["mainchain_address"].as_str(), result["claim_script"].as_str()) {
+            (Some(address), Some(claim_script)) => Ok((address.to_string(), claim_script.to_string())),
+            _ => Err(format!("Unexpected result of getpeginaddress: {}", result)),
+        }
+    }
+
+    /// Claims a peg-in once its Bitcoin transaction is deep enough and returns the txid of the claim.
+    pub fn claim_pegin(&self, bitcoin_tx: &str, txout_proof: &str, claim_script: &str) -> Result<String, String> {
+        Self::as_string(
+            self.call("claimpegin", json!([bitcoin_tx, txout_proof, claim_script]))?,
+            "claimpegin",
+        )
+    }
+
+    /// Sats the wallet received in a transaction.
+    pub fn received_sats(&self, txid: &str) -> Result<u64, String> {
+        let result = self.call("gettransaction", json!([txid]))?;
+        // Elements reports amounts per asset label.
+        let amount = result["amount"]["bitcoin"]
+            .as_f64()
+            .or_else(|| result["amount"].as_f64())
+            .ok_or_else(|| format!("Unexpected result of gettransaction: {}", result))?;
+        Decimal::from_f64(amount)
+            .and_then(btc_to_sats)
+            .ok_or_else(|| format!("Invalid amount {}", amount))
+    }
+
+    /// Sends L-BTC to a Liquid address.
+    pub fn send_to_address(&self, address: &str, amount_sats: u64) -> Result<String, String> {
+        Self::as_string(
+            self.call("sendtoaddress", json!([address, sats_to_btc(amount_sats).to_string()]))?,
+            "sendtoaddress",
+        )
+    }
+
+    /// Pegs L-BTC out to a Bitcoin address.
+    pub fn send_to_mainchain(&self, address: &str, amount_sats: u64) -> Result<String, String> {
+        Self::as_string(
+            self.call(
+                "sendtomainchain",
+                json!([address, sats_to_btc(amount_sats).to_string()]),
+            )?,
+            "sendtomainchain",
+        )
+    }
+
+    /// L-BTC received since `block`, the whole history if not set, and the block to continue from next time.
+    /// Receipts with fewer than `min_confirmations` are returned again on the next call.
+    pub fn received_since(&self, block: Option<&str>) -> Result<(Vec<LiquidReceipt>, String), String> {
+        let result = self.call(
+            "listsinceblock",
+            json!([block.unwrap_or(""), self.settings.min_confirmations.max(1)]),
+        )?;
+        let receipts = result["transactions"]
+            .as_array()
+            .map(|transactions| transactions.iter().filter_map(Self::receipt).collect())
+            .unwrap_or_default();
+        let last_block = Self::as_string(result["lastblock"].clone(), "listsinceblock")?;
+        Ok((receipts, last_block))
+    }
+
+    fn receipt(transaction: &Value) -> Option<LiquidReceipt> {
+        if transaction["category"].as_str() != Some("receive") {
+            return None;
+        }
+        // Only L-BTC is credited, other assets paid to the wallet are left to the operator.
+        if transaction["assetlabel"]
+            .as_str()
+            .map_or(false, |label| label != "bitcoin")
+        {
+            return None;
+        }
+        Some(LiquidReceipt {
+            txid: transaction["txid"].as_str()?.to_string(),
+            vout: transaction["vout"].as_u64()? as u32,
+            address: transaction["address"].as_str()?.to_string(),
+            amount_sats: Decimal::from_f64(transaction["amount"].as_f64()?).and_then(btc_to_sats)?,
+            confirmations: transaction["confirmations"].as_i64().unwrap_or(0).max(0) as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receipt() {
+        let transaction = json!({
+            "category": "receive",
+            "address": "lq1qqexample",
+            "amount": 0.0125,
+            "assetlabel": "bitcoin",
+            "txid": "ab",
+            "vout": 1,
+            "confirmations": 2,
+        });
+        let receipt = ElementsClient::receipt(&transaction).unwrap();
+        assert_eq!(receipt.amount_sats, 1_250_000);
+        assert_eq!(receipt.vout, 1);
+
+        let other_asset = json!({
+            "category": "receive",
+            "address": "lq1qqexample",
+            "amount": 100.0,
+            "assetlabel": "USDt",
+            "txid": "ab",
+            "vout": 0,
+        });
+        assert!(ElementsClient::receipt(&other_asset).is_none());
+    }
+}
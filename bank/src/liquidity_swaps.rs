@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Requests to the swap daemon taking longer than this fail.
+const SWAP_PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Liquidity is checked and swaps in flight are polled this often.
+pub const LIQUIDITY_SWAP_CHECK_INTERVAL_MS: u64 = 300_000;
+/// Confirmations the on-chain side of a swap out is swept with.
+const SWEEP_CONF_TARGET: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwapProviderKind {
+    /// Lightning Labs' loopd.
+    Loop,
+    /// The boltz-client daemon boltzd.
+    Boltz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwapDirection {
+    /// Channel funds are swapped to on-chain funds, the node gains inbound liquidity.
+    Out,
+    /// On-chain funds of the node's wallet are swapped into its channels, the node gains outbound liquidity.
+    In,
+}
+
+impl fmt::Display for SwapDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self {
+            Self::Out => "Out",
+            Self::In => "In",
+        };
+        write!(f, "{}", direction)
+    }
+}
+
+impl FromStr for SwapDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Out" => Ok(Self::Out),
+            "In" => Ok(Self::In),
+            _ => Err(format!("Unknown swap direction {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapState {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl fmt::Display for SwapState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            Self::Pending => "Pending",
+            Self::Succeeded => "Succeeded",
+            Self::Failed => "Failed",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SwapStatus {
+    pub state: SwapState,
+    /// Server, on-chain and routing costs together, failed swaps may have cost something too.
+    pub cost_sats: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquiditySwapSettings {
+    pub provider: SwapProviderKind,
+    /// REST url of the swap daemon, e.g. `https://localhost:8081`.
+    pub url: String,
+    /// Hex encoded macaroon the daemon is authenticated with.
+    pub macaroon: Option<String>,
+    /// A swap out is started once the inbound liquidity of the node drops below this many sats.
+    pub min_inbound_sats: Option<u64>,
+    /// A swap in is started once the outbound liquidity of the node drops below this many sats.
+    pub min_outbound_sats: Option<u64>,
+    pub swap_amount_sats: u64,
+    /// Loop refuses swaps that would cost more. Boltz swaps are bounded by the fees configured in boltzd.
+    pub max_cost_sats: u64,
+}
+
+/// Direction of the swap the liquidity of the node calls for, if any. A swap out needs the outbound liquidity
+/// to pay the swap amount.
+pub fn swap_needed(settings: &LiquiditySwapSettings, inbound_sats: u64, outbound_sats: u64) -> Option<SwapDirection> {
+    if let Some(min_inbound) = settings.min_inbound_sats {
+        if inbound_sats < min_inbound && outbound_sats >= settings.swap_amount_sats {
+            return Some(SwapDirection::Out);
+        }
+    }
+    if let Some(min_outbound) = settings.min_outbound_sats {
+        if outbound_sats < min_outbound {
+            return Some(SwapDirection::In);
+        }
+    }
+    None
+}
+
+/// Swaps funds between the node's channels and the chain, e.g. a submarine swap service.
+pub trait SwapProvider: Send {
+    fn name(&self) -> &'static str;
+    /// Starts a swap and returns its id at the provider.
+    fn start(&self, direction: SwapDirection, amount_sats: u64, max_cost_sats: u64) -> Result<String, String>;
+    fn status(&self, swap_id: &str) -> Result<SwapStatus, String>;
+}
+
+pub fn swap_provider(settings: &LiquiditySwapSettings) -> Box<dyn SwapProvider> {
+    let client = SwapDaemonClient {
+        url: settings.url.trim_end_matches('/').to_string(),
+        macaroon: settings.macaroon.clone(),
+    };
+    match settings.provider {
+        SwapProviderKind::Loop => Box::new(LoopProvider { client }),
+        SwapProviderKind::Boltz => Box::new(BoltzProvider { client }),
+    }
+}
+
+/// Talks to the REST gateway of a swap daemon, loopd and boltzd both authenticate with a macaroon header.
+struct SwapDaemonClient {
+    url: String,
+    macaroon: Option<String>,
+}
+
+impl SwapDaemonClient {
+    fn client(&self) -> Result<reqwest::Client, String> {
+        reqwest::Client::builder()
+            .timeout(SWAP_PROVIDER_TIMEOUT)
+            .build()
+            .map_err(|err| format!("Failed to build http client: {}", err))
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<Value, String> {
+        let mut request = self.client()?.post(&format!("{}{}", self.url, path)).json(&body);
+        if let Some(macaroon) = &self.macaroon {
+            request = request.header("Grpc-Metadata-macaroon", macaroon.as_str());
+        }
+        Self::read(request.send())
+    }
+
+    fn get(&self, path: &str) -> Result<Value, String> {
+        let mut request = self.client()?.get(&format!("{}{}", self.url, path));
+        if let Some(macaroon) = &self.macaroon {
+            request = request.header("Grpc-Metadata-macaroon", macaroon.as_str());
+        }
+        Self::read(request.send())
+    }
+
+    fn read(response: Result<reqwest::Response, reqwest::Error>) -> Result<Value, String> {
+        match response {
+            Ok(mut response) if response.status().is_success() => response
+                .json::<Value>()
+                .map_err(|err| format!("Invalid response: {}", err)),
+            Ok(mut response) => Err(format!(
+                "Swap daemon answered {}: {}",
+                response.status(),
+                response.text().unwrap_or_default()
+            )),
+            Err(err) => Err(format!("Swap daemon unreachable: {}", err)),
+        }
+    }
+}
+
+/// The gateways encode 64 bit integers as strings.
+fn as_u64(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|value| value.parse().ok()))
+}
+
+fn swap_id(response: &Value) -> Result<String, String> {
+    response["id"]
+        .as_str()
+        .map(|id| id.to_string())
+        .ok_or_else(|| format!("Swap daemon returned no swap id: {}", response))
+}
+
+struct LoopProvider {
+    client: SwapDaemonClient,
+}
+
+impl SwapProvider for LoopProvider {
+    fn name(&self) -> &'static str {
+        "Loop"
+    }
+
+    fn start(&self, direction: SwapDirection, amount_sats: u64, max_cost_sats: u64) -> Result<String, String> {
+        let max_cost = max_cost_sats.to_string();
+        let response = match direction {
+            SwapDirection::Out => self.client.post(
+                "/v1/loop/out",
+                json!({
+                    "amt": amount_sats.to_string(),
+                    "max_swap_fee": max_cost,
+                    "max_prepay_amt": max_cost,
+                    "max_miner_fee": max_cost,
+                    "max_swap_routing_fee": max_cost,
+                    "max_prepay_routing_fee": max_cost,
+                    "sweep_conf_target": SWEEP_CONF_TARGET,
+                }),
+            )?,
+            SwapDirection::In => self.client.post(
+                "/v1/loop/in",
+                json!({
+                    "amt": amount_sats.to_string(),
+                    "max_swap_fee": max_cost,
+                    "max_miner_fee": max_cost,
+                }),
+            )?,
+        };
+        swap_id(&response)
+    }
+
+    fn status(&self, swap_id: &str) -> Result<SwapStatus, String> {
+        let response = self.client.get(&format!("/v1/loop/swap/{}", swap_id))?;
+        let state = match response["state"].as_str() {
+            Some("SUCCESS") => SwapState::Succeeded,
+            Some("FAILED") => SwapState::Failed,
+            _ => SwapState::Pending,
+        };
+        let cost_sats = ["cost_server", "cost_onchain", "cost_offchain"]
+            .iter()
+            .map(|field| as_u64(&response[*field]))
+            .sum::<Option<u64>>();
+        Ok(SwapStatus { state, cost_sats })
+    }
+}
+
+struct BoltzProvider {
+    client: SwapDaemonClient,
+}
+
+impl SwapProvider for BoltzProvider {
+    fn name(&self) -> &'static str {
+        "Boltz"
+    }
+
+    fn start(&self, direction: SwapDirection, amount_sats: u64, _max_cost_sats: u64) -> Result<String, String> {
+        let response = match direction {
+            SwapDirection::Out => self.client.post(
+                "/v1/createreverseswap",
+                json!({
+                    "amount": amount_sats.to_string(),
+                    "accept_zero_conf": false,
+                }),
+            )?,
+            SwapDirection::In => self.client.post(
+                "/v1/createswap",
+                json!({
+                    "amount": amount_sats.to_string(),
+                    "send_from_internal": true,
+                }),
+            )?,
+        };
+        swap_id(&response)
+    }
+
+    fn status(&self, swap_id: &str) -> Result<SwapStatus, String> {
+        let response = self.client.get(&format!("/v1/swap/{}", swap_id))?;
+        let swap = if response["reverse_swap"].is_object() {
+            &response["reverse_swap"]
+        } else {
+            &response["swap"]
+        };
+        let state = match swap["state"].as_str() {
+            Some("SUCCESSFUL") => SwapState::Succeeded,
+            Some("ERROR") | Some("SERVER_ERROR") | Some("REFUNDED") | Some("ABANDONED") => SwapState::Failed,
+            _ => SwapState::Pending,
+        };
+        let routing_fee_sats = as_u64(&swap["routing_fee_msat"]).unwrap_or(0) / 1000;
+        let cost_sats = match (as_u64(&swap["service_fee"]), as_u64(&swap["onchain_fee"])) {
+            (Some(service_fee), Some(onchain_fee)) => Some(service_fee + onchain_fee + routing_fee_sats),
+            _ => None,
+        };
+        Ok(SwapStatus { state, cost_sats })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_needed() {
+        let settings = LiquiditySwapSettings {
+            provider: SwapProviderKind::Loop,
+            url: String::from("https://localhost:8081"),
+            macaroon: None,
+            min_inbound_sats: Some(1_000_000),
+            min_outbound_sats: Some(500_000),
+            swap_amount_sats: 2_000_000,
+            max_cost_sats: 10_000,
+        };
+        assert_eq!(swap_needed(&settings, 500_000, 5_000_000), Some(SwapDirection::Out));
+        // Not enough outbound liquidity to pay a swap out.
+        assert_eq!(swap_needed(&settings, 500_000, 1_000_000), None);
+        assert_eq!(swap_needed(&settings, 5_000_000, 100_000), Some(SwapDirection::In));
+        assert_eq!(swap_needed(&settings, 5_000_000, 5_000_000), None);
+    }
+}
@@ -1,6 +1,3 @@
-pub mod bank_engine;
-pub mod ledger;
-
 use utils::xzmq::SocketContext;
 
 use bank::{bank_engine::*, start};
@@ -8,11 +5,16 @@ use lnd_connector::connector::LndConnectorSettings;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    utils::config::register_currencies_from_env().expect("Failed to register currencies.");
     let settings = utils::config::get_config_from_env::<BankEngineSettings>().expect("Failed to load settings.");
     let lnd_connector_settings =
         utils::config::get_config_from_env::<LndConnectorSettings>().expect("Failed to load settings.");
 
-    let context = SocketContext::new();
+    bank::preflight::run(&settings, &lnd_connector_settings)
+        .await
+        .print_or_exit();
+
+    let context = SocketContext::new().with_high_water_mark(settings.backpressure.zmq_high_water_mark);
     let api_rx = context.create_pull(&settings.bank_zmq_pull_address);
     let api_tx = context.create_publisher(&settings.bank_zmq_publish_address);
 
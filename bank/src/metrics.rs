@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use utils::metrics::*;
+
+/// Metrics of the bank served to Prometheus, next to the state written to influx.
+pub struct BankMetrics {
+    pub registry: Arc<Registry>,
+    pub payments_attempted: Arc<Counter>,
+    pub payments_succeeded: Arc<Counter>,
+    pub payments_failed: Arc<Counter>,
+    pub payment_latency: Arc<Histogram>,
+    pub invoices_created: Arc<Counter>,
+    pub shed_requests: Arc<Counter>,
+    pub drifting_accounts: Arc<Gauge>,
+    pub max_ledger_drift: Arc<Gauge>,
+}
+
+impl BankMetrics {
+    pub fn new() -> Self {
+        let registry = Arc::new(Registry::new());
+        Self {
+            payments_attempted: registry.counter(
+                "bank_payments_attempted_total",
+                "Attempts to pay an invoice, retries included.",
+            ),
+            payments_succeeded: registry.counter("bank_payments_succeeded_total", "Attempts that paid the invoice."),
+            payments_failed: registry.counter("bank_payments_failed_total", "Attempts that failed."),
+            payment_latency: registry.histogram(
+                "bank_payment_latency_seconds",
+                "Time from the first attempt of a payment to its final result.",
+                &PAYMENT_LATENCY_BUCKETS,
+            ),
+            invoices_created: registry.counter("bank_invoices_created_total", "Invoices created at the node."),
+            shed_requests: registry.counter(
+                "bank_shed_requests_total",
+                "Requests answered with ServiceOverloaded because too many were waiting.",
+            ),
+            drifting_accounts: registry.gauge(
+                "bank_drifting_accounts",
+                "Accounts whose ledger balance differs from the database.",
+            ),
+            max_ledger_drift: registry.gauge(
+                "bank_max_ledger_drift",
+                "Largest difference between a ledger balance and the database.",
+            ),
+            registry,
+        }
+    }
+
+    /// Messages waiting in one of the queues the bank's loop reads from.
+    pub fn queue_depth(&self, queue: &str) -> Arc<Gauge> {
+        self.registry.labeled_gauge(
+            "bank_queue_depth",
+            "Messages waiting to be processed by the bank.",
+            &[("queue", queue)],
+        )
+    }
+}
+
+impl Default for BankMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,98 @@
+use crate::bank_engine::BankEngineSettings;
+
+use core_types::Currency;
+use diesel::{Connection, PgConnection};
+use lnd_connector::connector::{check_node, LndConnectorSettings};
+use models::accounts::Account;
+use rust_decimal_macros::dec;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use utils::preflight::{check_bindable, PreflightReport};
+
+/// Problems with the settings that would otherwise only surface as a panic once the bank is running.
+/// `held_currencies` are the currencies of the accounts in the database.
+pub fn check_settings(settings: &BankEngineSettings, held_currencies: &[String]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let mut limited = Vec::new();
+    for (currency, limit) in settings.deposit_limits.iter() {
+        match Currency::from_str(currency) {
+            Ok(currency) => limited.push(currency),
+            Err(_) => problems.push(format!("deposit_limits has an unknown currency {}", currency)),
+        }
+        if *limit < dec!(0) {
+            problems.push(format!("the deposit limit of {} is negative", currency));
+        }
+    }
+
+    let held_currencies = held_currencies
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once("BTC"))
+        .collect::<BTreeSet<_>>();
+    for held in held_currencies {
+        match Currency::from_str(held) {
+            Ok(currency) if !limited.contains(&currency) => problems.push(format!(
+                "{} is enabled but has no deposit limit, add it to deposit_limits",
+                currency
+            )),
+            Ok(_) => {}
+            Err(_) => problems.push(format!(
+                "accounts in the database are held in an unknown currency {}",
+                held
+            )),
+        }
+    }
+
+    if settings.reserve_ratio < dec!(0) || settings.reserve_ratio > dec!(1) {
+        problems.push(format!(
+            "reserve_ratio {} is not between 0 and 1",
+            settings.reserve_ratio
+        ));
+    }
+    if settings.ln_network_fee_margin < dec!(0) || settings.ln_network_max_fee < dec!(0) {
+        problems.push(String::from(
+            "ln_network_fee_margin and ln_network_max_fee can't be negative",
+        ));
+    }
+    if settings.ln_fee_budgets.iter().any(|budget| budget.fee_rate < dec!(0)) {
+        problems.push(String::from("fee_rate of ln_fee_budgets can't be negative"));
+    }
+    problems
+}
+
+/// Checks the database, lnd, the zmq endpoints the bank binds and the settings before anything is started.
+pub async fn run(settings: &BankEngineSettings, lnd_connector_settings: &LndConnectorSettings) -> PreflightReport {
+    let mut report = PreflightReport::new("bank");
+
+    for (name, outcome) in models::check_database(&settings.psql_url) {
+        report.add(name, outcome);
+    }
+
+    for node in lnd_connector_settings.nodes() {
+        report.add(&format!("lnd {}:{}", node.host, node.port), check_node(&node).await);
+    }
+
+    for address in [
+        &settings.bank_zmq_pull_address,
+        &settings.bank_zmq_publish_address,
+        &settings.bank_dealer_pull_address,
+        &settings.bank_cli_resp_address,
+    ] {
+        report.add(&format!("zmq {}", address), check_bindable(address));
+    }
+
+    let held_currencies = PgConnection::establish(&settings.psql_url)
+        .ok()
+        .and_then(|conn| Account::get_currencies(&conn).ok())
+        .unwrap_or_default();
+    let problems = check_settings(settings, &held_currencies);
+    if problems.is_empty() {
+        report.add("settings", Ok(String::from("look sane")));
+    }
+    for problem in problems {
+        report.add("settings", Err(problem));
+    }
+
+    report
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+/// Probed routes are reused for this long.
+pub const PROBE_TTL_MS: u64 = 60_000;
+
+/// Payment hash of the invoice, or the destination of a keysend payment, and the amount in msat.
+pub type ProbeKey = (String, u64);
+
+/// Fees of the best routes found by probing, so a route quoted to a user isn't probed again when the
+/// withdrawal follows. Only found routes are kept, a probe that found none is retried next time.
+#[derive(Default)]
+pub struct ProbeCache {
+    fees_msat: HashMap<ProbeKey, (i64, u64)>,
+}
+
+impl ProbeCache {
+    pub fn get(&self, key: &ProbeKey, now: u64) -> Option<i64> {
+        self.fees_msat
+            .get(key)
+            .filter(|(_, probed_at)| *probed_at + PROBE_TTL_MS > now)
+            .map(|(fee_msat, _)| *fee_msat)
+    }
+
+    pub fn insert(&mut self, key: ProbeKey, fee_msat: i64, now: u64) {
+        self.fees_msat
+            .retain(|_, (_, probed_at)| *probed_at + PROBE_TTL_MS > now);
+        self.fees_msat.insert(key, (fee_msat, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probes_expire() {
+        let mut cache = ProbeCache::default();
+        let key = (String::from("hash"), 1000);
+        cache.insert(key.clone(), 5, 0);
+        assert_eq!(cache.get(&key, PROBE_TTL_MS - 1), Some(5));
+        assert_eq!(cache.get(&(String::from("hash"), 2000), 0), None);
+        assert_eq!(cache.get(&key, PROBE_TTL_MS), None);
+    }
+}
@@ -0,0 +1,120 @@
+use core_types::UserId;
+use diesel::result::Error as DieselError;
+use models::rate_limit_buckets::RateLimitBucket;
+use std::collections::HashMap;
+
+use crate::bank_engine::RateLimiterSettings;
+
+/// Refills a bucket for the time passed since it was last used and takes a token from it.
+/// Returns the tokens left or none if the bucket is empty.
+pub fn refill_and_take(tokens: f64, updated_at: u64, capacity: f64, refill_per_milli: f64, now: u64) -> Option<f64> {
+    let tokens = (tokens + now.saturating_sub(updated_at) as f64 * refill_per_milli).min(capacity);
+    if tokens < 1.0 {
+        return None;
+    }
+    Some(tokens - 1.0)
+}
+
+/// Token bucket per user allowing `request_limit` requests per replenishment interval, refilled continuously.
+/// The buckets are kept in the database so limits survive restarts of the bank, they are only kept in memory
+/// when the bank runs without one.
+#[derive(Debug)]
+pub struct TokenBucketLimiter {
+    name: &'static str,
+    settings: RateLimiterSettings,
+    buckets: HashMap<UserId, (f64, u64)>,
+}
+
+impl TokenBucketLimiter {
+    pub fn new(name: &'static str, settings: RateLimiterSettings) -> Self {
+        Self {
+            name,
+            settings,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        self.settings.request_limit as f64
+    }
+
+    fn refill_per_milli(&self) -> f64 {
+        self.settings.request_limit as f64 / self.settings.replenishment_interval.max(1) as f64
+    }
+
+    fn bucket_key(&self, uid: UserId) -> String {
+        format!("{}:{}", self.name, uid)
+    }
+
+    /// Takes a token from the bucket of the user, returns false if the user ran out of requests.
+    pub fn take(&mut self, conn: Option<&diesel::PgConnection>, uid: UserId, now: u64) -> Result<bool, DieselError> {
+        let conn = match conn {
+            Some(conn) => conn,
+            None => return Ok(self.take_in_memory(uid, now)),
+        };
+        let bucket = RateLimitBucket::take(
+            conn,
+            &self.bucket_key(uid),
+            self.capacity(),
+            self.refill_per_milli(),
+            now as i64,
+        )?;
+        Ok(bucket.is_some())
+    }
+
+    pub fn take_in_memory(&mut self, uid: UserId, now: u64) -> bool {
+        let capacity = self.capacity();
+        let refill_per_milli = self.refill_per_milli();
+        let (tokens, updated_at) = self.buckets.entry(uid).or_insert((capacity, now));
+        match refill_and_take(*tokens, *updated_at, capacity, refill_per_milli, now) {
+            Some(left) => {
+                *tokens = left;
+                *updated_at = now.max(*updated_at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops the bucket of the user so the next request starts with a full one.
+    pub fn reset(&mut self, conn: Option<&diesel::PgConnection>, uid: UserId) -> Result<(), DieselError> {
+        self.buckets.remove(&uid);
+        if let Some(conn) = conn {
+            RateLimitBucket::delete(conn, &self.bucket_key(uid))?;
+        }
+        Ok(())
+    }
+
+    /// Buckets unused for a whole interval are full again and can be dropped.
+    pub fn prune(&mut self, conn: Option<&diesel::PgConnection>, now: u64) -> Result<usize, DieselError> {
+        let idle_since = now.saturating_sub(self.settings.replenishment_interval);
+        self.buckets.retain(|_, (_, updated_at)| *updated_at >= idle_since);
+        match conn {
+            Some(conn) => RateLimitBucket::delete_idle(conn, idle_since as i64),
+            None => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_the_interval() {
+        let mut limiter = TokenBucketLimiter::new(
+            "withdrawal",
+            RateLimiterSettings {
+                request_limit: 2,
+                replenishment_interval: 1000,
+            },
+        );
+        assert!(limiter.take_in_memory(1, 0));
+        assert!(limiter.take_in_memory(1, 0));
+        assert!(!limiter.take_in_memory(1, 100));
+        assert!(limiter.take_in_memory(2, 100));
+        // One token is back after half the interval.
+        assert!(limiter.take_in_memory(1, 500));
+        assert!(!limiter.take_in_memory(1, 500));
+    }
+}
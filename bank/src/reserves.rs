@@ -0,0 +1,80 @@
+use core_types::Currency;
+use lnd_connector::connector::{LndConnector, NodeBalances};
+use msgs::api::{ReservesReport, ReservesReportError};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use utils::currencies::SATS_DECIMALS;
+
+fn sats_to_btc(sats: u64) -> Decimal {
+    Decimal::new(sats as i64, SATS_DECIMALS)
+}
+
+/// Canonical message signed by the node. Liabilities are sorted by currency so the
+/// message can be rebuilt from a report to verify the signature.
+pub fn attestation_message(
+    timestamp: u64,
+    node_pubkey: &str,
+    total_reserves: Decimal,
+    liabilities: &HashMap<Currency, Decimal>,
+) -> String {
+    let mut liabilities = liabilities
+        .iter()
+        .map(|(currency, amount)| format!("{}:{}", currency, amount.normalize()))
+        .collect::<Vec<String>>();
+    liabilities.sort();
+    format!(
+        "lndhubx proof of reserves|{}|{}|BTC:{}|{}",
+        timestamp,
+        node_pubkey,
+        total_reserves.normalize(),
+        liabilities.join(",")
+    )
+}
+
+fn coverage_ratio(total_reserves: Decimal, liabilities: &HashMap<Currency, Decimal>) -> Option<Decimal> {
+    match liabilities.get(&Currency::BTC) {
+        Some(btc_liabilities) if *btc_liabilities > Decimal::ZERO => Some(total_reserves / btc_liabilities),
+        _ => None,
+    }
+}
+
+/// Fetches channel and wallet balances of the node, compares them against user liabilities
+/// and has the node sign the result.
+pub async fn build_reserves_report(
+    lnd_connector: &mut LndConnector,
+    node_pubkey: &str,
+    liabilities: HashMap<Currency, Decimal>,
+    timestamp: u64,
+) -> Result<ReservesReport, ReservesReportError> {
+    let NodeBalances {
+        channel_local_balance,
+        channel_pending_open_local_balance,
+        wallet_confirmed_balance,
+        wallet_unconfirmed_balance,
+    } = lnd_connector
+        .get_balances()
+        .await
+        .map_err(|_| ReservesReportError::FailedToFetchBalances)?;
+
+    let channel_balance = sats_to_btc(channel_local_balance + channel_pending_open_local_balance);
+    let wallet_balance = sats_to_btc(wallet_confirmed_balance + wallet_unconfirmed_balance);
+    let total_reserves = channel_balance + wallet_balance;
+
+    let attestation = attestation_message(timestamp, node_pubkey, total_reserves, &liabilities);
+    let signature = lnd_connector
+        .sign_message(&attestation)
+        .await
+        .map_err(|_| ReservesReportError::FailedToSignAttestation)?;
+
+    Ok(ReservesReport {
+        timestamp,
+        node_pubkey: node_pubkey.to_string(),
+        channel_balance,
+        wallet_balance,
+        total_reserves,
+        coverage_ratio: coverage_ratio(total_reserves, &liabilities),
+        liabilities,
+        attestation,
+        signature,
+    })
+}
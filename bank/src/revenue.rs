@@ -0,0 +1,182 @@
+use models::operator_revenues::OperatorRevenue;
+use models::summary_transactions::SummaryTransaction;
+use msgs::cli::{FeeIncomeBucket, RevenueBucket, RevenuePeriod};
+
+use core_types::Currency;
+use rust_decimal::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub const MILLIS_IN_DAY: u64 = 86_400_000;
+
+pub const INTERNAL_FEES: &str = "internal_fees";
+pub const EXTERNAL_FEES: &str = "external_fees";
+pub const CONVERSION_SPREAD: &str = "conversion_spread";
+pub const EXCESS_ROUTING_FEES: &str = "excess_routing_fees";
+pub const CONVERSION_FEES: &str = "conversion_fees";
+pub const REFERRAL_PAYOUTS: &str = "referral_payouts";
+
+fn to_decimal(value: &bigdecimal::BigDecimal) -> Decimal {
+    Decimal::from_str(&value.to_string()).unwrap_or(Decimal::ZERO)
+}
+
+pub fn day_start(timestamp: u64) -> u64 {
+    timestamp - timestamp % MILLIS_IN_DAY
+}
+
+/// Start of the week (Monday) or month (1st) the day belongs to, in UTC.
+pub fn period_start(day: u64, period: RevenuePeriod) -> u64 {
+    let days = day / MILLIS_IN_DAY;
+    match period {
+        RevenuePeriod::Day => day,
+        // The epoch was a Thursday.
+        RevenuePeriod::Week => (days - (days + 3) % 7) * MILLIS_IN_DAY,
+        RevenuePeriod::Month => (days - (day_of_month(days) - 1)) * MILLIS_IN_DAY,
+    }
+}
+
+/// Day of the month of a day since the epoch, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn day_of_month(days: u64) -> u64 {
+    let z = days + 719_468;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    day_of_year - (153 * month + 2) / 5 + 1
+}
+
+/// Source of the operator's revenue a summary transaction's fees count towards.
+fn revenue_source(tx: &SummaryTransaction) -> Option<&'static str> {
+    match tx.reference.as_deref() {
+        Some("Swap") => Some(CONVERSION_SPREAD),
+        Some("ExcessRoutingFees") => Some(EXCESS_ROUTING_FEES),
+        Some("ReferralPayout") => Some(REFERRAL_PAYOUTS),
+        // Refunded fees were never earned.
+        Some("PaymentRefund") | Some("FeeRefund") => None,
+        Some("InternalFee") | Some("InternalFeeRefund") => Some(INTERNAL_FEES),
+        Some("ExternalFee") | Some("ExternalFeeRefund") => Some(EXTERNAL_FEES),
+        Some("SwapFee") | Some("SwapFeeRefund") | Some("ConversionFee") | Some("ConversionFeeRefund") => {
+            Some(CONVERSION_FEES)
+        }
+        _ if tx.tx_type == "External" => Some(EXTERNAL_FEES),
+        _ => Some(INTERNAL_FEES),
+    }
+}
+
+/// Refunds of collected fees are deducted from the revenue they were counted towards.
+fn is_fee_refund(tx: &SummaryTransaction) -> bool {
+    matches!(tx.reference.as_deref(), Some(reference) if reference.ends_with("FeeRefund") && reference != "FeeRefund")
+}
+
+/// Sums the fees of summary transactions per source and currency together with the number of
+/// transactions that paid them. Fees are booked in the inbound currency.
+pub fn aggregate_revenue(txs: &[SummaryTransaction]) -> HashMap<(&'static str, Currency), (Decimal, usize)> {
+    let mut revenue = HashMap::new();
+    for tx in txs {
+        let fees = to_decimal(&tx.fees);
+        if fees <= Decimal::ZERO {
+            continue;
+        }
+        let source = match revenue_source(tx) {
+            Some(source) => source,
+            None => continue,
+        };
+        let currency = match Currency::from_str(&tx.inbound_currency) {
+            Ok(currency) => currency,
+            Err(_) => continue,
+        };
+        let (amount, count) = revenue.entry((source, currency)).or_insert((Decimal::ZERO, 0));
+        if is_fee_refund(tx) {
+            *amount -= fees;
+            *count = count.saturating_sub(1);
+        } else if tx.reference.as_deref() == Some("ReferralPayout") {
+            // Fees shared with referrers are counted as negative revenue.
+            *amount -= fees;
+            *count += 1;
+        } else {
+            *amount += fees;
+            *count += 1;
+        }
+    }
+    revenue
+}
+
+/// Sums daily revenues into buckets of the period, ordered by their start.
+pub fn bucket_revenues(revenues: &[OperatorRevenue], period: RevenuePeriod) -> Vec<RevenueBucket> {
+    let mut buckets = BTreeMap::<u64, RevenueBucket>::new();
+    for revenue in revenues {
+        let currency = match Currency::from_str(&revenue.currency) {
+            Ok(currency) => currency,
+            Err(_) => continue,
+        };
+        let start = period_start(revenue.day as u64, period);
+        let bucket = buckets.entry(start).or_insert_with(|| RevenueBucket {
+            start,
+            revenue: HashMap::new(),
+            transactions: 0,
+        });
+        *bucket
+            .revenue
+            .entry(revenue.source.clone())
+            .or_default()
+            .entry(currency)
+            .or_insert(Decimal::ZERO) += to_decimal(&revenue.amount);
+        bucket.transactions += revenue.tx_count as usize;
+    }
+    buckets.into_values().collect()
+}
+
+/// Sums the postings into and out of the fee accounts into buckets of the period, ordered by their start.
+pub fn bucket_fee_income(
+    txs: &[SummaryTransaction],
+    fee_account_ids: &HashSet<Uuid>,
+    period: RevenuePeriod,
+) -> Vec<FeeIncomeBucket> {
+    let mut buckets = BTreeMap::<u64, FeeIncomeBucket>::new();
+    for tx in txs {
+        let (currency, amount) = if fee_account_ids.contains(&tx.inbound_account_id) {
+            (&tx.inbound_currency, to_decimal(&tx.inbound_amount))
+        } else if fee_account_ids.contains(&tx.outbound_account_id) {
+            (&tx.outbound_currency, -to_decimal(&tx.outbound_amount))
+        } else {
+            continue;
+        };
+        let currency = match Currency::from_str(currency) {
+            Ok(currency) => currency,
+            Err(_) => continue,
+        };
+        let start = period_start(day_start(tx.created_at as u64), period);
+        let bucket = buckets.entry(start).or_insert_with(|| FeeIncomeBucket {
+            start,
+            income: HashMap::new(),
+            collected: 0,
+            refunded: 0,
+        });
+        *bucket.income.entry(currency).or_insert(Decimal::ZERO) += amount;
+        if amount >= Decimal::ZERO {
+            bucket.collected += 1;
+        } else {
+            bucket.refunded += 1;
+        }
+    }
+    buckets.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods_start_on_mondays_and_first_days_of_months() {
+        // Wednesday 2022-11-30.
+        let day = 1_669_766_400_000;
+        assert_eq!(period_start(day, RevenuePeriod::Day), day);
+        // Monday 2022-11-28.
+        assert_eq!(period_start(day, RevenuePeriod::Week), 1_669_593_600_000);
+        // 2022-11-01.
+        assert_eq!(period_start(day, RevenuePeriod::Month), 1_667_260_800_000);
+        // 2024-03-01 follows a leap day.
+        assert_eq!(period_start(1_709_251_200_000, RevenuePeriod::Month), 1_709_251_200_000);
+    }
+}
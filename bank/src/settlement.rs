@@ -0,0 +1,206 @@
+use core_types::RequestId;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use uuid::Uuid;
+
+fn default_window_secs() -> u64 {
+    10
+}
+
+fn default_max_batch_size() -> usize {
+    20
+}
+
+/// Settlements between the dealer and the bank are collected this long before they are netted and executed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementBatchingSettings {
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// A batch is executed right away once it holds this many settlements.
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+}
+
+impl Default for SettlementBatchingSettings {
+    fn default() -> Self {
+        Self {
+            window_secs: default_window_secs(),
+            max_batch_size: default_max_batch_size(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SettlementKind {
+    /// Sweeps and settlements of the dealer's exchange account.
+    Trading,
+    /// Margin top-ups and withdrawals paid from and to the bank's liabilities.
+    Insurance,
+}
+
+impl fmt::Display for SettlementKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Trading => "Trading",
+            Self::Insurance => "Insurance",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// An invoice of the exchange the bank pays for the dealer.
+#[derive(Debug, Clone)]
+pub struct PendingPayment {
+    pub req_id: RequestId,
+    pub payment_request: String,
+    pub payment_hash: String,
+    pub destination: String,
+    pub amount_in_sats: Decimal,
+    pub queued_at: u64,
+}
+
+/// A withdrawal from the exchange the dealer asked the bank for an invoice for.
+#[derive(Debug, Clone)]
+pub struct PendingWithdrawal {
+    pub req_id: RequestId,
+    pub amount: u64,
+    pub memo: String,
+    pub queued_at: u64,
+}
+
+/// The settlements of one kind executed together. Withdrawals are merged into a single invoice and
+/// payments to the same destination are sent along one route.
+#[derive(Debug, Clone)]
+pub struct SettlementBatch {
+    pub group_id: Uuid,
+    pub kind: SettlementKind,
+    pub payments: Vec<PendingPayment>,
+    pub withdrawals: Vec<PendingWithdrawal>,
+}
+
+impl SettlementBatch {
+    pub fn withdrawal_total(&self) -> u64 {
+        self.withdrawals.iter().map(|withdrawal| withdrawal.amount).sum()
+    }
+
+    /// Payments grouped by destination. Invoices the dealer forwarded more than once are only paid once.
+    pub fn payments_by_destination(&self) -> BTreeMap<String, Vec<PendingPayment>> {
+        let mut payments = BTreeMap::<String, Vec<PendingPayment>>::new();
+        for payment in self.payments.iter() {
+            let destination_payments = payments.entry(payment.destination.clone()).or_default();
+            if destination_payments
+                .iter()
+                .all(|queued| queued.payment_hash != payment.payment_hash)
+            {
+                destination_payments.push(payment.clone());
+            }
+        }
+        payments
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SettlementQueue {
+    payments: HashMap<SettlementKind, Vec<PendingPayment>>,
+    withdrawals: HashMap<SettlementKind, Vec<PendingWithdrawal>>,
+}
+
+impl SettlementQueue {
+    pub fn push_payment(&mut self, kind: SettlementKind, payment: PendingPayment) {
+        self.payments.entry(kind).or_default().push(payment);
+    }
+
+    pub fn push_withdrawal(&mut self, kind: SettlementKind, withdrawal: PendingWithdrawal) {
+        self.withdrawals.entry(kind).or_default().push(withdrawal);
+    }
+
+    /// Takes the batches whose oldest settlement waited for the window or that reached the max size.
+    pub fn take_due(&mut self, now: u64, settings: &SettlementBatchingSettings) -> Vec<SettlementBatch> {
+        let window = settings.window_secs * 1000;
+        let mut kinds = self
+            .payments
+            .keys()
+            .chain(self.withdrawals.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        kinds.sort();
+        kinds.dedup();
+
+        let mut batches = Vec::new();
+        for kind in kinds {
+            let payments = self.payments.get(&kind).map_or(&[][..], |payments| &payments[..]);
+            let withdrawals = self
+                .withdrawals
+                .get(&kind)
+                .map_or(&[][..], |withdrawals| &withdrawals[..]);
+            let oldest = payments
+                .iter()
+                .map(|payment| payment.queued_at)
+                .chain(withdrawals.iter().map(|withdrawal| withdrawal.queued_at))
+                .min();
+            let is_due = match oldest {
+                Some(oldest) => oldest + window <= now || payments.len() + withdrawals.len() >= settings.max_batch_size,
+                None => false,
+            };
+            if !is_due {
+                continue;
+            }
+            batches.push(SettlementBatch {
+                group_id: Uuid::new_v4(),
+                kind,
+                payments: self.payments.remove(&kind).unwrap_or_default(),
+                withdrawals: self.withdrawals.remove(&kind).unwrap_or_default(),
+            });
+        }
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(payment_hash: &str, destination: &str, queued_at: u64) -> PendingPayment {
+        PendingPayment {
+            req_id: Uuid::new_v4(),
+            payment_request: format!("lnbc{}", payment_hash),
+            payment_hash: payment_hash.to_string(),
+            destination: destination.to_string(),
+            amount_in_sats: Decimal::new(1000, 0),
+            queued_at,
+        }
+    }
+
+    #[test]
+    fn batches_are_taken_once_due() {
+        let settings = SettlementBatchingSettings {
+            window_secs: 10,
+            max_batch_size: 3,
+        };
+        let mut queue = SettlementQueue::default();
+        queue.push_payment(SettlementKind::Trading, payment("a", "exchange", 0));
+        queue.push_payment(SettlementKind::Trading, payment("a", "exchange", 1_000));
+        queue.push_withdrawal(
+            SettlementKind::Trading,
+            PendingWithdrawal {
+                req_id: Uuid::new_v4(),
+                amount: 500,
+                memo: String::new(),
+                queued_at: 2_000,
+            },
+        );
+        queue.push_payment(SettlementKind::Insurance, payment("b", "exchange", 5_000));
+
+        // The trading batch is full, the insurance batch waits for its window.
+        let batches = queue.take_due(6_000, &settings);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].kind, SettlementKind::Trading);
+        assert_eq!(batches[0].withdrawal_total(), 500);
+        assert_eq!(batches[0].payments_by_destination()["exchange"].len(), 1);
+
+        assert!(queue.take_due(14_999, &settings).is_empty());
+        assert_eq!(queue.take_due(15_000, &settings).len(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+use models::summary_transactions::SummaryTransaction;
+use msgs::cli::{Simulate, SimulationReport};
+
+use core_types::{Currency, UserId};
+use rust_decimal::prelude::*;
+use std::str::FromStr;
+
+const MILLIS_IN_DAY: u64 = 86_400_000;
+
+/// Fee settings currently used by the bank.
+pub struct CurrentFees {
+    pub internal_tx_fee: Decimal,
+    pub external_tx_fee: Decimal,
+}
+
+enum TxKind {
+    Deposit,
+    Withdrawal,
+    Internal,
+    Other,
+}
+
+fn tx_kind(tx: &SummaryTransaction, bank_uid: UserId, dealer_uid: UserId) -> TxKind {
+    let outbound_uid = tx.outbound_uid as UserId;
+    let inbound_uid = tx.inbound_uid as UserId;
+    if outbound_uid == bank_uid {
+        TxKind::Deposit
+    } else if inbound_uid == bank_uid {
+        TxKind::Withdrawal
+    } else if outbound_uid == inbound_uid || outbound_uid == dealer_uid || inbound_uid == dealer_uid {
+        TxKind::Other
+    } else {
+        TxKind::Internal
+    }
+}
+
+fn to_decimal(value: &bigdecimal::BigDecimal) -> Decimal {
+    Decimal::from_str(&value.to_string()).unwrap_or(Decimal::ZERO)
+}
+
+/// Returns the start and end of the replayed period in millis.
+pub fn simulation_period(request: &Simulate, now: u64) -> (u64, u64) {
+    (now.saturating_sub(request.days * MILLIS_IN_DAY), now)
+}
+
+/// Replays summary transactions under the proposed settings.
+///
+/// Internal transactions are charged the proposed internal fee instead of the recorded one and
+/// withdrawals are charged the difference between the proposed and current external fee on top of
+/// the recorded fee since the rest of it covers network fees. Transactions above a proposed limit
+/// are counted as rejected and don't contribute to the simulated revenue.
+pub fn simulate(
+    txs: &[SummaryTransaction],
+    request: &Simulate,
+    current: &CurrentFees,
+    bank_uid: UserId,
+    dealer_uid: UserId,
+) -> SimulationReport {
+    let internal_tx_fee = request.internal_tx_fee.unwrap_or(current.internal_tx_fee);
+    let external_tx_fee = request.external_tx_fee.unwrap_or(current.external_tx_fee);
+
+    let mut report = SimulationReport {
+        transactions: txs.len(),
+        ..Default::default()
+    };
+
+    for tx in txs {
+        let outbound_currency = match Currency::from_str(&tx.outbound_currency) {
+            Ok(currency) => currency,
+            Err(_) => continue,
+        };
+        let inbound_currency = match Currency::from_str(&tx.inbound_currency) {
+            Ok(currency) => currency,
+            Err(_) => continue,
+        };
+        let outbound_amount = to_decimal(&tx.outbound_amount);
+        let inbound_amount = to_decimal(&tx.inbound_amount);
+        let fees = to_decimal(&tx.fees);
+
+        *report.revenue.entry(outbound_currency).or_insert(Decimal::ZERO) += fees;
+
+        let simulated_fees = match tx_kind(tx, bank_uid, dealer_uid) {
+            TxKind::Deposit => {
+                if let Some(limit) = request.deposit_limits.get(&inbound_currency) {
+                    if inbound_amount > *limit {
+                        report.rejected_deposits += 1;
+                        *report.rejected_volume.entry(inbound_currency).or_insert(Decimal::ZERO) += inbound_amount;
+                        continue;
+                    }
+                }
+                fees
+            }
+            TxKind::Withdrawal => {
+                if let Some(limit) = request.withdrawal_limits.get(&outbound_currency) {
+                    if outbound_amount > *limit {
+                        report.rejected_withdrawals += 1;
+                        *report.rejected_volume.entry(outbound_currency).or_insert(Decimal::ZERO) += outbound_amount;
+                        continue;
+                    }
+                }
+                (fees + outbound_amount * (external_tx_fee - current.external_tx_fee)).max(Decimal::ZERO)
+            }
+            TxKind::Internal => outbound_amount * internal_tx_fee,
+            TxKind::Other => fees,
+        };
+
+        *report
+            .simulated_revenue
+            .entry(outbound_currency)
+            .or_insert(Decimal::ZERO) += simulated_fees;
+    }
+
+    report
+}
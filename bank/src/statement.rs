@@ -0,0 +1,186 @@
+use models::summary_transactions::SummaryTransaction;
+use msgs::api::StatementEntry;
+
+use core_types::{Currency, Money, UserId};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const STATEMENT_HEADER: &str =
+    "date,txid,tx_type,account,currency,amount,fees,balance,valuation_currency,valuation,reference";
+
+/// How far before the period rates are looked up so the first transactions can be valued.
+pub const RATE_LOOKBACK_MILLIS: u64 = 7 * 86_400_000;
+
+fn to_decimal(value: &bigdecimal::BigDecimal) -> Decimal {
+    Decimal::from_str(&value.to_string()).unwrap_or(Decimal::ZERO)
+}
+
+/// Balances keep their msat precision, only the statement shows whole sats.
+fn round_for_display(currency: Currency, value: Decimal) -> Decimal {
+    Money::new(currency, Some(value)).round_for_display().value
+}
+
+/// Tracks the latest known price of one BTC in every currency.
+#[derive(Default)]
+struct Prices {
+    btc_prices: HashMap<Currency, Decimal>,
+}
+
+impl Prices {
+    /// Every exchange between BTC and another currency updates the price in that currency.
+    fn observe(&mut self, tx: &SummaryTransaction) {
+        let (outbound_currency, inbound_currency) = match (
+            Currency::from_str(&tx.outbound_currency),
+            Currency::from_str(&tx.inbound_currency),
+        ) {
+            (Ok(outbound_currency), Ok(inbound_currency)) => (outbound_currency, inbound_currency),
+            _ => return,
+        };
+        let (outbound_amount, inbound_amount) = (to_decimal(&tx.outbound_amount), to_decimal(&tx.inbound_amount));
+        if outbound_amount.is_zero() || inbound_amount.is_zero() {
+            return;
+        }
+        if outbound_currency == Currency::BTC && inbound_currency != Currency::BTC {
+            self.btc_prices
+                .insert(inbound_currency, inbound_amount / outbound_amount);
+        } else if inbound_currency == Currency::BTC && outbound_currency != Currency::BTC {
+            self.btc_prices
+                .insert(outbound_currency, outbound_amount / inbound_amount);
+        }
+    }
+
+    fn btc_price(&self, currency: Currency) -> Option<Decimal> {
+        if currency == Currency::BTC {
+            return Some(Decimal::ONE);
+        }
+        self.btc_prices.get(&currency).copied()
+    }
+
+    fn value(&self, amount: Decimal, currency: Currency, valuation_currency: Currency) -> Option<Decimal> {
+        if currency == valuation_currency {
+            return Some(amount);
+        }
+        let price = self.btc_price(currency)?;
+        if price.is_zero() {
+            return None;
+        }
+        Some((amount / price * self.btc_price(valuation_currency)?).round_dp(8))
+    }
+}
+
+/// Builds the statement of a user for the period `[from, to]`.
+///
+/// `user_txs` have to contain all transactions of the user up to `to` so running balances start
+/// from the first transaction. `rate_txs` are the transactions of all users the rates at the time
+/// of each transaction are taken from.
+pub fn build_statement(
+    uid: UserId,
+    user_txs: &[SummaryTransaction],
+    rate_txs: &[SummaryTransaction],
+    from: u64,
+    to: u64,
+    valuation_currency: Currency,
+) -> Vec<StatementEntry> {
+    let mut user_txs = user_txs.iter().collect::<Vec<_>>();
+    user_txs.sort_by_key(|tx| tx.created_at);
+    let mut rate_txs = rate_txs.iter().collect::<Vec<_>>();
+    rate_txs.sort_by_key(|tx| tx.created_at);
+
+    let mut prices = Prices::default();
+    let mut rate_txs = rate_txs.into_iter().peekable();
+    let mut balances = HashMap::new();
+    let mut entries = Vec::new();
+
+    for tx in user_txs {
+        while let Some(rate_tx) = rate_txs.next_if(|rate_tx| rate_tx.created_at <= tx.created_at) {
+            prices.observe(rate_tx);
+        }
+        // The transaction's own rate is the most accurate one for itself.
+        prices.observe(tx);
+
+        let mut bookings = Vec::new();
+        if tx.outbound_uid as UserId == uid {
+            bookings.push((
+                tx.outbound_account_id,
+                &tx.outbound_currency,
+                -to_decimal(&tx.outbound_amount),
+                to_decimal(&tx.fees),
+            ));
+        }
+        if tx.inbound_uid as UserId == uid {
+            bookings.push((
+                tx.inbound_account_id,
+                &tx.inbound_currency,
+                to_decimal(&tx.inbound_amount),
+                Decimal::ZERO,
+            ));
+        }
+
+        for (account_id, currency, amount, fees) in bookings {
+            let balance = balances.entry(account_id).or_insert(Decimal::ZERO);
+            *balance += amount;
+
+            let created_at = tx.created_at as u64;
+            if created_at < from || created_at > to {
+                continue;
+            }
+
+            let currency = match Currency::from_str(currency) {
+                Ok(currency) => currency,
+                Err(_) => continue,
+            };
+
+            entries.push(StatementEntry {
+                created_at,
+                txid: tx.txid.clone(),
+                tx_type: tx.tx_type.clone(),
+                account_id,
+                currency,
+                amount: round_for_display(currency, amount),
+                fees: round_for_display(currency, fees),
+                balance: round_for_display(currency, *balance),
+                valuation_currency,
+                valuation: prices.value(amount, currency, valuation_currency),
+                reference: tx.reference.clone(),
+            });
+        }
+    }
+
+    entries
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn statement_csv(entries: &[StatementEntry]) -> String {
+    let mut statement = String::from(STATEMENT_HEADER);
+    statement.push('\n');
+
+    for entry in entries {
+        statement.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            entry.created_at,
+            entry.txid,
+            escape_csv(&entry.tx_type),
+            entry.account_id,
+            entry.currency,
+            entry.amount,
+            entry.fees,
+            entry.balance,
+            entry.valuation_currency,
+            entry
+                .valuation
+                .map(|valuation| valuation.to_string())
+                .unwrap_or_default(),
+            escape_csv(entry.reference.as_deref().unwrap_or_default()),
+        ));
+    }
+
+    statement
+}
@@ -0,0 +1,139 @@
+use models::flow_statistics::{FlowStatistic, PublicFlowStatistic};
+use models::summary_transactions::SummaryTransaction;
+
+use core_types::UserId;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+pub const DEPOSIT: &str = "deposit";
+pub const WITHDRAWAL: &str = "withdrawal";
+pub const SWAP: &str = "swap";
+
+fn default_min_tx_count() -> u64 {
+    5
+}
+
+fn default_publish_days() -> u64 {
+    30
+}
+
+/// Opt-in aggregation of anonymized daily flows, see `aggregate_flows`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlowStatisticsSettings {
+    /// Flows with fewer transactions on a day are left out so single users can't be told apart.
+    #[serde(default = "default_min_tx_count")]
+    pub min_tx_count: u64,
+    /// Json file the statistics of the last days are written to after each aggregated day.
+    #[serde(default)]
+    pub publish_path: Option<String>,
+    #[serde(default = "default_publish_days")]
+    pub publish_days: u64,
+}
+
+/// Volume, fees and number of transactions of a flow over a day.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlowAggregate {
+    pub volume: Decimal,
+    pub fees: Decimal,
+    pub tx_count: usize,
+}
+
+fn to_decimal(value: &bigdecimal::BigDecimal) -> Decimal {
+    Decimal::from_str(&value.to_string()).unwrap_or(Decimal::ZERO)
+}
+
+/// Sums the deposits and withdrawals per currency and the swaps per pair. Only totals are kept, flows
+/// with fewer than `min_tx_count` transactions are dropped.
+pub fn aggregate_flows(
+    txs: &[SummaryTransaction],
+    bank_uid: UserId,
+    min_tx_count: u64,
+) -> BTreeMap<(&'static str, String), FlowAggregate> {
+    let mut flows = BTreeMap::<(&'static str, String), FlowAggregate>::new();
+    for tx in txs {
+        let (flow, market, volume) = if tx.outbound_uid as UserId == bank_uid {
+            (DEPOSIT, tx.inbound_currency.clone(), &tx.inbound_amount)
+        } else if tx.inbound_uid as UserId == bank_uid {
+            (WITHDRAWAL, tx.outbound_currency.clone(), &tx.outbound_amount)
+        } else if tx.reference.as_deref() == Some("Swap") {
+            (
+                SWAP,
+                format!("{}/{}", tx.outbound_currency, tx.inbound_currency),
+                &tx.outbound_amount,
+            )
+        } else {
+            continue;
+        };
+        let aggregate = flows.entry((flow, market)).or_default();
+        aggregate.volume += to_decimal(volume);
+        aggregate.fees += to_decimal(&tx.fees);
+        aggregate.tx_count += 1;
+    }
+    flows.retain(|_, aggregate| aggregate.tx_count as u64 >= min_tx_count);
+    flows
+}
+
+/// Statistics as they are published by the bank and served by the api.
+pub fn statistics_to_json(statistics: &[FlowStatistic]) -> serde_json::Value {
+    let statistics = statistics.iter().map(PublicFlowStatistic::from).collect::<Vec<_>>();
+    json!({ "statistics": statistics })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use uuid::Uuid;
+
+    fn tx(
+        outbound_uid: i32,
+        inbound_uid: i32,
+        currencies: (&str, &str),
+        amount: i64,
+        reference: &str,
+    ) -> SummaryTransaction {
+        SummaryTransaction {
+            txid: Uuid::new_v4().to_string(),
+            fee_txid: None,
+            outbound_txid: None,
+            inbound_txid: None,
+            created_at: 0,
+            outbound_amount: BigDecimal::from(amount),
+            inbound_amount: BigDecimal::from(amount),
+            outbound_account_id: Uuid::new_v4(),
+            inbound_account_id: Uuid::new_v4(),
+            outbound_uid,
+            inbound_uid,
+            outbound_currency: currencies.0.to_string(),
+            inbound_currency: currencies.1.to_string(),
+            exchange_rate: BigDecimal::from(1),
+            tx_type: String::from("Internal"),
+            fees: BigDecimal::from(1),
+            reference: Some(reference.to_string()),
+        }
+    }
+
+    #[test]
+    fn small_flows_are_left_out() {
+        let txs = vec![
+            tx(1, 2, ("BTC", "BTC"), 10, "InternalTransfer"),
+            tx(1, 100, ("BTC", "BTC"), 10, "ExternalPayment"),
+            tx(2, 100, ("BTC", "BTC"), 20, "ExternalPayment"),
+            tx(100, 3, ("BTC", "BTC"), 5, "ExternalPayment"),
+            tx(3, 3, ("BTC", "USD"), 7, "Swap"),
+        ];
+        let flows = aggregate_flows(&txs, 100, 2);
+        assert_eq!(flows.len(), 1);
+        assert_eq!(
+            flows[&(WITHDRAWAL, String::from("BTC"))],
+            FlowAggregate {
+                volume: Decimal::new(30, 0),
+                fees: Decimal::new(2, 0),
+                tx_count: 2,
+            }
+        );
+        assert_eq!(aggregate_flows(&txs, 100, 1).len(), 3);
+    }
+}
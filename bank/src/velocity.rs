@@ -0,0 +1,73 @@
+use crate::revenue::MILLIS_IN_DAY;
+use models::withdrawal_limits::WithdrawalLimit;
+use serde::{Deserialize, Serialize};
+
+pub const MILLIS_IN_WEEK: u64 = 7 * MILLIS_IN_DAY;
+
+/// Max value a user withdraws over rolling windows, in sats. Windows without a limit are unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VelocityLimits {
+    #[serde(default)]
+    pub daily_sats: Option<u64>,
+    #[serde(default)]
+    pub weekly_sats: Option<u64>,
+}
+
+impl VelocityLimits {
+    /// Limits of a user, the limits set by the override of the user replace the global ones.
+    pub fn for_user(&self, user_override: Option<&WithdrawalLimit>) -> Self {
+        match user_override {
+            Some(user_override) => Self {
+                daily_sats: user_override
+                    .daily_sats
+                    .map(|limit| limit.max(0) as u64)
+                    .or(self.daily_sats),
+                weekly_sats: user_override
+                    .weekly_sats
+                    .map(|limit| limit.max(0) as u64)
+                    .or(self.weekly_sats),
+            },
+            None => *self,
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.daily_sats.is_none() && self.weekly_sats.is_none()
+    }
+
+    /// Whether withdrawing the amount on top of what was withdrawn over the last day and week stays within the limits.
+    pub fn allows(&self, withdrawn_last_day: u64, withdrawn_last_week: u64, amount: u64) -> bool {
+        let within = |limit: Option<u64>, withdrawn: u64| match limit {
+            Some(limit) => withdrawn.saturating_add(amount) <= limit,
+            None => true,
+        };
+        within(self.daily_sats, withdrawn_last_day) && within(self.weekly_sats, withdrawn_last_week)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_override_replaces_global_limits() {
+        let global = VelocityLimits {
+            daily_sats: Some(100_000),
+            weekly_sats: Some(500_000),
+        };
+        let user_override = WithdrawalLimit {
+            uid: 1,
+            daily_sats: Some(1_000_000),
+            weekly_sats: None,
+            updated_at: 0,
+        };
+        let limits = global.for_user(Some(&user_override));
+        assert_eq!(limits.daily_sats, Some(1_000_000));
+        assert_eq!(limits.weekly_sats, Some(500_000));
+
+        assert!(global.allows(50_000, 50_000, 50_000));
+        assert!(!global.allows(50_000, 50_000, 50_001));
+        assert!(!global.allows(0, 480_000, 50_000));
+        assert!(VelocityLimits::default().allows(u64::MAX, u64::MAX, 1));
+    }
+}
@@ -1,11 +1,27 @@
-use core_types::{Currency, UserId};
-use msgs::cli::{Cli, MakeTx};
+use core_types::{AccountType, Currency, UserId};
+use msgs::cli::{
+    ApproveWithdrawal, Cli, ClosePeriod, ExportJournal, ForceCloseAccount, FreezeAccount, FreezeReason, FreezeUser,
+    GetRevenueReport, LedgerBook, ListDeadLetters, ListFrozenAccounts, ListLiquiditySwaps, ListPendingLnurlWithdrawals,
+    ListPendingWithdrawals, MakeTx, QueryLedger, QueryRevenue, Rebalance, RejectWithdrawal, ReloadConfig, ReopenPeriod,
+    ReplayDeadLetter, ResetRateLimits, RevenuePeriod, SetDepositLimit, SetWithdrawalLimit, Simulate, UnfreezeAccount,
+};
 use msgs::dealer::{BankStateRequest, CreateInvoiceRequest, Dealer};
 use msgs::Message;
 use rust_decimal::Decimal;
+use std::str::FromStr;
 use structopt::StructOpt;
 use uuid::Uuid;
 
+/// Parses a limit in the form of `<currency>=<amount>`, e.g. `BTC=0.001`.
+fn parse_limit(s: &str) -> Result<(Currency, Decimal), String> {
+    let (currency, amount) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid limit {}, expected <currency>=<amount>", s))?;
+    let currency = Currency::from_str(currency).map_err(|_| format!("Invalid currency {}", currency))?;
+    let amount = Decimal::from_str(amount).map_err(|_| format!("Invalid amount {}", amount))?;
+    Ok((currency, amount))
+}
+
 #[derive(Debug, StructOpt)]
 pub enum Action {
     CreateInsuranceInvoice {
@@ -27,6 +43,206 @@ pub enum Action {
         #[structopt(short = "c", long = "currency")]
         currency: Currency,
     },
+    ExportJournal {
+        #[structopt(long = "from")]
+        from: u64,
+        #[structopt(long = "to")]
+        to: Option<u64>,
+        #[structopt(short = "p", long = "path")]
+        path: String,
+    },
+    /// Replays recent transactions under proposed fees and limits.
+    Simulate {
+        #[structopt(short = "d", long = "days", default_value = "30")]
+        days: u64,
+        #[structopt(long = "internal_tx_fee")]
+        internal_tx_fee: Option<Decimal>,
+        #[structopt(long = "external_tx_fee")]
+        external_tx_fee: Option<Decimal>,
+        #[structopt(long = "deposit_limit", parse(try_from_str = parse_limit))]
+        deposit_limits: Vec<(Currency, Decimal)>,
+        #[structopt(long = "withdrawal_limit", parse(try_from_str = parse_limit))]
+        withdrawal_limits: Vec<(Currency, Decimal)>,
+    },
+    /// Pages through bank liabilities, dealer accounts or the insurance fund.
+    QueryLedger {
+        /// One of bank_liabilities, dealer_accounts, insurance_fund, funding_income, hedging_pnl or
+        /// operator_expenses.
+        #[structopt(short = "b", long = "book")]
+        book: LedgerBook,
+        #[structopt(short = "c", long = "currency")]
+        currency: Option<Currency>,
+        #[structopt(long = "account_type")]
+        account_type: Option<AccountType>,
+        #[structopt(long = "offset", default_value = "0")]
+        offset: usize,
+        #[structopt(long = "limit", default_value = "20")]
+        limit: usize,
+        /// Number of recent postings shown per account.
+        #[structopt(long = "postings", default_value = "10")]
+        postings: usize,
+    },
+    /// Shows the operator revenue per source aggregated by day, week or month.
+    QueryRevenue {
+        /// One of day, week or month.
+        #[structopt(short = "p", long = "period", default_value = "day")]
+        period: RevenuePeriod,
+        #[structopt(long = "from")]
+        from: u64,
+        #[structopt(long = "to")]
+        to: Option<u64>,
+    },
+    /// Shows the income of the fee accounts per currency by day, week or month.
+    GetRevenueReport {
+        /// One of day, week or month.
+        #[structopt(short = "p", long = "period", default_value = "month")]
+        period: RevenuePeriod,
+        #[structopt(long = "from")]
+        from: u64,
+        #[structopt(long = "to")]
+        to: Option<u64>,
+    },
+    /// Locks all transactions up to the given time in millis and prints the closing balances.
+    ClosePeriod {
+        #[structopt(long = "until")]
+        until: u64,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+    },
+    /// Reopens the last closed period.
+    ReopenPeriod {
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+        #[structopt(short = "r", long = "reason")]
+        reason: String,
+    },
+    /// Overrides the withdrawal velocity limits of a user, limits left out fall back to the global ones.
+    SetWithdrawalLimit {
+        #[structopt(short = "u", long = "uid")]
+        uid: UserId,
+        #[structopt(long = "daily_sats")]
+        daily_sats: Option<u64>,
+        #[structopt(long = "weekly_sats")]
+        weekly_sats: Option<u64>,
+        /// Removes the override of the user.
+        #[structopt(long = "clear")]
+        clear: bool,
+    },
+    /// Lists the withdrawals held for approval.
+    ListPendingWithdrawals,
+    /// Releases a withdrawal held for approval.
+    ApproveWithdrawal {
+        #[structopt(long = "req_id")]
+        req_id: Uuid,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+    },
+    /// Rejects a withdrawal held for approval, the user is told the reason.
+    RejectWithdrawal {
+        #[structopt(long = "req_id")]
+        req_id: Uuid,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+        #[structopt(short = "r", long = "reason")]
+        reason: Option<String>,
+    },
+    /// Blocks withdrawals and swaps of a user, deposits are still accepted.
+    FreezeUser {
+        #[structopt(short = "u", long = "uid")]
+        uid: UserId,
+        /// Lifts the freeze instead.
+        #[structopt(long = "unfreeze")]
+        unfreeze: bool,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+        #[structopt(short = "r", long = "reason")]
+        reason: Option<String>,
+    },
+    /// Closes an account of a user even if it isn't empty, its balance stays on it.
+    ForceCloseAccount {
+        #[structopt(short = "u", long = "uid")]
+        uid: UserId,
+        #[structopt(long = "account_id")]
+        account_id: Uuid,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+        #[structopt(short = "r", long = "reason")]
+        reason: Option<String>,
+    },
+    /// Refills the request rate limits of a user.
+    ResetRateLimits {
+        #[structopt(short = "u", long = "uid")]
+        uid: UserId,
+    },
+    /// Overrides the deposit limit of a user for a currency, leave out the limit to fall back to the global one.
+    SetDepositLimit {
+        #[structopt(short = "u", long = "uid")]
+        uid: UserId,
+        #[structopt(short = "c", long = "currency")]
+        currency: Currency,
+        #[structopt(long = "max_balance")]
+        max_balance: Option<Decimal>,
+    },
+    /// Blocks withdrawals and swaps from a single account until it is unfrozen.
+    FreezeAccount {
+        #[structopt(short = "u", long = "uid")]
+        uid: UserId,
+        #[structopt(long = "account_id")]
+        account_id: Uuid,
+        /// One of compliance, fraud, legal_hold, chargeback or other.
+        #[structopt(short = "r", long = "reason")]
+        reason: FreezeReason,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+        #[structopt(short = "n", long = "note")]
+        note: Option<String>,
+    },
+    /// Lifts the freeze of an account.
+    UnfreezeAccount {
+        #[structopt(long = "account_id")]
+        account_id: Uuid,
+        #[structopt(short = "o", long = "operator")]
+        operator: String,
+        #[structopt(short = "n", long = "note")]
+        note: Option<String>,
+    },
+    /// Lists the frozen accounts with the reason of the freeze.
+    ListFrozenAccounts,
+    /// Lists the most recent messages the bank couldn't decode or had no handler for.
+    ListDeadLetters {
+        #[structopt(long = "limit", default_value = "20")]
+        limit: usize,
+        /// Also lists the dead letters that were replayed already.
+        #[structopt(long = "include_replayed")]
+        include_replayed: bool,
+    },
+    /// Processes a dead letter again, e.g. after an upgrade that can decode it.
+    ReplayDeadLetter {
+        #[structopt(long = "id")]
+        id: i32,
+    },
+    /// Applies the fees, limits and notices of the settings file without restarting the bank.
+    ReloadConfig,
+    /// Moves liquidity from one of the node's channels to another with a payment to itself.
+    Rebalance {
+        /// Channel the payment leaves through.
+        #[structopt(long = "outgoing_chan_id")]
+        outgoing_chan_id: u64,
+        /// Peer of the channel the payment comes back through.
+        #[structopt(long = "last_hop_pubkey")]
+        last_hop_pubkey: String,
+        #[structopt(long = "amount")]
+        amount_sats: u64,
+        #[structopt(long = "max_fee")]
+        max_fee_sats: u64,
+    },
+    /// Lists the most recent swaps started to manage the liquidity of the node with their state and cost.
+    ListLiquiditySwaps {
+        #[structopt(long = "limit", default_value = "20")]
+        limit: usize,
+    },
+    /// Lists the LNURL withdrawals that haven't been paid or expired yet.
+    ListPendingLnurlWithdrawals,
 }
 
 impl Action {
@@ -57,6 +273,150 @@ impl Action {
                 amount,
                 currency,
             })),
+            Self::ExportJournal { from, to, path } => {
+                Message::Cli(Cli::ExportJournal(ExportJournal { from, to, path }))
+            }
+            Self::Simulate {
+                days,
+                internal_tx_fee,
+                external_tx_fee,
+                deposit_limits,
+                withdrawal_limits,
+            } => Message::Cli(Cli::Simulate(Simulate {
+                days,
+                internal_tx_fee,
+                external_tx_fee,
+                deposit_limits: deposit_limits.into_iter().collect(),
+                withdrawal_limits: withdrawal_limits.into_iter().collect(),
+            })),
+            Self::QueryLedger {
+                book,
+                currency,
+                account_type,
+                offset,
+                limit,
+                postings,
+            } => Message::Cli(Cli::QueryLedger(QueryLedger {
+                book,
+                currency,
+                account_type,
+                offset,
+                limit,
+                postings,
+            })),
+            Self::QueryRevenue { period, from, to } => {
+                Message::Cli(Cli::QueryRevenue(QueryRevenue { period, from, to }))
+            }
+            Self::GetRevenueReport { period, from, to } => {
+                Message::Cli(Cli::GetRevenueReport(GetRevenueReport { period, from, to }))
+            }
+            Self::ClosePeriod { until, operator } => Message::Cli(Cli::ClosePeriod(ClosePeriod { until, operator })),
+            Self::ReopenPeriod { operator, reason } => {
+                Message::Cli(Cli::ReopenPeriod(ReopenPeriod { operator, reason }))
+            }
+            Self::SetWithdrawalLimit {
+                uid,
+                daily_sats,
+                weekly_sats,
+                clear,
+            } => Message::Cli(Cli::SetWithdrawalLimit(SetWithdrawalLimit {
+                uid,
+                daily_sats,
+                weekly_sats,
+                clear,
+            })),
+            Self::ListPendingWithdrawals => Message::Cli(Cli::ListPendingWithdrawals(ListPendingWithdrawals {})),
+            Self::ApproveWithdrawal { req_id, operator } => {
+                Message::Cli(Cli::ApproveWithdrawal(ApproveWithdrawal { req_id, operator }))
+            }
+            Self::RejectWithdrawal {
+                req_id,
+                operator,
+                reason,
+            } => Message::Cli(Cli::RejectWithdrawal(RejectWithdrawal {
+                req_id,
+                operator,
+                reason,
+            })),
+            Self::FreezeUser {
+                uid,
+                unfreeze,
+                operator,
+                reason,
+            } => Message::Cli(Cli::FreezeUser(FreezeUser {
+                uid,
+                unfreeze,
+                operator,
+                reason,
+            })),
+            Self::ForceCloseAccount {
+                uid,
+                account_id,
+                operator,
+                reason,
+            } => Message::Cli(Cli::ForceCloseAccount(ForceCloseAccount {
+                uid,
+                account_id,
+                operator,
+                reason,
+            })),
+            Self::ResetRateLimits { uid } => Message::Cli(Cli::ResetRateLimits(ResetRateLimits { uid })),
+            Self::SetDepositLimit {
+                uid,
+                currency,
+                max_balance,
+            } => Message::Cli(Cli::SetDepositLimit(SetDepositLimit {
+                uid,
+                currency,
+                max_balance,
+            })),
+            Self::FreezeAccount {
+                uid,
+                account_id,
+                reason,
+                operator,
+                note,
+            } => Message::Cli(Cli::FreezeAccount(FreezeAccount {
+                uid,
+                account_id,
+                reason,
+                operator,
+                note,
+            })),
+            Self::UnfreezeAccount {
+                account_id,
+                operator,
+                note,
+            } => Message::Cli(Cli::UnfreezeAccount(UnfreezeAccount {
+                account_id,
+                operator,
+                note,
+            })),
+            Self::ListFrozenAccounts => Message::Cli(Cli::ListFrozenAccounts(ListFrozenAccounts {})),
+            Self::ListDeadLetters {
+                limit,
+                include_replayed,
+            } => Message::Cli(Cli::ListDeadLetters(ListDeadLetters {
+                limit,
+                include_replayed,
+            })),
+            Self::ReplayDeadLetter { id } => Message::Cli(Cli::ReplayDeadLetter(ReplayDeadLetter { id })),
+            Self::ReloadConfig => Message::Cli(Cli::ReloadConfig(ReloadConfig {})),
+            Self::Rebalance {
+                outgoing_chan_id,
+                last_hop_pubkey,
+                amount_sats,
+                max_fee_sats,
+            } => Message::Cli(Cli::Rebalance(Rebalance {
+                outgoing_chan_id,
+                last_hop_pubkey,
+                amount_sats,
+                max_fee_sats,
+            })),
+            Self::ListLiquiditySwaps { limit } => Message::Cli(Cli::ListLiquiditySwaps(ListLiquiditySwaps { limit })),
+            Self::ListPendingLnurlWithdrawals => {
+                Message::Cli(Cli::ListPendingLnurlWithdrawals(ListPendingLnurlWithdrawals {}))
+            }
         }
     }
 }
@@ -0,0 +1,18 @@
+use cli::seed::{seed, SeedOptions, SeedSettings};
+use diesel::{Connection, PgConnection};
+use structopt::StructOpt;
+
+fn main() {
+    let settings = utils::config::get_config_from_env::<SeedSettings>().expect("Failed to load settings.");
+    let options = SeedOptions::from_args();
+
+    let conn = PgConnection::establish(&settings.psql_url).expect("Failed to connect to the database.");
+
+    match seed(&conn, &options) {
+        Ok(summary) => match serde_json::to_string_pretty(&summary) {
+            Ok(summary) => println!("Seeded:\n{}", summary),
+            Err(_) => println!("Seeded: {:?}", summary),
+        },
+        Err(err) => eprintln!("Seeding failed, nothing was written: {:?}", err),
+    }
+}
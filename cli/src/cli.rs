@@ -19,7 +19,7 @@ pub struct Cli {
 impl Cli {
     pub fn execute(self, socket: ZmqSocket) -> ResponseHandler {
         let msg = self.action.into_request();
-        utils::xzmq::send_as_bincode(&socket, &msg);
+        utils::xzmq::send_message(&socket, &msg);
 
         ResponseHandler { socket }
     }
@@ -43,6 +43,136 @@ impl ResponseHandler {
                     Message::Cli(CliMsg::MakeTxResult(tx_result)) => {
                         println!("Received transaction result: {:?}", tx_result);
                     }
+                    Message::Cli(CliMsg::ExportJournalResult(export_result)) => {
+                        println!("Received journal export result: {:?}", export_result);
+                    }
+                    Message::Cli(CliMsg::SimulationResult(simulation_result)) => match simulation_result.report {
+                        Some(report) => match serde_json::to_string_pretty(&report) {
+                            Ok(report) => println!("Simulation report:\n{}", report),
+                            Err(_) => println!("Simulation report: {:?}", report),
+                        },
+                        None => println!("Simulation failed: {:?}", simulation_result.error),
+                    },
+                    Message::Cli(CliMsg::LedgerQueryResult(query_result)) => {
+                        match serde_json::to_string_pretty(&query_result) {
+                            Ok(query_result) => println!("Ledger query result:\n{}", query_result),
+                            Err(_) => println!("Ledger query result: {:?}", query_result),
+                        }
+                    }
+                    Message::Cli(CliMsg::RevenueQueryResult(query_result)) => match query_result.error {
+                        Some(error) => println!("Revenue query failed: {}", error),
+                        None => match serde_json::to_string_pretty(&query_result.buckets) {
+                            Ok(buckets) => println!("Operator revenue:\n{}", buckets),
+                            Err(_) => println!("Operator revenue: {:?}", query_result.buckets),
+                        },
+                    },
+                    Message::Cli(CliMsg::RevenueReport(report)) => match report.error {
+                        Some(error) => println!("Revenue report failed: {}", error),
+                        None => match serde_json::to_string_pretty(&report) {
+                            Ok(report) => println!("Fee income:\n{}", report),
+                            Err(_) => println!("Fee income: {:?}", report),
+                        },
+                    },
+                    Message::Cli(CliMsg::ClosePeriodResult(close_result)) => match close_result.report {
+                        Some(report) => match serde_json::to_string_pretty(&report) {
+                            Ok(report) => println!("Closing report:\n{}", report),
+                            Err(_) => println!("Closing report: {:?}", report),
+                        },
+                        None => println!("Closing period failed: {:?}", close_result.error),
+                    },
+                    Message::Cli(CliMsg::ReopenPeriodResult(reopen_result)) => {
+                        println!("Received reopen result: {:?}", reopen_result);
+                    }
+                    Message::Cli(CliMsg::SetWithdrawalLimitResult(result)) => match result.error {
+                        Some(error) => println!("Setting withdrawal limit failed: {}", error),
+                        None => println!("Withdrawal limit set: {:?}", result.request),
+                    },
+                    Message::Cli(CliMsg::PendingWithdrawalsResult(result)) => match result.error {
+                        Some(error) => println!("Listing pending withdrawals failed: {}", error),
+                        None => match serde_json::to_string_pretty(&result.withdrawals) {
+                            Ok(withdrawals) => println!("Pending withdrawals:\n{}", withdrawals),
+                            Err(_) => println!("Pending withdrawals: {:?}", result.withdrawals),
+                        },
+                    },
+                    Message::Cli(CliMsg::ApproveWithdrawalResult(result)) => match result.error {
+                        Some(error) => println!("Approving withdrawal failed: {}", error),
+                        None => println!("Withdrawal approved: {:?}", result.request),
+                    },
+                    Message::Cli(CliMsg::RejectWithdrawalResult(result)) => match result.error {
+                        Some(error) => println!("Rejecting withdrawal failed: {}", error),
+                        None => println!("Withdrawal rejected: {:?}", result.request),
+                    },
+                    Message::Cli(CliMsg::FreezeUserResult(result)) => match result.error {
+                        Some(error) => println!("Freezing user failed: {}", error),
+                        None => println!("User updated: {:?}", result.request),
+                    },
+                    Message::Cli(CliMsg::ForceCloseAccountResult(result)) => match result.error {
+                        Some(error) => println!("Closing account failed: {}", error),
+                        None => println!(
+                            "Account {} closed with a balance of {:?}",
+                            result.request.account_id, result.balance
+                        ),
+                    },
+                    Message::Cli(CliMsg::ResetRateLimitsResult(result)) => match result.error {
+                        Some(error) => println!("Resetting rate limits failed: {}", error),
+                        None => println!("Rate limits of user {} reset", result.request.uid),
+                    },
+                    Message::Cli(CliMsg::SetDepositLimitResult(result)) => match result.error {
+                        Some(error) => println!("Setting deposit limit failed: {}", error),
+                        None => println!("Deposit limit set: {:?}", result.request),
+                    },
+                    Message::Cli(CliMsg::FreezeAccountResult(result)) => match result.error {
+                        Some(error) => println!("Freezing account failed: {}", error),
+                        None => println!("Account frozen: {:?}", result.request),
+                    },
+                    Message::Cli(CliMsg::UnfreezeAccountResult(result)) => match result.error {
+                        Some(error) => println!("Unfreezing account failed: {}", error),
+                        None => println!("Account {} unfrozen", result.request.account_id),
+                    },
+                    Message::Cli(CliMsg::FrozenAccountsResult(result)) => match result.error {
+                        Some(error) => println!("Listing frozen accounts failed: {}", error),
+                        None => match serde_json::to_string_pretty(&result.accounts) {
+                            Ok(accounts) => println!("Frozen accounts:\n{}", accounts),
+                            Err(_) => println!("Frozen accounts: {:?}", result.accounts),
+                        },
+                    },
+                    Message::Cli(CliMsg::DeadLettersResult(result)) => match result.error {
+                        Some(error) => println!("Listing dead letters failed: {}", error),
+                        None => match serde_json::to_string_pretty(&result.dead_letters) {
+                            Ok(dead_letters) => println!("Dead letters:\n{}", dead_letters),
+                            Err(_) => println!("Dead letters: {:?}", result.dead_letters),
+                        },
+                    },
+                    Message::Cli(CliMsg::ReplayDeadLetterResult(result)) => match result.error {
+                        Some(error) => println!("Replaying dead letter failed: {}", error),
+                        None => println!("Dead letter {} replayed", result.request.id),
+                    },
+                    Message::Cli(CliMsg::ReloadConfigResult(result)) => match result.error {
+                        Some(error) => println!("Reloading config failed: {}", error),
+                        None => println!("Reloaded: {}", result.reloaded.join(", ")),
+                    },
+                    Message::Cli(CliMsg::RebalanceResult(result)) => match result.error {
+                        Some(error) => println!("Rebalancing failed: {}", error),
+                        None => println!(
+                            "Rebalanced {} sats out of channel {}, fee: {} msat",
+                            result.request.amount_sats,
+                            result.request.outgoing_chan_id,
+                            result.fee_msat.unwrap_or(0)
+                        ),
+                    },
+                    Message::Cli(CliMsg::LiquiditySwapsResult(result)) => match result.error {
+                        Some(error) => println!("Listing liquidity swaps failed: {}", error),
+                        None => match serde_json::to_string_pretty(&result.swaps) {
+                            Ok(swaps) => println!("Liquidity swaps:\n{}", swaps),
+                            Err(_) => println!("Liquidity swaps: {:?}", result.swaps),
+                        },
+                    },
+                    Message::Cli(CliMsg::PendingLnurlWithdrawalsResult(result)) => {
+                        match serde_json::to_string_pretty(&result.withdrawals) {
+                            Ok(withdrawals) => println!("Pending LNURL withdrawals:\n{}", withdrawals),
+                            Err(_) => println!("Pending LNURL withdrawals: {:?}", result.withdrawals),
+                        }
+                    }
                     _ => {
                         println!("Received unhandled message: {:?}", msg)
                     }
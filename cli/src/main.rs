@@ -3,6 +3,7 @@ use structopt::StructOpt;
 use utils::xzmq::SocketContext;
 
 fn main() {
+    utils::config::register_currencies_from_env().expect("Failed to register currencies.");
     let settings = utils::config::get_config_from_env::<CliSettings>().expect("Failed to load settings.");
 
     let context = SocketContext::new();
@@ -0,0 +1,446 @@
+use bigdecimal::BigDecimal;
+use core_types::{currency_registry, Currency};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use models::accounts::{Account, InsertableAccount, UpdateAccount};
+use models::invoices::InsertableInvoice;
+use models::summary_transactions::SummaryTransaction;
+use models::transactions::Transaction;
+use models::users::{hash, InsertableUser};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+const BANK_UID: i32 = 23193913;
+const MILLIS_IN_DAY: u64 = 86_400_000;
+const INVOICE_EXPIRY_SECS: u64 = 3600;
+const MEMOS: [&str; 6] = [
+    "Coffee",
+    "Groceries",
+    "Lunch",
+    "Donation",
+    "Rent share",
+    "Concert tickets",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeedSettings {
+    pub psql_url: String,
+}
+
+/// Populates a development database with demo users, accounts, invoices and transaction history.
+/// The bank has to be stopped while seeding and its ledger journal removed, so it loads the seeded
+/// accounts from the database on its next start.
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(name = "seed")]
+pub struct SeedOptions {
+    /// Runs with the same seed and `until` create the same data.
+    #[structopt(long = "seed", default_value = "1")]
+    pub seed: u64,
+    #[structopt(long = "users", default_value = "10")]
+    pub users: usize,
+    /// Fiat accounts every user gets next to the BTC account, at most one per currency.
+    #[structopt(long = "fiat_accounts", default_value = "1")]
+    pub fiat_accounts: usize,
+    /// Invoices per user, most of them are settled and deposit to the user's BTC account.
+    #[structopt(long = "invoices", default_value = "5")]
+    pub invoices: usize,
+    /// Transfers between random users, paid from the BTC balance the sender has at the time.
+    #[structopt(long = "transfers", default_value = "50")]
+    pub transfers: usize,
+    /// Days of history before `until`.
+    #[structopt(long = "days", default_value = "30")]
+    pub days: u64,
+    /// End of the history in millis, defaults to now.
+    #[structopt(long = "until")]
+    pub until: Option<u64>,
+    /// Usernames are the prefix followed by the number of the user.
+    #[structopt(long = "prefix", default_value = "demo")]
+    pub prefix: String,
+    #[structopt(long = "password", default_value = "demo-password")]
+    pub password: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SeedSummary {
+    pub users: usize,
+    pub accounts: usize,
+    pub invoices: usize,
+    pub transactions: usize,
+}
+
+/// SplitMix64, it is small and doesn't change between versions, so a seed always yields the same data.
+pub struct SeedRng(u64);
+
+impl SeedRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `[low, high)`.
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        if high <= low {
+            return low;
+        }
+        low + self.next_u64() % (high - low)
+    }
+
+    pub fn uuid(&mut self) -> Uuid {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+        uuid::Builder::from_bytes(bytes)
+            .set_variant(uuid::Variant::RFC4122)
+            .set_version(uuid::Version::Random)
+            .build()
+    }
+
+    pub fn hex(&mut self, len: usize) -> String {
+        (0..len).map(|_| format!("{:02x}", self.next_u64() as u8)).collect()
+    }
+}
+
+enum Event {
+    Deposit { user: usize, sats: u64 },
+    Transfer { from: usize, to: usize, share: u64 },
+}
+
+struct SeededAccount {
+    currency: Currency,
+    balance: Decimal,
+}
+
+struct Seeder<'a> {
+    conn: &'a PgConnection,
+    options: &'a SeedOptions,
+    rng: SeedRng,
+    accounts: HashMap<Uuid, SeededAccount>,
+    tx_seq: u64,
+    summary: SeedSummary,
+}
+
+fn to_bigdecimal(value: Decimal) -> BigDecimal {
+    BigDecimal::from_str(&value.to_string()).unwrap_or_else(|_| BigDecimal::from(0))
+}
+
+fn sats_to_btc(sats: u64) -> Decimal {
+    Decimal::new(sats as i64, 8)
+}
+
+/// Seeds the database in a single database transaction, nothing is written if any step fails.
+pub fn seed(conn: &PgConnection, options: &SeedOptions) -> Result<SeedSummary, DieselError> {
+    conn.transaction(|| {
+        let mut seeder = Seeder {
+            conn,
+            options,
+            rng: SeedRng::new(options.seed),
+            accounts: HashMap::new(),
+            tx_seq: 0,
+            summary: SeedSummary::default(),
+        };
+        seeder.run()?;
+        Ok(seeder.summary)
+    })
+}
+
+impl<'a> Seeder<'a> {
+    fn run(&mut self) -> Result<(), DieselError> {
+        let until = self.options.until.unwrap_or_else(utils::time::time_now);
+        let from = until.saturating_sub(self.options.days * MILLIS_IN_DAY);
+
+        let liabilities_account_id = self.bank_liabilities_account()?;
+
+        let mut users = Vec::with_capacity(self.options.users);
+        for number in 0..self.options.users {
+            users.push(self.create_user(number)?);
+        }
+
+        let mut events = Vec::new();
+        for (user, (uid, btc_account_id)) in users.iter().enumerate() {
+            for _ in 0..self.options.invoices {
+                if let Some(event) = self.create_invoice(*uid, *btc_account_id, user, from, until)? {
+                    events.push(event);
+                }
+            }
+        }
+        if users.len() > 1 {
+            for _ in 0..self.options.transfers {
+                let from_user = self.rng.range(0, users.len() as u64) as usize;
+                let to_user = (from_user + self.rng.range(1, users.len() as u64) as usize) % users.len();
+                let created_at = self.rng.range(from, until);
+                let share = self.rng.range(1, 50);
+                events.push((
+                    created_at,
+                    Event::Transfer {
+                        from: from_user,
+                        to: to_user,
+                        share,
+                    },
+                ));
+            }
+        }
+
+        // Booked in the order they happened so no balance ever goes negative.
+        events.sort_by_key(|(created_at, _)| *created_at);
+        for (created_at, event) in events {
+            match event {
+                Event::Deposit { user, sats } => {
+                    let (uid, account_id) = users[user];
+                    self.book(
+                        (BANK_UID, liabilities_account_id),
+                        (uid, account_id),
+                        sats_to_btc(sats),
+                        created_at,
+                        "ExternalDeposit",
+                    )?;
+                }
+                Event::Transfer { from, to, share } => {
+                    let (from_uid, from_account_id) = users[from];
+                    let balance = self.accounts[&from_account_id].balance;
+                    let amount = (balance * Decimal::new(share as i64, 2)).round_dp(8);
+                    if amount <= Decimal::ZERO {
+                        continue;
+                    }
+                    self.book(
+                        (from_uid, from_account_id),
+                        users[to],
+                        amount,
+                        created_at,
+                        "InternalTransfer",
+                    )?;
+                }
+            }
+        }
+
+        for (account_id, account) in self.accounts.iter() {
+            UpdateAccount {
+                account_id: *account_id,
+                balance: Some(to_bigdecimal(account.balance)),
+                currency: account.currency.to_string(),
+                ..Default::default()
+            }
+            .update(self.conn, *account_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the BTC account deposits are booked from, it is created if the bank never ran.
+    fn bank_liabilities_account(&mut self) -> Result<Uuid, DieselError> {
+        let existing = Account::get_bank_liabilities(self.conn)?
+            .into_iter()
+            .find(|account| account.currency == Currency::BTC.to_string());
+        let (account_id, balance) = match existing {
+            Some(account) => (
+                account.account_id,
+                Decimal::from_str(&account.balance.to_string()).unwrap_or_default(),
+            ),
+            None => {
+                let account_id = self.insert_account(BANK_UID, Currency::BTC, "External")?;
+                (account_id, Decimal::ZERO)
+            }
+        };
+        self.accounts.insert(
+            account_id,
+            SeededAccount {
+                currency: Currency::BTC,
+                balance,
+            },
+        );
+        Ok(account_id)
+    }
+
+    fn insert_account(&mut self, uid: i32, currency: Currency, account_type: &str) -> Result<Uuid, DieselError> {
+        let account_id = InsertableAccount {
+            account_id: self.rng.uuid(),
+            balance: None,
+            currency: currency.to_string(),
+            account_type: account_type.to_string(),
+            uid,
+            account_class: String::from("Cash"),
+            label: None,
+        }
+        .insert(self.conn)?;
+        self.summary.accounts += 1;
+        Ok(account_id)
+    }
+
+    /// Creates the user with its accounts and returns its uid and BTC account.
+    fn create_user(&mut self, number: usize) -> Result<(i32, Uuid), DieselError> {
+        let username = format!("{}{}", self.options.prefix, number);
+        let uid = InsertableUser {
+            password: hash(&username, &self.options.password),
+            username,
+            is_internal: false,
+        }
+        .insert(self.conn)?;
+        self.summary.users += 1;
+
+        let btc_account_id = self.insert_account(uid, Currency::BTC, "Internal")?;
+        self.accounts.insert(
+            btc_account_id,
+            SeededAccount {
+                currency: Currency::BTC,
+                balance: Decimal::ZERO,
+            },
+        );
+
+        for currency in currency_registry::synthetics()
+            .into_iter()
+            .take(self.options.fiat_accounts)
+        {
+            let account_id = self.insert_account(uid, currency, "Internal")?;
+            self.accounts.insert(
+                account_id,
+                SeededAccount {
+                    currency,
+                    balance: Decimal::ZERO,
+                },
+            );
+        }
+        Ok((uid, btc_account_id))
+    }
+
+    /// Creates an invoice, settled ones return the deposit they caused.
+    fn create_invoice(
+        &mut self,
+        uid: i32,
+        account_id: Uuid,
+        user: usize,
+        from: u64,
+        until: u64,
+    ) -> Result<Option<(u64, Event)>, DieselError> {
+        let created_at = self.rng.range(from, until);
+        let sats = self.rng.range(1_000, 1_000_000);
+        let outcome = self.rng.range(0, 10);
+        let settled = outcome < 7;
+        let expires_at = created_at + INVOICE_EXPIRY_SECS * 1000;
+        let expired = !settled && expires_at < until;
+        let settled_at = created_at + self.rng.range(1_000, 600_000).min(until - created_at);
+        let payment_hash = self.rng.hex(32);
+        let memo = MEMOS[self.rng.range(0, MEMOS.len() as u64) as usize];
+
+        InsertableInvoice {
+            payment_request: format!("lnbcrt{}n1demo{}", sats * 10, self.rng.hex(16)),
+            rhash: payment_hash.clone(),
+            payment_hash,
+            created_at: created_at as i64,
+            value: sats as i64,
+            value_msat: sats as i64 * 1000,
+            expiry: INVOICE_EXPIRY_SECS as i64,
+            settled,
+            add_index: -1,
+            settled_date: if settled { settled_at as i64 } else { 0 },
+            account_id: account_id.to_string(),
+            uid,
+            incoming: true,
+            owner: None,
+            fees: None,
+            currency: Some(Currency::BTC.to_string()),
+            target_account_currency: Some(Currency::BTC.to_string()),
+            reference: Some(memo.to_string()),
+            expired,
+            metadata_fields: None,
+        }
+        .insert(self.conn)?;
+        self.summary.invoices += 1;
+
+        if !settled {
+            return Ok(None);
+        }
+        Ok(Some((settled_at, Event::Deposit { user, sats })))
+    }
+
+    /// Books a transaction in the accounts' currency with its summary.
+    fn book(
+        &mut self,
+        outbound: (i32, Uuid),
+        inbound: (i32, Uuid),
+        amount: Decimal,
+        created_at: u64,
+        reference: &str,
+    ) -> Result<(), DieselError> {
+        let (outbound_uid, outbound_account_id) = outbound;
+        let (inbound_uid, inbound_account_id) = inbound;
+        let currency = self.accounts[&outbound_account_id].currency.to_string();
+        let tx_type = if outbound_uid == BANK_UID {
+            "External"
+        } else {
+            "Internal"
+        };
+
+        self.tx_seq += 1;
+        let txid = format!("seed-{}-{}", self.options.seed, self.tx_seq);
+
+        Transaction {
+            txid: txid.clone(),
+            created_at: created_at as i64,
+            outbound_amount: to_bigdecimal(amount),
+            inbound_amount: to_bigdecimal(amount),
+            outbound_account_id,
+            inbound_account_id,
+            outbound_uid,
+            inbound_uid,
+            outbound_currency: currency.clone(),
+            inbound_currency: currency.clone(),
+            exchange_rate: BigDecimal::from(1),
+            tx_type: tx_type.to_string(),
+            fees: BigDecimal::from(0),
+        }
+        .insert(self.conn)?;
+
+        SummaryTransaction {
+            txid: format!("{}-summary", txid),
+            fee_txid: None,
+            outbound_txid: Some(txid.clone()),
+            inbound_txid: Some(txid),
+            created_at: created_at as i64,
+            outbound_amount: to_bigdecimal(amount),
+            inbound_amount: to_bigdecimal(amount),
+            outbound_account_id,
+            inbound_account_id,
+            outbound_uid,
+            inbound_uid,
+            outbound_currency: currency.clone(),
+            inbound_currency: currency,
+            exchange_rate: BigDecimal::from(1),
+            tx_type: tx_type.to_string(),
+            fees: BigDecimal::from(0),
+            reference: Some(reference.to_string()),
+        }
+        .insert(self.conn)?;
+
+        if let Some(account) = self.accounts.get_mut(&outbound_account_id) {
+            account.balance -= amount;
+        }
+        if let Some(account) = self.accounts.get_mut(&inbound_account_id) {
+            account.balance += amount;
+        }
+        self.summary.transactions += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_yields_same_values() {
+        let mut first = SeedRng::new(42);
+        let mut second = SeedRng::new(42);
+        assert_eq!(first.uuid(), second.uuid());
+        assert_eq!(first.hex(32), second.hex(32));
+        assert_eq!(first.range(10, 20), second.range(10, 20));
+        assert_ne!(SeedRng::new(1).next_u64(), SeedRng::new(2).next_u64());
+    }
+}
@@ -0,0 +1,208 @@
+//! Typed client for services that talk to the bank over its message bus, e.g. bots or merchant backends.
+//!
+//! The client pushes requests to the bank like the api does and waits on the bank's publish socket for
+//! the response with the same request id. Every message the bank publishes to the api is visible to
+//! the client, so it must only be deployed next to the api.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use core_types::{Currency, Money, UserId};
+use msgs::api::*;
+use msgs::wire::WireError;
+use msgs::Message;
+use uuid::Uuid;
+
+pub mod requests;
+
+pub use requests::BankRequest;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum ClientError {
+    Transport(zmq::Error),
+    Serialization(WireError),
+    Timeout,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(err) => write!(f, "Transport: {}", err),
+            ClientError::Serialization(err) => write!(f, "Serialization: {}", err),
+            ClientError::Timeout => write!(f, "Timeout"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<zmq::Error> for ClientError {
+    fn from(err: zmq::Error) -> Self {
+        ClientError::Transport(err)
+    }
+}
+
+impl From<WireError> for ClientError {
+    fn from(err: WireError) -> Self {
+        ClientError::Serialization(err)
+    }
+}
+
+pub struct Client {
+    pusher: zmq::Socket,
+    subscriber: zmq::Socket,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Connects to the addresses configured as `api_zmq_push_address` and `api_zmq_subscribe_address`.
+    pub fn connect(push_address: &str, subscribe_address: &str) -> Result<Self, ClientError> {
+        let context = zmq::Context::new();
+
+        let pusher = context.socket(zmq::PUSH)?;
+        pusher.connect(push_address)?;
+
+        let subscriber = context.socket(zmq::SUB)?;
+        subscriber.set_subscribe(&[])?;
+        subscriber.connect(subscribe_address)?;
+
+        Ok(Self {
+            pusher,
+            subscriber,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets how long requests wait for their response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends a request and waits for its response. Messages for other requests are skipped.
+    pub fn request<R: BankRequest>(&self, request: R) -> Result<R::Response, ClientError> {
+        let req_id = request.req_id();
+        let payload = msgs::wire::encode(&request.into_message())?;
+        self.pusher.send(payload, 0)?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(ClientError::Timeout);
+            }
+
+            if self.subscriber.poll(zmq::POLLIN, remaining.as_millis() as i64)? == 0 {
+                return Err(ClientError::Timeout);
+            }
+
+            let frames = self.subscriber.recv_multipart(0)?;
+            let message = match frames.last().map(|frame| msgs::wire::decode(frame)) {
+                Some(Ok(message)) => message,
+                _ => continue,
+            };
+
+            if let Some(response) = R::response(message, req_id) {
+                return Ok(response);
+            }
+        }
+    }
+
+    pub fn balances(&self, uid: UserId) -> Result<Balances, ClientError> {
+        self.request(GetBalances {
+            req_id: Uuid::new_v4(),
+            uid,
+        })
+    }
+
+    /// Creates an invoice over `amount` that is credited to the account of `amount`'s currency.
+    pub fn create_invoice(&self, uid: UserId, amount: Money, meta: String) -> Result<InvoiceResponse, ClientError> {
+        self.request(InvoiceRequest {
+            req_id: Uuid::new_v4(),
+            uid,
+            currency: amount.currency,
+            amount,
+            meta,
+            metadata: None,
+            account_id: None,
+            target_account_currency: None,
+            metadata_fields: None,
+            idempotency_key: None,
+            comment: None,
+            payer_data: None,
+        })
+    }
+
+    /// Pays a bolt11 invoice from the account of `currency`. The response might only report that a retry was
+    /// scheduled, the final outcome is published later with the same request id.
+    pub fn pay_invoice(
+        &self,
+        uid: UserId,
+        payment_request: String,
+        currency: Currency,
+        idempotency_key: Option<String>,
+    ) -> Result<PaymentResponse, ClientError> {
+        self.request(PaymentRequest {
+            req_id: Uuid::new_v4(),
+            uid,
+            payment_request: Some(payment_request),
+            currency,
+            receipient: None,
+            target_account_id: None,
+            account_id: None,
+            destination: None,
+            keysend_message: None,
+            amount: None,
+            rate: None,
+            fees: None,
+            requoted: false,
+            idempotency_key,
+            origin: None,
+            totp_code: None,
+        })
+    }
+
+    pub fn quote(
+        &self,
+        uid: UserId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+    ) -> Result<QuoteResponse, ClientError> {
+        self.request(QuoteRequest {
+            req_id: Uuid::new_v4(),
+            uid,
+            amount,
+            from,
+            to,
+        })
+    }
+
+    /// Swaps between two accounts of the user, optionally at the rate of a previous quote.
+    /// The quote is passed on with its signature if it has one.
+    pub fn swap(
+        &self,
+        uid: UserId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+        quote: Option<&QuoteResponse>,
+    ) -> Result<SwapResponse, ClientError> {
+        let quote_id = quote.and_then(|quote| quote.quote_id);
+        let signed_quote = quote.and_then(|quote| quote.firm_quote());
+        self.request(SwapRequest {
+            req_id: Uuid::new_v4(),
+            uid,
+            amount,
+            from,
+            to,
+            quote_id,
+            signed_quote,
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
+        })
+    }
+}
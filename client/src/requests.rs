@@ -0,0 +1,59 @@
+use core_types::RequestId;
+use msgs::api::*;
+use msgs::Message;
+
+/// A request the bank answers with exactly one typed response carrying the same request id.
+pub trait BankRequest {
+    type Response;
+
+    fn req_id(&self) -> RequestId;
+
+    fn into_message(self) -> Message;
+
+    /// Returns the response if `message` answers the request with id `req_id`.
+    fn response(message: Message, req_id: RequestId) -> Option<Self::Response>;
+}
+
+macro_rules! bank_request {
+    ($request:ident => $response:ident) => {
+        impl BankRequest for $request {
+            type Response = $response;
+
+            fn req_id(&self) -> RequestId {
+                self.req_id
+            }
+
+            fn into_message(self) -> Message {
+                Message::Api(Api::$request(self))
+            }
+
+            fn response(message: Message, req_id: RequestId) -> Option<Self::Response> {
+                match message {
+                    Message::Api(Api::$response(response)) if response.req_id == req_id => Some(response),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+bank_request!(InvoiceRequest => InvoiceResponse);
+bank_request!(PaymentRequest => PaymentResponse);
+bank_request!(SwapRequest => SwapResponse);
+bank_request!(GetBalances => Balances);
+bank_request!(QuoteRequest => QuoteResponse);
+bank_request!(AvailableCurrenciesRequest => AvailableCurrenciesResponse);
+bank_request!(GetNodeInfoRequest => GetNodeInfoResponse);
+bank_request!(QueryRouteRequest => QueryRouteResponse);
+bank_request!(CreateLnurlWithdrawalRequest => CreateLnurlWithdrawalResponse);
+bank_request!(GetStatusRequest => GetStatusResponse);
+bank_request!(GetReservesReport => GetReservesReportResponse);
+bank_request!(ExportStatementRequest => ExportStatementResponse);
+bank_request!(CashOutRequest => PaymentResponse);
+bank_request!(InternalTransferRequest => InternalTransferResponse);
+bank_request!(CreateAccountRequest => AccountResponse);
+bank_request!(RenameAccountRequest => AccountResponse);
+bank_request!(ArchiveAccountRequest => AccountResponse);
+bank_request!(InviteAccountMemberRequest => AccountMemberResponse);
+bank_request!(AcceptAccountInvitationRequest => AccountMemberResponse);
+bank_request!(RemoveAccountMemberRequest => AccountMemberResponse);
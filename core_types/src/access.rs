@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Where a request entered the api.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestOrigin {
+    pub ip: Option<String>,
+    /// ISO 3166-1 alpha-2 country code.
+    pub country: Option<String>,
+    /// Token id of the API key or device the request was made with, `None` for master tokens.
+    #[serde(default)]
+    pub key_id: Option<i32>,
+}
+
+/// Restricts the origins money-moving requests are accepted from.
+/// Empty lists don't restrict anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessPolicy {
+    /// IP ranges in CIDR notation, e.g. `10.0.0.0/8`. A plain address only matches itself.
+    #[serde(default)]
+    pub allowed_ip_ranges: Vec<String>,
+    /// ISO 3166-1 alpha-2 country codes.
+    #[serde(default)]
+    pub allowed_countries: Vec<String>,
+}
+
+/// Returns whether `range` is an address or a range in CIDR notation.
+pub fn is_valid_ip_range(range: &str) -> bool {
+    let (network, prefix_len) = match range.split_once('/') {
+        Some((network, prefix_len)) => match prefix_len.parse::<u32>() {
+            Ok(prefix_len) => (network, Some(prefix_len)),
+            Err(_) => return false,
+        },
+        None => (range, None),
+    };
+    match network.trim().parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => prefix_len.unwrap_or(32) <= 32,
+        Ok(IpAddr::V6(_)) => prefix_len.unwrap_or(128) <= 128,
+        Err(_) => false,
+    }
+}
+
+/// Returns whether `ip` is part of `range`. Invalid ranges never match.
+pub fn ip_in_range(ip: &IpAddr, range: &str) -> bool {
+    let (network, prefix_len) = match range.split_once('/') {
+        Some((network, prefix_len)) => match prefix_len.parse::<u32>() {
+            Ok(prefix_len) => (network, Some(prefix_len)),
+            Err(_) => return false,
+        },
+        None => (range, None),
+    };
+
+    let network = match network.trim().parse::<IpAddr>() {
+        Ok(network) => network,
+        Err(_) => return false,
+    };
+
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let prefix_len = prefix_len.unwrap_or(32);
+            if prefix_len > 32 {
+                return false;
+            }
+            let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+            u32::from(*ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let prefix_len = prefix_len.unwrap_or(128);
+            if prefix_len > 128 {
+                return false;
+            }
+            let mask = u128::MAX.checked_shl(128 - prefix_len).unwrap_or(0);
+            u128::from(*ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+impl AccessPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.allowed_ip_ranges.is_empty() && self.allowed_countries.is_empty()
+    }
+
+    /// Origins without an ip or country are rejected as soon as the respective restriction is set.
+    pub fn allows(&self, origin: &RequestOrigin) -> bool {
+        if !self.allowed_ip_ranges.is_empty() {
+            let ip = match origin.ip.as_ref().and_then(|ip| ip.parse::<IpAddr>().ok()) {
+                Some(ip) => ip,
+                None => return false,
+            };
+            if !self.allowed_ip_ranges.iter().any(|range| ip_in_range(&ip, range)) {
+                return false;
+            }
+        }
+
+        if !self.allowed_countries.is_empty() {
+            let country = match &origin.country {
+                Some(country) => country,
+                None => return false,
+            };
+            if !self
+                .allowed_countries
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(country))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{Currency, Symbol};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+/// Description of a currency the services accept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrencyInfo {
+    pub code: String,
+    /// Perpetual the dealer hedges the currency with. Currencies without one aren't synthetics.
+    #[serde(default)]
+    pub symbol: Option<Symbol>,
+    /// Whether the currency is backed by an asset the bank holds instead of a position of the dealer.
+    #[serde(default)]
+    pub asset_backed: bool,
+}
+
+impl CurrencyInfo {
+    pub fn currency(&self) -> Result<Currency, String> {
+        Currency::new(&self.code)
+    }
+
+    fn synthetic(code: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            symbol: Some(format!("BTC{}.PERP", code)),
+            asset_backed: false,
+        }
+    }
+
+    fn plain(code: &str, asset_backed: bool) -> Self {
+        Self {
+            code: code.to_string(),
+            symbol: None,
+            asset_backed,
+        }
+    }
+}
+
+/// Currencies section of the settings of a service. Messages and the rest of the settings only hold registered
+/// currencies, so it is loaded and registered first.
+#[derive(Debug, Default, Deserialize)]
+pub struct CurrencySettings {
+    #[serde(default)]
+    pub currencies: Vec<CurrencyInfo>,
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: RwLock<Vec<(Currency, CurrencyInfo)>> = RwLock::new(
+        vec![
+            CurrencyInfo::plain("BTC", false),
+            CurrencyInfo::synthetic("USD"),
+            CurrencyInfo::synthetic("EUR"),
+            CurrencyInfo::synthetic("GBP"),
+            CurrencyInfo::plain("USDT", true),
+            CurrencyInfo::plain("LBTC", true),
+        ]
+        .into_iter()
+        .map(|info| (Currency::new(&info.code).expect("built-in currency code"), info))
+        .collect()
+    );
+}
+
+/// Adds a currency or replaces the description of a known one.
+pub fn register(info: CurrencyInfo) -> Result<Currency, String> {
+    let currency = info.currency()?;
+    let mut registry = REGISTRY.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match registry.iter_mut().find(|(known, _)| *known == currency) {
+        Some(entry) => entry.1 = info,
+        None => registry.push((currency, info)),
+    }
+    Ok(currency)
+}
+
+/// Registers the currencies of the settings of a service, usually right after they are loaded.
+pub fn register_all(infos: &[CurrencyInfo]) -> Result<(), String> {
+    for info in infos.iter() {
+        register(info.clone())?;
+    }
+    Ok(())
+}
+
+pub fn get(currency: Currency) -> Option<CurrencyInfo> {
+    let registry = REGISTRY.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    registry
+        .iter()
+        .find(|(known, _)| *known == currency)
+        .map(|(_, info)| info.clone())
+}
+
+/// All known currencies in the order they were registered.
+pub fn all() -> Vec<CurrencyInfo> {
+    let registry = REGISTRY.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    registry.iter().map(|(_, info)| info.clone()).collect()
+}
+
+/// Fiat synthetics, the currencies that are only available while the dealer can hedge them.
+pub fn synthetics() -> Vec<Currency> {
+    let registry = REGISTRY.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    registry
+        .iter()
+        .filter(|(_, info)| info.symbol.is_some())
+        .map(|(currency, _)| *currency)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn registered_synthetics_parse_and_hedge() {
+        assert!(Currency::from_str("jpy").is_err());
+        assert_eq!(Currency::from_str("usd"), Ok(Currency::USD));
+        assert!(Currency::LBTC.is_asset_backed());
+
+        let jpy = register(CurrencyInfo::synthetic("JPY")).unwrap();
+        assert_eq!(Currency::from_str("jpy"), Ok(jpy));
+        assert_eq!(jpy.to_string(), "JPY");
+        assert_eq!(Symbol::try_from(jpy), Ok("BTCJPY.PERP".to_string()));
+        assert!(Symbol::try_from(Currency::USDT).is_err());
+        assert!(synthetics().contains(&jpy));
+        assert!(!synthetics().contains(&Currency::BTC));
+
+        assert!(register(CurrencyInfo::plain("TOOLONGCODE", false)).is_err());
+    }
+}
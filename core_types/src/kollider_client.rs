@@ -9,7 +9,7 @@ pub type OrderId = u64;
 pub type UserId = u64;
 pub type ExtOrderId = Uuid;
 
-#[derive(Copy, Clone, Deserialize, Serialize, Debug)]
+#[derive(Copy, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
 pub enum Side {
     Bid,
     Ask,
@@ -8,9 +8,14 @@ use std::fmt;
 use crate::kollider_client::Side;
 use serde::{Deserialize, Serialize};
 
+pub mod access;
+pub mod currency_registry;
 pub mod kollider_client;
 
 pub const SATS_IN_BITCOIN: Decimal = dec!(100000000.0);
+pub const MSATS_IN_BITCOIN: Decimal = dec!(100000000000.0);
+/// BTC amounts are accounted in msats but shown to users in whole sats.
+pub const BTC_DISPLAY_DECIMALS: u32 = 8;
 
 #[derive(Debug, Clone, Copy)]
 pub enum TxState {
@@ -57,6 +62,12 @@ impl FromStr for AccountType {
 pub enum AccountClass {
     Cash,
     Fees,
+    /// Realized profit and loss of the dealer's hedges.
+    Pnl,
+    /// Costs the operator pays out of the bank's funds, e.g. routing fees of rebalancing.
+    Expense,
+    /// Savings of a user, funds only leave it after the vault withdrawal delay.
+    Vault,
 }
 
 impl fmt::Display for AccountClass {
@@ -64,6 +75,9 @@ impl fmt::Display for AccountClass {
         let sign = match self {
             Self::Cash => "Cash",
             Self::Fees => "Fee",
+            Self::Pnl => "Pnl",
+            Self::Expense => "Expense",
+            Self::Vault => "Vault",
         };
 
         write!(f, "{}", sign)
@@ -76,19 +90,167 @@ impl FromStr for AccountClass {
     fn from_str(accountType: &str) -> Result<AccountClass, Self::Err> {
         match accountType {
             "Cash" => Ok(AccountClass::Cash),
-            "Fees" => Ok(AccountClass::Fees),
+            "Fee" | "Fees" => Ok(AccountClass::Fees),
+            "Pnl" => Ok(AccountClass::Pnl),
+            "Expense" => Ok(AccountClass::Expense),
+            "Vault" => Ok(AccountClass::Vault),
             _ => Err("unknown account class".to_string()),
         }
     }
 }
 
-/// Available currencies.
-#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, Eq, Hash)]
-pub enum Currency {
-    USD,
-    GBP,
-    EUR,
-    BTC,
+/// Role of a member of a shared account.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum MemberRole {
+    /// Sees the balance of the account.
+    Viewer,
+    /// Pays from the account up to the member's spend limit.
+    Spender,
+    /// Pays without limit and manages the members of the account.
+    Admin,
+}
+
+impl fmt::Display for MemberRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let role = match self {
+            Self::Viewer => "Viewer",
+            Self::Spender => "Spender",
+            Self::Admin => "Admin",
+        };
+
+        write!(f, "{}", role)
+    }
+}
+
+impl FromStr for MemberRole {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<MemberRole, Self::Err> {
+        match role {
+            "Viewer" => Ok(MemberRole::Viewer),
+            "Spender" => Ok(MemberRole::Spender),
+            "Admin" => Ok(MemberRole::Admin),
+            _ => Err("unknown member role".to_string()),
+        }
+    }
+}
+
+const CURRENCY_CODE_LEN: usize = 8;
+
+/// Code of a currency known to the [`currency_registry`], e.g. `USD`.
+/// The built-in currencies are available as constants, further fiat synthetics are registered at startup.
+#[derive(PartialEq, Clone, Copy, Eq, Hash)]
+pub struct Currency([u8; CURRENCY_CODE_LEN]);
+
+impl Currency {
+    pub const USD: Currency = Currency::from_code(b"USD");
+    pub const GBP: Currency = Currency::from_code(b"GBP");
+    pub const EUR: Currency = Currency::from_code(b"EUR");
+    pub const BTC: Currency = Currency::from_code(b"BTC");
+    /// Tether held as a Taproot Asset rather than hedged by the dealer.
+    pub const USDT: Currency = Currency::from_code(b"USDT");
+    /// Bitcoin pegged into the Liquid sidechain.
+    pub const LBTC: Currency = Currency::from_code(b"LBTC");
+
+    const fn from_code(code: &[u8]) -> Self {
+        let mut bytes = [0_u8; CURRENCY_CODE_LEN];
+        let mut i = 0;
+        while i < code.len() {
+            bytes[i] = code[i];
+            i += 1;
+        }
+        Currency(bytes)
+    }
+
+    /// Currency for a code of up to 8 ascii letters or digits, whether it is registered or not.
+    pub fn new(code: &str) -> Result<Self, String> {
+        if code.is_empty() || code.len() > CURRENCY_CODE_LEN || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("invalid currency code {}", code));
+        }
+        Ok(Currency::from_code(code.to_uppercase().as_bytes()))
+    }
+
+    pub fn code(&self) -> &str {
+        let len = self.0.iter().position(|b| *b == 0).unwrap_or(CURRENCY_CODE_LEN);
+        std::str::from_utf8(&self.0[..len]).unwrap_or_default()
+    }
+
+    /// Whether the currency is backed by an asset the bank holds instead of a position of the dealer.
+    pub fn is_asset_backed(&self) -> bool {
+        currency_registry::get(*self).map_or(false, |info| info.asset_backed)
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Currencies in the order of the variants of the enum they used to be, binary formats still encode them by index.
+const LEGACY_CURRENCIES: [Currency; 4] = [Currency::USD, Currency::GBP, Currency::EUR, Currency::BTC];
+const LEGACY_VARIANTS: [&str; 5] = ["USD", "GBP", "EUR", "BTC", "Other"];
+
+/// Human readable formats write the code. Binary formats keep the layout of the enum, so legacy bincode messages
+/// stay readable, and encode currencies registered later as an extra variant holding the code.
+impl Serialize for Currency {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(self.code());
+        }
+        match LEGACY_CURRENCIES.iter().position(|legacy| legacy == self) {
+            Some(index) => serializer.serialize_unit_variant("Currency", index as u32, LEGACY_VARIANTS[index]),
+            None => serializer.serialize_newtype_variant(
+                "Currency",
+                LEGACY_CURRENCIES.len() as u32,
+                LEGACY_VARIANTS[LEGACY_CURRENCIES.len()],
+                self.code(),
+            ),
+        }
+    }
+}
+
+/// Only registered currencies are accepted, the same as when a code is parsed.
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let currency = if deserializer.is_human_readable() {
+            let code = String::deserialize(deserializer)?;
+            Currency::new(&code).map_err(serde::de::Error::custom)?
+        } else {
+            deserializer.deserialize_enum("Currency", &LEGACY_VARIANTS, LegacyCurrencyVisitor)?
+        };
+        match currency_registry::get(currency) {
+            Some(_) => Ok(currency),
+            None => Err(serde::de::Error::custom(format!("unknown currency {}", currency))),
+        }
+    }
+}
+
+struct LegacyCurrencyVisitor;
+
+impl<'de> serde::de::Visitor<'de> for LegacyCurrencyVisitor {
+    type Value = Currency;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a currency")
+    }
+
+    fn visit_enum<A: serde::de::EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        use serde::de::{Error, VariantAccess};
+
+        let (index, variant) = data.variant::<u32>()?;
+        match LEGACY_CURRENCIES.get(index as usize) {
+            Some(currency) => {
+                variant.unit_variant()?;
+                Ok(*currency)
+            }
+            None if index as usize == LEGACY_CURRENCIES.len() => {
+                let code = variant.newtype_variant::<String>()?;
+                Currency::new(&code).map_err(A::Error::custom)
+            }
+            None => Err(A::Error::custom(format!("unknown currency variant {}", index))),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
@@ -101,10 +263,9 @@ pub enum Denom {
 impl Denom {
     pub fn from_currency(currency: Currency) -> Self {
         match currency {
-            Currency::BTC => Denom::Sats(100000000),
-            Currency::USD => Denom::MilliCents(100000),
+            Currency::BTC | Currency::LBTC => Denom::Sats(100000000),
             Currency::GBP => Denom::MilliPence(100000),
-            Currency::EUR => Denom::MilliCents(100000),
+            _ => Denom::MilliCents(100000),
         }
     }
 
@@ -119,14 +280,7 @@ impl Denom {
 
 impl fmt::Display for Currency {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let sign = match self {
-            Self::BTC => "BTC",
-            Self::USD => "USD",
-            Self::GBP => "GBP",
-            Self::EUR => "EUR",
-        };
-
-        write!(f, "{}", sign)
+        write!(f, "{}", self.code())
     }
 }
 
@@ -134,25 +288,21 @@ impl FromStr for Currency {
     type Err = String;
 
     fn from_str(currency: &str) -> Result<Currency, Self::Err> {
-        let currency = currency.to_lowercase();
-        match &currency[..] {
-            "btc" => Ok(Currency::BTC),
-            "eur" => Ok(Currency::EUR),
-            "gbp" => Ok(Currency::GBP),
-            "usd" => Ok(Currency::USD),
+        match Currency::new(currency) {
+            Ok(currency) if currency_registry::get(currency).is_some() => Ok(currency),
             _ => Err("unknown currency".to_string()),
         }
     }
 }
 
-impl From<Currency> for Symbol {
-    fn from(currency: Currency) -> Self {
-        match currency {
-            Currency::USD => String::from("BTCUSD.PERP"),
-            Currency::EUR => String::from("BTCEUR.PERP"),
-            Currency::GBP => String::from("BTCGBP.PERP"),
-            Currency::BTC => panic!("Incorrect usage"),
-        }
+/// Perpetual a currency is hedged with, only synthetics have one.
+impl TryFrom<Currency> for Symbol {
+    type Error = String;
+
+    fn try_from(currency: Currency) -> Result<Self, Self::Error> {
+        currency_registry::get(currency)
+            .and_then(|info| info.symbol)
+            .ok_or_else(|| format!("{} is not hedged with a perpetual", currency))
     }
 }
 
@@ -169,6 +319,16 @@ pub struct Account {
     pub currency: Currency,
     pub account_type: AccountType,
     pub account_class: AccountClass,
+    /// Funds that moved but aren't settled yet and therefore aren't part of `balance`.
+    /// In-flight withdrawals are negative, deposits waiting for conversion positive.
+    #[serde(default)]
+    pub pending_balance: Decimal,
+    /// Name the user gave a sub-account, e.g. savings.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Archived accounts keep their history but no longer receive funds.
+    #[serde(default)]
+    pub archived: bool,
 }
 
 impl Account {
@@ -178,7 +338,10 @@ impl Account {
             account_type,
             account_class,
             balance: dec!(0),
+            pending_balance: dec!(0),
             account_id: Uuid::new_v4(),
+            label: None,
+            archived: false,
         }
     }
 }
@@ -205,12 +368,12 @@ pub struct ConversionInfo {
 }
 
 impl ConversionInfo {
-    pub fn new(from: Currency, to: Currency) -> Self {
+    pub fn new(from: Currency, to: Currency) -> Result<Self, String> {
         if from == to {
-            panic!("Conversion between the same currency is not supported")
+            return Err("Conversion between the same currency is not supported".to_string());
         }
         if !matches!(from, Currency::BTC) && !matches!(to, Currency::BTC) {
-            panic!("Conversions must involve BTC")
+            return Err("Conversions must involve BTC".to_string());
         }
         let fiat = {
             if matches!(from, Currency::BTC) {
@@ -219,7 +382,7 @@ impl ConversionInfo {
                 from
             }
         };
-        let symbol: Symbol = fiat.into();
+        let symbol = Symbol::try_from(fiat)?;
         let side = {
             if to == fiat {
                 Side::Ask
@@ -227,14 +390,14 @@ impl ConversionInfo {
                 Side::Bid
             }
         };
-        Self {
+        Ok(Self {
             from,
             to,
             base: Currency::BTC,
             quote: fiat,
             symbol,
             side,
-        }
+        })
     }
 
     pub fn is_linear(&self) -> bool {
@@ -245,6 +408,7 @@ impl ConversionInfo {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LndNodeInfo {
     pub identity_pubkey: String,
+    pub alias: String,
     pub uris: Vec<String>,
     pub num_active_channels: u64,
     pub num_pending_channels: u64,
@@ -256,6 +420,7 @@ impl Default for LndNodeInfo {
     fn default() -> Self {
         Self {
             identity_pubkey: String::from(""),
+            alias: String::from(""),
             uris: vec![],
             num_active_channels: 0,
             num_pending_channels: 0,
@@ -294,15 +459,44 @@ impl Money {
 
     pub fn try_sats(&self) -> Result<Decimal, String> {
         if self.currency == Currency::BTC {
-           Ok(self.value * SATS_IN_BITCOIN)
+            Ok(self.value * SATS_IN_BITCOIN)
         } else {
             Err("Is not Bitcoin.".to_string())
         }
     }
+
     pub fn from_sats(value: Decimal) -> Self {
         Self {
             currency: Currency::BTC,
-            value: value / SATS_IN_BITCOIN
+            value: value / SATS_IN_BITCOIN,
+        }
+    }
+
+    pub fn try_msats(&self) -> Result<Decimal, String> {
+        if self.currency == Currency::BTC {
+            Ok(self.value * MSATS_IN_BITCOIN)
+        } else {
+            Err("Is not Bitcoin.".to_string())
+        }
+    }
+
+    pub fn from_msats(value: Decimal) -> Self {
+        Self {
+            currency: Currency::BTC,
+            value: value / MSATS_IN_BITCOIN,
+        }
+    }
+
+    /// Rounds BTC amounts down to whole sats for display. Fiat amounts are left untouched.
+    pub fn round_for_display(&self) -> Money {
+        let value = if self.currency == Currency::BTC {
+            self.value.round_dp_with_strategy(BTC_DISPLAY_DECIMALS, RoundingStrategy::ToZero)
+        } else {
+            self.value
+        };
+        Money {
+            currency: self.currency,
+            value,
         }
     }
 
@@ -331,13 +525,9 @@ impl FromStr for Money {
     type Err = String;
 
     fn from_str(currency: &str) -> Result<Money, Self::Err> {
-        let currency = currency.to_lowercase();
-        match &currency[..] {
-            "btc" => Ok(Money::new(Currency::BTC, None)),
-            "eur" => Ok(Money::new(Currency::EUR, None)),
-            "gbp" => Ok(Money::new(Currency::GBP, None)),
-            "usd" => Ok(Money::new(Currency::USD, None)),
-            _ => Err("unknown money".to_string()),
+        match Currency::from_str(currency) {
+            Ok(currency) => Ok(Money::new(currency, None)),
+            Err(_) => Err("unknown money".to_string()),
         }
     }
 }
@@ -347,6 +537,13 @@ pub struct Rate {
     pub value: Decimal,
     pub quote: Currency,
     pub base: Currency,
+    /// Time in millis at which the rate was obtained.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// Max age in millis after which the rate must not be used anymore.
+    /// No max age means the rate never becomes stale.
+    #[serde(default)]
+    pub max_age_ms: Option<u64>,
 }
 
 impl Rate {
@@ -356,6 +553,8 @@ impl Rate {
             quote,
             base,
             value,
+            timestamp: 0,
+            max_age_ms: None,
         }
     }
 
@@ -367,7 +566,16 @@ impl Rate {
         return Rate {
             base: self.quote,
             quote: self.base,
-            value: Decimal::ONE / self.value
+            value: Decimal::ONE / self.value,
+            timestamp: self.timestamp,
+            max_age_ms: self.max_age_ms,
+        }
+    }
+
+    pub fn is_stale(&self, now: u64) -> bool {
+        match self.max_age_ms {
+            Some(max_age) => now.saturating_sub(self.timestamp) > max_age,
+            None => false,
         }
     }
 }
@@ -378,6 +586,8 @@ impl Default for Rate {
             value: Decimal::MIN,
             quote: Currency::BTC,
             base: Currency:: BTC,
+            timestamp: 0,
+            max_age_ms: None,
         }
     }
 }
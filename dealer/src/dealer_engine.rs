@@ -2,8 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
 use msgs::api::{
-    Api, AvailableCurrenciesResponse, InvoiceResponse, InvoiceResponseError, QuoteResponse, QuoteResponseError,
-    SwapRequest, SwapResponse, SwapResponseError,
+    Api, AvailableCurrenciesResponse, CurrencyPair, FirmQuote, InvoiceResponse, InvoiceResponseError, PairRate,
+    QuoteResponse, QuoteResponseError, RatesUpdate, SwapRequest, SwapResponse, SwapResponseError,
 };
 use msgs::dealer::*;
 use msgs::kollider_client::*;
@@ -13,19 +13,89 @@ use std::ops::{Add, Sub};
 use ws_client::WsClient;
 use xerror::dealer::*;
 
+use core_types::currency_registry::{self, CurrencyInfo};
 use core_types::{kollider_client::*, *};
 
 use rust_decimal::prelude::*;
 use rust_decimal_macros::*;
 
+use std::sync::Mutex;
 use std::time::{Duration, Instant, SystemTime};
-use utils::currencies::get_base_currency_from_symbol;
+use utils::currencies::{get_base_currency_from_symbol, SATS_DECIMALS};
 use utils::time::time_now;
 use utils::xlogging::{init_log, LoggingSettings};
 use uuid::Uuid;
 use xerror::kollider_client::KolliderClientError;
 
+use crate::metrics::DealerMetrics;
+use crate::paper::*;
+use crate::shadow::*;
+use crate::venues::*;
+
 const QUOTE_TTL_MS: u64 = 5000;
+// Max age of a rate before the bank considers it stale and asks for a new one.
+const RATE_MAX_AGE_MS: u64 = 3000;
+// Rates of a pair are published for this long after the api last subscribed to it.
+const RATE_SUBSCRIPTION_TTL_MS: u64 = 30000;
+const DEFAULT_RATES_PUBLISH_INTERVAL_MS: u64 = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BankStateStalenessSettings {
+    /// Seconds without a bank state after which the dealer asks the bank for a new one.
+    pub rerequest_after_secs: u64,
+    /// Seconds without a bank state after which quotes are widened.
+    pub widen_quotes_after_secs: u64,
+    /// Seconds without a bank state after which quoting is suspended.
+    pub suspend_quotes_after_secs: u64,
+    /// Factor the spread is multiplied with while quotes are widened.
+    pub widened_spread_multiplier: Decimal,
+}
+
+impl Default for BankStateStalenessSettings {
+    fn default() -> Self {
+        Self {
+            rerequest_after_secs: 30,
+            widen_quotes_after_secs: 60,
+            suspend_quotes_after_secs: 300,
+            widened_spread_multiplier: dec!(2),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SweepBatchingSettings {
+    /// Excess funds below this amount in sats are never swept.
+    pub min_sweep_amount_sats: u64,
+    /// Excess funds of at least this amount in sats are swept right away.
+    pub batch_threshold_sats: u64,
+    /// Seconds excess funds above the minimum are accumulated before they are swept regardless of the batch threshold.
+    pub batch_window_secs: u64,
+    /// Largest amount in sats settled in a single transfer. Anything above would need channel capacity to be
+    /// spliced in and is left for the next batch.
+    pub splice_threshold_sats: Option<u64>,
+    /// Seconds after which a sweep that didn't result in a withdrawal is no longer considered pending.
+    pub pending_sweep_timeout_secs: u64,
+}
+
+impl Default for SweepBatchingSettings {
+    fn default() -> Self {
+        Self {
+            min_sweep_amount_sats: 10_000,
+            batch_threshold_sats: 10_000,
+            batch_window_secs: 0,
+            splice_threshold_sats: None,
+            pending_sweep_timeout_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BankStateStaleness {
+    Fresh,
+    Stale,
+    QuotesWidened,
+    QuotesSuspended,
+}
 
 pub struct HedgeSettings {
     // The amount of unhedged value to tolerate before a an adjustment.
@@ -57,6 +127,34 @@ pub struct DealerEngineSettings {
     pub leverage_check_interval_ms: u64,
 
     pub spread: Decimal,
+    #[serde(default)]
+    pub bank_state_staleness_settings: BankStateStalenessSettings,
+    #[serde(default)]
+    pub sweep_batching_settings: SweepBatchingSettings,
+    /// Candidate pricing that is only logged next to the live one, disabled if not set.
+    #[serde(default)]
+    pub shadow_pricing: Option<ShadowPricingSettings>,
+    /// Venues the exposure is hedged at with their weights, everything is hedged at kollider if not set.
+    #[serde(default)]
+    pub hedging_venues: Vec<HedgingVenueSettings>,
+    /// Key quotes are signed with, shared with the bank. Quotes aren't signed if not set.
+    #[serde(default)]
+    pub quote_signing_key: Option<String>,
+    /// Min interval between two updates of the streamed rates, once per second if not set.
+    #[serde(default)]
+    pub rates_publish_interval_ms: Option<u64>,
+    /// Simulates all hedging orders instead of placing them at the venues, hedges for real if not set.
+    #[serde(default)]
+    pub paper_trading: Option<PaperTradingSettings>,
+    /// Address Prometheus metrics are served on, not served if not set.
+    #[serde(default)]
+    pub dealer_metrics_address: Option<String>,
+    /// OTLP collector the spans of requests are exported to, not traced if not set.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Currencies on top of the built-in ones, advertised to the bank with every health report.
+    #[serde(default)]
+    pub currencies: Vec<CurrencyInfo>,
 }
 
 pub struct DealerEngine {
@@ -81,6 +179,34 @@ pub struct DealerEngine {
     leverage_check_interval_ms: u64,
     last_leverage_check_timestamp: Instant,
     spread: Decimal,
+    bank_state_staleness_settings: BankStateStalenessSettings,
+    bank_state_staleness: BankStateStaleness,
+    last_bank_state_request_timestamp: Option<Instant>,
+    sweep_batching_settings: SweepBatchingSettings,
+    // sweeps requested from the bank that haven't been withdrawn from the exchange yet
+    pending_sweeps: HashMap<RequestId, (u64, Instant)>,
+    // time since which excess funds above the minimum sweep amount are waiting to be swept
+    sweep_batch_started: Option<Instant>,
+    // funding of each position as of its last state, the difference to the next state is reported
+    last_funding: HashMap<Symbol, Decimal>,
+    // realized pnl of each position as of its last state, reported the same way as funding
+    last_rpnl: HashMap<Symbol, Decimal>,
+    // candidate pricing whose quotes and hedges are compared to the live ones but never used
+    shadow_pricing: Option<Box<dyn PricingModel>>,
+    shadow_stats: Mutex<ShadowStats>,
+    hedging_venues: Vec<HedgingVenueSettings>,
+    quote_signing_key: Option<String>,
+    // open limit swap orders as last sent by the bank, reduced by the fills sent since
+    swap_orders: Vec<OpenSwapOrder>,
+    // pairs the api streams rates of with the time their subscription expires
+    rate_subscriptions: HashMap<CurrencyPair, Instant>,
+    rates_publish_interval_ms: u64,
+    last_rates_publish: Option<Instant>,
+    // venues other than kollider, registered with `with_venue`
+    external_venues: HashMap<String, Box<dyn HedgingVenue>>,
+    // simulated stand-ins of all hedging venues in paper trading mode, empty otherwise
+    paper_venues: HashMap<String, PaperVenue>,
+    pub metrics: DealerMetrics,
 }
 
 impl DealerEngine {
@@ -109,6 +235,34 @@ impl DealerEngine {
 
         let hedged_qtys = HashMap::new();
 
+        let hedging_venues = if settings.hedging_venues.is_empty() {
+            vec![HedgingVenueSettings {
+                name: PRIMARY_VENUE.to_string(),
+                weight: dec!(1),
+            }]
+        } else {
+            settings.hedging_venues.clone()
+        };
+
+        let paper_venues = match &settings.paper_trading {
+            Some(paper_settings) => {
+                slog::warn!(logger, "Paper trading, hedging orders are only simulated");
+                hedging_venues
+                    .iter()
+                    .map(|venue| (venue.name.clone(), PaperVenue::new(paper_settings)))
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+
+        let shadow_pricing = settings.shadow_pricing.as_ref().map(|shadow_settings| {
+            Box::new(SpreadPricingModel::from_settings(
+                shadow_settings,
+                settings.spread,
+                &risk_tolerances,
+            )) as Box<dyn PricingModel>
+        });
+
         // making sure that leverage adjustment action is performed first time position state is received
         let last_leverage_check_timestamp =
             Instant::now().sub(Duration::from_millis(settings.leverage_check_interval_ms + 1));
@@ -134,9 +288,53 @@ impl DealerEngine {
             leverage_check_interval_ms: settings.leverage_check_interval_ms,
             last_leverage_check_timestamp,
             spread: settings.spread,
+            bank_state_staleness_settings: settings.bank_state_staleness_settings,
+            bank_state_staleness: BankStateStaleness::Fresh,
+            last_bank_state_request_timestamp: None,
+            sweep_batching_settings: settings.sweep_batching_settings,
+            pending_sweeps: HashMap::new(),
+            sweep_batch_started: None,
+            last_funding: HashMap::new(),
+            last_rpnl: HashMap::new(),
+            shadow_pricing,
+            shadow_stats: Mutex::new(ShadowStats::default()),
+            hedging_venues,
+            quote_signing_key: settings.quote_signing_key.clone(),
+            swap_orders: Vec::new(),
+            rate_subscriptions: HashMap::new(),
+            rates_publish_interval_ms: settings
+                .rates_publish_interval_ms
+                .unwrap_or(DEFAULT_RATES_PUBLISH_INTERVAL_MS),
+            last_rates_publish: None,
+            external_venues: HashMap::new(),
+            paper_venues,
+            metrics: DealerMetrics::new(),
         }
     }
 
+    /// Registers a venue other than kollider, it is hedged at if it is listed in the hedging venues.
+    pub fn with_venue(mut self, name: &str, venue: impl HedgingVenue + 'static) -> Self {
+        self.external_venues.insert(name.to_string(), Box::new(venue));
+        self
+    }
+
+    /// Runs `f` with the venue of the given name, None if no such venue was registered.
+    /// Paper trading replaces all venues by their simulation.
+    fn with_hedging_venue<R>(&self, name: &str, f: impl FnOnce(&dyn HedgingVenue) -> R) -> Option<R> {
+        if let Some(venue) = self.paper_venues.get(name) {
+            return Some(f(venue));
+        }
+        if name == PRIMARY_VENUE {
+            return Some(f(&ExchangeVenue(self.ws_client.as_ref())));
+        }
+        self.external_venues.get(name).map(|venue| f(venue.as_ref()))
+    }
+
+    pub fn shadow_stats(&self) -> Option<ShadowStats> {
+        self.shadow_pricing.as_ref()?;
+        Some(self.shadow_stats.lock().unwrap().clone())
+    }
+
     pub fn is_ready(&self) -> bool {
         self.has_received_init_data
     }
@@ -145,7 +343,18 @@ impl DealerEngine {
         self.last_bank_state.is_some()
     }
 
+    pub fn is_paper_trading(&self) -> bool {
+        !self.paper_venues.is_empty()
+    }
+
     pub fn get_hedged_quantity(&self, symbol: Symbol) -> Result<Decimal, KolliderClientError> {
+        if self.is_paper_trading() {
+            return self
+                .paper_venues
+                .values()
+                .map(|venue| venue.hedged_quantity(&symbol))
+                .sum();
+        }
         let position_state = self.ws_client.get_position_state(&symbol)?;
         let position = match position_state {
             Some(p) => match p.side {
@@ -186,28 +395,78 @@ impl DealerEngine {
         }
     }
 
-    pub fn sweep_excess_funds<F: FnMut(Message)>(&self, listener: &mut F) {
-        if let Some(balances) = self.ws_client.get_all_balances() {
-            slog::info!(self.logger, "Sweeping: {:?}", balances);
-            if let Some(sat_balance) = balances.cash.get(&Symbol::from("SAT")) {
-                if *sat_balance > dec!(10_000) {
-                    if let Some(amount) = sat_balance.to_u64() {
-                        let msg = Message::Dealer(Dealer::CreateInvoiceRequest(CreateInvoiceRequest {
-                            req_id: Uuid::new_v4(),
-                            amount,
-                            memo: "Excess funds withdrawal".to_string(),
-                        }));
-                        listener(msg);
-                    } else {
-                        slog::info!(
-                            self.logger,
-                            "Sweeping excess funds failed. Could not convert balnce value: {} to u64",
-                            sat_balance
-                        );
-                    }
-                }
+    /// Sats requested from the bank by sweeps that haven't been withdrawn from the exchange yet.
+    pub fn pending_sweep_exposure(&self) -> u64 {
+        self.pending_sweeps.values().map(|(amount, _)| amount).sum()
+    }
+
+    pub fn sweep_excess_funds<F: FnMut(Message)>(&mut self, listener: &mut F) {
+        let timeout = Duration::from_secs(self.sweep_batching_settings.pending_sweep_timeout_secs);
+        let logger = self.logger.clone();
+        self.pending_sweeps.retain(|req_id, (amount, requested_at)| {
+            let is_pending = requested_at.elapsed() < timeout;
+            if !is_pending {
+                slog::warn!(logger, "Sweep {} of {} sats timed out", req_id, amount);
             }
+            is_pending
+        });
+
+        let balances = match self.ws_client.get_all_balances() {
+            Some(balances) => balances,
+            None => return,
+        };
+        slog::info!(self.logger, "Sweeping: {:?}", balances);
+
+        let sat_balance = match balances.cash.get(&Symbol::from("SAT")) {
+            Some(sat_balance) => *sat_balance,
+            None => return,
+        };
+
+        let excess = match sat_balance.to_u64() {
+            Some(balance) => balance.saturating_sub(self.pending_sweep_exposure()),
+            None => {
+                slog::info!(
+                    self.logger,
+                    "Sweeping excess funds failed. Could not convert balnce value: {} to u64",
+                    sat_balance
+                );
+                return;
+            }
+        };
+
+        let settings = &self.sweep_batching_settings;
+        if excess <= settings.min_sweep_amount_sats {
+            self.sweep_batch_started = None;
+            return;
+        }
+
+        let batch_started = *self.sweep_batch_started.get_or_insert_with(Instant::now);
+        if excess < settings.batch_threshold_sats
+            && batch_started.elapsed() < Duration::from_secs(settings.batch_window_secs)
+        {
+            slog::info!(
+                self.logger,
+                "Batching {} sats of excess funds until the batch threshold or window is reached",
+                excess
+            );
+            return;
         }
+
+        let amount = match settings.splice_threshold_sats {
+            Some(splice_threshold) => excess.min(splice_threshold),
+            None => excess,
+        };
+
+        let req_id = Uuid::new_v4();
+        self.pending_sweeps.insert(req_id, (amount, Instant::now()));
+        self.sweep_batch_started = None;
+
+        let msg = Message::Dealer(Dealer::CreateInvoiceRequest(CreateInvoiceRequest {
+            req_id,
+            amount,
+            memo: "Excess funds withdrawal".to_string(),
+        }));
+        listener(msg);
     }
 
     pub fn check_health<F: FnMut(Message)>(&self, listener: &mut F) {
@@ -250,12 +509,290 @@ impl DealerEngine {
             status,
             available_currencies,
             timestamp: time_now(),
+            currencies: currency_registry::all(),
         };
 
         let msg = Message::Dealer(Dealer::Health(dealer_health));
         listener(msg);
     }
 
+    /// Publishes the best rates of every currency for clients to show live conversion values.
+    /// Nothing is published while quotes are suspended.
+    pub fn publish_market_prices<F: FnMut(Message)>(&self, listener: &mut F) {
+        if self.bank_state_staleness == BankStateStaleness::QuotesSuspended {
+            return;
+        }
+
+        let prices = self
+            .bid_quotes
+            .keys()
+            .filter_map(|symbol| {
+                let currency = Currency::from_str(symbol.get(3..6)?).ok()?;
+                let (bid, _, ask) = self.best_prices(symbol)?;
+                Some(MarketPrice { currency, bid, ask })
+            })
+            .collect::<Vec<MarketPrice>>();
+
+        if prices.is_empty() {
+            return;
+        }
+
+        let msg = Message::Dealer(Dealer::MarketPrices(MarketPrices {
+            prices,
+            timestamp: time_now(),
+        }));
+        listener(msg);
+    }
+
+    /// Publishes the rates of the pairs the api is subscribed to, at most once per publish interval.
+    /// Subscriptions the api didn't renew expire and nothing is published while quotes are suspended.
+    pub fn publish_rates<F: FnMut(Message)>(&mut self, listener: &mut F) {
+        let now = Instant::now();
+        self.rate_subscriptions.retain(|_, expires_at| *expires_at > now);
+        if self.rate_subscriptions.is_empty() || self.bank_state_staleness == BankStateStaleness::QuotesSuspended {
+            return;
+        }
+        if let Some(last_publish) = self.last_rates_publish {
+            if now.duration_since(last_publish) < Duration::from_millis(self.rates_publish_interval_ms) {
+                return;
+            }
+        }
+
+        let rates = self
+            .rate_subscriptions
+            .keys()
+            .filter_map(|pair| self.pair_rate(*pair))
+            .collect::<Vec<PairRate>>();
+
+        if rates.is_empty() {
+            return;
+        }
+        self.last_rates_publish = Some(now);
+
+        let msg = Message::Api(Api::RatesUpdate(RatesUpdate {
+            rates,
+            timestamp: time_now(),
+        }));
+        listener(msg);
+    }
+
+    /// Best price of BTC in the currency of the symbol as bid, mid and ask. Bid and ask include the spread.
+    fn best_prices(&self, symbol: &Symbol) -> Option<(Decimal, Decimal, Decimal)> {
+        // Same sides and smallest levels swaps of BTC to fiat and back are quoted from.
+        let (_, btc_to_fiat_price) = self.ask_quotes.get(symbol)?.iter().next()?;
+        let (_, fiat_to_btc_price) = self.bid_quotes.get(symbol)?.iter().next()?;
+        Some((
+            self.get_linear_rate(*btc_to_fiat_price),
+            (*btc_to_fiat_price + *fiat_to_btc_price) / Decimal::TWO,
+            *fiat_to_btc_price * self.get_inverse_modifier(),
+        ))
+    }
+
+    /// Rates of a pair swaps are quoted for, None for pairs without BTC or without prices.
+    fn pair_rate(&self, pair: CurrencyPair) -> Option<PairRate> {
+        let (bid, mid, ask) = match (pair.base, pair.quote) {
+            (Currency::BTC, Currency::BTC) => return None,
+            (Currency::BTC, fiat) => self.best_prices(&Symbol::try_from(fiat).ok()?)?,
+            (fiat, Currency::BTC) => {
+                let (bid, mid, ask) = self.best_prices(&Symbol::try_from(fiat).ok()?)?;
+                // Fiat is sold at the price BTC is bought at and the other way around.
+                (
+                    Decimal::ONE.checked_div(ask)?,
+                    Decimal::ONE.checked_div(mid)?,
+                    Decimal::ONE.checked_div(bid)?,
+                )
+            }
+            _ => return None,
+        };
+        Some(PairRate { pair, bid, mid, ask })
+    }
+
+    /// Reports the funding a position received since its last state to the bank, which books it as
+    /// funding income the interest on fiat balances is paid from. Funding is paid in sats and valued
+    /// at the best price of the symbol.
+    fn report_funding<F: FnMut(Message)>(&mut self, position: &PositionState, listener: &mut F) {
+        let last_funding = self.last_funding.insert(position.symbol.clone(), position.funding);
+        // The first state is only the baseline, funding before it was received before the dealer started.
+        let delta_sats = match last_funding {
+            Some(last_funding) => position.funding - last_funding,
+            None => return,
+        };
+
+        if let Some((currency, amount)) = self.value_sats(&position.symbol, delta_sats, "funding") {
+            let msg = Message::Dealer(Dealer::FundingIncome(FundingIncome {
+                currency,
+                amount,
+                timestamp: time_now(),
+            }));
+            listener(msg);
+        }
+    }
+
+    /// Reports the profit a position realized since its last state to the bank, which books it on the
+    /// hedging pnl account so the cost of hedging can be told apart from the dealer's balance.
+    fn report_hedging_pnl<F: FnMut(Message)>(&mut self, position: &PositionState, listener: &mut F) {
+        let last_rpnl = self.last_rpnl.insert(position.symbol.clone(), position.rpnl);
+        let delta_sats = match last_rpnl {
+            Some(last_rpnl) => position.rpnl - last_rpnl,
+            None => return,
+        };
+
+        if let Some((currency, amount)) = self.value_sats(&position.symbol, delta_sats, "realized pnl") {
+            let msg = Message::Dealer(Dealer::HedgingPnl(HedgingPnl {
+                currency,
+                amount,
+                timestamp: time_now(),
+            }));
+            listener(msg);
+        }
+    }
+
+    /// Fills the limit swap orders the market reached. An order is filled as far as the book gives a rate
+    /// at least as good as its limit, the rest of it keeps resting until the market moves further.
+    fn fill_swap_orders<F: FnMut(Message)>(&mut self, listener: &mut F) {
+        for index in 0..self.swap_orders.len() {
+            let order = self.swap_orders[index].clone();
+            let (amount, rate, fees) = match self.fillable_amount(&order) {
+                Some(fill) => fill,
+                None => continue,
+            };
+            self.swap_orders[index].remaining -= amount;
+            slog::info!(
+                self.logger,
+                "Filling {} {} of swap order {} at {}",
+                amount,
+                order.from,
+                order.order_id,
+                rate.value
+            );
+            let msg = Message::Dealer(Dealer::SwapOrderFill(SwapOrderFill {
+                fill_id: Uuid::new_v4(),
+                order_id: order.order_id,
+                uid: order.uid,
+                amount: Money::new(order.from, Some(amount)),
+                from: order.from,
+                to: order.to,
+                rate,
+                fees,
+            }));
+            listener(msg);
+        }
+        self.swap_orders.retain(|order| order.remaining > Decimal::ZERO);
+    }
+
+    /// Largest part of an order that can be swapped at its limit rate or better, with the rate and fees.
+    /// Rates only get worse with the amount, so if the whole order can't be filled the depth of each level of
+    /// the book is tried until the rate misses the limit.
+    fn fillable_amount(&self, order: &OpenSwapOrder) -> Option<(Decimal, Rate, Option<Money>)> {
+        if order.remaining <= Decimal::ZERO || (order.from != Currency::BTC && order.to != Currency::BTC) {
+            return None;
+        }
+        let conversion_info = ConversionInfo::new(order.from, order.to).ok()?;
+        let fill_at = |amount: Decimal| {
+            let (rate, fees) = self.get_rate(Money::new(order.from, Some(amount)), conversion_info.clone());
+            rate.filter(|rate| rate.value >= order.limit_rate)
+                .map(|rate| (amount, rate, fees))
+        };
+
+        if let Some(fill) = fill_at(order.remaining) {
+            return Some(fill);
+        }
+
+        let quotes = match conversion_info.side {
+            Side::Bid => self.bid_quotes.get(&conversion_info.symbol),
+            Side::Ask => self.ask_quotes.get(&conversion_info.symbol),
+        }?;
+        let best_price = *quotes.values().next()?;
+        let mut best_fill = None;
+        for volume in quotes.keys() {
+            let amount = if conversion_info.from != conversion_info.quote {
+                (Decimal::from(*volume) / best_price).round_dp_with_strategy(SATS_DECIMALS, RoundingStrategy::ToZero)
+            } else {
+                Decimal::from(*volume)
+            };
+            if amount <= Decimal::ZERO {
+                continue;
+            }
+            if amount >= order.remaining {
+                break;
+            }
+            match fill_at(amount) {
+                Some(fill) => best_fill = Some(fill),
+                None => break,
+            }
+        }
+        best_fill
+    }
+
+    /// Values sats paid on a symbol at its best price in the base currency of the symbol.
+    /// Returns None if there is nothing to report.
+    fn value_sats(&self, symbol: &Symbol, sats: Decimal, what: &str) -> Option<(Currency, Decimal)> {
+        if sats.is_zero() {
+            return None;
+        }
+
+        let currency = get_base_currency_from_symbol(symbol.clone()).ok()?;
+        let price = match self.ask_quotes.get(symbol).and_then(|quotes| quotes.values().next()) {
+            Some(price) => *price,
+            None => {
+                slog::warn!(self.logger, "No price to value {} of {} sats of {}", what, sats, symbol);
+                return None;
+            }
+        };
+
+        let amount = (sats * price / SATS_IN_BITCOIN).round_dp(SATS_DECIMALS);
+        if amount.is_zero() {
+            return None;
+        }
+        Some((currency, amount))
+    }
+
+    /// Moves through the staleness states depending on how long ago the last bank state was received.
+    /// While stale the bank state is re-requested, after that quotes are widened and eventually suspended.
+    pub fn check_bank_state_staleness<F: FnMut(Message)>(&mut self, listener: &mut F) {
+        let elapsed_secs = match self.last_bank_state_timestamp {
+            Some(timestamp) => timestamp.elapsed().as_secs(),
+            None => return,
+        };
+
+        let settings = &self.bank_state_staleness_settings;
+        let staleness = if elapsed_secs >= settings.suspend_quotes_after_secs {
+            BankStateStaleness::QuotesSuspended
+        } else if elapsed_secs >= settings.widen_quotes_after_secs {
+            BankStateStaleness::QuotesWidened
+        } else if elapsed_secs >= settings.rerequest_after_secs {
+            BankStateStaleness::Stale
+        } else {
+            BankStateStaleness::Fresh
+        };
+
+        if staleness != self.bank_state_staleness {
+            slog::warn!(
+                self.logger,
+                "Bank state staleness changed from {:?} to {:?}. Last bank state received {}s ago.",
+                self.bank_state_staleness,
+                staleness,
+                elapsed_secs
+            );
+            self.bank_state_staleness = staleness;
+        }
+
+        if staleness == BankStateStaleness::Fresh {
+            return;
+        }
+
+        let should_request = match self.last_bank_state_request_timestamp {
+            Some(timestamp) => timestamp.elapsed().as_secs() >= self.bank_state_staleness_settings.rerequest_after_secs,
+            None => true,
+        };
+
+        if should_request {
+            self.last_bank_state_request_timestamp = Some(Instant::now());
+            let msg = Message::Dealer(Dealer::BankStateRequest(BankStateRequest { req_id: Uuid::new_v4() }));
+            listener(msg);
+        }
+    }
+
     pub fn check_risk<F: FnMut(Message)>(&mut self, _listener: &mut F) {
         if let Some(state) = self.last_bank_state.clone() {
             self.check_risk_from_bank_state(state, _listener);
@@ -276,12 +813,19 @@ impl DealerEngine {
             let currency = account.currency;
             let exposure = account.balance;
 
-            if currency == Currency::BTC {
+            if currency == Currency::BTC || currency.is_asset_backed() {
                 continue;
             }
 
-            let symbol = Symbol::from(currency);
+            let symbol = match Symbol::try_from(currency) {
+                Ok(symbol) => symbol,
+                Err(err) => {
+                    slog::error!(self.logger, "Can't hedge account {}: {}", account_id, err);
+                    continue;
+                }
+            };
             let denom = Denom::from_currency(currency);
+            self.metrics.exposure(currency).set(exposure.to_f64().unwrap_or(0.0));
 
             let qty_contracts_required = match self.calc_num_contracts_for_value(exposure, symbol.clone(), denom) {
                 Ok(q) => q,
@@ -295,29 +839,59 @@ impl DealerEngine {
                 qty_contracts_required
             );
 
-            let currently_hedged_qty = match self.ws_client.get_position_state(&symbol) {
-                Ok(position_state) => match position_state {
-                    Some(p) => match p.side {
-                        None => dec!(0),
-                        Some(side) => {
-                            let side_sign = Decimal::new(side.to_sign(), 0);
-                            side_sign * p.quantity
+            let positions = self
+                .hedging_venues
+                .iter()
+                .map(|venue| {
+                    let position = self.with_hedging_venue(&venue.name, |client| {
+                        (client.is_available(), client.hedged_quantity(&symbol))
+                    });
+                    let (available, hedged_qty) = match position {
+                        Some((available, Ok(hedged_qty))) => (available, Some(hedged_qty)),
+                        Some((available, Err(err))) => {
+                            slog::info!(
+                                self.logger,
+                                "Position state at {} is undefined: {:?} - keeping its position for symbol: {}",
+                                venue.name,
+                                err,
+                                symbol
+                            );
+                            (available, None)
                         }
-                    },
-                    None => dec!(0),
-                },
-                Err(err) => {
-                    slog::info!(
-                        self.logger,
-                        "Position state is undefined: {:?} - skipping risk calculation for symbol: {}",
-                        err,
-                        symbol
-                    );
-                    continue;
-                }
-            };
+                        None => {
+                            slog::error!(self.logger, "Hedging venue {} is not registered", venue.name);
+                            (false, None)
+                        }
+                    };
+                    if !available {
+                        slog::warn!(
+                            self.logger,
+                            "Hedging venue {} is unavailable, its exposure moves to the other venues",
+                            venue.name
+                        );
+                    }
+                    VenuePosition {
+                        weight: venue.weight,
+                        available,
+                        hedged_qty,
+                    }
+                })
+                .collect::<Vec<_>>();
 
+            if positions.iter().all(|position| position.hedged_qty.is_none()) {
+                slog::info!(
+                    self.logger,
+                    "No position state is defined - skipping risk calculation for symbol: {}",
+                    symbol
+                );
+                continue;
+            }
+
+            let currently_hedged_qty: Decimal = positions.iter().filter_map(|position| position.hedged_qty).sum();
             self.hedged_qtys.insert(symbol.clone(), currently_hedged_qty);
+            self.metrics
+                .hedged_contracts(currency)
+                .set(currently_hedged_qty.to_f64().unwrap_or(0.0));
 
             slog::info!(
                 self.logger,
@@ -330,48 +904,72 @@ impl DealerEngine {
             // This works under the assumption that qty_contracts_required is <= 0.
             let delta_qty = qty_contracts_required - currently_hedged_qty;
 
+            self.shadow_hedge(currency, &symbol, delta_qty);
+
             let risk_tolerance = match self.risk_tolerances.get(&currency) {
-                Some(t) => t,
+                Some(t) => Decimal::new(*t as i64, 0),
                 None => continue,
             };
 
-            if delta_qty.abs() < Decimal::new(*risk_tolerance as i64, 0) {
-                slog::info!(
-                    self.logger,
-                    "Delta qty of {} within risk tolerance of {}. NO ACTION.",
-                    delta_qty,
-                    risk_tolerance
-                );
-                continue;
-            }
+            let allocations = allocate_exposure(qty_contracts_required, &positions);
+            for ((venue, position), allocation) in self.hedging_venues.iter().zip(positions).zip(allocations) {
+                let (target_qty, hedged_qty) = match (allocation, position.hedged_qty) {
+                    (Some(target_qty), Some(hedged_qty)) => (target_qty, hedged_qty),
+                    _ => continue,
+                };
+                let venue_delta_qty = target_qty - hedged_qty;
 
-            let (order_quantity, trade_side) = match delta_qty.to_i64() {
-                Some(converted) => (converted.abs() as u64, Side::from_sign(converted)),
-                None => {
-                    slog::error!(
+                if venue_delta_qty.abs() < risk_tolerance {
+                    slog::info!(
                         self.logger,
-                        "Could not convert delta quantity of {} into i64",
-                        delta_qty,
+                        "Delta qty of {} at {} within risk tolerance of {}. NO ACTION.",
+                        venue_delta_qty,
+                        venue.name,
+                        risk_tolerance
                     );
-                    panic!("Could not convert delta quantity into i64");
+                    continue;
                 }
-            };
 
-            slog::info!(
-                self.logger,
-                "Placing trade on side: {:?} of qty: {} for symbol: {}",
-                trade_side,
-                order_quantity,
-                symbol
-            );
+                let (order_quantity, trade_side) = match venue_delta_qty.to_i64() {
+                    Some(converted) => (converted.abs() as u64, Side::from_sign(converted)),
+                    None => {
+                        slog::error!(
+                            self.logger,
+                            "Could not convert delta quantity of {} into i64",
+                            venue_delta_qty,
+                        );
+                        panic!("Could not convert delta quantity into i64");
+                    }
+                };
 
-            self.ws_client
-                .make_order(order_quantity, symbol, trade_side)
-                .expect("Failed to create order");
+                slog::info!(
+                    self.logger,
+                    "Placing trade at {} on side: {:?} of qty: {} for symbol: {}",
+                    venue.name,
+                    trade_side,
+                    order_quantity,
+                    symbol
+                );
+
+                let placed = self.with_hedging_venue(&venue.name, |client| {
+                    client.place_order(order_quantity, symbol.clone(), trade_side)
+                });
+                match placed {
+                    Some(Ok(())) => self.metrics.hedge_orders.inc(),
+                    Some(Err(err)) => slog::error!(
+                        self.logger,
+                        "Failed to place order at {}: {:?}, retrying at the next risk check",
+                        venue.name,
+                        err
+                    ),
+                    None => {}
+                }
+            }
         }
     }
 
     pub fn process_msg<F: FnMut(Message)>(&mut self, msg: Message, listener: &mut F) {
+        let _span = utils::telemetry::request_span("dealer", msg.trace_id()).entered();
         match msg {
             Message::Api(msg) => match msg {
                 Api::SwapRequest(swap_request) => {
@@ -400,8 +998,10 @@ impl DealerEngine {
                         .expect("System time should not be set to earlier than epoch start")
                         .as_micros();
                     self.guaranteed_quotes = self.guaranteed_quotes.split_off(&invalidated_quotes);
-                    let conversion_info = ConversionInfo::new(swap_request.from.clone(), swap_request.to.clone());
-                    let (current_rate, fees) = self.get_rate(swap_request.amount.clone(), conversion_info.clone());
+                    let (current_rate, fees) = match ConversionInfo::new(swap_request.from, swap_request.to) {
+                        Ok(conversion_info) => self.get_rate(swap_request.amount.clone(), conversion_info),
+                        Err(_) => (None, None),
+                    };
 
                     match swap_request.quote_id {
                         None => {
@@ -420,15 +1020,10 @@ impl DealerEngine {
                             }
                             Some(quote) => match validate_quote(&quote, &swap_request) {
                                 Ok(_) => {
-                                    let best_rate =
-                                        get_better_rate(quote.rate, current_rate, conversion_info);
-                                    let best_fees = if let (Some(fees), Some(quote_fees)) = (fees, quote.fees) {
-                                        Some(std::cmp::min(fees.value, quote_fees.value))
-                                    } else {
-                                        Some(dec!(0))
-                                    };
-                                    swap_response.rate = None;
-                                    swap_response.fees = None;
+                                    // Quotes are firm, the swap is made at the quoted rate even if the
+                                    // market moved in between.
+                                    swap_response.rate = quote.rate;
+                                    swap_response.fees = quote.fees;
                                 }
                                 Err(_) => {
                                     swap_response.success = false;
@@ -452,6 +1047,7 @@ impl DealerEngine {
                         quote_id: None,
                         error: None,
                         fees: None,
+                        signature: None,
                     };
                     if quote_request.from != Currency::BTC && quote_request.to != Currency::BTC {
                         quote_response.error = Some(QuoteResponseError::BTCNotFromTo);
@@ -459,8 +1055,10 @@ impl DealerEngine {
                         listener(msg);
                         return;
                     }
-                    let conversion_info = ConversionInfo::new(quote_request.from, quote_request.to);
-                    let (rate, fees) = self.get_rate(quote_request.amount, conversion_info);
+                    let (rate, fees) = match ConversionInfo::new(quote_request.from, quote_request.to) {
+                        Ok(conversion_info) => self.get_rate(quote_request.amount, conversion_info),
+                        Err(_) => (None, None),
+                    };
                     if rate.is_some() {
                         let time_now = SystemTime::now();
                         let quote_id = time_now
@@ -476,6 +1074,9 @@ impl DealerEngine {
                         quote_response.rate = rate;
                         quote_response.valid_until = valid_until;
                         quote_response.fees = fees;
+                        if let Some(key) = &self.quote_signing_key {
+                            quote_response.signature = sign_quote(key, &quote_response);
+                        }
                         self.guaranteed_quotes.insert(quote_id, quote_response.clone());
                     } else {
                         quote_response.error = Some(QuoteResponseError::CurrencyNotAvailable);
@@ -502,9 +1103,11 @@ impl DealerEngine {
                     listener(msg);
                 }
                 Api::InvoiceRequest(invoice_request) => {
-                    let conversion_info = ConversionInfo::new(Currency::BTC, invoice_request.currency);
                     // We assume user specifies the value not the amount.
-                    let (rate, fees) = self.get_rate_inv(invoice_request.amount.clone(), conversion_info);
+                    let (rate, fees) = match ConversionInfo::new(Currency::BTC, invoice_request.currency) {
+                        Ok(conversion_info) => self.get_rate_inv(invoice_request.amount.clone(), conversion_info),
+                        Err(_) => (None, None),
+                    };
                     let mut invoice_response = InvoiceResponse {
                         rate: None,
                         amount: invoice_request.amount,
@@ -529,7 +1132,13 @@ impl DealerEngine {
                     listener(msg);
                 }
                 Api::PaymentRequest(mut msg) => {
-                    let conversion_info = ConversionInfo::new(msg.currency.clone(), Currency::BTC);
+                    let conversion_info = match ConversionInfo::new(msg.currency, Currency::BTC) {
+                        Ok(conversion_info) => conversion_info,
+                        Err(err) => {
+                            slog::error!(self.logger, "Discarded a payment request {}: {}", msg.req_id, err);
+                            return;
+                        }
+                    };
                     // We assume user specifies the value not the amount.
                     match msg.amount.clone() {
                         Some(amount) => {
@@ -549,7 +1158,13 @@ impl DealerEngine {
                     }
                 }
                 Api::CreateLnurlWithdrawalRequest(mut msg) => {
-                    let conversion_info = ConversionInfo::new(msg.currency.clone(), Currency::BTC);
+                    let conversion_info = match ConversionInfo::new(msg.currency, Currency::BTC) {
+                        Ok(conversion_info) => conversion_info,
+                        Err(err) => {
+                            slog::error!(self.logger, "Discarded a withdrawal request {}: {}", msg.req_id, err);
+                            return;
+                        }
+                    };
                     // We assume user specifies the value not the amount.
                     let amount = msg.amount.clone();
                     let (rate, fees) = self.get_rate_inv(amount, conversion_info);
@@ -562,6 +1177,12 @@ impl DealerEngine {
                     let msg = Message::Api(Api::CreateLnurlWithdrawalRequest(msg));
                     listener(msg);
                 }
+                Api::SubscribeRates(subscription) => {
+                    let expires_at = Instant::now().add(Duration::from_millis(RATE_SUBSCRIPTION_TTL_MS));
+                    for pair in subscription.pairs {
+                        self.rate_subscriptions.insert(pair, expires_at);
+                    }
+                }
                 _ => {}
             },
             Message::KolliderApiResponse(msg) => {
@@ -640,10 +1261,16 @@ impl DealerEngine {
                     }
                     KolliderApiResponse::PositionStates(position) => {
                         slog::info!(self.logger, "Received position state {:?}", position);
-                        self.maintain_leverage(&position);
+                        // Positions at kollider aren't the simulated hedges and are left alone.
+                        if !self.is_paper_trading() {
+                            self.maintain_leverage(&position);
+                            self.report_funding(&position, listener);
+                            self.report_hedging_pnl(&position, listener);
+                        }
                     }
                     KolliderApiResponse::Level2State(level2state) => {
                         self.process_orderbook_update(level2state);
+                        self.fill_swap_orders(listener);
                     }
                     KolliderApiResponse::TradableSymbols(tradable_symbols) => {
                         slog::info!(self.logger, "Received Symbols");
@@ -711,10 +1338,19 @@ impl DealerEngine {
             Message::Dealer(Dealer::BankState(bank_state)) => {
                 dbg!(&bank_state);
                 self.last_bank_state_timestamp = Some(Instant::now());
+                self.last_bank_state_request_timestamp = None;
+                if self.bank_state_staleness != BankStateStaleness::Fresh {
+                    slog::info!(self.logger, "Received bank state. Resuming normal quoting.");
+                    self.bank_state_staleness = BankStateStaleness::Fresh;
+                }
                 self.last_bank_state = Some(bank_state.clone());
                 self.check_risk_from_bank_state(bank_state, listener);
             }
 
+            Message::Dealer(Dealer::SwapOrders(swap_orders)) => {
+                self.swap_orders = swap_orders.orders;
+                self.fill_swap_orders(listener);
+            }
             Message::Dealer(Dealer::CreateInvoiceResponse(ref create_invoice_response)) => {
                 slog::info!(self.logger, "Dealer trying to withdrawal.");
                 self.ws_client
@@ -723,11 +1359,17 @@ impl DealerEngine {
                         create_invoice_response.payment_request.clone(),
                     )
                     .expect("Failed to make a withdrawal");
+                self.pending_sweeps.remove(&create_invoice_response.req_id);
+                for req_id in create_invoice_response.netted_req_ids.iter() {
+                    self.pending_sweeps.remove(req_id);
+                }
             }
             Message::Dealer(Dealer::FiatDepositRequest(msg)) => {
-                let conversion_info = ConversionInfo::new(Currency::BTC, msg.currency.clone());
                 // We assume user specifies the value not the amount.
-                let (rate, fees) = self.get_rate_inv(msg.amount.clone(), conversion_info);
+                let (rate, fees) = match ConversionInfo::new(Currency::BTC, msg.currency) {
+                    Ok(conversion_info) => self.get_rate_inv(msg.amount.clone(), conversion_info),
+                    Err(_) => (None, None),
+                };
 
                 let mut fiat_deposit_response = FiatDepositResponse {
                     req_id: msg.req_id,
@@ -791,6 +1433,23 @@ impl DealerEngine {
             _ => panic!("Unsupported level2 update"),
         }
         self.update_quotes(&symbol);
+        self.update_paper_prices(&symbol);
+    }
+
+    /// Simulated orders of the symbol are filled around the middle of its book.
+    fn update_paper_prices(&self, symbol: &Symbol) {
+        let book = match self.level2_data.get(symbol) {
+            Some(book) if self.is_paper_trading() => book,
+            _ => return,
+        };
+        let (best_bid, best_ask) = match (book.bids.keys().next_back(), book.asks.keys().next()) {
+            (Some(best_bid), Some(best_ask)) => (best_bid, best_ask),
+            _ => return,
+        };
+        let mid_price = (best_bid + best_ask) / Decimal::TWO;
+        for venue in self.paper_venues.values() {
+            venue.set_mid_price(symbol, mid_price);
+        }
     }
 
     fn update_quotes(&mut self, symbol: &Symbol) {
@@ -885,9 +1544,61 @@ impl DealerEngine {
         }
     }
 
+    #[inline]
+    fn get_spread_multiplier(&self) -> Decimal {
+        if self.bank_state_staleness >= BankStateStaleness::QuotesWidened {
+            self.bank_state_staleness_settings.widened_spread_multiplier
+        } else {
+            Decimal::ONE
+        }
+    }
+
     #[inline]
     fn get_spread(&self) -> Decimal {
-        self.spread
+        self.spread * self.get_spread_multiplier()
+    }
+
+    /// Logs the rate the shadow pricing would have quoted for the same book price.
+    fn shadow_quote(&self, conversion_info: &ConversionInfo, price: Decimal, live_rate: Decimal) {
+        let shadow_pricing = match &self.shadow_pricing {
+            Some(shadow_pricing) => shadow_pricing,
+            None => return,
+        };
+        let shadow_rate = shadow_pricing.user_rate(price, conversion_info.is_linear(), self.get_spread_multiplier());
+        let diff_bps = self.shadow_stats.lock().unwrap().record_quote(live_rate, shadow_rate);
+        slog::info!(
+            self.logger,
+            "Shadow quote of {} for {} -> {}: live rate {}, shadow rate {}, difference {} bps",
+            shadow_pricing.name(),
+            conversion_info.from,
+            conversion_info.to,
+            live_rate,
+            shadow_rate,
+            diff_bps.round_dp(2)
+        );
+    }
+
+    /// Logs the order the shadow pricing would have placed to hedge the same difference in contracts.
+    fn shadow_hedge(&self, currency: Currency, symbol: &Symbol, delta_qty: Decimal) {
+        let shadow_pricing = match &self.shadow_pricing {
+            Some(shadow_pricing) => shadow_pricing,
+            None => return,
+        };
+        let live_order = self
+            .risk_tolerances
+            .get(&currency)
+            .and_then(|risk_tolerance| hedge_order(delta_qty, *risk_tolerance));
+        let shadow_order = shadow_pricing.hedge_order(currency, delta_qty);
+        self.shadow_stats.lock().unwrap().record_hedge(live_order, shadow_order);
+        slog::info!(
+            self.logger,
+            "Shadow hedge of {} for {} with delta qty {}: live order {:?}, shadow order {:?}",
+            shadow_pricing.name(),
+            symbol,
+            delta_qty,
+            live_order,
+            shadow_order
+        );
     }
 
     #[inline]
@@ -932,6 +1643,10 @@ impl DealerEngine {
         // symbol: BTC/USD
         // Look Bid Side
 
+        if self.bank_state_staleness == BankStateStaleness::QuotesSuspended {
+            return (None, None);
+        }
+
         let maybe_quotes = match conversion_info.side {
             Side::Bid => self.bid_quotes.get(&conversion_info.symbol),
             Side::Ask => self.ask_quotes.get(&conversion_info.symbol),
@@ -959,6 +1674,7 @@ impl DealerEngine {
                         Some((_level_vol, price)) => {
                             if conversion_info.is_linear() {
                                 let user_rate = self.get_linear_rate(*price);
+                                self.shadow_quote(&conversion_info, *price, user_rate);
                                 // Fees are paid in the target currency.
                                 let fees = Money {
                                     value: (price - user_rate) / price * value_in_fiat,
@@ -968,15 +1684,20 @@ impl DealerEngine {
                                     base: conversion_info.from,
                                     quote: conversion_info.to,
                                     value: user_rate,
+                                    timestamp: time_now(),
+                                    max_age_ms: Some(RATE_MAX_AGE_MS),
                                 };
                                 (Some(rate), Some(fees))
                             } else {
                                 let no_fee_inverse_rate = Decimal::ONE / price;
                                 let user_inverse_rate = self.get_inverse_rate(*price);
+                                self.shadow_quote(&conversion_info, *price, user_inverse_rate);
                                 let rate = Rate {
                                     base: conversion_info.from,
                                     quote: conversion_info.to,
                                     value: user_inverse_rate,
+                                    timestamp: time_now(),
+                                    max_age_ms: Some(RATE_MAX_AGE_MS),
                                 };
                                 // Fees are paid in the target currency.
                                 let fees = Money {
@@ -995,6 +1716,9 @@ impl DealerEngine {
     }
 
     fn get_rate_inv(&self, amount: Money, conversion_info: ConversionInfo) -> (Option<Rate>, Option<Money>) {
+        if self.bank_state_staleness == BankStateStaleness::QuotesSuspended {
+            return (None, None);
+        }
 
         let maybe_quotes = match conversion_info.side {
             Side::Bid => self.bid_quotes.get(&conversion_info.symbol),
@@ -1013,6 +1737,7 @@ impl DealerEngine {
                         Some((_level_vol, price)) => {
                             if conversion_info.is_linear() {
                                 let user_rate = self.get_linear_rate(*price);
+                                self.shadow_quote(&conversion_info, *price, user_rate);
                                 // Fees are paid in the target currency.
                                 let fees = Money {
                                     value: (price - user_rate) / price * value_in_fiat,
@@ -1022,15 +1747,20 @@ impl DealerEngine {
                                     base: conversion_info.from,
                                     quote: conversion_info.to,
                                     value: user_rate,
+                                    timestamp: time_now(),
+                                    max_age_ms: Some(RATE_MAX_AGE_MS),
                                 };
                                 (Some(rate), Some(fees))
                             } else {
                                 let no_fee_inverse_rate = Decimal::ONE / price;
                                 let user_inverse_rate = self.get_inverse_rate(*price);
+                                self.shadow_quote(&conversion_info, *price, user_inverse_rate);
                                 let rate = Rate {
                                     base: conversion_info.from,
                                     quote: conversion_info.to,
                                     value: user_inverse_rate,
+                                    timestamp: time_now(),
+                                    max_age_ms: Some(RATE_MAX_AGE_MS),
                                 };
                                 // Fees are paid in the target currency.
                                 let fees = Money {
@@ -1093,6 +1823,17 @@ impl DealerEngine {
     }
 }
 
+/// Signs the terms of a quote so the bank can check them when the swap refers to the quote.
+fn sign_quote(key: &str, quote: &QuoteResponse) -> Option<String> {
+    let firm_quote = FirmQuote {
+        rate: quote.rate.as_ref()?.value,
+        valid_until: quote.valid_until,
+        signature: String::new(),
+    };
+    let payload = firm_quote.signing_payload(quote.quote_id?, quote.uid, &quote.amount, quote.from, quote.to);
+    Some(utils::signing::sign(key, &payload))
+}
+
 fn validate_quote(quote: &QuoteResponse, swap_request: &SwapRequest) -> Result<(), ()> {
     if quote.from != swap_request.from
         || quote.to != swap_request.to
@@ -1104,31 +1845,6 @@ fn validate_quote(quote: &QuoteResponse, swap_request: &SwapRequest) -> Result<(
     Ok(())
 }
 
-fn get_better_rate(
-    rate1: Option<Rate>,
-    rate2: Option<Rate>,
-    conversion_info: ConversionInfo,
-) -> Option<Rate> {
-    let is_linear = conversion_info.is_linear();
-    if is_linear {
-        let r1 = rate1.unwrap_or(Rate::default());
-        let r2 = rate2.unwrap_or(Rate::default());
-        if r1.value > r2.value {
-           Some(r1)
-        } else {
-           Some(r2)
-        }
-    } else {
-        let r1 = rate1.unwrap_or(Rate::default());
-        let r2 = rate2.unwrap_or(Rate::default());
-        if r1.value < r2.value {
-           Some(r1)
-        } else {
-           Some(r2)
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use core_types::Money;
@@ -1219,7 +1935,7 @@ mod tests {
 
         fn get_balance(&self, currency: Currency) -> ws_client::Result<Decimal> {
             if !matches!(currency, Currency::BTC) {
-                let symbol: Symbol = currency.into();
+                let symbol = Symbol::try_from(currency).map_err(|_| KolliderClientError::BalanceNotAvailable)?;
                 {
                     let (side, upnl) = match self.position_states.get(&symbol) {
                         Some(position) => match position.side.as_ref() {
@@ -1312,11 +2028,14 @@ mod tests {
         }
     }
 
-    use crate::dealer_engine::QUOTE_TTL_MS;
+    use crate::dealer_engine::{BankStateStaleness, BankStateStalenessSettings, SweepBatchingSettings, QUOTE_TTL_MS};
     use crate::{DealerEngine, DealerEngineSettings};
     use core_types::kollider_client::{Balances, MarkPrice, PositionState, Side};
     use core_types::{Currency, Symbol, SATS_IN_BITCOIN};
-    use msgs::api::{Api, QuoteRequest, QuoteResponseError, SwapRequest, SwapResponseError};
+    use msgs::api::{
+        Api, CurrencyPair, QuoteRequest, QuoteResponseError, SubscribeRates, SwapRequest, SwapResponseError,
+    };
+    use msgs::dealer::{Dealer, OpenSwapOrder, SwapOrders};
     use msgs::kollider_client::{KolliderApiResponse, Level2State, TradableSymbol};
     use msgs::Message;
     use rust_decimal::Decimal;
@@ -1355,6 +2074,15 @@ mod tests {
             position_max_leverage: dec!(1.0001),
             leverage_check_interval_ms: 1000,
             spread: dec!(0.01),
+            bank_state_staleness_settings: BankStateStalenessSettings::default(),
+            sweep_batching_settings: SweepBatchingSettings::default(),
+            shadow_pricing: None,
+            hedging_venues: Vec::new(),
+            quote_signing_key: None,
+            rates_publish_interval_ms: None,
+            paper_trading: None,
+            dealer_metrics_address: None,
+            otlp_endpoint: None,
         };
         let ws_client = MockWsClient::new();
         let mut dealer = DealerEngine::new(settings, ws_client);
@@ -1628,6 +2356,10 @@ mod tests {
             from: Currency::BTC,
             to: Currency::USD,
             quote_id: None,
+            signed_quote: None,
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1653,6 +2385,10 @@ mod tests {
             from: Currency::USD,
             to: Currency::BTC,
             quote_id: None,
+            signed_quote: None,
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1709,6 +2445,10 @@ mod tests {
             from: quote.from,
             to: quote.to,
             quote_id: quote.quote_id,
+            signed_quote: quote.firm_quote(),
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1741,6 +2481,10 @@ mod tests {
             from: Currency::BTC,
             to: Currency::USD,
             quote_id: Some(12345),
+            signed_quote: None,
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1766,6 +2510,10 @@ mod tests {
             from: Currency::USD,
             to: Currency::BTC,
             quote_id: Some(67890),
+            signed_quote: None,
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1823,6 +2571,10 @@ mod tests {
             from: quote.from,
             to: quote.to,
             quote_id: quote.quote_id,
+            signed_quote: quote.firm_quote(),
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1880,6 +2632,10 @@ mod tests {
             from: quote.from,
             to: quote.to,
             quote_id: quote.quote_id,
+            signed_quote: quote.firm_quote(),
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1906,6 +2662,10 @@ mod tests {
             from: quote.from,
             to: quote.to,
             quote_id: quote.quote_id,
+            signed_quote: quote.firm_quote(),
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1928,6 +2688,10 @@ mod tests {
             from: Currency::GBP,
             to: Currency::BTC,
             quote_id: quote.quote_id,
+            signed_quote: quote.firm_quote(),
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1984,6 +2748,10 @@ mod tests {
             from: quote.from,
             to: quote.to,
             quote_id: quote.quote_id,
+            signed_quote: quote.firm_quote(),
+            idempotency_key: None,
+            origin: None,
+            totp_code: None,
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request.clone())), &mut |msg| {
             out_msg.push_back(msg);
@@ -2012,4 +2780,161 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn quotes_suspended_on_stale_bank_state() {
+        let mut dealer_engine = initialise_dealer_engine();
+        let mut out_msg = VecDeque::new();
+        dealer_engine.bank_state_staleness_settings = BankStateStalenessSettings {
+            rerequest_after_secs: 0,
+            widen_quotes_after_secs: 0,
+            suspend_quotes_after_secs: 0,
+            widened_spread_multiplier: dec!(2),
+        };
+        dealer_engine.last_bank_state_timestamp = Some(std::time::Instant::now());
+        dealer_engine.check_bank_state_staleness(&mut |msg| {
+            out_msg.push_back(msg);
+        });
+        assert_eq!(dealer_engine.bank_state_staleness, BankStateStaleness::QuotesSuspended);
+        assert!(matches!(
+            out_msg.pop_front(),
+            Some(Message::Dealer(Dealer::BankStateRequest(_)))
+        ));
+
+        let uid = 1003;
+        let quote_request = QuoteRequest {
+            req_id: Uuid::new_v4(),
+            uid,
+            amount: Money {
+                currency: Currency::BTC,
+                value: dec!(0.0001),
+            },
+            from: Currency::BTC,
+            to: Currency::USD,
+        };
+        dealer_engine.process_msg(Message::Api(Api::QuoteRequest(quote_request)), &mut |msg| {
+            out_msg.push_back(msg);
+        });
+        while let Some(msg) = out_msg.pop_front() {
+            if let Message::Api(Api::QuoteResponse(quote_response)) = msg {
+                assert!(quote_response.rate.is_none());
+                assert!(matches!(
+                    quote_response.error,
+                    Some(QuoteResponseError::CurrencyNotAvailable)
+                ));
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn sweeps_are_batched_and_capped() {
+        let mut dealer_engine = initialise_dealer_engine();
+        let ws_client = MockWsClient::new();
+        ws_client
+            .balances
+            .borrow_mut()
+            .cash
+            .insert(Symbol::from("SAT"), dec!(50_000));
+        dealer_engine.ws_client = Box::new(ws_client);
+        dealer_engine.sweep_batching_settings = SweepBatchingSettings {
+            min_sweep_amount_sats: 10_000,
+            batch_threshold_sats: 100_000,
+            batch_window_secs: 3600,
+            splice_threshold_sats: Some(30_000),
+            pending_sweep_timeout_secs: 600,
+        };
+
+        let mut out_msg = VecDeque::new();
+        dealer_engine.sweep_excess_funds(&mut |msg| out_msg.push_back(msg));
+        assert!(out_msg.is_empty());
+
+        dealer_engine.sweep_batching_settings.batch_window_secs = 0;
+        dealer_engine.sweep_excess_funds(&mut |msg| out_msg.push_back(msg));
+        dealer_engine.sweep_excess_funds(&mut |msg| out_msg.push_back(msg));
+        dealer_engine.sweep_excess_funds(&mut |msg| out_msg.push_back(msg));
+
+        let amounts = out_msg
+            .into_iter()
+            .filter_map(|msg| match msg {
+                Message::Dealer(Dealer::CreateInvoiceRequest(req)) => Some(req.amount),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec![30_000, 20_000]);
+        assert_eq!(dealer_engine.pending_sweep_exposure(), 50_000);
+    }
+
+    #[test]
+    fn swap_orders_are_filled_at_their_limit() {
+        let mut dealer_engine = initialise_dealer_engine();
+        let order = |limit_rate| OpenSwapOrder {
+            order_id: Uuid::new_v4(),
+            uid: 1003,
+            from: Currency::BTC,
+            to: Currency::USD,
+            remaining: dec!(0.0875),
+            limit_rate,
+        };
+        let swap_orders = SwapOrders {
+            orders: vec![order(dec!(60000)), order(dec!(51000))],
+        };
+
+        let mut out_msg = VecDeque::new();
+        dealer_engine.process_msg(Message::Dealer(Dealer::SwapOrders(swap_orders)), &mut |msg| {
+            out_msg.push_back(msg)
+        });
+
+        let fills = out_msg
+            .into_iter()
+            .filter_map(|msg| match msg {
+                Message::Dealer(Dealer::SwapOrderFill(fill)) => Some(fill),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].amount.value, dec!(0.0875));
+        assert!(fills[0].rate.value >= dec!(51000));
+        // The order above the market keeps resting.
+        assert_eq!(dealer_engine.swap_orders.len(), 1);
+        assert_eq!(dealer_engine.swap_orders[0].limit_rate, dec!(60000));
+    }
+
+    #[test]
+    fn subscribed_rates_are_published() {
+        let mut dealer_engine = initialise_dealer_engine();
+        let pairs = vec![
+            CurrencyPair {
+                base: Currency::BTC,
+                quote: Currency::USD,
+            },
+            CurrencyPair {
+                base: Currency::USD,
+                quote: Currency::BTC,
+            },
+        ];
+        dealer_engine.process_msg(
+            Message::Api(Api::SubscribeRates(SubscribeRates { pairs })),
+            &mut |_msg| {},
+        );
+
+        let mut out_msg = VecDeque::new();
+        dealer_engine.publish_rates(&mut |msg| out_msg.push_back(msg));
+        // Throttled until the publish interval passed.
+        dealer_engine.publish_rates(&mut |msg| out_msg.push_back(msg));
+        assert_eq!(out_msg.len(), 1);
+
+        let rates = match out_msg.pop_front() {
+            Some(Message::Api(Api::RatesUpdate(update))) => update.rates,
+            msg => panic!("Unexpected message {:?}", msg),
+        };
+        assert_eq!(rates.len(), 2);
+        for rate in rates {
+            if rate.pair.base == Currency::BTC {
+                assert_eq!(rate.mid, dec!(35000));
+            } else {
+                assert_eq!(rate.mid, Decimal::ONE / dec!(35000));
+            }
+        }
+    }
 }
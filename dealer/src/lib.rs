@@ -1,4 +1,9 @@
 pub mod dealer_engine;
+pub mod metrics;
+pub mod paper;
+pub mod preflight;
+pub mod shadow;
+pub mod venues;
 
 use crossbeam::channel::bounded;
 use dealer_engine::*;
@@ -19,11 +24,19 @@ pub async fn insert_dealer_state(dealer: &DealerEngine, client: &Client, bucket:
     let usd_hedged_qty = dealer.get_hedged_quantity(Symbol::from("BTCUSD.PERP"));
     let eur_hedged_qty = dealer.get_hedged_quantity(Symbol::from("BTCEUR.PERP"));
 
-    let fields = vec![
+    let mut fields = vec![
         ("usd_hedged_quantity", usd_hedged_qty),
         ("eur_hedged_quantity", eur_hedged_qty),
+        ("pending_sweep_sats", Ok(Decimal::from(dealer.pending_sweep_exposure()))),
     ];
 
+    if let Some(shadow_stats) = dealer.shadow_stats() {
+        fields.push(("shadow_quotes", Ok(Decimal::from(shadow_stats.quotes))));
+        fields.push(("shadow_mean_rate_diff_bps", Ok(shadow_stats.mean_rate_diff_bps())));
+        fields.push(("shadow_max_rate_diff_bps", Ok(shadow_stats.max_rate_diff_bps)));
+        fields.push(("shadow_diverging_hedges", Ok(Decimal::from(shadow_stats.diverging_hedges))));
+    }
+
     let builder = fields.into_iter().fold(
         influxdb2::models::DataPoint::builder("dealer_states"),
         |builder, (field_name, value)| {
@@ -68,6 +81,18 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
 
     let mut synth_dealer = DealerEngine::new(settings.clone(), ws_client);
 
+    if let Some(address) = &settings.dealer_metrics_address {
+        if let Err(err) = utils::metrics::serve(synth_dealer.metrics.registry.clone(), address) {
+            eprintln!("Failed to serve metrics on {}: {}", address, err);
+        }
+    }
+
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        if let Err(err) = utils::telemetry::init_tracing("dealer", endpoint) {
+            eprintln!("Failed to export traces to {}: {}", endpoint, err);
+        }
+    }
+
     let influx_client = Client::new(
         settings.influx_host.clone(),
         settings.influx_org.clone(),
@@ -75,12 +100,13 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
     );
 
     let mut listener = |msg: Message| {
-        utils::xzmq::send_as_bincode(&bank_sender, &msg);
+        utils::xzmq::send_message(&bank_sender, &msg);
     };
 
     let mut last_health_check = Instant::now();
     let mut last_house_keeping = Instant::now();
     let mut last_risk_check = Instant::now();
+    let mut last_staleness_check = Instant::now();
 
     loop {
         // Before we proceed we have to have received a bank state message
@@ -88,7 +114,7 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
             let msg = Message::Dealer(Dealer::BankStateRequest(BankStateRequest { req_id: Uuid::new_v4() }));
             listener(msg);
             while let Ok(frame) = bank_recv.recv_msg(0) {
-                if let Ok(message) = bincode::deserialize::<Message>(&frame) {
+                if let Ok(message) = msgs::wire::decode(&frame) {
                     if let Message::Dealer(Dealer::BankState(ref _bank_state)) = message {
                         synth_dealer.process_msg(message, &mut listener);
                         last_risk_check = Instant::now();
@@ -99,7 +125,7 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
         }
 
         if let Ok(frame) = bank_recv.recv_msg(1) {
-            if let Ok(message) = bincode::deserialize::<Message>(&frame) {
+            if let Ok(message) = msgs::wire::decode(&frame) {
                 synth_dealer.process_msg(message, &mut listener);
             };
         }
@@ -116,6 +142,18 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
             insert_dealer_state(&synth_dealer, &influx_client, &settings.influx_bucket.clone()).await;
         }
 
+        synth_dealer.publish_rates(&mut listener);
+
+        if last_staleness_check.elapsed().as_secs() >= 1 {
+            synth_dealer
+                .metrics
+                .queue_depth("kollider")
+                .set(kollider_client_rx.len() as f64);
+            synth_dealer.check_bank_state_staleness(&mut listener);
+            synth_dealer.publish_market_prices(&mut listener);
+            last_staleness_check = Instant::now();
+        }
+
         if last_health_check.elapsed().as_secs() > 5 {
             synth_dealer.check_health(&mut listener);
             last_health_check = Instant::now();
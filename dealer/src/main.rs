@@ -1,5 +1,3 @@
-pub mod dealer_engine;
-
 use utils::xzmq::SocketContext;
 
 use dealer::dealer_engine::*;
@@ -7,8 +5,11 @@ use dealer::start;
 
 #[tokio::main]
 async fn main() {
+    utils::config::register_currencies_from_env().expect("Failed to register currencies.");
     let settings = utils::config::get_config_from_env::<DealerEngineSettings>().expect("Failed to load settings.");
 
+    dealer::preflight::run(&settings).print_or_exit();
+
     let context = SocketContext::new();
     let bank_rx = context.create_pull(&settings.dealer_bank_pull_address);
     let bank_tx = context.create_push(&settings.dealer_bank_push_address);
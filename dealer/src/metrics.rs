@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use utils::metrics::*;
+
+use core_types::Currency;
+
+/// Metrics of the dealer served to Prometheus, next to the state written to influx.
+pub struct DealerMetrics {
+    pub registry: Arc<Registry>,
+    pub hedge_orders: Arc<Counter>,
+}
+
+impl DealerMetrics {
+    pub fn new() -> Self {
+        let registry = Arc::new(Registry::new());
+        Self {
+            hedge_orders: registry.counter("dealer_hedge_orders_total", "Orders placed to adjust a hedge."),
+            registry,
+        }
+    }
+
+    /// Fiat the bank's users hold in the currency as of the last bank state.
+    pub fn exposure(&self, currency: Currency) -> Arc<Gauge> {
+        self.registry.labeled_gauge(
+            "dealer_exposure",
+            "Fiat exposure per currency as of the last bank state.",
+            &[("currency", &currency.to_string())],
+        )
+    }
+
+    /// Contracts held across all venues, negative for short positions.
+    pub fn hedged_contracts(&self, currency: Currency) -> Arc<Gauge> {
+        self.registry.labeled_gauge(
+            "dealer_hedged_contracts",
+            "Contracts held to hedge the currency across all venues.",
+            &[("currency", &currency.to_string())],
+        )
+    }
+
+    /// Messages waiting in one of the queues the dealer's loop reads from.
+    pub fn queue_depth(&self, queue: &str) -> Arc<Gauge> {
+        self.registry.labeled_gauge(
+            "dealer_queue_depth",
+            "Messages waiting to be processed by the dealer.",
+            &[("queue", queue)],
+        )
+    }
+}
+
+impl Default for DealerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
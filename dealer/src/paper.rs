@@ -0,0 +1,117 @@
+use core_types::kollider_client::Side;
+use core_types::Symbol;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use xerror::kollider_client::KolliderClientError;
+
+use crate::venues::HedgingVenue;
+
+/// Hedging orders are only simulated, prices are still taken from kollider. Meant for staging and tests.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PaperTradingSettings {
+    /// Share of the price simulated fills are worse than the middle of the book, e.g. 0.001 for 10 bps.
+    #[serde(default)]
+    pub slippage: Decimal,
+}
+
+/// A simulated order of a paper venue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaperFill {
+    pub symbol: Symbol,
+    pub side: Side,
+    pub quantity: u64,
+    pub price: Decimal,
+}
+
+#[derive(Default)]
+struct PaperBook {
+    // contracts held per symbol, negative for short positions
+    positions: HashMap<Symbol, Decimal>,
+    mid_prices: HashMap<Symbol, Decimal>,
+    fills: Vec<PaperFill>,
+}
+
+/// Stands in for a hedging venue, orders are filled right away at the middle of the book plus slippage.
+pub struct PaperVenue {
+    slippage: Decimal,
+    book: Mutex<PaperBook>,
+}
+
+impl PaperVenue {
+    pub fn new(settings: &PaperTradingSettings) -> Self {
+        Self {
+            slippage: settings.slippage,
+            book: Mutex::new(PaperBook::default()),
+        }
+    }
+
+    /// Price the next orders of the symbol are filled around.
+    pub fn set_mid_price(&self, symbol: &Symbol, price: Decimal) {
+        self.book.lock().unwrap().mid_prices.insert(symbol.clone(), price);
+    }
+
+    pub fn fills(&self) -> Vec<PaperFill> {
+        self.book.lock().unwrap().fills.clone()
+    }
+}
+
+impl HedgingVenue for PaperVenue {
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn hedged_quantity(&self, symbol: &Symbol) -> Result<Decimal, KolliderClientError> {
+        Ok(self
+            .book
+            .lock()
+            .unwrap()
+            .positions
+            .get(symbol)
+            .copied()
+            .unwrap_or(dec!(0)))
+    }
+
+    fn place_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<(), KolliderClientError> {
+        let mut book = self.book.lock().unwrap();
+        // Orders are refused like at a real venue until the book of the symbol is known.
+        let mid_price = *book
+            .mid_prices
+            .get(&symbol)
+            .ok_or(KolliderClientError::PositionStateNotAvailable)?;
+        let sign = Decimal::new(side.to_sign(), 0);
+        // Buying fills above the middle of the book and selling below.
+        let price = mid_price * (Decimal::ONE + sign * self.slippage);
+
+        *book.positions.entry(symbol.clone()).or_insert(dec!(0)) += sign * Decimal::from(quantity);
+        book.fills.push(PaperFill {
+            symbol,
+            side,
+            quantity,
+            price,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orders_are_filled_with_slippage() {
+        let venue = PaperVenue::new(&PaperTradingSettings { slippage: dec!(0.01) });
+        let symbol = Symbol::from("BTCUSD.PERP");
+        assert!(venue.place_order(10, symbol.clone(), Side::Ask).is_err());
+
+        venue.set_mid_price(&symbol, dec!(20000));
+        venue.place_order(10, symbol.clone(), Side::Ask).unwrap();
+        venue.place_order(4, symbol.clone(), Side::Bid).unwrap();
+
+        assert_eq!(venue.hedged_quantity(&symbol).unwrap(), dec!(-6));
+        let prices = venue.fills().into_iter().map(|fill| fill.price).collect::<Vec<_>>();
+        assert_eq!(prices, vec![dec!(19800), dec!(20200)]);
+    }
+}
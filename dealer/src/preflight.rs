@@ -0,0 +1,76 @@
+use crate::dealer_engine::DealerEngineSettings;
+
+use core_types::Currency;
+use rust_decimal_macros::dec;
+use std::collections::HashSet;
+use std::str::FromStr;
+use utils::preflight::{check_bindable, PreflightReport};
+
+/// Problems with the settings that would otherwise only surface once the dealer is running.
+pub fn check_settings(settings: &DealerEngineSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for currency in settings.risk_tolerances.keys() {
+        match Currency::from_str(currency) {
+            Ok(Currency::BTC) => problems.push(String::from("risk_tolerances can't have BTC, it is never hedged")),
+            Ok(_) => {}
+            Err(_) => problems.push(format!("risk_tolerances has an unknown currency {}", currency)),
+        }
+    }
+    if settings.risk_tolerances.is_empty() {
+        problems.push(String::from(
+            "risk_tolerances is empty, no fiat currency would be enabled",
+        ));
+    }
+
+    if settings.spread < dec!(0) {
+        problems.push(format!("spread {} is negative", settings.spread));
+    }
+    if settings.position_min_leverage <= dec!(0) || settings.position_min_leverage > settings.position_max_leverage {
+        problems.push(format!(
+            "position_min_leverage {} has to be positive and at most position_max_leverage {}",
+            settings.position_min_leverage, settings.position_max_leverage
+        ));
+    }
+    let mut venue_names = HashSet::new();
+    for venue in settings.hedging_venues.iter() {
+        if !venue_names.insert(venue.name.as_str()) {
+            problems.push(format!("hedging venue {} is listed twice", venue.name));
+        }
+        if venue.weight < dec!(0) {
+            problems.push(format!("the weight of hedging venue {} is negative", venue.name));
+        }
+    }
+
+    if !settings.kollider_ws_url.starts_with("ws://") && !settings.kollider_ws_url.starts_with("wss://") {
+        problems.push(format!(
+            "kollider_ws_url {} is not a websocket url",
+            settings.kollider_ws_url
+        ));
+    }
+    problems
+}
+
+/// Checks the database, the zmq endpoint the dealer binds and the settings before anything is started.
+pub fn run(settings: &DealerEngineSettings) -> PreflightReport {
+    let mut report = PreflightReport::new("dealer");
+
+    for (name, outcome) in models::check_database(&settings.psql_url) {
+        report.add(name, outcome);
+    }
+
+    report.add(
+        &format!("zmq {}", settings.dealer_bank_pull_address),
+        check_bindable(&settings.dealer_bank_pull_address),
+    );
+
+    let problems = check_settings(settings);
+    if problems.is_empty() {
+        report.add("settings", Ok(String::from("look sane")));
+    }
+    for problem in problems {
+        report.add("settings", Err(problem));
+    }
+
+    report
+}
@@ -0,0 +1,166 @@
+use core_types::{kollider_client::*, *};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Candidate pricing and hedging parameters that run next to the live ones without affecting users.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShadowPricingSettings {
+    /// Name the shadow quotes and hedges are logged with, e.g. the version of the candidate.
+    pub name: String,
+    /// Spread of the candidate, the live spread is used if not set.
+    #[serde(default)]
+    pub spread: Option<Decimal>,
+    /// Risk tolerances of the candidate per currency, the live ones are used for currencies not set.
+    #[serde(default)]
+    pub risk_tolerances: HashMap<String, u64>,
+}
+
+/// A pricing and hedging implementation of the dealer.
+pub trait PricingModel: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Rate offered to users for the book price, the spread multiplier widens quotes on a stale bank state.
+    fn user_rate(&self, price: Decimal, is_linear: bool, spread_multiplier: Decimal) -> Decimal;
+
+    /// Order that brings the hedge of the currency to its target, given the difference in contracts.
+    fn hedge_order(&self, currency: Currency, delta_qty: Decimal) -> Option<(u64, Side)>;
+}
+
+/// The pricing of the live dealer with its own spread and risk tolerances.
+pub struct SpreadPricingModel {
+    name: String,
+    spread: Decimal,
+    risk_tolerances: HashMap<Currency, u64>,
+}
+
+impl SpreadPricingModel {
+    pub fn new(name: String, spread: Decimal, risk_tolerances: HashMap<Currency, u64>) -> Self {
+        Self {
+            name,
+            spread,
+            risk_tolerances,
+        }
+    }
+
+    /// The candidate of the settings, falling back to the live spread and risk tolerances.
+    pub fn from_settings(
+        settings: &ShadowPricingSettings,
+        live_spread: Decimal,
+        live_risk_tolerances: &HashMap<Currency, u64>,
+    ) -> Self {
+        let mut risk_tolerances = live_risk_tolerances.clone();
+        for (currency, tolerance) in settings.risk_tolerances.iter() {
+            match Currency::from_str(currency) {
+                Ok(currency) => {
+                    risk_tolerances.insert(currency, *tolerance);
+                }
+                Err(err) => panic!(
+                    "Failed to convert a shadow risk tolerance {} into a currency, reason: {:?}",
+                    currency, err
+                ),
+            }
+        }
+        Self::new(
+            settings.name.clone(),
+            settings.spread.unwrap_or(live_spread),
+            risk_tolerances,
+        )
+    }
+}
+
+impl PricingModel for SpreadPricingModel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn user_rate(&self, price: Decimal, is_linear: bool, spread_multiplier: Decimal) -> Decimal {
+        let half_spread = self.spread * spread_multiplier / Decimal::TWO;
+        if is_linear {
+            price * (Decimal::ONE - half_spread)
+        } else {
+            Decimal::ONE / (price * (Decimal::ONE + half_spread))
+        }
+    }
+
+    fn hedge_order(&self, currency: Currency, delta_qty: Decimal) -> Option<(u64, Side)> {
+        hedge_order(delta_qty, *self.risk_tolerances.get(&currency)?)
+    }
+}
+
+/// Order for the difference in contracts, none while it's within the tolerance.
+pub fn hedge_order(delta_qty: Decimal, risk_tolerance: u64) -> Option<(u64, Side)> {
+    if delta_qty.abs() < Decimal::new(risk_tolerance as i64, 0) {
+        return None;
+    }
+    let delta_qty = delta_qty.to_i64()?;
+    Some((delta_qty.unsigned_abs(), Side::from_sign(delta_qty)))
+}
+
+/// Aggregated difference between the shadow and the live decisions since the dealer started.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowStats {
+    pub quotes: u64,
+    /// Sum of the absolute differences of the shadow rates to the live ones, in basis points.
+    pub total_rate_diff_bps: Decimal,
+    pub max_rate_diff_bps: Decimal,
+    pub hedges: u64,
+    pub diverging_hedges: u64,
+}
+
+impl ShadowStats {
+    /// Records a quote and returns the difference of the shadow rate to the live one in basis points.
+    pub fn record_quote(&mut self, live_rate: Decimal, shadow_rate: Decimal) -> Decimal {
+        let diff_bps = if live_rate.is_zero() {
+            Decimal::ZERO
+        } else {
+            (shadow_rate - live_rate) / live_rate * Decimal::new(10_000, 0)
+        };
+        self.quotes += 1;
+        self.total_rate_diff_bps += diff_bps.abs();
+        self.max_rate_diff_bps = self.max_rate_diff_bps.max(diff_bps.abs());
+        diff_bps
+    }
+
+    pub fn record_hedge(&mut self, live_order: Option<(u64, Side)>, shadow_order: Option<(u64, Side)>) {
+        self.hedges += 1;
+        if live_order != shadow_order {
+            self.diverging_hedges += 1;
+        }
+    }
+
+    pub fn mean_rate_diff_bps(&self) -> Decimal {
+        if self.quotes == 0 {
+            return Decimal::ZERO;
+        }
+        self.total_rate_diff_bps / Decimal::from(self.quotes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn shadow_model_differs_from_live_by_its_spread() {
+        let live = SpreadPricingModel::new(String::from("live"), dec!(0.01), HashMap::new());
+        let shadow = SpreadPricingModel::new(
+            String::from("candidate"),
+            dec!(0.02),
+            HashMap::from([(Currency::USD, 10)]),
+        );
+
+        let mut stats = ShadowStats::default();
+        let diff = stats.record_quote(
+            live.user_rate(dec!(20000), true, Decimal::ONE),
+            shadow.user_rate(dec!(20000), true, Decimal::ONE),
+        );
+        assert!(diff < dec!(-50) && diff > dec!(-51));
+
+        assert_eq!(shadow.hedge_order(Currency::USD, dec!(-5)), None);
+        assert_eq!(shadow.hedge_order(Currency::USD, dec!(-15)), Some((15, Side::Ask)));
+        stats.record_hedge(None, shadow.hedge_order(Currency::USD, dec!(-15)));
+        assert_eq!(stats.diverging_hedges, 1);
+    }
+}
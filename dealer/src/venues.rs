@@ -0,0 +1,143 @@
+use core_types::kollider_client::Side;
+use core_types::Symbol;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use ws_client::WsClient;
+use xerror::kollider_client::KolliderClientError;
+
+/// Name of the venue the dealer takes its prices from, it is always a hedging venue.
+pub const PRIMARY_VENUE: &str = "kollider";
+
+/// An exchange the dealer can hedge its fiat exposure at.
+pub trait HedgingVenue {
+    /// Whether orders can be placed right now. Exposure of unavailable venues is moved to the others.
+    fn is_available(&self) -> bool;
+    /// Number of contracts held, negative for short positions.
+    fn hedged_quantity(&self, symbol: &Symbol) -> Result<Decimal, KolliderClientError>;
+    fn place_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<(), KolliderClientError>;
+}
+
+/// Hedges through an exchange client, e.g. the `KolliderHedgingClient` the dealer also takes its prices from.
+pub struct ExchangeVenue<'a>(pub &'a dyn WsClient);
+
+impl HedgingVenue for ExchangeVenue<'_> {
+    fn is_available(&self) -> bool {
+        self.0.is_connected() && self.0.is_authenticated()
+    }
+
+    fn hedged_quantity(&self, symbol: &Symbol) -> Result<Decimal, KolliderClientError> {
+        let quantity = match self.0.get_position_state(symbol)? {
+            Some(position) => match position.side {
+                Some(side) => Decimal::new(side.to_sign(), 0) * position.quantity,
+                None => dec!(0),
+            },
+            None => dec!(0),
+        };
+        Ok(quantity)
+    }
+
+    fn place_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<(), KolliderClientError> {
+        self.0.make_order(quantity, symbol, side)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HedgingVenueSettings {
+    pub name: String,
+    /// Share of the exposure hedged at the venue relative to the weights of the other venues.
+    pub weight: Decimal,
+}
+
+/// State of a venue during one risk check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenuePosition {
+    pub weight: Decimal,
+    pub available: bool,
+    /// Contracts held, None if the position couldn't be read.
+    pub hedged_qty: Option<Decimal>,
+}
+
+/// Splits the contracts required to hedge a symbol across the venues by their weights.
+/// Positions at venues that are down or whose position is unknown can't be changed, what is left is allocated
+/// to the available venues so their hedges take over. The rounding remainder goes to the first available venue.
+/// Returns the target quantity per venue, None for the venues that can't be traded at.
+pub fn allocate_exposure(required_qty: Decimal, venues: &[VenuePosition]) -> Vec<Option<Decimal>> {
+    let tradable = |venue: &VenuePosition| venue.available && venue.hedged_qty.is_some();
+
+    let stuck_qty: Decimal = venues
+        .iter()
+        .filter(|venue| !tradable(venue))
+        .filter_map(|venue| venue.hedged_qty)
+        .sum();
+    let total_weight: Decimal = venues
+        .iter()
+        .filter(|venue| tradable(venue))
+        .map(|venue| venue.weight)
+        .sum();
+    // Venues that only act as fallback share the exposure equally once they are the only ones left.
+    let weight = |venue: &VenuePosition| {
+        if total_weight.is_zero() {
+            dec!(1)
+        } else {
+            venue.weight
+        }
+    };
+    let total_weight = if total_weight.is_zero() {
+        Decimal::from(venues.iter().filter(|venue| tradable(venue)).count())
+    } else {
+        total_weight
+    };
+
+    let to_allocate = required_qty - stuck_qty;
+    let mut allocations = venues
+        .iter()
+        .map(|venue| {
+            if tradable(venue) {
+                Some((to_allocate * weight(venue) / total_weight).trunc())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let remainder = to_allocate - allocations.iter().flatten().sum::<Decimal>();
+    if let Some(first) = allocations
+        .iter_mut()
+        .zip(venues)
+        .filter(|(_, venue)| !weight(venue).is_zero())
+        .find_map(|(allocation, _)| allocation.as_mut())
+    {
+        *first += remainder;
+    }
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn venue(weight: Decimal, available: bool, hedged_qty: Option<Decimal>) -> VenuePosition {
+        VenuePosition {
+            weight,
+            available,
+            hedged_qty,
+        }
+    }
+
+    #[test]
+    fn test_allocate_exposure() {
+        let venues = vec![venue(dec!(2), true, Some(dec!(0))), venue(dec!(1), true, Some(dec!(0)))];
+        assert_eq!(
+            allocate_exposure(dec!(-100), &venues),
+            vec![Some(dec!(-67)), Some(dec!(-33))]
+        );
+
+        // The second venue is down, its position stays and the first one hedges the rest.
+        let venues = vec![
+            venue(dec!(2), true, Some(dec!(-60))),
+            venue(dec!(1), false, Some(dec!(-30))),
+        ];
+        assert_eq!(allocate_exposure(dec!(-100), &venues), vec![Some(dec!(-70)), None]);
+    }
+}
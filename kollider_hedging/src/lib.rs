@@ -273,7 +273,7 @@ impl KolliderHedgingClient {
         if matches!(currency, Currency::BTC) {
             return Err(KolliderClientError::NonFiatCurrency);
         }
-        let symbol: Symbol = currency.into();
+        let symbol = Symbol::try_from(currency).map_err(|_| KolliderClientError::NonFiatCurrency)?;
         let order = Request::Order(Order::new(side, quantity, symbol, Some(Uuid::new_v4())));
         self.checked_send_request(&order)
     }
@@ -295,7 +295,7 @@ impl WsClient for KolliderHedgingClient {
 
     fn get_balance(&self, currency: Currency) -> Result<Decimal> {
         if !matches!(currency, Currency::BTC) {
-            let symbol: Symbol = currency.into();
+            let symbol = Symbol::try_from(currency).map_err(|_| KolliderClientError::BalanceNotAvailable)?;
             {
                 let shared_state = get_locked_state(&self.state);
                 let (side, upnl) = match shared_state.position_states.get(&symbol) {
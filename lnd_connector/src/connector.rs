@@ -3,6 +3,7 @@ use msgs::*;
 use xerror::lnd_connector::*;
 
 use crossbeam_channel::Sender;
+use msgs::bank::{Bank, LndNodeHealth};
 use rust_decimal::prelude::*;
 use serde::{Deserialize, Serialize};
 use utils::time::*;
@@ -13,82 +14,362 @@ use sha256::digest;
 use unescape::unescape;
 
 const MINIMUM_FEE: i64 = 10;
+const MPP_TIMEOUT_SECS: i32 = 60;
+const DEFAULT_MPP_MAX_PARTS: u32 = 16;
+// Custom records of keysend payments, see bLIP-0003.
+const KEYSEND_PREIMAGE_RECORD: u64 = 5482373484;
+const KEYSEND_MESSAGE_RECORD: u64 = 34349334;
+// Feature bits of invoices accepting atomic multi-path payments.
+const AMP_REQUIRED_FEATURE: u32 = 30;
+const AMP_OPTIONAL_FEATURE: u32 = 31;
+// Error lnd tracks payments it doesn't know with.
+const PAYMENT_NOT_INITIATED: &str = "payment isn't initiated";
+// A node that answered recently isn't asked again before every call.
+const HEALTH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[derive(Debug, Clone)]
 pub struct PayResponse {
     pub payment_hash: String,
     pub fee: u64,
+    pub fee_msat: u64,
     pub preimage: Option<String>,
 }
 
+/// Final state of a payment as known by the node.
+#[derive(Debug, Clone)]
+pub enum PaymentLookup {
+    Succeeded(PayResponse),
+    Failed,
+    /// The node never dispatched the payment.
+    NotFound,
+}
+
+/// State of an invoice as known by the node.
+#[derive(Debug, Clone)]
+pub struct InvoiceLookup {
+    pub settled: bool,
+    pub amt_paid_msat: i64,
+}
+
+/// Random preimage of a keysend payment and its hex encoded payment hash.
+pub fn keysend_preimage() -> (Vec<u8>, String) {
+    // Two v4 uuids come from the os random number generator.
+    let preimage = [*Uuid::new_v4().as_bytes(), *Uuid::new_v4().as_bytes()].concat();
+    let payment_hash = digest(preimage.as_slice());
+    (preimage, payment_hash)
+}
+
+/// Funds held by the node in sats.
+#[derive(Debug, Clone, Default)]
+pub struct NodeBalances {
+    pub channel_local_balance: u64,
+    pub channel_pending_open_local_balance: u64,
+    pub wallet_confirmed_balance: u64,
+    pub wallet_unconfirmed_balance: u64,
+}
+
+/// Inbound liquidity of the active channels in sats, without the reserves peers have to keep.
+#[derive(Debug, Clone, Default)]
+pub struct InboundCapacity {
+    pub total: u64,
+    /// Largest amount receivable through a single channel, i.e. without multi-path payments.
+    pub largest_channel: u64,
+}
+
+/// Outbound liquidity of the active channels in sats, without the reserves the node has to keep.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundCapacity {
+    pub total: u64,
+    /// Largest amount payable through a single channel, i.e. without multi-path payments.
+    pub largest_channel: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LndNodeSettings {
+    pub host: String,
+    pub port: u32,
+    pub macaroon_path: String,
+    pub tls_path: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LndConnectorSettings {
     pub host: String,
     pub port: u32,
     pub macaroon_path: String,
     pub tls_path: String,
+    /// Standby nodes tried in order whenever the active node becomes unreachable.
+    #[serde(default)]
+    pub failover_nodes: Vec<LndNodeSettings>,
+    /// Payments above this amount in sats are split across multiple routes.
+    #[serde(default)]
+    pub mpp_threshold_sats: Option<u64>,
+    /// Max number of parts a multi-path payment is split into.
+    #[serde(default)]
+    pub mpp_max_parts: Option<u32>,
+    /// Pays invoices advertising AMP and sends keysend payments as atomic multi-path payments, so static
+    /// invoices can be paid repeatedly and spontaneous payments can be split.
+    #[serde(default)]
+    pub amp_enabled: bool,
+}
+
+impl LndConnectorSettings {
+    /// Returns the primary node followed by all configured standby nodes.
+    pub fn nodes(&self) -> Vec<LndNodeSettings> {
+        let primary = LndNodeSettings {
+            host: self.host.clone(),
+            port: self.port,
+            macaroon_path: self.macaroon_path.clone(),
+            tls_path: self.tls_path.clone(),
+        };
+        std::iter::once(primary)
+            .chain(self.failover_nodes.clone().into_iter())
+            .collect()
+    }
 }
 
+#[derive(Clone)]
 pub struct LndConnector {
-    _settings: LndConnectorSettings,
+    settings: LndConnectorSettings,
+    nodes: Vec<LndNodeSettings>,
+    active_node: usize,
+    health_listener: Option<Sender<Message>>,
+    /// When the active node last answered, `None` after an error so the next call checks it again.
+    last_healthy_at: Option<std::time::Instant>,
     ln_client: tonic_openssl_lnd::LndLightningClient,
-    _router_client: tonic_openssl_lnd::LndRouterClient,
+    router_client: tonic_openssl_lnd::LndRouterClient,
 }
 
-impl LndConnector {
-    pub async fn new(settings: LndConnectorSettings) -> Self {
-        let ln_client = tonic_openssl_lnd::connect_lightning(
-            settings.host.clone(),
-            settings.port,
-            settings.tls_path.clone(),
-            settings.macaroon_path.clone(),
+async fn connect_node(
+    node: &LndNodeSettings,
+) -> Option<(tonic_openssl_lnd::LndLightningClient, tonic_openssl_lnd::LndRouterClient)> {
+    let ln_client = tonic_openssl_lnd::connect_lightning(
+        node.host.clone(),
+        node.port,
+        node.tls_path.clone(),
+        node.macaroon_path.clone(),
+    )
+    .await
+    .ok()?;
+
+    let router_client = tonic_openssl_lnd::connect_router(
+        node.host.clone(),
+        node.port,
+        node.tls_path.clone(),
+        node.macaroon_path.clone(),
+    )
+    .await
+    .ok()?;
+
+    Some((ln_client, router_client))
+}
+
+/// Maps the payment error reported by lnd to a connector error.
+fn classify_payment_error(payment_error: &str) -> LndConnectorError {
+    let payment_error = payment_error.to_lowercase();
+    if payment_error.contains("unable to find a path") || payment_error.contains("no_route") {
+        LndConnectorError::NoRouteFound
+    } else if payment_error.contains("temporarychannelfailure") || payment_error.contains("temporary channel failure") {
+        LndConnectorError::TemporaryPaymentFailure
+    } else {
+        LndConnectorError::FailedToSendPayment
+    }
+}
+
+/// Startup check of a node: the files exist, the node is reachable and the macaroon grants the permissions the
+/// bank uses. Only permissions that can be probed without side effects are checked.
+pub async fn check_node(node: &LndNodeSettings) -> Result<String, String> {
+    for (kind, path) in [("tls certificate", &node.tls_path), ("macaroon", &node.macaroon_path)] {
+        if let Err(err) = std::fs::metadata(path) {
+            return Err(format!("can't read the {} at {}: {}", kind, path, err));
+        }
+    }
+
+    let (mut ln_client, _) = connect_node(node).await.ok_or_else(|| {
+        format!(
+            "can't connect to {}:{}. Check that lnd is running and the tls certificate belongs to it",
+            node.host, node.port
         )
-        .await
-        .expect("failed to connect");
+    })?;
 
-        let router_client = tonic_openssl_lnd::connect_router(
-            settings.host.clone(),
-            settings.port,
-            settings.tls_path.clone(),
-            settings.macaroon_path.clone(),
+    let denied = |permission: &str, status: &dyn std::fmt::Display| {
+        format!(
+            "the macaroon at {} lacks {}: {}. Bake one with the permissions of an admin macaroon",
+            node.macaroon_path, permission, status
         )
+    };
+
+    let info = ln_client
+        .get_info(tonic_openssl_lnd::lnrpc::GetInfoRequest::default())
+        .await
+        .map_err(|status| denied("info:read", &status))?
+        .into_inner();
+    ln_client
+        .list_invoices(tonic_openssl_lnd::lnrpc::ListInvoiceRequest {
+            num_max_invoices: 1,
+            ..Default::default()
+        })
+        .await
+        .map_err(|status| denied("invoices:read", &status))?;
+    ln_client
+        .channel_balance(tonic_openssl_lnd::lnrpc::ChannelBalanceRequest::default())
         .await
-        .expect("failed to connect");
+        .map_err(|status| denied("offchain:read", &status))?;
+    ln_client
+        .wallet_balance(tonic_openssl_lnd::lnrpc::WalletBalanceRequest::default())
+        .await
+        .map_err(|status| denied("onchain:read", &status))?;
+    ln_client
+        .sign_message(tonic_openssl_lnd::lnrpc::SignMessageRequest {
+            msg: b"preflight".to_vec(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|status| denied("message:write", &status))?;
+
+    if !info.synced_to_chain {
+        return Err(format!("{} is not synced to the chain yet", info.alias));
+    }
+    Ok(format!("{} at block {}", info.alias, info.block_height))
+}
 
-        Self {
-            _settings: settings,
-            ln_client,
-            _router_client: router_client,
+impl LndConnector {
+    pub async fn new(settings: LndConnectorSettings) -> Self {
+        Self::connect(settings).await.expect("failed to connect")
+    }
+
+    /// Connects to the first reachable node, `None` if none of them could be reached.
+    pub async fn connect(settings: LndConnectorSettings) -> Option<Self> {
+        let nodes = settings.nodes();
+
+        for (index, node) in nodes.iter().enumerate() {
+            if let Some((ln_client, router_client)) = connect_node(node).await {
+                return Some(Self {
+                    settings,
+                    nodes,
+                    active_node: index,
+                    health_listener: None,
+                    last_healthy_at: Some(std::time::Instant::now()),
+                    ln_client,
+                    router_client,
+                });
+            }
+            dbg!(format!("Failed to connect to lnd node at {}:{}", node.host, node.port));
+        }
+
+        None
+    }
+
+    /// Health events are sent to the given channel whenever the connector switches nodes.
+    pub fn with_health_listener(mut self, listener: Sender<Message>) -> Self {
+        self.health_listener = Some(listener);
+        self
+    }
+
+    /// Checks that the active node responds and switches over to the next reachable
+    /// node otherwise. Returns false if no node could be reached. The node is only asked
+    /// once per `HEALTH_CHECK_INTERVAL`, or on the next call after an error.
+    pub(crate) async fn ensure_healthy(&mut self) -> bool {
+        if let Some(last_healthy_at) = self.last_healthy_at {
+            if last_healthy_at.elapsed() < HEALTH_CHECK_INTERVAL {
+                return true;
+            }
+        }
+        let get_info = tonic_openssl_lnd::lnrpc::GetInfoRequest::default();
+        if self.ln_client.get_info(get_info).await.is_ok() {
+            self.last_healthy_at = Some(std::time::Instant::now());
+            return true;
         }
+        self.failover().await
+    }
+
+    /// The active node is checked again before the next call.
+    fn mark_unhealthy(&mut self) {
+        self.last_healthy_at = None;
     }
 
-    pub async fn sub_invoices(&mut self, listener: Sender<Message>) {
+    async fn failover(&mut self) -> bool {
+        let failed_node = self.active_node;
+
+        for offset in 1..=self.nodes.len() {
+            let index = (failed_node + offset) % self.nodes.len();
+            let (mut ln_client, router_client) = match connect_node(&self.nodes[index]).await {
+                Some(clients) => clients,
+                None => continue,
+            };
+            let get_info = tonic_openssl_lnd::lnrpc::GetInfoRequest::default();
+            if ln_client.get_info(get_info).await.is_err() {
+                continue;
+            }
+
+            self.ln_client = ln_client;
+            self.router_client = router_client;
+            self.active_node = index;
+            self.last_healthy_at = Some(std::time::Instant::now());
+            self.emit_health(failed_node, true);
+            return true;
+        }
+
+        self.emit_health(failed_node, false);
+        false
+    }
+
+    fn emit_health(&self, failed_node: usize, is_healthy: bool) {
+        if let Some(listener) = &self.health_listener {
+            let failed = &self.nodes[failed_node];
+            let active = &self.nodes[self.active_node];
+            let msg = Message::Bank(Bank::LndNodeHealth(LndNodeHealth {
+                failed_node: format!("{}:{}", failed.host, failed.port),
+                active_node: format!("{}:{}", active.host, active.port),
+                is_healthy,
+                timestamp: time_now(),
+            }));
+            if let Err(err) = listener.send(msg) {
+                dbg!(format!("Failed to send lnd health event: {:?}", err));
+            }
+        }
+    }
+
+    /// Sends settled invoices as deposits. Lnd first replays the invoices settled after `settle_index`, so
+    /// deposits made while the bank was down are caught up, and reconnects resume after the last invoice sent.
+    /// Once the stream breaks the subscription moves to a standby node if the active one stopped responding.
+    pub async fn sub_invoices(&mut self, listener: Sender<Message>, mut add_index: u64, mut settle_index: u64) {
         loop {
-            while let Ok(inv) = self
+            if !self.ensure_healthy().await {
+                dbg!("No lnd node reachable for the invoice subscription");
+            }
+            if let Ok(response) = self
                 .ln_client
                 .subscribe_invoices(tonic_openssl_lnd::lnrpc::InvoiceSubscription {
-                    add_index: 0,
-                    settle_index: 0,
+                    add_index,
+                    settle_index,
                 })
                 .await
             {
-                if let Ok(Some(invoice)) = inv.into_inner().message().await {
+                let mut invoices = response.into_inner();
+                while let Ok(Some(invoice)) = invoices.message().await {
+                    add_index = add_index.max(invoice.add_index);
                     if let Some(tonic_openssl_lnd::lnrpc::invoice::InvoiceState::Settled) =
                         tonic_openssl_lnd::lnrpc::invoice::InvoiceState::from_i32(invoice.state)
                     {
+                        settle_index = settle_index.max(invoice.settle_index);
                         let deposit = Deposit {
                             payment_request: invoice.payment_request,
+                            add_index: invoice.add_index,
+                            settle_index: invoice.settle_index,
                         };
                         let msg = Message::Deposit(deposit);
                         listener.send(msg).expect("Failed to send a message");
                     }
                 }
             }
+            self.mark_unhealthy();
             // Sleeping for a little bit before trying to reconnect.
             tokio::time::sleep(std::time::Duration::from_secs(5)).await;
         }
     }
 
+    #[tracing::instrument(skip(self, memo, metadata))]
     pub async fn create_invoice(
         &mut self,
         amount: u64,
@@ -97,6 +378,9 @@ impl LndConnector {
         account_id: Uuid,
         metadata: Option<String>,
     ) -> Result<Invoice, LndConnectorError> {
+        if !self.ensure_healthy().await {
+            return Err(LndConnectorError::NoHealthyNode);
+        }
 
         let hash = match metadata {
             Some(m) => {
@@ -108,7 +392,7 @@ impl LndConnector {
             },
             None => String::from(""),
         };
-        let description_hash = hex::decode(hash).expect("Decoding failed");
+        let description_hash = hex::decode(&hash).expect("Decoding failed");
 
         let invoice = tonic_openssl_lnd::lnrpc::Invoice {
             value: amount as i64,
@@ -138,12 +422,23 @@ impl LndConnector {
                 currency: None,
                 target_account_currency: None,
                 reference: Some(memo),
+                expired: false,
+                metadata_fields: None,
+                reference_hash: None,
+                metadata_fields_hash: None,
+                descriptions_hashed_at: None,
+                asset_units: None,
+                comment: None,
+                payer_data: None,
+                description_hash: if hash.is_empty() { None } else { Some(hash) },
             };
             return Ok(invoice);
         }
+        self.mark_unhealthy();
         Err(LndConnectorError::FailedToCreateInvoice)
     }
 
+    #[tracing::instrument(skip(self, payment_request))]
     pub async fn pay_invoice(
         &mut self,
         payment_request: String,
@@ -154,6 +449,11 @@ impl LndConnector {
         if max_fee_as_pp.is_none() && max_fee_in_sats.is_none() {
             return Err(LndConnectorError::FailedToSendPayment);
         }
+        // Only switching nodes before the payment is dispatched so that a payment
+        // can never be sent twice from two different nodes.
+        if !self.ensure_healthy().await {
+            return Err(LndConnectorError::NoHealthyNode);
+        }
         let mut max_fee = match max_fee_as_pp {
             Some(m) => (amount_in_sats * m).round_dp(0).to_i64().unwrap_or(0),
             None => 0,
@@ -164,12 +464,52 @@ impl LndConnector {
             None => max_fee,
         };
 
-        let limit = tonic_openssl_lnd::lnrpc::fee_limit::Limit::Fixed(max_fee);
+        self.send_payment(payment_request, amount_in_sats, max_fee * 1000, true).await
+    }
+
+    /// Pays an invoice without ever exceeding the fee quoted to the user and without
+    /// routing through our own node, so the quote is the final fee of the payment.
+    #[tracing::instrument(skip(self, payment_request))]
+    pub async fn pay_invoice_with_quoted_fee(
+        &mut self,
+        payment_request: String,
+        amount_in_sats: Decimal,
+        quoted_fee_msat: i64,
+    ) -> Result<PayResponse, LndConnectorError> {
+        if !self.ensure_healthy().await {
+            return Err(LndConnectorError::NoHealthyNode);
+        }
+        self.send_payment(payment_request, amount_in_sats, quoted_fee_msat, false).await
+    }
+
+    async fn send_payment(
+        &mut self,
+        payment_request: String,
+        amount_in_sats: Decimal,
+        max_fee_msat: i64,
+        allow_self_payment: bool,
+    ) -> Result<PayResponse, LndConnectorError> {
+        if self.settings.amp_enabled && self.supports_amp(&payment_request).await {
+            let max_parts = self.settings.mpp_max_parts.unwrap_or(DEFAULT_MPP_MAX_PARTS);
+            return self
+                .pay_invoice_multi_path(payment_request, max_fee_msat, max_parts, allow_self_payment, true)
+                .await;
+        }
+        if let Some(threshold) = self.settings.mpp_threshold_sats {
+            if amount_in_sats > Decimal::new(threshold as i64, 0) {
+                let max_parts = self.settings.mpp_max_parts.unwrap_or(DEFAULT_MPP_MAX_PARTS);
+                return self
+                    .pay_invoice_multi_path(payment_request, max_fee_msat, max_parts, allow_self_payment, false)
+                    .await;
+            }
+        }
+
+        let limit = tonic_openssl_lnd::lnrpc::fee_limit::Limit::FixedMsat(max_fee_msat);
         let fee_limit = tonic_openssl_lnd::lnrpc::FeeLimit { limit: Some(limit) };
         let send_payment = tonic_openssl_lnd::lnrpc::SendRequest {
             payment_request,
             fee_limit: Some(fee_limit),
-            allow_self_payment: true,
+            allow_self_payment,
             ..Default::default()
         };
 
@@ -177,20 +517,306 @@ impl LndConnector {
             let r = resp.into_inner();
             if !r.payment_error.is_empty() {
                 dbg!(format!("Payment error: {:?}", r.payment_error));
-                return Err(LndConnectorError::FailedToSendPayment);
+                return Err(classify_payment_error(&r.payment_error));
             }
-            let fee = match r.payment_route {
-                Some(pr) => pr.total_fees.try_into().unwrap_or(0),
-                None => 0,
+            let (fee, fee_msat) = match r.payment_route {
+                Some(pr) => (
+                    pr.total_fees.try_into().unwrap_or(0),
+                    pr.total_fees_msat.try_into().unwrap_or(0),
+                ),
+                None => (0, 0),
             };
             let response = PayResponse {
                 fee,
+                fee_msat,
                 payment_hash: hex::encode(r.payment_hash),
                 preimage: Some(hex::encode(r.payment_preimage)),
             };
             return Ok(response);
         }
-        Err(LndConnectorError::FailedToSendPayment)
+        // Lnd may have dispatched the payment before the call failed.
+        self.mark_unhealthy();
+        Err(LndConnectorError::PaymentStatusUnknown)
+    }
+
+    /// Whether the invoice accepts AMP payments, false if it can't be decoded.
+    pub async fn supports_amp(&mut self, payment_request: &str) -> bool {
+        match self.decode_payment_request(payment_request.to_string()).await {
+            Ok(pay_req) => {
+                pay_req.features.contains_key(&AMP_REQUIRED_FEATURE)
+                    || pay_req.features.contains_key(&AMP_OPTIONAL_FEATURE)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Pays an invoice through the router allowing lnd to split it into several parts, as an AMP payment if
+    /// `amp` is set. Fees of all settled parts are aggregated into a single response.
+    async fn pay_invoice_multi_path(
+        &mut self,
+        payment_request: String,
+        max_fee_msat: i64,
+        max_parts: u32,
+        allow_self_payment: bool,
+        amp: bool,
+    ) -> Result<PayResponse, LndConnectorError> {
+        let send_payment = tonic_openssl_lnd::routerrpc::SendPaymentRequest {
+            payment_request,
+            fee_limit_msat: max_fee_msat,
+            timeout_seconds: MPP_TIMEOUT_SECS,
+            max_parts,
+            allow_self_payment,
+            amp,
+            ..Default::default()
+        };
+        self.send_payment_v2(send_payment).await
+    }
+
+    /// Pushes `amount_in_sats` to the node with the hex encoded `destination` pubkey without an invoice. The
+    /// preimage is chosen by the sender, see `keysend_preimage`, and sent along with the optional message.
+    /// With AMP enabled the payment may be split and the preimage only identifies the payment, the shares
+    /// derive their own.
+    #[tracing::instrument(skip(self, preimage, message))]
+    pub async fn keysend(
+        &mut self,
+        destination: &str,
+        amount_in_sats: Decimal,
+        max_fee_msat: i64,
+        preimage: &[u8],
+        message: Option<&str>,
+    ) -> Result<PayResponse, LndConnectorError> {
+        let dest = hex::decode(destination).map_err(|_| LndConnectorError::FailedToSendPayment)?;
+        if !self.ensure_healthy().await {
+            return Err(LndConnectorError::NoHealthyNode);
+        }
+
+        let amp = self.settings.amp_enabled;
+        let mut dest_custom_records = std::collections::HashMap::new();
+        if !amp {
+            dest_custom_records.insert(KEYSEND_PREIMAGE_RECORD, preimage.to_vec());
+        }
+        if let Some(message) = message {
+            dest_custom_records.insert(KEYSEND_MESSAGE_RECORD, message.as_bytes().to_vec());
+        }
+        let send_payment = tonic_openssl_lnd::routerrpc::SendPaymentRequest {
+            dest,
+            amt: amount_in_sats.to_i64().unwrap_or(0),
+            payment_hash: hex::decode(digest(preimage)).map_err(|_| LndConnectorError::FailedToSendPayment)?,
+            dest_custom_records,
+            fee_limit_msat: max_fee_msat,
+            timeout_seconds: MPP_TIMEOUT_SECS,
+            max_parts: if amp {
+                self.settings.mpp_max_parts.unwrap_or(DEFAULT_MPP_MAX_PARTS)
+            } else {
+                1
+            },
+            amp,
+            ..Default::default()
+        };
+        self.send_payment_v2(send_payment).await
+    }
+
+    /// Pays an invoice of our own node out of the channel `outgoing_chan_id` and back in through the channel with
+    /// the peer `last_hop_pubkey`, which moves `amount_in_sats` of liquidity from the former to the latter.
+    #[tracing::instrument(skip(self))]
+    pub async fn rebalance(
+        &mut self,
+        outgoing_chan_id: u64,
+        last_hop_pubkey: &str,
+        amount_in_sats: u64,
+        max_fee_msat: i64,
+    ) -> Result<PayResponse, LndConnectorError> {
+        let last_hop_pubkey = hex::decode(last_hop_pubkey).map_err(|_| LndConnectorError::FailedToSendPayment)?;
+        if !self.ensure_healthy().await {
+            return Err(LndConnectorError::NoHealthyNode);
+        }
+
+        let invoice = tonic_openssl_lnd::lnrpc::Invoice {
+            value: amount_in_sats as i64,
+            memo: String::from("Rebalance"),
+            expiry: MPP_TIMEOUT_SECS as i64,
+            ..Default::default()
+        };
+        let payment_request = match self.ln_client.add_invoice(invoice).await {
+            Ok(resp) => resp.into_inner().payment_request,
+            Err(_) => return Err(LndConnectorError::FailedToCreateInvoice),
+        };
+
+        let send_payment = tonic_openssl_lnd::routerrpc::SendPaymentRequest {
+            payment_request,
+            fee_limit_msat: max_fee_msat,
+            timeout_seconds: MPP_TIMEOUT_SECS,
+            outgoing_chan_ids: vec![outgoing_chan_id],
+            last_hop_pubkey,
+            allow_self_payment: true,
+            ..Default::default()
+        };
+        self.send_payment_v2(send_payment).await
+    }
+
+    /// Sends the payment through the router and waits for its final state. Only final states lnd reports are
+    /// definite, a call or stream that breaks before leaves the payment in an unknown state to be looked up.
+    async fn send_payment_v2(
+        &mut self,
+        send_payment: tonic_openssl_lnd::routerrpc::SendPaymentRequest,
+    ) -> Result<PayResponse, LndConnectorError> {
+        let mut updates = match self.router_client.send_payment_v2(send_payment).await {
+            Ok(resp) => resp.into_inner(),
+            Err(err) => {
+                dbg!(&err);
+                self.mark_unhealthy();
+                return Err(LndConnectorError::PaymentStatusUnknown);
+            }
+        };
+
+        while let Ok(Some(payment)) = updates.message().await {
+            match tonic_openssl_lnd::lnrpc::payment::PaymentStatus::from_i32(payment.status) {
+                Some(tonic_openssl_lnd::lnrpc::payment::PaymentStatus::Succeeded) => {
+                    let fee_msat = payment
+                        .htlcs
+                        .iter()
+                        .filter(|htlc| {
+                            htlc.status == tonic_openssl_lnd::lnrpc::htlc_attempt::HtlcStatus::Succeeded as i32
+                        })
+                        .filter_map(|htlc| htlc.route.as_ref())
+                        .map(|route| route.total_fees_msat)
+                        .sum::<i64>();
+                    let response = PayResponse {
+                        fee: (fee_msat / 1000).try_into().unwrap_or(0),
+                        fee_msat: fee_msat.try_into().unwrap_or(0),
+                        payment_hash: payment.payment_hash,
+                        preimage: Some(payment.payment_preimage),
+                    };
+                    return Ok(response);
+                }
+                Some(tonic_openssl_lnd::lnrpc::payment::PaymentStatus::Failed) => {
+                    dbg!(format!("Payment error: {:?}", payment.failure_reason));
+                    let error = match tonic_openssl_lnd::lnrpc::PaymentFailureReason::from_i32(payment.failure_reason) {
+                        Some(tonic_openssl_lnd::lnrpc::PaymentFailureReason::FailureReasonNoRoute) => {
+                            LndConnectorError::NoRouteFound
+                        }
+                        Some(tonic_openssl_lnd::lnrpc::PaymentFailureReason::FailureReasonTimeout) => {
+                            LndConnectorError::TemporaryPaymentFailure
+                        }
+                        _ => LndConnectorError::FailedToSendPayment,
+                    };
+                    return Err(error);
+                }
+                _ => continue,
+            }
+        }
+        self.mark_unhealthy();
+        Err(LndConnectorError::PaymentStatusUnknown)
+    }
+
+    /// Pays invoices of the same destination along a single route. The route is looked up once for the
+    /// largest amount and every invoice is sent over its hops with SendToRoute. Each invoice gets its own
+    /// result, as later payments are still attempted after one failed.
+    #[tracing::instrument(skip(self, payment_requests), fields(invoices = payment_requests.len()))]
+    pub async fn pay_invoices_along_route(
+        &mut self,
+        payment_requests: &[String],
+        max_fee_as_pp: Decimal,
+    ) -> Result<Vec<Result<PayResponse, LndConnectorError>>, LndConnectorError> {
+        if !self.ensure_healthy().await {
+            return Err(LndConnectorError::NoHealthyNode);
+        }
+
+        let mut decoded = Vec::with_capacity(payment_requests.len());
+        for payment_request in payment_requests {
+            decoded.push(self.decode_payment_request(payment_request.clone()).await?);
+        }
+        let destination = match decoded.first() {
+            Some(pay_req) => pay_req.destination.clone(),
+            None => return Ok(Vec::new()),
+        };
+        if decoded.iter().any(|pay_req| pay_req.destination != destination) {
+            return Err(LndConnectorError::FailedToQueryRoutes);
+        }
+
+        let max_amount = decoded.iter().map(|pay_req| pay_req.num_satoshis).max().unwrap_or(0);
+        let max_fee = (Decimal::new(max_amount, 0) * max_fee_as_pp)
+            .round_dp(0)
+            .to_i64()
+            .unwrap_or(MINIMUM_FEE)
+            .max(MINIMUM_FEE);
+        let query_routes = tonic_openssl_lnd::lnrpc::QueryRoutesRequest {
+            pub_key: destination,
+            amt: max_amount,
+            fee_limit: Some(tonic_openssl_lnd::lnrpc::FeeLimit {
+                limit: Some(tonic_openssl_lnd::lnrpc::fee_limit::Limit::Fixed(max_fee)),
+            }),
+            use_mission_control: true,
+            ..Default::default()
+        };
+        let route = match self.ln_client.query_routes(query_routes).await {
+            Ok(resp) => resp.into_inner().routes.into_iter().next(),
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToQueryRoutes);
+            }
+        };
+        let hop_pubkeys = match route {
+            Some(route) => route
+                .hops
+                .iter()
+                .filter_map(|hop| hex::decode(&hop.pub_key).ok())
+                .collect::<Vec<_>>(),
+            None => return Err(LndConnectorError::NoRouteFound),
+        };
+
+        let mut results = Vec::with_capacity(decoded.len());
+        for pay_req in decoded {
+            results.push(self.send_to_route(&pay_req, hop_pubkeys.clone()).await);
+        }
+        Ok(results)
+    }
+
+    async fn send_to_route(
+        &mut self,
+        pay_req: &tonic_openssl_lnd::lnrpc::PayReq,
+        hop_pubkeys: Vec<Vec<u8>>,
+    ) -> Result<PayResponse, LndConnectorError> {
+        let payment_hash = hex::decode(&pay_req.payment_hash).map_err(|_| LndConnectorError::FailedToSendPayment)?;
+        let build_route = tonic_openssl_lnd::routerrpc::BuildRouteRequest {
+            amt_msat: pay_req.num_satoshis * 1000,
+            final_cltv_delta: pay_req.cltv_expiry as i32,
+            hop_pubkeys,
+            payment_addr: pay_req.payment_addr.clone(),
+            ..Default::default()
+        };
+        let route = match self.router_client.build_route(build_route).await {
+            Ok(resp) => resp.into_inner().route,
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::NoRouteFound);
+            }
+        };
+
+        let send_to_route = tonic_openssl_lnd::routerrpc::SendToRouteRequest {
+            payment_hash: payment_hash.clone(),
+            route,
+            ..Default::default()
+        };
+        let attempt = match self.router_client.send_to_route_v2(send_to_route).await {
+            Ok(resp) => resp.into_inner(),
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToSendPayment);
+            }
+        };
+
+        if attempt.status != tonic_openssl_lnd::lnrpc::htlc_attempt::HtlcStatus::Succeeded as i32 {
+            dbg!(format!("Payment error: {:?}", attempt.failure));
+            return Err(LndConnectorError::TemporaryPaymentFailure);
+        }
+        let fee_msat = attempt.route.map_or(0, |route| route.total_fees_msat);
+        Ok(PayResponse {
+            fee: (fee_msat / 1000).try_into().unwrap_or(0),
+            fee_msat: fee_msat.try_into().unwrap_or(0),
+            payment_hash: hex::encode(payment_hash),
+            preimage: Some(hex::encode(attempt.preimage)),
+        })
     }
 
     pub async fn get_node_info(&mut self) -> Result<LndNodeInfo, LndConnectorError> {
@@ -200,6 +826,7 @@ impl LndConnector {
                 let resp = ni.into_inner();
                 let lnd_node_info = LndNodeInfo {
                     identity_pubkey: resp.identity_pubkey,
+                    alias: resp.alias,
                     uris: resp.uris,
                     num_active_channels: resp.num_active_channels as u64,
                     num_pending_channels: resp.num_pending_channels as u64,
@@ -215,6 +842,212 @@ impl LndConnector {
         }
     }
 
+    pub async fn get_balances(&mut self) -> Result<NodeBalances, LndConnectorError> {
+        let channel_balance = match self
+            .ln_client
+            .channel_balance(tonic_openssl_lnd::lnrpc::ChannelBalanceRequest::default())
+            .await
+        {
+            Ok(resp) => resp.into_inner(),
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToGetBalances);
+            }
+        };
+
+        let wallet_balance = match self
+            .ln_client
+            .wallet_balance(tonic_openssl_lnd::lnrpc::WalletBalanceRequest::default())
+            .await
+        {
+            Ok(resp) => resp.into_inner(),
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToGetBalances);
+            }
+        };
+
+        Ok(NodeBalances {
+            channel_local_balance: channel_balance
+                .local_balance
+                .map(|amount| amount.sat)
+                .unwrap_or(channel_balance.balance as u64),
+            channel_pending_open_local_balance: channel_balance
+                .pending_open_local_balance
+                .map(|amount| amount.sat)
+                .unwrap_or(channel_balance.pending_open_balance as u64),
+            wallet_confirmed_balance: wallet_balance.confirmed_balance as u64,
+            wallet_unconfirmed_balance: wallet_balance.unconfirmed_balance as u64,
+        })
+    }
+
+    pub async fn get_inbound_capacity(&mut self) -> Result<InboundCapacity, LndConnectorError> {
+        let request = tonic_openssl_lnd::lnrpc::ListChannelsRequest {
+            active_only: true,
+            ..Default::default()
+        };
+        let channels = match self.ln_client.list_channels(request).await {
+            Ok(resp) => resp.into_inner().channels,
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToListChannels);
+            }
+        };
+
+        Ok(channels
+            .iter()
+            .fold(InboundCapacity::default(), |mut capacity, channel| {
+                let reserve = channel
+                    .remote_constraints
+                    .as_ref()
+                    .map_or(0, |constraints| constraints.chan_reserve_sat);
+                let inbound = (channel.remote_balance.max(0) as u64).saturating_sub(reserve);
+                capacity.total += inbound;
+                capacity.largest_channel = capacity.largest_channel.max(inbound);
+                capacity
+            }))
+    }
+
+    pub async fn get_outbound_capacity(&mut self) -> Result<OutboundCapacity, LndConnectorError> {
+        let request = tonic_openssl_lnd::lnrpc::ListChannelsRequest {
+            active_only: true,
+            ..Default::default()
+        };
+        let channels = match self.ln_client.list_channels(request).await {
+            Ok(resp) => resp.into_inner().channels,
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToListChannels);
+            }
+        };
+
+        Ok(channels
+            .iter()
+            .fold(OutboundCapacity::default(), |mut capacity, channel| {
+                let reserve = channel
+                    .local_constraints
+                    .as_ref()
+                    .map_or(0, |constraints| constraints.chan_reserve_sat);
+                let outbound = (channel.local_balance.max(0) as u64).saturating_sub(reserve);
+                capacity.total += outbound;
+                capacity.largest_channel = capacity.largest_channel.max(outbound);
+                capacity
+            }))
+    }
+
+    /// Opens a channel of `local_funding_amount` sats to a peer the node is connected to and returns the txid of
+    /// the funding transaction once it was published.
+    #[tracing::instrument(skip(self))]
+    pub async fn open_channel(
+        &mut self,
+        node_pubkey: &str,
+        local_funding_amount: u64,
+        private: bool,
+    ) -> Result<String, LndConnectorError> {
+        let node_pubkey = hex::decode(node_pubkey).map_err(|_| LndConnectorError::FailedToOpenChannel)?;
+        let request = tonic_openssl_lnd::lnrpc::OpenChannelRequest {
+            node_pubkey,
+            local_funding_amount: local_funding_amount as i64,
+            private,
+            ..Default::default()
+        };
+        let channel_point = match self.ln_client.open_channel_sync(request).await {
+            Ok(resp) => resp.into_inner(),
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToOpenChannel);
+            }
+        };
+        match channel_point.funding_txid {
+            Some(tonic_openssl_lnd::lnrpc::channel_point::FundingTxid::FundingTxidStr(txid)) => Ok(txid),
+            // The bytes are in the reversed order of the txid.
+            Some(tonic_openssl_lnd::lnrpc::channel_point::FundingTxid::FundingTxidBytes(mut txid)) => {
+                txid.reverse();
+                Ok(hex::encode(txid))
+            }
+            None => Err(LndConnectorError::FailedToOpenChannel),
+        }
+    }
+
+    /// Signs a message with the node's identity key. The signature can be verified against the node pubkey.
+    pub async fn sign_message(&mut self, message: &str) -> Result<String, LndConnectorError> {
+        let request = tonic_openssl_lnd::lnrpc::SignMessageRequest {
+            msg: message.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        match self.ln_client.sign_message(request).await {
+            Ok(resp) => Ok(resp.into_inner().signature),
+            Err(err) => {
+                dbg!(&err);
+                Err(LndConnectorError::FailedToSignMessage)
+            }
+        }
+    }
+
+    /// Waits for the final state of a payment made by the node, payments in flight are only returned once they
+    /// settled or failed.
+    #[tracing::instrument(skip(self))]
+    pub async fn lookup_payment(&mut self, payment_hash: &str) -> Result<PaymentLookup, LndConnectorError> {
+        let track_payment = tonic_openssl_lnd::routerrpc::TrackPaymentRequest {
+            payment_hash: hex::decode(payment_hash).map_err(|_| LndConnectorError::FailedToLookupPayment)?,
+            no_inflight_updates: true,
+        };
+
+        let mut updates = match self.router_client.track_payment_v2(track_payment).await {
+            Ok(resp) => resp.into_inner(),
+            Err(err) if err.message().contains(PAYMENT_NOT_INITIATED) => return Ok(PaymentLookup::NotFound),
+            Err(err) => {
+                dbg!(&err);
+                return Err(LndConnectorError::FailedToLookupPayment);
+            }
+        };
+
+        loop {
+            let payment = match updates.message().await {
+                Ok(Some(payment)) => payment,
+                Err(err) if err.message().contains(PAYMENT_NOT_INITIATED) => return Ok(PaymentLookup::NotFound),
+                Ok(None) | Err(_) => return Err(LndConnectorError::FailedToLookupPayment),
+            };
+            match tonic_openssl_lnd::lnrpc::payment::PaymentStatus::from_i32(payment.status) {
+                Some(tonic_openssl_lnd::lnrpc::payment::PaymentStatus::Succeeded) => {
+                    let response = PayResponse {
+                        fee: payment.fee_sat.try_into().unwrap_or(0),
+                        fee_msat: payment.fee_msat.try_into().unwrap_or(0),
+                        payment_hash: payment.payment_hash,
+                        preimage: Some(payment.payment_preimage),
+                    };
+                    return Ok(PaymentLookup::Succeeded(response));
+                }
+                Some(tonic_openssl_lnd::lnrpc::payment::PaymentStatus::Failed) => return Ok(PaymentLookup::Failed),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Looks an invoice of the node up by its hex encoded payment hash.
+    pub async fn lookup_invoice(&mut self, r_hash: &str) -> Result<InvoiceLookup, LndConnectorError> {
+        let payment_hash = tonic_openssl_lnd::lnrpc::PaymentHash {
+            r_hash: hex::decode(r_hash).map_err(|_| LndConnectorError::FailedToLookupInvoice)?,
+            ..Default::default()
+        };
+        match self.ln_client.lookup_invoice(payment_hash).await {
+            Ok(resp) => {
+                let invoice = resp.into_inner();
+                Ok(InvoiceLookup {
+                    settled: matches!(
+                        tonic_openssl_lnd::lnrpc::invoice::InvoiceState::from_i32(invoice.state),
+                        Some(tonic_openssl_lnd::lnrpc::invoice::InvoiceState::Settled)
+                    ),
+                    amt_paid_msat: invoice.amt_paid_msat,
+                })
+            }
+            Err(err) => {
+                dbg!(&err);
+                Err(LndConnectorError::FailedToLookupInvoice)
+            }
+        }
+    }
+
     pub async fn decode_payment_request(
         &mut self,
         payment_request: String,
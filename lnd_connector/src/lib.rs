@@ -1 +1,3 @@
 pub mod connector;
+pub mod pool;
+pub mod taproot_assets;
@@ -10,6 +10,10 @@ async fn main() {
         port: 10009,
         tls_path: "tls.cert".to_string(),
         macaroon_path: "admin.macaroon".to_string(),
+        failover_nodes: vec![],
+        mpp_threshold_sats: None,
+        mpp_max_parts: None,
+        amp_enabled: false,
     };
 
     let mut lnd_connector = LndConnector::new(settings).await;
@@ -0,0 +1,68 @@
+use crate::connector::{LndConnector, LndConnectorSettings};
+
+use crossbeam_channel::Sender;
+use msgs::Message;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use xerror::lnd_connector::LndConnectorError;
+
+/// Connecting is given up after this many attempts.
+const MAX_CONNECT_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(16);
+
+/// Connection to lnd shared by the bank and its payment tasks. The grpc clients multiplex calls over a single
+/// channel, so handing out clones of the pooled connector saves every caller the tls and macaroon handshake.
+/// The connection is checked before it's handed out and re-established with backoff once it failed.
+#[derive(Clone)]
+pub struct LndConnectorPool {
+    settings: LndConnectorSettings,
+    health_listener: Option<Sender<Message>>,
+    connector: Arc<Mutex<Option<LndConnector>>>,
+}
+
+impl LndConnectorPool {
+    pub fn new(settings: LndConnectorSettings) -> Self {
+        Self {
+            settings,
+            health_listener: None,
+            connector: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Health events of the pooled connection are sent to the given channel.
+    pub fn with_health_listener(mut self, listener: Sender<Message>) -> Self {
+        self.health_listener = Some(listener);
+        self
+    }
+
+    pub async fn get(&self) -> Result<LndConnector, LndConnectorError> {
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=MAX_CONNECT_ATTEMPTS {
+            {
+                let mut pooled = self.connector.lock().await;
+                if let Some(connector) = pooled.as_mut() {
+                    // Fails over to a standby node if the active one stopped responding.
+                    if connector.ensure_healthy().await {
+                        return Ok(connector.clone());
+                    }
+                    *pooled = None;
+                }
+                if let Some(connector) = LndConnector::connect(self.settings.clone()).await {
+                    let connector = match &self.health_listener {
+                        Some(listener) => connector.with_health_listener(listener.clone()),
+                        None => connector,
+                    };
+                    *pooled = Some(connector.clone());
+                    return Ok(connector);
+                }
+            }
+            if attempt < MAX_CONNECT_ATTEMPTS {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        Err(LndConnectorError::NoHealthyNode)
+    }
+}
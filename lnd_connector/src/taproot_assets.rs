@@ -0,0 +1,325 @@
+use core_types::Currency;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use xerror::lnd_connector::LndConnectorError;
+
+use crate::connector::PayResponse;
+
+/// Requests to tapd taking longer than this fail, paying an invoice included.
+const TAPD_TIMEOUT: Duration = Duration::from_secs(90);
+const ASSET_INVOICE_EXPIRY_SECS: u64 = 86400;
+/// Seconds tapd tries to pay an invoice before giving up.
+const PAYMENT_TIMEOUT_SECS: u64 = 60;
+/// Seconds a sell quote of the asset channel peer is valid for.
+const SELL_QUOTE_EXPIRY_SECS: u64 = 60;
+const MSATS_IN_BITCOIN: u64 = 100_000_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaprootAsset {
+    /// Hex encoded id of the asset.
+    pub asset_id: String,
+    /// Currency the asset is held as in the ledger.
+    pub currency: Currency,
+    /// Decimal places of the asset, tapd counts amounts in the smallest unit.
+    pub decimal_display: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaprootAssetsSettings {
+    /// REST url of tapd, e.g. `https://localhost:8089`.
+    pub url: String,
+    /// Hex encoded macaroon tapd is authenticated with.
+    pub macaroon: Option<String>,
+    /// Pem certificate of tapd if it is self-signed.
+    pub tls_cert_path: Option<String>,
+    /// Node the asset channels of the bank are with, it quotes the asset against sats.
+    pub peer_pubkey: String,
+    /// Routing fees in sats an asset payment may spend.
+    pub fee_limit_sats: u64,
+    pub assets: Vec<TaprootAsset>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetInvoice {
+    pub payment_request: String,
+    pub r_hash: String,
+    pub payment_addr: String,
+    pub add_index: u64,
+}
+
+/// Rate the asset channel peer sells sats for the asset at.
+#[derive(Debug, Clone)]
+pub struct AssetSellQuote {
+    /// Base64 encoded id the payment refers to the quote with.
+    pub id: String,
+    pub units_per_btc: Decimal,
+}
+
+impl AssetSellQuote {
+    /// Asset units a payment of `amount_msat` costs, rounded up.
+    pub fn units_for_msat(&self, amount_msat: u64) -> Option<u64> {
+        (Decimal::from(amount_msat) * self.units_per_btc / Decimal::from(MSATS_IN_BITCOIN))
+            .ceil()
+            .to_u64()
+    }
+}
+
+impl TaprootAsset {
+    /// Amount in the smallest unit of the asset, cut at the asset's decimal places.
+    pub fn to_units(&self, amount: Decimal) -> Option<u64> {
+        (amount * Decimal::from(10u64.pow(self.decimal_display)))
+            .floor()
+            .to_u64()
+    }
+
+    pub fn from_units(&self, units: u64) -> Decimal {
+        Decimal::new(units as i64, self.decimal_display)
+    }
+}
+
+/// Receives and sends Taproot Assets over lightning through the REST gateway of the tapd next to the node.
+pub struct TaprootAssetsConnector {
+    client: reqwest::Client,
+    url: String,
+    macaroon: Option<String>,
+    peer_pubkey: String,
+    fee_limit_sats: u64,
+    assets: Vec<TaprootAsset>,
+}
+
+impl TaprootAssetsConnector {
+    pub fn new(settings: &TaprootAssetsSettings) -> Result<Self, String> {
+        let mut builder = reqwest::Client::builder().timeout(TAPD_TIMEOUT);
+        if let Some(path) = &settings.tls_cert_path {
+            let pem = std::fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+            let cert = reqwest::Certificate::from_pem(&pem).map_err(|err| format!("Invalid certificate: {}", err))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        let client = builder
+            .build()
+            .map_err(|err| format!("Failed to build http client: {}", err))?;
+        Ok(Self {
+            client,
+            url: settings.url.trim_end_matches('/').to_string(),
+            macaroon: settings.macaroon.clone(),
+            peer_pubkey: settings.peer_pubkey.clone(),
+            fee_limit_sats: settings.fee_limit_sats,
+            assets: settings.assets.clone(),
+        })
+    }
+
+    pub fn fee_limit_sats(&self) -> u64 {
+        self.fee_limit_sats
+    }
+
+    /// The asset a currency is held as, None for currencies that aren't backed by an asset.
+    pub fn asset(&self, currency: Currency) -> Option<&TaprootAsset> {
+        self.assets.iter().find(|asset| asset.currency == currency)
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<String, String> {
+        let mut request = self.client.post(&format!("{}{}", self.url, path)).json(&body);
+        if let Some(macaroon) = &self.macaroon {
+            request = request.header("Grpc-Metadata-macaroon", macaroon.as_str());
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|err| format!("tapd unreachable: {}", err))?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|err| format!("Invalid response: {}", err))?;
+        if !status.is_success() {
+            return Err(format!("tapd answered {}: {}", status, text));
+        }
+        Ok(text)
+    }
+
+    async fn post_json(&self, path: &str, body: Value) -> Result<Value, String> {
+        let text = self.post(path, body).await?;
+        serde_json::from_str(&text).map_err(|err| format!("Invalid response: {}", err))
+    }
+
+    /// Bytes are base64 encoded in the REST gateway.
+    fn hex_to_base64(value: &str) -> Result<String, String> {
+        hex::decode(value)
+            .map(base64::encode)
+            .map_err(|_| format!("Invalid hex {}", value))
+    }
+
+    fn base64_to_hex(value: &Value) -> String {
+        value
+            .as_str()
+            .and_then(|value| base64::decode(value).ok())
+            .map(hex::encode)
+            .unwrap_or_default()
+    }
+
+    /// The gateway encodes 64 bit integers as strings.
+    fn as_u64(value: &Value) -> Option<u64> {
+        value
+            .as_u64()
+            .or_else(|| value.as_str().and_then(|value| value.parse().ok()))
+    }
+
+    /// Creates an invoice paid in `units` of the asset, the payer's sats are converted by the asset channel peer.
+    #[tracing::instrument(skip(self))]
+    pub async fn add_invoice(
+        &self,
+        asset: &TaprootAsset,
+        units: u64,
+        memo: String,
+    ) -> Result<AssetInvoice, LndConnectorError> {
+        self.request_invoice(asset, units, memo).await.map_err(|err| {
+            tracing::error!("Failed to create asset invoice: {}", err);
+            LndConnectorError::FailedToCreateInvoice
+        })
+    }
+
+    async fn request_invoice(&self, asset: &TaprootAsset, units: u64, memo: String) -> Result<AssetInvoice, String> {
+        let body = json!({
+            "asset_id": Self::hex_to_base64(&asset.asset_id)?,
+            "asset_amount": units.to_string(),
+            "peer_pubkey": Self::hex_to_base64(&self.peer_pubkey)?,
+            "invoice_request": {
+                "memo": memo,
+                "expiry": ASSET_INVOICE_EXPIRY_SECS.to_string(),
+            },
+        });
+        let response = self.post_json("/v1/taproot-assets/channels/invoice", body).await?;
+        let invoice = &response["invoice_result"];
+        let payment_request = invoice["payment_request"]
+            .as_str()
+            .ok_or_else(|| format!("No invoice in response: {}", response))?;
+        Ok(AssetInvoice {
+            payment_request: payment_request.to_string(),
+            r_hash: Self::base64_to_hex(&invoice["r_hash"]),
+            payment_addr: Self::base64_to_hex(&invoice["payment_addr"]),
+            add_index: Self::as_u64(&invoice["add_index"]).unwrap_or(0),
+        })
+    }
+
+    /// Asks the asset channel peer for the rate of a payment of up to `payment_max_msat`.
+    #[tracing::instrument(skip(self))]
+    pub async fn sell_quote(
+        &self,
+        asset: &TaprootAsset,
+        payment_max_msat: u64,
+    ) -> Result<AssetSellQuote, LndConnectorError> {
+        self.request_sell_quote(asset, payment_max_msat).await.map_err(|err| {
+            tracing::error!("Failed to get asset sell quote: {}", err);
+            LndConnectorError::FailedToGetAssetQuote
+        })
+    }
+
+    async fn request_sell_quote(&self, asset: &TaprootAsset, payment_max_msat: u64) -> Result<AssetSellQuote, String> {
+        let body = json!({
+            "payment_max_amt": payment_max_msat.to_string(),
+            "expiry": (utils::time::time_now() / 1000 + SELL_QUOTE_EXPIRY_SECS).to_string(),
+            "peer_pub_key": Self::hex_to_base64(&self.peer_pubkey)?,
+            "timeout_seconds": 30,
+        });
+        let path = format!("/v1/taproot-assets/rfq/sellorder/asset-id/{}", asset.asset_id);
+        let response = self.post_json(&path, body).await?;
+        let quote = &response["accepted_quote"];
+        // The rate is a fixed point number, the coefficient shifted by `scale` decimal places.
+        let mut units_per_btc = quote["bid_asset_rate"]["coefficient"]
+            .as_str()
+            .and_then(|coefficient| Decimal::from_str(coefficient).ok())
+            .ok_or_else(|| format!("Quote rejected: {}", response))?;
+        let scale = Self::as_u64(&quote["bid_asset_rate"]["scale"]).unwrap_or(0) as u32;
+        units_per_btc
+            .set_scale(scale)
+            .map_err(|err| format!("Invalid rate scale: {}", err))?;
+        match quote["id"].as_str() {
+            Some(id) if units_per_btc > Decimal::ZERO => Ok(AssetSellQuote {
+                id: id.to_string(),
+                units_per_btc,
+            }),
+            _ => Err(format!("Invalid quote: {}", response)),
+        }
+    }
+
+    /// Pays an invoice with the asset at the rate of a sell quote.
+    #[tracing::instrument(skip(self, payment_request))]
+    pub async fn send_payment(
+        &self,
+        asset: &TaprootAsset,
+        payment_request: &str,
+        quote: &AssetSellQuote,
+    ) -> Result<PayResponse, LndConnectorError> {
+        self.request_payment(asset, payment_request, quote)
+            .await
+            .map_err(|err| {
+                tracing::error!("Failed to send asset payment: {}", err);
+                LndConnectorError::FailedToSendPayment
+            })
+    }
+
+    async fn request_payment(
+        &self,
+        asset: &TaprootAsset,
+        payment_request: &str,
+        quote: &AssetSellQuote,
+    ) -> Result<PayResponse, String> {
+        let body = json!({
+            "asset_id": Self::hex_to_base64(&asset.asset_id)?,
+            "rfq_id": quote.id,
+            "payment_request": {
+                "payment_request": payment_request,
+                "fee_limit_sat": self.fee_limit_sats.to_string(),
+                "timeout_seconds": PAYMENT_TIMEOUT_SECS,
+            },
+        });
+        let text = self.post("/v1/taproot-assets/channels/send-payment", body).await?;
+        // The gateway streams one json object per update, the last payment result is the final one.
+        let payment = text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .map(|update| update["result"]["payment_result"].clone())
+            .filter(|payment| payment.is_object())
+            .last()
+            .ok_or_else(|| format!("No payment result: {}", text))?;
+        if payment["status"].as_str() != Some("SUCCEEDED") {
+            return Err(format!("Payment failed: {}", payment));
+        }
+        let fee_msat = Self::as_u64(&payment["fee_msat"]).unwrap_or(0);
+        Ok(PayResponse {
+            payment_hash: payment["payment_hash"].as_str().unwrap_or_default().to_string(),
+            fee: fee_msat / 1000,
+            fee_msat,
+            preimage: payment["payment_preimage"]
+                .as_str()
+                .map(|preimage| preimage.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_asset_units() {
+        let asset = TaprootAsset {
+            asset_id: String::from("00"),
+            currency: Currency::USDT,
+            decimal_display: 6,
+        };
+        assert_eq!(asset.to_units(dec!(12.3456789)), Some(12_345_678));
+        assert_eq!(asset.from_units(12_345_678), dec!(12.345678));
+
+        // 50,000.000000 units per BTC, a 100,000 sats payment costs 50 units.
+        let quote = AssetSellQuote {
+            id: String::new(),
+            units_per_btc: dec!(50_000_000_000),
+        };
+        assert_eq!(quote.units_for_msat(100_000_000), Some(50_000_000));
+        assert_eq!(quote.units_for_msat(1), Some(1));
+    }
+}
@@ -0,0 +1,15 @@
+use std::fs;
+
+/// Exposes the version of the newest migration so services can check the schema of the database they connect to.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    let latest = fs::read_dir("migrations")
+        .expect("Failed to read migrations")
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| name.split('_').next().map(|version| version.replace('-', "")))
+        .filter(|version| !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()))
+        .max()
+        .unwrap_or_default();
+    println!("cargo:rustc-env=LATEST_MIGRATION_VERSION={}", latest);
+}
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE payment_retries (
+req_id TEXT NOT NULL PRIMARY KEY,
+uid integer NOT NULL,
+payment_request TEXT NOT NULL,
+payment_result TEXT NOT NULL,
+attempts integer NOT NULL DEFAULT 0,
+next_attempt_at BIGINT NOT NULL,
+created_at BIGINT NOT NULL
+);
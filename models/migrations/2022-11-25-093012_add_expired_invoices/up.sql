@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS expired BOOLEAN NOT NULL DEFAULT false;
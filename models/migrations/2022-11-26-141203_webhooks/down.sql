@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE webhooks;
+
+ALTER TABLE invoices DROP COLUMN metadata_fields;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE webhooks (
+webhook_id SERIAL PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+url TEXT NOT NULL,
+metadata_filter TEXT,
+created_at BIGINT NOT NULL
+);
+
+ALTER TABLE invoices ADD COLUMN metadata_fields TEXT;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE idempotency_keys (
+uid integer NOT NULL,
+idempotency_key TEXT NOT NULL,
+req_id TEXT NOT NULL,
+response TEXT,
+created_at BIGINT NOT NULL,
+PRIMARY KEY (uid, idempotency_key)
+);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE recovery_audit_logs;
+DROP TABLE recovery_approvals;
+DROP TABLE recovery_requests;
+DROP TABLE recovery_guardians;
+DROP TABLE recovery_configs;
@@ -0,0 +1,42 @@
+-- Your SQL goes here
+CREATE TABLE recovery_configs (
+uid integer NOT NULL PRIMARY KEY REFERENCES users(uid),
+threshold integer NOT NULL,
+delay_secs BIGINT NOT NULL,
+updated_at BIGINT NOT NULL
+);
+
+CREATE TABLE recovery_guardians (
+uid integer NOT NULL REFERENCES users(uid),
+guardian_uid integer NOT NULL REFERENCES users(uid),
+created_at BIGINT NOT NULL,
+PRIMARY KEY (uid, guardian_uid)
+);
+
+CREATE TABLE recovery_requests (
+recovery_id SERIAL PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+password TEXT NOT NULL,
+state TEXT NOT NULL,
+approvals_required integer NOT NULL,
+delay_secs BIGINT NOT NULL,
+created_at BIGINT NOT NULL,
+approved_at BIGINT,
+closed_at BIGINT
+);
+
+CREATE TABLE recovery_approvals (
+recovery_id integer NOT NULL REFERENCES recovery_requests(recovery_id),
+guardian_uid integer NOT NULL REFERENCES users(uid),
+created_at BIGINT NOT NULL,
+PRIMARY KEY (recovery_id, guardian_uid)
+);
+
+CREATE TABLE recovery_audit_logs (
+audit_id SERIAL PRIMARY KEY,
+uid integer NOT NULL,
+recovery_id integer,
+actor_uid integer,
+action TEXT NOT NULL,
+created_at BIGINT NOT NULL
+);
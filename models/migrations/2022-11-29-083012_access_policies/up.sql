@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE access_policies (
+uid integer PRIMARY KEY REFERENCES users(uid),
+allowed_ip_ranges TEXT,
+allowed_countries TEXT,
+updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE dealer_health_events (
+id SERIAL PRIMARY KEY,
+currency TEXT NOT NULL,
+available BOOLEAN NOT NULL,
+created_at BIGINT NOT NULL
+);
+
+CREATE INDEX dealer_health_events_currency_created_at ON dealer_health_events (currency, created_at);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE webhooks DROP COLUMN secret;
+ALTER TABLE webhooks DROP COLUMN events;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE webhooks ADD COLUMN secret TEXT;
+ALTER TABLE webhooks ADD COLUMN events TEXT;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE deposit_routing_rules (
+rule_id SERIAL PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+account_id UUID NOT NULL,
+memo_pattern TEXT,
+metadata_key TEXT,
+priority integer NOT NULL DEFAULT 0,
+created_at BIGINT NOT NULL
+);
+
+CREATE INDEX deposit_routing_rules_uid ON deposit_routing_rules (uid);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE accounts DROP COLUMN label;
+ALTER TABLE accounts DROP COLUMN archived;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE accounts ADD COLUMN label TEXT;
+ALTER TABLE accounts ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE account_members (
+id SERIAL PRIMARY KEY,
+account_id UUID NOT NULL REFERENCES accounts(account_id),
+uid integer NOT NULL REFERENCES users(uid),
+role TEXT NOT NULL,
+spend_limit decimal,
+invited_by integer NOT NULL,
+accepted BOOLEAN NOT NULL DEFAULT FALSE,
+created_at BIGINT NOT NULL,
+UNIQUE (account_id, uid)
+);
+
+CREATE INDEX account_members_uid ON account_members (uid);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE operator_revenues (
+id SERIAL PRIMARY KEY,
+day BIGINT NOT NULL,
+source TEXT NOT NULL,
+currency TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+tx_count integer NOT NULL,
+created_at BIGINT NOT NULL,
+UNIQUE (day, source, currency)
+);
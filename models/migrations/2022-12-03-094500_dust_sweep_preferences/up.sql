@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE dust_sweep_preferences (
+uid integer PRIMARY KEY REFERENCES users(uid),
+enabled BOOLEAN NOT NULL,
+updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER summary_transactions_closed_period ON summary_transactions;
+DROP TRIGGER transactions_closed_period ON transactions;
+DROP FUNCTION reject_closed_period_postings();
+DROP FUNCTION closed_until();
+DROP TABLE period_closing_balances;
+DROP TABLE period_closes;
@@ -0,0 +1,43 @@
+-- Your SQL goes here
+CREATE TABLE period_closes (
+id SERIAL PRIMARY KEY,
+closed_until BIGINT NOT NULL,
+closed_at BIGINT NOT NULL,
+closed_by TEXT NOT NULL,
+reopened_at BIGINT,
+reopened_by TEXT,
+reopen_reason TEXT
+);
+
+CREATE TABLE period_closing_balances (
+close_id integer NOT NULL REFERENCES period_closes(id),
+account_id UUID NOT NULL,
+balance NUMERIC NOT NULL,
+PRIMARY KEY (close_id, account_id)
+);
+
+-- End of the latest period that is closed and wasn't reopened.
+CREATE FUNCTION closed_until() RETURNS BIGINT AS $$
+    SELECT COALESCE(MAX(closed_until), -1) FROM period_closes WHERE reopened_at IS NULL;
+$$ LANGUAGE SQL STABLE;
+
+CREATE FUNCTION reject_closed_period_postings() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND OLD.created_at <= closed_until() THEN
+        RAISE EXCEPTION 'Transaction % belongs to a closed period', OLD.txid;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND NEW.created_at <= closed_until() THEN
+        RAISE EXCEPTION 'Transaction % belongs to a closed period', NEW.txid;
+    END IF;
+    IF TG_OP = 'DELETE' THEN
+        RETURN OLD;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_closed_period BEFORE INSERT OR UPDATE OR DELETE ON transactions
+FOR EACH ROW EXECUTE PROCEDURE reject_closed_period_postings();
+
+CREATE TRIGGER summary_transactions_closed_period BEFORE INSERT OR UPDATE OR DELETE ON summary_transactions
+FOR EACH ROW EXECUTE PROCEDURE reject_closed_period_postings();
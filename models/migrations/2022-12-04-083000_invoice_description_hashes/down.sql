@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE invoices DROP COLUMN descriptions_hashed_at;
+ALTER TABLE invoices DROP COLUMN metadata_fields_hash;
+ALTER TABLE invoices DROP COLUMN reference_hash;
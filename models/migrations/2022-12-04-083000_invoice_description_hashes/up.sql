@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE invoices ADD COLUMN reference_hash TEXT;
+ALTER TABLE invoices ADD COLUMN metadata_fields_hash TEXT;
+ALTER TABLE invoices ADD COLUMN descriptions_hashed_at BIGINT;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE data_exports (
+export_id UUID PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+content TEXT NOT NULL,
+created_at BIGINT NOT NULL,
+expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX data_exports_expires_at_idx ON data_exports (expires_at);
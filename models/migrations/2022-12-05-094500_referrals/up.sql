@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE referrals (
+referee_uid integer PRIMARY KEY REFERENCES users(uid),
+referrer_uid integer NOT NULL REFERENCES users(uid),
+created_at BIGINT NOT NULL,
+CHECK (referee_uid <> referrer_uid)
+);
+
+CREATE INDEX referrals_referrer_uid_idx ON referrals (referrer_uid);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE interest_accruals (
+id SERIAL PRIMARY KEY,
+account_id UUID NOT NULL,
+uid integer NOT NULL REFERENCES users(uid),
+day BIGINT NOT NULL,
+currency TEXT NOT NULL,
+balance NUMERIC NOT NULL,
+apy NUMERIC NOT NULL,
+amount NUMERIC NOT NULL,
+txid TEXT,
+created_at BIGINT NOT NULL,
+UNIQUE (account_id, day)
+);
+
+CREATE INDEX interest_accruals_uid_day_idx ON interest_accruals (uid, day);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE settlement_batches (
+group_id UUID NOT NULL,
+req_id UUID NOT NULL,
+kind TEXT NOT NULL,
+direction TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+txid TEXT,
+created_at BIGINT NOT NULL,
+PRIMARY KEY (group_id, req_id)
+);
+
+CREATE INDEX settlement_batches_txid_idx ON settlement_batches (txid);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE withdrawal_limits (
+uid integer PRIMARY KEY REFERENCES users(uid),
+daily_sats BIGINT,
+weekly_sats BIGINT,
+updated_at BIGINT NOT NULL,
+CHECK (daily_sats IS NULL OR daily_sats >= 0),
+CHECK (weekly_sats IS NULL OR weekly_sats >= 0)
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE rate_limit_buckets (
+bucket_key TEXT PRIMARY KEY,
+tokens DOUBLE PRECISION NOT NULL,
+updated_at BIGINT NOT NULL
+);
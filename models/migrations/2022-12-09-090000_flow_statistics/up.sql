@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE flow_statistics (
+id SERIAL PRIMARY KEY,
+day BIGINT NOT NULL,
+flow TEXT NOT NULL,
+market TEXT NOT NULL,
+volume NUMERIC NOT NULL,
+fees NUMERIC NOT NULL,
+tx_count integer NOT NULL,
+created_at BIGINT NOT NULL,
+UNIQUE (day, flow, market)
+);
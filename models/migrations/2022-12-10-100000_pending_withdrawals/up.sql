@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE pending_withdrawals (
+req_id TEXT NOT NULL PRIMARY KEY,
+uid integer NOT NULL,
+amount_sats BIGINT NOT NULL,
+currency TEXT NOT NULL,
+payment_request TEXT NOT NULL,
+status TEXT NOT NULL,
+decided_by TEXT,
+reason TEXT,
+created_at BIGINT NOT NULL,
+decided_at BIGINT,
+processed_at BIGINT
+);
+
+CREATE INDEX pending_withdrawals_status_idx ON pending_withdrawals (status, processed_at);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE deposit_limits;
+ALTER TABLE users DROP COLUMN frozen_reason;
+ALTER TABLE users DROP COLUMN frozen;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN frozen BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN frozen_reason TEXT;
+
+CREATE TABLE deposit_limits (
+uid integer NOT NULL REFERENCES users(uid),
+currency TEXT NOT NULL,
+max_balance NUMERIC NOT NULL,
+updated_at BIGINT NOT NULL,
+PRIMARY KEY (uid, currency),
+CHECK (max_balance >= 0)
+);
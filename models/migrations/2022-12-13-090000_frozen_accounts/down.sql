@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE account_freeze_audit_logs;
+DROP TABLE frozen_accounts;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE frozen_accounts (
+account_id UUID PRIMARY KEY REFERENCES accounts(account_id),
+uid integer NOT NULL REFERENCES users(uid),
+reason_code TEXT NOT NULL,
+note TEXT,
+frozen_by TEXT NOT NULL,
+frozen_at BIGINT NOT NULL
+);
+
+CREATE TABLE account_freeze_audit_logs (
+audit_id SERIAL PRIMARY KEY,
+account_id UUID NOT NULL REFERENCES accounts(account_id),
+uid integer NOT NULL REFERENCES users(uid),
+action TEXT NOT NULL,
+reason_code TEXT,
+note TEXT,
+operator TEXT NOT NULL,
+created_at BIGINT NOT NULL
+);
+
+CREATE INDEX account_freeze_audit_logs_account_id_idx ON account_freeze_audit_logs (account_id);
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE audit_logs (
+audit_id SERIAL PRIMARY KEY,
+txid TEXT NOT NULL,
+actor TEXT NOT NULL,
+actor_id TEXT,
+operation TEXT NOT NULL,
+req_id TEXT,
+uid integer NOT NULL,
+account_id UUID NOT NULL,
+currency TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+balance_before NUMERIC NOT NULL,
+balance_after NUMERIC NOT NULL,
+created_at BIGINT NOT NULL
+);
+
+CREATE INDEX audit_logs_uid_idx ON audit_logs (uid);
+CREATE INDEX audit_logs_req_id_idx ON audit_logs (req_id);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE swap_orders (
+order_id UUID PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+from_currency TEXT NOT NULL,
+to_currency TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+filled_amount NUMERIC NOT NULL DEFAULT 0,
+limit_rate NUMERIC NOT NULL,
+status TEXT NOT NULL,
+created_at BIGINT NOT NULL,
+updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX swap_orders_uid_idx ON swap_orders (uid);
+CREATE INDEX swap_orders_status_idx ON swap_orders (status);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE pending_payments (
+req_id TEXT NOT NULL PRIMARY KEY,
+uid integer NOT NULL,
+payment_hash TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+estimated_fee_sats NUMERIC NOT NULL,
+payment_result TEXT NOT NULL,
+created_at BIGINT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE dead_letters (
+id SERIAL PRIMARY KEY,
+source TEXT NOT NULL,
+reason TEXT NOT NULL,
+payload BYTEA NOT NULL,
+created_at BIGINT NOT NULL,
+replayed_at BIGINT
+);
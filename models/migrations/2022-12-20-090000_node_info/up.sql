@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE node_info (
+id INTEGER PRIMARY KEY,
+payload TEXT NOT NULL,
+updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE invoice_checkpoints (
+id INTEGER PRIMARY KEY,
+add_index BIGINT NOT NULL,
+settle_index BIGINT NOT NULL,
+updated_at BIGINT NOT NULL
+);
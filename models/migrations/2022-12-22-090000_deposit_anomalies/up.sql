@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE deposit_anomalies (
+id SERIAL PRIMARY KEY,
+payment_request TEXT NOT NULL,
+reason TEXT NOT NULL,
+expected_msat BIGINT NOT NULL,
+paid_msat BIGINT,
+created_at BIGINT NOT NULL
+);
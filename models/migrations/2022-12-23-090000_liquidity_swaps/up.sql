@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE liquidity_swaps (
+id SERIAL PRIMARY KEY,
+provider TEXT NOT NULL,
+swap_id TEXT NOT NULL,
+direction TEXT NOT NULL,
+amount_sats BIGINT NOT NULL,
+state TEXT NOT NULL,
+cost_sats BIGINT,
+created_at BIGINT NOT NULL,
+updated_at BIGINT NOT NULL
+);
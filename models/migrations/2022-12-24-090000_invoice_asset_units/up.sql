@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE invoices ADD COLUMN asset_units BIGINT;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE liquid_deposits;
+DROP TABLE liquid_addresses;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE liquid_addresses (
+address TEXT PRIMARY KEY,
+uid INT NOT NULL,
+account_id TEXT NOT NULL,
+claim_script TEXT,
+created_at BIGINT NOT NULL
+);
+
+CREATE TABLE liquid_deposits (
+txid TEXT NOT NULL,
+vout INT NOT NULL,
+uid INT NOT NULL,
+amount_sats BIGINT NOT NULL,
+credited_at BIGINT NOT NULL,
+PRIMARY KEY (txid, vout)
+);
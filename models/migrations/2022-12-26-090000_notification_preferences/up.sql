@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE notification_preferences (
+uid integer PRIMARY KEY REFERENCES users(uid),
+email TEXT,
+push_token TEXT,
+push_platform TEXT,
+deposits BOOLEAN NOT NULL DEFAULT TRUE,
+withdrawals BOOLEAN NOT NULL DEFAULT TRUE,
+failed_payments BOOLEAN NOT NULL DEFAULT TRUE,
+security BOOLEAN NOT NULL DEFAULT TRUE,
+updated_at BIGINT NOT NULL
+);
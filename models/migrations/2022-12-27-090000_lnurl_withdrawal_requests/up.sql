@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE lnurl_withdrawal_requests (
+req_id TEXT PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+payment_request TEXT NOT NULL,
+created_at BIGINT NOT NULL,
+expires_at BIGINT NOT NULL
+);
+CREATE INDEX lnurl_withdrawal_requests_expires_at_idx ON lnurl_withdrawal_requests (expires_at);
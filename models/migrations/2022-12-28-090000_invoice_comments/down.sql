@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE invoices DROP COLUMN description_hash;
+ALTER TABLE invoices DROP COLUMN payer_data;
+ALTER TABLE invoices DROP COLUMN comment;
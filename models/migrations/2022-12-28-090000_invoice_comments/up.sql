@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE invoices ADD COLUMN comment TEXT;
+ALTER TABLE invoices ADD COLUMN payer_data TEXT;
+ALTER TABLE invoices ADD COLUMN description_hash TEXT;
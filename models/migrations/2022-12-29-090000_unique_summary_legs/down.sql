@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX summary_transactions_fee_txid_idx;
+DROP INDEX summary_transactions_inbound_txid_idx;
+DROP INDEX summary_transactions_outbound_txid_idx;
+
+ALTER TABLE summary_transactions DISABLE TRIGGER summary_transactions_closed_period;
+
+UPDATE summary_transactions SET outbound_txid = d.leg_txid FROM summary_transaction_detached_legs d
+WHERE d.summary_txid = summary_transactions.txid AND d.leg = 'outbound';
+UPDATE summary_transactions SET inbound_txid = d.leg_txid FROM summary_transaction_detached_legs d
+WHERE d.summary_txid = summary_transactions.txid AND d.leg = 'inbound';
+UPDATE summary_transactions SET fee_txid = d.leg_txid FROM summary_transaction_detached_legs d
+WHERE d.summary_txid = summary_transactions.txid AND d.leg = 'fee';
+
+ALTER TABLE summary_transactions ENABLE TRIGGER summary_transactions_closed_period;
+
+DROP TABLE summary_transaction_detached_legs;
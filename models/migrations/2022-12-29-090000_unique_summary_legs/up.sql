@@ -0,0 +1,47 @@
+-- Your SQL goes here
+-- Txids used to be built from a timestamp and a per-process counter, so summaries written around a restart can
+-- reference the same leg. The earliest summary keeps the reference, the later ones are detached from it and the
+-- detached references are kept here so they can still be audited.
+CREATE TABLE summary_transaction_detached_legs (
+summary_txid TEXT NOT NULL,
+leg TEXT NOT NULL,
+leg_txid TEXT NOT NULL,
+PRIMARY KEY (summary_txid, leg)
+);
+
+INSERT INTO summary_transaction_detached_legs (summary_txid, leg, leg_txid)
+SELECT txid, 'outbound', outbound_txid FROM (
+    SELECT txid, outbound_txid,
+        ROW_NUMBER() OVER (PARTITION BY outbound_txid ORDER BY created_at, txid) AS n
+    FROM summary_transactions WHERE outbound_txid IS NOT NULL
+) legs WHERE n > 1;
+
+INSERT INTO summary_transaction_detached_legs (summary_txid, leg, leg_txid)
+SELECT txid, 'inbound', inbound_txid FROM (
+    SELECT txid, inbound_txid,
+        ROW_NUMBER() OVER (PARTITION BY inbound_txid ORDER BY created_at, txid) AS n
+    FROM summary_transactions WHERE inbound_txid IS NOT NULL
+) legs WHERE n > 1;
+
+INSERT INTO summary_transaction_detached_legs (summary_txid, leg, leg_txid)
+SELECT txid, 'fee', fee_txid FROM (
+    SELECT txid, fee_txid,
+        ROW_NUMBER() OVER (PARTITION BY fee_txid ORDER BY created_at, txid) AS n
+    FROM summary_transactions WHERE fee_txid IS NOT NULL
+) legs WHERE n > 1;
+
+-- Detaching a leg doesn't change any posting, so it is allowed in closed periods.
+ALTER TABLE summary_transactions DISABLE TRIGGER summary_transactions_closed_period;
+
+UPDATE summary_transactions SET outbound_txid = NULL FROM summary_transaction_detached_legs d
+WHERE d.summary_txid = summary_transactions.txid AND d.leg = 'outbound';
+UPDATE summary_transactions SET inbound_txid = NULL FROM summary_transaction_detached_legs d
+WHERE d.summary_txid = summary_transactions.txid AND d.leg = 'inbound';
+UPDATE summary_transactions SET fee_txid = NULL FROM summary_transaction_detached_legs d
+WHERE d.summary_txid = summary_transactions.txid AND d.leg = 'fee';
+
+ALTER TABLE summary_transactions ENABLE TRIGGER summary_transactions_closed_period;
+
+CREATE UNIQUE INDEX summary_transactions_outbound_txid_idx ON summary_transactions (outbound_txid);
+CREATE UNIQUE INDEX summary_transactions_inbound_txid_idx ON summary_transactions (inbound_txid);
+CREATE UNIQUE INDEX summary_transactions_fee_txid_idx ON summary_transactions (fee_txid);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE hedge_targets (
+uid integer PRIMARY KEY REFERENCES users(uid),
+currency TEXT NOT NULL,
+target_ratio decimal NOT NULL,
+updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE vault_withdrawals (
+req_id TEXT PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+account_id uuid NOT NULL,
+amount decimal,
+currency TEXT NOT NULL,
+payment_request TEXT NOT NULL,
+status TEXT NOT NULL,
+created_at BIGINT NOT NULL,
+release_at BIGINT NOT NULL,
+decided_at BIGINT
+);
+CREATE INDEX vault_withdrawals_status_release_at_idx ON vault_withdrawals (status, release_at);
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE api_keys (
+tid SERIAL PRIMARY KEY,
+uid integer NOT NULL REFERENCES users(uid),
+label TEXT NOT NULL,
+kind TEXT NOT NULL,
+roles TEXT NOT NULL,
+daily_budget_sats BIGINT,
+created_at BIGINT NOT NULL,
+revoked_at BIGINT
+);
+CREATE INDEX api_keys_uid_idx ON api_keys (uid);
+CREATE TABLE api_key_spends (
+tid integer NOT NULL REFERENCES api_keys(tid),
+req_id TEXT NOT NULL,
+amount_sats BIGINT NOT NULL,
+created_at BIGINT NOT NULL,
+PRIMARY KEY (tid, req_id)
+);
+CREATE INDEX api_key_spends_tid_created_at_idx ON api_key_spends (tid, created_at);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE pending_withdrawals DROP COLUMN amount_msat;
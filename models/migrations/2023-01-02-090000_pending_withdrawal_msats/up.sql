@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE pending_withdrawals ADD COLUMN amount_msat BIGINT;
+UPDATE pending_withdrawals SET amount_msat = amount_sats * 1000;
+ALTER TABLE pending_withdrawals ALTER COLUMN amount_msat SET NOT NULL;
@@ -0,0 +1,58 @@
+use crate::schema::access_policies;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Restricts money-moving operations of a user to the listed ip ranges and countries.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[primary_key(uid)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct AccessPolicy {
+    pub uid: i32,
+    /// Comma separated list of ip ranges in CIDR notation.
+    pub allowed_ip_ranges: Option<String>,
+    /// Comma separated list of ISO 3166-1 alpha-2 country codes.
+    pub allowed_countries: Option<String>,
+    pub updated_at: i64,
+}
+
+fn split_list(list: &Option<String>) -> Vec<String> {
+    list.as_ref()
+        .map(|list| {
+            list.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl AccessPolicy {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        access_policies::dsl::access_policies
+            .filter(access_policies::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(access_policies::table)
+            .values(self)
+            .on_conflict(access_policies::uid)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(access_policies::dsl::access_policies.filter(access_policies::uid.eq(uid))).execute(conn)
+    }
+
+    pub fn ip_ranges(&self) -> Vec<String> {
+        split_list(&self.allowed_ip_ranges)
+    }
+
+    pub fn countries(&self) -> Vec<String> {
+        split_list(&self.allowed_countries)
+    }
+}
@@ -0,0 +1,88 @@
+use crate::schema::account_members;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A user other than the owner with access to an account. Invited members have to accept first.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+pub struct AccountMember {
+    pub id: i32,
+    pub account_id: Uuid,
+    pub uid: i32,
+    pub role: String,
+    /// Max amount per payment in the currency of the account, only applies to spenders.
+    pub spend_limit: Option<BigDecimal>,
+    pub invited_by: i32,
+    pub accepted: bool,
+    pub created_at: i64,
+}
+
+impl AccountMember {
+    pub fn get(conn: &diesel::PgConnection, account_id: Uuid, uid: i32) -> Result<Self, DieselError> {
+        account_members::dsl::account_members
+            .filter(account_members::account_id.eq(account_id))
+            .filter(account_members::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_by_account_id(conn: &diesel::PgConnection, account_id: Uuid) -> Result<Vec<Self>, DieselError> {
+        account_members::dsl::account_members
+            .filter(account_members::account_id.eq(account_id))
+            .order(account_members::id.asc())
+            .load::<Self>(conn)
+    }
+
+    /// Memberships of the user, including invitations that weren't accepted yet.
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        account_members::dsl::account_members
+            .filter(account_members::uid.eq(uid))
+            .order(account_members::id.asc())
+            .load::<Self>(conn)
+    }
+
+    pub fn accept(conn: &diesel::PgConnection, account_id: Uuid, uid: i32) -> Result<usize, DieselError> {
+        diesel::update(
+            account_members::dsl::account_members.filter(
+                account_members::account_id
+                    .eq(account_id)
+                    .and(account_members::uid.eq(uid)),
+            ),
+        )
+        .set(account_members::accepted.eq(true))
+        .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, account_id: Uuid, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(
+            account_members::dsl::account_members.filter(
+                account_members::account_id
+                    .eq(account_id)
+                    .and(account_members::uid.eq(uid)),
+            ),
+        )
+        .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "account_members"]
+pub struct InsertableAccountMember {
+    pub account_id: Uuid,
+    pub uid: i32,
+    pub role: String,
+    pub spend_limit: Option<BigDecimal>,
+    pub invited_by: i32,
+    pub accepted: bool,
+    pub created_at: i64,
+}
+
+impl InsertableAccountMember {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<AccountMember, DieselError> {
+        diesel::insert_into(account_members::table)
+            .values(self)
+            .get_result(conn)
+    }
+}
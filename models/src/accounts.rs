@@ -17,6 +17,10 @@ pub struct Account {
     pub uid: i32,
     pub created_at: i64, 
     pub account_class: String,
+    /// Name the user gave the account, e.g. savings.
+    pub label: Option<String>,
+    /// Archived accounts keep their history but no longer receive funds.
+    pub archived: bool,
 }
 
 impl Default for Account {
@@ -29,6 +33,8 @@ impl Default for Account {
             account_class: String::from("Cash"),
             uid: 0,
             created_at: 0,
+            label: None,
+            archived: false,
         }
     }
 }
@@ -42,6 +48,7 @@ pub struct InsertableAccount {
     pub account_type: String,
     pub uid: i32,
     pub account_class: String,
+    pub label: Option<String>,
 }
 
 #[derive(Default, AsChangeset, Debug, Deserialize)]
@@ -53,6 +60,8 @@ pub struct UpdateAccount {
     pub account_type: Option<String>,
     pub uid: Option<i32>,
     pub account_class: Option<String>,
+    pub label: Option<String>,
+    pub archived: Option<bool>,
 }
 
 impl Account {
@@ -62,6 +71,14 @@ impl Account {
             .first::<Self>(conn)
     }
 
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        accounts::dsl::accounts.load::<Self>(conn)
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        accounts::dsl::accounts.filter(accounts::uid.eq(uid)).load::<Self>(conn)
+    }
+
     /// TODO: TECH DEBT.
     pub fn get_all_not_in(conn: &diesel::PgConnection, account_ids: &[Uuid]) -> Result<Vec<Self>, DieselError> {
         accounts::dsl::accounts
@@ -69,6 +86,14 @@ impl Account {
             .load::<Self>(conn)
     }
 
+    /// Currencies any account is held in.
+    pub fn get_currencies(conn: &diesel::PgConnection) -> Result<Vec<String>, DieselError> {
+        accounts::dsl::accounts
+            .select(accounts::currency)
+            .distinct()
+            .load::<String>(conn)
+    }
+
     pub fn get_non_internal_users_accounts(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
         users::dsl::users
             .inner_join(accounts::dsl::accounts)
@@ -80,6 +105,8 @@ impl Account {
                 accounts::uid,
                 accounts::created_at,
                 accounts::account_class,
+                accounts::label,
+                accounts::archived,
             ))
             .filter(users::is_internal.eq(false))
             .load::<Self>(conn)
@@ -104,6 +131,8 @@ impl Account {
                 accounts::uid,
                 accounts::created_at,
                 accounts::account_class,
+                accounts::label,
+                accounts::archived,
             ))
             .filter(users::uid.eq(uid))
             .filter(users::is_internal.eq(true))
@@ -126,9 +155,25 @@ impl Account {
         Ok(internal_accounts)
     }
 
+    pub fn get_dealer_funding_accounts(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        Self::get_accounts(conn, 52172712, "dealer", "Internal", "Fee")
+    }
+
+    pub fn get_dealer_pnl_accounts(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        Self::get_accounts(conn, 52172712, "dealer", "Internal", "Pnl")
+    }
+
     pub fn get_bank_liabilities(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
         Self::get_accounts(conn, 23193913, "bank", "External", "Cash")
     }
+
+    pub fn get_bank_fee_accounts(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        Self::get_accounts(conn, 23193913, "bank", "Internal", "Fee")
+    }
+
+    pub fn get_bank_expense_accounts(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        Self::get_accounts(conn, 23193913, "bank", "Internal", "Expense")
+    }
 }
 
 impl InsertableAccount {
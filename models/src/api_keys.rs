@@ -0,0 +1,136 @@
+use crate::schema::{api_key_spends, api_keys};
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+pub const KIND_API_KEY: &str = "ApiKey";
+pub const KIND_DEVICE: &str = "Device";
+
+/// An API key or a registered device of a user. Its token carries the `tid`, payments made with it are
+/// limited to the daily budget if one is set.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(tid)]
+pub struct ApiKey {
+    pub tid: i32,
+    pub uid: i32,
+    pub label: String,
+    pub kind: String,
+    /// Comma separated api roles of the token.
+    pub roles: String,
+    pub daily_budget_sats: Option<i64>,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "api_keys"]
+pub struct InsertableApiKey {
+    pub uid: i32,
+    pub label: String,
+    pub kind: String,
+    pub roles: String,
+    pub daily_budget_sats: Option<i64>,
+    pub created_at: i64,
+}
+
+/// Value of a payment made with a key, counted against its budget.
+#[derive(Queryable, Insertable, Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeySpend {
+    pub tid: i32,
+    pub req_id: String,
+    pub amount_sats: i64,
+    pub created_at: i64,
+}
+
+impl ApiKey {
+    pub fn get(conn: &diesel::PgConnection, tid: i32, uid: i32) -> Result<Option<Self>, DieselError> {
+        api_keys::dsl::api_keys
+            .filter(api_keys::tid.eq(tid))
+            .filter(api_keys::uid.eq(uid))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        api_keys::dsl::api_keys
+            .filter(api_keys::uid.eq(uid))
+            .filter(api_keys::revoked_at.is_null())
+            .order(api_keys::tid.asc())
+            .load(conn)
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    pub fn update_daily_budget(
+        conn: &diesel::PgConnection,
+        tid: i32,
+        uid: i32,
+        daily_budget_sats: Option<i64>,
+    ) -> Result<usize, DieselError> {
+        diesel::update(
+            api_keys::dsl::api_keys
+                .filter(api_keys::tid.eq(tid))
+                .filter(api_keys::uid.eq(uid))
+                .filter(api_keys::revoked_at.is_null()),
+        )
+        .set(api_keys::daily_budget_sats.eq(daily_budget_sats))
+        .execute(conn)
+    }
+
+    pub fn revoke(conn: &diesel::PgConnection, tid: i32, uid: i32, now: i64) -> Result<usize, DieselError> {
+        diesel::update(
+            api_keys::dsl::api_keys
+                .filter(api_keys::tid.eq(tid))
+                .filter(api_keys::uid.eq(uid))
+                .filter(api_keys::revoked_at.is_null()),
+        )
+        .set(api_keys::revoked_at.eq(now))
+        .execute(conn)
+    }
+}
+
+impl InsertableApiKey {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<i32, DieselError> {
+        diesel::insert_into(api_keys::table)
+            .values(self)
+            .returning(api_keys::tid)
+            .get_result(conn)
+    }
+}
+
+impl ApiKeySpend {
+    /// Records the spend once per request, returns 0 if it was recorded already.
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(api_key_spends::table)
+            .values(self)
+            .on_conflict((api_key_spends::tid, api_key_spends::req_id))
+            .do_nothing()
+            .execute(conn)
+    }
+
+    /// Releases the spend of a payment that was refused or failed, returns 0 if none was recorded.
+    pub fn delete(conn: &diesel::PgConnection, req_id: &str) -> Result<usize, DieselError> {
+        diesel::delete(api_key_spends::dsl::api_key_spends.filter(api_key_spends::req_id.eq(req_id))).execute(conn)
+    }
+
+    pub fn exists(conn: &diesel::PgConnection, tid: i32, req_id: &str) -> Result<bool, DieselError> {
+        api_key_spends::dsl::api_key_spends
+            .filter(api_key_spends::tid.eq(tid))
+            .filter(api_key_spends::req_id.eq(req_id))
+            .first::<Self>(conn)
+            .optional()
+            .map(|spend| spend.is_some())
+    }
+
+    pub fn get_spent_since(conn: &diesel::PgConnection, tid: i32, since: i64) -> Result<i64, DieselError> {
+        let values = api_key_spends::dsl::api_key_spends
+            .filter(api_key_spends::tid.eq(tid))
+            .filter(api_key_spends::created_at.ge(since))
+            .select(api_key_spends::amount_sats)
+            .load::<i64>(conn)?;
+        Ok(values.iter().sum())
+    }
+}
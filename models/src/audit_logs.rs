@@ -0,0 +1,67 @@
+use crate::schema::audit_logs;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// One side of a balance-affecting transaction, written together with the transaction.
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct AuditLog {
+    pub audit_id: i32,
+    pub txid: String,
+    /// One of User, Admin, Dealer or Bank.
+    pub actor: String,
+    /// Uid of the user if the actor is a user.
+    pub actor_id: Option<String>,
+    pub operation: String,
+    pub req_id: Option<String>,
+    pub uid: i32,
+    pub account_id: Uuid,
+    pub currency: String,
+    /// Signed change of the balance, negative for outgoing funds.
+    pub amount: BigDecimal,
+    pub balance_before: BigDecimal,
+    pub balance_after: BigDecimal,
+    pub created_at: i64,
+}
+
+impl AuditLog {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        audit_logs::dsl::audit_logs
+            .filter(audit_logs::uid.eq(uid))
+            .order(audit_logs::audit_id.asc())
+            .load::<Self>(conn)
+    }
+
+    pub fn get_by_req_id(conn: &diesel::PgConnection, req_id: String) -> Result<Vec<Self>, DieselError> {
+        audit_logs::dsl::audit_logs
+            .filter(audit_logs::req_id.eq(req_id))
+            .order(audit_logs::audit_id.asc())
+            .load::<Self>(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Clone, Deserialize)]
+#[table_name = "audit_logs"]
+pub struct InsertableAuditLog {
+    pub txid: String,
+    pub actor: String,
+    pub actor_id: Option<String>,
+    pub operation: String,
+    pub req_id: Option<String>,
+    pub uid: i32,
+    pub account_id: Uuid,
+    pub currency: String,
+    pub amount: BigDecimal,
+    pub balance_before: BigDecimal,
+    pub balance_after: BigDecimal,
+    pub created_at: i64,
+}
+
+impl InsertableAuditLog {
+    pub fn insert_all(conn: &diesel::PgConnection, entries: &[Self]) -> Result<usize, DieselError> {
+        diesel::insert_into(audit_logs::table).values(entries).execute(conn)
+    }
+}
@@ -0,0 +1,42 @@
+use crate::schema::data_exports;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Archive of the data of a user, held until it is downloaded once or expires.
+#[derive(Queryable, Identifiable, Insertable, Debug, Serialize, Deserialize)]
+#[primary_key(export_id)]
+pub struct DataExport {
+    pub export_id: Uuid,
+    pub uid: i32,
+    /// The archive as json.
+    pub content: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl DataExport {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(data_exports::table).values(self).execute(conn)
+    }
+
+    /// Removes the export and returns it unless it expired, so it can only be downloaded once.
+    pub fn take(conn: &diesel::PgConnection, export_id: Uuid, now: i64) -> Result<Option<Self>, DieselError> {
+        diesel::delete(
+            data_exports::dsl::data_exports.filter(
+                data_exports::export_id
+                    .eq(export_id)
+                    .and(data_exports::expires_at.gt(now)),
+            ),
+        )
+        .get_result(conn)
+        .optional()
+    }
+
+    /// Removes the exports that expired before `timestamp` without being downloaded. `timestamp` is in millis.
+    pub fn delete_expired(conn: &diesel::PgConnection, timestamp: i64) -> Result<usize, DieselError> {
+        diesel::delete(data_exports::dsl::data_exports.filter(data_exports::expires_at.le(timestamp))).execute(conn)
+    }
+}
@@ -0,0 +1,58 @@
+use crate::schema::dead_letters;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Serialize;
+
+/// A message a service couldn't decode or had no handler for, kept so it can be inspected and replayed.
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub id: i32,
+    /// Service the message was received from, e.g. api, dealer or cli.
+    pub source: String,
+    pub reason: String,
+    /// The frame as it was received.
+    pub payload: Vec<u8>,
+    pub created_at: i64,
+    pub replayed_at: Option<i64>,
+}
+
+impl DeadLetter {
+    pub fn get_by_id(conn: &diesel::PgConnection, id: i32) -> Result<Self, DieselError> {
+        dead_letters::dsl::dead_letters.find(id).first::<Self>(conn)
+    }
+
+    /// Most recent dead letters first.
+    pub fn get_recent(
+        conn: &diesel::PgConnection,
+        limit: i64,
+        include_replayed: bool,
+    ) -> Result<Vec<Self>, DieselError> {
+        let mut query = dead_letters::dsl::dead_letters.into_boxed();
+        if !include_replayed {
+            query = query.filter(dead_letters::replayed_at.is_null());
+        }
+        query.order(dead_letters::id.desc()).limit(limit).load::<Self>(conn)
+    }
+
+    pub fn mark_replayed(conn: &diesel::PgConnection, id: i32, replayed_at: i64) -> Result<usize, DieselError> {
+        diesel::update(dead_letters::dsl::dead_letters.find(id))
+            .set(dead_letters::replayed_at.eq(replayed_at))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "dead_letters"]
+pub struct InsertableDeadLetter {
+    pub source: String,
+    pub reason: String,
+    pub payload: Vec<u8>,
+    pub created_at: i64,
+}
+
+impl InsertableDeadLetter {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(dead_letters::table).values(self).execute(conn)
+    }
+}
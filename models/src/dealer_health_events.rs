@@ -0,0 +1,49 @@
+use crate::schema::dealer_health_events;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// A change of the dealer's ability to serve a currency.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+pub struct DealerHealthEvent {
+    pub id: i32,
+    pub currency: String,
+    pub available: bool,
+    pub created_at: i64,
+}
+
+impl DealerHealthEvent {
+    /// Latest transition of the currency before `before`, i.e. its state at that time.
+    pub fn get_latest_before(conn: &diesel::PgConnection, currency: String, before: i64) -> Result<Self, DieselError> {
+        dealer_health_events::dsl::dealer_health_events
+            .filter(dealer_health_events::currency.eq(currency))
+            .filter(dealer_health_events::created_at.lt(before))
+            .order(dealer_health_events::created_at.desc())
+            .first::<Self>(conn)
+    }
+
+    pub fn get_since(conn: &diesel::PgConnection, currency: String, from: i64) -> Result<Vec<Self>, DieselError> {
+        dealer_health_events::dsl::dealer_health_events
+            .filter(dealer_health_events::currency.eq(currency))
+            .filter(dealer_health_events::created_at.ge(from))
+            .order(dealer_health_events::created_at.asc())
+            .load::<Self>(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "dealer_health_events"]
+pub struct InsertableDealerHealthEvent {
+    pub currency: String,
+    pub available: bool,
+    pub created_at: i64,
+}
+
+impl InsertableDealerHealthEvent {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(dealer_health_events::table)
+            .values(self)
+            .execute(conn)
+    }
+}
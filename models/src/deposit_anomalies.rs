@@ -0,0 +1,43 @@
+use crate::schema::deposit_anomalies;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Serialize;
+
+/// A deposit the node didn't confirm as announced by the invoice subscription, so it wasn't credited.
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct DepositAnomaly {
+    pub id: i32,
+    pub payment_request: String,
+    pub reason: String,
+    pub expected_msat: i64,
+    /// Amount the node reports as paid, not set if the invoice couldn't be looked up.
+    pub paid_msat: Option<i64>,
+    pub created_at: i64,
+}
+
+impl DepositAnomaly {
+    /// Most recent anomalies first.
+    pub fn get_recent(conn: &diesel::PgConnection, limit: i64) -> Result<Vec<Self>, DieselError> {
+        deposit_anomalies::dsl::deposit_anomalies
+            .order(deposit_anomalies::id.desc())
+            .limit(limit)
+            .load::<Self>(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "deposit_anomalies"]
+pub struct InsertableDepositAnomaly {
+    pub payment_request: String,
+    pub reason: String,
+    pub expected_msat: i64,
+    pub paid_msat: Option<i64>,
+    pub created_at: i64,
+}
+
+impl InsertableDepositAnomaly {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(deposit_anomalies::table).values(self).execute(conn)
+    }
+}
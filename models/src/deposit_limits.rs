@@ -0,0 +1,40 @@
+use crate::schema::deposit_limits;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Per-user override of the deposit limit of a currency, the largest balance deposits may bring an account to.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(uid, currency)]
+pub struct DepositLimit {
+    pub uid: i32,
+    pub currency: String,
+    pub max_balance: BigDecimal,
+    pub updated_at: i64,
+}
+
+impl DepositLimit {
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        deposit_limits::dsl::deposit_limits.load::<Self>(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(deposit_limits::table)
+            .values(self)
+            .on_conflict((deposit_limits::uid, deposit_limits::currency))
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, uid: i32, currency: &str) -> Result<usize, DieselError> {
+        diesel::delete(
+            deposit_limits::dsl::deposit_limits
+                .filter(deposit_limits::uid.eq(uid))
+                .filter(deposit_limits::currency.eq(currency)),
+        )
+        .execute(conn)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::schema::deposit_routing_rules;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Credits deposits of invoices matching the rule to one of the user's accounts.
+#[derive(Queryable, Identifiable, Debug, Serialize, Deserialize)]
+#[primary_key(rule_id)]
+pub struct DepositRoutingRule {
+    pub rule_id: i32,
+    pub uid: i32,
+    pub account_id: Uuid,
+    /// Matches invoices whose memo contains the pattern, ignoring case.
+    pub memo_pattern: Option<String>,
+    /// Matches invoices carrying this metadata field.
+    pub metadata_key: Option<String>,
+    /// Rules with a lower priority are applied first.
+    pub priority: i32,
+    pub created_at: i64,
+}
+
+impl DepositRoutingRule {
+    /// Returns the rules of the user in the order they are applied.
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        deposit_routing_rules::dsl::deposit_routing_rules
+            .filter(deposit_routing_rules::uid.eq(uid))
+            .order((
+                deposit_routing_rules::priority.asc(),
+                deposit_routing_rules::rule_id.asc(),
+            ))
+            .load::<Self>(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, rule_id: i32, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(
+            deposit_routing_rules::dsl::deposit_routing_rules.filter(
+                deposit_routing_rules::rule_id
+                    .eq(rule_id)
+                    .and(deposit_routing_rules::uid.eq(uid)),
+            ),
+        )
+        .execute(conn)
+    }
+
+    /// A rule matches if all of its conditions hold, a rule without conditions never matches.
+    pub fn matches(&self, memo: &str, metadata_fields: &HashMap<String, String>) -> bool {
+        if self.memo_pattern.is_none() && self.metadata_key.is_none() {
+            return false;
+        }
+        let memo_matches = self
+            .memo_pattern
+            .as_ref()
+            .map_or(true, |pattern| memo.to_lowercase().contains(&pattern.to_lowercase()));
+        let metadata_matches = self
+            .metadata_key
+            .as_ref()
+            .map_or(true, |key| metadata_fields.contains_key(key));
+        memo_matches && metadata_matches
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "deposit_routing_rules"]
+pub struct InsertableDepositRoutingRule {
+    pub uid: i32,
+    pub account_id: Uuid,
+    pub memo_pattern: Option<String>,
+    pub metadata_key: Option<String>,
+    pub priority: i32,
+    pub created_at: i64,
+}
+
+impl InsertableDepositRoutingRule {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<DepositRoutingRule, DieselError> {
+        diesel::insert_into(deposit_routing_rules::table)
+            .values(self)
+            .get_result(conn)
+    }
+}
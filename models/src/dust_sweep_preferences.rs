@@ -0,0 +1,39 @@
+use crate::schema::dust_sweep_preferences;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Opt-in of a user to have tiny fiat balances swapped back to BTC.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[primary_key(uid)]
+pub struct DustSweepPreference {
+    pub uid: i32,
+    pub enabled: bool,
+    pub updated_at: i64,
+}
+
+impl DustSweepPreference {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        dust_sweep_preferences::dsl::dust_sweep_preferences
+            .filter(dust_sweep_preferences::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    /// Returns the users that opted in.
+    pub fn get_enabled_uids(conn: &diesel::PgConnection) -> Result<Vec<i32>, DieselError> {
+        dust_sweep_preferences::dsl::dust_sweep_preferences
+            .filter(dust_sweep_preferences::enabled.eq(true))
+            .select(dust_sweep_preferences::uid)
+            .load::<i32>(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(dust_sweep_preferences::table)
+            .values(self)
+            .on_conflict(dust_sweep_preferences::uid)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+}
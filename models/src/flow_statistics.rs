@@ -0,0 +1,101 @@
+use crate::schema::flow_statistics;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Anonymized volume of one kind of flow in one market over a UTC day.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+#[table_name = "flow_statistics"]
+pub struct FlowStatistic {
+    pub id: i32,
+    /// Start of the day in millis.
+    pub day: i64,
+    /// Deposit, withdrawal or swap.
+    pub flow: String,
+    /// Currency of deposits and withdrawals, pair of swaps, e.g. `BTC/USD`.
+    pub market: String,
+    pub volume: BigDecimal,
+    pub fees: BigDecimal,
+    pub tx_count: i32,
+    pub created_at: i64,
+}
+
+impl FlowStatistic {
+    /// Returns the statistics of the days starting in `[from, to)`.
+    pub fn get_between(conn: &diesel::PgConnection, from: i64, to: i64) -> Result<Vec<Self>, DieselError> {
+        flow_statistics::dsl::flow_statistics
+            .filter(flow_statistics::day.ge(from).and(flow_statistics::day.lt(to)))
+            .order((
+                flow_statistics::day.asc(),
+                flow_statistics::flow.asc(),
+                flow_statistics::market.asc(),
+            ))
+            .load::<Self>(conn)
+    }
+
+    /// Start of the last aggregated day.
+    pub fn get_last_day(conn: &diesel::PgConnection) -> Result<Option<i64>, DieselError> {
+        flow_statistics::dsl::flow_statistics
+            .select(diesel::dsl::max(flow_statistics::day))
+            .first::<Option<i64>>(conn)
+    }
+
+    /// Replaces the statistics of a day so a day can be aggregated again.
+    pub fn replace_day(
+        conn: &diesel::PgConnection,
+        day: i64,
+        statistics: &[InsertableFlowStatistic],
+    ) -> Result<usize, DieselError> {
+        conn.transaction(|| {
+            diesel::delete(flow_statistics::dsl::flow_statistics.filter(flow_statistics::day.eq(day))).execute(conn)?;
+            diesel::insert_into(flow_statistics::table)
+                .values(statistics)
+                .execute(conn)
+        })
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "flow_statistics"]
+pub struct InsertableFlowStatistic {
+    pub day: i64,
+    pub flow: String,
+    pub market: String,
+    pub volume: BigDecimal,
+    pub fees: BigDecimal,
+    pub tx_count: i32,
+    pub created_at: i64,
+}
+
+/// Statistic as it is published, with the average fee per transaction instead of the sum of the fees.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicFlowStatistic {
+    pub day: i64,
+    pub flow: String,
+    pub market: String,
+    pub volume: Decimal,
+    pub tx_count: i32,
+    pub average_fee: Decimal,
+}
+
+impl From<&FlowStatistic> for PublicFlowStatistic {
+    fn from(statistic: &FlowStatistic) -> Self {
+        let to_decimal = |value: &BigDecimal| Decimal::from_str(&value.to_string()).unwrap_or(Decimal::ZERO);
+        let average_fee = if statistic.tx_count > 0 {
+            to_decimal(&statistic.fees) / Decimal::from(statistic.tx_count)
+        } else {
+            Decimal::ZERO
+        };
+        Self {
+            day: statistic.day,
+            flow: statistic.flow.clone(),
+            market: statistic.market.clone(),
+            volume: to_decimal(&statistic.volume),
+            tx_count: statistic.tx_count,
+            average_fee: average_fee.round_dp(8),
+        }
+    }
+}
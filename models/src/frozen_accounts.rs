@@ -0,0 +1,134 @@
+use crate::schema::{account_freeze_audit_logs, frozen_accounts};
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const FREEZE: &str = "Freeze";
+pub const UNFREEZE: &str = "Unfreeze";
+
+/// An account held by an admin, e.g. for compliance. Withdrawals and swaps from it are refused until it is unfrozen.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(account_id)]
+pub struct FrozenAccount {
+    pub account_id: Uuid,
+    pub uid: i32,
+    pub reason_code: String,
+    pub note: Option<String>,
+    pub frozen_by: String,
+    pub frozen_at: i64,
+}
+
+impl FrozenAccount {
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        frozen_accounts::dsl::frozen_accounts
+            .order(frozen_accounts::frozen_at.asc())
+            .load::<Self>(conn)
+    }
+
+    /// Freezes the account or replaces the reason of an existing freeze, the change is written to the audit log.
+    pub fn freeze(&self, conn: &diesel::PgConnection) -> Result<(), DieselError> {
+        conn.transaction(|| {
+            diesel::insert_into(frozen_accounts::table)
+                .values(self)
+                .on_conflict(frozen_accounts::account_id)
+                .do_update()
+                .set(self)
+                .execute(conn)?;
+
+            InsertableAccountFreezeAuditLog {
+                account_id: self.account_id,
+                uid: self.uid,
+                action: FREEZE.to_string(),
+                reason_code: Some(self.reason_code.clone()),
+                note: self.note.clone(),
+                operator: self.frozen_by.clone(),
+                created_at: self.frozen_at,
+            }
+            .insert(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Lifts the freeze of an account, returns the number of removed freezes.
+    /// Nothing is written to the audit log if the account wasn't frozen.
+    pub fn unfreeze(
+        conn: &diesel::PgConnection,
+        account_id: Uuid,
+        operator: String,
+        note: Option<String>,
+        now: i64,
+    ) -> Result<usize, DieselError> {
+        conn.transaction(|| {
+            let frozen = match frozen_accounts::dsl::frozen_accounts
+                .filter(frozen_accounts::account_id.eq(account_id))
+                .first::<Self>(conn)
+                .optional()?
+            {
+                Some(frozen) => frozen,
+                None => return Ok(0),
+            };
+
+            let deleted = diesel::delete(
+                frozen_accounts::dsl::frozen_accounts.filter(frozen_accounts::account_id.eq(account_id)),
+            )
+            .execute(conn)?;
+
+            InsertableAccountFreezeAuditLog {
+                account_id,
+                uid: frozen.uid,
+                action: UNFREEZE.to_string(),
+                reason_code: Some(frozen.reason_code),
+                note,
+                operator,
+                created_at: now,
+            }
+            .insert(conn)?;
+
+            Ok(deleted)
+        })
+    }
+}
+
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct AccountFreezeAuditLog {
+    pub audit_id: i32,
+    pub account_id: Uuid,
+    pub uid: i32,
+    pub action: String,
+    pub reason_code: Option<String>,
+    pub note: Option<String>,
+    pub operator: String,
+    pub created_at: i64,
+}
+
+impl AccountFreezeAuditLog {
+    pub fn get_by_account_id(conn: &diesel::PgConnection, account_id: Uuid) -> Result<Vec<Self>, DieselError> {
+        account_freeze_audit_logs::dsl::account_freeze_audit_logs
+            .filter(account_freeze_audit_logs::account_id.eq(account_id))
+            .order(account_freeze_audit_logs::created_at.asc())
+            .load::<Self>(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "account_freeze_audit_logs"]
+pub struct InsertableAccountFreezeAuditLog {
+    pub account_id: Uuid,
+    pub uid: i32,
+    pub action: String,
+    pub reason_code: Option<String>,
+    pub note: Option<String>,
+    pub operator: String,
+    pub created_at: i64,
+}
+
+impl InsertableAccountFreezeAuditLog {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(account_freeze_audit_logs::table)
+            .values(self)
+            .execute(conn)
+    }
+}
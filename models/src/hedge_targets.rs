@@ -0,0 +1,42 @@
+use crate::schema::hedge_targets;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Share of a user's balance to keep in a fiat synthetic, the rest is kept in BTC.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(uid)]
+pub struct HedgeTarget {
+    pub uid: i32,
+    pub currency: String,
+    /// Between 0 and 1, e.g. 0.7 to keep 70% of the value in the currency.
+    pub target_ratio: BigDecimal,
+    pub updated_at: i64,
+}
+
+impl HedgeTarget {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        hedge_targets::dsl::hedge_targets
+            .filter(hedge_targets::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        hedge_targets::dsl::hedge_targets.load::<Self>(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(hedge_targets::table)
+            .values(self)
+            .on_conflict(hedge_targets::uid)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(hedge_targets::dsl::hedge_targets.filter(hedge_targets::uid.eq(uid))).execute(conn)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::schema::idempotency_keys;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Identifiable, Insertable, Debug, Serialize, Deserialize)]
+#[primary_key(uid, idempotency_key)]
+pub struct IdempotencyKey {
+    pub uid: i32,
+    pub idempotency_key: String,
+    /// Request id of the first request that used the key.
+    pub req_id: String,
+    /// Serialized response to the first request. Not set while the request is in flight.
+    pub response: Option<String>,
+    pub created_at: i64,
+}
+
+impl IdempotencyKey {
+    pub fn get(conn: &diesel::PgConnection, uid: i32, idempotency_key: String) -> Result<Self, DieselError> {
+        idempotency_keys::dsl::idempotency_keys
+            .filter(
+                idempotency_keys::uid
+                    .eq(uid)
+                    .and(idempotency_keys::idempotency_key.eq(idempotency_key)),
+            )
+            .first::<Self>(conn)
+    }
+
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(idempotency_keys::table).values(self).execute(conn)
+    }
+
+    pub fn set_response(
+        conn: &diesel::PgConnection,
+        uid: i32,
+        idempotency_key: String,
+        response: String,
+    ) -> Result<usize, DieselError> {
+        diesel::update(
+            idempotency_keys::dsl::idempotency_keys.filter(
+                idempotency_keys::uid
+                    .eq(uid)
+                    .and(idempotency_keys::idempotency_key.eq(idempotency_key)),
+            ),
+        )
+        .set(idempotency_keys::response.eq(Some(response)))
+        .execute(conn)
+    }
+
+    /// Removes all keys created before `timestamp` so they can be reused. `timestamp` is in millis.
+    pub fn delete_older_than(conn: &diesel::PgConnection, timestamp: i64) -> Result<usize, DieselError> {
+        diesel::delete(idempotency_keys::dsl::idempotency_keys.filter(idempotency_keys::created_at.lt(timestamp)))
+            .execute(conn)
+    }
+}
@@ -0,0 +1,81 @@
+use crate::schema::interest_accruals;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Interest accrued on one account over a UTC day.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+pub struct InterestAccrual {
+    pub id: i32,
+    pub account_id: Uuid,
+    pub uid: i32,
+    /// Start of the day in millis.
+    pub day: i64,
+    pub currency: String,
+    /// Balance the interest was accrued on.
+    pub balance: BigDecimal,
+    pub apy: BigDecimal,
+    pub amount: BigDecimal,
+    /// Transaction the interest was paid with, not set if the run was interrupted before paying it.
+    pub txid: Option<String>,
+    pub created_at: i64,
+}
+
+impl InterestAccrual {
+    /// Returns the accruals of the user on the days starting in `[from, to)`.
+    pub fn get_by_uid_between(
+        conn: &diesel::PgConnection,
+        uid: i32,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Self>, DieselError> {
+        interest_accruals::dsl::interest_accruals
+            .filter(interest_accruals::uid.eq(uid))
+            .filter(interest_accruals::day.ge(from).and(interest_accruals::day.lt(to)))
+            .order((interest_accruals::day.asc(), interest_accruals::id.asc()))
+            .load::<Self>(conn)
+    }
+
+    /// Start of the last day interest was accrued for.
+    pub fn get_last_day(conn: &diesel::PgConnection) -> Result<Option<i64>, DieselError> {
+        interest_accruals::dsl::interest_accruals
+            .select(diesel::dsl::max(interest_accruals::day))
+            .first::<Option<i64>>(conn)
+    }
+
+    pub fn set_txid(conn: &diesel::PgConnection, id: i32, txid: &str) -> Result<usize, DieselError> {
+        diesel::update(interest_accruals::dsl::interest_accruals.filter(interest_accruals::id.eq(id)))
+            .set(interest_accruals::txid.eq(txid))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "interest_accruals"]
+pub struct InsertableInterestAccrual {
+    pub account_id: Uuid,
+    pub uid: i32,
+    pub day: i64,
+    pub currency: String,
+    pub balance: BigDecimal,
+    pub apy: BigDecimal,
+    pub amount: BigDecimal,
+    pub created_at: i64,
+}
+
+impl InsertableInterestAccrual {
+    /// Claims the accrual of the account for the day. Returns None if it was already accrued, which
+    /// makes reruns of a day idempotent.
+    pub fn claim(&self, conn: &diesel::PgConnection) -> Result<Option<i32>, DieselError> {
+        diesel::insert_into(interest_accruals::table)
+            .values(self)
+            .on_conflict((interest_accruals::account_id, interest_accruals::day))
+            .do_nothing()
+            .returning(interest_accruals::id)
+            .get_result(conn)
+            .optional()
+    }
+}
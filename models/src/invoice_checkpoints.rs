@@ -0,0 +1,44 @@
+use crate::schema::invoice_checkpoints;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Only row of the table.
+const CHECKPOINT_ID: i32 = 1;
+
+/// Indices of the last invoice the bank processed from lnd's invoice subscription, which resumes after it.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+pub struct InvoiceCheckpoint {
+    pub id: i32,
+    pub add_index: i64,
+    pub settle_index: i64,
+    pub updated_at: i64,
+}
+
+impl InvoiceCheckpoint {
+    pub fn new(add_index: i64, settle_index: i64, updated_at: i64) -> Self {
+        Self {
+            id: CHECKPOINT_ID,
+            add_index,
+            settle_index,
+            updated_at,
+        }
+    }
+
+    pub fn get(conn: &diesel::PgConnection) -> Result<Option<Self>, DieselError> {
+        invoice_checkpoints::dsl::invoice_checkpoints
+            .filter(invoice_checkpoints::id.eq(CHECKPOINT_ID))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(invoice_checkpoints::table)
+            .values(self)
+            .on_conflict(invoice_checkpoints::id)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+}
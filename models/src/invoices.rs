@@ -2,6 +2,7 @@ use crate::schema::invoices;
 
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
+use diesel::sql_types::BigInt;
 use serde::{Deserialize, Serialize};
 
 #[derive(Queryable, Insertable, Identifiable, Debug, Serialize, AsChangeset, Deserialize)]
@@ -25,6 +26,22 @@ pub struct Invoice {
     pub currency: Option<String>,
     pub target_account_currency: Option<String>,
     pub reference: Option<String>,
+    pub expired: bool,
+    /// Json object of merchant supplied fields, e.g. an order id.
+    pub metadata_fields: Option<String>,
+    /// Hex encoded sha256 of the reference once the plaintext was purged.
+    pub reference_hash: Option<String>,
+    /// Hex encoded sha256 of the metadata fields once the plaintext was purged.
+    pub metadata_fields_hash: Option<String>,
+    pub descriptions_hashed_at: Option<i64>,
+    /// Smallest units of the Taproot Asset an asset invoice credits, None for invoices paid in sats.
+    pub asset_units: Option<i64>,
+    /// Comment the payer sent along with an LNURL payment (LUD-12).
+    pub comment: Option<String>,
+    /// Json object of the identity the payer disclosed, e.g. name or identifier (LUD-18).
+    pub payer_data: Option<String>,
+    /// Hex encoded hash the invoice commits to instead of a memo, set for LNURL payments.
+    pub description_hash: Option<String>,
 }
 
 impl Invoice {
@@ -51,6 +68,83 @@ impl Invoice {
             .get_result(conn)
     }
 
+    /// Sats the user paid out through outgoing invoices created since `since`, excluding the given invoice
+    /// so a retried payment isn't counted twice.
+    pub fn get_withdrawn_since(
+        conn: &diesel::PgConnection,
+        uid: i32,
+        since: i64,
+        excluded_payment_request: &str,
+    ) -> Result<i64, DieselError> {
+        let values = invoices::dsl::invoices
+            .filter(
+                invoices::uid
+                    .eq(uid)
+                    .and(invoices::incoming.eq(false))
+                    .and(invoices::settled.eq(true))
+                    .and(invoices::created_at.ge(since))
+                    .and(invoices::payment_request.ne(excluded_payment_request)),
+            )
+            .select(invoices::value)
+            .load::<i64>(conn)?;
+        Ok(values.iter().sum())
+    }
+
+    /// Marks the invoice settled. Returns false if it already was, so a deposit redelivered by lnd is only
+    /// credited once.
+    pub fn mark_settled(
+        conn: &diesel::PgConnection,
+        payment_request: &str,
+        settled_date: i64,
+    ) -> Result<bool, DieselError> {
+        diesel::update(
+            invoices::dsl::invoices.filter(
+                invoices::payment_request
+                    .eq(payment_request)
+                    .and(invoices::settled.eq(false)),
+            ),
+        )
+        .set((invoices::settled.eq(true), invoices::settled_date.eq(settled_date)))
+        .execute(conn)
+        .map(|updated| updated > 0)
+    }
+
+    /// Marks all incoming invoices that are unsettled past their expiry as expired
+    /// and returns them. `now` is in millis.
+    pub fn expire_unsettled(conn: &diesel::PgConnection, now: i64) -> Result<Vec<Self>, DieselError> {
+        diesel::update(
+            invoices::dsl::invoices.filter(
+                invoices::incoming
+                    .eq(true)
+                    .and(invoices::settled.eq(false))
+                    .and(invoices::expired.eq(false))
+                    .and((invoices::created_at + invoices::expiry * 1000_i64).lt(now)),
+            ),
+        )
+        .set(invoices::expired.eq(true))
+        .get_results(conn)
+    }
+
+    /// Replaces the references and metadata fields of invoices created before `created_before` with their
+    /// hashes and drops the payer's comment and identity. Incoming invoices are only hashed once they are settled
+    /// or expired.
+    pub fn hash_descriptions(conn: &diesel::PgConnection, created_before: i64, now: i64) -> Result<usize, DieselError> {
+        diesel::sql_query(
+            "UPDATE invoices SET \
+                reference_hash = encode(sha256(convert_to(reference, 'UTF8')), 'hex'), \
+                metadata_fields_hash = encode(sha256(convert_to(metadata_fields, 'UTF8')), 'hex'), \
+                reference = NULL, \
+                metadata_fields = NULL, \
+                comment = NULL, \
+                payer_data = NULL, \
+                descriptions_hashed_at = $1 \
+            WHERE descriptions_hashed_at IS NULL AND created_at < $2 AND (settled OR expired OR NOT incoming)",
+        )
+        .bind::<BigInt, _>(now)
+        .bind::<BigInt, _>(created_before)
+        .execute(conn)
+    }
+
     pub fn update(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
         diesel::update(invoices::dsl::invoices.filter(invoices::account_id.eq(self.account_id.clone())))
             .set(self)
@@ -78,7 +172,9 @@ pub struct InsertableInvoice {
     pub fees: Option<i64>,
     pub currency: Option<String>,
     pub target_account_currency: Option<String>,
-    pub reference: Option<String>
+    pub reference: Option<String>,
+    pub expired: bool,
+    pub metadata_fields: Option<String>,
 }
 
 impl InsertableInvoice {
@@ -3,16 +3,51 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
+pub mod access_policies;
+pub mod account_members;
 pub mod accounts;
+pub mod api_keys;
+pub mod audit_logs;
 pub mod conversions;
+pub mod data_exports;
+pub mod dead_letters;
+pub mod dealer_health_events;
+pub mod deposit_anomalies;
+pub mod deposit_limits;
+pub mod deposit_routing_rules;
+pub mod dust_sweep_preferences;
+pub mod flow_statistics;
 mod error;
+pub mod frozen_accounts;
+pub mod hedge_targets;
+pub mod idempotency_keys;
+pub mod interest_accruals;
 pub mod internal_user_mappings;
+pub mod invoice_checkpoints;
 pub mod invoices;
+pub mod liquid;
+pub mod liquidity_swaps;
+pub mod lnurl_withdrawal_requests;
+pub mod node_info;
+pub mod notification_preferences;
+pub mod operator_revenues;
+pub mod payment_retries;
+pub mod pending_payments;
+pub mod pending_withdrawals;
+pub mod period_closes;
 pub mod pre_signups;
+pub mod rate_limit_buckets;
+pub mod recovery;
+pub mod referrals;
+pub mod settlement_batches;
 mod schema;
 pub mod transactions;
 pub mod summary_transactions;
+pub mod swap_orders;
 pub mod users;
+pub mod vault_withdrawals;
+pub mod webhooks;
+pub mod withdrawal_limits;
 
 cfg_if::cfg_if! {
     if #[cfg(debug_assertions)] {
@@ -33,6 +68,56 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Version of the newest migration of this build, see `build.rs`.
+pub const LATEST_MIGRATION_VERSION: &str = env!("LATEST_MIGRATION_VERSION");
+
+#[derive(QueryableByName)]
+struct SchemaVersion {
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    version: Option<String>,
+}
+
+/// Returns the version of the newest migration applied to the database, `None` if none ran yet.
+pub fn applied_schema_version(conn: &diesel::PgConnection) -> Result<Option<String>, diesel::result::Error> {
+    use diesel::RunQueryDsl;
+
+    diesel::sql_query("SELECT MAX(version) AS version FROM __diesel_schema_migrations")
+        .get_result::<SchemaVersion>(conn)
+        .map(|schema_version| schema_version.version)
+}
+
+/// Connects to the database and compares its schema with the migrations of this build. Returns the outcome of
+/// both checks in the format of a startup report.
+pub fn check_database(psql_url: &str) -> Vec<(&'static str, Result<String, String>)> {
+    use diesel::Connection;
+
+    let conn = match diesel::PgConnection::establish(psql_url) {
+        Ok(conn) => conn,
+        Err(err) => {
+            return vec![(
+                "database",
+                Err(format!(
+                    "can't connect: {}. Check psql_url and that postgres is running",
+                    err
+                )),
+            )]
+        }
+    };
+
+    let schema = match applied_schema_version(&conn) {
+        Ok(Some(applied)) if applied.as_str() >= LATEST_MIGRATION_VERSION => Ok(format!("at migration {}", applied)),
+        Ok(Some(applied)) => Err(format!(
+            "at migration {} but this build needs {}. Start the api or run `diesel migration run` first",
+            applied, LATEST_MIGRATION_VERSION
+        )),
+        Ok(None) => Err(String::from(
+            "no migrations ran yet. Start the api or run `diesel migration run` first",
+        )),
+        Err(err) => Err(format!("can't read the schema version: {}", err)),
+    };
+    vec![("database", Ok(String::from("connected"))), ("schema", schema)]
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
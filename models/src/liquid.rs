@@ -0,0 +1,57 @@
+use crate::schema::{liquid_addresses, liquid_deposits};
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+/// Address of the bank's Liquid wallet handed out to a user. Peg-in addresses are Bitcoin addresses and carry the
+/// claim script the peg-in is claimed with.
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "liquid_addresses"]
+pub struct LiquidAddress {
+    pub address: String,
+    pub uid: i32,
+    pub account_id: String,
+    pub claim_script: Option<String>,
+    pub created_at: i64,
+}
+
+impl LiquidAddress {
+    pub fn get(conn: &diesel::PgConnection, address: &str) -> Result<Self, DieselError> {
+        liquid_addresses::dsl::liquid_addresses
+            .find(address)
+            .first::<Self>(conn)
+    }
+
+    pub fn get_by_claim_script(conn: &diesel::PgConnection, uid: i32, claim_script: &str) -> Result<Self, DieselError> {
+        liquid_addresses::dsl::liquid_addresses
+            .filter(liquid_addresses::uid.eq(uid))
+            .filter(liquid_addresses::claim_script.eq(claim_script))
+            .first::<Self>(conn)
+    }
+
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(liquid_addresses::table).values(self).execute(conn)
+    }
+}
+
+/// An output paid to the bank's Liquid wallet that was credited to a user.
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "liquid_deposits"]
+pub struct LiquidDeposit {
+    pub txid: String,
+    pub vout: i32,
+    pub uid: i32,
+    pub amount_sats: i64,
+    pub credited_at: i64,
+}
+
+impl LiquidDeposit {
+    /// Records the deposit, false if it was recorded before and must not be credited again.
+    pub fn insert_new(&self, conn: &diesel::PgConnection) -> Result<bool, DieselError> {
+        diesel::insert_into(liquid_deposits::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map(|inserted| inserted > 0)
+    }
+}
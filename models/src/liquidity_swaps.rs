@@ -0,0 +1,74 @@
+use crate::schema::liquidity_swaps;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Serialize;
+
+/// A swap between channel and on-chain funds the bank started with a swap provider to manage liquidity.
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct LiquiditySwap {
+    pub id: i32,
+    pub provider: String,
+    /// Id of the swap at the provider.
+    pub swap_id: String,
+    pub direction: String,
+    pub amount_sats: i64,
+    pub state: String,
+    /// What the swap cost in total, set once the provider reports it.
+    pub cost_sats: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl LiquiditySwap {
+    /// Most recent swaps first.
+    pub fn get_recent(conn: &diesel::PgConnection, limit: i64) -> Result<Vec<Self>, DieselError> {
+        liquidity_swaps::dsl::liquidity_swaps
+            .order(liquidity_swaps::id.desc())
+            .limit(limit)
+            .load::<Self>(conn)
+    }
+
+    /// Swaps in one of the given states, oldest first.
+    pub fn get_by_states(conn: &diesel::PgConnection, states: &[&str]) -> Result<Vec<Self>, DieselError> {
+        liquidity_swaps::dsl::liquidity_swaps
+            .filter(liquidity_swaps::state.eq_any(states))
+            .order(liquidity_swaps::id.asc())
+            .load::<Self>(conn)
+    }
+
+    pub fn update_state(
+        conn: &diesel::PgConnection,
+        id: i32,
+        state: &str,
+        cost_sats: Option<i64>,
+        updated_at: i64,
+    ) -> Result<usize, DieselError> {
+        diesel::update(liquidity_swaps::dsl::liquidity_swaps.find(id))
+            .set((
+                liquidity_swaps::state.eq(state),
+                liquidity_swaps::cost_sats.eq(cost_sats),
+                liquidity_swaps::updated_at.eq(updated_at),
+            ))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "liquidity_swaps"]
+pub struct InsertableLiquiditySwap {
+    pub provider: String,
+    pub swap_id: String,
+    pub direction: String,
+    pub amount_sats: i64,
+    pub state: String,
+    pub cost_sats: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl InsertableLiquiditySwap {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(liquidity_swaps::table).values(self).execute(conn)
+    }
+}
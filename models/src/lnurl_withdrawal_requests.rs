@@ -0,0 +1,45 @@
+use crate::schema::lnurl_withdrawal_requests;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// An LNURL withdrawal created by a user whose QR code hasn't been scanned and paid yet.
+#[derive(Queryable, Identifiable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(req_id)]
+pub struct LnurlWithdrawalRequest {
+    pub req_id: String,
+    pub uid: i32,
+    /// Json of the payment request sent once the user's wallet calls back with an invoice.
+    pub payment_request: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl LnurlWithdrawalRequest {
+    /// Requests that expire after `timestamp`, in millis.
+    pub fn get_unexpired(conn: &diesel::PgConnection, timestamp: i64) -> Result<Vec<Self>, DieselError> {
+        lnurl_withdrawal_requests::dsl::lnurl_withdrawal_requests
+            .filter(lnurl_withdrawal_requests::expires_at.gt(timestamp))
+            .load::<Self>(conn)
+    }
+
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(lnurl_withdrawal_requests::table)
+            .values(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, req_id: &str) -> Result<usize, DieselError> {
+        diesel::delete(lnurl_withdrawal_requests::dsl::lnurl_withdrawal_requests.find(req_id)).execute(conn)
+    }
+
+    /// Removes the requests that expired before `timestamp`, in millis.
+    pub fn delete_expired(conn: &diesel::PgConnection, timestamp: i64) -> Result<usize, DieselError> {
+        diesel::delete(
+            lnurl_withdrawal_requests::dsl::lnurl_withdrawal_requests
+                .filter(lnurl_withdrawal_requests::expires_at.le(timestamp)),
+        )
+        .execute(conn)
+    }
+}
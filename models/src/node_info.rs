@@ -0,0 +1,43 @@
+use crate::schema::node_info;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Only row of the table.
+const NODE_INFO_ID: i32 = 1;
+
+/// Latest node info the bank answered with, so it can be served from a read replica.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[table_name = "node_info"]
+pub struct NodeInfo {
+    pub id: i32,
+    /// Serialized `GetNodeInfoResponse`.
+    pub payload: String,
+    pub updated_at: i64,
+}
+
+impl NodeInfo {
+    pub fn new(payload: String, updated_at: i64) -> Self {
+        Self {
+            id: NODE_INFO_ID,
+            payload,
+            updated_at,
+        }
+    }
+
+    pub fn get(conn: &diesel::PgConnection) -> Result<Self, DieselError> {
+        node_info::dsl::node_info
+            .filter(node_info::id.eq(NODE_INFO_ID))
+            .first::<Self>(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(node_info::table)
+            .values(self)
+            .on_conflict(node_info::id)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::schema::notification_preferences;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Where and about which events a user is notified.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(uid)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NotificationPreference {
+    pub uid: i32,
+    /// Address emails are sent to, no emails are sent if null.
+    pub email: Option<String>,
+    /// FCM registration token or APNs device token, no push messages are sent if null.
+    pub push_token: Option<String>,
+    /// Either `fcm` or `apns`.
+    pub push_platform: Option<String>,
+    pub deposits: bool,
+    pub withdrawals: bool,
+    pub failed_payments: bool,
+    pub security: bool,
+    pub updated_at: i64,
+}
+
+impl NotificationPreference {
+    /// Preferences of users that never set any, they aren't notified as long as no address is known.
+    pub fn default_for(uid: i32) -> Self {
+        Self {
+            uid,
+            email: None,
+            push_token: None,
+            push_platform: None,
+            deposits: true,
+            withdrawals: true,
+            failed_payments: true,
+            security: true,
+            updated_at: 0,
+        }
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        notification_preferences::dsl::notification_preferences
+            .filter(notification_preferences::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(notification_preferences::table)
+            .values(self)
+            .on_conflict(notification_preferences::uid)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+}
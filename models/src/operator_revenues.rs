@@ -0,0 +1,62 @@
+use crate::schema::operator_revenues;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Revenue of one source in one currency over a UTC day.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+pub struct OperatorRevenue {
+    pub id: i32,
+    /// Start of the day in millis.
+    pub day: i64,
+    pub source: String,
+    pub currency: String,
+    pub amount: BigDecimal,
+    pub tx_count: i32,
+    pub created_at: i64,
+}
+
+impl OperatorRevenue {
+    /// Returns the revenues of the days starting in `[from, to)`.
+    pub fn get_between(conn: &diesel::PgConnection, from: i64, to: i64) -> Result<Vec<Self>, DieselError> {
+        operator_revenues::dsl::operator_revenues
+            .filter(operator_revenues::day.ge(from).and(operator_revenues::day.lt(to)))
+            .order(operator_revenues::day.asc())
+            .load::<Self>(conn)
+    }
+
+    /// Start of the last aggregated day.
+    pub fn get_last_day(conn: &diesel::PgConnection) -> Result<Option<i64>, DieselError> {
+        operator_revenues::dsl::operator_revenues
+            .select(diesel::dsl::max(operator_revenues::day))
+            .first::<Option<i64>>(conn)
+    }
+
+    /// Replaces the revenues of a day so a day can be aggregated again.
+    pub fn replace_day(
+        conn: &diesel::PgConnection,
+        day: i64,
+        revenues: &[InsertableOperatorRevenue],
+    ) -> Result<usize, DieselError> {
+        conn.transaction(|| {
+            diesel::delete(operator_revenues::dsl::operator_revenues.filter(operator_revenues::day.eq(day)))
+                .execute(conn)?;
+            diesel::insert_into(operator_revenues::table)
+                .values(revenues)
+                .execute(conn)
+        })
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "operator_revenues"]
+pub struct InsertableOperatorRevenue {
+    pub day: i64,
+    pub source: String,
+    pub currency: String,
+    pub amount: BigDecimal,
+    pub tx_count: i32,
+    pub created_at: i64,
+}
@@ -0,0 +1,59 @@
+use crate::schema::payment_retries;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// `next_attempt_at` of a retry whose attempt is being paid, the result of the attempt reschedules or removes it.
+pub const ATTEMPT_IN_FLIGHT: i64 = i64::MAX;
+
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[primary_key(req_id)]
+pub struct PaymentRetry {
+    pub req_id: String,
+    pub uid: i32,
+    pub payment_request: String,
+    /// Serialized result of the last failed attempt.
+    pub payment_result: String,
+    pub attempts: i32,
+    pub next_attempt_at: i64,
+    pub created_at: i64,
+}
+
+impl PaymentRetry {
+    pub fn get_by_req_id(conn: &diesel::PgConnection, req_id: String) -> Result<Self, DieselError> {
+        payment_retries::dsl::payment_retries
+            .filter(payment_retries::req_id.eq(req_id))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_due(conn: &diesel::PgConnection, now: i64) -> Result<Vec<Self>, DieselError> {
+        payment_retries::dsl::payment_retries
+            .filter(payment_retries::next_attempt_at.le(now))
+            .order(payment_retries::next_attempt_at.asc())
+            .load(conn)
+    }
+
+    pub fn get_in_flight(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        payment_retries::dsl::payment_retries
+            .filter(payment_retries::next_attempt_at.eq(ATTEMPT_IN_FLIGHT))
+            .load(conn)
+    }
+
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<String, DieselError> {
+        diesel::insert_into(payment_retries::table)
+            .values(self)
+            .returning(payment_retries::req_id)
+            .get_result(conn)
+    }
+
+    pub fn update(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::update(payment_retries::dsl::payment_retries.filter(payment_retries::req_id.eq(self.req_id.clone())))
+            .set(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, req_id: String) -> Result<usize, DieselError> {
+        diesel::delete(payment_retries::dsl::payment_retries.filter(payment_retries::req_id.eq(req_id))).execute(conn)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::schema::pending_payments;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// An outgoing payment whose funds are debited while lnd is paying it. Written before each attempt is
+/// dispatched and removed once its result is booked or a retry is scheduled, so payments interrupted by a crash
+/// can be recovered.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[primary_key(req_id)]
+pub struct PendingPayment {
+    pub req_id: String,
+    pub uid: i32,
+    pub payment_hash: String,
+    /// Debited from the user in BTC, fee reserve included.
+    pub amount: BigDecimal,
+    pub estimated_fee_sats: BigDecimal,
+    /// Serialized result the payment is booked with once its outcome is known.
+    pub payment_result: String,
+    pub created_at: i64,
+}
+
+impl PendingPayment {
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        pending_payments::dsl::pending_payments
+            .order(pending_payments::created_at.asc())
+            .load(conn)
+    }
+
+    /// Retried payments replace the record of their previous attempt.
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(pending_payments::table)
+            .values(self)
+            .on_conflict(pending_payments::req_id)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, req_id: String) -> Result<usize, DieselError> {
+        diesel::delete(pending_payments::dsl::pending_payments.filter(pending_payments::req_id.eq(req_id)))
+            .execute(conn)
+    }
+}
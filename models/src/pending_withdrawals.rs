@@ -0,0 +1,90 @@
+use crate::schema::pending_withdrawals;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+pub const PENDING: &str = "Pending";
+pub const APPROVED: &str = "Approved";
+pub const REJECTED: &str = "Rejected";
+
+/// A withdrawal above the approval threshold, held until an admin approves or rejects it.
+#[derive(Queryable, Identifiable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(req_id)]
+pub struct PendingWithdrawal {
+    pub req_id: String,
+    pub uid: i32,
+    pub amount_sats: i64,
+    pub currency: String,
+    /// Serialized payment request of the user, processed again once approved.
+    pub payment_request: String,
+    pub status: String,
+    pub decided_by: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: i64,
+    pub decided_at: Option<i64>,
+    /// Set once the payment was released or the user was told about the rejection.
+    pub processed_at: Option<i64>,
+    /// Exact amount of the withdrawal, `amount_sats` is rounded to whole sats for the approval threshold.
+    pub amount_msat: i64,
+}
+
+impl PendingWithdrawal {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(pending_withdrawals::table)
+            .values(self)
+            .execute(conn)
+    }
+
+    pub fn get_by_req_id(conn: &diesel::PgConnection, req_id: String) -> Result<Self, DieselError> {
+        pending_withdrawals::dsl::pending_withdrawals
+            .filter(pending_withdrawals::req_id.eq(req_id))
+            .first::<Self>(conn)
+    }
+
+    /// Withdrawals waiting for a decision, oldest first.
+    pub fn get_pending(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        pending_withdrawals::dsl::pending_withdrawals
+            .filter(pending_withdrawals::status.eq(PENDING))
+            .order(pending_withdrawals::created_at.asc())
+            .load(conn)
+    }
+
+    /// Withdrawals that were approved or rejected but not processed yet.
+    pub fn get_decided_unprocessed(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        pending_withdrawals::dsl::pending_withdrawals
+            .filter(pending_withdrawals::status.ne(PENDING))
+            .filter(pending_withdrawals::processed_at.is_null())
+            .order(pending_withdrawals::decided_at.asc())
+            .load(conn)
+    }
+
+    /// Records the decision if the withdrawal is still pending, returns the number of updated rows.
+    pub fn decide(
+        conn: &diesel::PgConnection,
+        req_id: String,
+        status: &str,
+        decided_by: String,
+        reason: Option<String>,
+        now: i64,
+    ) -> Result<usize, DieselError> {
+        diesel::update(
+            pending_withdrawals::dsl::pending_withdrawals
+                .filter(pending_withdrawals::req_id.eq(req_id))
+                .filter(pending_withdrawals::status.eq(PENDING)),
+        )
+        .set((
+            pending_withdrawals::status.eq(status),
+            pending_withdrawals::decided_by.eq(decided_by),
+            pending_withdrawals::reason.eq(reason),
+            pending_withdrawals::decided_at.eq(now),
+        ))
+        .execute(conn)
+    }
+
+    pub fn mark_processed(conn: &diesel::PgConnection, req_id: String, now: i64) -> Result<usize, DieselError> {
+        diesel::update(pending_withdrawals::dsl::pending_withdrawals.filter(pending_withdrawals::req_id.eq(req_id)))
+            .set(pending_withdrawals::processed_at.eq(now))
+            .execute(conn)
+    }
+}
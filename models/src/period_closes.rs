@@ -0,0 +1,108 @@
+use crate::schema::{period_closes, period_closing_balances};
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Integer};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Locks all transactions created up to `closed_until`, the database rejects changes to them
+/// until the close is reopened. Reopened closes are kept as audit trail.
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodClose {
+    pub id: i32,
+    pub closed_until: i64,
+    pub closed_at: i64,
+    pub closed_by: String,
+    pub reopened_at: Option<i64>,
+    pub reopened_by: Option<String>,
+    pub reopen_reason: Option<String>,
+}
+
+impl PeriodClose {
+    /// Returns the close which wasn't reopened and ends last.
+    pub fn get_latest_active(conn: &diesel::PgConnection) -> Result<Option<Self>, DieselError> {
+        period_closes::dsl::period_closes
+            .filter(period_closes::reopened_at.is_null())
+            .order(period_closes::closed_until.desc())
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        period_closes::dsl::period_closes
+            .order(period_closes::id.asc())
+            .load::<Self>(conn)
+    }
+
+    pub fn reopen(
+        conn: &diesel::PgConnection,
+        id: i32,
+        reopened_at: i64,
+        reopened_by: &str,
+        reopen_reason: &str,
+    ) -> Result<Self, DieselError> {
+        diesel::update(
+            period_closes::dsl::period_closes
+                .filter(period_closes::id.eq(id).and(period_closes::reopened_at.is_null())),
+        )
+        .set((
+            period_closes::reopened_at.eq(reopened_at),
+            period_closes::reopened_by.eq(reopened_by),
+            period_closes::reopen_reason.eq(reopen_reason),
+        ))
+        .get_result(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "period_closes"]
+pub struct InsertablePeriodClose {
+    pub closed_until: i64,
+    pub closed_at: i64,
+    pub closed_by: String,
+}
+
+impl InsertablePeriodClose {
+    /// Closes the period and stores the balance of every account at its end.
+    pub fn close(&self, conn: &diesel::PgConnection) -> Result<(PeriodClose, Vec<ClosingBalance>), DieselError> {
+        conn.transaction(|| {
+            let close: PeriodClose = diesel::insert_into(period_closes::table)
+                .values(self)
+                .get_result(conn)?;
+
+            diesel::sql_query(
+                "INSERT INTO period_closing_balances (close_id, account_id, balance) \
+                SELECT $1, account_id, SUM(amount) FROM ( \
+                    SELECT inbound_account_id AS account_id, inbound_amount AS amount, created_at FROM transactions \
+                    UNION ALL \
+                    SELECT outbound_account_id AS account_id, -outbound_amount AS amount, created_at FROM transactions \
+                ) AS bookings WHERE created_at <= $2 GROUP BY account_id",
+            )
+            .bind::<Integer, _>(close.id)
+            .bind::<BigInt, _>(close.closed_until)
+            .execute(conn)?;
+
+            let balances = ClosingBalance::get_by_close_id(conn, close.id)?;
+            Ok((close, balances))
+        })
+    }
+}
+
+/// Balance of an account at the end of a closed period.
+#[derive(Queryable, Debug, Clone, Serialize, Deserialize)]
+pub struct ClosingBalance {
+    pub close_id: i32,
+    pub account_id: Uuid,
+    pub balance: BigDecimal,
+}
+
+impl ClosingBalance {
+    pub fn get_by_close_id(conn: &diesel::PgConnection, close_id: i32) -> Result<Vec<Self>, DieselError> {
+        period_closing_balances::dsl::period_closing_balances
+            .filter(period_closing_balances::close_id.eq(close_id))
+            .order(period_closing_balances::account_id.asc())
+            .load::<Self>(conn)
+    }
+}
@@ -0,0 +1,61 @@
+use crate::schema::rate_limit_buckets;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Double, Text};
+use serde::{Deserialize, Serialize};
+
+/// Token bucket of a rate limited operation, e.g. the withdrawals of a user.
+#[derive(Queryable, QueryableByName, Identifiable, Insertable, Debug, Serialize, Deserialize)]
+#[primary_key(bucket_key)]
+#[table_name = "rate_limit_buckets"]
+pub struct RateLimitBucket {
+    pub bucket_key: String,
+    pub tokens: f64,
+    pub updated_at: i64,
+}
+
+impl RateLimitBucket {
+    /// Refills the bucket for the time passed since it was last used and takes a token from it in a single
+    /// statement, so concurrent requests can't take the same token. Returns the bucket if a token was taken
+    /// and none if the bucket is empty. New buckets start full.
+    pub fn take(
+        conn: &diesel::PgConnection,
+        bucket_key: &str,
+        capacity: f64,
+        refill_per_milli: f64,
+        now: i64,
+    ) -> Result<Option<Self>, DieselError> {
+        diesel::sql_query(
+            "INSERT INTO rate_limit_buckets (bucket_key, tokens, updated_at) VALUES ($1, $2 - 1, $3) \
+            ON CONFLICT (bucket_key) DO UPDATE SET \
+                tokens = LEAST($2, rate_limit_buckets.tokens + \
+                    GREATEST($3 - rate_limit_buckets.updated_at, 0) * $4) - 1, \
+                updated_at = GREATEST($3, rate_limit_buckets.updated_at) \
+            WHERE LEAST($2, rate_limit_buckets.tokens + \
+                GREATEST($3 - rate_limit_buckets.updated_at, 0) * $4) >= 1 \
+            RETURNING bucket_key, tokens, updated_at",
+        )
+        .bind::<Text, _>(bucket_key)
+        .bind::<Double, _>(capacity)
+        .bind::<BigInt, _>(now)
+        .bind::<Double, _>(refill_per_milli)
+        .get_result::<Self>(conn)
+        .optional()
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, bucket_key: &str) -> Result<usize, DieselError> {
+        diesel::delete(
+            rate_limit_buckets::dsl::rate_limit_buckets.filter(rate_limit_buckets::bucket_key.eq(bucket_key)),
+        )
+        .execute(conn)
+    }
+
+    /// Removes buckets that have been full for a while, they'd start full anyway.
+    pub fn delete_idle(conn: &diesel::PgConnection, updated_before: i64) -> Result<usize, DieselError> {
+        diesel::delete(
+            rate_limit_buckets::dsl::rate_limit_buckets.filter(rate_limit_buckets::updated_at.lt(updated_before)),
+        )
+        .execute(conn)
+    }
+}
@@ -0,0 +1,255 @@
+use crate::schema::{recovery_approvals, recovery_audit_logs, recovery_configs, recovery_guardians, recovery_requests};
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Lifecycle of a recovery request.
+///
+/// `Pending` -> `Approved` once enough guardians approved, `Approved` -> `Completed` once the delay
+/// has passed and the new credentials were applied. Open requests can be `Cancelled` by the owner
+/// and `Pending` requests become `Expired` if they aren't approved in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecoveryState {
+    Pending,
+    Approved,
+    Completed,
+    Cancelled,
+    Expired,
+}
+
+impl RecoveryState {
+    pub fn is_open(&self) -> bool {
+        matches!(self, RecoveryState::Pending | RecoveryState::Approved)
+    }
+}
+
+impl fmt::Display for RecoveryState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for RecoveryState {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Pending" => Ok(RecoveryState::Pending),
+            "Approved" => Ok(RecoveryState::Approved),
+            "Completed" => Ok(RecoveryState::Completed),
+            "Cancelled" => Ok(RecoveryState::Cancelled),
+            "Expired" => Ok(RecoveryState::Expired),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[primary_key(uid)]
+pub struct RecoveryConfig {
+    pub uid: i32,
+    /// Number of guardians that have to approve a recovery.
+    pub threshold: i32,
+    /// Seconds between the final approval and the recovery becoming executable.
+    pub delay_secs: i64,
+    pub updated_at: i64,
+}
+
+impl RecoveryConfig {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        recovery_configs::dsl::recovery_configs
+            .filter(recovery_configs::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    /// Replaces the config and guardians of a user.
+    pub fn replace(&self, conn: &diesel::PgConnection, guardian_uids: &[i32]) -> Result<(), DieselError> {
+        conn.transaction(|| {
+            diesel::insert_into(recovery_configs::table)
+                .values(self)
+                .on_conflict(recovery_configs::uid)
+                .do_update()
+                .set(self)
+                .execute(conn)?;
+
+            diesel::delete(recovery_guardians::dsl::recovery_guardians.filter(recovery_guardians::uid.eq(self.uid)))
+                .execute(conn)?;
+
+            let guardians = guardian_uids
+                .iter()
+                .map(|guardian_uid| RecoveryGuardian {
+                    uid: self.uid,
+                    guardian_uid: *guardian_uid,
+                    created_at: self.updated_at,
+                })
+                .collect::<Vec<_>>();
+
+            diesel::insert_into(recovery_guardians::table)
+                .values(&guardians)
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize)]
+#[table_name = "recovery_guardians"]
+pub struct RecoveryGuardian {
+    pub uid: i32,
+    pub guardian_uid: i32,
+    pub created_at: i64,
+}
+
+impl RecoveryGuardian {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        recovery_guardians::dsl::recovery_guardians
+            .filter(recovery_guardians::uid.eq(uid))
+            .load::<Self>(conn)
+    }
+
+    pub fn is_guardian(conn: &diesel::PgConnection, uid: i32, guardian_uid: i32) -> Result<bool, DieselError> {
+        recovery_guardians::dsl::recovery_guardians
+            .filter(
+                recovery_guardians::uid
+                    .eq(uid)
+                    .and(recovery_guardians::guardian_uid.eq(guardian_uid)),
+            )
+            .first::<Self>(conn)
+            .optional()
+            .map(|guardian| guardian.is_some())
+    }
+}
+
+#[derive(Queryable, Identifiable, AsChangeset, Debug, Serialize)]
+#[primary_key(recovery_id)]
+pub struct RecoveryRequest {
+    pub recovery_id: i32,
+    pub uid: i32,
+    /// Hash of the credentials applied once the recovery completes.
+    #[serde(skip_serializing)]
+    pub password: String,
+    pub state: String,
+    pub approvals_required: i32,
+    pub delay_secs: i64,
+    pub created_at: i64,
+    pub approved_at: Option<i64>,
+    pub closed_at: Option<i64>,
+}
+
+impl RecoveryRequest {
+    pub fn get_by_id(conn: &diesel::PgConnection, recovery_id: i32) -> Result<Self, DieselError> {
+        recovery_requests::dsl::recovery_requests
+            .filter(recovery_requests::recovery_id.eq(recovery_id))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_open_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        recovery_requests::dsl::recovery_requests
+            .filter(recovery_requests::uid.eq(uid))
+            .filter(
+                recovery_requests::state
+                    .eq(RecoveryState::Pending.to_string())
+                    .or(recovery_requests::state.eq(RecoveryState::Approved.to_string())),
+            )
+            .load::<Self>(conn)
+    }
+
+    pub fn recovery_state(&self) -> Option<RecoveryState> {
+        RecoveryState::from_str(&self.state).ok()
+    }
+
+    /// Time in millis from which the recovery can be completed.
+    pub fn executable_at(&self) -> Option<i64> {
+        self.approved_at.map(|approved_at| approved_at + self.delay_secs * 1000)
+    }
+
+    pub fn update(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::update(
+            recovery_requests::dsl::recovery_requests.filter(recovery_requests::recovery_id.eq(self.recovery_id)),
+        )
+        .set(self)
+        .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "recovery_requests"]
+pub struct InsertableRecoveryRequest {
+    pub uid: i32,
+    pub password: String,
+    pub state: String,
+    pub approvals_required: i32,
+    pub delay_secs: i64,
+    pub created_at: i64,
+}
+
+impl InsertableRecoveryRequest {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<RecoveryRequest, DieselError> {
+        diesel::insert_into(recovery_requests::table)
+            .values(self)
+            .get_result(conn)
+    }
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize)]
+#[table_name = "recovery_approvals"]
+pub struct RecoveryApproval {
+    pub recovery_id: i32,
+    pub guardian_uid: i32,
+    pub created_at: i64,
+}
+
+impl RecoveryApproval {
+    pub fn get_by_recovery_id(conn: &diesel::PgConnection, recovery_id: i32) -> Result<Vec<Self>, DieselError> {
+        recovery_approvals::dsl::recovery_approvals
+            .filter(recovery_approvals::recovery_id.eq(recovery_id))
+            .load::<Self>(conn)
+    }
+
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(recovery_approvals::table)
+            .values(self)
+            .execute(conn)
+    }
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct RecoveryAuditLog {
+    pub audit_id: i32,
+    pub uid: i32,
+    pub recovery_id: Option<i32>,
+    pub actor_uid: Option<i32>,
+    pub action: String,
+    pub created_at: i64,
+}
+
+impl RecoveryAuditLog {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        recovery_audit_logs::dsl::recovery_audit_logs
+            .filter(recovery_audit_logs::uid.eq(uid))
+            .order(recovery_audit_logs::created_at.asc())
+            .load::<Self>(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "recovery_audit_logs"]
+pub struct InsertableRecoveryAuditLog {
+    pub uid: i32,
+    pub recovery_id: Option<i32>,
+    pub actor_uid: Option<i32>,
+    pub action: String,
+    pub created_at: i64,
+}
+
+impl InsertableRecoveryAuditLog {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(recovery_audit_logs::table)
+            .values(self)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,44 @@
+use crate::schema::{referrals, users};
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Links a user to the user who referred them, the referrer receives a share of the referee's fees.
+#[derive(Queryable, Identifiable, Insertable, Debug, Serialize, Deserialize)]
+#[primary_key(referee_uid)]
+pub struct Referral {
+    pub referee_uid: i32,
+    pub referrer_uid: i32,
+    pub created_at: i64,
+}
+
+/// A user referred by the referrer, as shown to the referrer.
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+pub struct Referee {
+    pub uid: i32,
+    pub username: String,
+    pub created_at: i64,
+}
+
+impl Referral {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(referrals::table).values(self).execute(conn)
+    }
+
+    pub fn get_by_referee(conn: &diesel::PgConnection, referee_uid: i32) -> Result<Option<Self>, DieselError> {
+        referrals::dsl::referrals
+            .filter(referrals::referee_uid.eq(referee_uid))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn get_referees(conn: &diesel::PgConnection, referrer_uid: i32) -> Result<Vec<Referee>, DieselError> {
+        referrals::dsl::referrals
+            .inner_join(users::dsl::users)
+            .select((referrals::referee_uid, users::username, referrals::created_at))
+            .filter(referrals::referrer_uid.eq(referrer_uid))
+            .order(referrals::created_at.asc())
+            .load::<Referee>(conn)
+    }
+}
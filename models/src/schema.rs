@@ -1,5 +1,40 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    access_policies (uid) {
+        uid -> Int4,
+        allowed_ip_ranges -> Nullable<Text>,
+        allowed_countries -> Nullable<Text>,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    account_freeze_audit_logs (audit_id) {
+        audit_id -> Int4,
+        account_id -> Uuid,
+        uid -> Int4,
+        action -> Text,
+        reason_code -> Nullable<Text>,
+        note -> Nullable<Text>,
+        operator -> Text,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    account_members (id) {
+        id -> Int4,
+        account_id -> Uuid,
+        uid -> Int4,
+        role -> Text,
+        spend_limit -> Nullable<Numeric>,
+        invited_by -> Int4,
+        accepted -> Bool,
+        created_at -> Int8,
+    }
+}
+
 diesel::table! {
     accounts (account_id) {
         account_id -> Uuid,
@@ -9,6 +44,176 @@ diesel::table! {
         uid -> Int4,
         created_at -> Int8,
         account_class -> Text,
+        label -> Nullable<Text>,
+        archived -> Bool,
+    }
+}
+
+diesel::table! {
+    api_key_spends (tid, req_id) {
+        tid -> Int4,
+        req_id -> Text,
+        amount_sats -> Int8,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    api_keys (tid) {
+        tid -> Int4,
+        uid -> Int4,
+        label -> Text,
+        kind -> Text,
+        roles -> Text,
+        daily_budget_sats -> Nullable<Int8>,
+        created_at -> Int8,
+        revoked_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    audit_logs (audit_id) {
+        audit_id -> Int4,
+        txid -> Text,
+        actor -> Text,
+        actor_id -> Nullable<Text>,
+        operation -> Text,
+        req_id -> Nullable<Text>,
+        uid -> Int4,
+        account_id -> Uuid,
+        currency -> Text,
+        amount -> Numeric,
+        balance_before -> Numeric,
+        balance_after -> Numeric,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    data_exports (export_id) {
+        export_id -> Uuid,
+        uid -> Int4,
+        content -> Text,
+        created_at -> Int8,
+        expires_at -> Int8,
+    }
+}
+
+diesel::table! {
+    dead_letters (id) {
+        id -> Int4,
+        source -> Text,
+        reason -> Text,
+        payload -> Bytea,
+        created_at -> Int8,
+        replayed_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    dealer_health_events (id) {
+        id -> Int4,
+        currency -> Text,
+        available -> Bool,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    deposit_anomalies (id) {
+        id -> Int4,
+        payment_request -> Text,
+        reason -> Text,
+        expected_msat -> Int8,
+        paid_msat -> Nullable<Int8>,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    deposit_limits (uid, currency) {
+        uid -> Int4,
+        currency -> Text,
+        max_balance -> Numeric,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    deposit_routing_rules (rule_id) {
+        rule_id -> Int4,
+        uid -> Int4,
+        account_id -> Uuid,
+        memo_pattern -> Nullable<Text>,
+        metadata_key -> Nullable<Text>,
+        priority -> Int4,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    dust_sweep_preferences (uid) {
+        uid -> Int4,
+        enabled -> Bool,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    flow_statistics (id) {
+        id -> Int4,
+        day -> Int8,
+        flow -> Text,
+        market -> Text,
+        volume -> Numeric,
+        fees -> Numeric,
+        tx_count -> Int4,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    frozen_accounts (account_id) {
+        account_id -> Uuid,
+        uid -> Int4,
+        reason_code -> Text,
+        note -> Nullable<Text>,
+        frozen_by -> Text,
+        frozen_at -> Int8,
+    }
+}
+
+diesel::table! {
+    hedge_targets (uid) {
+        uid -> Int4,
+        currency -> Text,
+        target_ratio -> Numeric,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (uid, idempotency_key) {
+        uid -> Int4,
+        idempotency_key -> Text,
+        req_id -> Text,
+        response -> Nullable<Text>,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    interest_accruals (id) {
+        id -> Int4,
+        account_id -> Uuid,
+        uid -> Int4,
+        day -> Int8,
+        currency -> Text,
+        balance -> Numeric,
+        apy -> Numeric,
+        amount -> Numeric,
+        txid -> Nullable<Text>,
+        created_at -> Int8,
     }
 }
 
@@ -19,6 +224,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    invoice_checkpoints (id) {
+        id -> Int4,
+        add_index -> Int8,
+        settle_index -> Int8,
+        updated_at -> Int8,
+    }
+}
+
 diesel::table! {
     invoices (payment_request) {
         payment_request -> Text,
@@ -39,6 +253,154 @@ diesel::table! {
         currency -> Nullable<Text>,
         target_account_currency -> Nullable<Text>,
         reference -> Nullable<Text>,
+        expired -> Bool,
+        metadata_fields -> Nullable<Text>,
+        reference_hash -> Nullable<Text>,
+        metadata_fields_hash -> Nullable<Text>,
+        descriptions_hashed_at -> Nullable<Int8>,
+        asset_units -> Nullable<Int8>,
+        comment -> Nullable<Text>,
+        payer_data -> Nullable<Text>,
+        description_hash -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    liquid_addresses (address) {
+        address -> Text,
+        uid -> Int4,
+        account_id -> Text,
+        claim_script -> Nullable<Text>,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    liquid_deposits (txid, vout) {
+        txid -> Text,
+        vout -> Int4,
+        uid -> Int4,
+        amount_sats -> Int8,
+        credited_at -> Int8,
+    }
+}
+
+diesel::table! {
+    liquidity_swaps (id) {
+        id -> Int4,
+        provider -> Text,
+        swap_id -> Text,
+        direction -> Text,
+        amount_sats -> Int8,
+        state -> Text,
+        cost_sats -> Nullable<Int8>,
+        created_at -> Int8,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    lnurl_withdrawal_requests (req_id) {
+        req_id -> Text,
+        uid -> Int4,
+        payment_request -> Text,
+        created_at -> Int8,
+        expires_at -> Int8,
+    }
+}
+
+diesel::table! {
+    node_info (id) {
+        id -> Int4,
+        payload -> Text,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    notification_preferences (uid) {
+        uid -> Int4,
+        email -> Nullable<Text>,
+        push_token -> Nullable<Text>,
+        push_platform -> Nullable<Text>,
+        deposits -> Bool,
+        withdrawals -> Bool,
+        failed_payments -> Bool,
+        security -> Bool,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    operator_revenues (id) {
+        id -> Int4,
+        day -> Int8,
+        source -> Text,
+        currency -> Text,
+        amount -> Numeric,
+        tx_count -> Int4,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    payment_retries (req_id) {
+        req_id -> Text,
+        uid -> Int4,
+        payment_request -> Text,
+        payment_result -> Text,
+        attempts -> Int4,
+        next_attempt_at -> Int8,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    pending_payments (req_id) {
+        req_id -> Text,
+        uid -> Int4,
+        payment_hash -> Text,
+        amount -> Numeric,
+        estimated_fee_sats -> Numeric,
+        payment_result -> Text,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    pending_withdrawals (req_id) {
+        req_id -> Text,
+        uid -> Int4,
+        amount_sats -> Int8,
+        currency -> Text,
+        payment_request -> Text,
+        status -> Text,
+        decided_by -> Nullable<Text>,
+        reason -> Nullable<Text>,
+        created_at -> Int8,
+        decided_at -> Nullable<Int8>,
+        processed_at -> Nullable<Int8>,
+        amount_msat -> Int8,
+    }
+}
+
+diesel::table! {
+    period_closes (id) {
+        id -> Int4,
+        closed_until -> Int8,
+        closed_at -> Int8,
+        closed_by -> Text,
+        reopened_at -> Nullable<Int8>,
+        reopened_by -> Nullable<Text>,
+        reopen_reason -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    period_closing_balances (close_id, account_id) {
+        close_id -> Int4,
+        account_id -> Uuid,
+        balance -> Numeric,
     }
 }
 
@@ -50,6 +412,92 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    rate_limit_buckets (bucket_key) {
+        bucket_key -> Text,
+        tokens -> Float8,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    recovery_approvals (recovery_id, guardian_uid) {
+        recovery_id -> Int4,
+        guardian_uid -> Int4,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    recovery_audit_logs (audit_id) {
+        audit_id -> Int4,
+        uid -> Int4,
+        recovery_id -> Nullable<Int4>,
+        actor_uid -> Nullable<Int4>,
+        action -> Text,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    recovery_configs (uid) {
+        uid -> Int4,
+        threshold -> Int4,
+        delay_secs -> Int8,
+        updated_at -> Int8,
+    }
+}
+
+diesel::table! {
+    recovery_guardians (uid, guardian_uid) {
+        uid -> Int4,
+        guardian_uid -> Int4,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    recovery_requests (recovery_id) {
+        recovery_id -> Int4,
+        uid -> Int4,
+        password -> Text,
+        state -> Text,
+        approvals_required -> Int4,
+        delay_secs -> Int8,
+        created_at -> Int8,
+        approved_at -> Nullable<Int8>,
+        closed_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    referrals (referee_uid) {
+        referee_uid -> Int4,
+        referrer_uid -> Int4,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    settlement_batches (group_id, req_id) {
+        group_id -> Uuid,
+        req_id -> Uuid,
+        kind -> Text,
+        direction -> Text,
+        amount -> Numeric,
+        txid -> Nullable<Text>,
+        created_at -> Int8,
+    }
+}
+
+diesel::table! {
+    summary_transaction_detached_legs (summary_txid, leg) {
+        summary_txid -> Text,
+        leg -> Text,
+        leg_txid -> Text,
+    }
+}
+
 diesel::table! {
     summary_transactions (txid) {
         txid -> Text,
@@ -72,6 +520,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    swap_orders (order_id) {
+        order_id -> Uuid,
+        uid -> Int4,
+        from_currency -> Text,
+        to_currency -> Text,
+        amount -> Numeric,
+        filled_amount -> Numeric,
+        limit_rate -> Numeric,
+        status -> Text,
+        created_at -> Int8,
+        updated_at -> Int8,
+    }
+}
+
 diesel::table! {
     transactions (txid) {
         txid -> Text,
@@ -97,18 +560,129 @@ diesel::table! {
         username -> Text,
         password -> Text,
         is_internal -> Bool,
+        totp_secret -> Nullable<Text>,
+        totp_enabled -> Bool,
+        totp_last_step -> Nullable<Int8>,
+        frozen -> Bool,
+        frozen_reason -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    vault_withdrawals (req_id) {
+        req_id -> Text,
+        uid -> Int4,
+        account_id -> Uuid,
+        amount -> Nullable<Numeric>,
+        currency -> Text,
+        payment_request -> Text,
+        status -> Text,
+        created_at -> Int8,
+        release_at -> Int8,
+        decided_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    webhooks (webhook_id) {
+        webhook_id -> Int4,
+        uid -> Int4,
+        url -> Text,
+        metadata_filter -> Nullable<Text>,
+        created_at -> Int8,
+        secret -> Nullable<Text>,
+        events -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    withdrawal_limits (uid) {
+        uid -> Int4,
+        daily_sats -> Nullable<Int8>,
+        weekly_sats -> Nullable<Int8>,
+        updated_at -> Int8,
     }
 }
 
+diesel::joinable!(access_policies -> users (uid));
+diesel::joinable!(account_members -> accounts (account_id));
+diesel::joinable!(account_freeze_audit_logs -> accounts (account_id));
+diesel::joinable!(account_freeze_audit_logs -> users (uid));
+diesel::joinable!(account_members -> users (uid));
 diesel::joinable!(accounts -> users (uid));
+diesel::joinable!(api_key_spends -> api_keys (tid));
+diesel::joinable!(api_keys -> users (uid));
+diesel::joinable!(data_exports -> users (uid));
+diesel::joinable!(deposit_limits -> users (uid));
+diesel::joinable!(deposit_routing_rules -> users (uid));
+diesel::joinable!(dust_sweep_preferences -> users (uid));
+diesel::joinable!(frozen_accounts -> accounts (account_id));
+diesel::joinable!(frozen_accounts -> users (uid));
+diesel::joinable!(hedge_targets -> users (uid));
+diesel::joinable!(interest_accruals -> users (uid));
 diesel::joinable!(internal_user_mappings -> users (uid));
+diesel::joinable!(lnurl_withdrawal_requests -> users (uid));
+diesel::joinable!(notification_preferences -> users (uid));
+diesel::joinable!(period_closing_balances -> period_closes (close_id));
+diesel::joinable!(recovery_approvals -> recovery_requests (recovery_id));
+diesel::joinable!(recovery_configs -> users (uid));
+diesel::joinable!(recovery_requests -> users (uid));
+diesel::joinable!(referrals -> users (referee_uid));
+diesel::joinable!(swap_orders -> users (uid));
+diesel::joinable!(vault_withdrawals -> users (uid));
+diesel::joinable!(webhooks -> users (uid));
+diesel::joinable!(withdrawal_limits -> users (uid));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    access_policies,
+    account_freeze_audit_logs,
+    account_members,
     accounts,
+    api_key_spends,
+    api_keys,
+    audit_logs,
+    data_exports,
+    dead_letters,
+    dealer_health_events,
+    deposit_anomalies,
+    deposit_limits,
+    deposit_routing_rules,
+    dust_sweep_preferences,
+    flow_statistics,
+    frozen_accounts,
+    hedge_targets,
+    idempotency_keys,
+    interest_accruals,
     internal_user_mappings,
+    invoice_checkpoints,
     invoices,
+    liquid_addresses,
+    liquid_deposits,
+    liquidity_swaps,
+    lnurl_withdrawal_requests,
+    node_info,
+    notification_preferences,
+    operator_revenues,
+    payment_retries,
+    pending_payments,
+    pending_withdrawals,
+    period_closes,
+    period_closing_balances,
     pre_signups,
+    rate_limit_buckets,
+    recovery_approvals,
+    recovery_audit_logs,
+    recovery_configs,
+    recovery_guardians,
+    recovery_requests,
+    referrals,
+    settlement_batches,
+    summary_transaction_detached_legs,
     summary_transactions,
+    swap_orders,
     transactions,
     users,
+    vault_withdrawals,
+    webhooks,
+    withdrawal_limits,
 );
@@ -0,0 +1,37 @@
+use crate::schema::settlement_batches;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A dealer settlement that was executed as part of a batch. The members of a batch share the group id.
+#[derive(Queryable, Identifiable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(group_id, req_id)]
+pub struct SettlementBatchMember {
+    pub group_id: Uuid,
+    pub req_id: Uuid,
+    pub kind: String,
+    /// Either `Payment` or `Withdrawal`.
+    pub direction: String,
+    pub amount: BigDecimal,
+    /// Summary transaction the settlement was booked with, not set for withdrawals which are booked
+    /// once the merged invoice is paid.
+    pub txid: Option<String>,
+    pub created_at: i64,
+}
+
+impl SettlementBatchMember {
+    pub fn insert_all(conn: &diesel::PgConnection, members: &[Self]) -> Result<usize, DieselError> {
+        diesel::insert_into(settlement_batches::table)
+            .values(members)
+            .execute(conn)
+    }
+
+    pub fn get_by_group(conn: &diesel::PgConnection, group_id: Uuid) -> Result<Vec<Self>, DieselError> {
+        settlement_batches::dsl::settlement_batches
+            .filter(settlement_batches::group_id.eq(group_id))
+            .load::<Self>(conn)
+    }
+}
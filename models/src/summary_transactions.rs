@@ -88,6 +88,23 @@ impl SummaryTransaction {
             .load(conn)
     }
 
+    pub fn get_between(conn: &diesel::PgConnection, from: i64, to: i64) -> Result<Vec<Self>, DieselError> {
+        summary_transactions::dsl::summary_transactions
+            .filter(
+                summary_transactions::created_at
+                    .ge(from)
+                    .and(summary_transactions::created_at.lt(to)),
+            )
+            .order(summary_transactions::created_at.asc())
+            .load(conn)
+    }
+
+    pub fn get_first_created_at(conn: &diesel::PgConnection) -> Result<Option<i64>, DieselError> {
+        summary_transactions::dsl::summary_transactions
+            .select(diesel::dsl::min(summary_transactions::created_at))
+            .first::<Option<i64>>(conn)
+    }
+
     pub fn insert(&self, conn: &diesel::PgConnection) -> Result<String, DieselError> {
         diesel::insert_into(summary_transactions::table)
             .values(self)
@@ -0,0 +1,84 @@
+use crate::schema::swap_orders;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const OPEN: &str = "Open";
+pub const PARTIALLY_FILLED: &str = "PartiallyFilled";
+pub const FILLED: &str = "Filled";
+pub const CANCELLED: &str = "Cancelled";
+
+/// A swap that is only made at the limit rate or better, filled by the dealer once the market reaches it.
+#[derive(Queryable, Identifiable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(order_id)]
+pub struct SwapOrder {
+    pub order_id: Uuid,
+    pub uid: i32,
+    pub from_currency: String,
+    pub to_currency: String,
+    /// Amount of the from currency to swap.
+    pub amount: BigDecimal,
+    pub filled_amount: BigDecimal,
+    /// Least amount of the to currency per unit of the from currency.
+    pub limit_rate: BigDecimal,
+    pub status: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl SwapOrder {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(swap_orders::table).values(self).execute(conn)
+    }
+
+    /// Orders that can still be filled, oldest first.
+    pub fn get_open(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        swap_orders::dsl::swap_orders
+            .filter(swap_orders::status.eq_any(vec![OPEN, PARTIALLY_FILLED]))
+            .order(swap_orders::created_at.asc())
+            .load(conn)
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        swap_orders::dsl::swap_orders
+            .filter(swap_orders::uid.eq(uid))
+            .order(swap_orders::created_at.desc())
+            .load(conn)
+    }
+
+    /// Records the filled amount of an order that is still open, returns the number of updated rows.
+    pub fn record_fill(
+        conn: &diesel::PgConnection,
+        order_id: Uuid,
+        filled_amount: BigDecimal,
+        status: &str,
+        now: i64,
+    ) -> Result<usize, DieselError> {
+        diesel::update(
+            swap_orders::dsl::swap_orders
+                .filter(swap_orders::order_id.eq(order_id))
+                .filter(swap_orders::status.eq_any(vec![OPEN, PARTIALLY_FILLED])),
+        )
+        .set((
+            swap_orders::filled_amount.eq(filled_amount),
+            swap_orders::status.eq(status),
+            swap_orders::updated_at.eq(now),
+        ))
+        .execute(conn)
+    }
+
+    /// Cancels what is left of an open order of the user, returns the number of updated rows.
+    pub fn cancel(conn: &diesel::PgConnection, order_id: Uuid, uid: i32, now: i64) -> Result<usize, DieselError> {
+        diesel::update(
+            swap_orders::dsl::swap_orders
+                .filter(swap_orders::order_id.eq(order_id))
+                .filter(swap_orders::uid.eq(uid))
+                .filter(swap_orders::status.eq_any(vec![OPEN, PARTIALLY_FILLED])),
+        )
+        .set((swap_orders::status.eq(CANCELLED), swap_orders::updated_at.eq(now)))
+        .execute(conn)
+    }
+}
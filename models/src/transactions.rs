@@ -6,6 +6,7 @@ use diesel::result::Error as DieselError;
 use serde::{Deserialize, Serialize};
 
 use bigdecimal::BigDecimal;
+use diesel::sql_types::{Numeric, Uuid as SqlUuid};
 use uuid::Uuid;
 
 fn time_now_as_i64() -> i64 {
@@ -33,7 +34,28 @@ pub struct Transaction {
     pub fees: BigDecimal,
 }
 
+/// Net amount booked on an account by all of its transactions.
+#[derive(QueryableByName, Debug)]
+pub struct AccountTransactionSum {
+    #[sql_type = "SqlUuid"]
+    pub account_id: Uuid,
+    #[sql_type = "Numeric"]
+    pub net_amount: BigDecimal,
+}
+
 impl Transaction {
+    /// Sums up inbound minus outbound amounts of all transactions per account.
+    pub fn get_net_amounts_by_account(conn: &diesel::PgConnection) -> Result<Vec<AccountTransactionSum>, DieselError> {
+        diesel::sql_query(
+            "SELECT account_id, SUM(amount) AS net_amount FROM ( \
+                SELECT inbound_account_id AS account_id, inbound_amount AS amount FROM transactions \
+                UNION ALL \
+                SELECT outbound_account_id AS account_id, -outbound_amount AS amount FROM transactions \
+            ) AS bookings GROUP BY account_id",
+        )
+        .load(conn)
+    }
+
     pub fn get_by_txid(conn: &diesel::PgConnection, txid: String) -> Result<Self, DieselError> {
         transactions::dsl::transactions
             .filter(transactions::txid.eq(txid))
@@ -84,6 +106,30 @@ impl Transaction {
             .load(conn)
     }
 
+    /// Returns the latest transactions that booked on `account_id`, newest first.
+    pub fn get_recent_by_account(
+        conn: &diesel::PgConnection,
+        account_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, DieselError> {
+        transactions::dsl::transactions
+            .filter(
+                transactions::outbound_account_id
+                    .eq(account_id)
+                    .or(transactions::inbound_account_id.eq(account_id)),
+            )
+            .order(transactions::created_at.desc())
+            .limit(limit)
+            .load(conn)
+    }
+
+    pub fn get_between(conn: &diesel::PgConnection, from: i64, to: i64) -> Result<Vec<Self>, DieselError> {
+        transactions::dsl::transactions
+            .filter(transactions::created_at.ge(from).and(transactions::created_at.lt(to)))
+            .order(transactions::created_at.asc())
+            .load(conn)
+    }
+
     pub fn insert(&self, conn: &diesel::PgConnection) -> Result<String, DieselError> {
         diesel::insert_into(transactions::table)
             .values(self)
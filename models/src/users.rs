@@ -50,6 +50,18 @@ pub struct User {
     pub password: String,
     /// Internal user flag
     pub is_internal: bool,
+    /// Base32 encoded secret of the authenticator app, set once the user enrolled
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    /// Whether the user confirmed the enrollment with a valid code
+    pub totp_enabled: bool,
+    /// Time step of the last accepted code, older codes are rejected
+    #[serde(skip_serializing)]
+    pub totp_last_step: Option<i64>,
+    /// Frozen users can still receive deposits but neither withdraw nor swap
+    pub frozen: bool,
+    /// Why an admin froze the user
+    pub frozen_reason: Option<String>,
 }
 
 #[derive(Insertable, Debug, Deserialize)]
@@ -84,6 +96,59 @@ impl User {
             .set(users::username.eq(username))
             .execute(conn)
     }
+
+    pub fn update_password(conn: &diesel::PgConnection, uid: i32, password: &str) -> Result<usize, DieselError> {
+        diesel::update(users::dsl::users.filter(users::uid.eq(uid)))
+            .set(users::password.eq(password))
+            .execute(conn)
+    }
+
+    /// Stores a new secret, it is only used once the enrollment is confirmed.
+    pub fn set_totp_secret(conn: &diesel::PgConnection, uid: i32, secret: Option<&str>) -> Result<usize, DieselError> {
+        diesel::update(users::dsl::users.filter(users::uid.eq(uid)))
+            .set((
+                users::totp_secret.eq(secret),
+                users::totp_enabled.eq(false),
+                users::totp_last_step.eq(None::<i64>),
+            ))
+            .execute(conn)
+    }
+
+    pub fn set_totp_enabled(conn: &diesel::PgConnection, uid: i32, enabled: bool) -> Result<usize, DieselError> {
+        diesel::update(users::dsl::users.filter(users::uid.eq(uid)))
+            .set(users::totp_enabled.eq(enabled))
+            .execute(conn)
+    }
+
+    pub fn set_frozen(
+        conn: &diesel::PgConnection,
+        uid: i32,
+        frozen: bool,
+        reason: Option<&str>,
+    ) -> Result<usize, DieselError> {
+        diesel::update(users::dsl::users.filter(users::uid.eq(uid)))
+            .set((users::frozen.eq(frozen), users::frozen_reason.eq(reason)))
+            .execute(conn)
+    }
+
+    pub fn get_frozen_uids(conn: &diesel::PgConnection) -> Result<Vec<i32>, DieselError> {
+        users::dsl::users
+            .filter(users::frozen.eq(true))
+            .select(users::uid)
+            .load::<i32>(conn)
+    }
+
+    /// Records the step of an accepted code unless a later one was accepted in the meantime, returns the
+    /// number of updated rows so a code raced by another request is rejected.
+    pub fn use_totp_step(conn: &diesel::PgConnection, uid: i32, step: i64) -> Result<usize, DieselError> {
+        diesel::update(
+            users::dsl::users
+                .filter(users::uid.eq(uid))
+                .filter(users::totp_last_step.is_null().or(users::totp_last_step.lt(step))),
+        )
+        .set(users::totp_last_step.eq(step))
+        .execute(conn)
+    }
 }
 
 impl InsertableUser {
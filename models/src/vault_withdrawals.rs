@@ -0,0 +1,76 @@
+use crate::schema::vault_withdrawals;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const PENDING: &str = "Pending";
+pub const RELEASED: &str = "Released";
+pub const CANCELLED: &str = "Cancelled";
+
+/// A payment from a vault account, made once its release time passed unless the user cancels it before.
+#[derive(Queryable, Identifiable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(req_id)]
+pub struct VaultWithdrawal {
+    pub req_id: String,
+    pub uid: i32,
+    pub account_id: Uuid,
+    /// Not known up front for payments of invoices in a fiat currency.
+    pub amount: Option<BigDecimal>,
+    pub currency: String,
+    /// Serialized payment request of the user, processed again once released.
+    #[serde(skip_serializing)]
+    pub payment_request: String,
+    pub status: String,
+    pub created_at: i64,
+    pub release_at: i64,
+    pub decided_at: Option<i64>,
+}
+
+impl VaultWithdrawal {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(vault_withdrawals::table).values(self).execute(conn)
+    }
+
+    /// Withdrawals of the user that can still be cancelled, next to be released first.
+    pub fn get_pending_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        vault_withdrawals::dsl::vault_withdrawals
+            .filter(vault_withdrawals::uid.eq(uid))
+            .filter(vault_withdrawals::status.eq(PENDING))
+            .order(vault_withdrawals::release_at.asc())
+            .load(conn)
+    }
+
+    /// Pending withdrawals whose delay passed.
+    pub fn get_due(conn: &diesel::PgConnection, now: i64) -> Result<Vec<Self>, DieselError> {
+        vault_withdrawals::dsl::vault_withdrawals
+            .filter(vault_withdrawals::status.eq(PENDING))
+            .filter(vault_withdrawals::release_at.le(now))
+            .order(vault_withdrawals::release_at.asc())
+            .load(conn)
+    }
+
+    /// Moves a pending withdrawal of the user to the status, returns the number of updated rows so a withdrawal
+    /// is never both released and cancelled.
+    pub fn decide(
+        conn: &diesel::PgConnection,
+        req_id: String,
+        uid: i32,
+        status: &str,
+        now: i64,
+    ) -> Result<usize, DieselError> {
+        diesel::update(
+            vault_withdrawals::dsl::vault_withdrawals
+                .filter(vault_withdrawals::req_id.eq(req_id))
+                .filter(vault_withdrawals::uid.eq(uid))
+                .filter(vault_withdrawals::status.eq(PENDING)),
+        )
+        .set((
+            vault_withdrawals::status.eq(status),
+            vault_withdrawals::decided_at.eq(now),
+        ))
+        .execute(conn)
+    }
+}
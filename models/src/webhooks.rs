@@ -0,0 +1,57 @@
+use crate::schema::webhooks;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Identifiable, Debug, Serialize, Deserialize)]
+#[primary_key(webhook_id)]
+pub struct Webhook {
+    pub webhook_id: i32,
+    pub uid: i32,
+    pub url: String,
+    /// Json object of invoice metadata fields an event has to carry to be dispatched.
+    /// A null value only requires the field to be present.
+    pub metadata_filter: Option<String>,
+    pub created_at: i64,
+    /// Key the payloads are signed with, only handed out when the webhook is created.
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    /// Comma separated events the webhook is subscribed to, all events if null.
+    pub events: Option<String>,
+}
+
+impl Webhook {
+    pub fn subscribes_to(&self, event: &str) -> bool {
+        match &self.events {
+            Some(events) => events.split(',').any(|subscribed| subscribed == event),
+            None => true,
+        }
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        webhooks::dsl::webhooks.filter(webhooks::uid.eq(uid)).load::<Self>(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, webhook_id: i32, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(webhooks::dsl::webhooks.filter(webhooks::webhook_id.eq(webhook_id).and(webhooks::uid.eq(uid))))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "webhooks"]
+pub struct InsertableWebhook {
+    pub uid: i32,
+    pub url: String,
+    pub metadata_filter: Option<String>,
+    pub created_at: i64,
+    pub secret: Option<String>,
+    pub events: Option<String>,
+}
+
+impl InsertableWebhook {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<Webhook, DieselError> {
+        diesel::insert_into(webhooks::table).values(self).get_result(conn)
+    }
+}
@@ -0,0 +1,38 @@
+use crate::schema::withdrawal_limits;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+/// Per-user override of the withdrawal velocity limits. Limits that are not set fall back to the global ones.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Clone, Serialize, Deserialize)]
+#[primary_key(uid)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct WithdrawalLimit {
+    pub uid: i32,
+    pub daily_sats: Option<i64>,
+    pub weekly_sats: Option<i64>,
+    pub updated_at: i64,
+}
+
+impl WithdrawalLimit {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Option<Self>, DieselError> {
+        withdrawal_limits::dsl::withdrawal_limits
+            .filter(withdrawal_limits::uid.eq(uid))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(withdrawal_limits::table)
+            .values(self)
+            .on_conflict(withdrawal_limits::uid)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(withdrawal_limits::dsl::withdrawal_limits.filter(withdrawal_limits::uid.eq(uid))).execute(conn)
+    }
+}
@@ -1,6 +1,8 @@
+use core_types::access::RequestOrigin;
 use core_types::*;
 use rust_decimal::prelude::*;
 use std::collections::HashMap;
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -14,6 +16,17 @@ pub enum InvoiceResponseError {
     RequestLimitExceeded,
     DatabaseConnectionFailed,
     InvoicingSuspended,
+    DuplicateRequest,
+    /// The node can't receive the amount, invoices up to the max are still payable.
+    InboundCapacityExceeded { max_receivable_sats: u64 },
+    /// The memo or metadata was rejected by the content filter.
+    ContentRejected,
+    /// The bank has too many requests queued, the request can be retried later.
+    ServiceOverloaded,
+    /// The currency is backed by an asset the bank can't receive right now.
+    AssetNotAvailable,
+    /// The amount is below the smallest unit of the asset.
+    InvalidAmount,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +35,8 @@ pub enum CreateLnurlWithdrawalError {
     FailedToCreateLnUrl,
     InvalidAmount,
     UserAccountNotFound,
+    RateExpired,
+    OriginNotAllowed,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,16 +49,75 @@ pub enum PayLnurlWithdrawalError {
     RequestNotFound,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LnurlChannelError {
+    /// The operator doesn't offer channels.
+    NotOffered,
+    /// The node has no public uri peers could connect to.
+    NodeNotReachable,
+    InsufficientFunds,
+    UserAccountNotFound,
+    RequestNotFound,
+    FailedToOpenChannel,
+    TransactionFailed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EcashError {
+    /// The bank isn't connected to a federation.
+    NotAvailable,
+    InvalidAmount,
+    InsufficientFunds,
+    UserAccountNotFound,
+    AccountDoesNotExist,
+    UserFrozen,
+    AccountFrozen,
+    WithdrawalsHalted,
+    DepositLimitExceeded,
+    /// The federation rejected the notes, e.g. because they were spent already.
+    InvalidNotes,
+    /// The payment through the federation's lightning gateway failed.
+    GatewayFailed,
+    TransactionFailed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LiquidError {
+    /// The bank isn't connected to a Liquid node.
+    NotAvailable,
+    InvalidAmount,
+    InsufficientFunds,
+    UserAccountNotFound,
+    AccountDoesNotExist,
+    UserFrozen,
+    AccountFrozen,
+    WithdrawalsHalted,
+    /// The claim script wasn't handed out to the user.
+    UnknownClaimScript,
+    /// Elementsd rejected the request, e.g. a peg-in without enough confirmations.
+    NodeFailed,
+    DatabaseConnectionFailed,
+    TransactionFailed,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SwapResponseError {
     Invalid,
     CurrencyNotAvailable,
     InvalidQuoteId,
+    QuoteExpired,
     NotEnoughAvailableBalance,
     BTCNotFromTo,
     UserAccountNotFound,
     DatabaseConnectionFailed,
     TransactionFailed,
+    DuplicateRequest,
+    OriginNotAllowed,
+    InvalidTotpCode,
+    UserFrozen,
+    AccountFrozen,
+    /// The bank has too many requests queued, the request can be retried later.
+    ServiceOverloaded,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,6 +149,14 @@ pub struct InvoiceRequest {
     pub currency: Currency,
     pub account_id: Option<Uuid>,
     pub target_account_currency: Option<Currency>,
+    /// Merchant supplied fields stored with the invoice, e.g. an order id.
+    pub metadata_fields: Option<HashMap<String, String>>,
+    /// Client supplied key. Retries with the same key get the response of the first request.
+    pub idempotency_key: Option<String>,
+    /// Comment the payer of an LNURL payment sent along (LUD-12).
+    pub comment: Option<String>,
+    /// Json object of the identity the payer of an LNURL payment disclosed (LUD-18).
+    pub payer_data: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -100,10 +182,27 @@ pub struct PaymentRequest {
     pub payment_request: Option<String>,
     pub currency: Currency,
     pub receipient: Option<String>,
+    /// Account of another user to transfer to, converted through the dealer if its currency differs.
+    pub target_account_id: Option<AccountId>,
+    /// Account the payment is made from, defaults to the main account of the currency.
+    pub account_id: Option<AccountId>,
+    /// Node pubkey the amount is pushed to with keysend when no invoice is given.
     pub destination: Option<String>,
+    /// Message sent along a keysend payment.
+    #[serde(default)]
+    pub keysend_message: Option<String>,
     pub amount: Option<Money>,
     pub rate: Option<Rate>,
     pub fees: Option<Money>,
+    /// Set once the request was sent back to the dealer because its rate was stale.
+    #[serde(default)]
+    pub requoted: bool,
+    /// Client supplied key. Retries with the same key get the response of the first request.
+    pub idempotency_key: Option<String>,
+    /// Origin of the request, checked against access policies.
+    pub origin: Option<RequestOrigin>,
+    /// Code of the authenticator app, required above the 2fa thresholds for users that enrolled.
+    pub totp_code: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,6 +221,60 @@ pub enum PaymentResponseError {
     DatabaseConnectionFailed,
     InvalidInvoice,
     CreatingInvoiceFailed,
+    RateExpired,
+    PaymentRetryScheduled,
+    DuplicateRequest,
+    WithdrawalsHalted,
+    OriginNotAllowed,
+    AccountDoesNotExist,
+    NotPermitted,
+    SpendLimitExceeded,
+    VelocityLimitExceeded,
+    /// The withdrawal is above the approval threshold and held until an admin reviews it.
+    PendingApproval,
+    WithdrawalRejected,
+    /// The payment is from a vault and is made once the withdrawal delay passed, unless it is cancelled.
+    WithdrawalDelayed,
+    InvalidTotpCode,
+    /// An admin froze the user, deposits are still accepted.
+    UserFrozen,
+    /// An admin froze the account the funds would leave from.
+    AccountFrozen,
+    /// The bank has too many requests queued, the request can be retried later.
+    ServiceOverloaded,
+    /// The node's channels can't send the amount right now, payments up to the max may still go through.
+    TemporaryLiquidityShortage { max_payable_sats: u64 },
+    /// The currency is backed by an asset the bank can't send right now.
+    AssetNotAvailable,
+    /// The API key or device the payment was made with spent its daily budget.
+    KeyBudgetExceeded,
+}
+
+/// Someone asked the guardians of the account to recover it. The api handles recoveries itself and sends
+/// this through the bank so the owner is notified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryRequested {
+    pub uid: UserId,
+    pub recovery_id: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceExpired {
+    pub uid: UserId,
+    pub payment_request: String,
+    pub payment_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceSettled {
+    pub uid: UserId,
+    pub payment_request: String,
+    pub payment_hash: String,
+    pub amount: Money,
+    pub metadata_fields: HashMap<String, String>,
+    pub comment: Option<String>,
+    pub payer_data: Option<String>,
+    pub description_hash: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -162,6 +315,13 @@ impl PaymentResponse {
             preimage: preimage,
         }
     }
+
+    /// Payments are settled in msats, users are shown the amount and fees in whole sats.
+    pub fn round_for_display(mut self) -> Self {
+        self.amount = self.amount.map(|amount| amount.round_for_display());
+        self.fees = self.fees.map(|fees| fees.round_for_display());
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -172,6 +332,29 @@ pub struct SwapRequest {
     pub from: Currency,
     pub to: Currency,
     pub quote_id: Option<u128>,
+    /// Terms of the quote as signed by the dealer, required to swap at a quote if quotes are signed.
+    #[serde(default)]
+    pub signed_quote: Option<FirmQuote>,
+    /// Client supplied key. Retries with the same key get the response of the first request.
+    pub idempotency_key: Option<String>,
+    /// Origin of the request, checked against access policies.
+    pub origin: Option<RequestOrigin>,
+    /// Code of the authenticator app, required above the 2fa thresholds for users that enrolled.
+    pub totp_code: Option<String>,
+}
+
+/// Swaps the whole balance of a fiat account to BTC and pays the invoice from it.
+/// Answered with a `PaymentResponse`, what is left after the payment stays on the BTC account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CashOutRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub currency: Currency,
+    pub payment_request: String,
+    /// Origin of the request, checked against access policies.
+    pub origin: Option<RequestOrigin>,
+    /// Code of the authenticator app, required above the 2fa thresholds for users that enrolled.
+    pub totp_code: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -198,11 +381,17 @@ pub struct Balances {
     pub req_id: RequestId,
     pub uid: UserId,
     pub accounts: HashMap<AccountId, Account>,
+    /// Accounts of other users the user is a member of.
+    #[serde(default)]
+    pub shared_accounts: HashMap<AccountId, Account>,
     pub error: Option<BalancesResponseError>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum BalancesResponseError {}
+pub enum BalancesResponseError {
+    /// The bank has too many requests queued, the request can be retried later.
+    ServiceOverloaded,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuoteRequest {
@@ -226,6 +415,96 @@ pub struct QuoteResponse {
     pub quote_id: Option<u128>,
     pub error: Option<QuoteResponseError>,
     pub fees: Option<Money>,
+    /// Hex encoded HMAC of the terms of the quote, see `FirmQuote`.
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+impl QuoteResponse {
+    /// Terms the swap request refers to, None unless the quote was signed.
+    pub fn firm_quote(&self) -> Option<FirmQuote> {
+        Some(FirmQuote {
+            rate: self.rate.as_ref()?.value,
+            valid_until: self.valid_until,
+            signature: self.signature.clone()?,
+        })
+    }
+}
+
+/// Rate the dealer guarantees for a quote until it expires. The signature covers the terms and the swap
+/// they were quoted for, so the bank can check them before trusting the rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirmQuote {
+    pub rate: Decimal,
+    // epoch in ms
+    pub valid_until: u64,
+    pub signature: String,
+}
+
+impl FirmQuote {
+    pub fn signing_payload(&self, quote_id: u128, uid: UserId, amount: &Money, from: Currency, to: Currency) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}",
+            quote_id,
+            uid,
+            amount.value.normalize(),
+            from,
+            to,
+            self.rate.normalize(),
+            self.valid_until
+        )
+    }
+}
+
+/// Direction of a conversion, e.g. BTC/USD is the rate of one BTC in USD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CurrencyPair {
+    pub base: Currency,
+    pub quote: Currency,
+}
+
+impl fmt::Display for CurrencyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+impl FromStr for CurrencyPair {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, quote) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid currency pair {}", s))?;
+        let base = Currency::from_str(base).map_err(|_| format!("Invalid currency {}", base))?;
+        let quote = Currency::from_str(quote).map_err(|_| format!("Invalid currency {}", quote))?;
+        Ok(Self { base, quote })
+    }
+}
+
+/// Asks the dealer to publish the rates of the pairs for a while, sent again by the api as long as
+/// clients are subscribed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeRates {
+    pub pairs: Vec<CurrencyPair>,
+}
+
+/// Rates of a pair in units of the quote currency per unit of the base currency, spread included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairRate {
+    pub pair: CurrencyPair,
+    /// Received when swapping the base currency to the quote currency.
+    pub bid: Decimal,
+    /// Middle of the market without the spread.
+    pub mid: Decimal,
+    /// Paid when swapping the quote currency to the base currency.
+    pub ask: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RatesUpdate {
+    pub rates: Vec<PairRate>,
+    pub timestamp: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -260,6 +539,103 @@ pub struct GetNodeInfoResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GetNodeInfoResponseError {}
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetStatusRequest {
+    pub req_id: RequestId,
+}
+
+/// Non-sensitive health data of the instance that can be shown publicly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetStatusResponse {
+    pub req_id: RequestId,
+    pub node_alias: String,
+    pub available_currencies: Vec<Currency>,
+    pub withdrawal_only: bool,
+    /// 95th percentile of the time it took to process recent withdrawals.
+    pub withdrawal_processing_p95_ms: Option<u64>,
+    pub maintenance_notices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetReservesReport {
+    pub req_id: RequestId,
+}
+
+/// Funds held by the node compared against what the bank owes its users.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservesReport {
+    pub timestamp: u64,
+    pub node_pubkey: String,
+    /// Local balance of all channels in BTC, including channels pending open.
+    pub channel_balance: Decimal,
+    /// On-chain wallet balance in BTC, including unconfirmed funds.
+    pub wallet_balance: Decimal,
+    pub total_reserves: Decimal,
+    /// Sum of all user account balances per currency.
+    pub liabilities: HashMap<Currency, Decimal>,
+    /// Total reserves divided by BTC liabilities.
+    pub coverage_ratio: Option<Decimal>,
+    /// Message the attestation signature was created for.
+    pub attestation: String,
+    /// Signature of the attestation by the node's identity key.
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReservesReportError {
+    FailedToFetchBalances,
+    FailedToSignAttestation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetReservesReportResponse {
+    pub req_id: RequestId,
+    pub report: Option<ReservesReport>,
+    pub error: Option<ReservesReportError>,
+}
+
+/// Starts the enrollment into 2fa with a new secret, replacing a secret that wasn't confirmed yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollTotpRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+}
+
+/// Enables 2fa once the user shows the authenticator app produces valid codes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmTotpRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisableTotpRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TotpError {
+    AlreadyEnabled,
+    NotEnrolled,
+    InvalidCode,
+    DatabaseConnectionFailed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub enabled: bool,
+    /// Base32 encoded secret, only returned on enrollment.
+    pub secret: Option<String>,
+    /// Uri to show as qr code to authenticator apps, only returned on enrollment.
+    pub otpauth_uri: Option<String>,
+    pub error: Option<TotpError>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateLnurlWithdrawalRequest {
     pub req_id: RequestId,
@@ -268,6 +644,11 @@ pub struct CreateLnurlWithdrawalRequest {
     pub currency: Currency,
     pub rate: Option<Rate>,
     pub fees: Option<Money>,
+    /// Set once the request was sent back to the dealer because its rate was stale.
+    #[serde(default)]
+    pub requoted: bool,
+    /// Origin of the request, checked against access policies.
+    pub origin: Option<RequestOrigin>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -305,6 +686,144 @@ pub struct PayLnurlWithdrawalResponse {
     pub error: Option<PayLnurlWithdrawalError>,
 }
 
+/// Asks for an address L-BTC deposits to are credited to the account, or a Bitcoin address to peg in from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidAddressRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    /// L-BTC account deposits are credited to, defaults to the main account.
+    pub account_id: Option<AccountId>,
+    pub peg_in: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidAddressResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub address: Option<String>,
+    /// Script a peg-in to the address is claimed with.
+    pub claim_script: Option<String>,
+    pub error: Option<LiquidError>,
+}
+
+/// Claims a peg-in once its Bitcoin transaction has 102 confirmations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidClaimPegInRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    /// Hex encoded Bitcoin transaction paying the peg-in address.
+    pub bitcoin_tx: String,
+    /// Hex encoded proof the transaction is in a block, see bitcoind's `gettxoutproof`.
+    pub txout_proof: String,
+    pub claim_script: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidClaimPegInResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub txid: Option<String>,
+    pub amount: Option<Money>,
+    pub error: Option<LiquidError>,
+}
+
+/// Sends L-BTC to a Liquid address or pegs it out to a Bitcoin address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidWithdrawalRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account_id: Option<AccountId>,
+    pub address: String,
+    pub amount: Money,
+    pub peg_out: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidWithdrawalResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub txid: Option<String>,
+    pub fees: Option<Money>,
+    pub error: Option<LiquidError>,
+}
+
+/// Withdraws BTC into ecash notes of the bank's federation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcashWithdrawRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub amount: Money,
+    /// BTC account the notes are paid from, defaults to the main account.
+    pub account_id: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcashWithdrawResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub amount: Money,
+    /// Serialized notes, the user redeems them in their own federation wallet.
+    pub notes: Option<String>,
+    pub fees: Option<Money>,
+    pub error: Option<EcashError>,
+}
+
+/// Deposits ecash notes of the bank's federation by redeeming them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcashDepositRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub notes: String,
+    pub account_id: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcashDepositResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    /// Amount credited, the value of the notes less the gateway fee.
+    pub amount: Option<Money>,
+    pub fees: Option<Money>,
+    pub error: Option<EcashError>,
+}
+
+/// Asks for the LNURL-channel parameters of a channel from the bank's node to the user's node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetLnurlChannelRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetLnurlChannelResponse {
+    pub req_id: RequestId,
+    /// Uri of the bank's node the user's node connects to before calling back.
+    pub uri: Option<String>,
+    pub callback: String,
+    /// Secret the callback is authorized with.
+    pub k1: RequestId,
+    pub channel_size_sats: u64,
+    /// Charged from the user's BTC account once the channel is opened.
+    pub fee_sats: u64,
+    pub error: Option<LnurlChannelError>,
+}
+
+/// Callback of LNURL-channel, opens the channel offered under `k1` to the node `remote_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestChannelOpen {
+    pub req_id: RequestId,
+    pub k1: RequestId,
+    pub remote_id: String,
+    pub private: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestChannelOpenResponse {
+    pub req_id: RequestId,
+    pub funding_txid: Option<String>,
+    pub error: Option<LnurlChannelError>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryRouteRequest {
     pub req_id: RequestId,
@@ -319,6 +838,401 @@ pub struct QueryRouteResponse {
     pub error: Option<QueryRouteError>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatementFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportStatementRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    /// Start of the period in millis, defaults to the first transaction.
+    pub from: Option<u64>,
+    /// End of the period in millis, defaults to now.
+    pub to: Option<u64>,
+    pub format: StatementFormat,
+    /// Currency transactions are valued in, defaults to USD.
+    pub valuation_currency: Option<Currency>,
+}
+
+/// A transaction as booked on one account of the user. Outbound amounts are negative.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementEntry {
+    pub created_at: u64,
+    pub txid: String,
+    pub tx_type: String,
+    pub account_id: Uuid,
+    pub currency: Currency,
+    pub amount: Decimal,
+    pub fees: Decimal,
+    /// Balance of the account after the transaction.
+    pub balance: Decimal,
+    pub valuation_currency: Currency,
+    /// Value of the amount at the time of the transaction, not set if no rate was known yet.
+    pub valuation: Option<Decimal>,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExportStatementError {
+    InvalidPeriod,
+    DatabaseConnectionFailed,
+    FailedToFetchTransactions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportStatementResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub format: StatementFormat,
+    /// The statement rendered in the requested format.
+    pub content: Option<String>,
+    pub error: Option<ExportStatementError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataExportDelivery {
+    /// The archive is stored and can be downloaded once through a signed link.
+    Link,
+    /// The archive follows the response in `ExportMyDataChunk` messages.
+    Inline,
+}
+
+/// Asks for an archive of the user's accounts, transactions, invoices and preferences.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportMyDataRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub delivery: DataExportDelivery,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExportMyDataError {
+    DatabaseConnectionFailed,
+    FailedToFetchData,
+    FailedToStoreExport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportMyDataResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub delivery: DataExportDelivery,
+    /// Id of the stored archive if it is delivered as link.
+    pub export_id: Option<Uuid>,
+    /// Time in millis after which the stored archive is deleted.
+    pub expires_at: Option<u64>,
+    /// Number of chunks following the response if the archive is delivered inline.
+    pub chunks: usize,
+    pub error: Option<ExportMyDataError>,
+}
+
+/// Part of an archive delivered inline, chunks are sent in order right after the response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportMyDataChunk {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub index: usize,
+    pub data: String,
+}
+
+/// Moves funds between two accounts of the same user and currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InternalTransferRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub from_account_id: AccountId,
+    pub to_account_id: AccountId,
+    pub amount: Decimal,
+    /// Origin of the request, checked against access policies.
+    pub origin: Option<RequestOrigin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InternalTransferError {
+    InvalidAmount,
+    SameAccount,
+    AccountNotFound,
+    CurrencyMismatch,
+    InsufficientFunds,
+    TransactionFailed,
+    OriginNotAllowed,
+    /// Funds only leave a vault through a delayed withdrawal.
+    VaultAccount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InternalTransferResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub success: bool,
+    pub from_account_id: AccountId,
+    pub to_account_id: AccountId,
+    pub amount: Decimal,
+    pub txid: Option<String>,
+    pub error: Option<InternalTransferError>,
+}
+
+/// Opens a labeled sub-account, e.g. to put funds aside for savings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAccountRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub currency: Currency,
+    pub label: String,
+    /// Opens a vault, withdrawals from it are delayed and can be cancelled in the meantime.
+    #[serde(default)]
+    pub vault: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameAccountRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account_id: AccountId,
+    pub label: String,
+}
+
+/// Only empty sub-accounts can be archived, they keep their history but no longer receive funds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveAccountRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account_id: AccountId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AccountResponseError {
+    AccountNotFound,
+    InvalidLabel,
+    LabelAlreadyUsed,
+    AccountLimitExceeded,
+    AccountNotEmpty,
+    MainAccount,
+    VaultsNotOffered,
+}
+
+/// Answers the create, rename and archive requests with the resulting account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account: Option<Account>,
+    pub error: Option<AccountResponseError>,
+}
+
+/// Invites a user to an account, only the owner and admins of the account can invite.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteAccountMemberRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account_id: AccountId,
+    pub username: String,
+    pub role: MemberRole,
+    /// Max amount per payment in the currency of the account, only applies to spenders.
+    pub spend_limit: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptAccountInvitationRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account_id: AccountId,
+}
+
+/// Removes a member from an account. Members can remove themselves, others only the owner and admins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveAccountMemberRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub account_id: AccountId,
+    pub member_uid: UserId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountMember {
+    pub account_id: AccountId,
+    pub uid: UserId,
+    pub role: MemberRole,
+    pub spend_limit: Option<Decimal>,
+    pub accepted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AccountMemberError {
+    AccountNotFound,
+    UserDoesNotExist,
+    NotPermitted,
+    AlreadyMember,
+    MemberNotFound,
+    DatabaseConnectionFailed,
+}
+
+/// Answers the invite, accept and remove requests with the affected membership.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountMemberResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub member: Option<AccountMember>,
+    pub error: Option<AccountMemberError>,
+}
+
+/// Asks for the interest accrued on the fiat balances of the user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetInterestHistory {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    /// Start of the period in millis, defaults to the first accrual.
+    pub from: Option<u64>,
+    /// End of the period in millis, defaults to now.
+    pub to: Option<u64>,
+}
+
+/// Interest accrued on one account over a UTC day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestEntry {
+    pub day: u64,
+    pub account_id: AccountId,
+    pub currency: Currency,
+    /// Balance the interest was accrued on.
+    pub balance: Decimal,
+    pub apy: Decimal,
+    pub amount: Decimal,
+    pub txid: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InterestHistoryError {
+    InvalidPeriod,
+    DatabaseConnectionFailed,
+    FailedToFetchData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestHistory {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub entries: Vec<InterestEntry>,
+    /// Interest accrued within the period per currency.
+    pub totals: HashMap<Currency, Decimal>,
+    pub error: Option<InterestHistoryError>,
+}
+
+/// Swaps the amount once the dealer's rate reaches the limit rate, in one or more fills.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapOrderRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub amount: Money,
+    pub from: Currency,
+    pub to: Currency,
+    /// Least amount of the to currency per unit of the from currency, like the value of a swap's rate.
+    pub limit_rate: Decimal,
+    /// Origin of the request, checked against access policies.
+    pub origin: Option<RequestOrigin>,
+    /// Code of the authenticator app, required above the 2fa thresholds for users that enrolled.
+    pub totp_code: Option<String>,
+}
+
+/// Cancels what is left of an order, fills made so far stay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelSwapOrderRequest {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub order_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetSwapOrders {
+    pub req_id: RequestId,
+    pub uid: UserId,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SwapOrderStatus {
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+}
+
+impl SwapOrderStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(self, SwapOrderStatus::Open | SwapOrderStatus::PartiallyFilled)
+    }
+}
+
+impl fmt::Display for SwapOrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            SwapOrderStatus::Open => "Open",
+            SwapOrderStatus::PartiallyFilled => "PartiallyFilled",
+            SwapOrderStatus::Filled => "Filled",
+            SwapOrderStatus::Cancelled => "Cancelled",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+impl FromStr for SwapOrderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Open" => Ok(SwapOrderStatus::Open),
+            "PartiallyFilled" => Ok(SwapOrderStatus::PartiallyFilled),
+            "Filled" => Ok(SwapOrderStatus::Filled),
+            "Cancelled" => Ok(SwapOrderStatus::Cancelled),
+            _ => Err(format!("Unknown swap order status {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapOrder {
+    pub order_id: Uuid,
+    pub uid: UserId,
+    pub from: Currency,
+    pub to: Currency,
+    /// Amount of the from currency to swap.
+    pub amount: Decimal,
+    pub filled_amount: Decimal,
+    pub limit_rate: Decimal,
+    pub status: SwapOrderStatus,
+    pub created_at: u64,
+}
+
+impl SwapOrder {
+    pub fn remaining(&self) -> Decimal {
+        self.amount - self.filled_amount
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SwapOrderError {
+    Invalid,
+    BTCNotFromTo,
+    CurrencyNotAvailable,
+    NotEnoughAvailableBalance,
+    OrderNotFound,
+    OriginNotAllowed,
+    InvalidTotpCode,
+    UserFrozen,
+    AccountFrozen,
+    DatabaseConnectionFailed,
+}
+
+/// Answers all swap order requests. Holds the placed or cancelled order, or all orders of the user if listed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapOrderResponse {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub orders: Vec<SwapOrder>,
+    pub error: Option<SwapOrderError>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Api {
     InvoiceRequest(InvoiceRequest),
@@ -341,6 +1255,134 @@ pub enum Api {
     GetLnurlWithdrawalResponse(GetLnurlWithdrawalResponse),
     PayLnurlWithdrawalRequest(PayLnurlWithdrawalRequest),
     PayLnurlWithdrawalResponse(PayLnurlWithdrawalResponse),
+    LiquidAddressRequest(LiquidAddressRequest),
+    LiquidAddressResponse(LiquidAddressResponse),
+    LiquidClaimPegInRequest(LiquidClaimPegInRequest),
+    LiquidClaimPegInResponse(LiquidClaimPegInResponse),
+    LiquidWithdrawalRequest(LiquidWithdrawalRequest),
+    LiquidWithdrawalResponse(LiquidWithdrawalResponse),
+    EcashWithdrawRequest(EcashWithdrawRequest),
+    EcashWithdrawResponse(EcashWithdrawResponse),
+    EcashDepositRequest(EcashDepositRequest),
+    EcashDepositResponse(EcashDepositResponse),
+    GetLnurlChannelRequest(GetLnurlChannelRequest),
+    GetLnurlChannelResponse(GetLnurlChannelResponse),
+    RequestChannelOpen(RequestChannelOpen),
+    RequestChannelOpenResponse(RequestChannelOpenResponse),
     QueryRouteRequest(QueryRouteRequest),
     QueryRouteResponse(QueryRouteResponse),
+    InvoiceExpired(InvoiceExpired),
+    InvoiceSettled(InvoiceSettled),
+    GetStatusRequest(GetStatusRequest),
+    GetStatusResponse(GetStatusResponse),
+    GetReservesReport(GetReservesReport),
+    GetReservesReportResponse(GetReservesReportResponse),
+    ExportStatementRequest(ExportStatementRequest),
+    ExportStatementResponse(ExportStatementResponse),
+    ExportMyDataRequest(ExportMyDataRequest),
+    ExportMyDataResponse(ExportMyDataResponse),
+    ExportMyDataChunk(ExportMyDataChunk),
+    CashOutRequest(CashOutRequest),
+    InternalTransferRequest(InternalTransferRequest),
+    InternalTransferResponse(InternalTransferResponse),
+    CreateAccountRequest(CreateAccountRequest),
+    RenameAccountRequest(RenameAccountRequest),
+    ArchiveAccountRequest(ArchiveAccountRequest),
+    AccountResponse(AccountResponse),
+    InviteAccountMemberRequest(InviteAccountMemberRequest),
+    AcceptAccountInvitationRequest(AcceptAccountInvitationRequest),
+    RemoveAccountMemberRequest(RemoveAccountMemberRequest),
+    AccountMemberResponse(AccountMemberResponse),
+    GetInterestHistory(GetInterestHistory),
+    InterestHistory(InterestHistory),
+    EnrollTotpRequest(EnrollTotpRequest),
+    ConfirmTotpRequest(ConfirmTotpRequest),
+    DisableTotpRequest(DisableTotpRequest),
+    TotpResponse(TotpResponse),
+    SwapOrderRequest(SwapOrderRequest),
+    CancelSwapOrderRequest(CancelSwapOrderRequest),
+    GetSwapOrders(GetSwapOrders),
+    SwapOrderResponse(SwapOrderResponse),
+    SubscribeRates(SubscribeRates),
+    RatesUpdate(RatesUpdate),
+    RecoveryRequested(RecoveryRequested),
+}
+
+impl Api {
+    /// Id of the request the message belongs to, responses carry the id of their request.
+    pub fn req_id(&self) -> Option<RequestId> {
+        match self {
+            Api::InvoiceRequest(msg) => Some(msg.req_id),
+            Api::InvoiceResponse(msg) => Some(msg.req_id),
+            Api::PaymentRequest(msg) => Some(msg.req_id),
+            Api::PaymentResponse(msg) => Some(msg.req_id),
+            Api::SwapRequest(msg) => Some(msg.req_id),
+            Api::SwapResponse(msg) => Some(msg.req_id),
+            Api::GetBalances(msg) => Some(msg.req_id),
+            Api::Balances(msg) => Some(msg.req_id),
+            Api::QuoteRequest(msg) => Some(msg.req_id),
+            Api::QuoteResponse(msg) => Some(msg.req_id),
+            Api::AvailableCurrenciesRequest(msg) => Some(msg.req_id),
+            Api::AvailableCurrenciesResponse(msg) => Some(msg.req_id),
+            Api::GetNodeInfoRequest(msg) => Some(msg.req_id),
+            Api::GetNodeInfoResponse(msg) => Some(msg.req_id),
+            Api::CreateLnurlWithdrawalRequest(msg) => Some(msg.req_id),
+            Api::CreateLnurlWithdrawalResponse(msg) => Some(msg.req_id),
+            Api::GetLnurlWithdrawalRequest(msg) => Some(msg.req_id),
+            Api::GetLnurlWithdrawalResponse(msg) => Some(msg.req_id),
+            Api::PayLnurlWithdrawalRequest(msg) => Some(msg.req_id),
+            Api::PayLnurlWithdrawalResponse(msg) => Some(msg.req_id),
+            Api::LiquidAddressRequest(msg) => Some(msg.req_id),
+            Api::LiquidAddressResponse(msg) => Some(msg.req_id),
+            Api::LiquidClaimPegInRequest(msg) => Some(msg.req_id),
+            Api::LiquidClaimPegInResponse(msg) => Some(msg.req_id),
+            Api::LiquidWithdrawalRequest(msg) => Some(msg.req_id),
+            Api::LiquidWithdrawalResponse(msg) => Some(msg.req_id),
+            Api::EcashWithdrawRequest(msg) => Some(msg.req_id),
+            Api::EcashWithdrawResponse(msg) => Some(msg.req_id),
+            Api::EcashDepositRequest(msg) => Some(msg.req_id),
+            Api::EcashDepositResponse(msg) => Some(msg.req_id),
+            Api::GetLnurlChannelRequest(msg) => Some(msg.req_id),
+            Api::GetLnurlChannelResponse(msg) => Some(msg.req_id),
+            Api::RequestChannelOpen(msg) => Some(msg.req_id),
+            Api::RequestChannelOpenResponse(msg) => Some(msg.req_id),
+            Api::QueryRouteRequest(msg) => Some(msg.req_id),
+            Api::QueryRouteResponse(msg) => Some(msg.req_id),
+            Api::GetStatusRequest(msg) => Some(msg.req_id),
+            Api::GetStatusResponse(msg) => Some(msg.req_id),
+            Api::GetReservesReport(msg) => Some(msg.req_id),
+            Api::GetReservesReportResponse(msg) => Some(msg.req_id),
+            Api::ExportStatementRequest(msg) => Some(msg.req_id),
+            Api::ExportStatementResponse(msg) => Some(msg.req_id),
+            Api::ExportMyDataRequest(msg) => Some(msg.req_id),
+            Api::ExportMyDataResponse(msg) => Some(msg.req_id),
+            Api::ExportMyDataChunk(msg) => Some(msg.req_id),
+            Api::CashOutRequest(msg) => Some(msg.req_id),
+            Api::InternalTransferRequest(msg) => Some(msg.req_id),
+            Api::InternalTransferResponse(msg) => Some(msg.req_id),
+            Api::CreateAccountRequest(msg) => Some(msg.req_id),
+            Api::RenameAccountRequest(msg) => Some(msg.req_id),
+            Api::ArchiveAccountRequest(msg) => Some(msg.req_id),
+            Api::AccountResponse(msg) => Some(msg.req_id),
+            Api::InviteAccountMemberRequest(msg) => Some(msg.req_id),
+            Api::AcceptAccountInvitationRequest(msg) => Some(msg.req_id),
+            Api::RemoveAccountMemberRequest(msg) => Some(msg.req_id),
+            Api::AccountMemberResponse(msg) => Some(msg.req_id),
+            Api::GetInterestHistory(msg) => Some(msg.req_id),
+            Api::InterestHistory(msg) => Some(msg.req_id),
+            Api::EnrollTotpRequest(msg) => Some(msg.req_id),
+            Api::ConfirmTotpRequest(msg) => Some(msg.req_id),
+            Api::DisableTotpRequest(msg) => Some(msg.req_id),
+            Api::TotpResponse(msg) => Some(msg.req_id),
+            Api::SwapOrderRequest(msg) => Some(msg.req_id),
+            Api::CancelSwapOrderRequest(msg) => Some(msg.req_id),
+            Api::GetSwapOrders(msg) => Some(msg.req_id),
+            Api::SwapOrderResponse(msg) => Some(msg.req_id),
+            Api::InvoiceExpired(_)
+            | Api::InvoiceSettled(_)
+            | Api::SubscribeRates(_)
+            | Api::RatesUpdate(_)
+            | Api::RecoveryRequested(_) => None,
+        }
+    }
 }
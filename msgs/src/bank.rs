@@ -14,9 +14,46 @@ pub struct PaymentResult {
     pub amount: Money,
     pub payment_response: PaymentResponse,
     pub error: Option<String>,
+    pub max_fee_in_sats: Decimal,
+    /// Number of attempts made to pay the invoice so far.
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub is_retryable: bool,
+    /// Fee of the bank collected upfront, refunded if the payment fails.
+    #[serde(default)]
+    pub service_fee: Option<Money>,
+    /// Set for payments pushed to a node without an invoice.
+    #[serde(default)]
+    pub keysend: Option<Keysend>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keysend {
+    pub destination: String,
+    /// Hex encoded preimage chosen by the bank.
+    pub preimage: String,
+    pub payment_hash: String,
+    pub message: Option<String>,
+}
+
+impl Keysend {
+    /// Key the payment is recorded under in the invoices table.
+    pub fn payment_request(&self) -> String {
+        format!("keysend:{}", self.payment_hash)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LndNodeHealth {
+    pub failed_node: String,
+    pub active_node: String,
+    pub is_healthy: bool,
+    pub timestamp: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Bank {
     PaymentResult(PaymentResult),
+    LndNodeHealth(LndNodeHealth),
 }
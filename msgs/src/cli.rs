@@ -1,11 +1,62 @@
-use core_types::{AccountId, Currency, UserId};
+use core_types::{Account, AccountId, AccountType, Currency, RequestId, UserId};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Cli {
     MakeTx(MakeTx),
     MakeTxResult(MakeTxResult),
+    ExportJournal(ExportJournal),
+    ExportJournalResult(ExportJournalResult),
+    Simulate(Simulate),
+    SimulationResult(SimulationResult),
+    QueryLedger(QueryLedger),
+    LedgerQueryResult(LedgerQueryResult),
+    QueryRevenue(QueryRevenue),
+    RevenueQueryResult(RevenueQueryResult),
+    GetRevenueReport(GetRevenueReport),
+    RevenueReport(RevenueReport),
+    ClosePeriod(ClosePeriod),
+    ClosePeriodResult(ClosePeriodResult),
+    ReopenPeriod(ReopenPeriod),
+    ReopenPeriodResult(ReopenPeriodResult),
+    SetWithdrawalLimit(SetWithdrawalLimit),
+    SetWithdrawalLimitResult(SetWithdrawalLimitResult),
+    ListPendingWithdrawals(ListPendingWithdrawals),
+    PendingWithdrawalsResult(PendingWithdrawalsResult),
+    ApproveWithdrawal(ApproveWithdrawal),
+    ApproveWithdrawalResult(ApproveWithdrawalResult),
+    RejectWithdrawal(RejectWithdrawal),
+    RejectWithdrawalResult(RejectWithdrawalResult),
+    FreezeUser(FreezeUser),
+    FreezeUserResult(FreezeUserResult),
+    ForceCloseAccount(ForceCloseAccount),
+    ForceCloseAccountResult(ForceCloseAccountResult),
+    ResetRateLimits(ResetRateLimits),
+    ResetRateLimitsResult(ResetRateLimitsResult),
+    SetDepositLimit(SetDepositLimit),
+    SetDepositLimitResult(SetDepositLimitResult),
+    FreezeAccount(FreezeAccount),
+    FreezeAccountResult(FreezeAccountResult),
+    UnfreezeAccount(UnfreezeAccount),
+    UnfreezeAccountResult(UnfreezeAccountResult),
+    ListFrozenAccounts(ListFrozenAccounts),
+    FrozenAccountsResult(FrozenAccountsResult),
+    ListDeadLetters(ListDeadLetters),
+    DeadLettersResult(DeadLettersResult),
+    ReplayDeadLetter(ReplayDeadLetter),
+    ReplayDeadLetterResult(ReplayDeadLetterResult),
+    ReloadConfig(ReloadConfig),
+    ReloadConfigResult(ReloadConfigResult),
+    Rebalance(Rebalance),
+    RebalanceResult(RebalanceResult),
+    ListLiquiditySwaps(ListLiquiditySwaps),
+    LiquiditySwapsResult(LiquiditySwapsResult),
+    ListPendingLnurlWithdrawals(ListPendingLnurlWithdrawals),
+    PendingLnurlWithdrawalsResult(PendingLnurlWithdrawalsResult),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,3 +74,584 @@ pub struct MakeTxResult {
     pub tx: MakeTx,
     pub result: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJournal {
+    /// Start of the exported period in millis.
+    pub from: u64,
+    /// End of the exported period in millis, defaults to now.
+    pub to: Option<u64>,
+    /// Path on the bank host the journal is written to.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJournalResult {
+    pub request: ExportJournal,
+    pub result: String,
+}
+
+/// Replays recent summary transactions under proposed settings. Settings that aren't set keep their current value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Simulate {
+    /// Number of days of transactions to replay.
+    pub days: u64,
+    pub internal_tx_fee: Option<Decimal>,
+    pub external_tx_fee: Option<Decimal>,
+    /// Largest deposit accepted per currency.
+    pub deposit_limits: HashMap<Currency, Decimal>,
+    /// Largest withdrawal accepted per currency.
+    pub withdrawal_limits: HashMap<Currency, Decimal>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub from: u64,
+    pub to: u64,
+    pub transactions: usize,
+    /// Fees collected per currency.
+    pub revenue: HashMap<Currency, Decimal>,
+    /// Fees that would have been collected per currency under the proposed settings.
+    pub simulated_revenue: HashMap<Currency, Decimal>,
+    pub rejected_deposits: usize,
+    pub rejected_withdrawals: usize,
+    /// Volume of rejected transactions per currency.
+    pub rejected_volume: HashMap<Currency, Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub request: Simulate,
+    pub report: Option<SimulationReport>,
+    pub error: Option<String>,
+}
+
+/// Internal books of the bank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LedgerBook {
+    BankLiabilities,
+    DealerAccounts,
+    InsuranceFund,
+    /// Funding received by the dealer's hedges.
+    FundingIncome,
+    /// Realized profit and loss of the dealer's hedges.
+    HedgingPnl,
+    /// Costs the operator paid out of the bank's funds.
+    OperatorExpenses,
+}
+
+impl FromStr for LedgerBook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BankLiabilities" | "bank_liabilities" => Ok(LedgerBook::BankLiabilities),
+            "DealerAccounts" | "dealer_accounts" => Ok(LedgerBook::DealerAccounts),
+            "InsuranceFund" | "insurance_fund" => Ok(LedgerBook::InsuranceFund),
+            "FundingIncome" | "funding_income" => Ok(LedgerBook::FundingIncome),
+            "HedgingPnl" | "hedging_pnl" => Ok(LedgerBook::HedgingPnl),
+            "OperatorExpenses" | "operator_expenses" => Ok(LedgerBook::OperatorExpenses),
+            _ => Err(format!("Unknown ledger book {}", s)),
+        }
+    }
+}
+
+/// Pages through the accounts of one of the internal books.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryLedger {
+    pub book: LedgerBook,
+    pub currency: Option<Currency>,
+    pub account_type: Option<AccountType>,
+    pub offset: usize,
+    pub limit: usize,
+    /// Number of most recent postings returned per account.
+    pub postings: usize,
+}
+
+/// A transaction as booked on a single account. Outbound amounts are negative.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerPosting {
+    pub txid: String,
+    pub created_at: u64,
+    pub amount: Decimal,
+    pub currency: Currency,
+    pub counterparty_uid: UserId,
+    pub tx_type: String,
+}
+
+/// Differences between the in-memory balance and the database found by the last reconciliation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerAccountDrift {
+    pub persisted_balance: Option<Decimal>,
+    pub transactions_balance: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerAccountEntry {
+    pub account: Account,
+    pub postings: Vec<LedgerPosting>,
+    /// Not set if the account matched the database at the last reconciliation.
+    pub drift: Option<LedgerAccountDrift>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerQueryResult {
+    pub request: QueryLedger,
+    /// Number of accounts matching the filters.
+    pub total: usize,
+    pub accounts: Vec<LedgerAccountEntry>,
+    /// Time of the last reconciliation with the database, not set if reconciliation is disabled.
+    pub last_reconciliation: Option<u64>,
+    pub withdrawals_halted: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RevenuePeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl FromStr for RevenuePeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Day" | "day" => Ok(RevenuePeriod::Day),
+            "Week" | "week" => Ok(RevenuePeriod::Week),
+            "Month" | "month" => Ok(RevenuePeriod::Month),
+            _ => Err(format!("Unknown revenue period {}", s)),
+        }
+    }
+}
+
+/// Queries the operator revenue aggregated by the nightly job. Days that weren't aggregated yet are missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryRevenue {
+    pub period: RevenuePeriod,
+    /// Start of the queried range in millis.
+    pub from: u64,
+    /// End of the queried range in millis, defaults to now.
+    pub to: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevenueBucket {
+    /// Start of the day, week or month in millis.
+    pub start: u64,
+    /// Revenue per source and currency.
+    pub revenue: HashMap<String, HashMap<Currency, Decimal>>,
+    /// Number of transactions the revenue was earned with.
+    pub transactions: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevenueQueryResult {
+    pub request: QueryRevenue,
+    pub buckets: Vec<RevenueBucket>,
+    pub error: Option<String>,
+}
+
+/// Reports the income of the fee accounts straight from the summary transactions, also for days the nightly
+/// revenue aggregation didn't cover yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetRevenueReport {
+    pub period: RevenuePeriod,
+    /// Start of the reported range in millis.
+    pub from: u64,
+    /// End of the reported range in millis, defaults to now.
+    pub to: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeIncomeBucket {
+    /// Start of the day, week or month in millis.
+    pub start: u64,
+    /// Collected fees less refunded fees per currency.
+    pub income: HashMap<Currency, Decimal>,
+    /// Number of postings into the fee accounts.
+    pub collected: usize,
+    /// Number of postings out of the fee accounts.
+    pub refunded: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevenueReport {
+    pub request: GetRevenueReport,
+    pub buckets: Vec<FeeIncomeBucket>,
+    /// Balances of the fee accounts per currency.
+    pub balances: HashMap<Currency, Decimal>,
+    pub error: Option<String>,
+}
+
+/// Locks all transactions up to `until`, they can't be changed and no transactions can be booked into the period
+/// until it is reopened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosePeriod {
+    /// End of the closed period in millis, it has to be in the past and after the end of the last closed period.
+    pub until: u64,
+    /// Operator closing the period, kept for the audit trail.
+    pub operator: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosingBalance {
+    pub account_id: AccountId,
+    /// Not set if the account isn't loaded by the bank.
+    pub uid: Option<UserId>,
+    pub currency: Option<Currency>,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosingReport {
+    pub close_id: i32,
+    pub closed_until: u64,
+    pub closed_at: u64,
+    pub closed_by: String,
+    pub balances: Vec<ClosingBalance>,
+    /// Sum of the closing balances per currency.
+    pub totals: HashMap<Currency, Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosePeriodResult {
+    pub request: ClosePeriod,
+    pub report: Option<ClosingReport>,
+    pub error: Option<String>,
+}
+
+/// Reopens the last closed period. The close is kept with the reason it was reopened for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReopenPeriod {
+    pub operator: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReopenPeriodResult {
+    pub request: ReopenPeriod,
+    pub reopened_close_id: Option<i32>,
+    /// End of the period that is still closed after reopening, if any.
+    pub closed_until: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Overrides the withdrawal velocity limits of a user, limits that are not set fall back to the global ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetWithdrawalLimit {
+    pub uid: UserId,
+    pub daily_sats: Option<u64>,
+    pub weekly_sats: Option<u64>,
+    /// Removes the override of the user instead.
+    pub clear: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetWithdrawalLimitResult {
+    pub request: SetWithdrawalLimit,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListPendingWithdrawals {}
+
+/// A withdrawal above the approval threshold waiting for a decision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldWithdrawal {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub amount_sats: u64,
+    pub currency: Currency,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingWithdrawalsResult {
+    pub withdrawals: Vec<HeldWithdrawal>,
+    pub error: Option<String>,
+}
+
+/// Releases a held withdrawal, its payment is made right away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApproveWithdrawal {
+    pub req_id: RequestId,
+    pub operator: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApproveWithdrawalResult {
+    pub request: ApproveWithdrawal,
+    pub error: Option<String>,
+}
+
+/// Rejects a held withdrawal, the user is told with the reason.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectWithdrawal {
+    pub req_id: RequestId,
+    pub operator: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectWithdrawalResult {
+    pub request: RejectWithdrawal,
+    pub error: Option<String>,
+}
+
+/// Freezes a user, frozen users keep receiving deposits but can't withdraw, pay other users or swap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeUser {
+    pub uid: UserId,
+    /// Unfreezes the user instead.
+    pub unfreeze: bool,
+    pub operator: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeUserResult {
+    pub request: FreezeUser,
+    pub error: Option<String>,
+}
+
+/// Archives an account even if it isn't empty. Its balance stays on it and can't be spent or topped up anymore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForceCloseAccount {
+    pub uid: UserId,
+    pub account_id: AccountId,
+    pub operator: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForceCloseAccountResult {
+    pub request: ForceCloseAccount,
+    /// Balance left on the closed account.
+    pub balance: Option<Decimal>,
+    pub error: Option<String>,
+}
+
+/// Refills the withdrawal and deposit request buckets of a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetRateLimits {
+    pub uid: UserId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetRateLimitsResult {
+    pub request: ResetRateLimits,
+    pub error: Option<String>,
+}
+
+/// Overrides the deposit limit of a user for a currency, the global limit applies again once it is cleared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetDepositLimit {
+    pub uid: UserId,
+    pub currency: Currency,
+    /// Largest balance deposits may bring an account to, clears the override if not set.
+    pub max_balance: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetDepositLimitResult {
+    pub request: SetDepositLimit,
+    pub error: Option<String>,
+}
+
+/// Why an account was frozen, stored with the freeze and its audit log entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FreezeReason {
+    Compliance,
+    Fraud,
+    LegalHold,
+    Chargeback,
+    Other,
+}
+
+impl fmt::Display for FreezeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for FreezeReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Compliance" | "compliance" => Ok(FreezeReason::Compliance),
+            "Fraud" | "fraud" => Ok(FreezeReason::Fraud),
+            "LegalHold" | "legal_hold" => Ok(FreezeReason::LegalHold),
+            "Chargeback" | "chargeback" => Ok(FreezeReason::Chargeback),
+            "Other" | "other" => Ok(FreezeReason::Other),
+            _ => Err(format!("Unknown freeze reason {}", s)),
+        }
+    }
+}
+
+/// Freezes a single account, withdrawals and swaps from it are refused until it is unfrozen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeAccount {
+    pub uid: UserId,
+    pub account_id: AccountId,
+    pub reason: FreezeReason,
+    pub operator: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeAccountResult {
+    pub request: FreezeAccount,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnfreezeAccount {
+    pub account_id: AccountId,
+    pub operator: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnfreezeAccountResult {
+    pub request: UnfreezeAccount,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListFrozenAccounts {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrozenAccountInfo {
+    pub uid: UserId,
+    pub account_id: AccountId,
+    pub reason: FreezeReason,
+    pub note: Option<String>,
+    pub frozen_by: String,
+    /// Time of the freeze in millis.
+    pub frozen_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrozenAccountsResult {
+    pub accounts: Vec<FrozenAccountInfo>,
+    pub error: Option<String>,
+}
+
+/// Lists the messages the bank couldn't decode or had no handler for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListDeadLetters {
+    pub limit: usize,
+    pub include_replayed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterInfo {
+    pub id: i32,
+    pub source: String,
+    pub reason: String,
+    /// The message if the payload can be decoded by now.
+    pub message: Option<String>,
+    pub size: usize,
+    /// Time the message was received in millis.
+    pub received_at: u64,
+    pub replayed_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLettersResult {
+    pub dead_letters: Vec<DeadLetterInfo>,
+    pub error: Option<String>,
+}
+
+/// Processes a dead letter again as if it was just received, each dead letter is replayed once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayDeadLetter {
+    pub id: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayDeadLetterResult {
+    pub request: ReplayDeadLetter,
+    pub error: Option<String>,
+}
+
+/// Reads the settings file again and applies the fees, limits and notices in it without a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadConfig {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadConfigResult {
+    /// Settings that were applied.
+    pub reloaded: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Pays the node itself out of one of its channels and back in through another one, moving liquidity between
+/// the two channels. The routing fee is booked on the operator expenses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rebalance {
+    /// Channel the payment leaves through, gets inbound liquidity.
+    pub outgoing_chan_id: u64,
+    /// Pubkey of the peer of the channel the payment comes back through, gets outbound liquidity.
+    pub last_hop_pubkey: String,
+    pub amount_sats: u64,
+    /// Routing fee the payment may pay at most.
+    pub max_fee_sats: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebalanceResult {
+    pub request: Rebalance,
+    /// Routing fee paid in msat.
+    pub fee_msat: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Lists the most recent swaps the bank started to manage the liquidity of the node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListLiquiditySwaps {
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquiditySwapInfo {
+    pub provider: String,
+    pub swap_id: String,
+    /// `Out` moves channel funds on-chain, `In` moves on-chain funds into the channels.
+    pub direction: String,
+    pub amount_sats: u64,
+    pub state: String,
+    pub cost_sats: Option<u64>,
+    /// Time the swap was started in millis.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquiditySwapsResult {
+    pub swaps: Vec<LiquiditySwapInfo>,
+    pub error: Option<String>,
+}
+
+/// Lists the LNURL withdrawals whose QR codes can still be scanned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListPendingLnurlWithdrawals {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingLnurlWithdrawal {
+    pub req_id: RequestId,
+    pub uid: UserId,
+    pub amount: Decimal,
+    pub currency: Currency,
+    /// Time the withdrawal was created in millis.
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingLnurlWithdrawalsResult {
+    pub withdrawals: Vec<PendingLnurlWithdrawal>,
+}
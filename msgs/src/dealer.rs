@@ -1,3 +1,4 @@
+use core_types::currency_registry::CurrencyInfo;
 use core_types::*;
 use rust_decimal::prelude::Decimal;
 use std::collections::HashMap;
@@ -25,6 +26,8 @@ pub struct BankState {
     pub total_exposures: HashMap<Currency, Decimal>,
     pub fiat_exposures: HashMap<AccountId, Account>,
     pub insurance_fund_account: Account,
+    /// Last published proof of reserves.
+    pub reserves: Option<crate::api::ReservesReport>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +48,9 @@ pub struct CreateInvoiceResponse {
     pub req_id: RequestId,
     pub amount: u64,
     pub payment_request: String,
+    /// Requests that were merged into this invoice when settlements are batched.
+    #[serde(default)]
+    pub netted_req_ids: Vec<RequestId>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
@@ -58,6 +64,9 @@ pub struct DealerHealth {
     pub status: HealthStatus,
     pub available_currencies: Vec<Currency>,
     pub timestamp: u64,
+    /// Currencies the dealer knows about, so the bank learns of newly added synthetics.
+    #[serde(default)]
+    pub currencies: Vec<CurrencyInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,6 +93,70 @@ pub struct FiatDepositResponse {
     pub fees: Option<Money>,
 }
 
+/// Price of one BTC in a fiat currency as users get it, spread included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPrice {
+    pub currency: Currency,
+    /// Received when swapping BTC to the currency.
+    pub bid: Decimal,
+    /// Paid when swapping the currency to BTC.
+    pub ask: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPrices {
+    pub prices: Vec<MarketPrice>,
+    pub timestamp: u64,
+}
+
+/// Funding the hedge of a currency received since the last report, valued in the currency.
+/// Negative if funding was paid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundingIncome {
+    pub currency: Currency,
+    pub amount: Decimal,
+    pub timestamp: u64,
+}
+
+/// Profit the hedge of a currency realized since the last report, valued in the currency.
+/// Negative if the hedge realized a loss.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HedgingPnl {
+    pub currency: Currency,
+    pub amount: Decimal,
+    pub timestamp: u64,
+}
+
+/// What is left of an open limit swap order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenSwapOrder {
+    pub order_id: Uuid,
+    pub uid: UserId,
+    pub from: Currency,
+    pub to: Currency,
+    pub remaining: Decimal,
+    pub limit_rate: Decimal,
+}
+
+/// All open limit swap orders, sent whenever they change and with every bank state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapOrders {
+    pub orders: Vec<OpenSwapOrder>,
+}
+
+/// Part of a limit swap order the dealer swaps at a rate at least as good as the limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapOrderFill {
+    pub fill_id: RequestId,
+    pub order_id: Uuid,
+    pub uid: UserId,
+    pub amount: Money,
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: Rate,
+    pub fees: Option<Money>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Dealer {
     BankStateRequest(BankStateRequest),
@@ -97,4 +170,9 @@ pub enum Dealer {
     CreateInvoiceResponse(CreateInvoiceResponse),
     FiatDepositRequest(FiatDepositRequest),
     FiatDepositResponse(FiatDepositResponse),
+    MarketPrices(MarketPrices),
+    FundingIncome(FundingIncome),
+    HedgingPnl(HedgingPnl),
+    SwapOrders(SwapOrders),
+    SwapOrderFill(SwapOrderFill),
 }
@@ -1,3 +1,4 @@
+use core_types::RequestId;
 use rust_decimal::prelude::*;
 
 use serde::{Deserialize, Serialize};
@@ -7,6 +8,7 @@ pub mod bank;
 pub mod cli;
 pub mod dealer;
 pub mod kollider_client;
+pub mod wire;
 
 use api::*;
 use bank::*;
@@ -17,6 +19,11 @@ use kollider_client::*;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Deposit {
     pub payment_request: String,
+    /// Indices of the invoice at the node, the invoice subscription resumes after them.
+    #[serde(default)]
+    pub add_index: u64,
+    #[serde(default)]
+    pub settle_index: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +42,31 @@ pub enum Message {
     Cli(Cli),
 }
 
+impl Message {
+    /// Id the handling of the message is traced with across services, messages that aren't part of a
+    /// request have none.
+    pub fn trace_id(&self) -> Option<RequestId> {
+        match self {
+            Message::Api(msg) => msg.req_id(),
+            Message::Bank(Bank::PaymentResult(result)) => Some(result.payment_response.req_id),
+            Message::Dealer(Dealer::BankStateRequest(msg)) => Some(msg.req_id),
+            Message::Dealer(Dealer::BankStateResponse(msg)) => Some(msg.req_id),
+            Message::Dealer(Dealer::PayInvoice(msg)) | Message::Dealer(Dealer::PayInsuranceInvoice(msg)) => {
+                Some(msg.req_id)
+            }
+            Message::Dealer(Dealer::CreateInvoiceRequest(msg))
+            | Message::Dealer(Dealer::CreateInsuranceInvoiceRequest(msg)) => Some(msg.req_id),
+            Message::Dealer(Dealer::CreateInvoiceResponse(msg)) => Some(msg.req_id),
+            Message::Dealer(Dealer::FiatDepositRequest(msg)) => Some(msg.req_id),
+            Message::Dealer(Dealer::FiatDepositResponse(msg)) => Some(msg.req_id),
+            Message::Dealer(Dealer::SwapOrderFill(fill)) => Some(fill.fill_id),
+            Message::Cli(Cli::ApproveWithdrawal(msg)) => Some(msg.req_id),
+            Message::Cli(Cli::RejectWithdrawal(msg)) => Some(msg.req_id),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
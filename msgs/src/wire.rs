@@ -0,0 +1,180 @@
+//! Frames messages are exchanged in between services.
+//!
+//! A frame starts with a marker and the schema version of its writer, followed by the message as json. Unlike
+//! bincode, json tolerates removed fields and added fields that are an `Option` or marked `#[serde(default)]`,
+//! so services can be upgraded one at a time. Any other added field makes frames of older writers fail to
+//! decode, such changes need a new schema version. Messages of variants the reader doesn't know yet fail to
+//! decode as well, they end up as dead letters. Frames without the marker are decoded as the plain bincode
+//! written before frames were versioned.
+
+use std::fmt;
+
+use crate::Message;
+
+/// Marks versioned frames, bincode encoded messages always start with a small variant index.
+const MARKER: [u8; 2] = [0xfe, 0x1d];
+/// Version of the schema of `Message` written by this build, bumped on changes older readers can't decode.
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum WireError {
+    Serialization(String),
+    /// The frame was written by a newer schema and holds a message this build doesn't know.
+    UnknownMessage {
+        version: u16,
+        reason: String,
+    },
+    Malformed(String),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Serialization(reason) => write!(f, "Failed to serialize message: {}", reason),
+            WireError::UnknownMessage { version, reason } => {
+                write!(f, "Unknown message of schema version {}: {}", version, reason)
+            }
+            WireError::Malformed(reason) => write!(f, "Malformed frame: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+pub fn encode(message: &Message) -> Result<Vec<u8>, WireError> {
+    let mut frame = MARKER.to_vec();
+    frame.extend_from_slice(&SCHEMA_VERSION.to_be_bytes());
+    serde_json::to_writer(&mut frame, message).map_err(|err| WireError::Serialization(err.to_string()))?;
+    Ok(frame)
+}
+
+pub fn decode(frame: &[u8]) -> Result<Message, WireError> {
+    match frame {
+        [m0, m1, v0, v1, payload @ ..] if [*m0, *m1] == MARKER => {
+            let version = u16::from_be_bytes([*v0, *v1]);
+            serde_json::from_slice(payload).map_err(|err| {
+                if version > SCHEMA_VERSION {
+                    WireError::UnknownMessage {
+                        version,
+                        reason: err.to_string(),
+                    }
+                } else {
+                    WireError::Malformed(err.to_string())
+                }
+            })
+        }
+        _ => bincode::deserialize(frame).map_err(|err| WireError::Malformed(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Api, GetBalances};
+    use core_types::Currency;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_decodes_versioned_and_legacy_frames() {
+        let req_id = Uuid::new_v4();
+        let message = Message::Api(Api::GetBalances(GetBalances { req_id, uid: 1 }));
+
+        let frame = encode(&message).unwrap();
+        assert_eq!(decode(&frame).unwrap().trace_id(), Some(req_id));
+
+        let legacy = bincode::serialize(&message).unwrap();
+        assert_eq!(decode(&legacy).unwrap().trace_id(), Some(req_id));
+    }
+
+    #[test]
+    fn test_decodes_frames_of_older_writers() {
+        let mut frame = MARKER.to_vec();
+        frame.extend_from_slice(&SCHEMA_VERSION.to_be_bytes());
+        // Written before the optional fields and the requoted flag were added to payment requests.
+        let req_id = Uuid::new_v4();
+        let payload = format!(
+            r#"{{"Api":{{"PaymentRequest":{{"req_id":"{}","uid":1,"payment_request":"lnbc1","currency":"BTC","receipient":null,"amount":null,"rate":null,"fees":null}}}}}}"#,
+            req_id
+        );
+        frame.extend_from_slice(payload.as_bytes());
+
+        match decode(&frame).unwrap() {
+            Message::Api(Api::PaymentRequest(request)) => {
+                assert_eq!(request.req_id, req_id);
+                assert_eq!(request.currency, Currency::BTC);
+                assert!(!request.requoted);
+                assert!(request.origin.is_none());
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_currencies_keep_their_legacy_layout() {
+        // The enum currencies were before they could be registered.
+        #[derive(serde::Serialize)]
+        #[allow(clippy::upper_case_acronyms)]
+        enum LegacyCurrency {
+            USD,
+            GBP,
+            EUR,
+            BTC,
+        }
+
+        let legacy = [
+            (LegacyCurrency::USD, Currency::USD),
+            (LegacyCurrency::GBP, Currency::GBP),
+            (LegacyCurrency::EUR, Currency::EUR),
+            (LegacyCurrency::BTC, Currency::BTC),
+        ];
+        for (legacy, currency) in legacy {
+            let bytes = bincode::serialize(&legacy).unwrap();
+            assert_eq!(bincode::serialize(&currency).unwrap(), bytes);
+            assert_eq!(bincode::deserialize::<Currency>(&bytes).unwrap(), currency);
+        }
+
+        let bytes = bincode::serialize(&Currency::USDT).unwrap();
+        assert_eq!(bincode::deserialize::<Currency>(&bytes).unwrap(), Currency::USDT);
+    }
+
+    #[test]
+    fn test_rejects_unregistered_currencies() {
+        let mut frame = MARKER.to_vec();
+        frame.extend_from_slice(&SCHEMA_VERSION.to_be_bytes());
+        let payload = format!(
+            r#"{{"Api":{{"PaymentRequest":{{"req_id":"{}","uid":1,"payment_request":"lnbc1","currency":"XYZ"}}}}}}"#,
+            Uuid::new_v4()
+        );
+        frame.extend_from_slice(payload.as_bytes());
+        assert!(matches!(decode(&frame), Err(WireError::Malformed(_))));
+
+        let unregistered = Currency::new("XYZ").unwrap();
+        let bytes = bincode::serialize(&unregistered).unwrap();
+        assert!(bincode::deserialize::<Currency>(&bytes).is_err());
+        assert!(serde_json::from_str::<Currency>(r#""XYZ""#).is_err());
+        assert_eq!(serde_json::from_str::<Currency>(r#""usd""#).unwrap(), Currency::USD);
+    }
+
+    #[test]
+    fn test_decodes_frames_of_newer_schemas() {
+        let newer_frame = |payload: &str| {
+            let mut frame = MARKER.to_vec();
+            frame.extend_from_slice(&(SCHEMA_VERSION + 1).to_be_bytes());
+            frame.extend_from_slice(payload.as_bytes());
+            frame
+        };
+
+        let req_id = Uuid::new_v4();
+        let added_field = format!(
+            r#"{{"Api":{{"GetBalances":{{"req_id":"{}","uid":1,"added":true}}}}}}"#,
+            req_id
+        );
+        assert_eq!(decode(&newer_frame(&added_field)).unwrap().trace_id(), Some(req_id));
+
+        let added_variant = r#"{"Api":{"AddedRequest":{}}}"#;
+        assert!(matches!(
+            decode(&newer_frame(added_variant)),
+            Err(WireError::UnknownMessage { version, .. }) if version == SCHEMA_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use diesel::{r2d2::ConnectionManager, PgConnection};
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+use tokio::sync::mpsc;
+
+use core_types::DbPool;
+use models::notification_preferences::NotificationPreference;
+use utils::xlogging::{init_log, LoggingSettings};
+use utils::xzmq::ZmqSocket;
+
+pub mod senders;
+pub mod templates;
+
+use senders::*;
+use templates::*;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NotificationSettings {
+    pub psql_url: String,
+    /// The bank's `bank_zmq_publish_address`, every message it publishes is received.
+    pub notifications_zmq_subscribe_address: String,
+    pub logging_settings: LoggingSettings,
+    /// No emails are sent if not set.
+    #[serde(default)]
+    pub notification_email: Option<EmailSettings>,
+    /// No push messages are sent to Android devices if not set.
+    #[serde(default)]
+    pub notification_fcm: Option<FcmSettings>,
+    /// No push messages are sent to Apple devices if not set.
+    #[serde(default)]
+    pub notification_apns: Option<ApnsSettings>,
+    /// Replaces the default templates of the events.
+    #[serde(default)]
+    pub notification_templates: HashMap<String, Template>,
+}
+
+struct Notifier {
+    logger: Logger,
+    pool: DbPool,
+    email: Option<Arc<EmailSender>>,
+    push: PushSender,
+    templates: HashMap<String, Template>,
+}
+
+impl Notifier {
+    fn new(settings: NotificationSettings, logger: Logger) -> Self {
+        let pool = r2d2::Pool::builder().build_unchecked(ConnectionManager::<PgConnection>::new(settings.psql_url));
+        let email = settings.notification_email.as_ref().map(|email| {
+            Arc::new(EmailSender::new(email).unwrap_or_else(|err| panic!("Invalid email settings: {}", err)))
+        });
+        let push = PushSender::new(settings.notification_fcm, settings.notification_apns)
+            .unwrap_or_else(|err| panic!("Invalid push settings: {}", err));
+        let mut templates = default_templates();
+        templates.extend(settings.notification_templates);
+        Self {
+            logger,
+            pool,
+            email,
+            push,
+            templates,
+        }
+    }
+
+    fn preference(&self, uid: i32) -> Option<NotificationPreference> {
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return None;
+            }
+        };
+        // Users without preferences have no address to notify.
+        NotificationPreference::get_by_uid(&conn, uid).ok()
+    }
+
+    /// Sends the notification to every address of the user that is subscribed to its category.
+    /// Failed deliveries are logged and not retried.
+    async fn notify(self: Arc<Self>, notification: Notification) {
+        let preference = match self.preference(notification.uid as i32) {
+            Some(preference) => preference,
+            None => return,
+        };
+        let subscribed = match notification.category {
+            Category::Deposits => preference.deposits,
+            Category::Withdrawals => preference.withdrawals,
+            Category::FailedPayments => preference.failed_payments,
+            Category::Security => preference.security,
+        };
+        if !subscribed {
+            return;
+        }
+        let template = match self.templates.get(notification.event) {
+            Some(template) => template,
+            None => return,
+        };
+        let (subject, body) = render(template, &notification.fields);
+
+        if let (Some(sender), Some(to)) = (self.email.clone(), preference.email.clone()) {
+            let (subject, body) = (subject.clone(), body.clone());
+            // lettre's smtp transport is blocking.
+            match tokio::task::spawn_blocking(move || sender.send(&to, &subject, &body)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => slog::error!(
+                    self.logger,
+                    "Failed to email {} to user {}: {}",
+                    notification.event,
+                    notification.uid,
+                    err
+                ),
+                Err(err) => slog::error!(self.logger, "Email task failed: {:?}", err),
+            }
+        }
+
+        if let (Some(platform), Some(token)) = (&preference.push_platform, &preference.push_token) {
+            if let Err(err) = self.push.send(platform, token, &subject, &body).await {
+                slog::error!(
+                    self.logger,
+                    "Failed to push {} to user {}: {}",
+                    notification.event,
+                    notification.uid,
+                    err
+                );
+            }
+        }
+    }
+}
+
+pub async fn start(settings: NotificationSettings, subscriber: ZmqSocket) {
+    let logger = init_log(&settings.logging_settings);
+    let notifier = Arc::new(Notifier::new(settings, logger.clone()));
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Messages of every api instance are received, so every event is notified once.
+    std::thread::spawn(move || {
+        while let Ok(frames) = subscriber.recv_multipart(0x00) {
+            let notification = match frames.last().map(|frame| msgs::wire::decode(frame)) {
+                Some(Ok(message)) => notification(&message),
+                _ => None,
+            };
+            if let Some(notification) = notification {
+                if tx.send(notification).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    slog::info!(logger, "Notifying users of the bank's events.");
+    while let Some(notification) = rx.recv().await {
+        tokio::task::spawn(notifier.clone().notify(notification));
+    }
+}
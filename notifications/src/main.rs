@@ -0,0 +1,14 @@
+use utils::xzmq::SocketContext;
+
+use notifications::{start, NotificationSettings};
+
+#[tokio::main]
+async fn main() {
+    utils::config::register_currencies_from_env().expect("Failed to register currencies.");
+    let settings = utils::config::get_config_from_env::<NotificationSettings>().expect("Failed to load settings.");
+
+    let context = SocketContext::new();
+    let subscriber = context.create_subscriber(&settings.notifications_zmq_subscribe_address);
+
+    start(settings, subscriber).await;
+}
@@ -0,0 +1,179 @@
+use std::sync::Mutex;
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message as Email, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const FCM_URL: &str = "https://fcm.googleapis.com/fcm/send";
+const APNS_URL: &str = "https://api.push.apple.com";
+const APNS_SANDBOX_URL: &str = "https://api.sandbox.push.apple.com";
+/// Apple rejects provider tokens older than an hour and throttles renewing them more than every 20 minutes.
+const APNS_TOKEN_LIFETIME_SECS: u64 = 45 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailSettings {
+    /// SMTP relay emails are submitted to over TLS.
+    pub smtp_host: String,
+    #[serde(default)]
+    pub smtp_port: Option<u16>,
+    pub smtp_user: String,
+    pub smtp_password: String,
+    /// Sender of the emails, e.g. `lndhubx <noreply@example.com>`.
+    pub from: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FcmSettings {
+    pub server_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApnsSettings {
+    /// Path of the .p8 signing key of the Apple developer account.
+    pub key_path: String,
+    pub key_id: String,
+    pub team_id: String,
+    /// Bundle id of the app.
+    pub topic: String,
+    /// Sends to Apple's sandbox if set, for development builds of the app.
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+pub struct EmailSender {
+    from: String,
+    transport: SmtpTransport,
+}
+
+impl EmailSender {
+    pub fn new(settings: &EmailSettings) -> Result<Self, String> {
+        let mut transport = SmtpTransport::relay(&settings.smtp_host).map_err(|err| err.to_string())?;
+        if let Some(port) = settings.smtp_port {
+            transport = transport.port(port);
+        }
+        let transport = transport
+            .credentials(Credentials::new(
+                settings.smtp_user.clone(),
+                settings.smtp_password.clone(),
+            ))
+            .build();
+        Ok(Self {
+            from: settings.from.clone(),
+            transport,
+        })
+    }
+
+    /// Blocks until the relay accepted the email.
+    pub fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        let email = Email::builder()
+            .from(self.from.parse().map_err(|err| format!("invalid sender: {:?}", err))?)
+            .to(to.parse().map_err(|err| format!("invalid recipient: {:?}", err))?)
+            .subject(subject)
+            .body(body.to_string())
+            .map_err(|err| err.to_string())?;
+        self.transport.send(&email).map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct ApnsClaims {
+    iss: String,
+    iat: u64,
+}
+
+pub struct PushSender {
+    client: reqwest::Client,
+    fcm: Option<FcmSettings>,
+    apns: Option<(ApnsSettings, EncodingKey)>,
+    /// Provider token and when it was issued, in seconds.
+    apns_token: Mutex<Option<(String, u64)>>,
+}
+
+impl PushSender {
+    pub fn new(fcm: Option<FcmSettings>, apns: Option<ApnsSettings>) -> Result<Self, String> {
+        let apns = match apns {
+            Some(settings) => {
+                let pem = std::fs::read(&settings.key_path).map_err(|err| err.to_string())?;
+                let key = EncodingKey::from_ec_pem(&pem).map_err(|err| err.to_string())?;
+                Some((settings, key))
+            }
+            None => None,
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            fcm,
+            apns,
+            apns_token: Mutex::new(None),
+        })
+    }
+
+    pub async fn send(&self, platform: &str, token: &str, title: &str, body: &str) -> Result<(), String> {
+        match platform {
+            "fcm" => self.send_fcm(token, title, body).await,
+            "apns" => self.send_apns(token, title, body).await,
+            _ => Err(format!("unknown push platform {}", platform)),
+        }
+    }
+
+    async fn send_fcm(&self, token: &str, title: &str, body: &str) -> Result<(), String> {
+        let settings = self.fcm.as_ref().ok_or("fcm is not configured")?;
+        let response = self
+            .client
+            .post(FCM_URL)
+            .header("Authorization", format!("key={}", settings.server_key))
+            .json(&json!({
+                "to": token,
+                "notification": { "title": title, "body": body },
+            }))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("fcm responded with {}", response.status()));
+        }
+        Ok(())
+    }
+
+    fn apns_token(&self, settings: &ApnsSettings, key: &EncodingKey) -> Result<String, String> {
+        let now = utils::time::time_now() / 1000;
+        let mut cached = self.apns_token.lock().map_err(|_| "apns token lock poisoned")?;
+        if let Some((token, issued_at)) = cached.as_ref() {
+            if now < issued_at + APNS_TOKEN_LIFETIME_SECS {
+                return Ok(token.clone());
+            }
+        }
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(settings.key_id.clone());
+        let claims = ApnsClaims {
+            iss: settings.team_id.clone(),
+            iat: now,
+        };
+        let token = encode(&header, &claims, key).map_err(|err| err.to_string())?;
+        *cached = Some((token.clone(), now));
+        Ok(token)
+    }
+
+    async fn send_apns(&self, device_token: &str, title: &str, body: &str) -> Result<(), String> {
+        let (settings, key) = self.apns.as_ref().ok_or("apns is not configured")?;
+        let token = self.apns_token(settings, key)?;
+        let url = if settings.sandbox { APNS_SANDBOX_URL } else { APNS_URL };
+        let response = self
+            .client
+            .post(format!("{}/3/device/{}", url, device_token))
+            .header("authorization", format!("bearer {}", token))
+            .header("apns-topic", settings.topic.clone())
+            .header("apns-push-type", "alert")
+            .json(&json!({
+                "aps": { "alert": { "title": title, "body": body } },
+            }))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("apns responded with {}", response.status()));
+        }
+        Ok(())
+    }
+}
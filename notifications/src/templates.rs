@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use core_types::{Money, UserId};
+use msgs::api::*;
+use msgs::*;
+
+/// Preferences users turn notifications on and off by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Deposits,
+    Withdrawals,
+    FailedPayments,
+    Security,
+}
+
+/// Subject and body of a notification, `{name}` is replaced with the field of the event of that name.
+/// Push messages use the subject as title.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Template {
+    pub subject: String,
+    pub body: String,
+}
+
+impl Template {
+    fn new(subject: &str, body: &str) -> Self {
+        Self {
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// An event a user is notified about.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub uid: UserId,
+    pub category: Category,
+    /// Name of the template the notification is rendered with.
+    pub event: &'static str,
+    pub fields: HashMap<&'static str, String>,
+}
+
+pub const DEPOSIT: &str = "deposit";
+pub const WITHDRAWAL: &str = "withdrawal";
+pub const PAYMENT_FAILED: &str = "payment_failed";
+pub const TOTP_ENABLED: &str = "totp_enabled";
+pub const TOTP_DISABLED: &str = "totp_disabled";
+pub const TOTP_CODE_REJECTED: &str = "totp_code_rejected";
+pub const RECOVERY_REQUESTED: &str = "recovery_requested";
+
+fn format_money(money: &Money) -> String {
+    format!("{} {}", money.round_for_display().value.normalize(), money.currency)
+}
+
+/// Returns the notification of a message the bank published, if users are notified about it.
+pub fn notification(message: &Message) -> Option<Notification> {
+    let (uid, category, event, fields) = match message {
+        Message::Api(Api::InvoiceSettled(settled)) => (
+            settled.uid,
+            Category::Deposits,
+            DEPOSIT,
+            vec![("amount", format_money(&settled.amount))],
+        ),
+        // A scheduled retry or a withdrawal held for approval or delayed is not the final outcome of a payment.
+        Message::Api(Api::PaymentResponse(PaymentResponse {
+            error:
+                Some(
+                    PaymentResponseError::PaymentRetryScheduled
+                    | PaymentResponseError::PendingApproval
+                    | PaymentResponseError::WithdrawalDelayed,
+                ),
+            ..
+        })) => return None,
+        Message::Api(Api::PaymentResponse(PaymentResponse {
+            uid,
+            error: Some(PaymentResponseError::InvalidTotpCode),
+            ..
+        })) => (*uid, Category::Security, TOTP_CODE_REJECTED, vec![]),
+        Message::Api(Api::PaymentResponse(response)) => {
+            let amount = response.amount.as_ref().map(format_money).unwrap_or_default();
+            if response.success {
+                let fees = response.fees.as_ref().map(format_money).unwrap_or_default();
+                (
+                    response.uid,
+                    Category::Withdrawals,
+                    WITHDRAWAL,
+                    vec![("amount", amount), ("fees", fees)],
+                )
+            } else {
+                let reason = response
+                    .error
+                    .as_ref()
+                    .map(|error| format!("{:?}", error))
+                    .unwrap_or_default();
+                (
+                    response.uid,
+                    Category::FailedPayments,
+                    PAYMENT_FAILED,
+                    vec![("amount", amount), ("reason", reason)],
+                )
+            }
+        }
+        // Enrollments return the secret but only enable 2fa once confirmed.
+        Message::Api(Api::TotpResponse(TotpResponse {
+            uid,
+            enabled,
+            secret: None,
+            error: None,
+            ..
+        })) => (
+            *uid,
+            Category::Security,
+            if *enabled { TOTP_ENABLED } else { TOTP_DISABLED },
+            vec![],
+        ),
+        Message::Api(Api::RecoveryRequested(requested)) => (
+            requested.uid,
+            Category::Security,
+            RECOVERY_REQUESTED,
+            vec![("recovery_id", requested.recovery_id.to_string())],
+        ),
+        _ => return None,
+    };
+    Some(Notification {
+        uid,
+        category,
+        event,
+        fields: fields.into_iter().collect(),
+    })
+}
+
+/// Templates notifications are rendered with if the operator didn't configure their own.
+pub fn default_templates() -> HashMap<String, Template> {
+    [
+        (DEPOSIT, Template::new("Deposit received", "You received {amount}.")),
+        (
+            WITHDRAWAL,
+            Template::new("Payment sent", "You sent {amount}, the fees were {fees}."),
+        ),
+        (
+            PAYMENT_FAILED,
+            Template::new("Payment failed", "Your payment of {amount} failed: {reason}."),
+        ),
+        (
+            TOTP_ENABLED,
+            Template::new(
+                "Two-factor authentication enabled",
+                "Two-factor authentication was enabled for your account.",
+            ),
+        ),
+        (
+            TOTP_DISABLED,
+            Template::new(
+                "Two-factor authentication disabled",
+                "Two-factor authentication was disabled for your account. If this wasn't you, contact support.",
+            ),
+        ),
+        (
+            TOTP_CODE_REJECTED,
+            Template::new(
+                "Wrong authentication code",
+                "A payment was rejected because of a wrong authentication code. If this wasn't you, change your \
+                 password.",
+            ),
+        ),
+        (
+            RECOVERY_REQUESTED,
+            Template::new(
+                "Account recovery requested",
+                "Your guardians were asked to recover your account with recovery {recovery_id}. If this wasn't you, \
+                 cancel it.",
+            ),
+        ),
+    ]
+    .into_iter()
+    .map(|(event, template)| (event.to_string(), template))
+    .collect()
+}
+
+/// Returns subject and body of the notification. Placeholders of unknown fields are left as they are.
+pub fn render(template: &Template, fields: &HashMap<&'static str, String>) -> (String, String) {
+    let fill = |text: &str| {
+        fields.iter().fold(text.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+    };
+    (fill(&template.subject), fill(&template.body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_fields() {
+        let template = Template::new("Payment of {amount}", "{amount} failed: {reason} {unknown}");
+        let fields = [
+            ("amount", "0.001 BTC".to_string()),
+            ("reason", "InsufficientFunds".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            render(&template, &fields),
+            (
+                "Payment of 0.001 BTC".to_string(),
+                "0.001 BTC failed: InsufficientFunds {unknown}".to_string()
+            )
+        );
+    }
+}
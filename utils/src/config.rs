@@ -13,3 +13,11 @@ where
     configuration.merge(config::File::with_name(&file_path))?;
     configuration.try_into()
 }
+
+/// Registers the currencies the settings of a service add. Has to run before the rest of the settings are loaded,
+/// they may refer to those currencies.
+pub fn register_currencies_from_env() -> Result<(), String> {
+    let settings = get_config_from_env::<core_types::currency_registry::CurrencySettings>()
+        .map_err(|err| format!("Failed to load currencies: {:?}", err))?;
+    core_types::currency_registry::register_all(&settings.currencies)
+}
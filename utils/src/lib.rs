@@ -1,6 +1,11 @@
 pub mod config;
 pub mod lnurl;
+pub mod metrics;
+pub mod preflight;
+pub mod signing;
 pub mod slack;
+pub mod telemetry;
+pub mod txid;
 pub mod xlogging;
 pub mod xzmq;
 
@@ -40,6 +45,7 @@ pub mod currencies {
 
     pub const SATS_IN_BITCOIN: u32 = 100000000;
     pub const SATS_DECIMALS: u32 = 8;
+    pub const MSATS_DECIMALS: u32 = 11;
 
     pub fn get_base_currency_from_symbol(symbol: Symbol) -> Result<Currency, String> {
         let base = symbol[3..6].to_string();
@@ -0,0 +1,251 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Buckets in seconds for the latency of lightning payments.
+pub const PAYMENT_LATENCY_BUCKETS: [f64; 9] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Holds the bits of an f64 so it can be set without a lock.
+#[derive(Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+pub struct Histogram {
+    bounds: Vec<f64>,
+    // observations per bucket, not cumulative
+    buckets: Vec<AtomicU64>,
+    sum: Mutex<f64>,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum: Mutex::new(0.0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        if let Some(index) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        *self.sum.lock().unwrap() += value;
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+impl Metric {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    help: String,
+    labels: Vec<(String, String)>,
+    metric: Metric,
+}
+
+/// Metrics of a process, rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Registry {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counter(&self, name: &str, help: &str) -> Arc<Counter> {
+        match self.register(name, help, &[], || Metric::Counter(Arc::new(Counter::default()))) {
+            Metric::Counter(counter) => counter,
+            _ => panic!("Metric {} is not a counter", name),
+        }
+    }
+
+    pub fn gauge(&self, name: &str, help: &str) -> Arc<Gauge> {
+        self.labeled_gauge(name, help, &[])
+    }
+
+    /// Gauges of the same name are told apart by their labels, the same labels return the same gauge.
+    pub fn labeled_gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Gauge> {
+        match self.register(name, help, labels, || Metric::Gauge(Arc::new(Gauge::default()))) {
+            Metric::Gauge(gauge) => gauge,
+            _ => panic!("Metric {} is not a gauge", name),
+        }
+    }
+
+    pub fn histogram(&self, name: &str, help: &str, bounds: &[f64]) -> Arc<Histogram> {
+        match self.register(name, help, &[], || Metric::Histogram(Arc::new(Histogram::new(bounds)))) {
+            Metric::Histogram(histogram) => histogram,
+            _ => panic!("Metric {} is not a histogram", name),
+        }
+    }
+
+    fn register(&self, name: &str, help: &str, labels: &[(&str, &str)], make: impl FnOnce() -> Metric) -> Metric {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries
+            .iter()
+            .find(|entry| entry.name == name && entry.labels == labels)
+        {
+            return entry.metric.clone();
+        }
+        let metric = make();
+        entries.push(Entry {
+            name: name.to_string(),
+            help: help.to_string(),
+            labels,
+            metric: metric.clone(),
+        });
+        metric
+    }
+
+    pub fn render(&self) -> String {
+        let entries = self.entries.lock().unwrap();
+        let mut out = String::new();
+        let mut described = Vec::<&str>::new();
+        for entry in entries.iter() {
+            if !described.contains(&entry.name.as_str()) {
+                described.push(&entry.name);
+                let _ = writeln!(out, "# HELP {} {}", entry.name, entry.help);
+                let _ = writeln!(out, "# TYPE {} {}", entry.name, entry.metric.type_name());
+            }
+            let labels = format_labels(&entry.labels, None);
+            match &entry.metric {
+                Metric::Counter(counter) => {
+                    let _ = writeln!(out, "{}{} {}", entry.name, labels, counter.get());
+                }
+                Metric::Gauge(gauge) => {
+                    let _ = writeln!(out, "{}{} {}", entry.name, labels, gauge.get());
+                }
+                Metric::Histogram(histogram) => {
+                    let mut cumulative = 0;
+                    for (bound, bucket) in histogram.bounds.iter().zip(histogram.buckets.iter()) {
+                        cumulative += bucket.load(Ordering::Relaxed);
+                        let le = format_labels(&entry.labels, Some(bound.to_string()));
+                        let _ = writeln!(out, "{}_bucket{} {}", entry.name, le, cumulative);
+                    }
+                    let count = histogram.count.load(Ordering::Relaxed);
+                    let le = format_labels(&entry.labels, Some("+Inf".to_string()));
+                    let _ = writeln!(out, "{}_bucket{} {}", entry.name, le, count);
+                    let _ = writeln!(out, "{}_sum{} {}", entry.name, labels, histogram.sum.lock().unwrap());
+                    let _ = writeln!(out, "{}_count{} {}", entry.name, labels, count);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn format_labels(labels: &[(String, String)], le: Option<String>) -> String {
+    let mut pairs = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value))
+        .collect::<Vec<_>>();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+/// Serves the metrics on `GET /metrics` for processes without an http server of their own.
+pub fn serve(registry: Arc<Registry>, address: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut request_line = String::new();
+            if BufReader::new(&stream).read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let response = if request_line.starts_with("GET /metrics ") {
+                let body = registry.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+            let _ = (&stream).write_all(response.as_bytes());
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let registry = Registry::new();
+        registry.counter("payments_total", "Payments made.").inc_by(3);
+        registry
+            .labeled_gauge("exposure", "Exposure per currency.", &[("currency", "USD")])
+            .set(1.5);
+        let latency = registry.histogram("latency_seconds", "Latency.", &[1.0, 5.0]);
+        latency.observe(0.5);
+        latency.observe(3.0);
+
+        let rendered = registry.render();
+        assert!(rendered.contains("# TYPE payments_total counter\npayments_total 3\n"));
+        assert!(rendered.contains("exposure{currency=\"USD\"} 1.5\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("latency_seconds_count 2\n"));
+    }
+}
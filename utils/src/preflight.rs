@@ -0,0 +1,120 @@
+use std::fmt;
+use std::net::TcpListener;
+
+/// Outcome of a single startup check. Failures say what is wrong and how to fix it.
+pub struct PreflightCheck {
+    pub name: String,
+    pub outcome: Result<String, String>,
+}
+
+/// Checks a service runs before it starts so every misconfiguration is reported at once,
+/// instead of panicking on the first one somewhere in the middle of the startup.
+pub struct PreflightReport {
+    service: String,
+    checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    pub fn new(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            checks: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, outcome: Result<String, String>) {
+        self.checks.push(PreflightCheck {
+            name: name.to_string(),
+            outcome,
+        });
+    }
+
+    pub fn checks(&self) -> &[PreflightCheck] {
+        &self.checks
+    }
+
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| check.outcome.is_err()).count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures() == 0
+    }
+
+    /// Prints the report and exits the process if any check failed.
+    pub fn print_or_exit(&self) {
+        if self.is_ok() {
+            println!("{}", self);
+            return;
+        }
+        eprintln!("{}", self);
+        std::process::exit(1);
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Preflight checks of the {}:", self.service)?;
+        for check in self.checks.iter() {
+            match &check.outcome {
+                Ok(detail) => writeln!(f, "  [ok]   {}: {}", check.name, detail)?,
+                Err(problem) => writeln!(f, "  [FAIL] {}: {}", check.name, problem)?,
+            }
+        }
+        match self.failures() {
+            0 => write!(f, "All {} checks passed.", self.checks.len()),
+            failures => write!(
+                f,
+                "{} of {} checks failed, not starting the {}.",
+                failures,
+                self.checks.len(),
+                self.service
+            ),
+        }
+    }
+}
+
+/// Checks that a zmq endpoint the service binds to is free. Only tcp endpoints are checked.
+pub fn check_bindable(address: &str) -> Result<String, String> {
+    let host_port = match address.strip_prefix("tcp://") {
+        Some(host_port) => host_port,
+        None if address.contains("://") => return Ok(format!("{} is not a tcp endpoint, not checked", address)),
+        None => {
+            return Err(format!(
+                "{} is not a valid endpoint, expected something like tcp://*:5555",
+                address
+            ))
+        }
+    };
+    let host_port = host_port.replacen('*', "0.0.0.0", 1);
+    match TcpListener::bind(&host_port) {
+        Ok(_) => Ok(format!("{} is free", address)),
+        Err(err) => Err(format!(
+            "can't bind {}: {}. Is another instance running or the port used by something else?",
+            address, err
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taken_ports_fail_the_report() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcp://{}", taken.local_addr().unwrap());
+
+        let mut report = PreflightReport::new("bank");
+        report.add("free endpoint", check_bindable("tcp://127.0.0.1:0"));
+        report.add("ipc endpoint", check_bindable("ipc:///tmp/bank"));
+        assert!(report.is_ok());
+
+        report.add("taken endpoint", check_bindable(&address));
+        report.add("invalid endpoint", check_bindable("localhost:5555"));
+        assert_eq!(report.failures(), 2);
+        assert!(report
+            .to_string()
+            .ends_with("2 of 4 checks failed, not starting the bank."));
+    }
+}
@@ -0,0 +1,35 @@
+use secp256k1::bitcoin_hashes::{hmac, sha256, Hash, HashEngine};
+
+/// Hex encoded HMAC-SHA256 of the payload.
+pub fn sign(key: &str, payload: &str) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(key.as_bytes());
+    engine.input(payload.as_bytes());
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_string()
+}
+
+/// Whether the signature of the payload was made with the key. Compared in constant time so the
+/// signature can't be guessed byte by byte.
+pub fn verify(key: &str, payload: &str, signature: &str) -> bool {
+    let expected = sign(key, payload);
+    expected.len() == signature.len()
+        && expected
+            .bytes()
+            .zip(signature.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let signature = sign("secret", "payload");
+        assert_eq!(signature.len(), 64);
+        assert!(verify("secret", "payload", &signature));
+        assert!(!verify("secret", "tampered", &signature));
+        assert!(!verify("other", "payload", &signature));
+        assert!(!verify("secret", "payload", &signature[1..]));
+    }
+}
@@ -0,0 +1,62 @@
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use uuid::Uuid;
+
+/// Exports the spans of the service to an OTLP collector, e.g. `http://localhost:4317`.
+/// Has to be called from within a tokio runtime.
+pub fn init_tracing(service_name: &str, otlp_endpoint: &str) -> Result<(), String> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(otlp_endpoint))
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )])))
+        .install_batch(opentelemetry::runtime::Tokio)
+        .map_err(|err| err.to_string())?;
+    let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber).map_err(|err| err.to_string())
+}
+
+/// Span of a service handling a request. The trace id of the span is the request id, so the spans of
+/// all processes handling the same request end up in one trace without passing a trace context around.
+pub fn request_span(operation: &'static str, req_id: Option<Uuid>) -> Span {
+    let span = tracing::info_span!("request", operation, req_id = tracing::field::Empty);
+    if let Some(req_id) = req_id {
+        span.record("req_id", tracing::field::display(req_id));
+        span.set_parent(request_context(req_id));
+    }
+    span
+}
+
+fn request_context(req_id: Uuid) -> Context {
+    let bytes = *req_id.as_bytes();
+    let mut span_id = [0; 8];
+    span_id.copy_from_slice(&bytes[8..]);
+    let span_context = SpanContext::new(
+        TraceId::from_bytes(bytes),
+        SpanId::from_bytes(span_id),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+    Context::new().with_remote_span_context(span_context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_id_is_request_id() {
+        let req_id = Uuid::new_v4();
+        let context = request_context(req_id);
+        let trace_id = context.span().span_context().trace_id();
+        assert_eq!(trace_id.to_bytes(), *req_id.as_bytes());
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+/// Last timestamp and counter handed out, packed as `millis << 12 | counter`, so
+/// ids generated within the same millisecond still sort in creation order.
+static LAST: AtomicU64 = AtomicU64::new(0);
+
+const COUNTER_BITS: u64 = 12;
+const COUNTER_MAX: u16 = 0x0fff;
+
+/// Returns a new transaction id in the UUIDv7 layout: a 48 bit millisecond
+/// timestamp, a 12 bit per-millisecond counter and 62 random bits. Ids are
+/// unique and strictly increasing within a process, and sort by creation time
+/// across processes.
+pub fn new_txid() -> String {
+    let (millis, counter) = next_tick(crate::time::time_now());
+    from_parts(millis, counter, Uuid::new_v4().as_bytes()).to_string()
+}
+
+/// Extracts the millisecond timestamp embedded in a txid produced by [`new_txid`].
+pub fn timestamp(txid: &str) -> Option<u64> {
+    let uuid = Uuid::parse_str(txid).ok()?;
+    let bytes = uuid.as_bytes();
+    if bytes[6] >> 4 != 7 {
+        return None;
+    }
+    Some(bytes[..6].iter().fold(0_u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn next_tick(now: u64) -> (u64, u16) {
+    // A clock going backwards or a full counter keeps counting past the last
+    // tick rather than repeating an id.
+    let previous = LAST
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(std::cmp::max(now << COUNTER_BITS, last + 1))
+        })
+        .unwrap_or_else(|last| last);
+    let tick = std::cmp::max(now << COUNTER_BITS, previous + 1);
+    (tick >> COUNTER_BITS, (tick as u16) & COUNTER_MAX)
+}
+
+fn from_parts(millis: u64, counter: u16, random: &[u8; 16]) -> Uuid {
+    let mut bytes = *random;
+    bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    bytes[6] = 0x70 | (counter >> 8) as u8;
+    bytes[7] = counter as u8;
+    bytes[8] = 0x80 | (bytes[8] & 0x3f);
+    Uuid::from_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn txids_are_unique_and_ordered() {
+        let txids: Vec<String> = (0..10_000).map(|_| new_txid()).collect();
+        assert!(txids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let now = crate::time::time_now();
+        let millis = timestamp(&txids[0]).unwrap();
+        assert!(millis <= now && now - millis < 60_000);
+        assert_eq!(timestamp("1668700000000-1"), None);
+    }
+
+    #[test]
+    fn full_counter_rolls_into_next_millisecond() {
+        let uuid = from_parts(1_000, COUNTER_MAX, &[0xff; 16]);
+        let next = from_parts(1_001, 0, &[0x00; 16]);
+        assert!(uuid.to_string() < next.to_string());
+        assert_eq!(timestamp(&uuid.to_string()), Some(1_000));
+    }
+}
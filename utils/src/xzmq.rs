@@ -1,14 +1,46 @@
+use msgs::Message;
 pub use zmq::{Context as ZmqContext, Socket as ZmqSocket, SocketType};
 
+/// Topic of messages published to every api instance.
+pub const BROADCAST_TOPIC: &[u8] = b"*\0";
+
+/// Topic of messages published to a single api instance. Topics end with a nul byte so the id of one instance
+/// never prefix matches the topic of another.
+pub fn instance_topic(instance_id: &str) -> Vec<u8> {
+    let mut topic = instance_id.as_bytes().to_vec();
+    topic.push(0);
+    topic
+}
+
 #[derive(Clone)]
 pub struct SocketContext {
     context: ZmqContext,
+    high_water_mark: Option<i32>,
 }
 
 impl SocketContext {
     pub fn new() -> Self {
         Self {
             context: ZmqContext::new(),
+            high_water_mark: None,
+        }
+    }
+
+    /// Sockets created afterwards queue at most this many messages per peer, zmq's default of 1000 otherwise.
+    pub fn with_high_water_mark(mut self, high_water_mark: i32) -> Self {
+        self.high_water_mark = Some(high_water_mark);
+        self
+    }
+
+    // Only applies to connections made after it is set.
+    fn set_high_water_mark(&self, socket: &ZmqSocket) {
+        if let Some(high_water_mark) = self.high_water_mark {
+            if let Err(err) = socket
+                .set_rcvhwm(high_water_mark)
+                .and_then(|_| socket.set_sndhwm(high_water_mark))
+            {
+                panic!("Failed to set the high water mark of a socket, reason: {:?}", err);
+            }
         }
     }
 
@@ -22,6 +54,7 @@ impl SocketContext {
                 );
             }
         };
+        self.set_high_water_mark(&socket);
         match socket.bind(address) {
             Ok(()) => socket,
             Err(err) => {
@@ -40,11 +73,7 @@ impl SocketContext {
                 );
             }
         };
-        if socket_type == zmq::SUB {
-            if let Err(err) = socket.set_subscribe(&[]) {
-                panic!("Failed to set subscribe on the socket, reason {:?}", err);
-            }
-        }
+        self.set_high_water_mark(&socket);
         match socket.connect(address) {
             Ok(()) => socket,
             Err(err) => {
@@ -58,7 +87,18 @@ impl SocketContext {
     }
 
     pub fn create_subscriber(&self, address: &str) -> ZmqSocket {
-        self.create_connecting_socket(address, zmq::SUB)
+        self.create_subscriber_to(address, &[&[]])
+    }
+
+    /// Subscriber that only receives messages published under one of the topics.
+    pub fn create_subscriber_to(&self, address: &str, topics: &[&[u8]]) -> ZmqSocket {
+        let socket = self.create_connecting_socket(address, zmq::SUB);
+        for topic in topics {
+            if let Err(err) = socket.set_subscribe(topic) {
+                panic!("Failed to set subscribe on the socket, reason {:?}", err);
+            }
+        }
+        socket
     }
 
     pub fn create_push(&self, address: &str) -> ZmqSocket {
@@ -146,3 +186,50 @@ where
         );
     }
 }
+
+/// Sends a message in the versioned frame format of `msgs::wire`, preceded by a frame with the id of the
+/// sending instance.
+pub fn send_message_from(socket: &ZmqSocket, instance_id: &str, message: &Message) {
+    let payload = match msgs::wire::encode(message) {
+        Ok(frame) => vec![instance_id.as_bytes().to_vec(), frame],
+        Err(err) => {
+            panic!("Failed to encode a message: {:?}, reason: {}", message, err);
+        }
+    };
+    if let Err(err) = socket.send_multipart(payload, 0x00) {
+        panic!(
+            "Failed to send a message: {:?} as multipart, reason: {:?}",
+            message, err
+        );
+    }
+}
+
+/// Sends a message in the versioned frame format of `msgs::wire`.
+pub fn send_message(socket: &ZmqSocket, message: &Message) {
+    let payload = match msgs::wire::encode(message) {
+        Ok(frame) => frame,
+        Err(err) => {
+            panic!("Failed to encode a message: {:?}, reason: {}", message, err);
+        }
+    };
+    if let Err(err) = socket.send(payload, 0x00) {
+        panic!("Failed to send a message: {:?}, reason: {:?}", message, err);
+    }
+}
+
+/// Sends a message in the versioned frame format of `msgs::wire` as the last of three frames, the first frame
+/// is the topic subscribers filter on.
+pub fn send_multipart_message(socket: &ZmqSocket, topic: &[u8], message: &Message) {
+    let payload = match msgs::wire::encode(message) {
+        Ok(frame) => vec![topic.to_vec(), vec![], frame],
+        Err(err) => {
+            panic!("Failed to encode a message: {:?}, reason: {}", message, err);
+        }
+    };
+    if let Err(err) = socket.send_multipart(payload, 0x00) {
+        panic!(
+            "Failed to send a message: {:?} as multipart, reason: {:?}",
+            message, err
+        );
+    }
+}
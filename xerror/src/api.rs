@@ -11,6 +11,8 @@ pub enum AuthError {
     UserExists,
     #[error(display = "Incorrect password supplied.")]
     IncorrectPassword,
+    #[error(display = "Token is not permitted to do this.")]
+    NotPermitted,
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -70,6 +72,23 @@ pub enum ExternalError {
     FailedToFetchExternalData,
 }
 
+#[derive(Debug, Error, Serialize)]
+#[error(display = "An Error has occurred during account recovery.")]
+pub enum RecoveryError {
+    #[error(display = "Account has no guardians set up.")]
+    NotConfigured,
+    #[error(display = "Too many recovery requests, try again later.")]
+    TooManyRequests,
+    #[error(display = "Recovery does not exist.")]
+    RecoveryDoesNotExist,
+    #[error(display = "User is not a guardian of this account.")]
+    NotGuardian,
+    #[error(display = "Recovery is not in a state that allows this action.")]
+    InvalidState,
+    #[error(display = "Recovery delay has not passed yet.")]
+    DelayNotPassed,
+}
+
 #[derive(Debug, Error, Serialize)]
 #[serde(untagged)]
 pub enum ApiError {
@@ -85,6 +104,8 @@ pub enum ApiError {
     Request(RequestError),
     #[error(display = "External error.")]
     External(ExternalError),
+    #[error(display = "Recovery error.")]
+    Recovery(RecoveryError),
 }
 
 impl error::ResponseError for ApiError {
@@ -93,6 +114,7 @@ impl error::ResponseError for ApiError {
             ApiError::Auth(auth) => match auth {
                 AuthError::UserExists => HttpResponse::Conflict(),
                 AuthError::IncorrectPassword => HttpResponse::Unauthorized(),
+                AuthError::NotPermitted => HttpResponse::Forbidden(),
             },
             ApiError::Db(db) => match db {
                 DbError::DbConnectionError => HttpResponse::InternalServerError(),
@@ -113,6 +135,14 @@ impl error::ResponseError for ApiError {
             ApiError::External(external) => match external {
                 ExternalError::FailedToFetchExternalData => HttpResponse::InternalServerError(),
             },
+            ApiError::Recovery(recovery) => match recovery {
+                RecoveryError::NotConfigured => HttpResponse::BadRequest(),
+                RecoveryError::TooManyRequests => HttpResponse::TooManyRequests(),
+                RecoveryError::RecoveryDoesNotExist => HttpResponse::NotFound(),
+                RecoveryError::NotGuardian => HttpResponse::Forbidden(),
+                RecoveryError::InvalidState => HttpResponse::Conflict(),
+                RecoveryError::DelayNotPassed => HttpResponse::Forbidden(),
+            },
         };
         response_builder.json(json!({ "error": self }))
     }
@@ -122,6 +152,7 @@ impl error::ResponseError for ApiError {
             ApiError::Auth(auth) => match auth {
                 AuthError::UserExists => StatusCode::CONFLICT,
                 AuthError::IncorrectPassword => StatusCode::UNAUTHORIZED,
+                AuthError::NotPermitted => StatusCode::FORBIDDEN,
             },
             ApiError::Db(db) => match db {
                 DbError::DbConnectionError => StatusCode::INTERNAL_SERVER_ERROR,
@@ -143,6 +174,14 @@ impl error::ResponseError for ApiError {
             ApiError::External(external) => match external {
                 ExternalError::FailedToFetchExternalData => StatusCode::INTERNAL_SERVER_ERROR,
             },
+            ApiError::Recovery(recovery) => match recovery {
+                RecoveryError::NotConfigured => StatusCode::BAD_REQUEST,
+                RecoveryError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+                RecoveryError::RecoveryDoesNotExist => StatusCode::NOT_FOUND,
+                RecoveryError::NotGuardian => StatusCode::FORBIDDEN,
+                RecoveryError::InvalidState => StatusCode::CONFLICT,
+                RecoveryError::DelayNotPassed => StatusCode::FORBIDDEN,
+            },
         }
     }
 }
@@ -5,6 +5,7 @@ pub enum BankError {
     UserAccountAlreadyExists,
     FailedTransaction,
     SwapError,
+    PeriodClosed,
 }
 
 impl std::fmt::Display for BankError {
@@ -15,6 +16,7 @@ impl std::fmt::Display for BankError {
             BankError::UserAccountAlreadyExists => "UserAccountAlreadyExists",
             BankError::FailedTransaction => "FailedTransaction",
             BankError::SwapError => "SwapError",
+            BankError::PeriodClosed => "PeriodClosed",
         };
         write!(f, "{}", output)
     }
@@ -5,6 +5,28 @@ pub enum LndConnectorError {
     FailedToGetNodeInfo,
     FailedToDecodePaymentRequest,
     FailedToQueryRoutes,
+    NoHealthyNode,
+    NoRouteFound,
+    TemporaryPaymentFailure,
+    FailedToGetBalances,
+    FailedToSignMessage,
+    FailedToListChannels,
+    FailedToLookupPayment,
+    FailedToLookupInvoice,
+    FailedToOpenChannel,
+    FailedToGetAssetQuote,
+    /// The connection broke after the payment was handed to lnd, it may still be in flight.
+    PaymentStatusUnknown,
+}
+
+impl LndConnectorError {
+    /// Whether a payment that failed with this error may succeed on another attempt.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            LndConnectorError::NoRouteFound | LndConnectorError::TemporaryPaymentFailure
+        )
+    }
 }
 
 impl std::fmt::Display for LndConnectorError {